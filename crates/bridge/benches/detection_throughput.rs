@@ -1,4 +1,5 @@
 use bridge::{Detection, DetectionReader, DetectionWriter};
+use common::WallClockNs;
 use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
 use std::fs;
 
@@ -23,6 +24,7 @@ fn write_detections(
                 box_: Some(&bbox),
                 confidence: det.confidence,
                 class_id: det.class_id,
+                appearance: None,
             },
         );
         detection_offsets.push(detection);
@@ -32,9 +34,10 @@ fn write_detections(
     writer.write_detections(
         camera_id,
         frame_number,
-        timestamp_ns,
+        WallClockNs::from_nanos(timestamp_ns).into(),
         detections_vector,
         None,
+        None,
     )
 }
 
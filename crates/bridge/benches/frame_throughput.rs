@@ -1,4 +1,4 @@
-use bridge::{FrameReader, FrameWriter};
+use bridge::{FrameReader, FrameWriter, HugePages};
 use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
 use std::fs;
 
@@ -159,10 +159,67 @@ fn benchmark_frame_roundtrip(c: &mut Criterion) {
     group.finish();
 }
 
+/// Compare page backings for a 4K write-read roundtrip
+///
+/// Runs in /dev/shm (tmpfs) like production. `BRIDGE_BENCH_HUGETLBFS_DIR` points the
+/// hugetlb case at a hugetlbfs mount; without it that case measures the fallback.
+fn benchmark_frame_page_size(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame_page_size");
+
+    let (width, height) = (3840u32, 2160u32);
+    let buffer_size = (width * height * 3 + 8192) as usize;
+    let pixel_data = vec![128u8; (width * height * 3) as usize];
+    let hugetlbfs_dir =
+        std::env::var("BRIDGE_BENCH_HUGETLBFS_DIR").unwrap_or_else(|_| "/dev/shm".to_string());
+
+    let modes = [
+        (HugePages::Off, "standard", "/dev/shm"),
+        (HugePages::Transparent, "transparent", "/dev/shm"),
+        (HugePages::Hugetlb, "hugetlb", hugetlbfs_dir.as_str()),
+    ];
+
+    for (mode, label, dir) in modes {
+        let path = format!("{}/bridge_bench_frame_page_size_{}", dir, label);
+        let _ = fs::remove_file(&path);
+
+        let mut writer = FrameWriter::build_with_options(&path, buffer_size, mode).unwrap();
+        let mut reader = FrameReader::with_options(&path, mode).unwrap();
+        eprintln!("{}: effective backing {:?}", label, writer.huge_pages());
+
+        group.bench_with_input(BenchmarkId::new("full_cycle_4k", label), label, |b, _| {
+            let mut frame_count = 0u64;
+            b.iter(|| {
+                writer
+                    .write_frame(
+                        black_box(0),
+                        black_box(&pixel_data),
+                        black_box(frame_count),
+                        black_box(width),
+                        black_box(height),
+                        black_box(None),
+                    )
+                    .unwrap();
+
+                let frame = reader.get_frame().unwrap().unwrap();
+                black_box(frame.pixels());
+
+                reader.mark_read();
+                frame_count += 1;
+            });
+        });
+
+        drop(writer);
+        let _ = fs::remove_file(&path);
+    }
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     benchmark_frame_write,
     benchmark_frame_read,
-    benchmark_frame_roundtrip
+    benchmark_frame_roundtrip,
+    benchmark_frame_page_size
 );
 criterion_main!(benches);
@@ -0,0 +1,99 @@
+//! Picking one frame out of an alarm burst
+//!
+//! On alarm entry capture writes a rapid burst of frames, each tagged with its
+//! position in the burst and its sharpness. Consumers that keep a single frame of
+//! the alarm (recording thumbnails, notification snapshots) feed every frame to a
+//! [`BurstSelector`] and keep the sharpest frame it hands back.
+
+use schema::Burst;
+
+/// Keeps the sharpest frame of the burst in progress
+#[derive(Debug)]
+pub struct BurstSelector<T> {
+    /// Burst in progress and its sharpest frame so far
+    best: Option<(Burst, T)>,
+}
+
+impl<T> Default for BurstSelector<T> {
+    fn default() -> Self {
+        Self { best: None }
+    }
+}
+
+impl<T> BurstSelector<T> {
+    /// Observe a frame, `burst` being its tag
+    ///
+    /// `frame` is only called for a burst frame sharper than the others seen so
+    /// far. Returns the sharpest frame of a burst once the burst is over: on its
+    /// last frame, or on the first frame outside it when frames were missed.
+    pub fn observe(&mut self, burst: Option<&Burst>, frame: impl FnOnce() -> T) -> Option<T> {
+        let Some(burst) = burst else {
+            return self.best.take().map(|(_, best)| best);
+        };
+
+        let mut finished = None;
+        match &self.best {
+            Some((best, _)) if best.id() == burst.id() => {
+                if burst.sharpness() > best.sharpness() {
+                    self.best = Some((*burst, frame()));
+                }
+            }
+            _ => {
+                finished = self.best.replace((*burst, frame())).map(|(_, best)| best);
+            }
+        }
+
+        if burst.index() + 1 >= burst.length() {
+            // Should a one-frame burst also end an unfinished one, the latest wins
+            return self.best.take().map(|(_, best)| best).or(finished);
+        }
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(id: u64, index: u16, sharpness: f32) -> Burst {
+        Burst::new(id, index, 4, sharpness)
+    }
+
+    #[test]
+    fn test_sharpest_frame_is_picked_at_burst_end() {
+        let mut selector = BurstSelector::default();
+        assert_eq!(selector.observe(None, || 0), None);
+
+        let sharpness = [10.0, 40.0, 25.0, 30.0];
+        let picked: Vec<Option<u64>> = sharpness
+            .iter()
+            .enumerate()
+            .map(|(i, &s)| selector.observe(Some(&tag(7, i as u16, s)), || 7 + i as u64))
+            .collect();
+        assert_eq!(picked, [None, None, None, Some(8)]);
+        assert_eq!(selector.observe(None, || 0), None);
+    }
+
+    #[test]
+    fn test_unfinished_burst_is_picked_when_it_ends_early() {
+        let mut selector = BurstSelector::default();
+        selector.observe(Some(&tag(7, 0, 10.0)), || 7);
+        selector.observe(Some(&tag(7, 1, 5.0)), || 8);
+        // The burst's last frames were missed
+        assert_eq!(selector.observe(None, || 0), Some(7));
+
+        selector.observe(Some(&tag(20, 0, 10.0)), || 20);
+        // A new burst starts before the previous one was seen ending
+        assert_eq!(selector.observe(Some(&tag(30, 0, 1.0)), || 30), Some(20));
+        assert_eq!(selector.observe(Some(&tag(30, 3, 0.5)), || 33), Some(30));
+    }
+
+    #[test]
+    fn test_frames_are_only_taken_when_sharper() {
+        let mut selector = BurstSelector::default();
+        selector.observe(Some(&tag(1, 0, 10.0)), || 1);
+        selector.observe(Some(&tag(1, 1, 5.0)), || {
+            unreachable!("blurrier frame taken")
+        });
+    }
+}
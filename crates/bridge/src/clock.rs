@@ -0,0 +1,66 @@
+//! Clock domain of the timestamps in the schema
+//!
+//! `timestamp_ns` fields are tagged with the clock they were read from. Writers
+//! split a [`Timestamp`] into the value and its tag, readers get it back through
+//! [`CapturedAt`] and convert it before comparing it with anything.
+
+use common::{MonotonicNs, Timestamp, WallClockNs};
+use schema::ClockDomain;
+
+/// Schema value and clock tag of `timestamp`
+#[cfg_attr(
+    not(any(feature = "frame-writer", feature = "detection-writer")),
+    allow(dead_code)
+)]
+pub(crate) fn to_schema(timestamp: Timestamp) -> (u64, ClockDomain) {
+    match timestamp {
+        Timestamp::WallClock(ns) => (ns.as_nanos(), ClockDomain::WallClock),
+        Timestamp::Monotonic(ns) => (ns.as_nanos(), ClockDomain::Monotonic),
+    }
+}
+
+fn from_schema(ns: u64, clock: ClockDomain) -> Timestamp {
+    match clock {
+        ClockDomain::Monotonic => MonotonicNs::from_nanos(ns).into(),
+        // Older writers only stamped the wall clock
+        _ => WallClockNs::from_nanos(ns).into(),
+    }
+}
+
+/// Capture time of a frame, or of the frame a detection result was computed on
+pub trait CapturedAt {
+    fn captured_at(&self) -> Timestamp;
+}
+
+impl CapturedAt for schema::Frame<'_> {
+    fn captured_at(&self) -> Timestamp {
+        from_schema(self.timestamp_ns(), self.timestamp_clock())
+    }
+}
+
+impl CapturedAt for schema::DetectionResult<'_> {
+    fn captured_at(&self) -> Timestamp {
+        from_schema(self.timestamp_ns(), self.timestamp_clock())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_roundtrip_keeps_the_clock() {
+        for timestamp in [
+            Timestamp::from(WallClockNs::from_nanos(1_700_000_000_000_000_000)),
+            Timestamp::from(MonotonicNs::from_nanos(5_000_000)),
+        ] {
+            let (ns, clock) = to_schema(timestamp);
+            assert_eq!(from_schema(ns, clock), timestamp);
+        }
+        // Unknown tags from a newer writer read as the wall clock
+        assert_eq!(
+            from_schema(7, ClockDomain(9)),
+            Timestamp::WallClock(WallClockNs::from_nanos(7))
+        );
+    }
+}
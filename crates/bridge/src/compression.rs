@@ -0,0 +1,136 @@
+//! LZ4 compression of frame pixels
+//!
+//! Raw RGB is the cheapest way to share frames through `/dev/shm`, but wasteful
+//! when the frame buffer lives on a slower tmpfs or is synced off the device. A
+//! `FrameWriter` set to [`FrameCompression::Lz4`] writes RGB frames as LZ4 blocks
+//! tagged `FrameEncoding::Lz4`; [`frame_payload`], [`decompress_into`] and
+//! `OwnedFrame` give readers the RGB back. JPEG frames are already compressed and
+//! written as they are.
+
+use crate::errors::BridgeError;
+use schema::{Frame, FrameEncoding};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+/// How a `FrameWriter` stores RGB frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameCompression {
+    #[default]
+    None,
+    Lz4,
+}
+
+impl FromStr for FrameCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "none" | "off" => Ok(Self::None),
+            "lz4" => Ok(Self::Lz4),
+            other => Err(format!(
+                "Unknown frame compression {:?} (expected none or lz4)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FrameCompression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::None => "none",
+            Self::Lz4 => "lz4",
+        })
+    }
+}
+
+/// Compress `pixels` into `out` as a size-prefixed LZ4 block
+#[cfg(feature = "frame-writer")]
+pub(crate) fn compress_into(pixels: &[u8], out: &mut Vec<u8>) {
+    out.clear();
+    out.extend_from_slice(&(pixels.len() as u32).to_le_bytes());
+    out.resize(
+        4 + lz4_flex::block::get_maximum_output_size(pixels.len()),
+        0,
+    );
+    let written = lz4_flex::block::compress_into(pixels, &mut out[4..])
+        .expect("output sized for the worst case");
+    out.truncate(4 + written);
+}
+
+/// Decompress a size-prefixed LZ4 block of `expected_len` bytes into `out`,
+/// reusing its allocation
+pub fn decompress_into(
+    block: &[u8],
+    expected_len: usize,
+    out: &mut Vec<u8>,
+) -> Result<(), BridgeError> {
+    let invalid = |reason: String| BridgeError::InvalidFrame(format!("LZ4 block: {reason}"));
+    let (size, compressed) = block
+        .split_first_chunk::<4>()
+        .ok_or_else(|| invalid("missing size prefix".to_string()))?;
+    let size = u32::from_le_bytes(*size) as usize;
+    // Checked before allocating, a corrupt prefix could ask for gigabytes
+    if size != expected_len {
+        return Err(invalid(format!(
+            "holds {size} bytes, the frame needs {expected_len}"
+        )));
+    }
+
+    out.resize(size, 0);
+    let decompressed =
+        lz4_flex::block::decompress_into(compressed, out).map_err(|e| invalid(e.to_string()))?;
+    if decompressed != size {
+        return Err(invalid(format!(
+            "{decompressed} bytes, the prefix says {size}"
+        )));
+    }
+    Ok(())
+}
+
+/// Payload of `frame` with any LZ4 compression undone, and its encoding:
+/// `Rgb` pixels or a `Jpeg` image
+pub fn frame_payload<'a>(frame: &Frame<'a>) -> Result<(FrameEncoding, Cow<'a, [u8]>), BridgeError> {
+    let payload = frame.pixels().map(|p| p.bytes()).unwrap_or_default();
+    match frame.encoding() {
+        FrameEncoding::Lz4 => {
+            let expected_len =
+                frame.width() as usize * frame.height() as usize * frame.channels() as usize;
+            let mut pixels = Vec::new();
+            decompress_into(payload, expected_len, &mut pixels)?;
+            Ok((FrameEncoding::Rgb, Cow::Owned(pixels)))
+        }
+        encoding => Ok((encoding, Cow::Borrowed(payload))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "frame-writer")]
+    #[test]
+    fn test_round_trip() {
+        let pixels: Vec<u8> = (0..64 * 48 * 3).map(|i| (i / 7 % 256) as u8).collect();
+        let mut block = Vec::new();
+        compress_into(&pixels, &mut block);
+        assert!(block.len() < pixels.len());
+
+        let mut decompressed = vec![1, 2, 3];
+        decompress_into(&block, pixels.len(), &mut decompressed).unwrap();
+        assert_eq!(decompressed, pixels);
+
+        assert!(decompress_into(&block, pixels.len() + 1, &mut decompressed).is_err());
+        assert!(decompress_into(&block[..2], pixels.len(), &mut decompressed).is_err());
+        let truncated = &block[..block.len() - 1];
+        assert!(decompress_into(truncated, pixels.len(), &mut decompressed).is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("LZ4".parse(), Ok(FrameCompression::Lz4));
+        assert_eq!("none".parse(), Ok(FrameCompression::None));
+        assert!("zstd".parse::<FrameCompression>().is_err());
+    }
+}
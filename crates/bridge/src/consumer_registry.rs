@@ -0,0 +1,529 @@
+//! Shared memory registry of frame consumers
+//!
+//! Every frame consumer (inference, gateway, ...) owns a message queue and registers
+//! its name here. Capture reads the registry on each frame and posts once per
+//! registered consumer, so consumers can come and go without touching capture.
+//!
+//! The registry is a fixed array of slots in `/dev/shm`. Consumers register and
+//! unregister under an exclusive lock on the registry file, so two consumers never
+//! claim the same name twice. A slot moves `FREE -> CLAIMED -> ACTIVE` while its
+//! name is written, and every name write is bracketed by a sequence counter the
+//! writer checks, so it never reads a name half rewritten by a concurrent
+//! unregister and register. Registering a name that is already active reuses the
+//! slot, which lets a restarted consumer pick up where it left off.
+//!
+//! A slot also records the pid of its consumer and the pid namespace it runs in.
+//! Registering reclaims the slots of consumers of the same pid namespace that exited
+//! without unregistering; consumers in other containers cannot be checked from
+//! here and keep their slots until they register again.
+
+use crate::errors::BridgeError;
+use crate::paths;
+use crate::process::{pid_namespace, process_alive};
+use crate::semaphore::BridgeSemaphore;
+use memmap2::MmapMut;
+use std::fs::{File, OpenOptions};
+use std::ops::Deref;
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicU8, AtomicU32, Ordering, fence};
+
+/// Maximum number of concurrently registered consumers
+pub const MAX_CONSUMERS: usize = 16;
+
+/// Maximum length of a consumer name
+pub const MAX_NAME_LEN: usize = 28;
+
+const FREE: u32 = 0;
+const CLAIMED: u32 = 1;
+const ACTIVE: u32 = 2;
+
+/// Attempts at reading a name before giving up on a slot being rewritten
+const READ_ATTEMPTS: usize = 64;
+
+#[repr(C)]
+struct Slot {
+    state: AtomicU32,
+    /// Odd while the name is being written
+    seq: AtomicU32,
+    /// Process of the consumer, to reclaim slots of consumers that died registered
+    pid: AtomicU32,
+    /// Pid namespace of the consumer, 0 if unknown
+    pid_ns: AtomicU32,
+    name: [AtomicU8; MAX_NAME_LEN],
+}
+
+impl Slot {
+    fn write_name(&self, name: &str) {
+        self.seq.fetch_add(1, Ordering::AcqRel);
+        fence(Ordering::Release);
+        for (i, byte) in self.name.iter().enumerate() {
+            byte.store(
+                name.as_bytes().get(i).copied().unwrap_or(0),
+                Ordering::Relaxed,
+            );
+        }
+        self.seq.fetch_add(1, Ordering::Release);
+    }
+
+    /// Free an active slot
+    fn release(&self) {
+        if self
+            .state
+            .compare_exchange(ACTIVE, CLAIMED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            self.write_name("");
+            self.pid.store(0, Ordering::Relaxed);
+            self.pid_ns.store(0, Ordering::Relaxed);
+            self.state.store(FREE, Ordering::Release);
+        }
+    }
+}
+
+const REGISTRY_SIZE: usize = MAX_CONSUMERS * size_of::<Slot>();
+
+/// Message queue a consumer receives its frame signals on
+pub fn queue_name(consumer: &str) -> String {
+    format!("/bridge_frame_{}", consumer)
+}
+
+pub struct ConsumerRegistry {
+    /// Locked while registering and unregistering
+    file: File,
+    _mmap: MmapMut,
+    slots: &'static [Slot; MAX_CONSUMERS],
+}
+
+unsafe impl Send for ConsumerRegistry {}
+unsafe impl Sync for ConsumerRegistry {}
+
+impl ConsumerRegistry {
+    /// Open the registry at the default path, creating it if needed
+    pub fn build() -> Result<Self, BridgeError> {
+        Self::new(paths::FRAME_CONSUMERS_PATH)
+    }
+
+    /// Create or open the registry at a custom path (useful for tests)
+    pub fn new(path: &str) -> Result<Self, BridgeError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(path)?;
+        crate::permissions::secure(&file)?;
+
+        file.lock()?;
+        let len = file.metadata()?.len();
+        let resized = if len != REGISTRY_SIZE as u64 {
+            if len != 0 {
+                tracing::warn!(
+                    path,
+                    len,
+                    "Consumer registry has an unknown layout, resetting it"
+                );
+            }
+            // Truncating first zeroes every slot of an older layout
+            file.set_len(0)
+                .and_then(|_| file.set_len(REGISTRY_SIZE as u64))
+        } else {
+            Ok(())
+        };
+        file.unlock()?;
+        resized?;
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+
+        let ptr = mmap.as_mut_ptr() as *const [Slot; MAX_CONSUMERS];
+        let slots = unsafe { &*ptr };
+
+        Ok(Self {
+            file,
+            _mmap: mmap,
+            slots,
+        })
+    }
+
+    /// Register interest in frames, reusing the slot if `name` is already registered
+    pub fn register(&self, name: &str) -> Result<(), BridgeError> {
+        self.register_as(name, std::process::id())
+    }
+
+    fn register_as(&self, name: &str, pid: u32) -> Result<(), BridgeError> {
+        validate_name(name)?;
+
+        self.file.lock()?;
+        let result = self.claim(name, pid);
+        self.file.unlock()?;
+        result
+    }
+
+    /// Claim a slot for `name`, with the registry file locked
+    fn claim(&self, name: &str, pid: u32) -> Result<(), BridgeError> {
+        let pid_ns = pid_namespace();
+        self.reclaim_exited(pid_ns);
+
+        if let Some(slot) = self.find(name) {
+            slot.pid.store(pid, Ordering::Relaxed);
+            slot.pid_ns.store(pid_ns, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        let slot = self
+            .slots
+            .iter()
+            .find(|slot| {
+                slot.state
+                    .compare_exchange(FREE, CLAIMED, Ordering::AcqRel, Ordering::Acquire)
+                    .is_ok()
+            })
+            .ok_or_else(|| {
+                BridgeError::ConsumerRegistry(format!(
+                    "registry full ({} consumers)",
+                    MAX_CONSUMERS
+                ))
+            })?;
+
+        slot.write_name(name);
+        slot.pid.store(pid, Ordering::Relaxed);
+        slot.pid_ns.store(pid_ns, Ordering::Relaxed);
+        slot.state.store(ACTIVE, Ordering::Release);
+        Ok(())
+    }
+
+    /// Free the slots of consumers in our pid namespace that exited registered
+    fn reclaim_exited(&self, pid_ns: u32) {
+        if pid_ns == 0 {
+            return;
+        }
+        for slot in self.slots {
+            if slot.state.load(Ordering::Acquire) != ACTIVE
+                || slot.pid_ns.load(Ordering::Relaxed) != pid_ns
+            {
+                continue;
+            }
+            let pid = slot.pid.load(Ordering::Relaxed);
+            if pid == 0 || process_alive(pid) {
+                continue;
+            }
+            let name = read_name(slot);
+            slot.release();
+            tracing::info!(consumer = ?name, pid, "Reclaimed slot of exited consumer");
+        }
+    }
+
+    /// Stop receiving frame signals, no-op if `name` is not registered
+    pub fn unregister(&self, name: &str) {
+        if let Err(e) = self.file.lock() {
+            tracing::warn!(consumer = %name, error = %e, "Failed to lock consumer registry");
+            return;
+        }
+        if let Some(slot) = self.find(name) {
+            slot.release();
+        }
+        let _ = self.file.unlock();
+    }
+
+    /// Names of all registered consumers
+    pub fn consumers(&self) -> Vec<String> {
+        self.slots.iter().filter_map(read_name).collect()
+    }
+
+    fn find(&self, name: &str) -> Option<&Slot> {
+        self.slots
+            .iter()
+            .find(|slot| read_name(slot).is_some_and(|n| n == name))
+    }
+}
+
+fn read_name(slot: &Slot) -> Option<String> {
+    for _ in 0..READ_ATTEMPTS {
+        if slot.state.load(Ordering::Acquire) != ACTIVE {
+            return None;
+        }
+
+        let before = slot.seq.load(Ordering::Acquire);
+        if before % 2 == 1 {
+            std::hint::spin_loop();
+            continue;
+        }
+        let bytes: Vec<u8> = slot
+            .name
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .take_while(|b| *b != 0)
+            .collect();
+        fence(Ordering::Acquire);
+        if slot.seq.load(Ordering::Relaxed) == before {
+            return String::from_utf8(bytes)
+                .ok()
+                .filter(|name| !name.is_empty());
+        }
+    }
+    None
+}
+
+fn validate_name(name: &str) -> Result<(), BridgeError> {
+    let valid_chars = name
+        .bytes()
+        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_' || b == b'-');
+
+    if name.is_empty() || name.len() > MAX_NAME_LEN || !valid_chars {
+        return Err(BridgeError::ConsumerRegistry(format!(
+            "invalid consumer name {:?}: expected 1-{} chars of [a-z0-9_-]",
+            name, MAX_NAME_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// A consumer's registration and frame queue, unregistered on drop
+pub struct FrameSubscription {
+    name: String,
+    semaphore: BridgeSemaphore,
+    registry: ConsumerRegistry,
+}
+
+impl FrameSubscription {
+    /// Register `name` in the default registry and open its queue
+    pub fn register(name: &str) -> Result<Self, BridgeError> {
+        Self::register_with_path(paths::FRAME_CONSUMERS_PATH, name)
+    }
+
+    pub fn register_with_path(path: &str, name: &str) -> Result<Self, BridgeError> {
+        validate_name(name)?;
+
+        // Create the queue before registering so the writer never posts to a missing queue
+        let semaphore = BridgeSemaphore::ensure_with_name(&queue_name(name))?;
+        // Signals queued before a restart point at frames long gone
+        semaphore.drain()?;
+
+        let registry = ConsumerRegistry::new(path)?;
+        registry.register(name)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            semaphore,
+            registry,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Deref for FrameSubscription {
+    type Target = BridgeSemaphore;
+
+    fn deref(&self) -> &BridgeSemaphore {
+        &self.semaphore
+    }
+}
+
+impl Drop for FrameSubscription {
+    fn drop(&mut self) {
+        self.registry.unregister(&self.name);
+    }
+}
+
+/// Writer side: posts one signal per registered consumer
+pub struct FrameFanout {
+    registry: ConsumerRegistry,
+    queues: Vec<(String, BridgeSemaphore)>,
+}
+
+impl FrameFanout {
+    pub fn build() -> Result<Self, BridgeError> {
+        Self::with_path(paths::FRAME_CONSUMERS_PATH)
+    }
+
+    pub fn with_path(path: &str) -> Result<Self, BridgeError> {
+        Ok(Self {
+            registry: ConsumerRegistry::new(path)?,
+            queues: Vec::new(),
+        })
+    }
+
+    /// Signal every registered consumer, returning how many were signalled
+    ///
+    /// A consumer whose queue is full is skipped rather than stalling the writer.
+    pub fn post(&mut self) -> usize {
+        self.sync_queues();
+
+        let mut signalled = 0;
+        for (name, queue) in &self.queues {
+            match queue.try_post() {
+                Ok(true) => signalled += 1,
+                Ok(false) => {
+                    tracing::trace!(consumer = %name, "Consumer queue full, skipping signal");
+                }
+                Err(e) => {
+                    tracing::warn!(consumer = %name, error = %e, "Failed to signal consumer");
+                }
+            }
+        }
+        signalled
+    }
+
+    /// Names of the consumers signalled by the last [`FrameFanout::post`]
+    pub fn consumers(&self) -> impl Iterator<Item = &str> {
+        self.queues.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Open queues of newly registered consumers and close those that left
+    fn sync_queues(&mut self) {
+        let registered = self.registry.consumers();
+
+        self.queues.retain(|(name, _)| registered.contains(name));
+
+        for name in registered {
+            if self.queues.iter().any(|(n, _)| *n == name) {
+                continue;
+            }
+
+            let queue = BridgeSemaphore::ensure_with_name(&queue_name(&name))
+                .and_then(|queue| queue.set_nonblocking().map(|_| queue));
+            match queue {
+                Ok(queue) => {
+                    tracing::info!(consumer = %name, "Frame consumer registered");
+                    self.queues.push((name, queue));
+                }
+                Err(e) => {
+                    tracing::warn!(consumer = %name, error = %e, "Failed to open consumer queue");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry_path(name: &str) -> String {
+        format!("{}/test_consumer_registry_{}", crate::paths::SHM_DIR, name)
+    }
+
+    #[test]
+    fn test_register_and_unregister() {
+        let path = registry_path("basic");
+        let _ = std::fs::remove_file(&path);
+        let registry = ConsumerRegistry::new(&path).unwrap();
+
+        registry.register("inference").unwrap();
+        registry.register("gateway").unwrap();
+        // Re-registering the same name reuses its slot
+        registry.register("inference").unwrap();
+        assert_eq!(registry.consumers(), vec!["inference", "gateway"]);
+
+        registry.unregister("inference");
+        assert_eq!(registry.consumers(), vec!["gateway"]);
+
+        // The freed slot is reused
+        registry.register("recorder").unwrap();
+        assert_eq!(registry.consumers(), vec!["recorder", "gateway"]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_rejects_invalid_names_and_overflow() {
+        let path = registry_path("overflow");
+        let _ = std::fs::remove_file(&path);
+        let registry = ConsumerRegistry::new(&path).unwrap();
+
+        assert!(registry.register("").is_err());
+        assert!(registry.register("Bad/Name").is_err());
+        assert!(registry.register(&"x".repeat(MAX_NAME_LEN + 1)).is_err());
+
+        for i in 0..MAX_CONSUMERS {
+            registry.register(&format!("consumer{}", i)).unwrap();
+        }
+        assert!(matches!(
+            registry.register("one-too-many"),
+            Err(BridgeError::ConsumerRegistry(_))
+        ));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_reclaims_slots_of_exited_consumers() {
+        let path = registry_path("reclaim");
+        let _ = std::fs::remove_file(&path);
+        let registry = ConsumerRegistry::new(&path).unwrap();
+
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let exited = child.id();
+        child.wait().unwrap();
+
+        registry.register_as("crashed", exited).unwrap();
+        registry.register("inference").unwrap();
+        assert_eq!(registry.consumers(), vec!["inference"]);
+
+        // A consumer still running keeps its slot
+        registry.register("gateway").unwrap();
+        assert_eq!(registry.consumers(), vec!["inference", "gateway"]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_skips_names_being_rewritten() {
+        let path = registry_path("seqlock");
+        let _ = std::fs::remove_file(&path);
+        let registry = ConsumerRegistry::new(&path).unwrap();
+        registry.register("inference").unwrap();
+
+        let slot = &registry.slots[0];
+        slot.seq.fetch_add(1, Ordering::AcqRel);
+        assert!(registry.consumers().is_empty());
+        slot.seq.fetch_add(1, Ordering::AcqRel);
+        assert_eq!(registry.consumers(), vec!["inference"]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_resets_registry_of_another_layout() {
+        let path = registry_path("layout");
+        std::fs::write(&path, [0xFFu8; 512]).unwrap();
+
+        let registry = ConsumerRegistry::new(&path).unwrap();
+        assert!(registry.consumers().is_empty());
+        registry.register("inference").unwrap();
+        assert_eq!(registry.consumers(), vec!["inference"]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_fanout_posts_once_per_subscription() {
+        let path = registry_path("fanout");
+        let _ = std::fs::remove_file(&path);
+
+        let mut fanout = FrameFanout::with_path(&path).unwrap();
+        assert_eq!(fanout.post(), 0);
+
+        let first = FrameSubscription::register_with_path(&path, "test_fanout_a").unwrap();
+        let second = FrameSubscription::register_with_path(&path, "test_fanout_b").unwrap();
+
+        assert_eq!(fanout.post(), 2);
+        assert!(first.try_wait().unwrap());
+        assert!(second.try_wait().unwrap());
+        assert!(!first.try_wait().unwrap());
+
+        // Dropping a subscription unregisters it
+        drop(second);
+        assert_eq!(fanout.post(), 1);
+        assert_eq!(
+            fanout.consumers().collect::<Vec<_>>(),
+            vec!["test_fanout_a"]
+        );
+        assert!(first.try_wait().unwrap());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
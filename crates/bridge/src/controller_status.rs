@@ -0,0 +1,275 @@
+//! Controller state shared with the gateway
+//!
+//! The controller publishes whether it is armed, the state of its alarm state
+//! machine and its config version here on every loop, and applies arm requests the
+//! gateway's `/api/controller/arm` leaves here. The gateway serves both over HTTP,
+//! so the web UI only talks to the gateway.
+//!
+//! Alert drills go through the same way: the gateway's `/api/controller/drill` asks
+//! for one, the controller runs it and leaves its JSON report here.
+
+use crate::errors::BridgeError;
+use crate::paths;
+use common::MonotonicNs;
+use memmap2::MmapMut;
+use std::fs::OpenOptions;
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicU8, AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+
+/// State of the controller's alarm state machine
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlarmPhase {
+    Standby = 1,
+    Validation = 2,
+    Tracking = 3,
+}
+
+impl AlarmPhase {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Self::Standby),
+            2 => Some(Self::Validation),
+            3 => Some(Self::Tracking),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Standby => "standby",
+            Self::Validation => "validation",
+            Self::Tracking => "tracking",
+        }
+    }
+}
+
+/// Latest state the controller published
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControllerSnapshot {
+    pub armed: bool,
+    pub phase: AlarmPhase,
+    pub config_version: u64,
+    /// Time since the controller published it
+    pub age: Duration,
+}
+
+/// No request / disarm / arm
+const ARM_NONE: u8 = 0;
+const ARM_DISARM: u8 = 1;
+const ARM_ARM: u8 = 2;
+
+/// Largest drill report kept, in bytes
+pub const MAX_DRILL_REPORT: usize = 4096;
+
+#[repr(C)]
+struct Slots {
+    /// Arm request waiting for the controller
+    arm_request: AtomicU8,
+    armed: AtomicU8,
+    /// `AlarmPhase`, 0 before the controller published
+    phase: AtomicU8,
+    config_version: AtomicU64,
+    /// `MonotonicNs` of the last publish
+    published_at: AtomicU64,
+    /// Drills the gateway asked for, and the last one the controller took
+    drill_requested: AtomicU64,
+    drill_taken: AtomicU64,
+    /// Odd while the controller writes the report
+    drill_report_seq: AtomicU64,
+    drill_report_len: AtomicU32,
+    drill_report: [AtomicU8; MAX_DRILL_REPORT],
+}
+
+pub struct ControllerStatus {
+    _mmap: MmapMut,
+    slots: &'static Slots,
+}
+
+unsafe impl Send for ControllerStatus {}
+unsafe impl Sync for ControllerStatus {}
+
+impl ControllerStatus {
+    /// Create or open the controller status at its default path
+    pub fn build() -> Result<Self, BridgeError> {
+        Self::new(paths::CONTROLLER_STATUS_PATH)
+    }
+
+    /// Create or open the controller status at `path` (useful for tests)
+    pub fn new(path: &str) -> Result<Self, BridgeError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(path)?;
+        crate::permissions::secure(&file)?;
+
+        let size = std::mem::size_of::<Slots>() as u64;
+        if file.metadata()?.len() < size {
+            file.set_len(size)?;
+        }
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+        let slots = unsafe { &*(mmap.as_mut_ptr() as *const Slots) };
+
+        Ok(Self { _mmap: mmap, slots })
+    }
+
+    /// Ask the controller to arm or disarm, replacing a request it has not applied
+    pub fn request_arm(&self, armed: bool) {
+        let request = if armed { ARM_ARM } else { ARM_DISARM };
+        self.slots.arm_request.store(request, Ordering::Release);
+    }
+
+    /// Arm request not applied yet
+    pub fn pending_arm(&self) -> Option<bool> {
+        decode_arm(self.slots.arm_request.load(Ordering::Acquire))
+    }
+
+    /// Take the pending arm request, for the controller to apply
+    pub fn take_arm_request(&self) -> Option<bool> {
+        decode_arm(self.slots.arm_request.swap(ARM_NONE, Ordering::AcqRel))
+    }
+
+    pub fn publish(&self, armed: bool, phase: AlarmPhase, config_version: u64) {
+        self.slots.armed.store(armed as u8, Ordering::Relaxed);
+        self.slots.phase.store(phase as u8, Ordering::Relaxed);
+        self.slots
+            .config_version
+            .store(config_version, Ordering::Relaxed);
+        // Last, a reader seeing the new time sees the values published with it
+        self.slots
+            .published_at
+            .store(MonotonicNs::now().as_nanos(), Ordering::Release);
+    }
+
+    /// Latest published state, `None` before the controller first published
+    pub fn snapshot(&self) -> Option<ControllerSnapshot> {
+        let published_at = MonotonicNs::from_nanos(self.slots.published_at.load(Ordering::Acquire));
+        let phase = AlarmPhase::from_u8(self.slots.phase.load(Ordering::Relaxed))?;
+        Some(ControllerSnapshot {
+            armed: self.slots.armed.load(Ordering::Relaxed) != 0,
+            phase,
+            config_version: self.slots.config_version.load(Ordering::Relaxed),
+            age: MonotonicNs::now().saturating_duration_since(published_at),
+        })
+    }
+}
+
+impl ControllerStatus {
+    /// Ask the controller for an alert drill, returning the request number
+    pub fn request_drill(&self) -> u64 {
+        self.slots.drill_requested.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    /// Take the latest drill request, for the controller to run; requests made
+    /// while it was busy are merged into one
+    pub fn take_drill_request(&self) -> Option<u64> {
+        let requested = self.slots.drill_requested.load(Ordering::Acquire);
+        (self.slots.drill_taken.swap(requested, Ordering::AcqRel) < requested).then_some(requested)
+    }
+
+    /// Replace the report of the last drill
+    pub fn publish_drill_report(&self, report: &[u8]) -> Result<(), BridgeError> {
+        if report.len() > MAX_DRILL_REPORT {
+            return Err(BridgeError::SizeMismatch);
+        }
+        let seq = self.slots.drill_report_seq.fetch_add(1, Ordering::AcqRel);
+        for (slot, byte) in self.slots.drill_report.iter().zip(report) {
+            slot.store(*byte, Ordering::Relaxed);
+        }
+        self.slots
+            .drill_report_len
+            .store(report.len() as u32, Ordering::Relaxed);
+        self.slots
+            .drill_report_seq
+            .store(seq + 2, Ordering::Release);
+        Ok(())
+    }
+
+    /// Report of the last drill, `None` before the first one
+    pub fn drill_report(&self) -> Option<Vec<u8>> {
+        loop {
+            let seq = self.slots.drill_report_seq.load(Ordering::Acquire);
+            if seq == 0 {
+                return None;
+            }
+            if seq % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let len = (self.slots.drill_report_len.load(Ordering::Relaxed) as usize)
+                .min(MAX_DRILL_REPORT);
+            let report = self.slots.drill_report[..len]
+                .iter()
+                .map(|b| b.load(Ordering::Relaxed))
+                .collect();
+            if self.slots.drill_report_seq.load(Ordering::Acquire) == seq {
+                return Some(report);
+            }
+        }
+    }
+}
+
+fn decode_arm(request: u8) -> Option<bool> {
+    match request {
+        ARM_ARM => Some(true),
+        ARM_DISARM => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arm_requests_and_state_are_shared() {
+        let path = &format!("{}/test_controller_status", crate::paths::SHM_DIR);
+        let _ = std::fs::remove_file(path);
+
+        let gateway = ControllerStatus::new(path).unwrap();
+        let controller = ControllerStatus::new(path).unwrap();
+        assert_eq!(gateway.snapshot(), None);
+        assert_eq!(controller.take_arm_request(), None);
+
+        // The latest request wins, and is applied once
+        gateway.request_arm(false);
+        gateway.request_arm(true);
+        assert_eq!(gateway.pending_arm(), Some(true));
+        assert_eq!(controller.take_arm_request(), Some(true));
+        assert_eq!(controller.take_arm_request(), None);
+        assert_eq!(gateway.pending_arm(), None);
+
+        controller.publish(true, AlarmPhase::Tracking, 3);
+        let snapshot = gateway.snapshot().unwrap();
+        assert!(snapshot.armed);
+        assert_eq!(snapshot.phase, AlarmPhase::Tracking);
+        assert_eq!(snapshot.config_version, 3);
+        assert!(snapshot.age < Duration::from_secs(1));
+
+        // Requests made before the controller took one are run once
+        assert_eq!(controller.take_drill_request(), None);
+        assert_eq!(gateway.request_drill(), 1);
+        assert_eq!(gateway.request_drill(), 2);
+        assert_eq!(controller.take_drill_request(), Some(2));
+        assert_eq!(controller.take_drill_request(), None);
+
+        assert_eq!(gateway.drill_report(), None);
+        controller
+            .publish_drill_report(br#"{"passed":true}"#)
+            .unwrap();
+        controller.publish_drill_report(b"{}").unwrap();
+        assert_eq!(gateway.drill_report().as_deref(), Some(b"{}".as_slice()));
+        assert!(
+            controller
+                .publish_drill_report(&[b' '; MAX_DRILL_REPORT + 1])
+                .is_err()
+        );
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -1,7 +1,9 @@
+use crate::types::InferenceTiming;
 use crate::{
     macros::impl_mmap_reader_base, mmap_reader::MmapReader, paths, utils::safe_flatbuffers_root,
 };
 use anyhow::Result;
+use common::Appearance;
 #[cfg(feature = "tracing")]
 use common::span;
 use schema::DetectionResult;
@@ -10,7 +12,11 @@ pub struct DetectionReader {
     reader: MmapReader,
 }
 
-impl_mmap_reader_base!(DetectionReader, paths::DETECTION_BUFFER_PATH);
+impl_mmap_reader_base!(
+    DetectionReader,
+    crate::types::BufferKind::Detection,
+    paths::DETECTION_BUFFER_PATH
+);
 
 impl DetectionReader {
     /// Get detections from the buffer.
@@ -29,22 +35,113 @@ impl DetectionReader {
         Ok(Some(detection_result))
     }
 
+    /// Capture and inference times of the current result, if its writer recorded them
+    pub fn inference_timing(&self) -> Result<Option<InferenceTiming>> {
+        Ok(self
+            .get_detections()?
+            .and_then(|result| InferenceTiming::from_result(&result)))
+    }
+
+    /// Whether the current result overran the inference deadline
+    pub fn is_late(&self) -> Result<bool> {
+        Ok(self.get_detections()?.is_some_and(|result| result.late()))
+    }
+
+    /// Whether the current result republishes the detections of an earlier frame
+    pub fn is_cached(&self) -> Result<bool> {
+        Ok(self
+            .get_detections()?
+            .is_some_and(|result| result.is_cached()))
+    }
+
     /// Check if a person (class_id == 0) is detected in the current buffer
     pub fn check_person_detected(&self) -> Result<bool> {
+        Ok(!self.detected_classes(&[0])?.is_empty())
+    }
+
+    /// Return which of `class_ids` appear in the current buffer, in the order given
+    pub fn detected_classes(&self, class_ids: &[u16]) -> Result<Vec<u16>> {
+        if self.current_sequence() == 0 {
+            return Ok(Vec::new());
+        }
+
+        let detection = safe_flatbuffers_root::<DetectionResult>(self.reader.buffer())?;
+
+        let Some(detections) = detection.detections() else {
+            return Ok(Vec::new());
+        };
+
+        Ok(class_ids
+            .iter()
+            .copied()
+            .filter(|&class_id| detections.iter().any(|det| det.class_id() == class_id))
+            .collect())
+    }
+
+    /// Highest confidence of each of `class_ids` present in the current buffer, in the
+    /// order given
+    pub fn class_confidences(&self, class_ids: &[u16]) -> Result<Vec<(u16, f32)>> {
         if self.current_sequence() == 0 {
-            return Ok(false);
+            return Ok(Vec::new());
         }
 
         let detection = safe_flatbuffers_root::<DetectionResult>(self.reader.buffer())?;
 
-        if let Some(detections) = detection.detections() {
-            for det in detections {
-                if det.class_id() == 0 {
-                    return Ok(true);
-                }
-            }
+        let Some(detections) = detection.detections() else {
+            return Ok(Vec::new());
+        };
+
+        Ok(class_ids
+            .iter()
+            .filter_map(|&class_id| {
+                detections
+                    .iter()
+                    .filter(|det| det.class_id() == class_id)
+                    .map(|det| det.confidence())
+                    .reduce(f32::max)
+                    .map(|confidence| (class_id, confidence))
+            })
+            .collect())
+    }
+
+    /// Number of detections of each of `class_ids` reaching `min_confidence` in the
+    /// current buffer, in the order given, absent classes left out
+    pub fn class_counts(&self, class_ids: &[u16], min_confidence: f32) -> Result<Vec<(u16, u32)>> {
+        if self.current_sequence() == 0 {
+            return Ok(Vec::new());
         }
 
-        Ok(false)
+        let detection = safe_flatbuffers_root::<DetectionResult>(self.reader.buffer())?;
+
+        let Some(detections) = detection.detections() else {
+            return Ok(Vec::new());
+        };
+
+        Ok(class_ids
+            .iter()
+            .filter_map(|&class_id| {
+                let count = detections
+                    .iter()
+                    .filter(|det| det.class_id() == class_id && det.confidence() >= min_confidence)
+                    .count() as u32;
+                (count > 0).then_some((class_id, count))
+            })
+            .collect())
+    }
+
+    /// Appearance descriptors of the detections of `class_ids` reaching
+    /// `min_confidence` in the current buffer, those written without one left out
+    pub fn appearances(&self, class_ids: &[u16], min_confidence: f32) -> Result<Vec<Appearance>> {
+        let Some(result) = self.get_detections()? else {
+            return Ok(Vec::new());
+        };
+
+        Ok(result
+            .detections()
+            .iter()
+            .flatten()
+            .filter(|det| class_ids.contains(&det.class_id()) && det.confidence() >= min_confidence)
+            .filter_map(|det| Appearance::from_bytes(det.appearance()?.bytes()))
+            .collect())
     }
 }
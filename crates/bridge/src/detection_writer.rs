@@ -1,18 +1,48 @@
+use crate::clock;
+use crate::errors::BridgeError;
 use crate::macros::impl_mmap_writer_base;
 use crate::mmap_writer::MmapWriter;
 use crate::paths;
 use anyhow::{Context, Result};
+use common::{Timestamp, WallClockNs};
 use flatbuffers::{FlatBufferBuilder, ForwardsUOffset, Vector, WIPOffset};
 
+/// Bytes a detection takes in a result at most: its table, vtable, vector slot and
+/// appearance descriptor
+const DETECTION_SIZE: usize = 64 + 4 + common::appearance::APPEARANCE_LEN;
+/// Room kept for the rest of a result: its fields, trace, provenance and plates
+const RESULT_RESERVE: usize = 8 * 1024;
+
+type PlatesOffset = WIPOffset<Vector<'static, ForwardsUOffset<schema::Plate<'static>>>>;
+
 pub struct DetectionWriter {
     writer: MmapWriter,
     builder: FlatBufferBuilder<'static>,
+    /// Plates to attach to the next result, built into `builder`
+    plates: Option<PlatesOffset>,
+    /// Inference start and completion times to attach to the next result
+    timing: Option<(WallClockNs, WallClockNs)>,
+    /// Mark the next result as late
+    late: bool,
+    /// Mark the next result as republished from an earlier frame
+    cached: bool,
+    /// Detections left out of the next result to fit the buffer
+    truncated: u32,
+    /// Size of the frame the boxes of the next result are in coordinates of
+    frame_size: Option<(u32, u32)>,
 }
 
 impl_mmap_writer_base!(
     DetectionWriter,
+    crate::types::BufferKind::Detection,
     paths::DETECTION_BUFFER_PATH,
-    paths::DEFAULT_DETECTION_BUFFER_SIZE
+    paths::DEFAULT_DETECTION_BUFFER_SIZE,
+    plates: None,
+    timing: None,
+    late: false,
+    cached: false,
+    truncated: 0,
+    frame_size: None,
 );
 
 impl DetectionWriter {
@@ -25,7 +55,10 @@ impl DetectionWriter {
 
     /// Commit the finished FlatBuffer data to shared memory.
     /// Call this after `builder.finish(...)`.
+    /// Whatever the `set_next_*` calls attached is dropped, so it does not leak onto
+    /// the result written after this one.
     pub fn commit(&mut self) -> Result<()> {
+        self.clear_next();
         let data = self.builder.finished_data();
         self.writer
             .write(data)
@@ -33,16 +66,88 @@ impl DetectionWriter {
         Ok(())
     }
 
+    /// Write a result serialized elsewhere, the `finished_data()` of another builder.
+    /// Data that does not verify as a `DetectionResult` is rejected before readers see it.
+    /// Like [`Self::commit`], it drops whatever the `set_next_*` calls attached.
+    pub fn write_finished(&mut self, data: &[u8]) -> Result<()> {
+        flatbuffers::root::<schema::DetectionResult>(data)
+            .map_err(|_| BridgeError::InvalidFlatBuffer)
+            .context("Pre-serialized data is not a DetectionResult")?;
+        self.clear_next();
+        self.writer
+            .write(data)
+            .context("Failed to write detection data")?;
+        Ok(())
+    }
+
+    /// Attach plates, built into [`Self::builder`], to the next `write_detections` call
+    pub fn set_next_plates(&mut self, plates: PlatesOffset) {
+        self.plates = Some(plates);
+    }
+
+    /// Attach the wall clock bounds of the inference pass to the next
+    /// `write_detections` call
+    pub fn set_next_inference_timing(&mut self, started: WallClockNs, completed: WallClockNs) {
+        self.timing = Some((started, completed));
+    }
+
+    /// Mark the next `write_detections` call as having overrun the inference deadline
+    pub fn set_next_late(&mut self) {
+        self.late = true;
+    }
+
+    /// Mark the next `write_detections` call as republishing the detections of an
+    /// earlier, unchanged frame instead of running the model
+    pub fn set_next_cached(&mut self) {
+        self.cached = true;
+    }
+
+    /// Record that the next `write_detections` call leaves out `count` detections,
+    /// the least confident, to fit the buffer
+    pub fn set_next_truncated(&mut self, count: u32) {
+        self.truncated = count;
+    }
+
+    /// Reset the state the `set_next_*` calls leave for the next result
+    fn clear_next(&mut self) {
+        self.plates = None;
+        self.timing = None;
+        self.late = false;
+        self.cached = false;
+        self.truncated = 0;
+        self.frame_size = None;
+    }
+
+    /// Record the size of the frame, in pixels, the boxes of the next
+    /// `write_detections` call are in coordinates of
+    pub fn set_next_frame_size(&mut self, width: u32, height: u32) {
+        self.frame_size = Some((width, height));
+    }
+
+    /// Most detections a result can hold without overflowing the buffer, keeping
+    /// room for its other fields
+    pub fn max_detections(&self) -> usize {
+        self.writer.capacity().saturating_sub(RESULT_RESERVE) / DETECTION_SIZE
+    }
+
     /// Build and write a DetectionResult with pre-built detection offsets.
     /// This is the zero-copy path where detections are built directly into the buffer.
+    ///
+    /// `timestamp` is the frame's capture time, as read with
+    /// [`CapturedAt`](crate::CapturedAt). `provenance` should be built into the
+    /// same builder, typically with `Provenance::copy_into` from the source frame.
     pub fn write_detections(
         &mut self,
         camera_id: u32,
         frame_number: u64,
-        timestamp_ns: u64,
+        timestamp: Timestamp,
         detections: WIPOffset<Vector<'_, ForwardsUOffset<schema::Detection<'_>>>>,
         trace_ctx: Option<&schema::TraceContext>,
+        provenance: Option<WIPOffset<schema::Provenance<'_>>>,
     ) -> Result<()> {
+        let (inference_started, inference_completed) = self.timing.take().unwrap_or_default();
+        let (frame_width, frame_height) = self.frame_size.take().unwrap_or_default();
+        let (timestamp_ns, timestamp_clock) = clock::to_schema(timestamp);
         let detection_result = schema::DetectionResult::create(
             &mut self.builder,
             &schema::DetectionResultArgs {
@@ -50,7 +155,17 @@ impl DetectionWriter {
                 frame_number,
                 timestamp_ns,
                 detections: Some(detections),
+                inference_started_ns: inference_started.as_nanos(),
+                inference_completed_ns: inference_completed.as_nanos(),
+                late: std::mem::take(&mut self.late),
                 trace: trace_ctx,
+                provenance,
+                plates: self.plates.take(),
+                timestamp_clock,
+                is_cached: std::mem::take(&mut self.cached),
+                truncated_count: std::mem::take(&mut self.truncated),
+                frame_width,
+                frame_height,
             },
         );
 
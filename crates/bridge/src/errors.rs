@@ -1,3 +1,4 @@
+use crate::types::BufferKind;
 use std::io;
 use thiserror::Error;
 
@@ -17,6 +18,48 @@ pub enum BridgeError {
 
     #[error("Semaphore error: {0}")]
     SemaphoreError(String),
+
+    #[error("Invalid buffer header: {0}")]
+    InvalidHeader(String),
+
+    #[error("Invalid frame payload: {0}")]
+    InvalidFrame(String),
+
+    #[error("Invalid frame signature: {0}")]
+    InvalidSignature(String),
+
+    #[error("Write gate error: {0}")]
+    WriteGate(String),
+
+    #[error(
+        "{path} needs {required} bytes but {dir} has {available} of {total} bytes free \
+         ({used} in use); enlarge it (e.g. docker --shm-size, or mount -o remount,size=) \
+         or reduce the buffer size"
+    )]
+    InsufficientSpace {
+        path: String,
+        dir: String,
+        required: u64,
+        available: u64,
+        used: u64,
+        total: u64,
+    },
+
+    #[error("expected {expected} buffer, found {found} buffer created by pid {creator_pid}")]
+    BufferKindMismatch {
+        expected: BufferKind,
+        found: BufferKind,
+        creator_pid: u32,
+    },
+
+    #[error("buffer taken over by another writer (pid {pid})")]
+    WriterTakeover { pid: u32 },
+
+    #[error("Shared memory permissions: {0}")]
+    Permissions(String),
+
+    #[error("Consumer registry error: {0}")]
+    ConsumerRegistry(String),
 }
 
 #[cfg(test)]
@@ -65,6 +108,58 @@ mod tests {
             "Semaphore error: lock failed",
             "SemaphoreError should display with custom message"
         );
+
+        // Test InvalidHeader display
+        let err = BridgeError::InvalidHeader("no magic".to_string());
+        assert_eq!(
+            err.to_string(),
+            "Invalid buffer header: no magic",
+            "InvalidHeader should display with 'Invalid buffer header:' prefix"
+        );
+
+        // Test InsufficientSpace display
+        let err = BridgeError::InsufficientSpace {
+            path: "/dev/shm/bridge_frame_buffer".to_string(),
+            dir: "/dev/shm".to_string(),
+            required: 8,
+            available: 4,
+            used: 60,
+            total: 64,
+        };
+        assert!(
+            err.to_string().starts_with(
+                "/dev/shm/bridge_frame_buffer needs 8 bytes but /dev/shm has 4 of 64 bytes free (60 in use)"
+            ),
+            "InsufficientSpace should report the requirement and the usage"
+        );
+
+        // Test BufferKindMismatch display
+        let err = BridgeError::BufferKindMismatch {
+            expected: BufferKind::Frame,
+            found: BufferKind::Event,
+            creator_pid: 42,
+        };
+        assert_eq!(
+            err.to_string(),
+            "expected frame buffer, found event buffer created by pid 42",
+            "BufferKindMismatch should name both kinds and the creator"
+        );
+
+        // Test WriterTakeover display
+        let err = BridgeError::WriterTakeover { pid: 7 };
+        assert_eq!(
+            err.to_string(),
+            "buffer taken over by another writer (pid 7)",
+            "WriterTakeover should name the new writer"
+        );
+
+        // Test ConsumerRegistry display
+        let err = BridgeError::ConsumerRegistry("registry full (16 consumers)".to_string());
+        assert_eq!(
+            err.to_string(),
+            "Consumer registry error: registry full (16 consumers)",
+            "ConsumerRegistry should display with custom message"
+        );
     }
 
     #[test]
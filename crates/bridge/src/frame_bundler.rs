@@ -0,0 +1,283 @@
+//! Time-synchronized frames from several cameras
+//!
+//! Stereo rigs and cameras with overlapping views each run their own capture,
+//! writing to their own frame buffer. `FrameBundler` reads all of them and groups
+//! frames captured within `window` of each other into a [`FrameBundle`], one frame
+//! per camera, so consumers see the scene from every camera at the same instant.
+//!
+//! Cameras are not triggered together, so their frames only line up approximately.
+//! A frame is dropped once it can no longer be matched: when the newest frame of
+//! some other camera was captured more than `window` after it. A camera that stops
+//! delivering holds bundles back until the others overflow their queues.
+
+use crate::{CapturedAt, FrameReader};
+use anyhow::Result;
+use common::WallClockNs;
+use schema::Frame;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Frames queued per camera while waiting for the others
+pub const DEFAULT_CAPACITY: usize = 8;
+
+/// Interval between buffer checks while blocked in [`FrameBundler::next_bundle`]
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A frame in a bundle
+#[derive(Debug)]
+pub struct BundledFrame<F> {
+    pub camera_id: u32,
+    pub frame_number: u64,
+    /// Capture time on the wall clock, the clock shared by every camera's frames
+    pub timestamp_ns: WallClockNs,
+    pub frame: F,
+}
+
+/// One frame per camera, in the order the cameras were given
+#[derive(Debug)]
+pub struct FrameBundle<F> {
+    pub frames: Vec<BundledFrame<F>>,
+}
+
+impl<F> FrameBundle<F> {
+    /// Capture time of the earliest frame
+    pub fn timestamp_ns(&self) -> WallClockNs {
+        self.frames
+            .iter()
+            .map(|f| f.timestamp_ns)
+            .min()
+            .unwrap_or_default()
+    }
+
+    /// Capture time between the earliest and latest frame
+    pub fn skew(&self) -> Duration {
+        let latest = self.frames.iter().map(|f| f.timestamp_ns).max();
+        latest
+            .unwrap_or_default()
+            .saturating_duration_since(self.timestamp_ns())
+    }
+}
+
+/// Per-camera frame queues and the matching rule, independent of the buffers
+struct BundleQueues<F> {
+    window: Duration,
+    capacity: usize,
+    queues: Vec<VecDeque<BundledFrame<F>>>,
+    dropped: u64,
+}
+
+impl<F> BundleQueues<F> {
+    fn new(cameras: usize, window: Duration, capacity: usize) -> Self {
+        Self {
+            window,
+            capacity: capacity.max(1),
+            queues: (0..cameras).map(|_| VecDeque::new()).collect(),
+            dropped: 0,
+        }
+    }
+
+    fn push(&mut self, camera: usize, frame: BundledFrame<F>) {
+        let queue = &mut self.queues[camera];
+        // Capture restarted, or the clock stepped back: older frames cannot follow it
+        if queue
+            .back()
+            .is_some_and(|last| frame.timestamp_ns < last.timestamp_ns)
+        {
+            self.dropped += queue.len() as u64;
+            queue.clear();
+        }
+        queue.push_back(frame);
+        if queue.len() > self.capacity {
+            queue.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    fn pop_bundle(&mut self) -> Option<FrameBundle<F>> {
+        loop {
+            let heads = self
+                .queues
+                .iter()
+                .map(|queue| queue.front().map(|f| f.timestamp_ns))
+                .collect::<Option<Vec<_>>>()?;
+            let (oldest, &earliest) = heads.iter().enumerate().min_by_key(|(_, t)| **t)?;
+            let latest = *heads.iter().max()?;
+
+            if latest.saturating_duration_since(earliest) <= self.window {
+                let frames = self
+                    .queues
+                    .iter_mut()
+                    .filter_map(VecDeque::pop_front)
+                    .collect();
+                return Some(FrameBundle { frames });
+            }
+
+            // Every other frame of the latest camera is later still, so the oldest
+            // head can never be bundled
+            self.queues[oldest].pop_front();
+            self.dropped += 1;
+        }
+    }
+}
+
+pub struct FrameBundler<F> {
+    readers: Vec<FrameReader>,
+    sequences: Vec<u64>,
+    convert: fn(&Frame<'_>) -> Result<F>,
+    queues: BundleQueues<F>,
+}
+
+impl<F> FrameBundler<F> {
+    /// Bundle frames from `readers` captured within `window` of each other
+    ///
+    /// `convert` runs once per new frame while the mmap is borrowed.
+    pub fn new(
+        readers: Vec<FrameReader>,
+        window: Duration,
+        convert: fn(&Frame<'_>) -> Result<F>,
+    ) -> Self {
+        let cameras = readers.len();
+        Self {
+            sequences: vec![0; cameras],
+            readers,
+            convert,
+            queues: BundleQueues::new(cameras, window, DEFAULT_CAPACITY),
+        }
+    }
+
+    /// Open the frame buffers at `paths`
+    pub fn with_paths(
+        paths: &[&str],
+        window: Duration,
+        convert: fn(&Frame<'_>) -> Result<F>,
+    ) -> Result<Self> {
+        let readers = paths
+            .iter()
+            .map(|path| FrameReader::with_path(path))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::new(readers, window, convert))
+    }
+
+    /// Number of frames queued per camera while waiting for the others
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.queues.capacity = capacity.max(1);
+        self
+    }
+
+    /// Next bundle, waiting at most `timeout`
+    ///
+    /// Returns `Ok(None)` if no bundle became available in time; a zero timeout
+    /// only checks the buffers once.
+    pub fn next_bundle(&mut self, timeout: Duration) -> Result<Option<FrameBundle<F>>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.poll()?;
+            if let Some(bundle) = self.queues.pop_bundle() {
+                return Ok(Some(bundle));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Ingest new frames from every camera without yielding bundles
+    pub fn poll(&mut self) -> Result<()> {
+        for (camera, reader) in self.readers.iter_mut().enumerate() {
+            let sequence = reader.current_sequence();
+            if sequence == self.sequences[camera] {
+                continue;
+            }
+            self.sequences[camera] = sequence;
+            reader.mark_read();
+
+            let Some(frame) = reader.get_frame()? else {
+                continue;
+            };
+            let bundled = BundledFrame {
+                camera_id: frame.camera_id(),
+                frame_number: frame.frame_number(),
+                timestamp_ns: frame.captured_at().to_wall_clock(),
+                frame: (self.convert)(&frame)?,
+            };
+            self.queues.push(camera, bundled);
+        }
+        Ok(())
+    }
+
+    /// Frames dropped because no other camera had a frame close enough in time
+    pub fn dropped_frames(&self) -> u64 {
+        self.queues.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(camera_id: u32, timestamp_ms: u64) -> BundledFrame<()> {
+        BundledFrame {
+            camera_id,
+            frame_number: timestamp_ms,
+            timestamp_ns: WallClockNs::from_nanos(timestamp_ms * 1_000_000),
+            frame: (),
+        }
+    }
+
+    fn cameras(bundle: &FrameBundle<()>) -> Vec<(u32, u64)> {
+        bundle
+            .frames
+            .iter()
+            .map(|f| (f.camera_id, f.frame_number))
+            .collect()
+    }
+
+    #[test]
+    fn test_frames_within_window_are_bundled() {
+        let mut queues = BundleQueues::new(2, Duration::from_millis(10), 8);
+
+        queues.push(0, frame(0, 100));
+        assert!(queues.pop_bundle().is_none());
+        queues.push(1, frame(1, 105));
+
+        let bundle = queues.pop_bundle().unwrap();
+        assert_eq!(cameras(&bundle), [(0, 100), (1, 105)]);
+        assert_eq!(bundle.skew(), Duration::from_millis(5));
+        assert_eq!(bundle.timestamp_ns(), WallClockNs::from_nanos(100_000_000));
+        assert_eq!(queues.dropped, 0);
+    }
+
+    #[test]
+    fn test_unmatched_frames_are_dropped() {
+        let mut queues = BundleQueues::new(2, Duration::from_millis(10), 8);
+
+        // Camera 0 runs at twice the rate of camera 1
+        for t in [100, 116, 133, 150] {
+            queues.push(0, frame(0, t));
+        }
+        for t in [118, 151] {
+            queues.push(1, frame(1, t));
+        }
+
+        let bundles: Vec<_> = std::iter::from_fn(|| queues.pop_bundle())
+            .map(|bundle| cameras(&bundle))
+            .collect();
+        assert_eq!(
+            bundles,
+            [vec![(0, 116), (1, 118)], vec![(0, 150), (1, 151)]]
+        );
+        assert_eq!(queues.dropped, 2);
+    }
+
+    #[test]
+    fn test_stalled_camera_overflows_queues() {
+        let mut queues = BundleQueues::new(2, Duration::from_millis(10), 2);
+
+        for t in [100, 133, 166] {
+            queues.push(0, frame(0, t));
+        }
+        assert!(queues.pop_bundle().is_none());
+        assert_eq!(queues.dropped, 1);
+    }
+}
@@ -9,7 +9,11 @@ pub struct FrameReader {
     reader: MmapReader,
 }
 
-impl_mmap_reader_base!(FrameReader, paths::FRAME_BUFFER_PATH);
+impl_mmap_reader_base!(
+    FrameReader,
+    crate::types::BufferKind::Frame,
+    paths::FRAME_BUFFER_PATH
+);
 
 impl FrameReader {
     /// Get the current frame from shared memory.
@@ -27,3 +31,80 @@ impl FrameReader {
         Ok(Some(frame))
     }
 }
+
+/// Size of the frames a reader gets, see `format_generation` in the frame schema
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameFormat {
+    pub width: u32,
+    pub height: u32,
+    pub generation: u32,
+}
+
+impl FrameFormat {
+    pub fn of(frame: &Frame<'_>) -> Self {
+        Self {
+            width: frame.width(),
+            height: frame.height(),
+            generation: frame.format_generation(),
+        }
+    }
+}
+
+/// Notices the writer changing frame size between the frames a reader reads
+///
+/// The frame buffer is sized once, so a camera switching resolution keeps
+/// writing to the same buffer and readers stay attached; they only have to drop
+/// what they sized for the previous frames, such as preprocessing buffers.
+#[derive(Debug, Default)]
+pub struct FormatWatch {
+    current: Option<FrameFormat>,
+}
+
+impl FormatWatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the format of the frame being read, returning the previous one
+    /// when it changed. The first frame is not a change.
+    pub fn observe(&mut self, format: FrameFormat) -> Option<FrameFormat> {
+        let previous = self.current.replace(format)?;
+        (previous != format).then_some(previous)
+    }
+
+    /// Format of the last frame observed
+    pub fn current(&self) -> Option<FrameFormat> {
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(width: u32, height: u32, generation: u32) -> FrameFormat {
+        FrameFormat {
+            width,
+            height,
+            generation,
+        }
+    }
+
+    #[test]
+    fn test_format_watch_reports_changes_only() {
+        let mut watch = FormatWatch::new();
+        assert_eq!(watch.observe(format(1280, 720, 0)), None);
+        assert_eq!(watch.observe(format(1280, 720, 0)), None);
+
+        assert_eq!(
+            watch.observe(format(640, 480, 1)),
+            Some(format(1280, 720, 0))
+        );
+        // Back to the old size, with the frames in between skipped
+        assert_eq!(
+            watch.observe(format(640, 480, 3)),
+            Some(format(640, 480, 1))
+        );
+        assert_eq!(watch.current(), Some(format(640, 480, 3)));
+    }
+}
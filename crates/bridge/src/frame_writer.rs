@@ -1,21 +1,186 @@
-use crate::{macros::impl_mmap_writer_base, mmap_writer::MmapWriter, paths};
+use crate::{
+    clock,
+    compression::{self, FrameCompression},
+    errors::BridgeError,
+    huge_pages::HugePages,
+    macros::impl_mmap_writer_base,
+    mmap_writer::MmapWriter,
+    paths,
+    types::Provenance,
+};
 use anyhow::{Context, Result};
-use common::span;
-use schema::{Frame, FrameArgs, TraceContext};
-use std::time::{SystemTime, UNIX_EPOCH};
+use common::{Timestamp, WallClockNs, span};
+use schema::{Burst, Frame, FrameArgs, FrameEncoding, TraceContext};
+
+/// What the `set_next_*` calls attach to the next frame written
+struct NextFrame {
+    timestamp: Option<Timestamp>,
+    encoding: FrameEncoding,
+    original_size: Option<(u32, u32)>,
+    burst: Option<Burst>,
+    nvmm_surface: Option<u32>,
+}
 
 pub struct FrameWriter {
     writer: MmapWriter,
     builder: flatbuffers::FlatBufferBuilder<'static>,
+    provenance: Option<Provenance>,
+    nvmm_surface: Option<u32>,
+    original_size: Option<(u32, u32)>,
+    encoding: FrameEncoding,
+    burst: Option<Burst>,
+    timestamp: Option<Timestamp>,
+    compression: FrameCompression,
+    /// Compressed pixels of the frame being written, reused across frames
+    compressed: Vec<u8>,
+    /// Size of the last frame written
+    format: Option<(u32, u32)>,
+    /// Bumped when the frame size changes, written with every frame
+    format_generation: u32,
+    #[cfg(feature = "signing")]
+    signer: Option<crate::signing::FrameSigner>,
 }
 
 impl_mmap_writer_base!(
     FrameWriter,
+    crate::types::BufferKind::Frame,
     paths::FRAME_BUFFER_PATH,
-    paths::DEFAULT_FRAME_BUFFER_SIZE
+    paths::DEFAULT_FRAME_BUFFER_SIZE,
+    provenance: None,
+    nvmm_surface: None,
+    original_size: None,
+    encoding: FrameEncoding::Rgb,
+    burst: None,
+    timestamp: None,
+    compression: FrameCompression::None,
+    compressed: Vec::new(),
+    format: None,
+    format_generation: 0,
+    #[cfg(feature = "signing")]
+    signer: None,
 );
 
 impl FrameWriter {
+    /// Create the frame buffer at its default path, sized for `width` x `height` frames
+    ///
+    /// The buffer gets the default size, or more for frames that need it. When the
+    /// shared memory filesystem cannot hold the default size but can hold what the
+    /// frames need, the buffer is shrunk to that; when it cannot even hold that,
+    /// creation fails with the filesystem usage and the requirement.
+    pub fn build_for_resolution(width: u32, height: u32) -> Result<Self> {
+        let required = paths::frame_buffer_size(width, height);
+        let preferred = required.max(paths::DEFAULT_FRAME_BUFFER_SIZE);
+        let huge_pages = HugePages::from_env();
+
+        let path = paths::FRAME_BUFFER_PATH;
+        let size = match crate::shm_space::headroom(path, huge_pages.file_size(preferred)) {
+            Ok(headroom) if headroom < 0 && preferred > required => {
+                tracing::warn!(
+                    preferred,
+                    required,
+                    "Not enough shared memory for the default frame buffer, shrinking it"
+                );
+                required
+            }
+            _ => preferred,
+        };
+        Self::build_with_options(path, size, huge_pages).with_context(|| {
+            format!("Frame buffer for {width}x{height} frames needs {required} bytes")
+        })
+    }
+
+    /// Set the capture provenance attached to every subsequent frame.
+    /// Update it whenever capture settings change; `None` stops attaching it.
+    pub fn set_provenance(&mut self, provenance: Option<Provenance>) {
+        self.provenance = provenance;
+    }
+
+    pub fn provenance(&self) -> Option<&Provenance> {
+        self.provenance.as_ref()
+    }
+
+    /// Tag the next written frame with the NVMM surface holding a copy of it
+    pub fn set_next_nvmm_surface(&mut self, index: u32) {
+        self.nvmm_surface = Some(index);
+    }
+
+    /// Record the camera size the next written frame was downscaled from,
+    /// so readers can map coordinates back to the original frame
+    pub fn set_next_original_size(&mut self, width: u32, height: u32) {
+        self.original_size = Some((width, height));
+    }
+
+    /// Mark the next written frame's payload as `encoding` instead of RGB
+    ///
+    /// With [`FrameEncoding::Jpeg`], `pixel_data` holds the compressed image and
+    /// `width` x `height` is its decoded size.
+    pub fn set_next_encoding(&mut self, encoding: FrameEncoding) {
+        self.encoding = encoding;
+    }
+
+    /// Longest side `width` x `height` RGB frames must be downscaled to for this
+    /// buffer to hold them, `None` when they fit as they are
+    ///
+    /// Readers keep their mapping for the writer's lifetime, so a camera switching
+    /// to a larger resolution cannot get a larger buffer.
+    pub fn max_dimension_for(&self, width: u32, height: u32) -> Option<u32> {
+        // Buffers are sized with `frame_buffer_size`, header included
+        let size = self.writer.capacity() + crate::header::Header::SIZE;
+        if paths::frame_buffer_size(width, height) <= size {
+            return None;
+        }
+        let pixels = size.saturating_sub(paths::FRAME_BUFFER_OVERHEAD) / 3;
+        let scale = (pixels as f64 / (width as f64 * height as f64)).sqrt();
+        Some(
+            ((width.max(height) as f64 * scale) as u32)
+                .saturating_sub(1)
+                .max(1),
+        )
+    }
+
+    /// Generation written with the frames, see `format_generation` in the schema
+    pub fn format_generation(&self) -> u32 {
+        self.format_generation
+    }
+
+    /// Compress the RGB frames written from now on, see [`compression`]
+    pub fn set_compression(&mut self, compression: FrameCompression) {
+        self.compression = compression;
+    }
+
+    /// Sign every frame written from now on, see [`crate::signing`]
+    #[cfg(feature = "signing")]
+    pub fn set_signer(&mut self, signer: Option<crate::signing::FrameSigner>) {
+        self.signer = signer;
+    }
+
+    #[cfg(feature = "signing")]
+    pub fn signer(&self) -> Option<&crate::signing::FrameSigner> {
+        self.signer.as_ref()
+    }
+
+    /// Tag the next written frame as part of a burst
+    pub fn set_next_burst(&mut self, burst: Burst) {
+        self.burst = Some(burst);
+    }
+
+    /// Stamp the next written frame with its capture time, such as the driver's
+    /// buffer timestamp, instead of the wall clock time it is written at
+    pub fn set_next_timestamp(&mut self, timestamp: impl Into<Timestamp>) {
+        self.timestamp = Some(timestamp.into());
+    }
+
+    /// Consume the state the `set_next_*` calls left for the next frame
+    fn take_next(&mut self) -> NextFrame {
+        NextFrame {
+            timestamp: self.timestamp.take(),
+            encoding: std::mem::replace(&mut self.encoding, FrameEncoding::Rgb),
+            original_size: self.original_size.take(),
+            burst: self.burst.take(),
+            nvmm_surface: self.nvmm_surface.take(),
+        }
+    }
+
     pub fn write_frame(
         &mut self,
         camera_id: u32,
@@ -27,14 +192,48 @@ impl FrameWriter {
     ) -> Result<()> {
         let _s = span!("write_frame");
 
-        let timestamp_ns = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .context("Time went backwards")?
-            .as_nanos() as u64;
+        let next = self.take_next();
+        let (timestamp_ns, timestamp_clock) =
+            clock::to_schema(next.timestamp.unwrap_or_else(|| WallClockNs::now().into()));
+
+        let (pixel_data, encoding) = match (self.compression, next.encoding) {
+            (FrameCompression::Lz4, FrameEncoding::Rgb) => {
+                compression::compress_into(pixel_data, &mut self.compressed);
+                (&self.compressed[..], FrameEncoding::Lz4)
+            }
+            _ => (pixel_data, next.encoding),
+        };
 
         self.builder.reset();
         let pixels_vec = self.builder.create_vector(pixel_data);
+        let provenance = self
+            .provenance
+            .as_ref()
+            .map(|p| p.create(&mut self.builder));
+        #[cfg(feature = "signing")]
+        let signature = self.signer.as_ref().map(|signer| {
+            signer
+                .sign(frame_count, timestamp_ns, pixel_data)
+                .create(&mut self.builder)
+        });
+        #[cfg(not(feature = "signing"))]
+        let signature = None;
 
+        let (original_width, original_height) = next.original_size.unwrap_or((0, 0));
+        if self.format != Some((width, height)) {
+            if let Some((previous_width, previous_height)) = self.format {
+                self.format_generation = self.format_generation.wrapping_add(1);
+                tracing::info!(
+                    previous_width,
+                    previous_height,
+                    width,
+                    height,
+                    generation = self.format_generation,
+                    "Frame size changed"
+                );
+            }
+            self.format = Some((width, height));
+        }
         let frame_fb = Frame::create(
             &mut self.builder,
             &FrameArgs {
@@ -45,7 +244,16 @@ impl FrameWriter {
                 height,
                 channels: 3,
                 pixels: Some(pixels_vec),
+                original_width,
+                original_height,
+                nvmm_surface: next.nvmm_surface.map_or(-1, |i| i as i32),
                 trace: trace_ctx,
+                provenance,
+                encoding,
+                burst: next.burst.as_ref(),
+                timestamp_clock,
+                signature,
+                format_generation: self.format_generation,
             },
         );
 
@@ -58,4 +266,19 @@ impl FrameWriter {
 
         Ok(())
     }
+
+    /// Write a frame serialized elsewhere, the `finished_data()` of another builder.
+    /// Data that does not verify as a `Frame` is rejected before readers see it.
+    /// It is written as is, without being signed, and whatever the `set_next_*` calls
+    /// attached is dropped instead of landing on the next `write_frame`.
+    pub fn write_finished(&mut self, data: &[u8]) -> Result<()> {
+        flatbuffers::root::<Frame>(data)
+            .map_err(|_| BridgeError::InvalidFlatBuffer)
+            .context("Pre-serialized data is not a Frame")?;
+        self.take_next();
+        self.writer
+            .write(data)
+            .context("Failed to write frame data")?;
+        Ok(())
+    }
 }
@@ -1,4 +1,15 @@
-use std::sync::atomic::AtomicU64;
+use crate::errors::BridgeError;
+use crate::types::BufferKind;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// Identifies a file as a bridge buffer
+pub const MAGIC: [u8; 8] = *b"DETRBUF\0";
+
+/// Layout of the header, bumped on every change to it
+pub const VERSION: u32 = 2;
+
+/// Magic of the unversioned headers (version 1), whose fields sit elsewhere
+pub const LEGACY_MAGIC: [u8; 8] = *b"DETRMMAP";
 
 /// SAFETY & MEMORY ORDERING:
 ///
@@ -12,6 +23,8 @@ use std::sync::atomic::AtomicU64;
 /// 1. Load sequence with `Ordering::Acquire`
 /// 2. If sequence changed, payload is guaranteed visible
 ///
+/// Both sides go through [`crate::sequence`], which is model checked with loom.
+///
 /// The Release-Acquire pair ensures:
 /// - All payload writes happen-before the sequence store
 /// - All sequence loads happen-before payload reads
@@ -21,22 +34,159 @@ use std::sync::atomic::AtomicU64;
 /// The `#[repr(C, align(8))]` ensures AtomicU64 is always 8-byte aligned,
 /// which is required for atomic operations. This prevents UB even if the
 /// mmap offset changes.
+///
+/// Metadata:
+/// `magic` to `capacity`, except `writer_pid`, are written once when the writer
+/// creates the buffer, before the first sequence is published, and never change
+/// afterwards. `magic` and `version` identify the layout: a buffer with another
+/// layout is rejected by readers and reset by writers.
+///
+/// Writer token:
+/// Each writer claims the buffer with a random `writer_token` when it creates or
+/// opens it. A writer finding another token in place stops writing, and a reader
+/// seeing the token change knows another process took the buffer over.
 #[repr(C, align(8))]
 pub struct Header {
     /// Monotonically increasing sequence number.
     /// Starts at 0, increments on each write.
-    /// 0 means "no data written yet"
+    /// 0 means "no data written yet", reset when the buffer is created again
     pub sequence: AtomicU64,
+    /// Always [`MAGIC`]
+    pub magic: [u8; 8],
+    /// Always [`VERSION`]
+    pub version: u32,
+    /// Raw [`BufferKind`]
+    pub kind: u32,
+    /// PID of the process that created the buffer
+    pub creator_pid: u32,
+    /// PID of the writer owning the buffer
+    pub writer_pid: AtomicU32,
+    /// Creation time, nanoseconds since the Unix epoch
+    pub created_at_ns: u64,
+    /// Payload bytes available after the header
+    pub capacity: u64,
+    /// Random token of the writer currently owning the buffer, 0 if none claimed it
+    pub writer_token: AtomicU64,
 }
 
 impl Header {
     pub const SIZE: usize = std::mem::size_of::<Self>();
+
+    /// Stamp the metadata of a freshly created buffer of `mapped_len` bytes
+    #[cfg(feature = "mmap-writer")]
+    pub(crate) fn init(&mut self, kind: BufferKind, mapped_len: usize) {
+        self.magic = MAGIC;
+        self.version = VERSION;
+        self.kind = kind as u32;
+        self.creator_pid = std::process::id();
+        self.created_at_ns = common::WallClockNs::now().as_nanos();
+        self.capacity = (mapped_len - Self::SIZE) as u64;
+    }
+
+    /// Make this process the buffer's writer, returning its token
+    #[cfg(feature = "mmap-writer")]
+    pub(crate) fn claim(&self) -> u64 {
+        use std::hash::{BuildHasher, Hasher};
+
+        let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+        hasher.write_u32(std::process::id());
+        hasher.write_u64(common::WallClockNs::now().as_nanos());
+        let token = hasher.finish().max(1);
+        self.writer_pid.store(std::process::id(), Ordering::Relaxed);
+        self.writer_token.store(token, Ordering::Release);
+        token
+    }
+
+    /// Token of the writer owning the buffer
+    pub(crate) fn writer_token(&self) -> u64 {
+        self.writer_token.load(Ordering::Acquire)
+    }
+
+    /// PID of the writer owning the buffer
+    pub(crate) fn writer_pid(&self) -> u32 {
+        self.writer_pid.load(Ordering::Relaxed)
+    }
+
+    /// Whether the header has this build's layout
+    #[cfg_attr(not(feature = "mmap-writer"), allow(dead_code))]
+    pub(crate) fn is_current(&self) -> bool {
+        self.magic == MAGIC && self.version == VERSION
+    }
+
+    /// Check that this is a `expected` buffer whose payload fits in `mapped_len` bytes
+    pub(crate) fn validate(
+        &self,
+        expected: BufferKind,
+        mapped_len: usize,
+    ) -> Result<(), BridgeError> {
+        if self.magic == LEGACY_MAGIC {
+            return Err(BridgeError::InvalidHeader(format!(
+                "expected {} buffer, found an unversioned header from an older bridge; \
+                 restart its writer to recreate the buffer",
+                expected
+            )));
+        }
+        if self.magic != MAGIC {
+            return Err(BridgeError::InvalidHeader(format!(
+                "expected {} buffer, found no bridge header",
+                expected
+            )));
+        }
+        if self.version != VERSION {
+            return Err(BridgeError::InvalidHeader(format!(
+                "expected {} buffer with header version {}, found version {}",
+                expected, VERSION, self.version
+            )));
+        }
+
+        match BufferKind::from_raw(self.kind) {
+            Some(found) if found == expected => {}
+            Some(found) => {
+                return Err(BridgeError::BufferKindMismatch {
+                    expected,
+                    found,
+                    creator_pid: self.creator_pid,
+                });
+            }
+            None => {
+                return Err(BridgeError::InvalidHeader(format!(
+                    "expected {} buffer, found unknown buffer type {} created by pid {}",
+                    expected, self.kind, self.creator_pid
+                )));
+            }
+        }
+
+        if self.capacity > (mapped_len - Self::SIZE) as u64 {
+            return Err(BridgeError::InvalidHeader(format!(
+                "{} buffer created by pid {} declares {} payload bytes but only {} are mapped",
+                expected,
+                self.creator_pid,
+                self.capacity,
+                mapped_len - Self::SIZE
+            )));
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn header() -> Header {
+        Header {
+            sequence: AtomicU64::new(0),
+            magic: [0; 8],
+            version: 0,
+            kind: 0,
+            creator_pid: 0,
+            writer_pid: AtomicU32::new(0),
+            created_at_ns: 0,
+            capacity: 0,
+            writer_token: AtomicU64::new(0),
+        }
+    }
+
     #[test]
     fn test_header_alignment() {
         assert_eq!(
@@ -50,8 +200,91 @@ mod tests {
     fn test_header_size() {
         assert_eq!(
             Header::SIZE,
-            8,
-            "Header should be exactly 8 bytes (just the sequence)"
+            56,
+            "Header should be exactly 56 bytes (sequence + metadata + writer)"
+        );
+    }
+
+    #[test]
+    fn test_validate_accepts_own_kind() {
+        let mut header = header();
+        header.init(BufferKind::Frame, 1024);
+
+        assert_eq!(header.creator_pid, std::process::id());
+        assert_eq!(header.capacity, 1024 - Header::SIZE as u64);
+        assert!(header.validate(BufferKind::Frame, 1024).is_ok());
+    }
+
+    #[test]
+    fn test_validate_reports_kind_and_creator() {
+        let mut header = header();
+        header.init(BufferKind::Detection, 1024);
+        header.creator_pid = 1234;
+
+        let err = header.validate(BufferKind::Frame, 1024).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected frame buffer, found detection buffer created by pid 1234"
         );
     }
+
+    #[test]
+    fn test_validate_rejects_other_layouts() {
+        let mut header = header();
+        header.init(BufferKind::Frame, 1024);
+        assert!(header.is_current());
+
+        header.version = VERSION + 1;
+        assert!(!header.is_current());
+        assert_eq!(
+            header
+                .validate(BufferKind::Frame, 1024)
+                .unwrap_err()
+                .to_string(),
+            format!(
+                "Invalid buffer header: expected frame buffer with header version {}, found version {}",
+                VERSION,
+                VERSION + 1
+            )
+        );
+
+        header.magic = LEGACY_MAGIC;
+        assert!(!header.is_current());
+        assert!(
+            header
+                .validate(BufferKind::Frame, 1024)
+                .unwrap_err()
+                .to_string()
+                .contains("unversioned header")
+        );
+    }
+
+    #[test]
+    fn test_claim_replaces_the_writer_token() {
+        let header = header();
+        let first = header.claim();
+        assert_ne!(first, 0);
+        assert_eq!(header.writer_token(), first);
+        assert_eq!(header.writer_pid(), std::process::id());
+
+        let second = header.claim();
+        assert_ne!(second, first);
+        assert_eq!(header.writer_token(), second);
+    }
+
+    #[test]
+    fn test_validate_rejects_missing_magic_and_truncation() {
+        let header_without_magic = header();
+        assert!(matches!(
+            header_without_magic.validate(BufferKind::Frame, 1024),
+            Err(BridgeError::InvalidHeader(_))
+        ));
+
+        let mut header = header();
+        header.init(BufferKind::Frame, 1024);
+        assert!(matches!(
+            header.validate(BufferKind::Frame, 512),
+            Err(BridgeError::InvalidHeader(_))
+        ));
+    }
 }
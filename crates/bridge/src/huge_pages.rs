@@ -0,0 +1,165 @@
+//! Huge page backing for mmap buffers
+//!
+//! A 4K RGB frame is ~24MB, i.e. ~6000 standard 4KB pages per frame copy. Backing
+//! the buffer with 2MB pages cuts TLB misses on both the writer and reader side.
+//!
+//! Two mechanisms are supported:
+//! - `Transparent`: `madvise(MADV_HUGEPAGE)` on the tmpfs mapping, effective when
+//!   `/sys/kernel/mm/transparent_hugepage/shmem_enabled` is `advise` (or `within_size`)
+//! - `Hugetlb`: explicit huge pages, which requires the buffer file to live on a
+//!   hugetlbfs mount with enough reserved pages (`vm.nr_hugepages`). Any mapping of a
+//!   hugetlbfs file is huge-page backed, as with `MAP_HUGETLB` for anonymous memory
+//!
+//! Both degrade gracefully: a buffer outside hugetlbfs falls back to transparent huge
+//! pages, and a failed `madvise` to standard pages.
+
+#[cfg(feature = "mmap-writer")]
+use memmap2::{MmapMut, MmapOptions};
+#[cfg(feature = "mmap-writer")]
+use std::fs::File;
+use std::str::FromStr;
+
+/// Size of a huge page on x86_64 and aarch64 (4KB granule)
+pub const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
+/// Page backing requested for a bridge buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HugePages {
+    /// Standard pages
+    #[default]
+    Off,
+    /// Transparent huge pages via `madvise(MADV_HUGEPAGE)`
+    Transparent,
+    /// Explicit huge pages via `MAP_HUGETLB` (hugetlbfs-backed file)
+    Hugetlb,
+}
+
+impl FromStr for HugePages {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" | "none" | "false" | "0" => Ok(Self::Off),
+            "transparent" | "thp" => Ok(Self::Transparent),
+            "hugetlb" | "hugetlbfs" => Ok(Self::Hugetlb),
+            other => Err(format!("unknown huge pages mode: {}", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for HugePages {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Off => "off",
+            Self::Transparent => "transparent",
+            Self::Hugetlb => "hugetlb",
+        })
+    }
+}
+
+impl HugePages {
+    /// Read the mode from `BRIDGE_HUGE_PAGES` (`off`, `transparent` or `hugetlb`)
+    pub fn from_env() -> Self {
+        common::get_env("BRIDGE_HUGE_PAGES", Self::Off)
+    }
+
+    /// Round a buffer size so it can be backed by whole huge pages
+    #[cfg(feature = "mmap-writer")]
+    pub(crate) fn file_size(self, size: usize) -> usize {
+        match self {
+            Self::Off => size,
+            Self::Transparent | Self::Hugetlb => size.next_multiple_of(HUGE_PAGE_SIZE),
+        }
+    }
+
+    /// Map `file` read-write with the requested backing
+    ///
+    /// Returns the mapping together with the mode actually in effect after fallbacks.
+    #[cfg(feature = "mmap-writer")]
+    pub(crate) fn map_mut(self, file: &File) -> std::io::Result<(MmapMut, HugePages)> {
+        let mmap = unsafe { MmapOptions::new().map_mut(file)? };
+
+        let effective = match self {
+            Self::Off => Self::Off,
+            Self::Hugetlb if is_hugetlbfs(file) => Self::Hugetlb,
+            Self::Transparent | Self::Hugetlb => {
+                if self == Self::Hugetlb {
+                    tracing::warn!(
+                        "Buffer is not on hugetlbfs, falling back to transparent huge pages"
+                    );
+                }
+                match advise_huge_pages(&mmap) {
+                    Ok(()) => Self::Transparent,
+                    Err(e) => {
+                        tracing::warn!(error = %e, "madvise(MADV_HUGEPAGE) failed, using standard pages");
+                        Self::Off
+                    }
+                }
+            }
+        };
+
+        Ok((mmap, effective))
+    }
+}
+
+/// `madvise(MADV_HUGEPAGE)`, which only Linux has
+#[cfg(feature = "mmap-writer")]
+fn advise_huge_pages(mmap: &MmapMut) -> std::io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        mmap.advise(memmap2::Advice::HugePage)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = mmap;
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Whether `file` lives on a hugetlbfs mount
+#[cfg(feature = "mmap-writer")]
+fn is_hugetlbfs(file: &File) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        use nix::sys::statfs::{HUGETLBFS_MAGIC, fstatfs};
+        fstatfs(file).is_ok_and(|stat| stat.filesystem_type() == HUGETLBFS_MAGIC)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = file;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_modes() {
+        assert_eq!("off".parse::<HugePages>(), Ok(HugePages::Off));
+        assert_eq!("THP".parse::<HugePages>(), Ok(HugePages::Transparent));
+        assert_eq!("hugetlb".parse::<HugePages>(), Ok(HugePages::Hugetlb));
+        assert!("huge".parse::<HugePages>().is_err());
+    }
+
+    #[cfg(feature = "mmap-writer")]
+    #[test]
+    fn test_regular_file_is_not_hugetlbfs() {
+        let file = tempfile::tempfile().unwrap();
+        assert!(!is_hugetlbfs(&file));
+    }
+
+    #[cfg(feature = "mmap-writer")]
+    #[test]
+    fn test_file_size_rounds_to_huge_pages() {
+        assert_eq!(HugePages::Off.file_size(1000), 1000);
+        assert_eq!(HugePages::Transparent.file_size(1000), HUGE_PAGE_SIZE);
+        assert_eq!(
+            HugePages::Hugetlb.file_size(HUGE_PAGE_SIZE + 1),
+            2 * HUGE_PAGE_SIZE
+        );
+    }
+}
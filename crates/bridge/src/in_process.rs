@@ -0,0 +1,53 @@
+//! Buffers shared between threads of one process instead of through `/dev/shm`
+//!
+//! An `InProcessBuffer` is an anonymous memory file (`memfd_create`) laid out
+//! like the shared memory buffers, header and sequence included, so the frame
+//! and detection readers and writers work on it unchanged: create the buffer,
+//! then hand it to their `in_process` constructors. This runs the pipeline
+//! stages as threads of a single binary, for demos and for machines where
+//! `/dev/shm` is missing or too small. Nothing outside the process can open it.
+
+use crate::errors::BridgeError;
+use crate::huge_pages::HugePages;
+use crate::mmap_reader::MmapReader;
+use crate::mmap_writer::MmapWriter;
+use crate::paths;
+use crate::types::BufferKind;
+use nix::sys::memfd::{MFdFlags, memfd_create};
+use std::fs::File;
+
+pub struct InProcessBuffer {
+    file: File,
+}
+
+impl InProcessBuffer {
+    /// Create an empty `kind` buffer of `size` bytes, header included
+    pub fn new(kind: BufferKind, size: usize) -> Result<Self, BridgeError> {
+        let name = format!("bridge_{kind}");
+        let file = File::from(
+            memfd_create(name.as_str(), MFdFlags::MFD_CLOEXEC).map_err(std::io::Error::from)?,
+        );
+        MmapWriter::init_file(&file, size, kind, HugePages::Off)?;
+        Ok(Self { file })
+    }
+
+    /// Frame buffer of the default size
+    pub fn frames() -> Result<Self, BridgeError> {
+        Self::new(BufferKind::Frame, paths::DEFAULT_FRAME_BUFFER_SIZE)
+    }
+
+    /// Detection buffer of the default size
+    pub fn detections() -> Result<Self, BridgeError> {
+        Self::new(BufferKind::Detection, paths::DEFAULT_DETECTION_BUFFER_SIZE)
+    }
+
+    /// Writer continuing the buffer's sequence, failing unless it holds a `kind` buffer
+    pub(crate) fn writer(&self, kind: BufferKind) -> Result<MmapWriter, BridgeError> {
+        MmapWriter::open_file(&self.file, kind, HugePages::Off)
+    }
+
+    /// Reader of the buffer, failing unless it holds a `kind` buffer
+    pub(crate) fn reader(&self, kind: BufferKind) -> Result<MmapReader, BridgeError> {
+        MmapReader::from_file(self.file.try_clone()?, kind, HugePages::Off)
+    }
+}
@@ -1,6 +1,8 @@
 // Core modules (always available)
+pub mod clock;
 pub mod errors;
 pub mod paths;
+pub mod permissions;
 pub mod types;
 
 // Trace context for distributed tracing (requires tracing feature)
@@ -19,39 +21,105 @@ pub(crate) mod macros;
 pub(crate) mod utils;
 
 // Conditionally compiled modules
+#[cfg(feature = "frame-reader")]
+pub mod burst;
+#[cfg(any(feature = "frame-reader", feature = "frame-writer"))]
+pub mod compression;
+#[cfg(feature = "semaphores")]
+pub mod consumer_registry;
+#[cfg(feature = "controller-status")]
+pub mod controller_status;
 #[cfg(feature = "detection-reader")]
 pub mod detection_reader;
 #[cfg(feature = "detection-writer")]
 pub mod detection_writer;
 #[cfg(feature = "frame-reader")]
+pub mod frame_bundler;
+#[cfg(feature = "frame-reader")]
 pub mod frame_reader;
 #[cfg(feature = "frame-writer")]
 pub mod frame_writer;
 #[cfg(any(feature = "mmap-reader", feature = "mmap-writer"))]
 pub(crate) mod header;
+#[cfg(any(feature = "mmap-reader", feature = "mmap-writer"))]
+pub mod huge_pages;
+#[cfg(feature = "in-process")]
+pub mod in_process;
 #[cfg(feature = "mmap-reader")]
 pub(crate) mod mmap_reader;
 #[cfg(feature = "mmap-writer")]
 pub(crate) mod mmap_writer;
+#[cfg(feature = "nvmm")]
+pub mod nvmm;
+#[cfg(feature = "pipeline-clock")]
+pub mod pipeline_clock;
+#[cfg(any(feature = "semaphores", feature = "write-gate"))]
+pub(crate) mod process;
 #[cfg(feature = "semaphores")]
 pub mod semaphore;
 #[cfg(feature = "sentry")]
 pub mod sentry_control;
+#[cfg(any(feature = "mmap-reader", feature = "mmap-writer"))]
+pub(crate) mod sequence;
+#[cfg(feature = "mmap-writer")]
+pub(crate) mod shm_space;
+#[cfg(feature = "signing")]
+pub mod signing;
+#[cfg(feature = "snapshots")]
+pub mod snapshot_control;
+#[cfg(feature = "dmabuf")]
+pub mod surface_share;
+#[cfg(all(feature = "frame-reader", feature = "detection-reader"))]
+pub mod synced_reader;
+#[cfg(feature = "thresholds")]
+pub mod threshold_control;
+#[cfg(feature = "write-gate")]
+pub mod write_gate;
 
 // Public re-exports
+#[cfg(feature = "frame-reader")]
+pub use burst::BurstSelector;
+pub use clock::CapturedAt;
+#[cfg(any(feature = "frame-reader", feature = "frame-writer"))]
+pub use compression::{FrameCompression, decompress_into, frame_payload};
+#[cfg(feature = "semaphores")]
+pub use consumer_registry::{ConsumerRegistry, FrameFanout, FrameSubscription};
+#[cfg(feature = "controller-status")]
+pub use controller_status::{AlarmPhase, ControllerSnapshot, ControllerStatus};
 #[cfg(feature = "detection-reader")]
 pub use detection_reader::DetectionReader;
 #[cfg(feature = "detection-writer")]
 pub use detection_writer::DetectionWriter;
 pub use errors::BridgeError;
 #[cfg(feature = "frame-reader")]
-pub use frame_reader::FrameReader;
+pub use frame_bundler::{BundledFrame, FrameBundle, FrameBundler};
+#[cfg(feature = "frame-reader")]
+pub use frame_reader::{FormatWatch, FrameFormat, FrameReader};
 #[cfg(feature = "frame-writer")]
 pub use frame_writer::FrameWriter;
+#[cfg(any(feature = "mmap-reader", feature = "mmap-writer"))]
+pub use huge_pages::HugePages;
+#[cfg(feature = "in-process")]
+pub use in_process::InProcessBuffer;
+pub use permissions::ShmPermissions;
+#[cfg(feature = "pipeline-clock")]
+pub use pipeline_clock::{ClockSample, PipelineClock, PipelineTime};
 #[cfg(feature = "semaphores")]
 pub use semaphore::{BridgeSemaphore, SemaphoreType};
 #[cfg(feature = "sentry")]
 pub use sentry_control::{SentryControl, SentryMode};
+#[cfg(feature = "signing")]
+pub use signing::{FrameSignature, FrameSigner};
+#[cfg(feature = "snapshots")]
+pub use snapshot_control::SnapshotControl;
+#[cfg(feature = "dmabuf")]
+pub use surface_share::{SurfaceExporter, import_surfaces};
+#[cfg(all(feature = "frame-reader", feature = "detection-reader"))]
+pub use synced_reader::{FramePair, OwnedFrame, SyncedReader};
+#[cfg(feature = "thresholds")]
+pub use threshold_control::{Threshold, ThresholdControl};
 #[cfg(feature = "tracing")]
 pub use trace_context::{capture_current_trace, set_trace_parent};
-pub use types::Detection;
+pub use types::{BufferKind, Detection, InferenceTiming, Provenance};
+#[cfg(feature = "write-gate")]
+pub use write_gate::{GateSubscription, WriteGate};
@@ -1,45 +1,117 @@
-/// Generates common MmapWriter boilerplate methods: `build()`, `build_with_path()`, `sequence()`
+/// Generates common MmapWriter boilerplate methods: `build()`, `build_with_path()`,
+/// `build_with_options()`, `in_process()`, `sequence()`, `huge_pages()` and, with
+/// the `write-gate` feature, `set_write_gate()` and `take_write_gate_overruns()`
+///
+/// `build()` picks the huge page mode from `BRIDGE_HUGE_PAGES`.
+///
+/// Extra fields beyond `writer` and `builder` can be initialized with trailing
+/// `field: expr` pairs.
 #[cfg(any(feature = "frame-writer", feature = "detection-writer"))]
 macro_rules! impl_mmap_writer_base {
-    ($struct_name:ident, $default_path:expr, $default_size:expr) => {
+    ($struct_name:ident, $kind:expr, $default_path:expr, $default_size:expr $(, $(#[$attr:meta])* $field:ident: $init:expr)* $(,)?) => {
         impl $struct_name {
             pub fn build() -> anyhow::Result<Self> {
-                Self::build_with_path($default_path, $default_size)
+                Self::build_with_options(
+                    $default_path,
+                    $default_size,
+                    crate::huge_pages::HugePages::from_env(),
+                )
             }
 
             pub fn build_with_path(mmap_path: &str, mmap_size: usize) -> anyhow::Result<Self> {
+                Self::build_with_options(mmap_path, mmap_size, crate::huge_pages::HugePages::Off)
+            }
+
+            pub fn build_with_options(
+                mmap_path: &str,
+                mmap_size: usize,
+                huge_pages: crate::huge_pages::HugePages,
+            ) -> anyhow::Result<Self> {
                 use anyhow::Context;
                 use std::path::Path;
 
                 let writer = if Path::new(mmap_path).exists() {
-                    crate::mmap_writer::MmapWriter::open_existing(mmap_path)
+                    crate::mmap_writer::MmapWriter::open_existing_with(mmap_path, $kind, huge_pages)
                         .context("Failed to open existing mmap writer")?
                 } else {
-                    crate::mmap_writer::MmapWriter::create_and_init(mmap_path, mmap_size)
-                        .context("Failed to create new mmap writer")?
+                    crate::mmap_writer::MmapWriter::create_and_init_with(
+                        mmap_path, mmap_size, $kind, huge_pages,
+                    )
+                    .context("Failed to create new mmap writer")?
                 };
                 let builder = flatbuffers::FlatBufferBuilder::new();
-                Ok(Self { writer, builder })
+                Ok(Self {
+                    writer,
+                    builder,
+                    $($(#[$attr])* $field: $init,)*
+                })
+            }
+
+            /// Writer of a buffer shared within this process
+            #[cfg(feature = "in-process")]
+            pub fn in_process(buffer: &crate::in_process::InProcessBuffer) -> anyhow::Result<Self> {
+                let writer = buffer.writer($kind)?;
+                let builder = flatbuffers::FlatBufferBuilder::new();
+                Ok(Self {
+                    writer,
+                    builder,
+                    $($(#[$attr])* $field: $init,)*
+                })
             }
 
             pub fn sequence(&self) -> u64 {
                 self.writer.sequence()
             }
+
+            /// Page backing in effect after any fallback
+            pub fn huge_pages(&self) -> crate::huge_pages::HugePages {
+                self.writer.huge_pages()
+            }
+
+            /// Wait for the readers registered on `gate` before replacing each
+            /// message, see [`crate::write_gate`]; `None` stops waiting
+            #[cfg(feature = "write-gate")]
+            pub fn set_write_gate(&mut self, gate: Option<crate::write_gate::WriteGate>) {
+                self.writer.set_gate(gate);
+            }
+
+            /// Messages published over a lagging reader since the last call, 0
+            /// without a write gate
+            #[cfg(feature = "write-gate")]
+            pub fn take_write_gate_overruns(&mut self) -> u64 {
+                self.writer.gate_mut().map_or(0, |gate| gate.take_overruns())
+            }
         }
     };
 }
 
-/// Generates common MmapReader boilerplate methods: `build()`, `with_path()`, `current_sequence()`, `mark_read()`
+/// Generates common MmapReader boilerplate methods: `build()`, `with_path()`, `with_options()`,
+/// `in_process()`, `current_sequence()`, `mark_read()`, `writer_changed()`
 #[cfg(any(feature = "frame-reader", feature = "detection-reader"))]
 macro_rules! impl_mmap_reader_base {
-    ($struct_name:ident, $default_path:expr) => {
+    ($struct_name:ident, $kind:expr, $default_path:expr) => {
         impl $struct_name {
             pub fn build() -> anyhow::Result<Self> {
-                Self::with_path($default_path)
+                Self::with_options($default_path, crate::huge_pages::HugePages::from_env())
             }
 
             pub fn with_path(mmap_path: &str) -> anyhow::Result<Self> {
-                let reader = crate::mmap_reader::MmapReader::build(mmap_path)?;
+                Self::with_options(mmap_path, crate::huge_pages::HugePages::Off)
+            }
+
+            pub fn with_options(
+                mmap_path: &str,
+                huge_pages: crate::huge_pages::HugePages,
+            ) -> anyhow::Result<Self> {
+                let reader =
+                    crate::mmap_reader::MmapReader::build_with(mmap_path, $kind, huge_pages)?;
+                Ok(Self { reader })
+            }
+
+            /// Reader of a buffer shared within this process
+            #[cfg(feature = "in-process")]
+            pub fn in_process(buffer: &crate::in_process::InProcessBuffer) -> anyhow::Result<Self> {
+                let reader = buffer.reader($kind)?;
                 Ok(Self { reader })
             }
 
@@ -50,6 +122,12 @@ macro_rules! impl_mmap_reader_base {
             pub fn mark_read(&mut self) {
                 self.reader.mark_read();
             }
+
+            /// PID of the new writer if another process claimed the buffer since
+            /// the last call, e.g. a restarted writer or an unexpected one
+            pub fn writer_changed(&mut self) -> Option<u32> {
+                self.reader.writer_changed()
+            }
         }
     };
 }
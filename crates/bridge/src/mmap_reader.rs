@@ -1,25 +1,70 @@
 use crate::errors::BridgeError;
 use crate::header::Header;
-use memmap2::{Mmap, MmapOptions};
+use crate::huge_pages::HugePages;
+use crate::sequence;
+use crate::types::BufferKind;
+use memmap2::{Advice, Mmap, MmapOptions};
 use std::fs::File;
 use std::path::Path;
-use std::sync::atomic::Ordering;
 
 pub(crate) struct MmapReader {
     _file: File,
     mmap: Mmap,
     last_sequence: u64,
+    /// Writer token seen last, see [`Header::claim`]
+    writer_token: u64,
 }
 
 impl MmapReader {
-    pub fn build(path: impl AsRef<Path>) -> Result<Self, BridgeError> {
-        let file = File::open(path)?;
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn build(path: impl AsRef<Path>, kind: BufferKind) -> Result<Self, BridgeError> {
+        Self::build_with(path, kind, HugePages::Off)
+    }
+
+    /// Map the buffer, asking for huge pages when the writer uses them.
+    ///
+    /// hugetlbfs files are huge-page backed whatever the reader does; for transparent
+    /// huge pages the reader mapping must opt in too to get huge TLB entries.
+    ///
+    /// Fails if the file is not a `kind` buffer, naming what was found instead.
+    pub fn build_with(
+        path: impl AsRef<Path>,
+        kind: BufferKind,
+        huge_pages: HugePages,
+    ) -> Result<Self, BridgeError> {
+        Self::from_file(File::open(path)?, kind, huge_pages)
+    }
+
+    /// Same as `build_with()` on an already open file
+    pub fn from_file(
+        file: File,
+        kind: BufferKind,
+        huge_pages: HugePages,
+    ) -> Result<Self, BridgeError> {
         let mmap = unsafe { MmapOptions::new().map(&file)? };
 
+        if mmap.len() <= Header::SIZE {
+            return Err(BridgeError::InvalidHeader(format!(
+                "expected {} buffer, found a {} byte file",
+                kind,
+                mmap.len()
+            )));
+        }
+        let header = unsafe { &*(mmap.as_ptr() as *const Header) };
+        header.validate(kind, mmap.len())?;
+
+        if huge_pages != HugePages::Off
+            && let Err(e) = mmap.advise(Advice::HugePage)
+        {
+            tracing::debug!(error = %e, "madvise(MADV_HUGEPAGE) failed on reader mapping");
+        }
+
+        let writer_token = header.writer_token();
         Ok(Self {
             _file: file,
             mmap,
             last_sequence: 0,
+            writer_token,
         })
     }
 
@@ -28,22 +73,21 @@ impl MmapReader {
     /// SAFETY: Uses Ordering::Acquire to ensure all payload writes
     /// are visible after observing a new sequence number.
     pub fn current_sequence(&self) -> u64 {
-        let header = unsafe { &*(self.mmap.as_ptr() as *const Header) };
-        header.sequence.load(Ordering::Acquire)
+        sequence::current(&self.header().sequence)
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { &*(self.mmap.as_ptr() as *const Header) }
     }
 
     /// Checks if new data is available and returns the new sequence if so.
     ///
     /// Returns Some(seq) if there is new data, None otherwise.
     /// This avoids double-loading the sequence number.
+    /// A sequence below the last one read means the writer recreated the buffer.
     #[cfg_attr(not(test), allow(dead_code))]
     pub fn has_new_data(&self) -> Option<u64> {
-        let seq = self.current_sequence();
-        if seq > self.last_sequence {
-            Some(seq)
-        } else {
-            None
-        }
+        sequence::observe(&self.header().sequence, self.last_sequence)
     }
 
     /// Returns data buffer (skips the header)
@@ -57,10 +101,7 @@ impl MmapReader {
     #[cfg_attr(not(test), allow(dead_code))]
     #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
     pub fn read_frame(&self) -> Option<(u64, &[u8])> {
-        let seq1 = self.current_sequence();
-        if seq1 <= self.last_sequence {
-            return None;
-        }
+        let seq1 = self.has_new_data()?;
         let buf = self.buffer();
         let seq2 = self.current_sequence();
         if seq1 != seq2 {
@@ -70,6 +111,17 @@ impl MmapReader {
         Some((seq1, buf))
     }
 
+    /// PID of the new writer if another one claimed the buffer since the last
+    /// call (or since the reader was built)
+    pub fn writer_changed(&mut self) -> Option<u32> {
+        let token = self.header().writer_token();
+        if token == self.writer_token {
+            return None;
+        }
+        self.writer_token = token;
+        Some(self.header().writer_pid())
+    }
+
     /// Mark current sequence as read
     pub fn mark_read(&mut self) {
         self.last_sequence = self.current_sequence();
@@ -102,10 +154,10 @@ mod tests {
         let path = temp_file.path();
 
         // Create a writer to initialize the file
-        let _writer = MmapWriter::create_and_init(path, 1024).unwrap();
+        let _writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
 
         // Create reader and verify it starts with sequence 0
-        let reader = MmapReader::build(path).unwrap();
+        let reader = MmapReader::build(path, BufferKind::Frame).unwrap();
         assert_eq!(
             reader.last_sequence(),
             0,
@@ -113,16 +165,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_reader_rejects_other_buffer_kinds() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path();
+
+        let _writer = MmapWriter::create_and_init(path, 1024, BufferKind::Detection).unwrap();
+
+        let err = MmapReader::build(path, BufferKind::Frame).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "expected frame buffer, found detection buffer created by pid {}",
+                std::process::id()
+            )
+        );
+
+        // A file that was never initialized by a writer is rejected too
+        let empty = NamedTempFile::new().unwrap();
+        empty.as_file().set_len(1024).unwrap();
+        assert!(matches!(
+            MmapReader::build(empty.path(), BufferKind::Frame),
+            Err(BridgeError::InvalidHeader(_))
+        ));
+    }
+
     #[test]
     fn test_has_new_data_returns_none_when_sequence_zero() {
         let temp_file = NamedTempFile::new().unwrap();
         let path = temp_file.path();
 
         // Create a writer but don't write any data
-        let _writer = MmapWriter::create_and_init(path, 1024).unwrap();
+        let _writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
 
         // Reader should report no new data when sequence is 0
-        let reader = MmapReader::build(path).unwrap();
+        let reader = MmapReader::build(path, BufferKind::Frame).unwrap();
         assert!(
             reader.has_new_data().is_none(),
             "has_new_data should return None when sequence is 0"
@@ -134,8 +211,8 @@ mod tests {
         let temp_file = NamedTempFile::new().unwrap();
         let path = temp_file.path();
 
-        let mut writer = MmapWriter::create_and_init(path, 1024).unwrap();
-        let reader = MmapReader::build(path).unwrap();
+        let mut writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
+        let reader = MmapReader::build(path, BufferKind::Frame).unwrap();
 
         // Initially no new data
         assert!(reader.has_new_data().is_none());
@@ -156,8 +233,8 @@ mod tests {
         let temp_file = NamedTempFile::new().unwrap();
         let path = temp_file.path();
 
-        let mut writer = MmapWriter::create_and_init(path, 1024).unwrap();
-        let mut reader = MmapReader::build(path).unwrap();
+        let mut writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
+        let mut reader = MmapReader::build(path, BufferKind::Frame).unwrap();
 
         // Write data (sequence becomes 1)
         writer.write(&[1, 2, 3]).unwrap();
@@ -187,13 +264,13 @@ mod tests {
         let temp_file = NamedTempFile::new().unwrap();
         let path = temp_file.path();
 
-        let mut writer = MmapWriter::create_and_init(path, 1024).unwrap();
+        let mut writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
 
         // Write known data
         let test_data = b"Hello, World!";
         writer.write(test_data).unwrap();
 
-        let reader = MmapReader::build(path).unwrap();
+        let reader = MmapReader::build(path, BufferKind::Frame).unwrap();
         let buffer = reader.buffer();
 
         // Buffer should skip the 8-byte header and start with our data
@@ -209,8 +286,8 @@ mod tests {
         let temp_file = NamedTempFile::new().unwrap();
         let path = temp_file.path();
 
-        let _writer = MmapWriter::create_and_init(path, 1024).unwrap();
-        let reader = MmapReader::build(path).unwrap();
+        let _writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
+        let reader = MmapReader::build(path, BufferKind::Frame).unwrap();
 
         // No data written yet
         assert!(
@@ -224,8 +301,8 @@ mod tests {
         let temp_file = NamedTempFile::new().unwrap();
         let path = temp_file.path();
 
-        let mut writer = MmapWriter::create_and_init(path, 1024).unwrap();
-        let mut reader = MmapReader::build(path).unwrap();
+        let mut writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
+        let mut reader = MmapReader::build(path, BufferKind::Frame).unwrap();
 
         // Write data
         writer.write(b"test data").unwrap();
@@ -255,6 +332,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_reader_follows_recreated_buffer() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path();
+
+        let mut writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
+        let mut reader = MmapReader::build(path, BufferKind::Frame).unwrap();
+        for _ in 0..5 {
+            writer.write(b"old").unwrap();
+        }
+        reader.mark_read();
+        assert_eq!(reader.last_sequence(), 5);
+
+        // A restarted writer starts over from 0
+        let mut writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
+        assert_eq!(reader.has_new_data(), None);
+        writer.write(b"new").unwrap();
+
+        let (seq, buf) = reader.read_frame().expect("new writer's frame");
+        assert_eq!(seq, 1);
+        assert_eq!(&buf[..3], b"new");
+    }
+
     #[test]
     fn test_concurrent_reads_during_writes_are_consistent() {
         use std::sync::Barrier;
@@ -264,7 +364,8 @@ mod tests {
 
         // Initialize file with proper size
         {
-            let writer = MmapWriter::create_and_init(path.as_ref(), 1024).unwrap();
+            let writer =
+                MmapWriter::create_and_init(path.as_ref(), 1024, BufferKind::Frame).unwrap();
             drop(writer);
         }
 
@@ -275,7 +376,8 @@ mod tests {
         let writer_path = Arc::clone(&path);
         let writer_barrier = Arc::clone(&barrier);
         let writer_handle = thread::spawn(move || {
-            let mut writer = MmapWriter::open_existing(writer_path.as_ref()).unwrap();
+            let mut writer =
+                MmapWriter::open_existing(writer_path.as_ref(), BufferKind::Frame).unwrap();
             writer_barrier.wait(); // Wait for all threads to be ready
 
             for i in 1..=100 {
@@ -291,7 +393,7 @@ mod tests {
                 let path = Arc::clone(&path);
                 let barrier = Arc::clone(&barrier);
                 thread::spawn(move || {
-                    let mut reader = MmapReader::build(path.as_ref()).unwrap();
+                    let mut reader = MmapReader::build(path.as_ref(), BufferKind::Frame).unwrap();
                     barrier.wait(); // Wait for all threads to be ready
 
                     let mut last_seq = 0;
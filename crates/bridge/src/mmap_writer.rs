@@ -1,24 +1,58 @@
 use crate::errors::BridgeError;
-use crate::header::Header;
-use memmap2::{MmapMut, MmapOptions};
-use std::fs::OpenOptions;
+use crate::header::{Header, MAGIC, VERSION};
+use crate::huge_pages::HugePages;
+use crate::sequence;
+use crate::types::BufferKind;
+#[cfg(feature = "write-gate")]
+use crate::write_gate::WriteGate;
+use memmap2::MmapMut;
+use std::fs::{File, OpenOptions};
 use std::os::unix::fs::OpenOptionsExt;
 use std::path::Path;
-use std::sync::atomic::Ordering;
 
 pub(crate) struct MmapWriter {
     mmap: MmapMut,
     sequence: u64,
+    huge_pages: HugePages,
+    /// Token claimed in the header, see [`Header::claim`]
+    token: u64,
+    /// Readers to wait for before replacing a message
+    #[cfg(feature = "write-gate")]
+    gate: Option<WriteGate>,
 }
 
 impl MmapWriter {
     /// Create or open an mmap file and reset the sequence to 0.
     ///
     /// Creates the file if it doesn't exist, expands it if undersized.
-    /// Resets the sequence number to 0 (readers will wait for new data).
+    /// Resets the sequence number to 0 (readers will wait for new data, then take
+    /// the restarted sequence as new even if below what they last saw) and stamps
+    /// the header with `kind`, this process and the payload capacity.
     ///
     /// Use `open_existing()` instead if you want to preserve the sequence.
-    pub fn create_and_init(path: impl AsRef<Path>, size: usize) -> Result<Self, BridgeError> {
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn create_and_init(
+        path: impl AsRef<Path>,
+        size: usize,
+        kind: BufferKind,
+    ) -> Result<Self, BridgeError> {
+        Self::create_and_init_with(path, size, kind, HugePages::Off)
+    }
+
+    /// Same as `create_and_init()`, backing the buffer with huge pages when possible.
+    ///
+    /// The file size is rounded up to a whole number of huge pages.
+    pub fn create_and_init_with(
+        path: impl AsRef<Path>,
+        size: usize,
+        kind: BufferKind,
+        huge_pages: HugePages,
+    ) -> Result<Self, BridgeError> {
+        if size <= Header::SIZE {
+            return Err(BridgeError::SizeMismatch);
+        }
+        let size = huge_pages.file_size(size);
+        crate::shm_space::ensure_space(path.as_ref(), size)?;
         let file = OpenOptions::new()
             .read(true)
             .write(true)
@@ -26,19 +60,44 @@ impl MmapWriter {
             .truncate(false)
             .mode(0o600)
             .open(&path)?;
+        crate::permissions::secure(&file)?;
+        Self::init_file(&file, size, kind, huge_pages)
+    }
+
+    /// Same as `create_and_init_with()` on an already open file, which is grown to
+    /// `size` if smaller
+    pub fn init_file(
+        file: &File,
+        size: usize,
+        kind: BufferKind,
+        huge_pages: HugePages,
+    ) -> Result<Self, BridgeError> {
+        if size <= Header::SIZE {
+            return Err(BridgeError::SizeMismatch);
+        }
 
         // Only resize if the file is smaller than needed
         if file.metadata()?.len() < size as u64 {
             file.set_len(size as u64)?;
         }
 
-        let mut mmap = unsafe { MmapOptions::new().map_mut(&file)? };
+        let (mut mmap, huge_pages) = huge_pages.map_mut(file)?;
 
-        // Initialize sequence number to 0
+        // Stamp the metadata, then initialize sequence number to 0
+        let mapped_len = mmap.len();
         let header = unsafe { &mut *(mmap.as_mut_ptr() as *mut Header) };
-        header.sequence.store(0, Ordering::Release);
-
-        Ok(Self { mmap, sequence: 0 })
+        header.init(kind, mapped_len);
+        let token = header.claim();
+        sequence::publish(&header.sequence, 0);
+
+        Ok(Self {
+            mmap,
+            sequence: 0,
+            huge_pages,
+            token,
+            #[cfg(feature = "write-gate")]
+            gate: None,
+        })
     }
 
     /// Open an existing mmap file and preserve the sequence number.
@@ -46,17 +105,65 @@ impl MmapWriter {
     /// Use this when a writer restarts and you want to continue from where
     /// the previous writer left off. Readers will not miss a beat.
     ///
-    /// Returns an error if the file doesn't exist.
-    pub fn open_existing(path: impl AsRef<Path>) -> Result<Self, BridgeError> {
+    /// Returns an error if the file doesn't exist, holds another kind of buffer or
+    /// has a header newer than this build. A file with an older header or none is
+    /// reset as if created: its layout cannot be trusted, so the sequence restarts
+    /// at 0 and the payload is zeroed.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn open_existing(path: impl AsRef<Path>, kind: BufferKind) -> Result<Self, BridgeError> {
+        Self::open_existing_with(path, kind, HugePages::Off)
+    }
+
+    /// Same as `open_existing()`, backing the buffer with huge pages when possible.
+    pub fn open_existing_with(
+        path: impl AsRef<Path>,
+        kind: BufferKind,
+        huge_pages: HugePages,
+    ) -> Result<Self, BridgeError> {
         let file = OpenOptions::new().read(true).write(true).open(path)?;
+        crate::permissions::secure(&file)?;
+        Self::open_file(&file, kind, huge_pages)
+    }
 
-        let mmap = unsafe { MmapOptions::new().map_mut(&file)? };
+    /// Same as `open_existing_with()` on an already open file
+    pub fn open_file(
+        file: &File,
+        kind: BufferKind,
+        huge_pages: HugePages,
+    ) -> Result<Self, BridgeError> {
+        let (mut mmap, huge_pages) = huge_pages.map_mut(file)?;
+        if mmap.len() <= Header::SIZE {
+            return Err(BridgeError::SizeMismatch);
+        }
 
-        // Read current sequence from file (don't reset to 0)
-        let header = unsafe { &*(mmap.as_ptr() as *const Header) };
-        let sequence = header.sequence.load(Ordering::Acquire);
+        let mapped_len = mmap.len();
+        let keep = {
+            let header = unsafe { &*(mmap.as_ptr() as *const Header) };
+            header.is_current() || (header.magic == MAGIC && header.version > VERSION)
+        };
+        if !keep {
+            tracing::warn!(kind = %kind, "Buffer has no header of this version, resetting it");
+            mmap.fill(0);
+        }
+        let header = unsafe { &mut *(mmap.as_mut_ptr() as *mut Header) };
+        if keep {
+            header.validate(kind, mapped_len)?;
+        } else {
+            header.init(kind, mapped_len);
+        }
 
-        Ok(Self { mmap, sequence })
+        // Read current sequence from file (0 if just reset)
+        let sequence = sequence::current(&header.sequence);
+        let token = header.claim();
+
+        Ok(Self {
+            mmap,
+            sequence,
+            huge_pages,
+            token,
+            #[cfg(feature = "write-gate")]
+            gate: None,
+        })
     }
 
     /// Write data to the buffer and publish with sequence increment.
@@ -66,9 +173,12 @@ impl MmapWriter {
     /// 2. Sequence is published with Ordering::Release
     ///
     /// This guarantees readers using Acquire will see the complete payload.
+    ///
+    /// Fails with [`BridgeError::WriterTakeover`], leaving the buffer untouched,
+    /// once another writer has claimed the buffer.
     #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, data), fields(data_len = data.len())))]
     pub fn write(&mut self, data: &[u8]) -> Result<(), BridgeError> {
-        let available_space = self.mmap.len() - Header::SIZE;
+        let available_space = self.capacity();
         if data.len() > available_space {
             tracing::error!(
                 "Buffer too small: data={} bytes, available={} bytes, mmap={}",
@@ -79,13 +189,25 @@ impl MmapWriter {
             return Err(BridgeError::SizeMismatch);
         }
 
+        #[cfg(feature = "write-gate")]
+        if let Some(gate) = self.gate.as_mut() {
+            gate.wait(self.sequence);
+        }
+
+        let header = unsafe { &*(self.mmap.as_ptr() as *const Header) };
+        if header.writer_token() != self.token {
+            return Err(BridgeError::WriterTakeover {
+                pid: header.writer_pid(),
+            });
+        }
+
         // Write payload first
         self.mmap[Header::SIZE..Header::SIZE + data.len()].copy_from_slice(data);
 
         // Publish with Release ordering (happens-after payload write)
         self.sequence += 1;
         let header = unsafe { &mut *(self.mmap.as_mut_ptr() as *mut Header) };
-        header.sequence.store(self.sequence, Ordering::Release);
+        sequence::publish(&header.sequence, self.sequence);
 
         Ok(())
     }
@@ -108,6 +230,26 @@ impl MmapWriter {
     pub fn sequence(&self) -> u64 {
         self.sequence
     }
+
+    /// Largest payload a write accepts
+    pub fn capacity(&self) -> usize {
+        self.mmap.len() - Header::SIZE
+    }
+
+    /// Page backing in effect after any fallback
+    pub fn huge_pages(&self) -> HugePages {
+        self.huge_pages
+    }
+
+    #[cfg(feature = "write-gate")]
+    pub fn set_gate(&mut self, gate: Option<WriteGate>) {
+        self.gate = gate;
+    }
+
+    #[cfg(feature = "write-gate")]
+    pub fn gate_mut(&mut self) -> Option<&mut WriteGate> {
+        self.gate.as_mut()
+    }
 }
 
 #[cfg(test)]
@@ -122,13 +264,13 @@ mod tests {
         let temp_file = NamedTempFile::new().unwrap();
         let path = temp_file.path();
 
-        let writer = MmapWriter::create_and_init(path, 1024).unwrap();
+        let writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
 
         // Internal sequence should be 0
         assert_eq!(writer.sequence(), 0, "New writer should have sequence = 0");
 
         // Sequence in mmap should also be 0
-        let reader = MmapReader::build(path).unwrap();
+        let reader = MmapReader::build(path, BufferKind::Frame).unwrap();
         assert_eq!(
             reader.current_sequence(),
             0,
@@ -141,7 +283,7 @@ mod tests {
         let temp_file = NamedTempFile::new().unwrap();
         let path = temp_file.path();
 
-        let mut writer = MmapWriter::create_and_init(path, 1024).unwrap();
+        let mut writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
 
         assert_eq!(writer.sequence(), 0);
 
@@ -162,7 +304,7 @@ mod tests {
         );
 
         // Verify reader sees the atomic updates
-        let reader = MmapReader::build(path).unwrap();
+        let reader = MmapReader::build(path, BufferKind::Frame).unwrap();
         assert_eq!(
             reader.current_sequence(),
             2,
@@ -175,14 +317,14 @@ mod tests {
         let temp_file = NamedTempFile::new().unwrap();
         let path = temp_file.path();
 
-        let mut writer = MmapWriter::create_and_init(path, 1024).unwrap();
+        let mut writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
         let test_data = b"Memory ordering test";
 
         // Write data
         writer.write(test_data).unwrap();
 
         // Reader should see both the updated sequence AND the data
-        let reader = MmapReader::build(path).unwrap();
+        let reader = MmapReader::build(path, BufferKind::Frame).unwrap();
         assert_eq!(reader.current_sequence(), 1, "Sequence should be updated");
 
         let buffer = reader.buffer();
@@ -198,7 +340,7 @@ mod tests {
         let temp_file = NamedTempFile::new().unwrap();
         let path = temp_file.path();
 
-        let mut writer = MmapWriter::create_and_init(path, 1024).unwrap();
+        let mut writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
 
         // Write and flush
         writer.write(b"flushed data").unwrap();
@@ -206,7 +348,7 @@ mod tests {
 
         // Create a new reader (forces re-reading from disk)
         drop(writer);
-        let reader = MmapReader::build(path).unwrap();
+        let reader = MmapReader::build(path, BufferKind::Frame).unwrap();
 
         assert_eq!(
             reader.current_sequence(),
@@ -228,7 +370,7 @@ mod tests {
         let temp_file = NamedTempFile::new().unwrap();
         let path = temp_file.path();
 
-        let mut writer = MmapWriter::create_and_init(path, 1024).unwrap();
+        let mut writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
 
         // Write directly to buffer
         let buffer = writer.buffer_mut();
@@ -242,7 +384,7 @@ mod tests {
         header.sequence.store(writer.sequence, Ordering::Release);
 
         // Verify data is readable
-        let reader = MmapReader::build(path).unwrap();
+        let reader = MmapReader::build(path, BufferKind::Frame).unwrap();
         let read_buffer = reader.buffer();
         assert_eq!(read_buffer[0], 42);
         assert_eq!(read_buffer[1], 43);
@@ -256,7 +398,7 @@ mod tests {
 
         // Initial writer creates and writes some frames
         {
-            let mut writer = MmapWriter::create_and_init(path, 1024).unwrap();
+            let mut writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
             writer.write(b"frame 1").unwrap();
             writer.write(b"frame 2").unwrap();
             writer.write(b"frame 3").unwrap();
@@ -264,7 +406,7 @@ mod tests {
         } // Writer drops
 
         // New writer opens existing file (simulates writer restart)
-        let mut writer = MmapWriter::open_existing(path).unwrap();
+        let mut writer = MmapWriter::open_existing(path, BufferKind::Frame).unwrap();
 
         // Sequence should be preserved from file
         assert_eq!(
@@ -278,26 +420,98 @@ mod tests {
         assert_eq!(writer.sequence(), 4);
 
         // Reader should see sequence 4
-        let reader = MmapReader::build(path).unwrap();
+        let reader = MmapReader::build(path, BufferKind::Frame).unwrap();
         assert_eq!(reader.current_sequence(), 4);
     }
 
+    #[test]
+    fn test_open_existing_resets_older_layouts_and_checks_kind() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path();
+
+        // Buffer left behind by a version without metadata: sequence then payload
+        let mut legacy = vec![0xABu8; 1024];
+        legacy[..8].copy_from_slice(&3u64.to_ne_bytes());
+        std::fs::write(path, &legacy).unwrap();
+
+        let mut writer = MmapWriter::open_existing(path, BufferKind::Detection).unwrap();
+        assert_eq!(
+            writer.sequence(),
+            0,
+            "the old sequence counted another layout"
+        );
+        assert!(writer.buffer_mut().iter().all(|&b| b == 0));
+        drop(writer);
+
+        assert!(MmapReader::build(path, BufferKind::Detection).is_ok());
+        assert!(matches!(
+            MmapWriter::open_existing(path, BufferKind::Frame),
+            Err(BridgeError::BufferKindMismatch {
+                expected: BufferKind::Frame,
+                found: BufferKind::Detection,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_open_existing_resets_unversioned_headers() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path();
+
+        // 40 byte header of the first metadata layout, without a version
+        let mut legacy = vec![0xABu8; 1024];
+        legacy[..8].copy_from_slice(&7u64.to_ne_bytes());
+        legacy[8..16].copy_from_slice(&crate::header::LEGACY_MAGIC);
+        legacy[16..20].copy_from_slice(&(BufferKind::Frame as u32).to_ne_bytes());
+        std::fs::write(path, &legacy).unwrap();
+
+        assert!(matches!(
+            MmapReader::build(path, BufferKind::Frame),
+            Err(BridgeError::InvalidHeader(_))
+        ));
+
+        let mut writer = MmapWriter::open_existing(path, BufferKind::Frame).unwrap();
+        assert_eq!(writer.sequence(), 0);
+        assert!(writer.buffer_mut().iter().all(|&b| b == 0));
+        writer.write(b"frame").unwrap();
+
+        let reader = MmapReader::build(path, BufferKind::Frame).unwrap();
+        assert_eq!(reader.current_sequence(), 1);
+    }
+
+    #[test]
+    fn test_open_existing_refuses_newer_layouts() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path();
+        MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
+
+        let mut bytes = std::fs::read(path).unwrap();
+        bytes[16..20].copy_from_slice(&(VERSION + 1).to_ne_bytes());
+        std::fs::write(path, &bytes).unwrap();
+
+        assert!(matches!(
+            MmapWriter::open_existing(path, BufferKind::Frame),
+            Err(BridgeError::InvalidHeader(_))
+        ));
+    }
+
     #[test]
     fn test_open_existing_safe_with_concurrent_readers() {
         let temp_file = NamedTempFile::new().unwrap();
         let path = temp_file.path();
 
         // Initial setup
-        let mut writer = MmapWriter::create_and_init(path, 1024).unwrap();
+        let mut writer = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
         writer.write(b"initial").unwrap();
         drop(writer);
 
         // Reader opens file and stays open
-        let mut reader = MmapReader::build(path).unwrap();
+        let mut reader = MmapReader::build(path, BufferKind::Frame).unwrap();
         assert_eq!(reader.current_sequence(), 1);
 
         // Writer restarts using open_existing() - should NOT cause SIGBUS
-        let mut writer = MmapWriter::open_existing(path).unwrap();
+        let mut writer = MmapWriter::open_existing(path, BufferKind::Frame).unwrap();
 
         // Write new data - reader should see it (no crash)
         writer.write(b"new data").unwrap();
@@ -308,12 +522,61 @@ mod tests {
 
         // Multiple reopens should all be safe
         drop(writer);
-        let mut writer = MmapWriter::open_existing(path).unwrap();
+        let mut writer = MmapWriter::open_existing(path, BufferKind::Frame).unwrap();
         writer.write(b"more data").unwrap();
 
         assert_eq!(reader.current_sequence(), 3);
     }
 
+    #[test]
+    fn test_second_writer_takes_over() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path();
+
+        let mut first = MmapWriter::create_and_init(path, 1024, BufferKind::Frame).unwrap();
+        let mut reader = MmapReader::build(path, BufferKind::Frame).unwrap();
+        first.write(b"first").unwrap();
+        assert_eq!(reader.writer_changed(), None);
+
+        let mut second = MmapWriter::open_existing(path, BufferKind::Frame).unwrap();
+        assert!(matches!(
+            first.write(b"stale"),
+            Err(BridgeError::WriterTakeover { pid }) if pid == std::process::id()
+        ));
+        assert_eq!(
+            reader.current_sequence(),
+            1,
+            "The old writer must not publish"
+        );
+
+        second.write(b"second").unwrap();
+        assert_eq!(reader.writer_changed(), Some(std::process::id()));
+        assert_eq!(reader.writer_changed(), None);
+        assert_eq!(&reader.buffer()[..6], b"second");
+    }
+
+    #[test]
+    fn test_huge_pages_fall_back_on_regular_files() {
+        use crate::huge_pages::HUGE_PAGE_SIZE;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let path = temp_file.path();
+
+        // A temp file is not on hugetlbfs, so MAP_HUGETLB must fall back
+        let mut writer =
+            MmapWriter::create_and_init_with(path, 1024, BufferKind::Frame, HugePages::Hugetlb)
+                .unwrap();
+        assert_ne!(writer.huge_pages(), HugePages::Hugetlb);
+        assert_eq!(
+            std::fs::metadata(path).unwrap().len(),
+            HUGE_PAGE_SIZE as u64
+        );
+
+        writer.write(b"huge").unwrap();
+        let reader = MmapReader::build(path, BufferKind::Frame).unwrap();
+        assert_eq!(&reader.buffer()[..4], b"huge");
+    }
+
     #[test]
     fn test_concurrent_producer_consumer() {
         use std::thread;
@@ -330,7 +593,12 @@ mod tests {
 
         // Producer thread
         let producer = thread::spawn(move || {
-            let mut writer = MmapWriter::create_and_init(&path_producer, FRAME_SIZE + 8).unwrap();
+            let mut writer = MmapWriter::create_and_init(
+                &path_producer,
+                FRAME_SIZE + Header::SIZE,
+                BufferKind::Frame,
+            )
+            .unwrap();
             thread::sleep(Duration::from_millis(50));
 
             for i in 1..=NUM_FRAMES {
@@ -347,7 +615,7 @@ mod tests {
         // Consumer thread
         let consumer = thread::spawn(move || {
             thread::sleep(Duration::from_millis(20));
-            let mut reader = MmapReader::build(&path_consumer).unwrap();
+            let mut reader = MmapReader::build(&path_consumer, BufferKind::Frame).unwrap();
             let mut frames_seen = Vec::new();
 
             let start = std::time::Instant::now();
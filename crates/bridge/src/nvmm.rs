@@ -0,0 +1,340 @@
+//! Jetson NVMM surfaces (`libnvbufsurface`)
+//!
+//! NVMM buffers live in memory the VIC, the ISP and the GPU can all address. A
+//! surface allocated here is exported as a dmabuf descriptor, shared through
+//! [`SurfaceExporter`](crate::surface_share::SurfaceExporter) and re-imported by the
+//! consumer with [`NvmmSurface::from_fd`], so a frame written by capture can be
+//! converted and normalized on the consumer side without a CPU copy.
+//!
+//! Only the subset of `nvbufsurface.h` used by the pipeline is declared.
+
+use crate::errors::BridgeError;
+use std::ffi::c_void;
+use std::os::fd::{AsRawFd, BorrowedFd, OwnedFd};
+
+pub mod ffi {
+    //! Raw declarations from `nvbufsurface.h` (JetPack 5/6)
+
+    use std::ffi::{c_int, c_void};
+
+    pub const NVBUF_MAX_PLANES: usize = 4;
+    const STRUCTURE_PADDING: usize = 4;
+
+    pub const NVBUF_MEM_SURFACE_ARRAY: u32 = 4;
+    pub const NVBUF_LAYOUT_PITCH: u32 = 0;
+    pub const NVBUF_COLOR_FORMAT_YUYV: u32 = 14;
+    pub const NVBUF_COLOR_FORMAT_RGBA: u32 = 19;
+    pub const NVBUF_MAP_READ: u32 = 0;
+    pub const NVBUF_MAP_WRITE: u32 = 1;
+
+    #[repr(C)]
+    pub struct NvBufSurfacePlaneParams {
+        pub num_planes: u32,
+        pub width: [u32; NVBUF_MAX_PLANES],
+        pub height: [u32; NVBUF_MAX_PLANES],
+        pub pitch: [u32; NVBUF_MAX_PLANES],
+        pub offset: [u32; NVBUF_MAX_PLANES],
+        pub psize: [u32; NVBUF_MAX_PLANES],
+        pub bytes_per_pix: [u32; NVBUF_MAX_PLANES],
+        _reserved: [*mut c_void; STRUCTURE_PADDING * NVBUF_MAX_PLANES],
+    }
+
+    #[repr(C)]
+    pub struct NvBufSurfaceMappedAddr {
+        pub addr: [*mut c_void; NVBUF_MAX_PLANES],
+        pub egl_image: *mut c_void,
+        _reserved: [*mut c_void; STRUCTURE_PADDING],
+    }
+
+    #[repr(C)]
+    pub struct NvBufSurfaceParams {
+        pub width: u32,
+        pub height: u32,
+        pub pitch: u32,
+        pub color_format: u32,
+        pub layout: u32,
+        /// dmabuf descriptor for `NVBUF_MEM_SURFACE_ARRAY` surfaces
+        pub buffer_desc: u64,
+        pub data_size: u32,
+        pub data_ptr: *mut c_void,
+        pub plane_params: NvBufSurfacePlaneParams,
+        pub mapped_addr: NvBufSurfaceMappedAddr,
+        pub paramex: *mut c_void,
+        _reserved: [*mut c_void; STRUCTURE_PADDING - 1],
+    }
+
+    #[repr(C)]
+    pub struct NvBufSurface {
+        pub gpu_id: u32,
+        pub batch_size: u32,
+        pub num_filled: u32,
+        pub is_contiguous: bool,
+        pub mem_type: u32,
+        pub surface_list: *mut NvBufSurfaceParams,
+        _reserved: [*mut c_void; STRUCTURE_PADDING],
+    }
+
+    #[repr(C)]
+    pub struct NvBufSurfaceCreateParams {
+        pub gpu_id: u32,
+        pub width: u32,
+        pub height: u32,
+        pub size: u32,
+        pub is_contiguous: bool,
+        pub color_format: u32,
+        pub layout: u32,
+        pub mem_type: u32,
+    }
+
+    // Layouts of the C structures on the 64-bit Jetson targets
+    #[cfg(target_pointer_width = "64")]
+    const _: () = {
+        use std::mem::{offset_of, size_of};
+
+        assert!(size_of::<NvBufSurfacePlaneParams>() == 232);
+        assert!(offset_of!(NvBufSurfacePlaneParams, pitch) == 36);
+        assert!(offset_of!(NvBufSurfacePlaneParams, _reserved) == 104);
+
+        assert!(size_of::<NvBufSurfaceMappedAddr>() == 72);
+        assert!(offset_of!(NvBufSurfaceMappedAddr, egl_image) == 32);
+
+        assert!(size_of::<NvBufSurfaceParams>() == 384);
+        assert!(offset_of!(NvBufSurfaceParams, buffer_desc) == 24);
+        assert!(offset_of!(NvBufSurfaceParams, data_size) == 32);
+        assert!(offset_of!(NvBufSurfaceParams, data_ptr) == 40);
+        assert!(offset_of!(NvBufSurfaceParams, plane_params) == 48);
+        assert!(offset_of!(NvBufSurfaceParams, mapped_addr) == 280);
+        assert!(offset_of!(NvBufSurfaceParams, paramex) == 352);
+
+        assert!(size_of::<NvBufSurface>() == 64);
+        assert!(offset_of!(NvBufSurface, mem_type) == 16);
+        assert!(offset_of!(NvBufSurface, surface_list) == 24);
+
+        assert!(size_of::<NvBufSurfaceCreateParams>() == 32);
+        assert!(offset_of!(NvBufSurfaceCreateParams, color_format) == 20);
+        assert!(offset_of!(NvBufSurfaceCreateParams, mem_type) == 28);
+    };
+
+    #[link(name = "nvbufsurface")]
+    unsafe extern "C" {
+        pub fn NvBufSurfaceCreate(
+            surf: *mut *mut NvBufSurface,
+            batch_size: u32,
+            params: *mut NvBufSurfaceCreateParams,
+        ) -> c_int;
+        pub fn NvBufSurfaceDestroy(surf: *mut NvBufSurface) -> c_int;
+        pub fn NvBufSurfaceFromFd(dmabuf_fd: c_int, buffer: *mut *mut c_void) -> c_int;
+        pub fn NvBufSurfaceMap(
+            surf: *mut NvBufSurface,
+            index: c_int,
+            plane: c_int,
+            flags: u32,
+        ) -> c_int;
+        pub fn NvBufSurfaceUnMap(surf: *mut NvBufSurface, index: c_int, plane: c_int) -> c_int;
+        pub fn NvBufSurfaceSyncForDevice(
+            surf: *mut NvBufSurface,
+            index: c_int,
+            plane: c_int,
+        ) -> c_int;
+        pub fn NvBufSurfaceMemSet(
+            surf: *mut NvBufSurface,
+            index: c_int,
+            plane: c_int,
+            value: u8,
+        ) -> c_int;
+        pub fn NvBufSurfaceMapEglImage(surf: *mut NvBufSurface, index: c_int) -> c_int;
+        pub fn NvBufSurfaceUnMapEglImage(surf: *mut NvBufSurface, index: c_int) -> c_int;
+    }
+}
+
+/// Value a reader holds on the NVMM write gate while reading surface `index`
+pub fn surface_hold(index: u32) -> u64 {
+    index as u64 + 1
+}
+
+fn check(ret: i32, what: &str) -> Result<(), BridgeError> {
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(BridgeError::IoError(std::io::Error::other(format!(
+            "{} failed ({})",
+            what, ret
+        ))))
+    }
+}
+
+/// Pixel layouts used by the pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NvmmFormat {
+    /// Packed YUV 4:2:2 as delivered by UVC cameras
+    Yuyv,
+    /// Packed 8-bit RGBA
+    Rgba,
+}
+
+impl NvmmFormat {
+    fn color_format(self) -> u32 {
+        match self {
+            Self::Yuyv => ffi::NVBUF_COLOR_FORMAT_YUYV,
+            Self::Rgba => ffi::NVBUF_COLOR_FORMAT_RGBA,
+        }
+    }
+}
+
+/// A single-plane pitch-linear NVMM surface
+pub struct NvmmSurface {
+    surface: *mut ffi::NvBufSurface,
+    /// Surfaces imported from a descriptor are owned by the exporting process
+    owned: bool,
+    mapped: bool,
+    egl_mapped: bool,
+}
+
+// The surface is only accessed through &mut self or read-only hardware operations
+unsafe impl Send for NvmmSurface {}
+
+impl NvmmSurface {
+    pub fn allocate(width: u32, height: u32, format: NvmmFormat) -> Result<Self, BridgeError> {
+        let mut params = ffi::NvBufSurfaceCreateParams {
+            gpu_id: 0,
+            width,
+            height,
+            size: 0,
+            is_contiguous: true,
+            color_format: format.color_format(),
+            layout: ffi::NVBUF_LAYOUT_PITCH,
+            mem_type: ffi::NVBUF_MEM_SURFACE_ARRAY,
+        };
+        let mut surface = std::ptr::null_mut();
+        check(
+            unsafe { ffi::NvBufSurfaceCreate(&mut surface, 1, &mut params) },
+            "NvBufSurfaceCreate",
+        )?;
+        unsafe { (*surface).num_filled = 1 };
+
+        Ok(Self {
+            surface,
+            owned: true,
+            mapped: false,
+            egl_mapped: false,
+        })
+    }
+
+    /// Wrap a surface exported by another process
+    ///
+    /// `fd` must stay open for the lifetime of the surface.
+    pub fn from_fd(fd: BorrowedFd<'_>) -> Result<Self, BridgeError> {
+        let mut surface: *mut c_void = std::ptr::null_mut();
+        check(
+            unsafe { ffi::NvBufSurfaceFromFd(fd.as_raw_fd(), &mut surface) },
+            "NvBufSurfaceFromFd",
+        )?;
+
+        Ok(Self {
+            surface: surface.cast(),
+            owned: false,
+            mapped: false,
+            egl_mapped: false,
+        })
+    }
+
+    fn params(&self) -> &ffi::NvBufSurfaceParams {
+        unsafe { &*(*self.surface).surface_list }
+    }
+
+    pub fn raw(&self) -> *mut ffi::NvBufSurface {
+        self.surface
+    }
+
+    pub fn width(&self) -> u32 {
+        self.params().width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.params().height
+    }
+
+    /// Bytes per row, including hardware alignment padding
+    pub fn pitch(&self) -> u32 {
+        self.params().pitch
+    }
+
+    /// Duplicate the dmabuf descriptor backing the surface, for export
+    pub fn export_fd(&self) -> Result<OwnedFd, BridgeError> {
+        let fd = self.params().buffer_desc as i32;
+        // SAFETY: the descriptor is valid while the surface is alive, we only duplicate it
+        let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+        Ok(borrowed.try_clone_to_owned()?)
+    }
+
+    /// Set every byte of the surface to `value`
+    pub fn fill(&mut self, value: u8) -> Result<(), BridgeError> {
+        check(
+            unsafe { ffi::NvBufSurfaceMemSet(self.surface, 0, 0, value) },
+            "NvBufSurfaceMemSet",
+        )
+    }
+
+    /// Copy rows of `row_bytes` spaced `stride` apart into the surface, honouring its pitch
+    pub fn write_rows(
+        &mut self,
+        data: &[u8],
+        stride: usize,
+        row_bytes: usize,
+    ) -> Result<(), BridgeError> {
+        let rows = self.height() as usize;
+        let pitch = self.pitch() as usize;
+        if row_bytes > pitch || row_bytes > stride || data.len() < rows * stride {
+            return Err(BridgeError::SizeMismatch);
+        }
+
+        if !self.mapped {
+            check(
+                unsafe { ffi::NvBufSurfaceMap(self.surface, 0, 0, ffi::NVBUF_MAP_WRITE) },
+                "NvBufSurfaceMap",
+            )?;
+            self.mapped = true;
+        }
+
+        let dst = self.params().mapped_addr.addr[0] as *mut u8;
+        for (row, src) in data.chunks_exact(stride).take(rows).enumerate() {
+            // SAFETY: the mapping spans `rows * pitch` bytes and `row_bytes <= pitch`
+            unsafe {
+                std::ptr::copy_nonoverlapping(src.as_ptr(), dst.add(row * pitch), row_bytes);
+            }
+        }
+
+        check(
+            unsafe { ffi::NvBufSurfaceSyncForDevice(self.surface, 0, 0) },
+            "NvBufSurfaceSyncForDevice",
+        )
+    }
+
+    /// EGLImage of the surface, for registration with CUDA
+    pub fn egl_image(&mut self) -> Result<*mut c_void, BridgeError> {
+        if !self.egl_mapped {
+            check(
+                unsafe { ffi::NvBufSurfaceMapEglImage(self.surface, 0) },
+                "NvBufSurfaceMapEglImage",
+            )?;
+            self.egl_mapped = true;
+        }
+        Ok(self.params().mapped_addr.egl_image)
+    }
+}
+
+impl Drop for NvmmSurface {
+    fn drop(&mut self) {
+        unsafe {
+            if self.egl_mapped {
+                ffi::NvBufSurfaceUnMapEglImage(self.surface, 0);
+            }
+            if self.mapped {
+                ffi::NvBufSurfaceUnMap(self.surface, 0, 0);
+            }
+            if self.owned {
+                ffi::NvBufSurfaceDestroy(self.surface);
+            }
+        }
+    }
+}
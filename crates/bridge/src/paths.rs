@@ -6,15 +6,65 @@
 //! Having these in one place ensures:
 //! - No path mismatches between producers and consumers
 //! - Single source of truth for IPC configuration
+//!
+//! Buffers live in `/dev/shm` (tmpfs) on Linux. Other hosts have no shared memory
+//! filesystem, and POSIX `shm_open` objects have no path the services could share
+//! or wait for, so buffers are plain files in `/tmp` there, mapped the same way.
+//! That is meant for development on macOS laptops, not for production.
+
+#[cfg(target_os = "linux")]
+macro_rules! shm_dir {
+    () => {
+        "/dev/shm"
+    };
+}
+
+#[cfg(not(target_os = "linux"))]
+macro_rules! shm_dir {
+    () => {
+        "/tmp"
+    };
+}
+
+/// Directory holding the shared buffers and, without message queues, the signal FIFOs
+pub const SHM_DIR: &str = shm_dir!();
 
 /// Frame buffer path - used by capture (write) and inference + gateway (read)
-pub const FRAME_BUFFER_PATH: &str = "/dev/shm/bridge_frame_buffer";
+pub const FRAME_BUFFER_PATH: &str = concat!(shm_dir!(), "/bridge_frame_buffer");
 
 /// Detection buffer path - used by inference (write) and gateway + controller (read)
-pub const DETECTION_BUFFER_PATH: &str = "/dev/shm/bridge_detection_buffer";
+pub const DETECTION_BUFFER_PATH: &str = concat!(shm_dir!(), "/bridge_detection_buffer");
 
 /// Sentry control shared memory path - used by controller (write) and capture (read)
-pub const SENTRY_CONTROL_PATH: &str = "/dev/shm/bridge_sentry_control";
+pub const SENTRY_CONTROL_PATH: &str = concat!(shm_dir!(), "/bridge_sentry_control");
+
+/// Threshold override path - used by gateway (write) and inference + controller (read)
+pub const THRESHOLD_CONTROL_PATH: &str = concat!(shm_dir!(), "/bridge_threshold_control");
+
+/// Snapshot request path - used by controller (write) and gateway (read)
+pub const SNAPSHOT_CONTROL_PATH: &str = concat!(shm_dir!(), "/bridge_snapshot_control");
+
+/// Full resolution still buffer - used by capture (write) and gateway (read), a frame buffer
+pub const SNAPSHOT_BUFFER_PATH: &str = concat!(shm_dir!(), "/bridge_snapshot_buffer");
+
+/// Controller status path - used by controller (publish, apply arm requests) and gateway (serve, request)
+pub const CONTROLLER_STATUS_PATH: &str = concat!(shm_dir!(), "/bridge_controller_status");
+
+/// Pipeline clock path - used by capture (write) and any service timing frames (read)
+pub const PIPELINE_CLOCK_PATH: &str = concat!(shm_dir!(), "/bridge_pipeline_clock");
+
+/// Write gates of the frame and detection buffers - lossless readers register, the writer waits for them
+pub const FRAME_WRITE_GATE_PATH: &str = concat!(shm_dir!(), "/bridge_frame_write_gate");
+pub const DETECTION_WRITE_GATE_PATH: &str = concat!(shm_dir!(), "/bridge_detection_write_gate");
+
+/// Frame consumer registry path - consumers (inference, gateway, ...) register, capture fans out
+pub const FRAME_CONSUMERS_PATH: &str = concat!(shm_dir!(), "/bridge_frame_consumers");
+
+/// Socket serving capture's NVMM surface descriptors (Jetson zero-copy path)
+pub const NVMM_SURFACES_SOCKET_PATH: &str = concat!(shm_dir!(), "/bridge_nvmm_surfaces.sock");
+
+/// Write gate of the NVMM surfaces - readers hold the surface they read, capture waits for its release
+pub const NVMM_WRITE_GATE_PATH: &str = concat!(shm_dir!(), "/bridge_nvmm_write_gate");
 
 /// Semaphore name for inference frame synchronization
 pub const SEMAPHORE_FRAME_INFERENCE: &str = "/bridge_frame_inference";
@@ -28,6 +78,14 @@ pub const SEMAPHORE_DETECTION_CONTROLLER: &str = "/bridge_detection_controller";
 /// Default frame buffer size (12MB - enough for 1920x1920 RGB + flatbuffers overhead)
 pub const DEFAULT_FRAME_BUFFER_SIZE: usize = 8 * 1024 * 1024;
 
+/// Room for the frame's other fields and the FlatBuffers framing beside the pixels
+pub const FRAME_BUFFER_OVERHEAD: usize = 64 * 1024;
+
+/// Frame buffer size for RGB frames of `width` x `height`
+pub const fn frame_buffer_size(width: u32, height: u32) -> usize {
+    width as usize * height as usize * 3 + FRAME_BUFFER_OVERHEAD
+}
+
 /// Default detection buffer size (1MB - enough for many detections)
 pub const DEFAULT_DETECTION_BUFFER_SIZE: usize = 1024 * 1024;
 
@@ -35,11 +93,35 @@ pub const DEFAULT_DETECTION_BUFFER_SIZE: usize = 1024 * 1024;
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_paths_are_in_shm_dir() {
+        for path in [
+            FRAME_BUFFER_PATH,
+            DETECTION_BUFFER_PATH,
+            SENTRY_CONTROL_PATH,
+            THRESHOLD_CONTROL_PATH,
+            SNAPSHOT_CONTROL_PATH,
+            SNAPSHOT_BUFFER_PATH,
+            PIPELINE_CLOCK_PATH,
+            FRAME_CONSUMERS_PATH,
+            FRAME_WRITE_GATE_PATH,
+            DETECTION_WRITE_GATE_PATH,
+            NVMM_SURFACES_SOCKET_PATH,
+            NVMM_WRITE_GATE_PATH,
+        ] {
+            assert!(path.starts_with(SHM_DIR), "{path}");
+        }
+    }
+
     #[test]
     fn test_paths_are_absolute() {
         assert!(FRAME_BUFFER_PATH.starts_with('/'));
         assert!(DETECTION_BUFFER_PATH.starts_with('/'));
         assert!(SENTRY_CONTROL_PATH.starts_with('/'));
+        assert!(THRESHOLD_CONTROL_PATH.starts_with('/'));
+        assert!(SNAPSHOT_CONTROL_PATH.starts_with('/'));
+        assert!(FRAME_CONSUMERS_PATH.starts_with('/'));
+        assert!(NVMM_SURFACES_SOCKET_PATH.starts_with('/'));
     }
 
     #[test]
@@ -0,0 +1,211 @@
+//! Permissions of the shared buffers and signal queues
+//!
+//! Every file the bridge creates in [`SHM_DIR`](crate::paths::SHM_DIR) (buffers,
+//! controls, FIFOs) and every message queue gets the same mode,
+//! `BRIDGE_SHM_MODE` in octal, `600` by default: only the user the services run
+//! as can open them. With `BRIDGE_SHM_GROUP` (a group name or id) they also
+//! belong to that group and the default becomes `660`, so services running as
+//! different users of the group share them.
+//!
+//! The mode is set explicitly once a file is open, so the umask does not matter,
+//! and again whenever a service opens a file it owns, so files left behind with
+//! other permissions are brought back in line. Files owned by another user are
+//! left alone. Modes letting other users write are refused.
+
+use crate::errors::BridgeError;
+use common::get_env_opt;
+use nix::sys::stat::{Mode, fchmod, fstat};
+use nix::unistd::{Gid, Group, fchown, geteuid};
+use std::os::fd::AsFd;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+use std::sync::OnceLock;
+
+const DEFAULT_MODE: u32 = 0o600;
+const DEFAULT_GROUP_MODE: u32 = 0o660;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShmPermissions {
+    /// Permission bits, e.g. `0o600`
+    pub mode: u32,
+    /// Group owning the files, left as created when unset
+    pub group: Option<u32>,
+}
+
+impl ShmPermissions {
+    /// Read `BRIDGE_SHM_MODE` and `BRIDGE_SHM_GROUP`
+    pub fn from_env() -> Result<Self, BridgeError> {
+        Self::parse(
+            get_env_opt::<String>("BRIDGE_SHM_MODE").as_deref(),
+            get_env_opt::<String>("BRIDGE_SHM_GROUP").as_deref(),
+        )
+    }
+
+    /// Permissions from an octal `mode` and a group name or id
+    pub fn parse(mode: Option<&str>, group: Option<&str>) -> Result<Self, BridgeError> {
+        let invalid = |message: String| BridgeError::Permissions(message);
+        let group = group
+            .map(|group| match group.parse::<u32>() {
+                Ok(gid) => Ok(gid),
+                Err(_) => Group::from_name(group)
+                    .map_err(|e| invalid(format!("failed to look up group {group:?}: {e}")))?
+                    .map(|group| group.gid.as_raw())
+                    .ok_or_else(|| invalid(format!("BRIDGE_SHM_GROUP {group:?} does not exist"))),
+            })
+            .transpose()?;
+
+        let mode = match mode {
+            Some(mode) => {
+                let digits = mode.trim_start_matches("0o");
+                u32::from_str_radix(digits, 8)
+                    .ok()
+                    .filter(|mode| *mode <= 0o777)
+                    .ok_or_else(|| {
+                        invalid(format!("BRIDGE_SHM_MODE {mode:?} is not an octal mode"))
+                    })?
+            }
+            None if group.is_some() => DEFAULT_GROUP_MODE,
+            None => DEFAULT_MODE,
+        };
+        if mode & 0o600 != 0o600 {
+            return Err(invalid(format!(
+                "BRIDGE_SHM_MODE {mode:o} must let the owner read and write"
+            )));
+        }
+        if mode & 0o002 != 0 {
+            return Err(invalid(format!(
+                "BRIDGE_SHM_MODE {mode:o} lets any user write frames"
+            )));
+        }
+        Ok(Self { mode, group })
+    }
+
+    /// Permissions of this process, read from the environment once
+    pub fn current() -> Result<Self, BridgeError> {
+        static CURRENT: OnceLock<Result<ShmPermissions, String>> = OnceLock::new();
+        CURRENT
+            .get_or_init(|| Self::from_env().map_err(|e| e.to_string()))
+            .clone()
+            .map_err(BridgeError::Permissions)
+    }
+
+    /// Give `fd` this mode and group if this process owns it
+    pub fn apply(&self, fd: impl AsFd) -> Result<(), BridgeError> {
+        let stat = fstat(fd.as_fd()).map_err(std::io::Error::from)?;
+        if stat.st_uid != geteuid().as_raw() {
+            return Ok(());
+        }
+        if let Some(group) = self.group
+            && stat.st_gid != group
+        {
+            fchown(fd.as_fd(), None, Some(Gid::from_raw(group))).map_err(|e| {
+                BridgeError::Permissions(format!("failed to hand the file to group {group}: {e}"))
+            })?;
+        }
+        // Changing the group may clear other bits, so the mode goes last
+        if stat.st_mode & 0o777 != self.mode || self.group.is_some() {
+            fchmod(fd.as_fd(), Mode::from_bits_truncate(self.mode))
+                .map_err(std::io::Error::from)?;
+        }
+        Ok(())
+    }
+
+    /// Same as [`apply`](Self::apply) by path, for sockets whose descriptor does
+    /// not carry the permissions of the path
+    pub fn apply_path(&self, path: &Path) -> Result<(), BridgeError> {
+        let metadata = std::fs::metadata(path)?;
+        if metadata.uid() != geteuid().as_raw() {
+            return Ok(());
+        }
+        if let Some(group) = self.group
+            && metadata.gid() != group
+        {
+            std::os::unix::fs::chown(path, None, Some(group)).map_err(|e| {
+                BridgeError::Permissions(format!(
+                    "failed to hand {} to group {group}: {e}",
+                    path.display()
+                ))
+            })?;
+        }
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(self.mode))?;
+        Ok(())
+    }
+}
+
+/// Apply this process's permissions to a file or queue it just opened
+#[cfg_attr(
+    not(any(
+        feature = "mmap-writer",
+        feature = "semaphores",
+        feature = "controller-status",
+        feature = "pipeline-clock",
+        feature = "sentry",
+        feature = "snapshots",
+        feature = "thresholds",
+        feature = "write-gate"
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn secure(fd: impl AsFd) -> Result<(), BridgeError> {
+    ShmPermissions::current()?.apply(fd)
+}
+
+/// Apply this process's permissions to a socket it just bound
+#[cfg_attr(not(feature = "dmabuf"), allow(dead_code))]
+pub(crate) fn secure_path(path: &Path) -> Result<(), BridgeError> {
+    ShmPermissions::current()?.apply_path(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_parse_defaults_and_refusals() {
+        assert_eq!(
+            ShmPermissions::parse(None, None).unwrap(),
+            ShmPermissions {
+                mode: 0o600,
+                group: None
+            }
+        );
+        assert_eq!(
+            ShmPermissions::parse(None, Some("1234")).unwrap(),
+            ShmPermissions {
+                mode: 0o660,
+                group: Some(1234)
+            }
+        );
+        assert_eq!(
+            ShmPermissions::parse(Some("0o640"), None).unwrap().mode,
+            0o640
+        );
+        assert_eq!(
+            ShmPermissions::parse(Some("0644"), None).unwrap().mode,
+            0o644
+        );
+
+        for mode in ["666", "400", "9", "1777", "rw"] {
+            assert!(ShmPermissions::parse(Some(mode), None).is_err(), "{mode}");
+        }
+        assert!(ShmPermissions::parse(None, Some("no-such-bridge-group")).is_err());
+    }
+
+    #[test]
+    fn test_apply_sets_the_mode_whatever_the_umask() {
+        let file = NamedTempFile::new().unwrap();
+        file.as_file()
+            .set_permissions(std::fs::Permissions::from_mode(0o644))
+            .unwrap();
+
+        let permissions = ShmPermissions {
+            mode: 0o640,
+            group: None,
+        };
+        permissions.apply(file.as_file()).unwrap();
+
+        let mode = file.as_file().metadata().unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+}
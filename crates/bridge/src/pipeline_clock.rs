@@ -0,0 +1,257 @@
+//! Pipeline epoch and clock drift shared between services
+//!
+//! Services stamp times on their own reading of the wall clock, which steps on
+//! NTP corrections and keeps running through a suspend while `CLOCK_MONOTONIC`
+//! stops, so latencies computed across services go wrong after either. Capture
+//! publishes a pipeline epoch here, the monotonic and wall clock readings of one
+//! instant, and refreshes a sample of both clocks as it runs. Other services
+//! express their monotonic readings relative to the epoch, map wall clock stamps
+//! onto the monotonic clock with capture's latest offset, and read the drift: how
+//! far the wall clock moved against the monotonic clock since the epoch.
+//!
+//! The epoch is kept across capture restarts, only a reboot (which clears
+//! `/dev/shm`) starts a new one.
+
+use crate::errors::BridgeError;
+use crate::paths;
+use common::{MonotonicNs, Timestamp, WallClockNs};
+use memmap2::MmapMut;
+use std::fs::OpenOptions;
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicU64, Ordering, fence};
+use std::time::Duration;
+
+/// Readings of both clocks at one instant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSample {
+    pub monotonic: MonotonicNs,
+    pub wall: WallClockNs,
+}
+
+impl ClockSample {
+    pub fn now() -> Self {
+        Self {
+            monotonic: MonotonicNs::now(),
+            wall: WallClockNs::now(),
+        }
+    }
+
+    /// Wall clock minus monotonic clock, in ns
+    fn offset(&self) -> i128 {
+        self.wall.as_nanos() as i128 - self.monotonic.as_nanos() as i128
+    }
+}
+
+/// Epoch and latest sample as published by capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineTime {
+    pub epoch: ClockSample,
+    pub latest: ClockSample,
+}
+
+impl PipelineTime {
+    /// Time since the epoch of a monotonic reading, zero before it
+    pub fn since_epoch(&self, at: MonotonicNs) -> Duration {
+        at.saturating_duration_since(self.epoch.monotonic)
+    }
+
+    /// A timestamp on either clock as time since the epoch, wall clock stamps
+    /// going through the latest offset between the clocks
+    pub fn since_epoch_of(&self, timestamp: Timestamp) -> Duration {
+        let monotonic = match timestamp {
+            Timestamp::Monotonic(ns) => ns,
+            Timestamp::WallClock(ns) => {
+                let ns = ns.as_nanos() as i128 - self.latest.offset();
+                MonotonicNs::from_nanos(ns.clamp(0, u64::MAX as i128) as u64)
+            }
+        };
+        self.since_epoch(monotonic)
+    }
+
+    /// How far the wall clock moved against the monotonic clock since the epoch,
+    /// in ns: NTP steps and slewing, and time spent suspended
+    pub fn drift_ns(&self) -> i64 {
+        (self.latest.offset() - self.epoch.offset()) as i64
+    }
+}
+
+/// Reads retried while capture rewrites the block, a write taking a few stores
+const READ_ATTEMPTS: usize = 1000;
+
+#[repr(C)]
+struct Block {
+    /// Odd while capture rewrites the block
+    version: AtomicU64,
+    epoch_monotonic: AtomicU64,
+    epoch_wall: AtomicU64,
+    latest_monotonic: AtomicU64,
+    latest_wall: AtomicU64,
+}
+
+pub struct PipelineClock {
+    _mmap: MmapMut,
+    block: &'static Block,
+}
+
+unsafe impl Send for PipelineClock {}
+unsafe impl Sync for PipelineClock {}
+
+impl PipelineClock {
+    /// Create or open the pipeline clock at its default path
+    pub fn build() -> Result<Self, BridgeError> {
+        Self::new(paths::PIPELINE_CLOCK_PATH)
+    }
+
+    /// Create or open the pipeline clock at `path` (useful for tests)
+    pub fn new(path: &str) -> Result<Self, BridgeError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(path)?;
+        crate::permissions::secure(&file)?;
+
+        let size = std::mem::size_of::<Block>() as u64;
+        if file.metadata()?.len() < size {
+            file.set_len(size)?;
+        }
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+        let block = unsafe { &*(mmap.as_mut_ptr() as *const Block) };
+
+        Ok(Self { _mmap: mmap, block })
+    }
+
+    /// Start the epoch now unless one of this boot is already published (capture)
+    pub fn start(&self) {
+        let now = ClockSample::now();
+        let epoch = match self.read() {
+            Some(time) if time.epoch.monotonic <= now.monotonic => time.epoch,
+            _ => now,
+        };
+        self.write(epoch, now);
+    }
+
+    /// Publish a fresh sample of both clocks (capture)
+    pub fn refresh(&self) {
+        if let Some(time) = self.read() {
+            self.write(time.epoch, ClockSample::now());
+        }
+    }
+
+    /// Epoch and latest sample, `None` until capture started the epoch, or while
+    /// a capture that died mid-write leaves the block torn
+    pub fn read(&self) -> Option<PipelineTime> {
+        let b = self.block;
+        for _ in 0..READ_ATTEMPTS {
+            let version = b.version.load(Ordering::Acquire);
+            if version % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let time = PipelineTime {
+                epoch: ClockSample {
+                    monotonic: MonotonicNs::from_nanos(b.epoch_monotonic.load(Ordering::Relaxed)),
+                    wall: WallClockNs::from_nanos(b.epoch_wall.load(Ordering::Relaxed)),
+                },
+                latest: ClockSample {
+                    monotonic: MonotonicNs::from_nanos(b.latest_monotonic.load(Ordering::Relaxed)),
+                    wall: WallClockNs::from_nanos(b.latest_wall.load(Ordering::Relaxed)),
+                },
+            };
+            fence(Ordering::Acquire);
+            if b.version.load(Ordering::Relaxed) == version {
+                return (version != 0).then_some(time);
+            }
+        }
+        None
+    }
+
+    /// Replace the block, bracketed by the version so readers never see a mix
+    fn write(&self, epoch: ClockSample, latest: ClockSample) {
+        let b = self.block;
+        let version = b.version.load(Ordering::Relaxed);
+        b.version.store(version | 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        b.epoch_monotonic
+            .store(epoch.monotonic.as_nanos(), Ordering::Relaxed);
+        b.epoch_wall.store(epoch.wall.as_nanos(), Ordering::Relaxed);
+        b.latest_monotonic
+            .store(latest.monotonic.as_nanos(), Ordering::Relaxed);
+        b.latest_wall
+            .store(latest.wall.as_nanos(), Ordering::Relaxed);
+        b.version.store((version | 1) + 1, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(monotonic_s: u64, wall_s: u64) -> ClockSample {
+        ClockSample {
+            monotonic: MonotonicNs::from_nanos(monotonic_s * 1_000_000_000),
+            wall: WallClockNs::from_nanos(wall_s * 1_000_000_000),
+        }
+    }
+
+    #[test]
+    fn test_conversions_and_drift() {
+        let time = PipelineTime {
+            epoch: sample(100, 1_000_000),
+            // The wall clock was stepped 2s forward since the epoch
+            latest: sample(160, 1_000_062),
+        };
+
+        assert_eq!(time.drift_ns(), 2_000_000_000);
+        assert_eq!(
+            time.since_epoch(MonotonicNs::from_nanos(130_000_000_000)),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            time.since_epoch(MonotonicNs::from_nanos(50_000_000_000)),
+            Duration::ZERO
+        );
+        // Wall clock stamps map through the latest offset, not the epoch's
+        assert_eq!(
+            time.since_epoch_of(WallClockNs::from_nanos(1_000_062_000_000_000).into()),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            time.since_epoch_of(MonotonicNs::from_nanos(160_000_000_000).into()),
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn test_epoch_is_shared_and_survives_restarts() {
+        let path = &format!("{}/test_pipeline_clock", crate::paths::SHM_DIR);
+        let _ = std::fs::remove_file(path);
+
+        let capture = PipelineClock::new(path).unwrap();
+        let reader = PipelineClock::new(path).unwrap();
+        assert_eq!(reader.read(), None);
+
+        capture.start();
+        let first = reader.read().unwrap();
+        assert_eq!(first.epoch, first.latest);
+
+        capture.refresh();
+        let refreshed = reader.read().unwrap();
+        assert_eq!(refreshed.epoch, first.epoch);
+        assert!(refreshed.latest.monotonic >= first.latest.monotonic);
+
+        // A restarted capture keeps the epoch
+        PipelineClock::new(path).unwrap().start();
+        assert_eq!(reader.read().unwrap().epoch, first.epoch);
+
+        // One from a later monotonic time is from another boot
+        capture.write(sample(u64::MAX / 2_000_000_000, 0), sample(0, 0));
+        capture.start();
+        assert!(reader.read().unwrap().epoch.monotonic <= MonotonicNs::now());
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,22 @@
+//! Liveness of the processes sharing a buffer or registry
+
+use std::os::unix::fs::MetadataExt;
+
+/// Whether `pid` names a running process of our pid namespace
+pub(crate) fn process_alive(pid: u32) -> bool {
+    // Signal 0 only checks the process exists; EPERM means it does, as another user
+    if unsafe { libc::kill(pid as libc::pid_t, 0) } == 0 {
+        return true;
+    }
+    std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+/// Identifier of our pid namespace, 0 if it cannot be read
+///
+/// Pids only mean something to [`process_alive`] within the same namespace, and
+/// containers sharing `/dev/shm` usually each have their own.
+pub(crate) fn pid_namespace() -> u32 {
+    std::fs::metadata("/proc/self/ns/pid")
+        .map(|metadata| metadata.ino() as u32)
+        .unwrap_or(0)
+}
@@ -1,17 +1,22 @@
 use crate::errors::BridgeError;
-use nix::mqueue::{
-    MQ_OFlag, MqAttr, MqdT, mq_close, mq_open, mq_receive, mq_send, mq_timedreceive, mq_unlink,
-};
-use nix::sys::stat::Mode;
-use nix::sys::time::TimeSpec;
-use nix::time::{ClockId, clock_gettime};
-use std::ffi::CString;
 use std::time::Duration;
 
+#[cfg(target_os = "linux")]
+mod mqueue;
+#[cfg(target_os = "linux")]
+use mqueue::Queue;
+
+#[cfg(not(target_os = "linux"))]
+mod fifo;
+#[cfg(not(target_os = "linux"))]
+use fifo::Queue;
+
+/// Fixed point-to-point queues
+///
+/// Frame notifications from capture are not listed here: each frame consumer
+/// registers its own queue in the [`ConsumerRegistry`](crate::ConsumerRegistry).
 #[derive(Copy, Clone)]
 pub enum SemaphoreType {
-    FrameCaptureToInference,
-    FrameCaptureToGateway,
     DetectionInferenceToController,
     ModeChangeControllerToCapture,
 }
@@ -19,8 +24,6 @@ pub enum SemaphoreType {
 impl SemaphoreType {
     fn name(&self) -> &str {
         match self {
-            Self::FrameCaptureToInference => "/bridge_frame_inference",
-            Self::FrameCaptureToGateway => "/bridge_frame_gateway",
             Self::DetectionInferenceToController => "/bridge_detection_controller",
             Self::ModeChangeControllerToCapture => "/bridge_mode_controller_capture",
         }
@@ -30,14 +33,21 @@ impl SemaphoreType {
 /// A wrapper around POSIX message queues for frame synchronization
 ///
 /// This uses message queues to signal when new frames are available.
-/// The gateway posts to the queue after writing each frame,
-/// signaling both inference and gateway processes.
+/// Capture posts once per registered consumer after writing each frame.
+/// Hosts without message queues (macOS) use named pipes in
+/// [`SHM_DIR`](crate::paths::SHM_DIR) with the same semantics, bounded by the pipe
+/// buffer instead of the queue capacity.
 ///
 /// Note: Message queues are treated as persistent system resources.
 /// They are not automatically deleted when this struct is dropped,
 /// allowing seamless pod restarts in Kubernetes.
+///
+/// Cleanup should happen via:
+/// - Init containers in Kubernetes
+/// - Manual cleanup during system maintenance
+/// - Or automatic cleanup on host reboot (queues are in /dev/mqueue)
 pub struct BridgeSemaphore {
-    mqd: Option<MqdT>,
+    queue: Queue,
 }
 
 unsafe impl Send for BridgeSemaphore {}
@@ -54,8 +64,17 @@ impl BridgeSemaphore {
 
     /// Open an existing semaphore, or create it if it doesn't exist
     pub fn ensure(semaphore_type: SemaphoreType) -> Result<Self, BridgeError> {
-        Self::open(semaphore_type).or_else(|_| Self::create(semaphore_type))
+        Self::ensure_with_name(semaphore_type.name())
     }
+
+    /// Open an existing message queue by name, or create it if it doesn't exist
+    ///
+    /// Unlike [`BridgeSemaphore::create_with_name`], an existing queue and its
+    /// pending signals are kept.
+    pub fn ensure_with_name(name: &str) -> Result<Self, BridgeError> {
+        Self::open_with_name(name).or_else(|_| Self::create_with_name(name))
+    }
+
     /// Create a new message queue
     ///
     /// This will create a new message queue or open an existing one.
@@ -67,25 +86,9 @@ impl BridgeSemaphore {
     /// # Returns
     /// A new BridgeSemaphore instance that owns the message queue
     pub fn create_with_name(name: &str) -> Result<Self, BridgeError> {
-        let c_name = CString::new(name)
-            .map_err(|e| BridgeError::SemaphoreError(format!("Invalid queue name: {}", e)))?;
-
-        // Try to unlink any existing queue first
-        let _ = mq_unlink(c_name.as_c_str());
-
-        // Set queue attributes: max 10 messages, 1 byte per message
-        let attr = MqAttr::new(0, 10, 1, 0);
-
-        // Create new message queue
-        let mqd = mq_open(
-            c_name.as_c_str(),
-            MQ_OFlag::O_CREAT | MQ_OFlag::O_EXCL | MQ_OFlag::O_RDWR,
-            Mode::S_IRUSR | Mode::S_IWUSR | Mode::S_IRGRP | Mode::S_IWGRP,
-            Some(&attr),
-        )
-        .map_err(|e| BridgeError::SemaphoreError(format!("Failed to create queue: {}", e)))?;
-
-        Ok(Self { mqd: Some(mqd) })
+        Ok(Self {
+            queue: Queue::create(name)?,
+        })
     }
 
     /// Open an existing message queue
@@ -96,14 +99,9 @@ impl BridgeSemaphore {
     /// # Returns
     /// A new BridgeSemaphore instance connected to the existing queue
     pub fn open_with_name(name: &str) -> Result<Self, BridgeError> {
-        let c_name = CString::new(name)
-            .map_err(|e| BridgeError::SemaphoreError(format!("Invalid queue name: {}", e)))?;
-
-        // Open existing message queue
-        let mqd = mq_open(c_name.as_c_str(), MQ_OFlag::O_RDWR, Mode::empty(), None)
-            .map_err(|e| BridgeError::SemaphoreError(format!("Failed to open queue: {}", e)))?;
-
-        Ok(Self { mqd: Some(mqd) })
+        Ok(Self {
+            queue: Queue::open(name)?,
+        })
     }
 
     /// Wait for a signal
@@ -111,25 +109,7 @@ impl BridgeSemaphore {
     /// This will block until a message (signal) is available in the queue.
     /// Automatically retries if interrupted by signals.
     pub fn wait(&self) -> Result<(), BridgeError> {
-        let mut buf = [0u8; 1];
-        let mut prio = 0u32;
-        let mqd = self
-            .mqd
-            .as_ref()
-            .ok_or_else(|| BridgeError::SemaphoreError("Message queue not initialized".into()))?;
-
-        loop {
-            match mq_receive(mqd, &mut buf, &mut prio) {
-                Ok(_) => return Ok(()),
-                Err(nix::errno::Errno::EINTR) => continue, // Retry on interrupt
-                Err(e) => {
-                    return Err(BridgeError::SemaphoreError(format!(
-                        "Queue receive failed: {}",
-                        e
-                    )));
-                }
-            }
-        }
+        self.queue.receive(None).map(|_| ())
     }
 
     /// Wait for a signal with a timeout
@@ -150,40 +130,7 @@ impl BridgeSemaphore {
     /// This method provides sub-second precision, useful for frame pacing where
     /// timeouts may be ~33ms (30fps) or similar.
     pub fn wait_timeout_duration(&self, timeout: Duration) -> Result<bool, BridgeError> {
-        let mut buf = [0u8; 1];
-        let mut prio = 0u32;
-        let mqd = self
-            .mqd
-            .as_ref()
-            .ok_or_else(|| BridgeError::SemaphoreError("Message queue not initialized".into()))?;
-
-        // mq_timedreceive uses absolute time, so we need to compute deadline from current time
-        let now = clock_gettime(ClockId::CLOCK_REALTIME).map_err(|e| {
-            BridgeError::SemaphoreError(format!("Failed to get current time: {}", e))
-        })?;
-        let deadline_secs = now.tv_sec() + timeout.as_secs() as i64;
-        let deadline_nanos = now.tv_nsec() + timeout.subsec_nanos() as i64;
-        // Handle nanosecond overflow
-        let (deadline_secs, deadline_nanos) = if deadline_nanos >= 1_000_000_000 {
-            (deadline_secs + 1, deadline_nanos - 1_000_000_000)
-        } else {
-            (deadline_secs, deadline_nanos)
-        };
-        let abs_timeout = TimeSpec::new(deadline_secs, deadline_nanos);
-
-        loop {
-            match mq_timedreceive(mqd, &mut buf, &mut prio, &abs_timeout) {
-                Ok(_) => return Ok(true),
-                Err(nix::errno::Errno::EINTR) => continue, // Retry on interrupt
-                Err(nix::errno::Errno::ETIMEDOUT) => return Ok(false), // Timeout
-                Err(e) => {
-                    return Err(BridgeError::SemaphoreError(format!(
-                        "Queue timed receive failed: {}",
-                        e
-                    )));
-                }
-            }
-        }
+        self.queue.receive(Some(timeout))
     }
 
     /// Try to wait without blocking
@@ -191,41 +138,34 @@ impl BridgeSemaphore {
     /// Returns Ok(true) if a signal was consumed, Ok(false) if none available.
     /// This is used by inference to "drain" pending signals and skip to the latest frame.
     pub fn try_wait(&self) -> Result<bool, BridgeError> {
-        let mut buf = [0u8; 1];
-        let mut prio = 0u32;
-        let mqd = self
-            .mqd
-            .as_ref()
-            .ok_or_else(|| BridgeError::SemaphoreError("Message queue not initialized".into()))?;
-
-        // Use current time as absolute timeout for non-blocking behavior
-        let now = clock_gettime(ClockId::CLOCK_REALTIME).map_err(|e| {
-            BridgeError::SemaphoreError(format!("Failed to get current time: {}", e))
-        })?;
-
-        match mq_timedreceive(mqd, &mut buf, &mut prio, &now) {
-            Ok(_) => Ok(true),
-            Err(nix::errno::Errno::ETIMEDOUT) => Ok(false), // No message available
-            Err(e) => Err(BridgeError::SemaphoreError(format!(
-                "Queue try_receive failed: {}",
-                e
-            ))),
-        }
+        self.queue.receive(Some(Duration::ZERO))
+    }
+
+    /// Make [`BridgeSemaphore::try_post`] fail fast on a full queue instead of blocking
+    ///
+    /// Only affects this descriptor: waiters on other descriptors still block.
+    pub fn set_nonblocking(&self) -> Result<(), BridgeError> {
+        self.queue.set_nonblocking()
+    }
+
+    /// Signal the queue without blocking
+    ///
+    /// Returns Ok(false) if the queue is full, i.e. the consumer has not drained its
+    /// previous signals. Requires [`BridgeSemaphore::set_nonblocking`].
+    pub fn try_post(&self) -> Result<bool, BridgeError> {
+        self.queue.send()
     }
 
     /// Signal the queue (send a message)
     ///
-    /// Gateway calls this after writing a frame.
-    /// It should be called twice per frame (once for inference, once for gateway)
-    /// to implement the fan-out pattern.
+    /// Blocks while the queue is full unless the descriptor is nonblocking.
     pub fn post(&self) -> Result<(), BridgeError> {
-        let msg = [1u8]; // Simple 1-byte message
-        let mqd = self
-            .mqd
-            .as_ref()
-            .ok_or_else(|| BridgeError::SemaphoreError("Message queue not initialized".into()))?;
-        mq_send(mqd, &msg, 0)
-            .map_err(|e| BridgeError::SemaphoreError(format!("Queue send failed: {}", e)))
+        match self.queue.send()? {
+            true => Ok(()),
+            false => Err(BridgeError::SemaphoreError(
+                "Queue send failed: queue full".into(),
+            )),
+        }
     }
 
     /// Drain all pending signals
@@ -241,27 +181,6 @@ impl BridgeSemaphore {
     }
 }
 
-impl Drop for BridgeSemaphore {
-    fn drop(&mut self) {
-        // Close the message queue descriptor
-        if let Some(mqd) = self.mqd.take() {
-            let _ = mq_close(mqd);
-        }
-
-        // NOTE: We intentionally DO NOT unlink the queue here.
-        // Message queues are treated as persistent system resources that survive pod restarts.
-        // This allows:
-        // - Capture pod can restart without breaking gateway/inference listeners
-        // - Gateway/inference keep their file descriptors and continue receiving messages
-        // - No need for complex reconnection logic
-        //
-        // Cleanup should happen via:
-        // - Init containers in Kubernetes
-        // - Manual cleanup during system maintenance
-        // - Or automatic cleanup on host reboot (queues are in /dev/mqueue)
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -397,4 +316,21 @@ mod tests {
             elapsed
         );
     }
+
+    // Named pipes are bounded by the pipe buffer, not the queue capacity
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_try_post_full_queue() {
+        let queue_name = "/test_bridge_queue7";
+        let mq = BridgeSemaphore::create_with_name(queue_name).expect("Failed to create queue");
+        mq.set_nonblocking().expect("Failed to set nonblocking");
+
+        // Queue holds 10 signals, the 11th is dropped instead of blocking
+        for _ in 0..10 {
+            assert!(mq.try_post().expect("Failed to post"));
+        }
+        assert!(!mq.try_post().expect("Failed to post"));
+
+        assert_eq!(mq.drain().expect("Failed to drain"), 10);
+    }
 }
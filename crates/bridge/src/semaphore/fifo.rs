@@ -0,0 +1,157 @@
+//! Named pipe backend for hosts without POSIX message queues (macOS)
+//!
+//! Each queue is a FIFO in [`SHM_DIR`](crate::paths::SHM_DIR) opened read-write, so it
+//! never sees EOF and any process can both post and wait. A signal is one byte;
+//! the pipe buffer bounds the pending signals instead of the queue capacity.
+
+use crate::errors::BridgeError;
+use crate::paths::SHM_DIR;
+use nix::sys::stat::Mode;
+use nix::unistd::mkfifo;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+pub(super) struct Queue {
+    fifo: File,
+    nonblocking: AtomicBool,
+}
+
+fn fifo_path(name: &str) -> PathBuf {
+    PathBuf::from(SHM_DIR).join(format!("{}.fifo", name.trim_start_matches('/')))
+}
+
+impl Queue {
+    pub(super) fn create(name: &str) -> Result<Self, BridgeError> {
+        let path = fifo_path(name);
+        let _ = std::fs::remove_file(&path);
+
+        mkfifo(&path, Mode::S_IRUSR | Mode::S_IWUSR)
+            .map_err(|e| BridgeError::SemaphoreError(format!("Failed to create queue: {}", e)))?;
+
+        Self::open(name)
+    }
+
+    pub(super) fn open(name: &str) -> Result<Self, BridgeError> {
+        let path = fifo_path(name);
+        let open_error = |e: &dyn std::fmt::Display| {
+            BridgeError::SemaphoreError(format!("Failed to open queue: {}", e))
+        };
+
+        let metadata = std::fs::metadata(&path).map_err(|e| open_error(&e))?;
+        if !metadata.file_type().is_fifo() {
+            return Err(open_error(&format!("{} is not a FIFO", path.display())));
+        }
+
+        // Read-write keeps a writer attached, so reads block instead of hitting EOF.
+        // The descriptor itself is nonblocking, waits go through poll().
+        let fifo = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&path)
+            .map_err(|e| open_error(&e))?;
+        crate::permissions::secure(&fifo)?;
+
+        Ok(Self {
+            fifo,
+            nonblocking: AtomicBool::new(false),
+        })
+    }
+
+    /// Wait until the FIFO is ready for `events`, at most `timeout` (forever if `None`)
+    fn poll(&self, events: libc::c_short, timeout: Option<Duration>) -> Result<bool, BridgeError> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        loop {
+            let timeout_ms = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    remaining
+                        .as_nanos()
+                        .div_ceil(1_000_000)
+                        .min(i32::MAX as u128) as i32
+                }
+                None => -1,
+            };
+            let mut fds = [libc::pollfd {
+                fd: self.fifo.as_raw_fd(),
+                events,
+                revents: 0,
+            }];
+
+            match unsafe { libc::poll(fds.as_mut_ptr(), 1, timeout_ms) } {
+                -1 => {
+                    let err = std::io::Error::last_os_error();
+                    if err.kind() == ErrorKind::Interrupted {
+                        continue; // Retry on interrupt
+                    }
+                    return Err(BridgeError::SemaphoreError(format!(
+                        "Queue poll failed: {}",
+                        err
+                    )));
+                }
+                0 => return Ok(false),
+                _ => return Ok(true),
+            }
+        }
+    }
+
+    /// Consume one signal, waiting at most `timeout` (forever if `None`)
+    ///
+    /// Returns Ok(false) if no signal arrived in time.
+    pub(super) fn receive(&self, timeout: Option<Duration>) -> Result<bool, BridgeError> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut buf = [0u8; 1];
+        loop {
+            match (&self.fifo).read(&mut buf) {
+                Ok(1) => return Ok(true),
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    return Err(BridgeError::SemaphoreError(format!(
+                        "Queue receive failed: {}",
+                        e
+                    )));
+                }
+            }
+
+            // Another waiter may take the signal between poll() and read()
+            let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            if remaining.is_some_and(|r| r.is_zero()) || !self.poll(libc::POLLIN, remaining)? {
+                return Ok(false);
+            }
+        }
+    }
+
+    pub(super) fn set_nonblocking(&self) -> Result<(), BridgeError> {
+        self.nonblocking.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Send one signal, returning Ok(false) if a nonblocking queue is full
+    pub(super) fn send(&self) -> Result<bool, BridgeError> {
+        loop {
+            match (&self.fifo).write(&[1u8]) {
+                Ok(_) => return Ok(true),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    if self.nonblocking.load(Ordering::Relaxed) {
+                        return Ok(false); // Queue full
+                    }
+                    self.poll(libc::POLLOUT, None)?;
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    return Err(BridgeError::SemaphoreError(format!(
+                        "Queue send failed: {}",
+                        e
+                    )));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! POSIX message queue backend (Linux)
+
+use crate::errors::BridgeError;
+use nix::mqueue::{
+    MQ_OFlag, MqAttr, MqdT, mq_close, mq_open, mq_receive, mq_send, mq_set_nonblock,
+    mq_timedreceive, mq_unlink,
+};
+use nix::sys::stat::Mode;
+use nix::sys::time::TimeSpec;
+use nix::time::{ClockId, clock_gettime};
+use std::ffi::CString;
+use std::time::Duration;
+
+pub(super) struct Queue {
+    mqd: Option<MqdT>,
+}
+
+fn c_name(name: &str) -> Result<CString, BridgeError> {
+    CString::new(name)
+        .map_err(|e| BridgeError::SemaphoreError(format!("Invalid queue name: {}", e)))
+}
+
+impl Queue {
+    pub(super) fn create(name: &str) -> Result<Self, BridgeError> {
+        let c_name = c_name(name)?;
+
+        // Try to unlink any existing queue first
+        let _ = mq_unlink(c_name.as_c_str());
+
+        // Set queue attributes: max 10 messages, 1 byte per message
+        let attr = MqAttr::new(0, 10, 1, 0);
+
+        // Create new message queue
+        let mqd = mq_open(
+            c_name.as_c_str(),
+            MQ_OFlag::O_CREAT | MQ_OFlag::O_EXCL | MQ_OFlag::O_RDWR,
+            Mode::S_IRUSR | Mode::S_IWUSR,
+            Some(&attr),
+        )
+        .map_err(|e| BridgeError::SemaphoreError(format!("Failed to create queue: {}", e)))?;
+        crate::permissions::secure(&mqd)?;
+
+        Ok(Self { mqd: Some(mqd) })
+    }
+
+    pub(super) fn open(name: &str) -> Result<Self, BridgeError> {
+        let c_name = c_name(name)?;
+
+        let mqd = mq_open(c_name.as_c_str(), MQ_OFlag::O_RDWR, Mode::empty(), None)
+            .map_err(|e| BridgeError::SemaphoreError(format!("Failed to open queue: {}", e)))?;
+
+        Ok(Self { mqd: Some(mqd) })
+    }
+
+    fn mqd(&self) -> Result<&MqdT, BridgeError> {
+        self.mqd
+            .as_ref()
+            .ok_or_else(|| BridgeError::SemaphoreError("Message queue not initialized".into()))
+    }
+
+    /// Consume one signal, waiting at most `timeout` (forever if `None`)
+    ///
+    /// Returns Ok(false) if no signal arrived in time.
+    pub(super) fn receive(&self, timeout: Option<Duration>) -> Result<bool, BridgeError> {
+        let mut buf = [0u8; 1];
+        let mut prio = 0u32;
+        let mqd = self.mqd()?;
+
+        let Some(timeout) = timeout else {
+            loop {
+                match mq_receive(mqd, &mut buf, &mut prio) {
+                    Ok(_) => return Ok(true),
+                    Err(nix::errno::Errno::EINTR) => continue, // Retry on interrupt
+                    Err(e) => {
+                        return Err(BridgeError::SemaphoreError(format!(
+                            "Queue receive failed: {}",
+                            e
+                        )));
+                    }
+                }
+            }
+        };
+
+        // mq_timedreceive uses absolute time, so we need to compute deadline from current time
+        let now = clock_gettime(ClockId::CLOCK_REALTIME).map_err(|e| {
+            BridgeError::SemaphoreError(format!("Failed to get current time: {}", e))
+        })?;
+        let deadline_secs = now.tv_sec() + timeout.as_secs() as i64;
+        let deadline_nanos = now.tv_nsec() + timeout.subsec_nanos() as i64;
+        // Handle nanosecond overflow
+        let (deadline_secs, deadline_nanos) = if deadline_nanos >= 1_000_000_000 {
+            (deadline_secs + 1, deadline_nanos - 1_000_000_000)
+        } else {
+            (deadline_secs, deadline_nanos)
+        };
+        let abs_timeout = TimeSpec::new(deadline_secs, deadline_nanos);
+
+        loop {
+            match mq_timedreceive(mqd, &mut buf, &mut prio, &abs_timeout) {
+                Ok(_) => return Ok(true),
+                Err(nix::errno::Errno::EINTR) => continue, // Retry on interrupt
+                // Timeout, or no message on a nonblocking descriptor
+                Err(nix::errno::Errno::ETIMEDOUT | nix::errno::Errno::EAGAIN) => return Ok(false),
+                Err(e) => {
+                    return Err(BridgeError::SemaphoreError(format!(
+                        "Queue timed receive failed: {}",
+                        e
+                    )));
+                }
+            }
+        }
+    }
+
+    pub(super) fn set_nonblocking(&self) -> Result<(), BridgeError> {
+        mq_set_nonblock(self.mqd()?)
+            .map(|_| ())
+            .map_err(|e| BridgeError::SemaphoreError(format!("Failed to set nonblocking: {}", e)))
+    }
+
+    /// Send one signal, returning Ok(false) if a nonblocking queue is full
+    pub(super) fn send(&self) -> Result<bool, BridgeError> {
+        match mq_send(self.mqd()?, &[1u8], 0) {
+            Ok(()) => Ok(true),
+            Err(nix::errno::Errno::EAGAIN) => Ok(false), // Queue full
+            Err(e) => Err(BridgeError::SemaphoreError(format!(
+                "Queue send failed: {}",
+                e
+            ))),
+        }
+    }
+}
+
+impl Drop for Queue {
+    fn drop(&mut self) {
+        // Only the descriptor is closed, the queue itself persists (see `BridgeSemaphore`)
+        if let Some(mqd) = self.mqd.take() {
+            let _ = mq_close(mqd);
+        }
+    }
+}
@@ -10,6 +10,8 @@ use std::sync::atomic::{AtomicU8, Ordering};
 pub enum SentryMode {
     Standby = 0,
     Alarmed = 1,
+    /// Low-confidence detections are trending up, capture faster ahead of an alarm
+    Elevated = 2,
 }
 
 impl SentryMode {
@@ -17,6 +19,7 @@ impl SentryMode {
         match value {
             0 => Some(SentryMode::Standby),
             1 => Some(SentryMode::Alarmed),
+            2 => Some(SentryMode::Elevated),
             _ => None,
         }
     }
@@ -38,11 +41,11 @@ impl SentryControl {
 
     /// Create or open shared memory control with custom path (useful for tests)
     ///
-    /// This creates a shared memory segment in /dev/shm for the sentry mode.
+    /// This creates a shared memory segment in [`SHM_DIR`](crate::paths::SHM_DIR) for the sentry mode.
     /// The segment is 1 byte containing an atomic U8.
     ///
     /// # Arguments
-    /// * `path` - Path in /dev/shm (e.g., "/dev/shm/bridge_sentry_control")
+    /// * `path` - Path in shared memory (e.g., [`SENTRY_CONTROL_PATH`](crate::paths::SENTRY_CONTROL_PATH))
     pub fn new(path: &str) -> Result<Self, BridgeError> {
         let file = OpenOptions::new()
             .read(true)
@@ -51,6 +54,7 @@ impl SentryControl {
             .truncate(false)
             .mode(0o600)
             .open(path)?;
+        crate::permissions::secure(&file)?;
 
         let metadata = file.metadata()?;
 
@@ -94,7 +98,7 @@ mod tests {
 
     #[test]
     fn test_sentry_control_create_and_set() {
-        let path = "/dev/shm/test_sentry_control";
+        let path = &format!("{}/test_sentry_control", crate::paths::SHM_DIR);
         let _ = std::fs::remove_file(path); // Clean up from previous runs
 
         let control = SentryControl::new(path).expect("Failed to create control");
@@ -106,6 +110,10 @@ mod tests {
         control.set_mode(SentryMode::Alarmed);
         assert_eq!(control.get_mode(), SentryMode::Alarmed);
 
+        // Set to Elevated
+        control.set_mode(SentryMode::Elevated);
+        assert_eq!(control.get_mode(), SentryMode::Elevated);
+
         // Set back to Standby
         control.set_mode(SentryMode::Standby);
         assert_eq!(control.get_mode(), SentryMode::Standby);
@@ -116,7 +124,7 @@ mod tests {
 
     #[test]
     fn test_sentry_control_shared_across_instances() {
-        let path = "/dev/shm/test_sentry_control_shared";
+        let path = &format!("{}/test_sentry_control_shared", crate::paths::SHM_DIR);
         let _ = std::fs::remove_file(path);
 
         let control1 = SentryControl::new(path).expect("Failed to create control1");
@@ -140,7 +148,7 @@ mod tests {
 
     #[test]
     fn test_try_set_mode() {
-        let path = "/dev/shm/test_sentry_try_set";
+        let path = &format!("{}/test_sentry_try_set", crate::paths::SHM_DIR);
         let _ = std::fs::remove_file(path);
 
         let control = SentryControl::new(path).expect("Failed to create control");
@@ -0,0 +1,171 @@
+//! Publication of buffer contents through the header sequence number
+//!
+//! The writer copies a payload into the buffer, then publishes it by storing the
+//! next sequence number with Release ordering. A reader that loads a sequence with
+//! Acquire ordering sees at least the payload published with it, possibly part of
+//! a newer one: there is a single buffer, rewritten in place.
+//!
+//! Creating a buffer over an existing file resets the sequence to 0. A reader that
+//! last saw sequence N therefore takes any other non-zero sequence as new data, not
+//! only one above N, or it would stall until the new writer caught up with N.
+//!
+//! The functions are generic over the atomic so that the protocol can be model
+//! checked with loom, under its own cfg since tokio drops its networking under
+//! `cfg(loom)`:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg bridge_loom" cargo test -p bridge --features ci --lib --release sequence
+//! ```
+
+use std::sync::atomic::Ordering;
+
+/// Atomic holding a sequence number
+pub(crate) trait SequenceCell {
+    fn load(&self, order: Ordering) -> u64;
+    fn store(&self, value: u64, order: Ordering);
+}
+
+impl SequenceCell for std::sync::atomic::AtomicU64 {
+    fn load(&self, order: Ordering) -> u64 {
+        self.load(order)
+    }
+
+    fn store(&self, value: u64, order: Ordering) {
+        self.store(value, order)
+    }
+}
+
+#[cfg(bridge_loom)]
+impl SequenceCell for loom::sync::atomic::AtomicU64 {
+    fn load(&self, order: Ordering) -> u64 {
+        self.load(order)
+    }
+
+    fn store(&self, value: u64, order: Ordering) {
+        self.store(value, order)
+    }
+}
+
+/// Publish `value` once the payload it stands for is written, 0 to reset
+#[cfg_attr(not(feature = "mmap-writer"), allow(dead_code))]
+pub(crate) fn publish(sequence: &impl SequenceCell, value: u64) {
+    sequence.store(value, Ordering::Release);
+}
+
+/// Current sequence, with the payload published with it visible
+pub(crate) fn current(sequence: &impl SequenceCell) -> u64 {
+    sequence.load(Ordering::Acquire)
+}
+
+/// Sequence published since the reader saw `last`, if any
+///
+/// A sequence below `last` means the buffer was created anew and counts as new
+/// data; 0 means nothing was written to it yet.
+#[cfg_attr(not(feature = "mmap-reader"), allow(dead_code))]
+pub(crate) fn observe(sequence: &impl SequenceCell, last: u64) -> Option<u64> {
+    let current = current(sequence);
+    (current != 0 && current != last).then_some(current)
+}
+
+#[cfg(all(test, not(bridge_loom)))]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU64;
+
+    #[test]
+    fn test_observe_follows_resets() {
+        let sequence = AtomicU64::new(0);
+        assert_eq!(observe(&sequence, 0), None);
+
+        publish(&sequence, 7);
+        assert_eq!(observe(&sequence, 0), Some(7));
+        assert_eq!(observe(&sequence, 7), None);
+
+        // A new writer starts over below what the reader saw
+        publish(&sequence, 0);
+        assert_eq!(observe(&sequence, 7), None);
+        publish(&sequence, 1);
+        assert_eq!(observe(&sequence, 7), Some(1));
+    }
+}
+
+#[cfg(all(test, bridge_loom))]
+mod loom_tests {
+    use super::*;
+    use loom::cell::UnsafeCell;
+    use loom::sync::Arc;
+    use loom::sync::atomic::AtomicU64;
+    use loom::thread;
+
+    #[test]
+    fn test_published_payload_is_visible() {
+        loom::model(|| {
+            let shared = Arc::new((AtomicU64::new(0), UnsafeCell::new(0u64)));
+
+            let writer = {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    shared.1.with_mut(|payload| unsafe { *payload = 42 });
+                    publish(&shared.0, 1);
+                })
+            };
+
+            // Reading the payload without the Release/Acquire pair is reported as
+            // a data race
+            if observe(&shared.0, 0) == Some(1) {
+                assert_eq!(shared.1.with(|payload| unsafe { *payload }), 42);
+            }
+            writer.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn test_payload_is_at_least_as_fresh_as_sequence() {
+        loom::model(|| {
+            // Payload words written in place, each holding the frame number
+            let shared = Arc::new((AtomicU64::new(0), [AtomicU64::new(0), AtomicU64::new(0)]));
+
+            let writer = {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    for frame in 1..=2 {
+                        for word in &shared.1 {
+                            word.store(frame, Ordering::Relaxed);
+                        }
+                        publish(&shared.0, frame);
+                    }
+                })
+            };
+
+            if let Some(sequence) = observe(&shared.0, 0) {
+                for word in &shared.1 {
+                    assert!(word.load(Ordering::Relaxed) >= sequence);
+                }
+            }
+            writer.join().unwrap();
+        });
+    }
+
+    #[test]
+    fn test_reader_follows_epoch_reset() {
+        loom::model(|| {
+            // The previous writer got to 2, which the reader has seen
+            let sequence = Arc::new(AtomicU64::new(2));
+            let last = 2;
+
+            let writer = {
+                let sequence = sequence.clone();
+                thread::spawn(move || {
+                    publish(&*sequence, 0);
+                    publish(&*sequence, 1);
+                })
+            };
+
+            let seen = observe(&*sequence, last);
+            assert!(seen.is_none() || seen == Some(1), "observed {seen:?}");
+            writer.join().unwrap();
+
+            assert_eq!(observe(&*sequence, last), Some(1));
+        });
+    }
+}
@@ -0,0 +1,101 @@
+//! Free space checks on the filesystem holding the shared buffers
+//!
+//! Buffer files are sparse: creating one succeeds on a full tmpfs, and the first
+//! write touching an unbacked page then kills the writer with SIGBUS. Writers check
+//! the space a buffer needs up front instead, and fail with the usage of the
+//! filesystem and what the buffer requires.
+
+use crate::errors::BridgeError;
+use nix::sys::statvfs::statvfs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+/// Size and free space of a filesystem, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpaceUsage {
+    pub total: u64,
+    /// Free space available to unprivileged users
+    pub available: u64,
+}
+
+impl SpaceUsage {
+    /// Usage of the filesystem holding `dir`
+    pub fn of(dir: impl AsRef<Path>) -> Result<Self, BridgeError> {
+        let stats = statvfs(dir.as_ref()).map_err(std::io::Error::from)?;
+        let fragment = stats.fragment_size() as u64;
+        Ok(Self {
+            total: stats.blocks() as u64 * fragment,
+            available: stats.blocks_available() as u64 * fragment,
+        })
+    }
+
+    pub fn used(&self) -> u64 {
+        self.total.saturating_sub(self.available)
+    }
+}
+
+/// Bytes `path` still needs to hold `size` bytes, given what it already occupies
+fn missing_bytes(path: &Path, size: usize) -> u64 {
+    let allocated = std::fs::metadata(path).map_or(0, |meta| meta.blocks() * 512);
+    (size as u64).saturating_sub(allocated)
+}
+
+/// Space left for a buffer at `path` once it reaches `size` bytes, negative when
+/// it does not fit
+#[cfg(feature = "frame-writer")]
+pub(crate) fn headroom(path: impl AsRef<Path>, size: usize) -> Result<i128, BridgeError> {
+    let path = path.as_ref();
+    let usage = SpaceUsage::of(parent_dir(path))?;
+    Ok(usage.available as i128 - missing_bytes(path, size) as i128)
+}
+
+/// Fail unless the filesystem holding `path` has room for a `size`-byte buffer
+pub(crate) fn ensure_space(path: &Path, size: usize) -> Result<(), BridgeError> {
+    let dir = parent_dir(path);
+    let usage = SpaceUsage::of(dir)?;
+    if missing_bytes(path, size) > usage.available {
+        return Err(BridgeError::InsufficientSpace {
+            path: path.display().to_string(),
+            dir: dir.display().to_string(),
+            required: size as u64,
+            available: usage.available,
+            used: usage.used(),
+            total: usage.total,
+        });
+    }
+    Ok(())
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_space_is_checked_against_the_filesystem() {
+        let path = Path::new(crate::paths::SHM_DIR).join("test_shm_space");
+        let _ = std::fs::remove_file(&path);
+        let usage = SpaceUsage::of(crate::paths::SHM_DIR).unwrap();
+        assert!(usage.total >= usage.available);
+
+        assert!(ensure_space(&path, 4096).is_ok());
+        let too_large = usage.available as usize + 1024 * 1024 * 1024;
+        match ensure_space(&path, too_large) {
+            Err(BridgeError::InsufficientSpace {
+                required, total, ..
+            }) => {
+                assert_eq!(required, too_large as u64);
+                assert_eq!(total, usage.total);
+            }
+            other => panic!("expected InsufficientSpace, got {other:?}"),
+        }
+        #[cfg(feature = "frame-writer")]
+        assert!(headroom(&path, too_large).unwrap() < 0);
+    }
+}
@@ -0,0 +1,191 @@
+//! On-demand snapshot requests shared between services
+//!
+//! A remote command (the controller's MQTT command topic) asks for a snapshot by
+//! bumping the request counter; the gateway keeps the next frame it broadcasts and
+//! records which request it fulfilled with which frame, served from
+//! `/api/snapshots/<frame_number>`.
+//!
+//! Stills are requested the same way, through their own counters: capture writes
+//! the next camera frame at its native resolution, before any downscaling, to the
+//! snapshot buffer ([`paths::SNAPSHOT_BUFFER_PATH`]) and records it fulfilled
+//! there. The gateway serves the latest one from `/api/stills/latest`. A still
+//! requested with [`SnapshotControl::request_night_still`] is taken with capture's
+//! long night exposure when the scene is dark (capture's `night_still`).
+
+use crate::errors::BridgeError;
+use crate::paths;
+use memmap2::MmapMut;
+use std::fs::OpenOptions;
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[repr(C)]
+struct Slots {
+    /// Number of the latest request, 0 before any
+    requested: AtomicU64,
+    /// Number of the latest fulfilled request
+    fulfilled: AtomicU64,
+    /// Frame kept for it
+    frame_number: AtomicU64,
+    /// Same as above, for full resolution stills
+    still_requested: AtomicU64,
+    still_fulfilled: AtomicU64,
+    still_frame_number: AtomicU64,
+    /// Latest still request asking for a long exposure
+    night_still_requested: AtomicU64,
+}
+
+pub struct SnapshotControl {
+    _mmap: MmapMut,
+    slots: &'static Slots,
+}
+
+unsafe impl Send for SnapshotControl {}
+unsafe impl Sync for SnapshotControl {}
+
+impl SnapshotControl {
+    /// Create or open the snapshot control at its default path
+    pub fn build() -> Result<Self, BridgeError> {
+        Self::new(paths::SNAPSHOT_CONTROL_PATH)
+    }
+
+    /// Create or open the snapshot control at `path` (useful for tests)
+    pub fn new(path: &str) -> Result<Self, BridgeError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(path)?;
+        crate::permissions::secure(&file)?;
+
+        let size = std::mem::size_of::<Slots>() as u64;
+        if file.metadata()?.len() < size {
+            file.set_len(size)?;
+        }
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+        let slots = unsafe { &*(mmap.as_mut_ptr() as *const Slots) };
+
+        Ok(Self { _mmap: mmap, slots })
+    }
+
+    /// Ask for a snapshot, returning the request number
+    pub fn request(&self) -> u64 {
+        self.slots.requested.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    /// Latest request not fulfilled yet
+    pub fn pending(&self) -> Option<u64> {
+        let requested = self.slots.requested.load(Ordering::Acquire);
+        (requested > self.slots.fulfilled.load(Ordering::Acquire)).then_some(requested)
+    }
+
+    /// Record `frame_number` as the snapshot for every request up to `request`
+    pub fn fulfill(&self, request: u64, frame_number: u64) {
+        // The frame number goes first, so a reader seeing the request sees its frame
+        self.slots
+            .frame_number
+            .store(frame_number, Ordering::Release);
+        self.slots.fulfilled.store(request, Ordering::Release);
+    }
+
+    /// Latest fulfilled request and its frame number
+    pub fn fulfilled(&self) -> Option<(u64, u64)> {
+        let request = self.slots.fulfilled.load(Ordering::Acquire);
+        (request > 0).then(|| (request, self.slots.frame_number.load(Ordering::Acquire)))
+    }
+
+    /// Ask capture for a full resolution still, returning the request number
+    pub fn request_still(&self) -> u64 {
+        self.slots.still_requested.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    /// Ask for a still taken with a long exposure if the scene is dark, returning
+    /// the request number
+    pub fn request_night_still(&self) -> u64 {
+        let request = self.request_still();
+        self.slots
+            .night_still_requested
+            .fetch_max(request, Ordering::AcqRel);
+        request
+    }
+
+    /// Whether a still request not fulfilled yet asked for a long exposure
+    pub fn pending_night_still(&self) -> bool {
+        self.slots.night_still_requested.load(Ordering::Acquire)
+            > self.slots.still_fulfilled.load(Ordering::Acquire)
+    }
+
+    /// Latest still request not fulfilled yet
+    pub fn pending_still(&self) -> Option<u64> {
+        let requested = self.slots.still_requested.load(Ordering::Acquire);
+        (requested > self.slots.still_fulfilled.load(Ordering::Acquire)).then_some(requested)
+    }
+
+    /// Record the still written to the snapshot buffer as `frame_number` for every
+    /// request up to `request`
+    pub fn fulfill_still(&self, request: u64, frame_number: u64) {
+        self.slots
+            .still_frame_number
+            .store(frame_number, Ordering::Release);
+        self.slots.still_fulfilled.store(request, Ordering::Release);
+    }
+
+    /// Latest fulfilled still request and its frame number
+    pub fn still_fulfilled(&self) -> Option<(u64, u64)> {
+        let request = self.slots.still_fulfilled.load(Ordering::Acquire);
+        (request > 0).then(|| {
+            (
+                request,
+                self.slots.still_frame_number.load(Ordering::Acquire),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_are_fulfilled_across_handles() {
+        let path = &format!("{}/test_snapshot_control", crate::paths::SHM_DIR);
+        let _ = std::fs::remove_file(path);
+
+        let controller = SnapshotControl::new(path).unwrap();
+        let gateway = SnapshotControl::new(path).unwrap();
+        assert_eq!(gateway.pending(), None);
+        assert_eq!(controller.fulfilled(), None);
+
+        assert_eq!(controller.request(), 1);
+        assert_eq!(controller.request(), 2);
+        // Both requests are served by the same frame
+        assert_eq!(gateway.pending(), Some(2));
+        gateway.fulfill(2, 1234);
+        assert_eq!(gateway.pending(), None);
+        assert_eq!(controller.fulfilled(), Some((2, 1234)));
+
+        // Stills are counted apart from snapshots
+        assert_eq!(gateway.pending_still(), None);
+        assert_eq!(controller.request_still(), 1);
+        assert_eq!(gateway.pending_still(), Some(1));
+        assert_eq!(gateway.pending(), None);
+        gateway.fulfill_still(1, 1240);
+        assert_eq!(gateway.pending_still(), None);
+        assert_eq!(controller.still_fulfilled(), Some((1, 1240)));
+        assert_eq!(controller.fulfilled(), Some((2, 1234)));
+
+        // A long exposure is wanted until the request asking for it is fulfilled
+        assert!(!gateway.pending_night_still());
+        assert_eq!(controller.request_night_still(), 2);
+        assert_eq!(controller.request_still(), 3);
+        assert!(gateway.pending_night_still());
+        gateway.fulfill_still(3, 1250);
+        assert!(!gateway.pending_night_still());
+        assert_eq!(controller.still_fulfilled(), Some((3, 1250)));
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,174 @@
+//! Sharing DMA buffer file descriptors with other processes
+//!
+//! Hardware buffers such as Jetson NVMM surfaces are only reachable through dmabuf
+//! file descriptors, which cannot be opened by path like the mmap buffers. The
+//! producer serves its descriptors on a Unix socket and every consumer receives its
+//! own duplicates (`SCM_RIGHTS`) when it connects. Frames then refer to a buffer by
+//! its index in that list.
+//!
+//! Descriptors are exchanged once: a producer restart allocates new buffers, so
+//! consumers import again when frame numbers restart.
+
+use crate::errors::BridgeError;
+use nix::sys::socket::{ControlMessage, ControlMessageOwned, MsgFlags, recvmsg, sendmsg};
+use std::io::{IoSlice, IoSliceMut};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+
+/// Maximum number of buffers shared by one producer
+pub const MAX_SURFACES: usize = 16;
+
+/// Serves a fixed set of dmabuf descriptors until dropped
+pub struct SurfaceExporter {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl SurfaceExporter {
+    /// Listen on `path` and hand `fds` to every consumer that connects
+    pub fn serve(path: &str, fds: Vec<OwnedFd>) -> Result<Self, BridgeError> {
+        if fds.is_empty() || fds.len() > MAX_SURFACES {
+            return Err(BridgeError::SemaphoreError(format!(
+                "Expected 1-{} surfaces to share, got {}",
+                MAX_SURFACES,
+                fds.len()
+            )));
+        }
+
+        // A socket left behind by a previous run refuses new binds
+        let _ = std::fs::remove_file(path);
+        let listener = UnixListener::bind(path)?;
+        crate::permissions::secure_path(std::path::Path::new(path))?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = Arc::clone(&stop);
+            std::thread::Builder::new()
+                .name("surface-export".into())
+                .spawn(move || accept_loop(listener, fds, stop))?
+        };
+
+        Ok(Self {
+            path: PathBuf::from(path),
+            stop,
+            handle: Some(handle),
+        })
+    }
+}
+
+fn accept_loop(listener: UnixListener, fds: Vec<OwnedFd>, stop: Arc<AtomicBool>) {
+    let raw: Vec<RawFd> = fds.iter().map(AsRawFd::as_raw_fd).collect();
+    let count = (raw.len() as u32).to_le_bytes();
+
+    for stream in listener.incoming() {
+        if stop.load(Ordering::Acquire) {
+            break;
+        }
+        let result = stream.map_err(BridgeError::from).and_then(|stream| {
+            sendmsg::<()>(
+                stream.as_raw_fd(),
+                &[IoSlice::new(&count)],
+                &[ControlMessage::ScmRights(&raw)],
+                MsgFlags::empty(),
+                None,
+            )
+            .map_err(|e| BridgeError::IoError(e.into()))
+        });
+        match result {
+            Ok(_) => tracing::debug!(surfaces = raw.len(), "Shared surfaces with consumer"),
+            Err(e) => tracing::warn!(error = %e, "Failed to share surfaces"),
+        }
+    }
+}
+
+impl Drop for SurfaceExporter {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        // Wake the accept loop so it sees the stop flag
+        let _ = UnixStream::connect(&self.path);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Receive the descriptors served at `path`, in the producer's order
+pub fn import_surfaces(path: &str) -> Result<Vec<OwnedFd>, BridgeError> {
+    let stream = UnixStream::connect(path)?;
+
+    let mut count = [0u8; 4];
+    let mut iov = [IoSliceMut::new(&mut count)];
+    let mut cmsg = nix::cmsg_space!([RawFd; MAX_SURFACES]);
+    let msg = recvmsg::<()>(
+        stream.as_raw_fd(),
+        &mut iov,
+        Some(&mut cmsg),
+        MsgFlags::MSG_CMSG_CLOEXEC,
+    )
+    .map_err(|e| BridgeError::IoError(e.into()))?;
+
+    let mut fds = Vec::new();
+    for cmsg in msg.cmsgs().map_err(|e| BridgeError::IoError(e.into()))? {
+        if let ControlMessageOwned::ScmRights(raw) = cmsg {
+            // SAFETY: descriptors received with SCM_RIGHTS are new and owned by us
+            fds.extend(
+                raw.into_iter()
+                    .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }),
+            );
+        }
+    }
+
+    let expected = u32::from_le_bytes(count) as usize;
+    if fds.len() != expected {
+        return Err(BridgeError::SizeMismatch);
+    }
+    Ok(fds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    #[test]
+    fn test_consumers_receive_shared_descriptors() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("surfaces.sock");
+        let socket = socket.to_str().unwrap();
+
+        let mut files: Vec<File> = (0..2).map(|_| tempfile::tempfile().unwrap()).collect();
+        files[1].write_all(b"surface one").unwrap();
+        let fds = files
+            .iter()
+            .map(|f| f.try_clone().unwrap().into())
+            .collect();
+        let exporter = SurfaceExporter::serve(socket, fds).unwrap();
+
+        for _ in 0..2 {
+            let imported = import_surfaces(socket).unwrap();
+            assert_eq!(imported.len(), 2);
+
+            // The imported descriptor refers to the same open file
+            let mut file = File::from(imported.into_iter().nth(1).unwrap());
+            file.seek(SeekFrom::Start(0)).unwrap();
+            let mut contents = String::new();
+            file.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents, "surface one");
+        }
+
+        drop(exporter);
+        assert!(import_surfaces(socket).is_err());
+    }
+
+    #[test]
+    fn test_rejects_empty_surface_list() {
+        assert!(SurfaceExporter::serve("/tmp/unused-surfaces.sock", Vec::new()).is_err());
+    }
+}
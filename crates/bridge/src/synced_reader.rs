@@ -0,0 +1,257 @@
+//! Frame and detection pairing
+//!
+//! The frame and detection buffers each hold only their latest write, and inference
+//! lags capture by one or more frames. Reading both buffers at the same instant pairs
+//! a frame with detections computed on an older one. `SyncedReader` buffers recent
+//! frames and detection results and pairs them by `frame_number` instead.
+//!
+//! Frames are converted as they are ingested (e.g. copied or JPEG-encoded straight
+//! from the mmap), so the buffered frames stay valid after the writer moves on.
+//!
+//! Pairs come out in frame order. A frame is yielded without detections once
+//! inference has produced results for a later frame (it was skipped), or when the
+//! frame buffer overflows.
+
+use crate::{
+    DetectionReader, FrameReader,
+    clock::CapturedAt,
+    types::{Detection, InferenceTiming},
+};
+use anyhow::Result;
+use common::WallClockNs;
+use schema::{Frame, FrameEncoding};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Frames waiting for their detections, about a quarter second at 30 fps
+pub const DEFAULT_CAPACITY: usize = 8;
+
+/// Interval between buffer checks while blocked in [`SyncedReader::next_pair`]
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Owned copy of a frame, the default conversion
+#[derive(Debug, Clone)]
+pub struct OwnedFrame {
+    pub camera_id: u32,
+    pub frame_number: u64,
+    /// Capture time on the wall clock
+    pub timestamp_ns: WallClockNs,
+    pub width: u32,
+    pub height: u32,
+    /// RGB pixels, or a JPEG image when `encoding` says so
+    pub pixels: Vec<u8>,
+    pub encoding: FrameEncoding,
+}
+
+impl From<&Frame<'_>> for OwnedFrame {
+    fn from(frame: &Frame<'_>) -> Self {
+        // A corrupt LZ4 frame is kept without pixels, like one without a payload
+        let (encoding, pixels) = crate::compression::frame_payload(frame)
+            .map(|(encoding, pixels)| (encoding, pixels.into_owned()))
+            .unwrap_or((FrameEncoding::Rgb, Vec::new()));
+        Self {
+            camera_id: frame.camera_id(),
+            frame_number: frame.frame_number(),
+            timestamp_ns: frame.captured_at().to_wall_clock(),
+            width: frame.width(),
+            height: frame.height(),
+            pixels,
+            encoding,
+        }
+    }
+}
+
+/// A frame and the detections computed on it
+#[derive(Debug)]
+pub struct FramePair<F> {
+    pub frame_number: u64,
+    pub frame: F,
+    /// `None` if inference skipped this frame
+    pub detections: Option<Vec<Detection>>,
+    /// When inference ran on this frame, if the detections record it
+    pub timing: Option<InferenceTiming>,
+}
+
+pub struct SyncedReader<F = OwnedFrame> {
+    frame_reader: FrameReader,
+    detection_reader: DetectionReader,
+    convert: fn(&Frame<'_>) -> Result<F>,
+    capacity: usize,
+    frames: VecDeque<(u64, F)>,
+    detections: BTreeMap<u64, (Vec<Detection>, Option<InferenceTiming>)>,
+    frame_sequence: u64,
+    detection_sequence: u64,
+}
+
+impl SyncedReader<OwnedFrame> {
+    /// Open both buffers at their default paths, copying frames as they arrive
+    pub fn build() -> Result<Self> {
+        Ok(Self::new(
+            FrameReader::build()?,
+            DetectionReader::build()?,
+            |frame| Ok(OwnedFrame::from(frame)),
+        ))
+    }
+
+    pub fn with_paths(frame_path: &str, detection_path: &str) -> Result<Self> {
+        Ok(Self::new(
+            FrameReader::with_path(frame_path)?,
+            DetectionReader::with_path(detection_path)?,
+            |frame| Ok(OwnedFrame::from(frame)),
+        ))
+    }
+}
+
+impl<F> SyncedReader<F> {
+    /// Pair frames from `frame_reader` with results from `detection_reader`
+    ///
+    /// `convert` runs once per new frame while the mmap is borrowed.
+    pub fn new(
+        frame_reader: FrameReader,
+        detection_reader: DetectionReader,
+        convert: fn(&Frame<'_>) -> Result<F>,
+    ) -> Self {
+        Self {
+            frame_reader,
+            detection_reader,
+            convert,
+            capacity: DEFAULT_CAPACITY,
+            frames: VecDeque::new(),
+            detections: BTreeMap::new(),
+            frame_sequence: 0,
+            detection_sequence: 0,
+        }
+    }
+
+    /// Number of frames held while waiting for detections
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Next frame in order with its detections, waiting at most `timeout`
+    ///
+    /// Returns `Ok(None)` if no pair became available in time; a zero timeout only
+    /// checks the buffers once.
+    pub fn next_pair(&mut self, timeout: Duration) -> Result<Option<FramePair<F>>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.poll()?;
+            if let Some(pair) = self.pop_pair() {
+                return Ok(Some(pair));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Ingest whatever is new in either buffer without yielding pairs
+    pub fn poll(&mut self) -> Result<()> {
+        self.poll_frame()?;
+        self.poll_detections()
+    }
+
+    /// Frames currently waiting for detections
+    pub fn pending_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// PID of the new frame writer if another one claimed the frame buffer, see
+    /// `FrameReader::writer_changed`
+    pub fn frame_writer_changed(&mut self) -> Option<u32> {
+        self.frame_reader.writer_changed()
+    }
+
+    /// PID of the new detection writer if another one claimed the detection buffer
+    pub fn detection_writer_changed(&mut self) -> Option<u32> {
+        self.detection_reader.writer_changed()
+    }
+
+    fn poll_frame(&mut self) -> Result<()> {
+        let sequence = self.frame_reader.current_sequence();
+        if sequence == self.frame_sequence {
+            return Ok(());
+        }
+        self.frame_sequence = sequence;
+        self.frame_reader.mark_read();
+
+        let Some(frame) = self.frame_reader.get_frame()? else {
+            return Ok(());
+        };
+        let frame_number = frame.frame_number();
+
+        // Frame numbers restart with capture, nothing buffered can match anymore
+        if self
+            .frames
+            .back()
+            .is_some_and(|(last, _)| frame_number <= *last)
+        {
+            tracing::debug!(frame_number, "Frame numbers restarted, clearing buffers");
+            self.frames.clear();
+            self.detections.clear();
+        }
+
+        let converted = (self.convert)(&frame)?;
+        self.frames.push_back((frame_number, converted));
+        Ok(())
+    }
+
+    fn poll_detections(&mut self) -> Result<()> {
+        let sequence = self.detection_reader.current_sequence();
+        if sequence == self.detection_sequence {
+            return Ok(());
+        }
+        self.detection_sequence = sequence;
+        self.detection_reader.mark_read();
+
+        let Some(result) = self.detection_reader.get_detections()? else {
+            return Ok(());
+        };
+        let detections = result
+            .detections()
+            .map(|dets| {
+                dets.iter()
+                    .filter_map(|d| Detection::try_from(&d).ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.detections.insert(
+            result.frame_number(),
+            (detections, InferenceTiming::from_result(&result)),
+        );
+
+        // Results for frames no longer buffered will never be paired
+        if let Some((oldest, _)) = self.frames.front() {
+            self.detections = self.detections.split_off(oldest);
+        }
+        while self.detections.len() > self.capacity {
+            self.detections.pop_first();
+        }
+        Ok(())
+    }
+
+    fn pop_pair(&mut self) -> Option<FramePair<F>> {
+        let &(frame_number, _) = self.frames.front()?;
+
+        let detections = self.detections.remove(&frame_number);
+        let superseded = self
+            .detections
+            .last_key_value()
+            .is_some_and(|(latest, _)| *latest > frame_number);
+
+        if detections.is_none() && !superseded && self.frames.len() <= self.capacity {
+            return None;
+        }
+
+        let (frame_number, frame) = self.frames.pop_front()?;
+        let (detections, timing) = detections.unzip();
+        Some(FramePair {
+            frame_number,
+            frame,
+            detections,
+            timing: timing.flatten(),
+        })
+    }
+}
@@ -0,0 +1,141 @@
+//! Runtime threshold overrides shared between services
+//!
+//! The gateway's tuning API writes requested thresholds here, and the service
+//! owning each threshold (inference for the detection confidence, controller for
+//! the alert confidence) applies them on its next frame and publishes the value
+//! it actually uses. Overrides live in shared memory only, so a restart of the
+//! owning service keeps them and a reboot returns to the configured values.
+
+use crate::errors::BridgeError;
+use crate::paths;
+use memmap2::MmapMut;
+use std::fs::OpenOptions;
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Marks a slot holding a value; an all-zero slot is unset
+const SET: u64 = 1 << 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Threshold {
+    /// Minimum confidence of a detection, applied by inference
+    Confidence = 0,
+    /// Minimum confidence of a detection raising an alert, applied by the controller
+    AlertConfidence = 1,
+}
+
+impl Threshold {
+    pub const ALL: [Self; 2] = [Self::Confidence, Self::AlertConfidence];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Confidence => "confidence",
+            Self::AlertConfidence => "alert_confidence",
+        }
+    }
+}
+
+#[repr(C)]
+struct Slots {
+    requested: [AtomicU64; 2],
+    effective: [AtomicU64; 2],
+}
+
+pub struct ThresholdControl {
+    _mmap: MmapMut,
+    slots: &'static Slots,
+}
+
+unsafe impl Send for ThresholdControl {}
+unsafe impl Sync for ThresholdControl {}
+
+impl ThresholdControl {
+    /// Create or open the threshold control at its default path
+    pub fn build() -> Result<Self, BridgeError> {
+        Self::new(paths::THRESHOLD_CONTROL_PATH)
+    }
+
+    /// Create or open the threshold control at `path` (useful for tests)
+    pub fn new(path: &str) -> Result<Self, BridgeError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(path)?;
+        crate::permissions::secure(&file)?;
+
+        let size = std::mem::size_of::<Slots>() as u64;
+        if file.metadata()?.len() < size {
+            file.set_len(size)?;
+        }
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+        let slots = unsafe { &*(mmap.as_mut_ptr() as *const Slots) };
+
+        Ok(Self { _mmap: mmap, slots })
+    }
+
+    /// Override requested at runtime, `None` to keep the configured value
+    pub fn requested(&self, threshold: Threshold) -> Option<f32> {
+        decode(self.slots.requested[threshold as usize].load(Ordering::Acquire))
+    }
+
+    pub fn request(&self, threshold: Threshold, value: Option<f32>) {
+        self.slots.requested[threshold as usize].store(encode(value), Ordering::Release);
+    }
+
+    /// Value the owning service applies, `None` until it published one
+    pub fn effective(&self, threshold: Threshold) -> Option<f32> {
+        decode(self.slots.effective[threshold as usize].load(Ordering::Acquire))
+    }
+
+    /// The requested override, or `configured` without one, published as effective
+    pub fn resolve(&self, threshold: Threshold, configured: f32) -> f32 {
+        let value = self.requested(threshold).unwrap_or(configured);
+        let slot = &self.slots.effective[threshold as usize];
+        if decode(slot.load(Ordering::Relaxed)) != Some(value) {
+            slot.store(encode(Some(value)), Ordering::Release);
+        }
+        value
+    }
+}
+
+fn encode(value: Option<f32>) -> u64 {
+    value.map_or(0, |v| SET | u64::from(v.to_bits()))
+}
+
+fn decode(slot: u64) -> Option<f32> {
+    (slot & SET != 0).then(|| f32::from_bits(slot as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides_are_shared_and_resolved() {
+        let path = &format!("{}/test_threshold_control", crate::paths::SHM_DIR);
+        let _ = std::fs::remove_file(path);
+
+        let gateway = ThresholdControl::new(path).unwrap();
+        let inference = ThresholdControl::new(path).unwrap();
+
+        assert_eq!(gateway.requested(Threshold::Confidence), None);
+        assert_eq!(gateway.effective(Threshold::Confidence), None);
+        assert_eq!(inference.resolve(Threshold::Confidence, 0.7), 0.7);
+        assert_eq!(gateway.effective(Threshold::Confidence), Some(0.7));
+
+        // Zero is a valid override, distinct from unset
+        gateway.request(Threshold::Confidence, Some(0.0));
+        assert_eq!(inference.resolve(Threshold::Confidence, 0.7), 0.0);
+        assert_eq!(gateway.effective(Threshold::Confidence), Some(0.0));
+        assert_eq!(gateway.effective(Threshold::AlertConfidence), None);
+
+        gateway.request(Threshold::Confidence, None);
+        assert_eq!(inference.resolve(Threshold::Confidence, 0.7), 0.7);
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -1,4 +1,40 @@
+use crate::clock::CapturedAt;
+use common::WallClockNs;
+use flatbuffers::{FlatBufferBuilder, WIPOffset};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+/// What a shared memory buffer carries, recorded in its header by the writer that
+/// created it
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferKind {
+    Frame = 1,
+    Detection = 2,
+    Event = 3,
+}
+
+impl BufferKind {
+    pub(crate) fn from_raw(raw: u32) -> Option<Self> {
+        match raw {
+            1 => Some(Self::Frame),
+            2 => Some(Self::Detection),
+            3 => Some(Self::Event),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BufferKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Frame => "frame",
+            Self::Detection => "detection",
+            Self::Event => "event",
+        })
+    }
+}
 
 /// Detection result with bounding box coordinates, confidence, and class.
 /// Used for JSON serialization at API boundaries (gateway).
@@ -28,3 +64,226 @@ impl TryFrom<&schema::Detection<'_>> for Detection {
         })
     }
 }
+
+impl From<&Detection> for common::event::EventDetection {
+    fn from(det: &Detection) -> Self {
+        Self {
+            x1: det.x1,
+            y1: det.y1,
+            x2: det.x2,
+            y2: det.y2,
+            confidence: det.confidence,
+            class_id: det.class_id,
+        }
+    }
+}
+
+/// When a detection result's frame was captured and inferred, on the wall clock.
+/// Maps to the timestamp fields of the FlatBuffers `DetectionResult` table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct InferenceTiming {
+    pub frame_timestamp_ns: WallClockNs,
+    pub inference_started_ns: WallClockNs,
+    pub inference_completed_ns: WallClockNs,
+}
+
+impl InferenceTiming {
+    /// Timing of `result`, `None` if its writer did not record it
+    pub fn from_result(result: &schema::DetectionResult<'_>) -> Option<Self> {
+        let (started, completed) = (
+            result.inference_started_ns(),
+            result.inference_completed_ns(),
+        );
+        (started != 0 && completed != 0).then(|| Self {
+            frame_timestamp_ns: result.captured_at().to_wall_clock(),
+            inference_started_ns: WallClockNs::from_nanos(started),
+            inference_completed_ns: WallClockNs::from_nanos(completed),
+        })
+    }
+
+    /// Time spent in preprocessing, the model and postprocessing
+    pub fn latency(&self) -> Duration {
+        self.inference_completed_ns
+            .saturating_duration_since(self.inference_started_ns)
+    }
+
+    /// Time the frame waited before inference picked it up
+    pub fn queue_delay(&self) -> Duration {
+        self.inference_started_ns
+            .saturating_duration_since(self.frame_timestamp_ns)
+    }
+
+    /// Age of the detections at `now`, counted from frame capture
+    pub fn age_at(&self, now: WallClockNs) -> Duration {
+        now.saturating_duration_since(self.frame_timestamp_ns)
+    }
+}
+
+/// Capture provenance describing where and how a frame was produced.
+/// Maps to the FlatBuffers `Provenance` table, which is carried by both
+/// `Frame` and `DetectionResult` so saved output stays self-describing.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Provenance {
+    pub capture_host: String,
+    pub device_path: String,
+    pub device_name: String,
+    pub driver: String,
+    pub decoder: String,
+    pub exposure_auto: bool,
+    /// Exposure upper limit in V4L2 `exposure_absolute` units (100µs), 0 if unset
+    pub exposure_limit: u32,
+    pub nominal_fps: f32,
+    pub firmware_version: String,
+    pub app_version: String,
+}
+
+impl Provenance {
+    /// Serialize into `fbb`, returning the offset to pass as `provenance` when
+    /// building a `Frame` or `DetectionResult`.
+    pub fn create<'a>(&self, fbb: &mut FlatBufferBuilder<'a>) -> WIPOffset<schema::Provenance<'a>> {
+        build_provenance(
+            fbb,
+            ProvenanceFields {
+                capture_host: &self.capture_host,
+                device_path: &self.device_path,
+                device_name: &self.device_name,
+                driver: &self.driver,
+                decoder: &self.decoder,
+                exposure_auto: self.exposure_auto,
+                exposure_limit: self.exposure_limit,
+                nominal_fps: self.nominal_fps,
+                firmware_version: &self.firmware_version,
+                app_version: &self.app_version,
+            },
+        )
+    }
+
+    /// Re-encode a provenance table read from another buffer into `fbb`
+    /// without going through an owned copy.
+    pub fn copy_into<'a>(
+        fbb: &mut FlatBufferBuilder<'a>,
+        src: &schema::Provenance<'_>,
+    ) -> WIPOffset<schema::Provenance<'a>> {
+        build_provenance(
+            fbb,
+            ProvenanceFields {
+                capture_host: src.capture_host().unwrap_or_default(),
+                device_path: src.device_path().unwrap_or_default(),
+                device_name: src.device_name().unwrap_or_default(),
+                driver: src.driver().unwrap_or_default(),
+                decoder: src.decoder().unwrap_or_default(),
+                exposure_auto: src.exposure_auto(),
+                exposure_limit: src.exposure_limit(),
+                nominal_fps: src.nominal_fps(),
+                firmware_version: src.firmware_version().unwrap_or_default(),
+                app_version: src.app_version().unwrap_or_default(),
+            },
+        )
+    }
+}
+
+impl From<&schema::Provenance<'_>> for Provenance {
+    fn from(p: &schema::Provenance) -> Self {
+        Self {
+            capture_host: p.capture_host().unwrap_or_default().to_owned(),
+            device_path: p.device_path().unwrap_or_default().to_owned(),
+            device_name: p.device_name().unwrap_or_default().to_owned(),
+            driver: p.driver().unwrap_or_default().to_owned(),
+            decoder: p.decoder().unwrap_or_default().to_owned(),
+            exposure_auto: p.exposure_auto(),
+            exposure_limit: p.exposure_limit(),
+            nominal_fps: p.nominal_fps(),
+            firmware_version: p.firmware_version().unwrap_or_default().to_owned(),
+            app_version: p.app_version().unwrap_or_default().to_owned(),
+        }
+    }
+}
+
+struct ProvenanceFields<'s> {
+    capture_host: &'s str,
+    device_path: &'s str,
+    device_name: &'s str,
+    driver: &'s str,
+    decoder: &'s str,
+    exposure_auto: bool,
+    exposure_limit: u32,
+    nominal_fps: f32,
+    firmware_version: &'s str,
+    app_version: &'s str,
+}
+
+fn build_provenance<'a>(
+    fbb: &mut FlatBufferBuilder<'a>,
+    fields: ProvenanceFields<'_>,
+) -> WIPOffset<schema::Provenance<'a>> {
+    let capture_host = fbb.create_string(fields.capture_host);
+    let device_path = fbb.create_string(fields.device_path);
+    let device_name = fbb.create_string(fields.device_name);
+    let driver = fbb.create_string(fields.driver);
+    let decoder = fbb.create_string(fields.decoder);
+    let firmware_version = fbb.create_string(fields.firmware_version);
+    let app_version = fbb.create_string(fields.app_version);
+
+    schema::Provenance::create(
+        fbb,
+        &schema::ProvenanceArgs {
+            capture_host: Some(capture_host),
+            device_path: Some(device_path),
+            device_name: Some(device_name),
+            driver: Some(driver),
+            decoder: Some(decoder),
+            exposure_auto: fields.exposure_auto,
+            exposure_limit: fields.exposure_limit,
+            nominal_fps: fields.nominal_fps,
+            firmware_version: Some(firmware_version),
+            app_version: Some(app_version),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_provenance() -> Provenance {
+        Provenance {
+            capture_host: "cam-host".into(),
+            device_path: "/dev/video0".into(),
+            device_name: "USB Camera".into(),
+            driver: "uvcvideo".into(),
+            decoder: "yuyv".into(),
+            exposure_auto: true,
+            exposure_limit: 200,
+            nominal_fps: 30.0,
+            firmware_version: "6.8.0".into(),
+            app_version: "0.1.0".into(),
+        }
+    }
+
+    #[test]
+    fn test_provenance_roundtrip() {
+        let provenance = sample_provenance();
+        let mut fbb = FlatBufferBuilder::new();
+        let offset = provenance.create(&mut fbb);
+        fbb.finish(offset, None);
+
+        let decoded = flatbuffers::root::<schema::Provenance>(fbb.finished_data()).unwrap();
+        assert_eq!(Provenance::from(&decoded), provenance);
+    }
+
+    #[test]
+    fn test_provenance_copy_into_other_builder() {
+        let provenance = sample_provenance();
+        let mut src = FlatBufferBuilder::new();
+        let offset = provenance.create(&mut src);
+        src.finish(offset, None);
+        let decoded = flatbuffers::root::<schema::Provenance>(src.finished_data()).unwrap();
+
+        let mut dst = FlatBufferBuilder::new();
+        let copied = Provenance::copy_into(&mut dst, &decoded);
+        dst.finish(copied, None);
+
+        let recopied = flatbuffers::root::<schema::Provenance>(dst.finished_data()).unwrap();
+        assert_eq!(Provenance::from(&recopied), provenance);
+    }
+}
@@ -0,0 +1,363 @@
+//! Optional write gate keeping a writer from outrunning lossless readers
+//!
+//! A buffer holds a single message, so a writer publishing sequence N+1 before a
+//! reader is done with N makes that reader skip N without anyone noticing. Readers
+//! that must see every message (an encoder, a recorder) register on the buffer's
+//! gate with a [`GateSubscription`] and acknowledge each sequence they consumed. A
+//! writer given the [`WriteGate`] waits, at most its timeout, until every reader
+//! that acknowledged N-1 has acknowledged N before publishing N+1.
+//!
+//! A reader still behind at the timeout is skipped and counted as an overrun, and
+//! is not waited for again until it catches up, so a stuck reader costs the writer
+//! one timeout rather than one per message. Readers of processes that exited
+//! without unregistering are ignored, unregistered readers are never waited for.
+//!
+//! A writer cycling through a ring of buffers uses the gate the other way round:
+//! readers [hold](GateSubscription::hold) the buffer they are reading and the
+//! writer [waits for its release](WriteGate::wait_released) before overwriting it.
+//! A gate is used either for acknowledgements or for holds, never both.
+
+use crate::errors::BridgeError;
+use crate::process::process_alive;
+use memmap2::MmapMut;
+use std::fs::OpenOptions;
+use std::os::unix::fs::OpenOptionsExt;
+use std::sync::atomic::{AtomicU8, AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Maximum number of readers registered on one gate
+pub const MAX_READERS: usize = 8;
+
+/// Maximum length of a reader name
+pub const MAX_NAME_LEN: usize = 28;
+
+const FREE: u32 = 0;
+const CLAIMED: u32 = 1;
+const ACTIVE: u32 = 2;
+
+/// Pause between checks of the acknowledgements while the writer waits
+const POLL_INTERVAL: Duration = Duration::from_micros(200);
+
+#[repr(C)]
+struct Slot {
+    state: AtomicU32,
+    /// Process of the reader, to ignore readers that died registered
+    pid: AtomicU32,
+    /// Last sequence the reader consumed
+    acknowledged: AtomicU64,
+    name: [AtomicU8; MAX_NAME_LEN],
+}
+
+const GATE_SIZE: usize = MAX_READERS * size_of::<Slot>();
+
+struct GateMap {
+    _mmap: MmapMut,
+    slots: &'static [Slot; MAX_READERS],
+}
+
+impl GateMap {
+    fn open(path: &str) -> Result<Self, BridgeError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(path)?;
+        crate::permissions::secure(&file)?;
+
+        if file.metadata()?.len() < GATE_SIZE as u64 {
+            file.set_len(GATE_SIZE as u64)?;
+        }
+
+        let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+        let slots = unsafe { &*(mmap.as_mut_ptr() as *const [Slot; MAX_READERS]) };
+
+        Ok(Self { _mmap: mmap, slots })
+    }
+}
+
+fn read_name(slot: &Slot) -> Option<String> {
+    if slot.state.load(Ordering::Acquire) != ACTIVE {
+        return None;
+    }
+    let bytes: Vec<u8> = slot
+        .name
+        .iter()
+        .map(|b| b.load(Ordering::Relaxed))
+        .take_while(|b| *b != 0)
+        .collect();
+    String::from_utf8(bytes).ok()
+}
+
+fn waits_for(slot: &Slot, blocks: impl Fn(u64) -> bool) -> bool {
+    slot.state.load(Ordering::Acquire) == ACTIVE
+        && blocks(slot.acknowledged.load(Ordering::Acquire))
+        && process_alive(slot.pid.load(Ordering::Acquire))
+}
+
+/// A reader's registration on a gate, unregistered on drop
+pub struct GateSubscription {
+    gate: GateMap,
+    slot: usize,
+}
+
+unsafe impl Send for GateSubscription {}
+unsafe impl Sync for GateSubscription {}
+
+impl GateSubscription {
+    /// Register `name` on the gate at `path`, reusing its slot if a previous run of
+    /// the reader left it registered
+    pub fn register(path: &str, name: &str) -> Result<Self, BridgeError> {
+        if name.is_empty() || name.len() > MAX_NAME_LEN {
+            return Err(BridgeError::WriteGate(format!(
+                "Invalid reader name {:?}: expected 1-{} bytes",
+                name, MAX_NAME_LEN
+            )));
+        }
+        let gate = GateMap::open(path)?;
+
+        let slot = match gate
+            .slots
+            .iter()
+            .position(|slot| read_name(slot).is_some_and(|n| n == name))
+        {
+            Some(slot) => slot,
+            None => Self::claim(&gate, name)?,
+        };
+        let entry = &gate.slots[slot];
+        // Forget what a previous run of the reader consumed
+        entry.acknowledged.store(0, Ordering::Relaxed);
+        entry.pid.store(std::process::id(), Ordering::Release);
+
+        Ok(Self { gate, slot })
+    }
+
+    fn claim(gate: &GateMap, name: &str) -> Result<usize, BridgeError> {
+        for (i, slot) in gate.slots.iter().enumerate() {
+            if slot
+                .state
+                .compare_exchange(FREE, CLAIMED, Ordering::AcqRel, Ordering::Acquire)
+                .is_err()
+            {
+                continue;
+            }
+            for (j, byte) in slot.name.iter().enumerate() {
+                byte.store(
+                    name.as_bytes().get(j).copied().unwrap_or(0),
+                    Ordering::Relaxed,
+                );
+            }
+            slot.state.store(ACTIVE, Ordering::Release);
+            return Ok(i);
+        }
+        Err(BridgeError::WriteGate(format!(
+            "Write gate full ({} readers)",
+            MAX_READERS
+        )))
+    }
+
+    /// Tell the writer this reader is done with `sequence`
+    pub fn acknowledge(&self, sequence: u64) {
+        self.gate.slots[self.slot]
+            .acknowledged
+            .store(sequence, Ordering::Release);
+    }
+
+    /// Keep the writer from overwriting `buffer` (never 0) until [`GateSubscription::release`]
+    pub fn hold(&self, buffer: u64) {
+        self.acknowledge(buffer);
+    }
+
+    /// Let the writer overwrite the buffer this reader held
+    pub fn release(&self) {
+        self.acknowledge(0);
+    }
+}
+
+impl Drop for GateSubscription {
+    fn drop(&mut self) {
+        let slot = &self.gate.slots[self.slot];
+        if slot
+            .state
+            .compare_exchange(ACTIVE, CLAIMED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            for byte in &slot.name {
+                byte.store(0, Ordering::Relaxed);
+            }
+            slot.state.store(FREE, Ordering::Release);
+        }
+    }
+}
+
+/// Writer side of a gate, see the [module documentation](self)
+pub struct WriteGate {
+    gate: GateMap,
+    timeout: Duration,
+    /// Messages published while a reader was still behind
+    overruns: u64,
+}
+
+unsafe impl Send for WriteGate {}
+unsafe impl Sync for WriteGate {}
+
+impl WriteGate {
+    /// Open the gate at `path`, waiting at most `timeout` for readers per message
+    pub fn new(path: &str, timeout: Duration) -> Result<Self, BridgeError> {
+        Ok(Self {
+            gate: GateMap::open(path)?,
+            timeout,
+            overruns: 0,
+        })
+    }
+
+    /// Wait until the readers that consumed `published - 1` consumed `published`,
+    /// the sequence about to be replaced. Returns how many were still behind at
+    /// the timeout.
+    pub fn wait(&mut self, published: u64) -> usize {
+        if published == 0 {
+            return 0;
+        }
+        self.wait_while(published, |acknowledged| acknowledged == published - 1)
+    }
+
+    /// Wait until no reader holds `buffer`, about to be overwritten. Returns how
+    /// many still held it at the timeout.
+    pub fn wait_released(&mut self, buffer: u64) -> usize {
+        if buffer == 0 {
+            return 0;
+        }
+        self.wait_while(buffer, |held| held == buffer)
+    }
+
+    /// Wait while readers whose acknowledged value matches `blocks` remain
+    fn wait_while(&mut self, sequence: u64, blocks: impl Fn(u64) -> bool) -> usize {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let behind = self.behind(&blocks);
+            if behind == 0 {
+                return 0;
+            }
+            if Instant::now() >= deadline {
+                self.overruns += 1;
+                for slot in self.gate.slots {
+                    if waits_for(slot, &blocks) {
+                        let reader = read_name(slot).unwrap_or_default();
+                        tracing::debug!(%reader, sequence, "Reader behind the writer, publishing anyway");
+                    }
+                }
+                return behind;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn behind(&self, blocks: impl Fn(u64) -> bool) -> usize {
+        self.gate
+            .slots
+            .iter()
+            .filter(|slot| waits_for(slot, &blocks))
+            .count()
+    }
+
+    /// Messages published over a reader since the last call
+    pub fn take_overruns(&mut self) -> u64 {
+        std::mem::take(&mut self.overruns)
+    }
+
+    /// Names of the registered readers
+    pub fn readers(&self) -> Vec<String> {
+        self.gate.slots.iter().filter_map(read_name).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gate_path(name: &str) -> String {
+        format!("{}/test_write_gate_{}", crate::paths::SHM_DIR, name)
+    }
+
+    #[test]
+    fn test_waits_for_readers_that_keep_up() {
+        let path = gate_path("keep_up");
+        let _ = std::fs::remove_file(&path);
+        let mut gate = WriteGate::new(&path, Duration::from_millis(20)).unwrap();
+        let reader = GateSubscription::register(&path, "recorder").unwrap();
+        assert_eq!(gate.readers(), vec!["recorder"]);
+
+        // Not waited for before it consumed anything
+        assert_eq!(gate.wait(5), 0);
+
+        reader.acknowledge(5);
+        assert_eq!(gate.wait(5), 0);
+        // Behind on 6 after consuming 5: the writer times out once
+        let started = Instant::now();
+        assert_eq!(gate.wait(6), 1);
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        // and doesn't wait for it again until it catches up
+        assert_eq!(gate.wait(7), 0);
+        assert_eq!(gate.take_overruns(), 1);
+        assert_eq!(gate.take_overruns(), 0);
+
+        reader.acknowledge(7);
+        let acknowledger = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(5));
+            reader.acknowledge(8);
+            reader
+        });
+        let mut gate = WriteGate::new(&path, Duration::from_secs(5)).unwrap();
+        assert_eq!(gate.wait(8), 0);
+        assert_eq!(gate.take_overruns(), 0);
+
+        drop(acknowledger.join().unwrap());
+        assert!(gate.readers().is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_waits_for_held_buffers() {
+        let path = gate_path("hold");
+        let _ = std::fs::remove_file(&path);
+        let mut gate = WriteGate::new(&path, Duration::from_millis(20)).unwrap();
+        let reader = GateSubscription::register(&path, "inference").unwrap();
+
+        reader.hold(2);
+        assert_eq!(gate.wait_released(1), 0);
+        let started = Instant::now();
+        assert_eq!(gate.wait_released(2), 1);
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert_eq!(gate.take_overruns(), 1);
+
+        let releaser = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(5));
+            reader.release();
+            reader
+        });
+        let mut gate = WriteGate::new(&path, Duration::from_secs(5)).unwrap();
+        assert_eq!(gate.wait_released(2), 0);
+        assert_eq!(gate.take_overruns(), 0);
+
+        drop(releaser.join().unwrap());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_rejects_invalid_names_and_overflow() {
+        let path = gate_path("overflow");
+        let _ = std::fs::remove_file(&path);
+
+        assert!(GateSubscription::register(&path, "").is_err());
+        let readers: Vec<_> = (0..MAX_READERS)
+            .map(|i| GateSubscription::register(&path, &format!("reader{i}")).unwrap())
+            .collect();
+        assert!(GateSubscription::register(&path, "one-too-many").is_err());
+        // A restarted reader takes its slot back
+        assert!(GateSubscription::register(&path, "reader0").is_ok());
+
+        drop(readers);
+        let _ = std::fs::remove_file(&path);
+    }
+}
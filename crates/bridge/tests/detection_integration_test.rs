@@ -1,4 +1,5 @@
 use bridge::{Detection, DetectionReader, DetectionWriter};
+use common::{Appearance, WallClockNs};
 use std::thread;
 use std::time::Duration;
 use tempfile::tempdir;
@@ -24,6 +25,7 @@ fn write_detections(
                 box_: Some(&bbox),
                 confidence: det.confidence,
                 class_id: det.class_id,
+                appearance: None,
             },
         );
         detection_offsets.push(detection);
@@ -33,9 +35,10 @@ fn write_detections(
     writer.write_detections(
         camera_id,
         frame_number,
-        timestamp_ns,
+        WallClockNs::from_nanos(timestamp_ns).into(),
         detections_vector,
         None,
+        None,
     )
 }
 
@@ -406,6 +409,152 @@ fn test_detection_reader_handles_no_data() {
     }
 }
 
+/// Test alert class lookup over the current detections
+#[test]
+fn test_detected_classes() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("detection_classes_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = DetectionWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    let reader = DetectionReader::with_path(path_str).unwrap();
+
+    assert!(reader.detected_classes(&[0, 21]).unwrap().is_empty());
+
+    let detections = [2u16, 21, 21].map(|class_id| Detection {
+        x1: 0.0,
+        y1: 0.0,
+        x2: 10.0,
+        y2: 10.0,
+        confidence: 0.9,
+        class_id,
+    });
+    write_detections(&mut writer, 0, 1, 0, &detections).unwrap();
+
+    assert_eq!(reader.detected_classes(&[0, 21]).unwrap(), vec![21]);
+    assert_eq!(reader.detected_classes(&[21, 2]).unwrap(), vec![21, 2]);
+    assert!(!reader.check_person_detected().unwrap());
+}
+
+/// Test per-class peak confidence over the current detections
+#[test]
+fn test_class_confidences() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("detection_confidences_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = DetectionWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    let reader = DetectionReader::with_path(path_str).unwrap();
+
+    assert!(reader.class_confidences(&[0]).unwrap().is_empty());
+
+    let detections = [(0u16, 0.4), (2, 0.9), (0, 0.55)].map(|(class_id, confidence)| Detection {
+        x1: 0.0,
+        y1: 0.0,
+        x2: 10.0,
+        y2: 10.0,
+        confidence,
+        class_id,
+    });
+    write_detections(&mut writer, 0, 1, 0, &detections).unwrap();
+
+    assert_eq!(reader.class_confidences(&[0, 21]).unwrap(), vec![(0, 0.55)]);
+    assert_eq!(
+        reader.class_confidences(&[2, 0]).unwrap(),
+        vec![(2, 0.9), (0, 0.55)]
+    );
+}
+
+/// Test per-class detection counts above a confidence
+#[test]
+fn test_class_counts() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("detection_counts_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = DetectionWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    let reader = DetectionReader::with_path(path_str).unwrap();
+
+    assert!(reader.class_counts(&[0], 0.0).unwrap().is_empty());
+
+    let detections =
+        [(0u16, 0.4), (2, 0.9), (0, 0.55), (0, 0.7)].map(|(class_id, confidence)| Detection {
+            x1: 0.0,
+            y1: 0.0,
+            x2: 10.0,
+            y2: 10.0,
+            confidence,
+            class_id,
+        });
+    write_detections(&mut writer, 0, 1, 0, &detections).unwrap();
+
+    assert_eq!(
+        reader.class_counts(&[0, 2, 21], 0.0).unwrap(),
+        vec![(0, 3), (2, 1)]
+    );
+    assert_eq!(
+        reader.class_counts(&[0, 2], 0.5).unwrap(),
+        vec![(0, 2), (2, 1)]
+    );
+    assert!(reader.class_counts(&[0], 0.8).unwrap().is_empty());
+}
+
+/// Write `count` detections of class 0 carrying `appearance`, and one without
+fn write_with_appearance(writer: &mut DetectionWriter, count: usize, appearance: &Appearance) {
+    let builder = writer.builder();
+    builder.reset();
+    let bbox = schema::BoundingBox::new(0.0, 0.0, 10.0, 10.0);
+    let mut offsets = Vec::with_capacity(count + 1);
+    for i in 0..=count {
+        let appearance = (i < count).then(|| builder.create_vector(appearance.as_bytes()));
+        offsets.push(schema::Detection::create(
+            builder,
+            &schema::DetectionArgs {
+                box_: Some(&bbox),
+                confidence: 0.9,
+                class_id: 0,
+                appearance,
+            },
+        ));
+    }
+    let detections = builder.create_vector(&offsets);
+    writer
+        .write_detections(
+            0,
+            1,
+            WallClockNs::from_nanos(0).into(),
+            detections,
+            None,
+            None,
+        )
+        .unwrap();
+}
+
+#[test]
+fn test_appearances() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("detection_appearance_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = DetectionWriter::build_with_path(path_str, 64 * 1024).unwrap();
+    let reader = DetectionReader::with_path(path_str).unwrap();
+    assert!(reader.appearances(&[0], 0.0).unwrap().is_empty());
+
+    let appearance = Appearance(std::array::from_fn(|i| i as u8 * 16));
+    write_with_appearance(&mut writer, 2, &appearance);
+    assert_eq!(
+        reader.appearances(&[0], 0.5).unwrap(),
+        vec![appearance, appearance]
+    );
+    assert!(reader.appearances(&[0], 0.95).unwrap().is_empty());
+    assert!(reader.appearances(&[2], 0.0).unwrap().is_empty());
+
+    // A full buffer of detections with descriptors still fits
+    let max = writer.max_detections();
+    write_with_appearance(&mut writer, max - 1, &appearance);
+    assert_eq!(reader.appearances(&[0], 0.0).unwrap().len(), max - 1);
+}
+
 /// Test various detection counts
 ///
 /// Validates that DetectionWriter/Reader handle edge cases correctly:
@@ -521,3 +670,205 @@ fn test_detection_float_precision() {
     assert!((det1.confidence() - 0.999999).abs() < epsilon);
     assert_eq!(det1.class_id(), 0);
 }
+
+/// Test plates set on the writer are attached to the next result only
+#[test]
+fn test_plates_attach_to_next_result() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("detection_plates_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = DetectionWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    let mut reader = DetectionReader::with_path(path_str).unwrap();
+
+    let builder = writer.builder();
+    builder.reset();
+    let bbox = schema::BoundingBox::new(10.0, 20.0, 110.0, 50.0);
+    let text = builder.create_string("AB123CD");
+    let plate = schema::Plate::create(
+        builder,
+        &schema::PlateArgs {
+            detection_index: 0,
+            box_: Some(&bbox),
+            text: Some(text),
+            confidence: 0.9,
+        },
+    );
+    let plates = builder.create_vector(&[plate]);
+    let detections = builder.create_vector::<flatbuffers::WIPOffset<schema::Detection>>(&[]);
+    writer.set_next_plates(plates);
+    writer
+        .write_detections(1, 1, WallClockNs::default().into(), detections, None, None)
+        .unwrap();
+
+    let result = reader.get_detections().unwrap().unwrap();
+    let plates = result.plates().expect("plates should be written");
+    assert_eq!(plates.len(), 1);
+    assert_eq!(plates.get(0).text(), Some("AB123CD"));
+    assert_eq!(plates.get(0).box_().unwrap().x2(), 110.0);
+    reader.mark_read();
+
+    write_detections(&mut writer, 1, 2, 0, &[]).unwrap();
+    let result = reader.get_detections().unwrap().unwrap();
+    assert!(result.plates().is_none());
+}
+
+#[test]
+fn test_inference_timing_attaches_to_next_result() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("detection_timing_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = DetectionWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    let reader = DetectionReader::with_path(path_str).unwrap();
+
+    writer.set_next_inference_timing(
+        WallClockNs::from_nanos(1_030_000_000),
+        WallClockNs::from_nanos(1_055_000_000),
+    );
+    write_detections(&mut writer, 0, 1, 1_000_000_000, &[]).unwrap();
+
+    let timing = reader.inference_timing().unwrap().expect("timing recorded");
+    assert_eq!(
+        timing.frame_timestamp_ns,
+        WallClockNs::from_nanos(1_000_000_000)
+    );
+    assert_eq!(timing.latency(), Duration::from_millis(25));
+    assert_eq!(timing.queue_delay(), Duration::from_millis(30));
+    assert_eq!(
+        timing.age_at(WallClockNs::from_nanos(1_100_000_000)),
+        Duration::from_millis(100)
+    );
+
+    // Results written without timing report none
+    write_detections(&mut writer, 0, 2, 2_000_000_000, &[]).unwrap();
+    assert!(reader.inference_timing().unwrap().is_none());
+}
+
+#[test]
+fn test_late_flag_attaches_to_next_result() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("detection_late_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = DetectionWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    let reader = DetectionReader::with_path(path_str).unwrap();
+
+    writer.set_next_late();
+    write_detections(&mut writer, 0, 1, 1_000_000_000, &[]).unwrap();
+    assert!(reader.is_late().unwrap());
+
+    write_detections(&mut writer, 0, 2, 2_000_000_000, &[]).unwrap();
+    assert!(!reader.is_late().unwrap());
+}
+
+#[test]
+fn test_cached_flag_attaches_to_next_result() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("detection_cached_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = DetectionWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    let reader = DetectionReader::with_path(path_str).unwrap();
+
+    writer.set_next_cached();
+    write_detections(&mut writer, 0, 1, 1_000_000_000, &[]).unwrap();
+    assert!(reader.is_cached().unwrap());
+    assert!(!reader.is_late().unwrap());
+
+    write_detections(&mut writer, 0, 2, 2_000_000_000, &[]).unwrap();
+    assert!(!reader.is_cached().unwrap());
+}
+
+#[test]
+fn test_max_detections_fit_the_buffer() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("detection_max_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = DetectionWriter::build_with_path(path_str, 64 * 1024).unwrap();
+    let reader = DetectionReader::with_path(path_str).unwrap();
+
+    let max = writer.max_detections();
+    assert!(max > 0);
+    let detections = vec![
+        Detection {
+            x1: 1.0,
+            y1: 2.0,
+            x2: 3.0,
+            y2: 4.0,
+            confidence: 0.5,
+            class_id: 0,
+        };
+        max
+    ];
+    writer.set_next_truncated(12);
+    write_detections(&mut writer, 0, 1, 1_000_000_000, &detections).unwrap();
+    let result = reader.get_detections().unwrap().unwrap();
+    assert_eq!(result.detections().unwrap().len(), max);
+    assert_eq!(result.truncated_count(), 12);
+
+    write_detections(&mut writer, 0, 2, 2_000_000_000, &[]).unwrap();
+    assert_eq!(
+        reader.get_detections().unwrap().unwrap().truncated_count(),
+        0
+    );
+}
+
+#[test]
+fn test_detection_write_finished_passthrough() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("detection_finished_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = DetectionWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    let reader = DetectionReader::with_path(path_str).unwrap();
+
+    let mut builder = flatbuffers::FlatBufferBuilder::new();
+    let bbox = schema::BoundingBox::new(10.0, 20.0, 30.0, 40.0);
+    let detection = schema::Detection::create(
+        &mut builder,
+        &schema::DetectionArgs {
+            box_: Some(&bbox),
+            confidence: 0.9,
+            class_id: 0,
+            appearance: None,
+        },
+    );
+    let detections = builder.create_vector(&[detection]);
+    let result = schema::DetectionResult::create(
+        &mut builder,
+        &schema::DetectionResultArgs {
+            frame_number: 7,
+            detections: Some(detections),
+            ..Default::default()
+        },
+    );
+    builder.finish(result, None);
+    writer.write_finished(builder.finished_data()).unwrap();
+
+    let result = reader.get_detections().unwrap().unwrap();
+    assert_eq!(result.frame_number(), 7);
+    assert_eq!(result.detections().unwrap().len(), 1);
+    assert!(reader.check_person_detected().unwrap());
+
+    // Bytes that are not a DetectionResult never reach the reader
+    assert!(writer.write_finished(&[0u8; 3]).is_err());
+    assert_eq!(reader.get_detections().unwrap().unwrap().frame_number(), 7);
+
+    // State left for a passed-through result does not land on the next one
+    writer.set_next_late();
+    writer.set_next_truncated(3);
+    writer.set_next_frame_size(1920, 1080);
+    writer.write_finished(builder.finished_data()).unwrap();
+    write_detections(&mut writer, 0, 8, 2_000_000_000, &[]).unwrap();
+    let result = reader.get_detections().unwrap().unwrap();
+    assert!(!result.late());
+    assert_eq!(result.truncated_count(), 0);
+    assert_eq!(result.frame_width(), 0);
+
+    writer.set_next_frame_size(1920, 1080);
+    write_detections(&mut writer, 0, 9, 3_000_000_000, &[]).unwrap();
+    let result = reader.get_detections().unwrap().unwrap();
+    assert_eq!((result.frame_width(), result.frame_height()), (1920, 1080));
+}
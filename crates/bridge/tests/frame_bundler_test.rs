@@ -0,0 +1,47 @@
+use bridge::{FrameBundler, FrameWriter};
+use std::time::Duration;
+use tempfile::tempdir;
+
+fn write_frame(writer: &mut FrameWriter, camera_id: u32, frame_number: u64) {
+    let pixels = vec![camera_id as u8; 4 * 4 * 3];
+    writer
+        .write_frame(camera_id, &pixels, frame_number, 4, 4, None)
+        .unwrap();
+}
+
+#[test]
+fn test_bundles_one_frame_per_camera() {
+    let dir = tempdir().unwrap();
+    let paths: Vec<String> = (0..2)
+        .map(|camera| {
+            let path = dir.path().join(format!("camera_{camera}.mmap"));
+            path.to_str().unwrap().to_string()
+        })
+        .collect();
+    let mut writers: Vec<FrameWriter> = paths
+        .iter()
+        .map(|path| FrameWriter::build_with_path(path, 64 * 1024).unwrap())
+        .collect();
+    let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+    let mut bundler = FrameBundler::with_paths(&paths, Duration::from_secs(5), |frame| {
+        Ok(frame.pixels().map(|p| p.bytes()[0]).unwrap_or_default())
+    })
+    .unwrap();
+
+    write_frame(&mut writers[0], 0, 1);
+    assert!(bundler.next_bundle(Duration::ZERO).unwrap().is_none());
+
+    write_frame(&mut writers[1], 1, 7);
+    let bundle = bundler.next_bundle(Duration::ZERO).unwrap().unwrap();
+    let frames: Vec<_> = bundle
+        .frames
+        .iter()
+        .map(|f| (f.camera_id, f.frame_number, f.frame))
+        .collect();
+    assert_eq!(frames, [(0, 1, 0), (1, 7, 1)]);
+    assert!(bundle.skew() < Duration::from_secs(5));
+    assert_eq!(bundler.dropped_frames(), 0);
+
+    // Both frames were consumed
+    assert!(bundler.next_bundle(Duration::ZERO).unwrap().is_none());
+}
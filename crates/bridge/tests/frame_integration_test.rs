@@ -1,4 +1,8 @@
-use bridge::{FrameReader, FrameWriter};
+use bridge::{
+    CapturedAt, FormatWatch, FrameCompression, FrameFormat, FrameReader, FrameWriter, Provenance,
+};
+use common::{MonotonicNs, Timestamp, WallClockNs};
+use schema::{Burst, FrameEncoding};
 use std::thread;
 use std::time::Duration;
 use tempfile::tempdir;
@@ -359,3 +363,343 @@ fn test_various_frame_resolutions() {
         );
     }
 }
+
+/// Test that a reader stays attached while the writer changes resolution
+///
+/// Tests:
+/// - The generation is bumped on each size change, not on every frame
+/// - FormatWatch reports the change once, with the previous format
+/// - A resolution too large for the buffer asks for a downscale that fits
+#[test]
+fn test_frame_resolution_change_in_band() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("frame_resolution_change_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let buffer_size = bridge::paths::frame_buffer_size(128, 96);
+    let mut writer = FrameWriter::build_with_path(path_str, buffer_size).unwrap();
+    let reader = FrameReader::with_path(path_str).unwrap();
+    let mut watch = FormatWatch::new();
+
+    let mut read = |writer: &mut FrameWriter, frame_number: u64, width: u32, height: u32| {
+        let pixels = vec![7u8; (width * height * 3) as usize];
+        writer
+            .write_frame(0, &pixels, frame_number, width, height, None)
+            .unwrap();
+        let frame = reader.get_frame().unwrap().unwrap();
+        assert_eq!(frame.pixels().unwrap().len(), pixels.len());
+        watch.observe(FrameFormat::of(&frame))
+    };
+
+    assert_eq!(read(&mut writer, 1, 128, 96), None);
+    assert_eq!(read(&mut writer, 2, 128, 96), None);
+    let previous = read(&mut writer, 3, 64, 48).expect("size change reported");
+    assert_eq!((previous.width, previous.height), (128, 96));
+    assert_eq!(writer.format_generation(), 1);
+    assert_eq!(read(&mut writer, 4, 64, 48), None);
+    assert!(read(&mut writer, 5, 96, 128).is_some());
+    assert_eq!(writer.format_generation(), 2);
+
+    assert_eq!(writer.max_dimension_for(128, 96), None);
+    let max_dimension = writer.max_dimension_for(256, 192).unwrap();
+    let (width, height) = (max_dimension, max_dimension * 3 / 4);
+    assert!(max_dimension < 256);
+    assert_eq!(writer.max_dimension_for(width, height), None);
+}
+
+/// Test that capture provenance travels with the frame
+#[test]
+fn test_frame_provenance_roundtrip() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("frame_provenance_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = FrameWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    let reader = FrameReader::with_path(path_str).unwrap();
+
+    let provenance = Provenance {
+        capture_host: "cam-host".into(),
+        device_path: "/dev/video0".into(),
+        decoder: "yuyv".into(),
+        nominal_fps: 30.0,
+        app_version: "0.1.0".into(),
+        ..Default::default()
+    };
+    writer.set_provenance(Some(provenance.clone()));
+
+    let pixels = vec![0u8; 64 * 48 * 3];
+    writer.write_frame(0, &pixels, 1, 64, 48, None).unwrap();
+
+    let frame = reader.get_frame().unwrap().unwrap();
+    let decoded = frame.provenance().expect("provenance should be present");
+    assert_eq!(Provenance::from(&decoded), provenance);
+
+    writer.set_provenance(None);
+    writer.write_frame(0, &pixels, 2, 64, 48, None).unwrap();
+    let frame = reader.get_frame().unwrap().unwrap();
+    assert!(frame.provenance().is_none());
+}
+
+/// Test that downscaled frames carry the size they were captured at
+#[test]
+fn test_frame_original_size_roundtrip() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("frame_original_size_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = FrameWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    let reader = FrameReader::with_path(path_str).unwrap();
+
+    let pixels = vec![0u8; 64 * 36 * 3];
+    writer.set_next_original_size(3840, 2160);
+    writer.write_frame(0, &pixels, 1, 64, 36, None).unwrap();
+
+    let frame = reader.get_frame().unwrap().unwrap();
+    assert_eq!((frame.width(), frame.height()), (64, 36));
+    assert_eq!(
+        (frame.original_width(), frame.original_height()),
+        (3840, 2160)
+    );
+
+    // Only applies to the next frame
+    writer.write_frame(0, &pixels, 2, 64, 36, None).unwrap();
+    let frame = reader.get_frame().unwrap().unwrap();
+    assert_eq!((frame.original_width(), frame.original_height()), (0, 0));
+}
+
+/// Test that a JPEG payload is tagged as such for the next frame only
+#[test]
+fn test_frame_jpeg_encoding_roundtrip() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("frame_jpeg_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = FrameWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    let reader = FrameReader::with_path(path_str).unwrap();
+
+    let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0xFF, 0xD9];
+    writer.set_next_encoding(FrameEncoding::Jpeg);
+    writer.write_frame(0, &jpeg, 1, 1280, 720, None).unwrap();
+
+    let frame = reader.get_frame().unwrap().unwrap();
+    assert_eq!(frame.encoding(), FrameEncoding::Jpeg);
+    assert_eq!((frame.width(), frame.height()), (1280, 720));
+    assert_eq!(frame.pixels().unwrap().bytes(), jpeg);
+
+    let pixels = vec![0u8; 64 * 36 * 3];
+    writer.write_frame(0, &pixels, 2, 64, 36, None).unwrap();
+    let frame = reader.get_frame().unwrap().unwrap();
+    assert_eq!(frame.encoding(), FrameEncoding::Rgb);
+}
+
+/// Test that LZ4 frames read back as the RGB written, and JPEG is left as is
+#[test]
+fn test_frame_lz4_compression_roundtrip() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("frame_lz4_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = FrameWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    writer.set_compression(FrameCompression::Lz4);
+    let reader = FrameReader::with_path(path_str).unwrap();
+
+    let pixels: Vec<u8> = (0..320 * 240 * 3).map(|i| (i / 960) as u8).collect();
+    writer.write_frame(0, &pixels, 1, 320, 240, None).unwrap();
+
+    let frame = reader.get_frame().unwrap().unwrap();
+    assert_eq!(frame.encoding(), FrameEncoding::Lz4);
+    assert!(frame.pixels().unwrap().len() < pixels.len());
+    let (encoding, payload) = bridge::frame_payload(&frame).unwrap();
+    assert_eq!(encoding, FrameEncoding::Rgb);
+    assert_eq!(&payload[..], &pixels[..]);
+
+    let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, 0xFF, 0xD9];
+    writer.set_next_encoding(FrameEncoding::Jpeg);
+    writer.write_frame(0, &jpeg, 2, 1280, 720, None).unwrap();
+    let frame = reader.get_frame().unwrap().unwrap();
+    assert_eq!(frame.encoding(), FrameEncoding::Jpeg);
+    assert_eq!(frame.pixels().unwrap().bytes(), jpeg);
+}
+
+/// Test that burst frames carry their tag, the next frame only
+#[test]
+fn test_frame_burst_roundtrip() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("frame_burst_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = FrameWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    let reader = FrameReader::with_path(path_str).unwrap();
+
+    let pixels = vec![0u8; 64 * 36 * 3];
+    writer.set_next_burst(Burst::new(40, 2, 10, 123.5));
+    writer.write_frame(0, &pixels, 42, 64, 36, None).unwrap();
+
+    let frame = reader.get_frame().unwrap().unwrap();
+    let burst = frame.burst().unwrap();
+    assert_eq!((burst.id(), burst.index(), burst.length()), (40, 2, 10));
+    assert_eq!(burst.sharpness(), 123.5);
+
+    writer.write_frame(0, &pixels, 43, 64, 36, None).unwrap();
+    let frame = reader.get_frame().unwrap().unwrap();
+    assert!(frame.burst().is_none());
+}
+
+/// Test that frames keep the clock they were stamped with
+#[test]
+fn test_frame_timestamp_clock_roundtrip() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("frame_clock_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = FrameWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    let reader = FrameReader::with_path(path_str).unwrap();
+    let pixels = vec![0u8; 64 * 36 * 3];
+
+    // Stamped at write time on the wall clock by default
+    let before = WallClockNs::now();
+    writer.write_frame(0, &pixels, 1, 64, 36, None).unwrap();
+    let frame = reader.get_frame().unwrap().unwrap();
+    match frame.captured_at() {
+        Timestamp::WallClock(ns) => assert!(ns >= before && ns <= WallClockNs::now()),
+        other => panic!("expected a wall clock stamp, got {other:?}"),
+    }
+
+    let exposure = MonotonicNs::from_timeval(12, 500);
+    writer.set_next_timestamp(exposure);
+    writer.write_frame(0, &pixels, 2, 64, 36, None).unwrap();
+    let frame = reader.get_frame().unwrap().unwrap();
+    assert_eq!(frame.captured_at(), Timestamp::Monotonic(exposure));
+    assert_eq!(frame.timestamp_ns(), 12_000_500_000);
+}
+
+/// Test that a frame serialized outside the writer is passed through as is
+#[test]
+fn test_frame_write_finished_passthrough() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("frame_finished_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = FrameWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    let reader = FrameReader::with_path(path_str).unwrap();
+
+    let mut builder = flatbuffers::FlatBufferBuilder::new();
+    let pixels = builder.create_vector(&[7u8; 4 * 2 * 3]);
+    let frame = schema::Frame::create(
+        &mut builder,
+        &schema::FrameArgs {
+            frame_number: 42,
+            camera_id: 3,
+            width: 4,
+            height: 2,
+            pixels: Some(pixels),
+            ..Default::default()
+        },
+    );
+    builder.finish(frame, None);
+    writer.write_finished(builder.finished_data()).unwrap();
+
+    let frame = reader.get_frame().unwrap().unwrap();
+    assert_eq!(frame.frame_number(), 42);
+    assert_eq!(frame.camera_id(), 3);
+    assert_eq!(frame.pixels().unwrap().bytes(), [7u8; 4 * 2 * 3]);
+
+    // Bytes that are not a Frame never reach the reader
+    assert!(writer.write_finished(&[0u8; 3]).is_err());
+    assert_eq!(reader.get_frame().unwrap().unwrap().frame_number(), 42);
+
+    // State left for a passed-through frame does not land on the next one
+    writer.set_next_original_size(1920, 1080);
+    writer.set_next_nvmm_surface(1);
+    writer.write_finished(builder.finished_data()).unwrap();
+    writer
+        .write_frame(0, &[0u8; 4 * 2 * 3], 43, 4, 2, None)
+        .unwrap();
+    let frame = reader.get_frame().unwrap().unwrap();
+    assert_eq!(frame.original_width(), 0);
+    assert_eq!(frame.nvmm_surface(), -1);
+}
+
+/// A gated writer waits for a slow registered reader instead of skipping frames
+#[cfg(feature = "write-gate")]
+#[test]
+fn test_write_gate_keeps_slow_reader_lossless() {
+    use bridge::{GateSubscription, WriteGate};
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("frame_gate_test.mmap");
+    let gate_path = dir.path().join("frame_gate_test.gate");
+    let (path, gate_path) = (
+        path.to_str().unwrap().to_string(),
+        gate_path.to_str().unwrap().to_string(),
+    );
+
+    let mut writer = FrameWriter::build_with_path(&path, 64 * 1024).unwrap();
+    writer.set_write_gate(Some(
+        WriteGate::new(&gate_path, Duration::from_secs(5)).unwrap(),
+    ));
+    let subscription = GateSubscription::register(&gate_path, "recorder").unwrap();
+    let reader = FrameReader::with_path(&path).unwrap();
+
+    let recorder = thread::spawn(move || {
+        let mut seen = Vec::new();
+        let mut last = 0;
+        while seen.len() < 20 {
+            let sequence = reader.current_sequence();
+            if sequence == last {
+                thread::yield_now();
+                continue;
+            }
+            seen.push(reader.get_frame().unwrap().unwrap().frame_number());
+            // Slower than the writer
+            thread::sleep(Duration::from_millis(2));
+            subscription.acknowledge(sequence);
+            last = sequence;
+        }
+        seen
+    });
+
+    let pixels = vec![0u8; 16 * 16 * 3];
+    for frame_number in 1..=20 {
+        writer
+            .write_frame(0, &pixels, frame_number, 16, 16, None)
+            .unwrap();
+    }
+
+    assert_eq!(recorder.join().unwrap(), (1..=20).collect::<Vec<_>>());
+    assert_eq!(writer.take_write_gate_overruns(), 0);
+}
+
+/// Test that a signed frame verifies against the payload as written
+#[cfg(feature = "signing")]
+#[test]
+fn test_frame_signature_covers_written_payload() {
+    use bridge::{FrameSignature, FrameSigner};
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("frame_signing_test.mmap");
+    let path_str = path.to_str().unwrap();
+
+    let mut writer = FrameWriter::build_with_path(path_str, 1024 * 1024).unwrap();
+    let reader = FrameReader::with_path(path_str).unwrap();
+    let pixels = vec![3u8; 64 * 36 * 3];
+
+    writer.write_frame(0, &pixels, 1, 64, 36, None).unwrap();
+    assert!(reader.get_frame().unwrap().unwrap().signature().is_none());
+
+    let signer = FrameSigner::new("door", vec![5; 32]).unwrap();
+    writer.set_signer(Some(signer.clone()));
+    writer.set_compression(FrameCompression::Lz4);
+    writer.write_frame(0, &pixels, 2, 64, 36, None).unwrap();
+
+    let frame = reader.get_frame().unwrap().unwrap();
+    let signature = FrameSignature::from_frame(&frame).unwrap().unwrap();
+    assert_eq!(signature.key_id, "door");
+    assert_eq!(signature.frame_number, 2);
+    assert_eq!(signature.timestamp_ns, frame.timestamp_ns());
+    // Signed over the LZ4 block stored in the frame
+    signer
+        .verify(&signature, Some(frame.pixels().unwrap().bytes()))
+        .unwrap();
+    assert!(signer.verify(&signature, Some(&pixels)).is_err());
+}
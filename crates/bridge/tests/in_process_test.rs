@@ -0,0 +1,55 @@
+use bridge::{DetectionReader, DetectionWriter, FrameReader, FrameWriter, InProcessBuffer};
+use common::WallClockNs;
+use std::thread;
+
+/// Frames written on one thread are read on another without touching `/dev/shm`
+#[test]
+fn test_frames_cross_threads() {
+    let buffer = InProcessBuffer::frames().unwrap();
+    let reader = FrameReader::in_process(&buffer).unwrap();
+    assert!(reader.get_frame().unwrap().is_none());
+
+    let mut writer = FrameWriter::in_process(&buffer).unwrap();
+    thread::scope(|s| {
+        s.spawn(|| {
+            for frame_number in 1..=3 {
+                let pixels = vec![frame_number as u8; 64 * 48 * 3];
+                writer
+                    .write_frame(0, &pixels, frame_number, 64, 48, None)
+                    .unwrap();
+            }
+        });
+    });
+
+    assert_eq!(reader.current_sequence(), 3);
+    let frame = reader.get_frame().unwrap().unwrap();
+    assert_eq!(frame.frame_number(), 3);
+    assert_eq!(frame.pixels().unwrap().bytes()[0], 3);
+
+    // A second writer continues the sequence
+    let mut writer = FrameWriter::in_process(&buffer).unwrap();
+    writer
+        .write_frame(0, &[0; 64 * 48 * 3], 4, 64, 48, None)
+        .unwrap();
+    assert_eq!(writer.sequence(), 4);
+    assert_eq!(reader.current_sequence(), 4);
+}
+
+#[test]
+fn test_buffers_keep_their_kind() {
+    let frames = InProcessBuffer::frames().unwrap();
+    let detections = InProcessBuffer::detections().unwrap();
+    assert!(DetectionReader::in_process(&frames).is_err());
+    assert!(FrameWriter::in_process(&detections).is_err());
+
+    let mut writer = DetectionWriter::in_process(&detections).unwrap();
+    let reader = DetectionReader::in_process(&detections).unwrap();
+    let builder = writer.builder();
+    builder.reset();
+    let empty = builder.create_vector::<flatbuffers::WIPOffset<schema::Detection>>(&[]);
+    writer
+        .write_detections(0, 1, WallClockNs::from_nanos(1).into(), empty, None, None)
+        .unwrap();
+    let result = reader.get_detections().unwrap().unwrap();
+    assert_eq!(result.frame_number(), 1);
+}
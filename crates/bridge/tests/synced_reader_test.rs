@@ -0,0 +1,168 @@
+use bridge::{DetectionWriter, FrameWriter, SyncedReader};
+use common::WallClockNs;
+use std::time::Duration;
+use tempfile::{TempDir, tempdir};
+
+struct Buffers {
+    _dir: TempDir,
+    frames: FrameWriter,
+    detections: DetectionWriter,
+    reader: SyncedReader,
+}
+
+fn buffers(capacity: usize) -> Buffers {
+    let dir = tempdir().unwrap();
+    let frame_path = dir.path().join("frames.mmap");
+    let detection_path = dir.path().join("detections.mmap");
+    let frame_path = frame_path.to_str().unwrap();
+    let detection_path = detection_path.to_str().unwrap();
+
+    let frames = FrameWriter::build_with_path(frame_path, 64 * 1024).unwrap();
+    let detections = DetectionWriter::build_with_path(detection_path, 64 * 1024).unwrap();
+    let reader = SyncedReader::with_paths(frame_path, detection_path)
+        .unwrap()
+        .with_capacity(capacity);
+
+    Buffers {
+        _dir: dir,
+        frames,
+        detections,
+        reader,
+    }
+}
+
+fn write_frame(buffers: &mut Buffers, frame_number: u64) {
+    let pixels = vec![frame_number as u8; 4 * 4 * 3];
+    buffers
+        .frames
+        .write_frame(0, &pixels, frame_number, 4, 4, None)
+        .unwrap();
+    buffers.reader.poll().unwrap();
+}
+
+/// Write `count` person detections for `frame_number`
+fn write_detections(buffers: &mut Buffers, frame_number: u64, count: usize) {
+    let builder = buffers.detections.builder();
+    builder.reset();
+    let offsets: Vec<_> = (0..count)
+        .map(|_| {
+            let bbox = schema::BoundingBox::new(0.0, 0.0, 1.0, 1.0);
+            schema::Detection::create(
+                builder,
+                &schema::DetectionArgs {
+                    box_: Some(&bbox),
+                    confidence: 0.9,
+                    class_id: 0,
+                    appearance: None,
+                },
+            )
+        })
+        .collect();
+    let vector = builder.create_vector(&offsets);
+    buffers
+        .detections
+        .write_detections(
+            0,
+            frame_number,
+            WallClockNs::default().into(),
+            vector,
+            None,
+            None,
+        )
+        .unwrap();
+    buffers.reader.poll().unwrap();
+}
+
+#[test]
+fn test_pairs_by_frame_number_despite_lag() {
+    let mut buffers = buffers(8);
+
+    // Inference lags: frame 2 is already written when detections for frame 1 arrive
+    write_frame(&mut buffers, 1);
+    write_frame(&mut buffers, 2);
+    assert!(buffers.reader.next_pair(Duration::ZERO).unwrap().is_none());
+
+    write_detections(&mut buffers, 1, 1);
+    let pair = buffers.reader.next_pair(Duration::ZERO).unwrap().unwrap();
+    assert_eq!(pair.frame_number, 1);
+    assert_eq!(pair.frame.frame_number, 1);
+    assert_eq!(pair.frame.pixels[0], 1);
+    assert_eq!(pair.detections.unwrap().len(), 1);
+
+    // Frame 2 still waits for its own detections
+    assert!(buffers.reader.next_pair(Duration::ZERO).unwrap().is_none());
+    write_detections(&mut buffers, 2, 3);
+    let pair = buffers.reader.next_pair(Duration::ZERO).unwrap().unwrap();
+    assert_eq!(pair.frame_number, 2);
+    assert_eq!(pair.detections.unwrap().len(), 3);
+}
+
+#[test]
+fn test_skipped_frames_are_yielded_without_detections() {
+    let mut buffers = buffers(8);
+
+    write_frame(&mut buffers, 1);
+    write_frame(&mut buffers, 2);
+    write_frame(&mut buffers, 3);
+    // Inference skipped frames 1 and 2
+    write_detections(&mut buffers, 3, 2);
+
+    let pairs: Vec<_> = std::iter::from_fn(|| buffers.reader.next_pair(Duration::ZERO).unwrap())
+        .map(|pair| (pair.frame_number, pair.detections.map(|d| d.len())))
+        .collect();
+    assert_eq!(pairs, vec![(1, None), (2, None), (3, Some(2))]);
+}
+
+#[test]
+fn test_overflow_releases_oldest_frames() {
+    let mut buffers = buffers(2);
+
+    for frame_number in 1..=3 {
+        write_frame(&mut buffers, frame_number);
+    }
+
+    let pair = buffers.reader.next_pair(Duration::ZERO).unwrap().unwrap();
+    assert_eq!(pair.frame_number, 1);
+    assert!(pair.detections.is_none());
+    assert_eq!(buffers.reader.pending_frames(), 2);
+    assert!(
+        buffers
+            .reader
+            .next_pair(Duration::from_millis(5))
+            .unwrap()
+            .is_none()
+    );
+}
+
+#[test]
+fn test_frame_number_restart_clears_buffers() {
+    let mut buffers = buffers(8);
+
+    write_frame(&mut buffers, 10);
+    write_detections(&mut buffers, 11, 1);
+    // Capture restarted
+    write_frame(&mut buffers, 1);
+    assert_eq!(buffers.reader.pending_frames(), 1);
+
+    // The stale result for frame 11 must not mark frame 1 as skipped
+    assert!(buffers.reader.next_pair(Duration::ZERO).unwrap().is_none());
+    write_detections(&mut buffers, 1, 1);
+    let pair = buffers.reader.next_pair(Duration::ZERO).unwrap().unwrap();
+    assert_eq!(pair.frame_number, 1);
+    assert!(pair.detections.is_some());
+}
+
+#[test]
+fn test_reports_writers_taking_the_buffers_over() {
+    let mut buffers = buffers(4);
+    assert_eq!(buffers.reader.frame_writer_changed(), None);
+
+    let path = buffers._dir.path().join("frames.mmap");
+    let _frames = FrameWriter::build_with_path(path.to_str().unwrap(), 64 * 1024).unwrap();
+    assert_eq!(
+        buffers.reader.frame_writer_changed(),
+        Some(std::process::id())
+    );
+    assert_eq!(buffers.reader.frame_writer_changed(), None);
+    assert_eq!(buffers.reader.detection_writer_changed(), None);
+}
@@ -0,0 +1,72 @@
+//! TensorRT INT8 calibration cache
+//!
+//! The cache TensorRT reads back through `readCalibrationCache` is text: a
+//! `TRT-<version>-<calibrator>` header, then one `<tensor>: <scale>` line per
+//! tensor, the scale being the bits of the `f32` `amax / 127` in hex. Only the
+//! network input is measured here; the activations behind it need the network
+//! run, which `build_calibration_cache.py` does.
+
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// TensorRT version as the cache header spells it (`NV_TENSORRT_VERSION`):
+/// `10.3.0` is `100300`, but `8.6.1` is `8601`
+pub fn parse_version(version: &str) -> Result<u32> {
+    let parts = version
+        .split('.')
+        .map(str::parse::<u32>)
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid TensorRT version {version:?}"))?;
+    match parts[..] {
+        [major, minor, patch] if minor < 10 && patch < 100 && major < 10 => {
+            Ok(major * 1_000 + minor * 100 + patch)
+        }
+        [major, minor, patch] if minor < 100 && patch < 100 => {
+            Ok(major * 10_000 + minor * 100 + patch)
+        }
+        _ => anyhow::bail!("Invalid TensorRT version {version:?}, expected major.minor.patch"),
+    }
+}
+
+/// Cache contents for `scales`, tensor names with their `amax / 127`
+pub fn render(version: u32, algorithm: &str, scales: &[(&str, f32)]) -> String {
+    let mut cache = format!("TRT-{version}-{algorithm}\n");
+    for (tensor, scale) in scales {
+        cache.push_str(&format!("{tensor}: {:08x}\n", scale.to_bits()));
+    }
+    cache
+}
+
+pub fn write(path: &Path, version: u32, algorithm: &str, scales: &[(&str, f32)]) -> Result<()> {
+    std::fs::write(path, render(version, algorithm, scales))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renders_header_and_hex_scales() {
+        let cache = render(100300, "EntropyCalibration2", &[("input", 1.0 / 127.0)]);
+        let mut lines = cache.lines();
+
+        assert_eq!(lines.next(), Some("TRT-100300-EntropyCalibration2"));
+        let (tensor, scale) = lines.next().unwrap().split_once(": ").unwrap();
+        assert_eq!(tensor, "input");
+        assert_eq!(scale, "3c010204");
+        assert_eq!(
+            f32::from_bits(u32::from_str_radix(scale, 16).unwrap()),
+            1.0 / 127.0
+        );
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("10.3.0").unwrap(), 100300);
+        assert_eq!(parse_version("8.6.1").unwrap(), 8601);
+        assert!(parse_version("10.3").is_err());
+        assert!(parse_version("ten").is_err());
+    }
+}
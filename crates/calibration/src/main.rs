@@ -1,6 +1,11 @@
+mod cache;
+mod stats;
+
 use clap::Parser;
 use indicatif::{ProgressBar, ProgressStyle};
-use preprocess::{DEFAULT_INPUT_SIZE, PreProcessor};
+use preprocess::{PreProcessor, PreprocessProfile};
+use serde::Serialize;
+use stats::{Calibration, Granularity, ImageStats, Method, RangeReport};
 use std::fs::{self, File};
 use std::io::Write;
 use std::path::PathBuf;
@@ -8,7 +13,10 @@ use std::path::PathBuf;
 /// Generate calibration tensors for INT8 quantization.
 ///
 /// This tool preprocesses JPEG images using the same pipeline as inference
-/// and saves the resulting tensors as binary files for TensorRT calibration.
+/// and saves the resulting tensors as binary files for TensorRT calibration,
+/// with a `calibration.json` holding the method, the input's dynamic range and
+/// per-image statistics. Images whose tensor holds NaN or infinite values fail
+/// the run; flat images are skipped.
 #[derive(Parser)]
 #[command(version, about)]
 struct Args {
@@ -23,12 +31,61 @@ struct Args {
     /// Number of images to process (0 = all)
     #[arg(long, default_value = "100")]
     count: usize,
+
+    /// Preprocessing profile of the model (PREPROCESS_PROFILE)
+    #[arg(long, default_value = "rfdetr")]
+    profile: PreprocessProfile,
+
+    /// How the dynamic range is estimated, matching the TensorRT calibrator
+    #[arg(long, value_enum, default_value_t = Method::Entropy)]
+    method: Method,
+
+    /// Report the range of the whole tensor or of each channel as well
+    #[arg(long, value_enum, default_value_t = Granularity::PerTensor)]
+    granularity: Granularity,
+
+    /// Also write a TensorRT calibration cache holding the input's range
+    #[arg(long, requires = "trt_version")]
+    cache_path: Option<PathBuf>,
+
+    /// TensorRT version the cache is for, e.g. 10.3.0; TensorRT does not use a
+    /// cache written for another version
+    #[arg(long)]
+    trt_version: Option<String>,
+
+    /// Name of the model input, as exported to ONNX
+    #[arg(long, default_value = "input")]
+    input_name: String,
+}
+
+/// `calibration.json`, next to the tensors
+#[derive(Serialize)]
+struct Metadata<'a> {
+    profile: &'a str,
+    input_name: &'a str,
+    input_shape: Vec<usize>,
+    method: Method,
+    granularity: Granularity,
+    /// Calibrator the cache and `build_calibration_cache.py` use
+    trt_calibrator: &'static str,
+    images: usize,
+    /// Flat images left out
+    skipped: usize,
+    tensor: RangeReport,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    channels: Vec<RangeReport>,
+    per_image: Vec<ImageStats>,
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
 
-    let mut pre = PreProcessor::new(DEFAULT_INPUT_SIZE);
+    let mut pre = PreProcessor::new(args.profile.input_size).with_profile(args.profile);
+    let trt_version = args
+        .trt_version
+        .as_deref()
+        .map(cache::parse_version)
+        .transpose()?;
 
     // Resolve paths relative to workspace root if not absolute
     let workspace_root = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
@@ -49,6 +106,14 @@ fn main() -> anyhow::Result<()> {
         workspace_root.join(&args.output_dir)
     };
 
+    let cache_path = args.cache_path.map(|path| {
+        if path.is_absolute() {
+            path
+        } else {
+            workspace_root.join(path)
+        }
+    });
+
     // Create output directory if it doesn't exist
     fs::create_dir_all(&output_dir)?;
 
@@ -83,20 +148,85 @@ fn main() -> anyhow::Result<()> {
     );
 
     let mut processed = 0;
+    let mut calibration = Calibration::new(args.method, args.granularity);
+    let mut per_image = Vec::with_capacity(total);
+    let mut input_shape = Vec::new();
 
     for img_path in image_paths.into_iter().take(total) {
         let img = image::open(&img_path)?.to_rgb8();
         let (tensor, _, _, _) = pre.preprocess_from_u8_slice(&img, img.width(), img.height())?;
+        pb.inc(1);
 
-        let output_path = output_dir.join(format!("calib_{processed:04}.bin"));
-        dump_tensor(&output_path, &tensor)?;
-
+        let name = format!("calib_{processed:04}.bin");
+        let values = tensor
+            .as_slice()
+            .ok_or_else(|| anyhow::anyhow!("Tensor is not contiguous in memory"))?;
+        let image_stats = ImageStats::measure(img_path.display().to_string(), name.clone(), values);
+        if image_stats.non_finite > 0 {
+            anyhow::bail!(
+                "{}: tensor holds {} NaN or infinite values",
+                img_path.display(),
+                image_stats.non_finite
+            );
+        }
+        if image_stats.is_flat() {
+            pb.println(format!("Skipping flat image {}", img_path.display()));
+            continue;
+        }
+
+        dump_tensor(&output_dir.join(&name), &tensor)?;
+        calibration.observe(&tensor)?;
+        input_shape = tensor.shape().to_vec();
+        per_image.push(image_stats);
         processed += 1;
-        pb.inc(1);
     }
 
     pb.finish_with_message("done");
 
+    if processed == 0 {
+        anyhow::bail!("Every image in {} is flat", input_dir.display());
+    }
+
+    let metadata = Metadata {
+        profile: args.profile.name,
+        input_name: &args.input_name,
+        input_shape,
+        method: args.method,
+        granularity: args.granularity,
+        trt_calibrator: args.method.trt_algorithm(),
+        images: processed,
+        skipped: total - processed,
+        tensor: calibration.tensor(),
+        channels: calibration.channels(),
+        per_image,
+    };
+    fs::write(
+        output_dir.join("calibration.json"),
+        serde_json::to_string_pretty(&metadata)?,
+    )?;
+
+    println!("\nDynamic range ({:?}):", args.method);
+    for range in std::iter::once(&metadata.tensor).chain(&metadata.channels) {
+        let label = match range.channel {
+            Some(channel) => format!("channel {channel}"),
+            None => "tensor".to_string(),
+        };
+        println!(
+            "  {label:<10} min {:>8.4}  max {:>8.4}  mean {:>8.4}  std {:>7.4}  amax {:>8.4}  scale {:.6}",
+            range.min, range.max, range.mean, range.std, range.amax, range.scale
+        );
+    }
+
+    if let (Some(path), Some(version)) = (&cache_path, trt_version) {
+        cache::write(
+            path,
+            version,
+            args.method.trt_algorithm(),
+            &[(args.input_name.as_str(), metadata.tensor.scale)],
+        )?;
+        println!("TensorRT cache for the input written to {}", path.display());
+    }
+
     println!(
         "\nProcessed {} images, tensors saved to {}",
         processed,
@@ -0,0 +1,367 @@
+//! Dynamic range of the calibration tensors
+//!
+//! INT8 maps `[-amax, amax]` onto 255 levels, with `scale = amax / 127`. Min-max
+//! calibration takes the largest magnitude seen, so one outlier spreads the levels
+//! thin; entropy calibration (TensorRT's `EntropyCalibration2`) picks the
+//! threshold whose quantized histogram loses the least information (KL
+//! divergence) against the original, clipping rare outliers instead.
+
+use clap::ValueEnum;
+use ndarray::{ArrayD, Axis};
+use serde::Serialize;
+
+/// Histogram bins over `[0, amax]`, as TensorRT uses
+const NUM_BINS: usize = 2048;
+/// Positive INT8 levels the histogram is quantized to
+const QUANTIZED_BINS: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    /// Threshold minimizing the KL divergence, TensorRT's default
+    Entropy,
+    /// Largest magnitude seen
+    MinMax,
+}
+
+impl Method {
+    /// Calibrator name TensorRT writes in the cache header
+    pub fn trt_algorithm(self) -> &'static str {
+        match self {
+            Self::Entropy => "EntropyCalibration2",
+            Self::MinMax => "MinMaxCalibration",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Granularity {
+    /// One range for the whole tensor
+    PerTensor,
+    /// One range per channel (axis 1 of NCHW), plus the whole tensor
+    PerChannel,
+}
+
+/// Histogram of magnitudes, widened by merging bin pairs when a larger one comes
+#[derive(Debug, Clone)]
+struct Histogram {
+    bins: Vec<u64>,
+    /// Width of a bin, 0 until a non-zero value was seen
+    width: f32,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            bins: vec![0; NUM_BINS],
+            width: 0.0,
+        }
+    }
+
+    fn observe(&mut self, values: &[f32]) {
+        let amax = values.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+        if self.width == 0.0 {
+            self.width = amax / NUM_BINS as f32;
+        }
+        while amax > self.width * NUM_BINS as f32 {
+            for i in 0..NUM_BINS / 2 {
+                self.bins[i] = self.bins[2 * i] + self.bins[2 * i + 1];
+            }
+            self.bins[NUM_BINS / 2..].fill(0);
+            self.width *= 2.0;
+        }
+        for value in values {
+            let bin = if self.width == 0.0 {
+                0
+            } else {
+                (value.abs() / self.width) as usize
+            };
+            self.bins[bin.min(NUM_BINS - 1)] += 1;
+        }
+    }
+
+    /// Threshold keeping the quantized histogram closest to this one
+    fn entropy_threshold(&self) -> f32 {
+        let mut outliers: u64 = self.bins[QUANTIZED_BINS..].iter().sum();
+        let mut best = (f64::INFINITY, NUM_BINS);
+        for i in QUANTIZED_BINS..=NUM_BINS {
+            let kept = &self.bins[..i];
+            // Everything above the threshold is clipped into the last bin
+            let mut p: Vec<f64> = kept.iter().map(|&count| count as f64).collect();
+            p[i - 1] += outliers as f64;
+            if i < NUM_BINS {
+                outliers -= self.bins[i];
+            }
+
+            // Each INT8 level spreads its count evenly over the bins `p` has mass in
+            let mut q = vec![0.0f64; i];
+            for level in 0..QUANTIZED_BINS {
+                let (start, end) = (level * i / QUANTIZED_BINS, (level + 1) * i / QUANTIZED_BINS);
+                let nonzero = p[start..end].iter().filter(|&&p| p > 0.0).count();
+                if nonzero == 0 {
+                    continue;
+                }
+                let share = kept[start..end].iter().sum::<u64>() as f64 / nonzero as f64;
+                for (q, &p) in q[start..end].iter_mut().zip(&p[start..end]) {
+                    if p > 0.0 {
+                        *q = share;
+                    }
+                }
+            }
+
+            let divergence = kl_divergence(&p, &q);
+            if divergence < best.0 {
+                best = (divergence, i);
+            }
+        }
+        best.1 as f32 * self.width
+    }
+}
+
+/// Probability `q` gives to a bin it has no mass in, so the divergence stays finite
+const SMOOTHING: f64 = 1e-8;
+
+/// KL divergence of `q` from `p`, both normalized here
+fn kl_divergence(p: &[f64], q: &[f64]) -> f64 {
+    let (p_sum, q_sum) = (p.iter().sum::<f64>(), q.iter().sum::<f64>());
+    if p_sum == 0.0 || q_sum == 0.0 {
+        return f64::INFINITY;
+    }
+    p.iter()
+        .zip(q)
+        .filter(|(p, _)| **p > 0.0)
+        .map(|(p, q)| {
+            let (p, q) = (p / p_sum, (q / q_sum).max(SMOOTHING));
+            p * (p / q).ln()
+        })
+        .sum()
+}
+
+/// Running range of a tensor or one of its channels
+#[derive(Debug, Clone)]
+struct Range {
+    count: u64,
+    min: f32,
+    max: f32,
+    sum: f64,
+    sum_sq: f64,
+    histogram: Histogram,
+}
+
+/// A range as reported in `calibration.json`
+#[derive(Debug, Clone, Serialize)]
+pub struct RangeReport {
+    /// Channel index, `None` for the whole tensor
+    pub channel: Option<usize>,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f64,
+    pub std: f64,
+    /// Magnitude mapped to ±127 by the chosen method
+    pub amax: f32,
+    pub scale: f32,
+}
+
+impl Range {
+    fn new() -> Self {
+        Self {
+            count: 0,
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+            sum: 0.0,
+            sum_sq: 0.0,
+            histogram: Histogram::new(),
+        }
+    }
+
+    fn observe(&mut self, values: &[f32]) {
+        for &value in values {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+            self.sum += value as f64;
+            self.sum_sq += (value as f64).powi(2);
+        }
+        self.count += values.len() as u64;
+        self.histogram.observe(values);
+    }
+
+    fn report(&self, method: Method, channel: Option<usize>) -> RangeReport {
+        let count = self.count.max(1) as f64;
+        let mean = self.sum / count;
+        let amax = match method {
+            Method::MinMax => self.min.abs().max(self.max.abs()),
+            Method::Entropy => self.histogram.entropy_threshold(),
+        };
+        RangeReport {
+            channel,
+            min: self.min,
+            max: self.max,
+            mean,
+            std: (self.sum_sq / count - mean * mean).max(0.0).sqrt(),
+            amax,
+            scale: amax / 127.0,
+        }
+    }
+}
+
+/// Ranges of the input tensor over every calibration image
+pub struct Calibration {
+    method: Method,
+    tensor: Range,
+    /// Per channel with [`Granularity::PerChannel`], empty otherwise
+    channels: Vec<Range>,
+    granularity: Granularity,
+}
+
+impl Calibration {
+    pub fn new(method: Method, granularity: Granularity) -> Self {
+        Self {
+            method,
+            tensor: Range::new(),
+            channels: Vec::new(),
+            granularity,
+        }
+    }
+
+    /// Add an NCHW tensor
+    pub fn observe(&mut self, tensor: &ArrayD<f32>) -> anyhow::Result<()> {
+        let values = tensor
+            .as_slice()
+            .ok_or_else(|| anyhow::anyhow!("Tensor is not contiguous in memory"))?;
+        self.tensor.observe(values);
+        if self.granularity == Granularity::PerChannel {
+            let channels = tensor.shape().get(1).copied().unwrap_or(1);
+            self.channels.resize_with(channels, Range::new);
+            for (channel, range) in self.channels.iter_mut().enumerate() {
+                for plane in tensor.index_axis(Axis(1), channel).axis_iter(Axis(0)) {
+                    range.observe(&plane.iter().copied().collect::<Vec<_>>());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Range of the whole tensor
+    pub fn tensor(&self) -> RangeReport {
+        self.tensor.report(self.method, None)
+    }
+
+    /// Range of each channel, empty unless per channel
+    pub fn channels(&self) -> Vec<RangeReport> {
+        self.channels
+            .iter()
+            .enumerate()
+            .map(|(channel, range)| range.report(self.method, Some(channel)))
+            .collect()
+    }
+}
+
+/// Range of one image's tensor, checked before it joins the calibration set
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageStats {
+    pub image: String,
+    pub tensor: String,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f64,
+    /// NaN and infinite values
+    pub non_finite: usize,
+}
+
+impl ImageStats {
+    pub fn measure(image: String, tensor: String, values: &[f32]) -> Self {
+        let finite: Vec<f32> = values.iter().copied().filter(|v| v.is_finite()).collect();
+        Self {
+            image,
+            tensor,
+            min: finite.iter().copied().fold(f32::INFINITY, f32::min),
+            max: finite.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+            mean: finite.iter().map(|&v| v as f64).sum::<f64>() / finite.len().max(1) as f64,
+            non_finite: values.len() - finite.len(),
+        }
+    }
+
+    /// The image is one flat color, e.g. a blank frame or a broken download,
+    /// and says nothing about the range
+    pub fn is_flat(&self) -> bool {
+        self.max - self.min < 1e-6
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tensor(channels: &[Vec<f32>]) -> ArrayD<f32> {
+        let plane = channels[0].len();
+        ArrayD::from_shape_vec(vec![1, channels.len(), 1, plane], channels.concat()).unwrap()
+    }
+
+    #[test]
+    fn test_histogram_widens_without_losing_counts() {
+        let mut histogram = Histogram::new();
+        histogram.observe(&[0.1, -0.2, 0.3]);
+        histogram.observe(&[5.0, -12.0]);
+
+        assert_eq!(histogram.bins.iter().sum::<u64>(), 5);
+        assert!(histogram.width * NUM_BINS as f32 >= 12.0);
+    }
+
+    #[test]
+    fn test_entropy_clips_outliers_min_max_keeps_them() {
+        // Exponential, with a long thin tail
+        let n = 100_000;
+        let mut values: Vec<f32> = (0..n)
+            .map(|i| -(1.0 - (i as f32 + 0.5) / n as f32).ln() * 0.1)
+            .collect();
+        values.push(5.0);
+        let input = tensor(&[values]);
+
+        let mut entropy = Calibration::new(Method::Entropy, Granularity::PerTensor);
+        entropy.observe(&input).unwrap();
+        let mut min_max = Calibration::new(Method::MinMax, Granularity::PerTensor);
+        min_max.observe(&input).unwrap();
+
+        assert_eq!(min_max.tensor().amax, 5.0);
+        let amax = entropy.tensor().amax;
+        assert!((0.3..2.0).contains(&amax), "{amax}");
+        assert!((entropy.tensor().scale - amax / 127.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_per_channel_ranges() {
+        let input = tensor(&[vec![-1.0, 1.0], vec![0.0, 4.0]]);
+        let mut calibration = Calibration::new(Method::MinMax, Granularity::PerChannel);
+        calibration.observe(&input).unwrap();
+
+        let channels = calibration.channels();
+        assert_eq!(channels.len(), 2);
+        assert_eq!(
+            (channels[0].min, channels[0].max, channels[0].amax),
+            (-1.0, 1.0, 1.0)
+        );
+        assert_eq!((channels[1].mean, channels[1].amax), (2.0, 4.0));
+        assert_eq!(calibration.tensor().amax, 4.0);
+        assert!(
+            Calibration::new(Method::MinMax, Granularity::PerTensor)
+                .channels()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_image_stats_flag_bad_tensors() {
+        let stats = ImageStats::measure(
+            "a.jpg".to_string(),
+            "calib_0000.bin".to_string(),
+            &[0.5, f32::NAN, f32::INFINITY, -0.5],
+        );
+        assert_eq!(stats.non_finite, 2);
+        assert_eq!((stats.min, stats.max), (-0.5, 0.5));
+        assert!(!stats.is_flat());
+
+        let flat = ImageStats::measure(String::new(), String::new(), &[0.2; 4]);
+        assert!(flat.is_flat());
+    }
+}
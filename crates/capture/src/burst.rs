@@ -0,0 +1,125 @@
+//! Burst capture on alarm entry
+//!
+//! When the controller raises the alarm, the next `ALARM_BURST_FRAMES` frames are
+//! captured back to back, ignoring pacing, and tagged with their sharpness so
+//! consumers can keep the sharpest instead of the first, possibly blurred one.
+
+use schema::Burst;
+
+/// Frames of the burst in progress
+pub struct BurstCapture {
+    length: u16,
+    /// Frame number of the burst's first frame and the next index
+    current: Option<(u64, u16)>,
+}
+
+impl BurstCapture {
+    /// Bursts of `length` frames, none when 0
+    pub fn new(length: u16) -> Self {
+        Self {
+            length,
+            current: None,
+        }
+    }
+
+    /// Start a burst, restarting one in progress
+    pub fn start(&mut self) {
+        if self.length > 0 {
+            self.current = Some((0, 0));
+        }
+    }
+
+    pub fn active(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Tag for the next frame of the burst, `frame_number` being its number
+    pub fn next(&mut self, frame_number: u64, sharpness: f32) -> Option<Burst> {
+        let (id, index) = self.current.as_mut()?;
+        if *index == 0 {
+            *id = frame_number;
+        }
+        let burst = Burst::new(*id, *index, self.length, sharpness);
+        *index += 1;
+        if *index == self.length {
+            self.current = None;
+        }
+        Some(burst)
+    }
+}
+
+/// Variance of the Laplacian of the frame's luma, a measure of how sharp it is
+///
+/// Motion blur and defocus flatten edges, which lowers the variance. Sampled on
+/// every other row and column, which is plenty to rank frames of the same scene.
+pub fn sharpness(rgb: &[u8], width: u32, height: u32) -> f32 {
+    let (width, height) = (width as usize, height as usize);
+    if width < 3 || height < 3 || rgb.len() < width * height * 3 {
+        return 0.0;
+    }
+    let luma = |x: usize, y: usize| {
+        let i = (y * width + x) * 3;
+        // BT.601 weights in integers
+        (77 * rgb[i] as i32 + 150 * rgb[i + 1] as i32 + 29 * rgb[i + 2] as i32) >> 8
+    };
+
+    let (mut sum, mut sum_squares, mut count) = (0f64, 0f64, 0u64);
+    for y in (1..height - 1).step_by(2) {
+        for x in (1..width - 1).step_by(2) {
+            let laplacian = (luma(x - 1, y) + luma(x + 1, y) + luma(x, y - 1) + luma(x, y + 1)
+                - 4 * luma(x, y)) as f64;
+            sum += laplacian;
+            sum_squares += laplacian * laplacian;
+            count += 1;
+        }
+    }
+    let mean = sum / count as f64;
+    (sum_squares / count as f64 - mean * mean) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_tags_frames_until_its_length() {
+        let mut burst = BurstCapture::new(3);
+        assert!(burst.next(5, 1.0).is_none());
+
+        burst.start();
+        let tags: Vec<(u64, u16, u16)> = (10..15)
+            .filter_map(|n| burst.next(n, 1.0))
+            .map(|b| (b.id(), b.index(), b.length()))
+            .collect();
+        assert_eq!(tags, [(10, 0, 3), (10, 1, 3), (10, 2, 3)]);
+        assert!(!burst.active());
+
+        let mut disabled = BurstCapture::new(0);
+        disabled.start();
+        assert!(!disabled.active());
+    }
+
+    #[test]
+    fn test_blurred_frame_is_less_sharp() {
+        let (width, height) = (64u32, 48u32);
+        // Vertical stripes two pixels wide, then blurred across columns
+        let stripe = |x: i32| {
+            if x.clamp(0, width as i32 - 1) / 2 % 2 == 0 {
+                0
+            } else {
+                255
+            }
+        };
+        let frame = |column: &dyn Fn(i32) -> i32| -> Vec<u8> {
+            (0..width * height)
+                .flat_map(|i| [column((i % width) as i32) as u8; 3])
+                .collect()
+        };
+        let stripes = frame(&stripe);
+        let blurred = frame(&|x| (stripe(x - 1) + 2 * stripe(x) + stripe(x + 1)) / 4);
+
+        let sharp = sharpness(&stripes, width, height);
+        assert!(sharp > sharpness(&blurred, width, height));
+        assert_eq!(sharpness(&vec![128; 64 * 48 * 3], width, height), 0.0);
+    }
+}
@@ -1,43 +1,200 @@
+use crate::burst::{self, BurstCapture};
 use crate::config::CameraConfig;
 use crate::decoder::{FrameDecoder, MjpegDecoder, YuyvDecoder};
 use crate::device::{CameraDevice, PixelFormat};
+use crate::exposure::AutoExposure;
+use crate::metrics::CaptureMetrics;
+use crate::night_still::NightStill;
 use crate::pacing::CapturePacing;
-use crate::sink::FrameSink;
-use crate::source::FrameSource;
-use anyhow::Result;
-use bridge::{BridgeSemaphore, SentryControl, SentryMode, capture_current_trace};
-use common::span;
+use crate::sink::{CapturedFrame, FrameTee, MjpegFileSink, RtspPushSink, ShmSink, SinkSpec};
+use crate::source::{FrameSource, StallDetector, capture_time};
+use anyhow::{Context, Result};
+use bridge::{
+    BridgeSemaphore, FrameCompression, FrameSigner, PipelineClock, Provenance, SentryControl,
+    SentryMode, capture_current_trace,
+};
+use common::{Watchdog, log_throttle, span};
+use std::io;
 use std::sync::{
     Arc,
     atomic::{AtomicBool, Ordering},
 };
+use std::time::Duration;
+
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|h| h.trim().to_string())
+        .unwrap_or_default()
+}
 
 pub struct Camera {
     camera_id: u32,
     device: CameraDevice,
     decoder: Box<dyn FrameDecoder>,
-    sink: FrameSink,
+    sinks: FrameTee,
     sentry_mode_fps: f64,
+    elevated_mode_fps: f64,
+    auto_exposure: Option<AutoExposure>,
+    night_still: Option<NightStill>,
+    /// Frames are written as the camera's JPEG, decoded only for auto exposure
+    jpeg_passthrough: bool,
+    buffer_count: u32,
+    dequeue_timeout: Duration,
+    /// Consecutive dequeue timeouts before the stream is re-created
+    stall_timeouts: u32,
+    /// Frames captured back to back on alarm entry
+    alarm_burst_frames: u16,
+    /// Pipeline epoch and clock sample published for the other services
+    clock: Option<PipelineClock>,
+    metrics: CaptureMetrics,
+    #[cfg(feature = "jetson")]
+    nvmm: Option<crate::nvmm::NvmmRing>,
 }
 
 impl Camera {
     pub fn build(config: CameraConfig) -> Result<Self> {
         let camera_id = config.camera_id;
-        let device = CameraDevice::open(&config)?;
+        let mut device = CameraDevice::open(&config)?;
+
+        let auto_exposure = match config.auto_exposure.clone() {
+            Some(ae_config) => match AutoExposure::attach(&device.device, ae_config) {
+                Ok(ae) => {
+                    device.exposure.auto = false;
+                    Some(ae)
+                }
+                Err(e) => {
+                    tracing::warn!("Auto exposure loop disabled: {:#}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let night_still = config.night_still.clone().and_then(|night_config| {
+            NightStill::attach(&device.device, night_config)
+                .inspect_err(|e| tracing::warn!("Night stills disabled: {:#}", e))
+                .ok()
+        });
+        if let Some(controls) = device.controls.as_mut() {
+            controls.save(&device.device);
+        }
 
         let decoder: Box<dyn FrameDecoder> = match device.pixel_format {
             PixelFormat::Yuyv => Box::new(YuyvDecoder::new()),
             PixelFormat::Mjpeg => Box::new(MjpegDecoder::new()?),
         };
 
-        let sink = FrameSink::new()?;
+        let jpeg_passthrough = match (config.jpeg_passthrough, device.pixel_format) {
+            (false, _) => false,
+            (true, PixelFormat::Yuyv) => {
+                tracing::warn!("JPEG passthrough requires an MJPEG camera, disabled for YUYV");
+                false
+            }
+            (true, PixelFormat::Mjpeg) if config.max_frame_dimension.is_some() => {
+                tracing::warn!("JPEG passthrough disabled: frames are downscaled");
+                false
+            }
+            (true, PixelFormat::Mjpeg) => {
+                tracing::info!("Writing MJPEG frames without decoding them");
+                true
+            }
+        };
+
+        let mut sink = ShmSink::new(device.width, device.height)?;
+        sink.set_jpeg_passthrough(jpeg_passthrough);
+        sink.set_provenance(Provenance {
+            capture_host: hostname(),
+            device_path: device.path.clone(),
+            device_name: device.card.clone(),
+            driver: device.driver.clone(),
+            decoder: if jpeg_passthrough {
+                "jpeg-passthrough".to_string()
+            } else {
+                decoder.name().to_string()
+            },
+            exposure_auto: device.exposure.auto,
+            exposure_limit: device.exposure.limit.unwrap_or(0),
+            nominal_fps: device.max_fps as f32,
+            firmware_version: device.driver_version.clone(),
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+        });
+
+        if let Some(path) = &config.signing_key_file {
+            let key_id = config.signing_key_id.clone().unwrap_or_else(hostname);
+            let signer = FrameSigner::from_key_file(key_id, path)
+                .with_context(|| format!("Failed to load the signing key {}", path.display()))?;
+            tracing::info!(key_id = signer.key_id(), "Signing frames");
+            sink.set_signer(signer);
+        }
+
+        if config.frame_compression != FrameCompression::None {
+            sink.set_compression(config.frame_compression);
+            tracing::info!(compression = %config.frame_compression, "Compressing frames before writing them");
+        }
+
+        if let Some(timeout) = config.frame_write_gate {
+            sink.set_write_gate(timeout)?;
+            tracing::info!(
+                ?timeout,
+                "Waiting for lossless frame readers before replacing frames"
+            );
+        }
+
+        if let Some(max_dimension) = config.max_frame_dimension {
+            sink.set_max_dimension(max_dimension);
+            tracing::info!(max_dimension, "Downscaling frames before writing them");
+        }
+
+        let mut sinks = FrameTee::new(sink);
+        for spec in &config.sinks {
+            match spec {
+                SinkSpec::Shm => {}
+                SinkSpec::MjpegFile(path) => sinks.add(Box::new(MjpegFileSink::new(path.clone()))),
+                SinkSpec::RtspPush(url) => sinks.add(Box::new(RtspPushSink::new(
+                    url.clone(),
+                    config.ffmpeg.clone(),
+                ))),
+            }
+        }
+
+        #[cfg(feature = "jetson")]
+        let nvmm = match (config.nvmm_export, device.pixel_format) {
+            (false, _) => None,
+            (true, PixelFormat::Yuyv) => {
+                Some(crate::nvmm::NvmmRing::new(device.width, device.height)?)
+            }
+            (true, PixelFormat::Mjpeg) => {
+                tracing::warn!("NVMM export requires a YUYV camera, disabled for MJPEG");
+                None
+            }
+        };
+        #[cfg(not(feature = "jetson"))]
+        if config.nvmm_export {
+            tracing::warn!("NVMM export requested but jetson feature not enabled");
+        }
+
+        let clock = PipelineClock::build()
+            .inspect(PipelineClock::start)
+            .inspect_err(|e| tracing::warn!(error = %e, "Pipeline clock unavailable"))
+            .ok();
 
         Ok(Self {
             camera_id,
             device,
             decoder,
-            sink,
+            sinks,
             sentry_mode_fps: config.sentry_mode_fps,
+            elevated_mode_fps: config.elevated_mode_fps,
+            auto_exposure,
+            night_still,
+            jpeg_passthrough,
+            buffer_count: config.buffer_count,
+            dequeue_timeout: config.dequeue_timeout,
+            stall_timeouts: config.stall_timeouts,
+            alarm_burst_frames: config.alarm_burst_frames,
+            clock,
+            metrics: CaptureMetrics::new("capture"),
+            #[cfg(feature = "jetson")]
+            nvmm,
         })
     }
 
@@ -46,6 +203,7 @@ impl Camera {
         shutdown: &Arc<AtomicBool>,
         sentry: &SentryControl,
         mode_semaphore: &BridgeSemaphore,
+        watchdog: &mut Watchdog,
     ) -> Result<()> {
         tracing::info!(
             "Starting camera stream at {}x{}...",
@@ -53,19 +211,30 @@ impl Camera {
             self.device.height,
         );
 
-        let mut source = FrameSource::new(&self.device.device)?;
-        let mut pacing = CapturePacing::new(self.device.max_fps, self.sentry_mode_fps);
+        let mut source =
+            FrameSource::new(&self.device.device, self.buffer_count, self.dequeue_timeout)?;
+        let mut stall = StallDetector::new(self.stall_timeouts);
+        let mut pacing = CapturePacing::new(
+            self.device.max_fps,
+            self.sentry_mode_fps,
+            self.elevated_mode_fps,
+        );
+        let mut burst = BurstCapture::new(self.alarm_burst_frames);
 
         let mut frame_count = 0u64;
         let mut dropped_frames = 0u64;
 
         while !shutdown.load(Ordering::Relaxed) {
             let start_time = std::time::Instant::now();
+            watchdog.ping();
+            if let Some(clock) = &self.clock {
+                clock.refresh();
+            }
 
             let mode = sentry.get_mode();
             if pacing.update(mode) {
-                // Flush stale frames when entering Alarmed mode
-                if mode == SentryMode::Alarmed {
+                // Flush stale frames when speeding up
+                if mode != SentryMode::Standby {
                     let flushed = source.flush();
                     if flushed > 0 {
                         tracing::debug!("Flushed {} stale frames on mode transition", flushed);
@@ -76,61 +245,184 @@ impl Camera {
                     mode,
                     pacing.frame_duration()
                 );
+                if mode == SentryMode::Alarmed {
+                    burst.start();
+                    if burst.active() {
+                        tracing::info!(frames = self.alarm_burst_frames, "Alarm burst started");
+                    }
+                }
             }
 
             match source.next_frame() {
                 Ok((buf, meta)) => {
                     let _s = span!("capture_frame");
+                    stall.frame();
 
-                    // Decode directly using split borrow (decoder + sink are separate fields)
-                    let rgb_data =
+                    // Passthrough frames are only decoded when auto exposure meters them,
+                    // to measure the sharpness of burst frames, or the brightness of
+                    // the scene a night still was requested in
+                    let night_still_pending =
+                        self.night_still.is_some() && self.sinks.shm().night_still_pending();
+                    let decode = !self.jpeg_passthrough
+                        || burst.active()
+                        || night_still_pending
+                        || self
+                            .auto_exposure
+                            .as_ref()
+                            .is_some_and(AutoExposure::wants_frame);
+
+                    // Decode directly using split borrow (decoder + sinks are separate fields)
+                    let rgb_data = if decode {
                         match self
                             .decoder
                             .decode(buf, self.device.width, self.device.height)
                         {
-                            Ok(data) => data,
+                            Ok(data) => Some(data),
                             Err(e) => {
                                 dropped_frames += 1;
-                                tracing::warn!("Frame #{} decode error: {}", frame_count, e);
+                                log_throttle!(warn, "Frame #{} decode error: {}", frame_count, e);
                                 continue;
                             }
-                        };
-
-                    let trace_ctx = capture_current_trace();
+                        }
+                    } else {
+                        None
+                    };
 
-                    if let Err(e) = self.sink.write(
-                        rgb_data,
+                    let mut frame = CapturedFrame::new(
                         self.camera_id,
                         frame_count,
                         self.device.width,
                         self.device.height,
-                        trace_ctx.as_ref(),
-                    ) {
+                    );
+                    frame.rgb = rgb_data;
+                    if self.device.pixel_format == PixelFormat::Mjpeg {
+                        frame.jpeg = Some(buf);
+                    }
+
+                    #[cfg(feature = "jetson")]
+                    if let Some(ring) = self.nvmm.as_mut() {
+                        match ring.write(buf) {
+                            Ok(index) => frame.nvmm_surface = Some(index),
+                            Err(e) => {
+                                log_throttle!(warn, "Frame #{} NVMM copy error: {}", frame_count, e)
+                            }
+                        }
+                    }
+
+                    if burst.active() {
+                        let sharpness = rgb_data.map_or(0.0, |rgb| {
+                            burst::sharpness(rgb, self.device.width, self.device.height)
+                        });
+                        frame.burst = burst.next(frame_count, sharpness);
+                    }
+
+                    if let Some(night_still) = self.night_still.as_mut() {
+                        let rgb = rgb_data.map(|rgb| (rgb, self.device.width, self.device.height));
+                        let held =
+                            night_still.before_frame(&self.device.device, night_still_pending, rgb);
+                        self.sinks.shm().hold_stills(held);
+                    }
+
+                    frame.timestamp = capture_time(&meta);
+                    let trace_ctx = capture_current_trace();
+                    frame.trace = trace_ctx.as_ref();
+
+                    if let Err(e) = self.sinks.write(&frame) {
                         dropped_frames += 1;
-                        tracing::warn!("Frame #{} write error: {}", frame_count, e);
+                        log_throttle!(warn, "Frame #{} write error: {}", frame_count, e);
                     } else {
                         frame_count += 1;
                     }
 
+                    let night_still_active = match self.night_still.as_mut() {
+                        // The still's own frame is not metered either
+                        Some(night_still) => {
+                            let active = night_still.active();
+                            night_still.after_frame(&self.device.device);
+                            active
+                        }
+                        None => false,
+                    };
+                    if let Some(ae) = self.auto_exposure.as_mut() {
+                        match rgb_data.filter(|_| !night_still_active) {
+                            Some(rgb) => ae.update(
+                                &self.device.device,
+                                rgb,
+                                self.device.width,
+                                self.device.height,
+                            ),
+                            None => ae.skip(),
+                        }
+                    }
+
                     if frame_count > 0 && frame_count.is_multiple_of(30) {
+                        if let Some(time) = self.clock.as_ref().and_then(PipelineClock::read) {
+                            self.metrics.record_clock_drift(time.drift_ns());
+                        }
+                        let overruns = self.sinks.shm().take_write_gate_overruns();
+                        if overruns > 0 {
+                            self.metrics.record_write_gate_overruns(overruns);
+                            tracing::warn!(
+                                overruns,
+                                "Frames replaced before a lossless reader consumed them"
+                            );
+                        }
                         tracing::debug!(
                             "Status: [Frames: {}] [Dropped: {}] [Seq: {}] [V4L seq: {}] [Mode: {:?}]",
                             frame_count,
                             dropped_frames,
-                            self.sink.sequence(),
+                            self.sinks.shm().sequence(),
                             meta.sequence,
                             pacing.mode()
                         );
                     }
                 }
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                    self.metrics.record_dequeue_timeout();
+                    tracing::warn!("No frame from the camera within {:?}", self.dequeue_timeout);
+                    if stall.timeout() {
+                        tracing::error!(
+                            "Camera stalled for {} dequeues, restarting the stream",
+                            self.stall_timeouts
+                        );
+                        self.metrics.record_stream_restart();
+                        if let Err(e) = source.restart() {
+                            tracing::error!("Failed to restart capture stream: {:#}", e);
+                        } else if let Some((width, height)) = self.device.renegotiated_size() {
+                            tracing::warn!(
+                                previous_width = self.device.width,
+                                previous_height = self.device.height,
+                                width,
+                                height,
+                                "Camera switched resolution, following it"
+                            );
+                            self.metrics.record_resolution_change();
+                            self.device.width = width;
+                            self.device.height = height;
+                            self.sinks.shm().set_resolution(width, height);
+                            #[cfg(feature = "jetson")]
+                            if self.nvmm.take().is_some() {
+                                tracing::warn!(
+                                    "NVMM surfaces were sized for the previous resolution, export disabled"
+                                );
+                            }
+                        }
+                        // A stall is often a bus reset, which drops the controls
+                        if let Some(controls) = &self.device.controls {
+                            controls.restore(&self.device.device);
+                        }
+                    }
+                }
                 Err(e) => {
                     dropped_frames += 1;
-                    tracing::warn!("Frame #{} capture error: {}", frame_count, e);
+                    log_throttle!(warn, "Frame #{} capture error: {}", frame_count, e);
                 }
             }
 
             let elapsed = start_time.elapsed();
-            if elapsed < pacing.frame_duration() {
+            if burst.active() {
+                // Burst frames are taken as fast as the camera delivers them
+            } else if elapsed < pacing.frame_duration() {
                 let remaining = pacing.frame_duration() - elapsed;
                 // Wait on mqueue instead of sleeping - allows instant wake on mode change
                 match mode_semaphore.wait_timeout_duration(remaining) {
@@ -151,6 +443,14 @@ impl Camera {
             }
         }
 
+        drop(source);
+        if let Some(night_still) = self.night_still.as_mut() {
+            night_still.cancel(&self.device.device);
+        }
+        if let Some(controls) = self.device.controls.as_mut() {
+            controls.save(&self.device.device);
+        }
+
         tracing::info!(
             "Shutdown: {} frames captured, {} dropped.",
             frame_count,
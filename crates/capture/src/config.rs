@@ -1,4 +1,11 @@
-use common::{Environment, get_env, get_env_opt};
+use crate::controls::ControlsConfig;
+use crate::exposure::AutoExposureConfig;
+use crate::night_still::NightStillConfig;
+use crate::sink::SinkSpec;
+use bridge::FrameCompression;
+use common::{ConfigCheck, Environment, Scheduling, get_env, get_env_opt};
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct CameraConfig {
@@ -6,7 +13,44 @@ pub struct CameraConfig {
     pub camera_id: u32,
     pub device_id: u32,
     pub sentry_mode_fps: f64,
+    /// Frame rate while the controller sees weak detections, between sentry and max fps
+    pub elevated_mode_fps: f64,
     pub otel_endpoint: Option<String>,
+    /// Share raw frames as NVMM surfaces (requires the jetson feature and a YUYV camera)
+    pub nvmm_export: bool,
+    /// Downscale frames whose longest side exceeds this before writing them to shm
+    pub max_frame_dimension: Option<u32>,
+    /// Steer exposure and gain from the metering zones instead of the camera
+    pub auto_exposure: Option<AutoExposureConfig>,
+    /// Long exposure for stills requested at night, see `night_still.rs`
+    pub night_still: Option<NightStillConfig>,
+    /// Control values restored on open and pinned by id, see `controls.rs`
+    pub controls: Option<ControlsConfig>,
+    /// Write MJPEG frames to shm as JPEG instead of decoding them to RGB
+    pub jpeg_passthrough: bool,
+    /// How RGB frames are stored in shm, JPEG passthrough frames are never compressed
+    pub frame_compression: FrameCompression,
+    /// Longest wait for lossless frame readers before replacing a frame they have
+    /// not consumed yet, readers are never waited for when unset
+    pub frame_write_gate: Option<Duration>,
+    /// V4L2 mmap buffers queued to the driver
+    pub buffer_count: u32,
+    /// Longest wait for the driver to fill a buffer
+    pub dequeue_timeout: Duration,
+    /// Consecutive dequeue timeouts after which the stream is re-created
+    pub stall_timeouts: u32,
+    /// Cores and real-time priority of the capture loop
+    pub scheduling: Scheduling,
+    /// Frames captured back to back on alarm entry, no burst when 0
+    pub alarm_burst_frames: u16,
+    /// Where frames are written besides the shared memory buffer, see `sink`
+    pub sinks: Vec<SinkSpec>,
+    /// `ffmpeg` executable pushing frames over RTSP
+    pub ffmpeg: String,
+    /// Key signing every written frame, frames are not signed when unset
+    pub signing_key_file: Option<PathBuf>,
+    /// Name of the signing key recorded in each signature, the host name when unset
+    pub signing_key_id: Option<String>,
 }
 
 impl CameraConfig {
@@ -16,7 +60,83 @@ impl CameraConfig {
             camera_id: get_env("CAMERA_ID", 0),
             device_id: get_env("DEVICE_ID", 0),
             sentry_mode_fps: get_env("SENTRY_MODE_FPS", 3.0),
+            elevated_mode_fps: get_env("ELEVATED_MODE_FPS", 10.0),
             otel_endpoint: get_env_opt("OTEL_EXPORTER_OTLP_ENDPOINT"),
+            nvmm_export: get_env("NVMM_EXPORT", false),
+            max_frame_dimension: get_env_opt("FRAME_MAX_DIMENSION"),
+            auto_exposure: AutoExposureConfig::from_env()?,
+            night_still: NightStillConfig::from_env()?,
+            controls: ControlsConfig::from_env()?,
+            jpeg_passthrough: get_env("JPEG_PASSTHROUGH", false),
+            frame_compression: get_env("FRAME_COMPRESSION", FrameCompression::None),
+            frame_write_gate: get_env_opt("FRAME_WRITE_GATE_MS").map(Duration::from_millis),
+            buffer_count: get_env("V4L2_BUFFER_COUNT", 4),
+            dequeue_timeout: Duration::from_millis(get_env("DQBUF_TIMEOUT_MS", 2000)),
+            stall_timeouts: get_env("STALL_RESTART_TIMEOUTS", 3),
+            scheduling: Scheduling::from_env()?,
+            alarm_burst_frames: get_env("ALARM_BURST_FRAMES", 10),
+            sinks: SinkSpec::parse_list(&get_env("CAPTURE_SINKS", "shm".to_string()))?,
+            ffmpeg: get_env("FFMPEG_PATH", "ffmpeg".to_string()),
+            signing_key_file: get_env_opt("FRAME_SIGNING_KEY_FILE"),
+            signing_key_id: get_env_opt("FRAME_SIGNING_KEY_ID"),
         })
     }
+
+    /// Check the camera device exists, the frame rate, V4L2 buffer and format
+    /// settings agree, and the sink, controls and signing key paths are usable
+    pub fn check(&self, check: &mut ConfigCheck) {
+        check.readable_file("DEVICE_ID", format!("/dev/video{}", self.device_id));
+        check.in_range("SENTRY_MODE_FPS", self.sentry_mode_fps, 0.1..=120.0);
+        check.in_range("ELEVATED_MODE_FPS", self.elevated_mode_fps, 0.1..=120.0);
+        check.ensure(self.elevated_mode_fps >= self.sentry_mode_fps, || {
+            "ELEVATED_MODE_FPS is below SENTRY_MODE_FPS".to_string()
+        });
+        if let Some(max_dimension) = self.max_frame_dimension {
+            check.in_range("FRAME_MAX_DIMENSION", max_dimension, 64..=7680);
+        }
+        check.ensure(
+            !(self.jpeg_passthrough && self.max_frame_dimension.is_some()),
+            || "JPEG_PASSTHROUGH is ignored while FRAME_MAX_DIMENSION is set".to_string(),
+        );
+        check.ensure(
+            !(self.jpeg_passthrough && self.frame_compression != FrameCompression::None),
+            || "FRAME_COMPRESSION only applies to decoded frames, not JPEG_PASSTHROUGH".to_string(),
+        );
+        if let Some(gate) = self.frame_write_gate {
+            check.ensure(!gate.is_zero(), || {
+                "FRAME_WRITE_GATE_MS of 0 never waits, leave it unset instead".to_string()
+            });
+        }
+        check.in_range("V4L2_BUFFER_COUNT", self.buffer_count, 2..=32);
+        check.ensure(!self.dequeue_timeout.is_zero(), || {
+            "DQBUF_TIMEOUT_MS must be above 0".to_string()
+        });
+        check.ensure(
+            self.dequeue_timeout.as_secs_f64() * self.sentry_mode_fps >= 1.0,
+            || "DQBUF_TIMEOUT_MS is shorter than a frame at SENTRY_MODE_FPS".to_string(),
+        );
+        check.ensure(!(self.jpeg_passthrough && self.nvmm_export), || {
+            "JPEG_PASSTHROUGH and NVMM_EXPORT need different camera formats".to_string()
+        });
+        if let Some(night_still) = &self.night_still {
+            night_still.check(check, self.buffer_count);
+        }
+        if let Some(dir) = self.controls.as_ref().and_then(|c| c.dir.as_ref()) {
+            check.ensure(dir.is_dir(), || {
+                format!("CAMERA_CONTROLS_DIR: {} is not a directory", dir.display())
+            });
+        }
+        self.scheduling.check(check);
+        check.in_range("ALARM_BURST_FRAMES", self.alarm_burst_frames, 0..=120);
+        for sink in &self.sinks {
+            if let SinkSpec::MjpegFile(path) = sink
+                && let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty())
+            {
+                check.creatable_dir("CAPTURE_SINKS", dir);
+            }
+        }
+        if let Some(path) = &self.signing_key_file {
+            check.readable_file("FRAME_SIGNING_KEY_FILE", path);
+        }
+    }
 }
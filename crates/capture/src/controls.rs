@@ -0,0 +1,271 @@
+//! Camera controls kept across re-enumeration
+//!
+//! A camera that re-enumerates (hotplug, USB bus reset) comes back with its
+//! driver defaults, losing the exposure set at startup and whatever the auto
+//! exposure loop converged to. With `CAMERA_CONTROLS_DIR` set, the values of the
+//! controls capture manages are saved there in one JSON file per device, named
+//! after its card and bus, and applied again whenever the device is opened or
+//! its stream restarted. They are saved once the camera is configured and on
+//! shutdown.
+//!
+//! `CAMERA_CONTROL_OVERRIDES` pins controls to fixed values over both the saved
+//! ones and what capture would set itself, as `id=value` pairs with the ids of
+//! videodev2.h in decimal or `0x` hex, e.g. `0x009a0902=150,0x00980913=8`.
+
+use anyhow::{Context, Result};
+use common::{fs::write_atomic, get_env_opt};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use v4l::{
+    Device,
+    control::{Control, Value},
+};
+
+// V4L2 control IDs (from videodev2.h)
+const V4L2_CID_EXPOSURE_AUTO: u32 = 0x009a0901;
+const V4L2_CID_EXPOSURE_ABSOLUTE: u32 = 0x009a0902;
+const V4L2_CID_GAIN: u32 = 0x00980913;
+
+/// Controls set by capture itself, saved along with any overridden ones
+const MANAGED: [u32; 3] = [
+    V4L2_CID_EXPOSURE_AUTO,
+    V4L2_CID_EXPOSURE_ABSOLUTE,
+    V4L2_CID_GAIN,
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct ControlsConfig {
+    /// Directory of the per-device files, nothing is persisted when unset
+    pub dir: Option<PathBuf>,
+    /// Values applied over the saved ones, by control id
+    pub overrides: BTreeMap<u32, i64>,
+}
+
+impl ControlsConfig {
+    /// Enabled when `CAMERA_CONTROLS_DIR` or `CAMERA_CONTROL_OVERRIDES` is set
+    pub fn from_env() -> Result<Option<Self>> {
+        let dir = get_env_opt::<String>("CAMERA_CONTROLS_DIR").map(PathBuf::from);
+        let overrides = match get_env_opt::<String>("CAMERA_CONTROL_OVERRIDES") {
+            Some(overrides) => parse_overrides(&overrides)?,
+            None => BTreeMap::new(),
+        };
+        if dir.is_none() && overrides.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self { dir, overrides }))
+    }
+}
+
+/// Parse `id=value` pairs separated by commas
+pub fn parse_overrides(value: &str) -> Result<BTreeMap<u32, i64>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (id, value) = entry.split_once('=').with_context(|| {
+                format!("CAMERA_CONTROL_OVERRIDES entries must be id=value, got {entry:?}")
+            })?;
+            let id = parse_id(id.trim())
+                .with_context(|| format!("Invalid CAMERA_CONTROL_OVERRIDES id in {entry:?}"))?;
+            let value = value
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid CAMERA_CONTROL_OVERRIDES value in {entry:?}"))?;
+            Ok((id, value))
+        })
+        .collect()
+}
+
+fn parse_id(id: &str) -> Result<u32, std::num::ParseIntError> {
+    match id.strip_prefix("0x").or_else(|| id.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => id.parse(),
+    }
+}
+
+/// File name identifying a device by card and bus, stable across `/dev/videoN` renumbering
+fn file_name(card: &str, bus: &str) -> String {
+    let name: String = format!("{card}-{bus}")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{name}.json")
+}
+
+/// JSON object of the values keyed by hex control id
+fn to_json(values: &BTreeMap<u32, i64>) -> String {
+    let object: serde_json::Map<_, _> = values
+        .iter()
+        .map(|(id, value)| (format!("0x{id:08x}"), (*value).into()))
+        .collect();
+    serde_json::to_string_pretty(&object).unwrap_or_default()
+}
+
+fn from_json(json: &str) -> Result<BTreeMap<u32, i64>> {
+    let object: BTreeMap<String, i64> = serde_json::from_str(json)?;
+    object
+        .into_iter()
+        .map(|(id, value)| {
+            let id = parse_id(&id).with_context(|| format!("Invalid control id {id:?}"))?;
+            Ok((id, value))
+        })
+        .collect()
+}
+
+/// Control values of one device, as last saved
+#[derive(Debug)]
+pub struct ControlStore {
+    path: Option<PathBuf>,
+    saved: BTreeMap<u32, i64>,
+    overrides: BTreeMap<u32, i64>,
+}
+
+impl ControlStore {
+    /// Load the values saved for the device with `card` and `bus`, if any
+    pub fn load(config: &ControlsConfig, card: &str, bus: &str) -> Self {
+        let path = config
+            .dir
+            .as_ref()
+            .map(|dir| dir.join(file_name(card, bus)));
+        let saved = match path.as_deref().map(std::fs::read_to_string) {
+            Some(Ok(json)) => from_json(&json).unwrap_or_else(|e| {
+                tracing::warn!(path = ?path, error = %e, "Ignoring invalid camera controls file");
+                BTreeMap::new()
+            }),
+            _ => BTreeMap::new(),
+        };
+        Self {
+            path,
+            saved,
+            overrides: config.overrides.clone(),
+        }
+    }
+
+    /// Values to apply: the saved ones with the overrides on top, in id order so
+    /// exposure mode is set before exposure time
+    fn targets(&self) -> BTreeMap<u32, i64> {
+        let mut targets = self.saved.clone();
+        targets.extend(&self.overrides);
+        targets
+    }
+
+    /// Apply the saved and overridden values, returning those the device accepted
+    pub fn restore(&self, device: &Device) -> BTreeMap<u32, i64> {
+        let mut applied = BTreeMap::new();
+        for (id, value) in self.targets() {
+            match device.set_control(Control {
+                id,
+                value: Value::Integer(value),
+            }) {
+                Ok(()) => {
+                    applied.insert(id, value);
+                }
+                Err(e) => {
+                    tracing::warn!(control = id, value, error = %e, "Failed to restore camera control")
+                }
+            }
+        }
+        if !applied.is_empty() {
+            tracing::info!(controls = applied.len(), "Restored camera controls");
+        }
+        applied
+    }
+
+    /// Read the current values of the managed and overridden controls and save them
+    pub fn save(&mut self, device: &Device) {
+        let ids: Vec<u32> = MANAGED
+            .into_iter()
+            .chain(self.saved.keys().copied())
+            .chain(self.overrides.keys().copied())
+            .collect();
+        for id in ids {
+            match device.control(id).map(|control| control.value) {
+                Ok(Value::Integer(value)) => {
+                    self.saved.insert(id, value);
+                }
+                Ok(Value::Boolean(value)) => {
+                    self.saved.insert(id, value as i64);
+                }
+                // Missing on this camera, or not a plain value
+                _ => {}
+            }
+        }
+
+        let Some(path) = &self.path else {
+            return;
+        };
+        match write_atomic(path, to_json(&self.saved)) {
+            Ok(()) => tracing::debug!(path = %path.display(), "Saved camera controls"),
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "Failed to save camera controls")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_overrides() {
+        let overrides = parse_overrides("0x009a0902=150, 9963795=-2,").unwrap();
+        assert_eq!(
+            overrides.into_iter().collect::<Vec<_>>(),
+            [(0x00980913, -2), (0x009a0902, 150)]
+        );
+        assert!(parse_overrides("").unwrap().is_empty());
+        assert!(parse_overrides("0x009a0902").is_err());
+        assert!(parse_overrides("exposure=150").is_err());
+        assert!(parse_overrides("0x009a0902=high").is_err());
+    }
+
+    #[test]
+    fn test_file_name_is_per_device() {
+        assert_eq!(
+            file_name("HD Pro Webcam C920", "usb-0000:00:14.0-1"),
+            "HD_Pro_Webcam_C920_usb_0000_00_14_0_1.json"
+        );
+        assert_ne!(
+            file_name("HD Pro Webcam C920", "usb-0000:00:14.0-1"),
+            file_name("HD Pro Webcam C920", "usb-0000:00:14.0-2")
+        );
+    }
+
+    #[test]
+    fn test_saved_values_round_trip_under_overrides() {
+        let dir = std::env::temp_dir().join(format!("camera-controls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = ControlsConfig {
+            dir: Some(dir.clone()),
+            overrides: BTreeMap::from([(V4L2_CID_GAIN, 8)]),
+        };
+
+        let saved = BTreeMap::from([(V4L2_CID_EXPOSURE_AUTO, 3), (V4L2_CID_GAIN, 0)]);
+        let path = dir.join(file_name("cam", "usb-1"));
+        write_atomic(&path, to_json(&saved)).unwrap();
+        assert!(
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .contains("\"0x009a0901\": 3")
+        );
+
+        let store = ControlStore::load(&config, "cam", "usb-1");
+        assert_eq!(
+            store.targets().into_iter().collect::<Vec<_>>(),
+            [(V4L2_CID_GAIN, 8), (V4L2_CID_EXPOSURE_AUTO, 3)]
+        );
+
+        // Another device starts from the overrides only
+        let other = ControlStore::load(&config, "cam", "usb-2");
+        assert_eq!(other.targets().len(), 1);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert_eq!(
+            ControlStore::load(&config, "cam", "usb-1").targets().len(),
+            1
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
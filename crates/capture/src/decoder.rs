@@ -6,6 +6,9 @@ pub trait FrameDecoder: Send {
     /// Decode raw frame data to RGB (3 bytes per pixel).
     /// Returns a reference to the decoder's internal buffer.
     fn decode(&mut self, raw: &[u8], width: u32, height: u32) -> Result<&[u8]>;
+
+    /// Short identifier recorded in frame provenance.
+    fn name(&self) -> &'static str;
 }
 
 /// YUYV (YUV 4:2:2) decoder.
@@ -79,6 +82,10 @@ impl FrameDecoder for YuyvDecoder {
 
         Ok(&self.rgb_buffer[..rgb_size])
     }
+
+    fn name(&self) -> &'static str {
+        "yuyv"
+    }
 }
 
 /// MJPEG decoder using turbojpeg (libjpeg-turbo)
@@ -121,6 +128,10 @@ impl FrameDecoder for MjpegDecoder {
 
         Ok(&self.rgb_buffer[..rgb_size])
     }
+
+    fn name(&self) -> &'static str {
+        "mjpeg-turbojpeg"
+    }
 }
 
 #[cfg(test)]
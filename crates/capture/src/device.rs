@@ -1,4 +1,5 @@
 use crate::config::CameraConfig;
+use crate::controls::ControlStore;
 use anyhow::{Context, Result, anyhow};
 use common::retry::retry_with_backoff;
 use v4l::{
@@ -38,11 +39,11 @@ fn find_usable_camera() -> Option<u32> {
         .map(|dev| dev.index() as u32)
 }
 
-fn open_device(index: u32) -> Result<Device> {
+fn open_device(index: u32) -> Result<(Device, u32)> {
     if let Ok(dev) = Device::new(index as usize)
         && dev.query_caps().is_ok()
     {
-        return Ok(dev);
+        return Ok((dev, index));
     }
 
     tracing::debug!(
@@ -51,7 +52,8 @@ fn open_device(index: u32) -> Result<Device> {
     );
 
     let best_idx = find_usable_camera().ok_or_else(|| anyhow!("No usable video devices found"))?;
-    Device::new(best_idx as usize).context("Failed to open fallback camera device")
+    let dev = Device::new(best_idx as usize).context("Failed to open fallback camera device")?;
+    Ok((dev, best_idx))
 }
 
 /// Select best pixel format: prefer YUYV (faster decode), fallback to MJPEG
@@ -79,13 +81,24 @@ fn select_format(device: &Device) -> Result<PixelFormat> {
     ))
 }
 
+/// Exposure settings applied at startup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExposureSettings {
+    /// Auto exposure (aperture priority) is active
+    pub auto: bool,
+    /// Exposure upper limit in V4L2 units (100µs)
+    pub limit: Option<u32>,
+}
+
 /// Configure camera for crisp motion capture (fast shutter, no temporal blending)
-fn configure_for_crisp_motion(device: &Device) {
+fn configure_for_crisp_motion(device: &Device) -> ExposureSettings {
+    let mut applied = ExposureSettings::default();
+
     let controls = match device.query_controls() {
         Ok(c) => c,
         Err(e) => {
             tracing::warn!("Failed to query camera controls: {}", e);
-            return;
+            return applied;
         }
     };
 
@@ -106,6 +119,7 @@ fn configure_for_crisp_motion(device: &Device) {
         }) {
             tracing::debug!("Aperture priority mode not supported: {}", e);
         } else {
+            applied.auto = true;
             tracing::info!("Exposure mode: aperture priority (auto with limits)");
         }
     }
@@ -124,6 +138,7 @@ fn configure_for_crisp_motion(device: &Device) {
         }) {
             tracing::debug!("Failed to set exposure limit: {}", e);
         } else {
+            applied.limit = Some(exposure as u32);
             tracing::info!(
                 "Exposure limit: {} ({}ms max)",
                 exposure,
@@ -135,19 +150,29 @@ fn configure_for_crisp_motion(device: &Device) {
     if !has_exposure_auto && !has_exposure_absolute {
         tracing::info!("Camera does not expose exposure controls");
     }
+
+    applied
 }
 
 pub struct CameraDevice {
     pub device: Device,
+    pub path: String,
+    pub card: String,
+    pub driver: String,
+    pub driver_version: String,
     pub width: u32,
     pub height: u32,
     pub pixel_format: PixelFormat,
     pub max_fps: f64,
+    pub exposure: ExposureSettings,
+    /// Saved control values, restored on open
+    pub controls: Option<ControlStore>,
 }
 
 impl CameraDevice {
     pub fn open(config: &CameraConfig) -> Result<Self> {
-        let device = retry_with_backoff(|| open_device(config.device_id), 10, 200, "Camera init")?;
+        let (device, index) =
+            retry_with_backoff(|| open_device(config.device_id), 10, 200, "Camera init")?;
 
         let caps = device.query_caps()?;
         tracing::info!("Camera opened: {} ({})", caps.card, caps.driver);
@@ -170,18 +195,50 @@ impl CameraDevice {
             pixel_format
         );
 
-        configure_for_crisp_motion(&device);
+        let mut exposure = configure_for_crisp_motion(&device);
+        let controls = config.controls.as_ref().map(|controls| {
+            let store = ControlStore::load(controls, &caps.card, &caps.bus);
+            let restored = store.restore(&device);
+            if let Some(&mode) = restored.get(&V4L2_CID_EXPOSURE_AUTO) {
+                exposure.auto = mode == V4L2_EXPOSURE_APERTURE_PRIORITY;
+            }
+            if let Some(&limit) = restored.get(&V4L2_CID_EXPOSURE_ABSOLUTE) {
+                exposure.limit = Some(limit as u32);
+            }
+            store
+        });
 
         let params = device.params()?;
         let fps = params.interval.denominator as f64 / params.interval.numerator as f64;
         tracing::info!("Frame rate: {:.1} fps", fps);
 
+        let (major, minor, patch) = caps.version;
+
         Ok(Self {
             device,
+            path: format!("/dev/video{}", index),
+            card: caps.card,
+            driver: caps.driver,
+            driver_version: format!("{}.{}.{}", major, minor, patch),
             width: format.width,
             height: format.height,
             pixel_format,
             max_fps: fps,
+            exposure,
+            controls,
         })
     }
+
+    /// Frame size the driver delivers now, when it is no longer the one the
+    /// device was opened with. Drivers may settle on a lower resolution when the
+    /// stream is re-created, e.g. once the USB bus lacks the bandwidth.
+    pub fn renegotiated_size(&self) -> Option<(u32, u32)> {
+        let format = self
+            .device
+            .format()
+            .inspect_err(|e| tracing::warn!(error = %e, "Failed to query the capture format"))
+            .ok()?;
+        ((format.width, format.height) != (self.width, self.height))
+            .then_some((format.width, format.height))
+    }
 }
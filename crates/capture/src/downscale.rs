@@ -0,0 +1,93 @@
+//! Writer-side downscale of decoded frames
+//!
+//! A 4K RGB frame is ~24MB, while the model only looks at 640². Shrinking frames
+//! before they are serialized cuts the shared memory bandwidth of every consumer;
+//! the original size travels with the frame so detections map back to it.
+
+use anyhow::Result;
+use common::span;
+use fast_image_resize::{
+    FilterType, PixelType, ResizeAlg, ResizeOptions, Resizer,
+    images::{Image, ImageRef},
+};
+
+pub struct Downscaler {
+    max_dimension: u32,
+    resizer: Resizer,
+    options: ResizeOptions,
+    /// Downscaled frame, reused across frames of the same size
+    buffer: Vec<u8>,
+}
+
+impl Downscaler {
+    pub fn new(max_dimension: u32) -> Self {
+        Self {
+            max_dimension: max_dimension.max(1),
+            resizer: Resizer::new(),
+            options: ResizeOptions::new().resize_alg(ResizeAlg::Convolution(FilterType::Bilinear)),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Size of a `width` x `height` frame once its longest side fits `max_dimension`
+    ///
+    /// Keeps the aspect ratio; frames that already fit are left as they are.
+    pub fn target_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let longest = width.max(height);
+        if longest <= self.max_dimension {
+            return (width, height);
+        }
+        let scale = |side: u32| {
+            ((side as u64 * self.max_dimension as u64 + longest as u64 / 2) / longest as u64).max(1)
+                as u32
+        };
+        (scale(width), scale(height))
+    }
+
+    /// Downscale an RGB frame, or `None` when it already fits
+    pub fn downscale(
+        &mut self,
+        rgb: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<Option<(&[u8], u32, u32)>> {
+        let (new_width, new_height) = self.target_size(width, height);
+        if (new_width, new_height) == (width, height) {
+            return Ok(None);
+        }
+        let _s = span!("downscale_frame");
+
+        self.buffer.resize((new_width * new_height * 3) as usize, 0);
+        let src = ImageRef::new(width, height, rgb, PixelType::U8x3)?;
+        let mut dst =
+            Image::from_slice_u8(new_width, new_height, &mut self.buffer, PixelType::U8x3)?;
+        self.resizer.resize(&src, &mut dst, &self.options)?;
+
+        Ok(Some((&self.buffer, new_width, new_height)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target_size_keeps_aspect_ratio() {
+        let downscaler = Downscaler::new(640);
+
+        assert_eq!(downscaler.target_size(3840, 2160), (640, 360));
+        assert_eq!(downscaler.target_size(1080, 1920), (360, 640));
+        assert_eq!(downscaler.target_size(1280, 721), (640, 361));
+        // Frames that already fit are untouched
+        assert_eq!(downscaler.target_size(640, 480), (640, 480));
+        assert_eq!(downscaler.target_size(320, 240), (320, 240));
+    }
+
+    #[test]
+    fn test_frames_that_fit_are_not_copied() {
+        let mut downscaler = Downscaler::new(640);
+        let rgb = vec![0u8; 64 * 48 * 3];
+
+        assert!(downscaler.downscale(&rgb, 64, 48).unwrap().is_none());
+    }
+}
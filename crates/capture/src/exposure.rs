@@ -0,0 +1,426 @@
+//! Closed-loop exposure and gain tuning
+//!
+//! The camera's own auto-exposure meters the whole frame, so a porch light or a
+//! bright sky leaves the driveway too dark for the detector at night. With
+//! `AUTO_EXPOSURE` enabled, capture switches exposure to manual and steers it
+//! from the brightness and contrast of the metering zones instead: exposure
+//! first (up to a blur limit), then gain once exposure is maxed out.
+
+use anyhow::{Context, Result, ensure};
+use common::{Rect, get_env, get_env_opt};
+use v4l::{
+    Device,
+    control::{Control, Value},
+};
+
+// V4L2 control IDs (from videodev2.h)
+const V4L2_CID_EXPOSURE_AUTO: u32 = 0x009a0901;
+const V4L2_CID_EXPOSURE_ABSOLUTE: u32 = 0x009a0902;
+const V4L2_CID_GAIN: u32 = 0x00980913;
+
+const V4L2_EXPOSURE_MANUAL: i64 = 1;
+
+/// Largest exposure change applied in one step
+const MAX_STEP_FACTOR: f32 = 2.0;
+/// Gain moves in this many steps across its range
+const GAIN_STEPS: i64 = 16;
+/// Luma samples taken per zone row and column
+const SAMPLES_PER_SIDE: u32 = 64;
+
+#[derive(Debug, Clone)]
+pub struct AutoExposureConfig {
+    /// Mean luma (0-255) kept between these bounds
+    pub min_brightness: f32,
+    pub max_brightness: f32,
+    /// Luma standard deviation below which a dim scene counts as underexposed
+    pub min_contrast: f32,
+    /// Metering zones, the whole frame when empty
+    pub zones: Vec<Rect>,
+    /// Exposure upper limit in V4L2 units (100µs), bounds motion blur
+    pub max_exposure: i64,
+    /// Gain upper limit, the camera's maximum when unset
+    pub max_gain: Option<i64>,
+    /// Frames between two adjustments, so the camera settles first
+    pub interval_frames: u64,
+}
+
+impl AutoExposureConfig {
+    /// Enabled when `AUTO_EXPOSURE` is true
+    pub fn from_env() -> Result<Option<Self>> {
+        if !get_env("AUTO_EXPOSURE", false) {
+            return Ok(None);
+        }
+
+        let zones = get_env("AUTO_EXPOSURE_ZONES", String::new())
+            .split(';')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|zone| {
+                Rect::parse(zone)
+                    .with_context(|| format!("Invalid AUTO_EXPOSURE_ZONES entry {zone:?}"))
+            })
+            .collect::<Result<_>>()?;
+
+        let config = Self {
+            min_brightness: get_env("AUTO_EXPOSURE_MIN_BRIGHTNESS", 80.0),
+            max_brightness: get_env("AUTO_EXPOSURE_MAX_BRIGHTNESS", 160.0),
+            min_contrast: get_env("AUTO_EXPOSURE_MIN_CONTRAST", 20.0),
+            zones,
+            max_exposure: get_env("AUTO_EXPOSURE_MAX_EXPOSURE", 200),
+            max_gain: get_env_opt("AUTO_EXPOSURE_MAX_GAIN"),
+            interval_frames: get_env("AUTO_EXPOSURE_INTERVAL_FRAMES", 10u64).max(1),
+        };
+        ensure!(
+            config.min_brightness < config.max_brightness,
+            "AUTO_EXPOSURE_MIN_BRIGHTNESS must be below AUTO_EXPOSURE_MAX_BRIGHTNESS"
+        );
+        Ok(Some(config))
+    }
+
+    fn target_brightness(&self) -> f32 {
+        (self.min_brightness + self.max_brightness) / 2.0
+    }
+}
+
+/// Luma statistics of the metering zones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    /// Mean luma, 0-255
+    pub brightness: f32,
+    /// Luma standard deviation
+    pub contrast: f32,
+}
+
+/// Sample the luma of an RGB frame inside `zones` (the whole frame when empty)
+pub fn measure(rgb: &[u8], width: u32, height: u32, zones: &[Rect]) -> FrameStats {
+    let zones = if zones.is_empty() {
+        &[Rect::FULL_FRAME][..]
+    } else {
+        zones
+    };
+
+    let (mut count, mut sum, mut sum_sq) = (0u64, 0f64, 0f64);
+    for zone in zones {
+        let (x1, y1, x2, y2) = zone.pixels(width, height);
+        let step_x = ((x2 - x1) / SAMPLES_PER_SIDE).max(1);
+        let step_y = ((y2 - y1) / SAMPLES_PER_SIDE).max(1);
+
+        for y in (y1..y2).step_by(step_y as usize) {
+            for x in (x1..x2).step_by(step_x as usize) {
+                let i = ((y * width + x) * 3) as usize;
+                let Some(p) = rgb.get(i..i + 3) else {
+                    continue;
+                };
+                // BT.601 luma
+                let luma = 0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64;
+                count += 1;
+                sum += luma;
+                sum_sq += luma * luma;
+            }
+        }
+    }
+
+    if count == 0 {
+        return FrameStats {
+            brightness: 0.0,
+            contrast: 0.0,
+        };
+    }
+    let mean = sum / count as f64;
+    let variance = (sum_sq / count as f64 - mean * mean).max(0.0);
+    FrameStats {
+        brightness: mean as f32,
+        contrast: variance.sqrt() as f32,
+    }
+}
+
+/// Integer camera control bounded to a range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Setting {
+    value: i64,
+    min: i64,
+    max: i64,
+}
+
+impl Setting {
+    fn new(value: i64, min: i64, max: i64) -> Self {
+        let max = max.max(min);
+        Self {
+            value: value.clamp(min, max),
+            min,
+            max,
+        }
+    }
+
+    /// Multiply the value, moving by at least one unit. Returns true if it changed.
+    fn scale(&mut self, factor: f32) -> bool {
+        let scaled = (self.value as f32 * factor).round() as i64;
+        let next = if factor > 1.0 {
+            scaled.max(self.value + 1)
+        } else {
+            scaled.min(self.value - 1)
+        };
+        self.set(next)
+    }
+
+    /// Move one gain step up (`direction` 1) or down (-1). Returns true if it changed.
+    fn nudge(&mut self, direction: i64) -> bool {
+        let step = ((self.max - self.min) / GAIN_STEPS).max(1);
+        self.set(self.value + direction * step)
+    }
+
+    fn set(&mut self, value: i64) -> bool {
+        let value = value.clamp(self.min, self.max);
+        let changed = value != self.value;
+        self.value = value;
+        changed
+    }
+}
+
+/// Exposure and gain controller
+#[derive(Debug)]
+pub struct AutoExposure {
+    config: AutoExposureConfig,
+    exposure: Setting,
+    gain: Option<Setting>,
+    frames_since_adjustment: u64,
+}
+
+impl AutoExposure {
+    /// Switch the camera to manual exposure and take over from its current settings
+    pub fn attach(device: &Device, config: AutoExposureConfig) -> Result<Self> {
+        let controls = device
+            .query_controls()
+            .context("Failed to query camera controls")?;
+        let exposure = controls
+            .iter()
+            .find(|c| c.id == V4L2_CID_EXPOSURE_ABSOLUTE)
+            .context("Camera has no absolute exposure control")?;
+        let gain = controls.iter().find(|c| c.id == V4L2_CID_GAIN);
+
+        if controls.iter().any(|c| c.id == V4L2_CID_EXPOSURE_AUTO) {
+            device
+                .set_control(Control {
+                    id: V4L2_CID_EXPOSURE_AUTO,
+                    value: Value::Integer(V4L2_EXPOSURE_MANUAL),
+                })
+                .context("Failed to switch exposure to manual")?;
+        }
+
+        let current = |id: u32, default: i64| match device.control(id) {
+            Ok(Control {
+                value: Value::Integer(v),
+                ..
+            }) => v,
+            _ => default,
+        };
+
+        let exposure = Setting::new(
+            current(exposure.id, exposure.default),
+            exposure.minimum.max(1),
+            config.max_exposure.min(exposure.maximum),
+        );
+        let gain = gain.map(|g| {
+            let max = config.max_gain.map_or(g.maximum, |m| m.min(g.maximum));
+            Setting::new(current(g.id, g.default), g.minimum, max)
+        });
+
+        tracing::info!(
+            exposure = exposure.value,
+            max_exposure = exposure.max,
+            gain = gain.map(|g| g.value),
+            max_gain = gain.map(|g| g.max),
+            zones = config.zones.len(),
+            "Auto exposure loop enabled"
+        );
+
+        let auto_exposure = Self {
+            config,
+            exposure,
+            gain,
+            frames_since_adjustment: 0,
+        };
+        // Starting values may have been clamped to the configured limits
+        auto_exposure.apply(device);
+        Ok(auto_exposure)
+    }
+
+    /// Whether the next frame is due for metering, for callers that only decode
+    /// one frame per interval
+    pub fn wants_frame(&self) -> bool {
+        (self.frames_since_adjustment + 1).is_multiple_of(self.config.interval_frames)
+    }
+
+    /// Count a frame that was not decoded toward the measurement interval
+    pub fn skip(&mut self) {
+        self.frames_since_adjustment += 1;
+    }
+
+    /// Measure a frame and retune the camera when it drifted out of range
+    pub fn update(&mut self, device: &Device, rgb: &[u8], width: u32, height: u32) {
+        self.frames_since_adjustment += 1;
+        if self.frames_since_adjustment < self.config.interval_frames {
+            return;
+        }
+
+        let stats = measure(rgb, width, height, &self.config.zones);
+        if self.step(stats) {
+            self.frames_since_adjustment = 0;
+            tracing::debug!(
+                brightness = stats.brightness,
+                contrast = stats.contrast,
+                exposure = self.exposure.value,
+                gain = self.gain.map(|g| g.value),
+                "Adjusted exposure"
+            );
+            self.apply(device);
+        }
+    }
+
+    /// Move the settings toward the target range. Returns true if they changed.
+    fn step(&mut self, stats: FrameStats) -> bool {
+        let target = self.config.target_brightness();
+        let underexposed = stats.brightness < self.config.min_brightness
+            || (stats.contrast < self.config.min_contrast && stats.brightness < target);
+
+        if underexposed {
+            let factor = (target / stats.brightness.max(1.0)).min(MAX_STEP_FACTOR);
+            self.exposure.scale(factor) || self.gain.as_mut().is_some_and(|g| g.nudge(1))
+        } else if stats.brightness > self.config.max_brightness {
+            // Drop gain first: it adds noise, exposure only blur
+            let factor = (target / stats.brightness).max(1.0 / MAX_STEP_FACTOR);
+            self.gain.as_mut().is_some_and(|g| g.nudge(-1)) || self.exposure.scale(factor)
+        } else {
+            false
+        }
+    }
+
+    fn apply(&self, device: &Device) {
+        let mut controls = vec![(V4L2_CID_EXPOSURE_ABSOLUTE, self.exposure.value)];
+        controls.extend(self.gain.map(|g| (V4L2_CID_GAIN, g.value)));
+
+        for (id, value) in controls {
+            if let Err(e) = device.set_control(Control {
+                id,
+                value: Value::Integer(value),
+            }) {
+                tracing::warn!(control = id, value, error = %e, "Failed to set camera control");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AutoExposureConfig {
+        AutoExposureConfig {
+            min_brightness: 80.0,
+            max_brightness: 160.0,
+            min_contrast: 20.0,
+            zones: Vec::new(),
+            max_exposure: 200,
+            max_gain: None,
+            interval_frames: 1,
+        }
+    }
+
+    fn controller(exposure: i64, gain: i64) -> AutoExposure {
+        AutoExposure {
+            config: config(),
+            exposure: Setting::new(exposure, 1, 200),
+            gain: Some(Setting::new(gain, 0, 64)),
+            frames_since_adjustment: 0,
+        }
+    }
+
+    fn stats(brightness: f32, contrast: f32) -> FrameStats {
+        FrameStats {
+            brightness,
+            contrast,
+        }
+    }
+
+    #[test]
+    fn test_measure_only_samples_zones() {
+        // Left half black, right half white
+        let (width, height) = (100, 10);
+        let mut rgb = vec![0u8; (width * height * 3) as usize];
+        for y in 0..height {
+            for x in width / 2..width {
+                let i = ((y * width + x) * 3) as usize;
+                rgb[i..i + 3].fill(255);
+            }
+        }
+
+        let right = Rect::parse("0.5,0,1,1").unwrap();
+        let measured = measure(&rgb, width, height, &[right]);
+        assert!((measured.brightness - 255.0).abs() < 0.5);
+        assert!(measured.contrast < 0.5);
+
+        let whole = measure(&rgb, width, height, &[]);
+        assert!((whole.brightness - 127.5).abs() < 1.0);
+        assert!(whole.contrast > 120.0);
+    }
+
+    #[test]
+    fn test_dark_scene_raises_exposure_then_gain() {
+        let mut ae = controller(150, 0);
+
+        assert!(ae.step(stats(40.0, 10.0)));
+        assert_eq!(ae.exposure.value, 200);
+        assert_eq!(ae.gain.unwrap().value, 0);
+
+        // Exposure capped at the blur limit, gain takes over
+        assert!(ae.step(stats(60.0, 10.0)));
+        assert_eq!(ae.exposure.value, 200);
+        assert_eq!(ae.gain.unwrap().value, 4);
+    }
+
+    #[test]
+    fn test_bright_scene_lowers_gain_then_exposure() {
+        let mut ae = controller(200, 4);
+
+        assert!(ae.step(stats(220.0, 50.0)));
+        assert_eq!(ae.gain.unwrap().value, 0);
+        assert_eq!(ae.exposure.value, 200);
+
+        assert!(ae.step(stats(220.0, 50.0)));
+        assert_eq!(ae.exposure.value, 109);
+    }
+
+    #[test]
+    fn test_in_range_scene_is_left_alone() {
+        let mut ae = controller(100, 8);
+
+        assert!(!ae.step(stats(120.0, 40.0)));
+        // Dim but contrasted enough
+        assert!(!ae.step(stats(90.0, 30.0)));
+        // Flat and dim: underexposed even within range
+        assert!(ae.step(stats(90.0, 5.0)));
+    }
+
+    #[test]
+    fn test_settings_stop_at_limits() {
+        let mut ae = controller(1, 0);
+
+        assert!(!ae.step(stats(250.0, 50.0)));
+        assert_eq!(ae.exposure.value, 1);
+    }
+
+    #[test]
+    fn test_wants_one_frame_per_interval() {
+        let mut ae = controller(100, 0);
+        ae.config.interval_frames = 3;
+
+        let wanted: Vec<bool> = (0..7)
+            .map(|_| {
+                let wanted = ae.wants_frame();
+                ae.skip();
+                wanted
+            })
+            .collect();
+        assert_eq!(wanted, [false, false, true, false, false, true, false]);
+    }
+}
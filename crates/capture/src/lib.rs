@@ -1,8 +1,16 @@
+pub mod burst;
 pub mod camera;
 pub mod config;
+pub mod controls;
 pub mod decoder;
 pub mod device;
+pub mod downscale;
+pub mod exposure;
 pub mod logging;
+pub mod metrics;
+pub mod night_still;
+#[cfg(feature = "jetson")]
+pub mod nvmm;
 pub mod pacing;
 pub mod sink;
 pub mod source;
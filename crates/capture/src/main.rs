@@ -1,7 +1,7 @@
 use anyhow::Context;
 use bridge::{BridgeSemaphore, SemaphoreType, SentryControl};
 use capture::{camera::Camera, config::CameraConfig, logging::setup_logging};
-use common::TelemetryGuard;
+use common::{TelemetryGuard, Watchdog};
 use signal_hook::{
     consts::{SIGINT, SIGTERM},
     flag,
@@ -10,6 +10,13 @@ use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
 
 fn main() -> anyhow::Result<()> {
+    common::load_profile()?;
+    if common::config_check::requested() {
+        return common::config_check::run(|check| {
+            CameraConfig::from_env()?.check(check);
+            Ok(())
+        });
+    }
     let config = CameraConfig::from_env()?;
 
     // TelemetryGuard requires a Tokio runtime for async OTLP exporters.
@@ -32,6 +39,7 @@ fn main() -> anyhow::Result<()> {
 
     tracing::info!("Signal handlers registered (SIGTERM, SIGINT)");
 
+    let scheduling = config.scheduling.clone();
     let mut camera = Camera::build(config)
         .context("Failed to initialize camera - check V4L2 device availability")?;
 
@@ -45,7 +53,16 @@ fn main() -> anyhow::Result<()> {
 
     tracing::info!("Mode change semaphore connected");
 
-    match camera.run(&shutdown, &sentry_control, &mode_semaphore) {
+    // The capture loop runs on this thread
+    scheduling.apply();
+
+    let mut watchdog = Watchdog::from_env();
+    watchdog.ready();
+
+    let result = camera.run(&shutdown, &sentry_control, &mode_semaphore, &mut watchdog);
+    watchdog.stopping();
+
+    match result {
         Ok(_) => {
             tracing::info!("Camera capture stopped gracefully");
             Ok(())
@@ -0,0 +1,69 @@
+//! OpenTelemetry instruments of the capture service
+//!
+//! Exported through the OTLP pipeline set up by `common::TelemetryGuard`; without
+//! an endpoint the global meter is a no-op.
+
+use opentelemetry::{
+    global,
+    metrics::{Counter, Gauge},
+};
+
+pub struct CaptureMetrics {
+    dequeue_timeouts: Counter<u64>,
+    stream_restarts: Counter<u64>,
+    resolution_changes: Counter<u64>,
+    write_gate_overruns: Counter<u64>,
+    clock_drift: Gauge<f64>,
+}
+
+impl CaptureMetrics {
+    pub fn new(meter_name: &'static str) -> Self {
+        let meter = global::meter(meter_name);
+
+        Self {
+            dequeue_timeouts: meter
+                .u64_counter("capture_dequeue_timeouts_total")
+                .with_description("Waits for a camera buffer that timed out")
+                .build(),
+            stream_restarts: meter
+                .u64_counter("capture_stream_restarts_total")
+                .with_description("Capture streams re-created after the driver stalled")
+                .build(),
+            resolution_changes: meter
+                .u64_counter("capture_resolution_changes_total")
+                .with_description("Frame sizes the driver switched to on a re-created stream")
+                .build(),
+            write_gate_overruns: meter
+                .u64_counter("capture_write_gate_overruns_total")
+                .with_description("Frames replaced before a lossless reader consumed them")
+                .build(),
+            clock_drift: meter
+                .f64_gauge("capture_clock_drift_seconds")
+                .with_description(
+                    "Wall clock movement against the monotonic clock since the pipeline epoch",
+                )
+                .with_unit("s")
+                .build(),
+        }
+    }
+
+    pub fn record_dequeue_timeout(&self) {
+        self.dequeue_timeouts.add(1, &[]);
+    }
+
+    pub fn record_stream_restart(&self) {
+        self.stream_restarts.add(1, &[]);
+    }
+
+    pub fn record_resolution_change(&self) {
+        self.resolution_changes.add(1, &[]);
+    }
+
+    pub fn record_write_gate_overruns(&self, overruns: u64) {
+        self.write_gate_overruns.add(overruns, &[]);
+    }
+
+    pub fn record_clock_drift(&self, drift_ns: i64) {
+        self.clock_drift.record(drift_ns as f64 / 1e9, &[]);
+    }
+}
@@ -0,0 +1,331 @@
+//! Long exposure stills at night
+//!
+//! The exposure the stream runs at is kept short so moving people stay sharp,
+//! which leaves a dark scene grainy and dim. A still requested with
+//! `SnapshotControl::request_night_still` (the controller does on alarm entry
+//! with `NIGHT_STILL_ON_ALARM`) is taken differently when the scene is darker
+//! than `NIGHT_STILL_MAX_BRIGHTNESS`: capture switches the camera to manual
+//! exposure at `NIGHT_STILL_EXPOSURE` (and `NIGHT_STILL_GAIN`), lets
+//! `NIGHT_STILL_SETTLE_FRAMES` frames go by for the sensor to catch up, writes the
+//! next one as the still and puts the previous settings back. Auto exposure is
+//! paused meanwhile. The frames taken in between still reach the stream, brighter
+//! and blurrier than the others.
+//!
+//! In daylight the still is taken from the next frame as any other.
+
+use crate::exposure;
+use anyhow::{Context, Result, ensure};
+use common::{ConfigCheck, get_env, get_env_opt};
+use v4l::{
+    Device,
+    control::{Control, Value},
+};
+
+// V4L2 control IDs (from videodev2.h)
+const V4L2_CID_EXPOSURE_AUTO: u32 = 0x009a0901;
+const V4L2_CID_EXPOSURE_ABSOLUTE: u32 = 0x009a0902;
+const V4L2_CID_GAIN: u32 = 0x00980913;
+
+const V4L2_EXPOSURE_MANUAL: i64 = 1;
+
+#[derive(Debug, Clone)]
+pub struct NightStillConfig {
+    /// Exposure of the still in V4L2 units (100µs)
+    pub exposure: i64,
+    /// Gain of the still, left as is when unset
+    pub gain: Option<i64>,
+    /// Mean luma (0-255) below which the scene counts as dark
+    pub max_brightness: f32,
+    /// Frames skipped after switching exposure, covering the queued buffers
+    pub settle_frames: u32,
+}
+
+impl NightStillConfig {
+    /// Enabled when `NIGHT_STILL_EXPOSURE` is set
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(exposure) = get_env_opt("NIGHT_STILL_EXPOSURE") else {
+            return Ok(None);
+        };
+        ensure!(exposure > 0, "NIGHT_STILL_EXPOSURE must be above 0");
+        Ok(Some(Self {
+            exposure,
+            gain: get_env_opt("NIGHT_STILL_GAIN"),
+            max_brightness: get_env("NIGHT_STILL_MAX_BRIGHTNESS", 50.0),
+            settle_frames: get_env("NIGHT_STILL_SETTLE_FRAMES", 6),
+        }))
+    }
+
+    pub fn check(&self, check: &mut ConfigCheck, buffer_count: u32) {
+        check.in_range(
+            "NIGHT_STILL_MAX_BRIGHTNESS",
+            self.max_brightness,
+            1.0..=255.0,
+        );
+        check.in_range("NIGHT_STILL_SETTLE_FRAMES", self.settle_frames, 1..=60);
+        check.ensure(self.settle_frames > buffer_count, || {
+            format!(
+                "NIGHT_STILL_SETTLE_FRAMES ({}) does not cover the {buffer_count} V4L2 buffers, \
+                 the still may be taken at the old exposure",
+                self.settle_frames
+            )
+        });
+    }
+}
+
+/// Where a night still is at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Idle,
+    /// Long exposure set, frames left before the still
+    Settling(u32),
+    /// The current frame is the still
+    Capturing,
+}
+
+/// What the camera does with the current frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    /// Nothing to do, a pending still is taken as usual
+    Pass,
+    /// Switch to the long exposure and hold the still back
+    Expose,
+    /// Hold the still back while the exposure settles
+    Settle,
+    /// Take the still from this frame
+    Capture,
+    /// The request went away, put the settings back
+    Abort,
+}
+
+/// Control values saved while the long exposure is set
+#[derive(Debug, Default)]
+struct Saved {
+    auto: Option<i64>,
+    exposure: Option<i64>,
+    gain: Option<i64>,
+}
+
+#[derive(Debug)]
+pub struct NightStill {
+    config: NightStillConfig,
+    phase: Phase,
+    has_auto: bool,
+    has_gain: bool,
+    saved: Saved,
+}
+
+impl NightStill {
+    /// Check the camera has the controls a long exposure needs
+    pub fn attach(device: &Device, mut config: NightStillConfig) -> Result<Self> {
+        let controls = device
+            .query_controls()
+            .context("Failed to query camera controls")?;
+        let exposure = controls
+            .iter()
+            .find(|c| c.id == V4L2_CID_EXPOSURE_ABSOLUTE)
+            .context("Camera has no absolute exposure control")?;
+        if config.exposure > exposure.maximum {
+            tracing::warn!(
+                exposure = config.exposure,
+                maximum = exposure.maximum,
+                "NIGHT_STILL_EXPOSURE above what the camera supports, clamped"
+            );
+            config.exposure = exposure.maximum;
+        }
+        let gain = controls.iter().find(|c| c.id == V4L2_CID_GAIN);
+        if let (Some(value), Some(gain)) = (config.gain, gain) {
+            config.gain = Some(value.clamp(gain.minimum, gain.maximum));
+        }
+
+        tracing::info!(
+            exposure = config.exposure,
+            gain = config.gain,
+            max_brightness = config.max_brightness,
+            "Night stills enabled"
+        );
+        Ok(Self {
+            has_auto: controls.iter().any(|c| c.id == V4L2_CID_EXPOSURE_AUTO),
+            has_gain: gain.is_some(),
+            config,
+            phase: Phase::Idle,
+            saved: Saved::default(),
+        })
+    }
+
+    /// Whether the camera runs at the long exposure, auto exposure stays off meanwhile
+    pub fn active(&self) -> bool {
+        self.phase != Phase::Idle
+    }
+
+    /// Called before a frame is written, with `pending` whether a night still is
+    /// requested and `rgb` the decoded frame, needed when one is. Returns whether
+    /// the still is held back from this frame.
+    pub fn before_frame(
+        &mut self,
+        device: &Device,
+        pending: bool,
+        rgb: Option<(&[u8], u32, u32)>,
+    ) -> bool {
+        // Only metered when a still could start
+        let brightness = rgb
+            .filter(|_| pending && !self.active())
+            .map(|(rgb, width, height)| exposure::measure(rgb, width, height, &[]).brightness);
+        match self.step(pending, brightness) {
+            Step::Pass | Step::Capture => false,
+            Step::Expose => {
+                tracing::info!(
+                    brightness,
+                    exposure = self.config.exposure,
+                    "Dark scene, switching to a long exposure for the still"
+                );
+                self.expose(device);
+                true
+            }
+            Step::Settle => true,
+            Step::Abort => {
+                self.restore(device);
+                false
+            }
+        }
+    }
+
+    /// Called once the frame is written, puts the settings back after the still
+    pub fn after_frame(&mut self, device: &Device) {
+        if self.phase == Phase::Capturing {
+            tracing::info!("Night still taken, exposure restored");
+            self.restore(device);
+        }
+    }
+
+    /// Put the settings back if a still is under way, e.g. on shutdown
+    pub fn cancel(&mut self, device: &Device) {
+        if self.active() {
+            self.restore(device);
+        }
+    }
+
+    fn step(&mut self, pending: bool, brightness: Option<f32>) -> Step {
+        match self.phase {
+            Phase::Idle => {
+                if !pending || brightness.is_none_or(|b| b >= self.config.max_brightness) {
+                    return Step::Pass;
+                }
+                self.phase = Phase::Settling(self.config.settle_frames);
+                Step::Expose
+            }
+            _ if !pending => Step::Abort,
+            Phase::Settling(left) if left > 1 => {
+                self.phase = Phase::Settling(left - 1);
+                Step::Settle
+            }
+            Phase::Settling(_) | Phase::Capturing => {
+                self.phase = Phase::Capturing;
+                Step::Capture
+            }
+        }
+    }
+
+    fn expose(&mut self, device: &Device) {
+        let current = |id: u32| match device.control(id) {
+            Ok(Control {
+                value: Value::Integer(v),
+                ..
+            }) => Some(v),
+            _ => None,
+        };
+        self.saved = Saved {
+            auto: self
+                .has_auto
+                .then(|| current(V4L2_CID_EXPOSURE_AUTO))
+                .flatten(),
+            exposure: current(V4L2_CID_EXPOSURE_ABSOLUTE),
+            gain: self.has_gain.then(|| current(V4L2_CID_GAIN)).flatten(),
+        };
+
+        let mut controls = Vec::new();
+        if self.has_auto {
+            controls.push((V4L2_CID_EXPOSURE_AUTO, V4L2_EXPOSURE_MANUAL));
+        }
+        controls.push((V4L2_CID_EXPOSURE_ABSOLUTE, self.config.exposure));
+        if self.has_gain {
+            controls.extend(self.config.gain.map(|gain| (V4L2_CID_GAIN, gain)));
+        }
+        set_controls(device, &controls);
+    }
+
+    fn restore(&mut self, device: &Device) {
+        self.phase = Phase::Idle;
+        let saved = std::mem::take(&mut self.saved);
+        // Exposure and gain while still in manual mode, the camera's own mode last
+        let controls: Vec<_> = [
+            (V4L2_CID_EXPOSURE_ABSOLUTE, saved.exposure),
+            (V4L2_CID_GAIN, saved.gain),
+            (V4L2_CID_EXPOSURE_AUTO, saved.auto),
+        ]
+        .into_iter()
+        .filter_map(|(id, value)| value.map(|value| (id, value)))
+        .collect();
+        set_controls(device, &controls);
+    }
+}
+
+fn set_controls(device: &Device, controls: &[(u32, i64)]) {
+    for &(id, value) in controls {
+        if let Err(e) = device.set_control(Control {
+            id,
+            value: Value::Integer(value),
+        }) {
+            tracing::warn!(control = id, value, error = %e, "Failed to set camera control");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn night_still(settle_frames: u32) -> NightStill {
+        NightStill {
+            config: NightStillConfig {
+                exposure: 3000,
+                gain: None,
+                max_brightness: 50.0,
+                settle_frames,
+            },
+            phase: Phase::Idle,
+            has_auto: true,
+            has_gain: true,
+            saved: Saved::default(),
+        }
+    }
+
+    #[test]
+    fn test_dark_scene_settles_then_captures() {
+        let mut still = night_still(3);
+
+        assert_eq!(still.step(true, Some(20.0)), Step::Expose);
+        assert!(still.active());
+        assert_eq!(still.step(true, Some(90.0)), Step::Settle);
+        assert_eq!(still.step(true, Some(90.0)), Step::Settle);
+        assert_eq!(still.step(true, Some(90.0)), Step::Capture);
+        assert_eq!(still.phase, Phase::Capturing);
+    }
+
+    #[test]
+    fn test_bright_or_unrequested_frames_pass() {
+        let mut still = night_still(3);
+
+        assert_eq!(still.step(false, Some(20.0)), Step::Pass);
+        assert_eq!(still.step(true, Some(120.0)), Step::Pass);
+        assert_eq!(still.step(true, None), Step::Pass);
+        assert!(!still.active());
+    }
+
+    #[test]
+    fn test_request_gone_while_settling_aborts() {
+        let mut still = night_still(3);
+
+        assert_eq!(still.step(true, Some(20.0)), Step::Expose);
+        assert_eq!(still.step(false, None), Step::Abort);
+    }
+}
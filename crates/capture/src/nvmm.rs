@@ -0,0 +1,82 @@
+//! Jetson NVMM export of raw camera frames
+//!
+//! Each YUYV frame is copied once from the V4L2 buffer into the next surface of a
+//! small NVMM ring, whose descriptors inference imports at startup. Inference then
+//! converts, letterboxes and normalizes the frame on the VIC and GPU instead of
+//! reading the decoded RGB copy from the frame buffer.
+//!
+//! The copy is done by the CPU: the V4L2 stream uses the UVC driver's mmap buffers,
+//! which live in memory the VIC cannot address, so the frame has to be moved into
+//! NVMM once. Importing the camera buffers directly would need a driver exporting
+//! NVMM-backed dmabufs, which UVC cameras do not have.
+//!
+//! Readers hold the surface they transform on the NVMM write gate, and capture
+//! waits at most `RELEASE_TIMEOUT` for its release before overwriting it. A reader
+//! that only holds a surface after `RING_SIZE` more frames were written still
+//! reads a newer frame.
+
+use anyhow::{Context, Result};
+use bridge::nvmm::{NvmmFormat, NvmmSurface, surface_hold};
+use bridge::{SurfaceExporter, WriteGate, paths};
+use std::time::Duration;
+
+const RING_SIZE: usize = 4;
+
+/// Longest wait for a reader to release the surface about to be overwritten,
+/// well above one VIC transform
+const RELEASE_TIMEOUT: Duration = Duration::from_millis(20);
+
+pub struct NvmmRing {
+    surfaces: Vec<NvmmSurface>,
+    next: usize,
+    width: u32,
+    gate: WriteGate,
+    _exporter: SurfaceExporter,
+}
+
+impl NvmmRing {
+    pub fn new(width: u32, height: u32) -> Result<Self> {
+        let surfaces = (0..RING_SIZE)
+            .map(|_| NvmmSurface::allocate(width, height, NvmmFormat::Yuyv))
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to allocate NVMM surfaces")?;
+
+        let fds = surfaces
+            .iter()
+            .map(NvmmSurface::export_fd)
+            .collect::<Result<Vec<_>, _>>()?;
+        let exporter = SurfaceExporter::serve(paths::NVMM_SURFACES_SOCKET_PATH, fds)
+            .context("Failed to export NVMM surfaces")?;
+        let gate = WriteGate::new(paths::NVMM_WRITE_GATE_PATH, RELEASE_TIMEOUT)
+            .context("Failed to open the NVMM write gate")?;
+
+        tracing::info!(
+            surfaces = RING_SIZE,
+            width,
+            height,
+            "Exporting YUYV frames as NVMM surfaces"
+        );
+
+        Ok(Self {
+            surfaces,
+            next: 0,
+            width,
+            gate,
+            _exporter: exporter,
+        })
+    }
+
+    /// Copy a raw YUYV frame into the next surface, returning its index
+    pub fn write(&mut self, raw: &[u8]) -> Result<u32> {
+        let index = self.next;
+        if self.gate.wait_released(surface_hold(index as u32)) > 0 {
+            tracing::debug!(surface = index, "NVMM surface still held, overwriting it");
+        }
+        let surface = &mut self.surfaces[index];
+        let stride = raw.len() / surface.height() as usize;
+        surface.write_rows(raw, stride, self.width as usize * 2)?;
+
+        self.next = (index + 1) % RING_SIZE;
+        Ok(index as u32)
+    }
+}
@@ -3,18 +3,21 @@ use std::time::Duration;
 
 pub struct CapturePacing {
     standby: Duration,
+    elevated: Duration,
     alarmed: Duration,
     current: Duration,
     mode: SentryMode,
 }
 
 impl CapturePacing {
-    pub fn new(max_fps: f64, sentry_fps: f64) -> Self {
+    pub fn new(max_fps: f64, sentry_fps: f64, elevated_fps: f64) -> Self {
         let standby = Duration::from_secs_f64(1.0 / sentry_fps);
+        let elevated = Duration::from_secs_f64(1.0 / elevated_fps.min(max_fps).max(sentry_fps));
         let alarmed = Duration::from_secs_f64(1.0 / max_fps);
 
         Self {
             standby,
+            elevated,
             alarmed,
             current: standby,
             mode: SentryMode::Standby,
@@ -30,6 +33,7 @@ impl CapturePacing {
         self.mode = new_mode;
         self.current = match new_mode {
             SentryMode::Standby => self.standby,
+            SentryMode::Elevated => self.elevated,
             SentryMode::Alarmed => self.alarmed,
         };
         true
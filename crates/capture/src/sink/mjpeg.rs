@@ -0,0 +1,75 @@
+//! Local MJPEG recording
+//!
+//! Frames are appended to the file as concatenated JPEGs, which `ffplay -f mjpeg`
+//! and most NVRs read as is. The file is opened on the first frame and reopened
+//! after a failed write. Rotate it with `logrotate`'s `copytruncate`, writes
+//! always go to its end.
+
+use super::{CapturedFrame, FrameSink};
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+pub struct MjpegFileSink {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl MjpegFileSink {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, file: None }
+    }
+}
+
+impl FrameSink for MjpegFileSink {
+    fn name(&self) -> &str {
+        "mjpeg"
+    }
+
+    fn write(&mut self, frame: &CapturedFrame<'_>) -> Result<()> {
+        let jpeg = frame.to_jpeg()?;
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => self.file.insert(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)
+                    .with_context(|| format!("Failed to open {}", self.path.display()))?,
+            ),
+        };
+        if let Err(e) = file.write_all(jpeg) {
+            self.file = None;
+            return Err(e).with_context(|| format!("Failed to write {}", self.path.display()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_appends_jpeg_frames() {
+        let path = std::env::temp_dir().join(format!("capture_sink_{}.mjpeg", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut sink = MjpegFileSink::new(path.clone());
+
+        for (frame_no, jpeg) in [[0xFF, 0xD8, 1, 0xFF, 0xD9], [0xFF, 0xD8, 2, 0xFF, 0xD9]]
+            .iter()
+            .enumerate()
+        {
+            let mut frame = CapturedFrame::new(0, frame_no as u64, 640, 480);
+            frame.jpeg = Some(jpeg);
+            sink.write(&frame).unwrap();
+        }
+
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            [0xFF, 0xD8, 1, 0xFF, 0xD9, 0xFF, 0xD8, 2, 0xFF, 0xD9]
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,314 @@
+//! Outputs of the capture loop
+//!
+//! Every captured frame goes to the bridge's shared memory buffer, which the
+//! inference pipeline reads, and to the sinks listed in `CAPTURE_SINKS`:
+//! - `shm`: the shared memory buffer, always written whether listed or not
+//! - `mjpeg:<path>`: JPEG frames appended to a local MJPEG file
+//! - `rtsp:<url>`, or the bare `rtsp://` URL: H.264 pushed to an RTSP server
+//!   (e.g. an NVR) through ffmpeg
+//!
+//! e.g. `CAPTURE_SINKS=shm,rtsp://nvr:8554/door` feeds an NVR and the detection
+//! pipeline from the same camera. The extra sinks fail independently: a failing
+//! one is logged and skipped for a while, the frame still reaches the others.
+
+mod mjpeg;
+mod rtsp;
+mod shm;
+
+pub use mjpeg::MjpegFileSink;
+pub use rtsp::RtspPushSink;
+pub use shm::ShmSink;
+
+use anyhow::{Context, Result, bail};
+use common::{MonotonicNs, log_throttle};
+use std::cell::OnceCell;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Quality of the JPEG sent to sinks when the camera delivers raw frames
+const JPEG_QUALITY: i32 = 85;
+
+/// Longest time a failing sink is skipped before it is retried
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A destination of captured frames
+pub trait FrameSink: Send {
+    /// Name used in logs
+    fn name(&self) -> &str;
+
+    fn write(&mut self, frame: &CapturedFrame<'_>) -> Result<()>;
+}
+
+/// A frame handed to every sink
+pub struct CapturedFrame<'a> {
+    pub camera_id: u32,
+    pub frame_no: u64,
+    /// Native size of the frame
+    pub width: u32,
+    pub height: u32,
+    /// Decoded pixels, `None` for JPEG passthrough frames that were not decoded
+    pub rgb: Option<&'a [u8]>,
+    /// The frame as the camera delivered it, for MJPEG cameras
+    pub jpeg: Option<&'a [u8]>,
+    /// Capture time, the time it is written when `None`
+    pub timestamp: Option<MonotonicNs>,
+    /// Position of the frame in an alarm burst
+    pub burst: Option<schema::Burst>,
+    /// NVMM surface holding the raw copy of the frame
+    #[cfg(feature = "jetson")]
+    pub nvmm_surface: Option<u32>,
+    pub trace: Option<&'a schema::TraceContext>,
+    /// `rgb` compressed by the first sink asking for a JPEG
+    encoded: OnceCell<Vec<u8>>,
+}
+
+impl<'a> CapturedFrame<'a> {
+    pub fn new(camera_id: u32, frame_no: u64, width: u32, height: u32) -> Self {
+        Self {
+            camera_id,
+            frame_no,
+            width,
+            height,
+            rgb: None,
+            jpeg: None,
+            timestamp: None,
+            burst: None,
+            #[cfg(feature = "jetson")]
+            nvmm_surface: None,
+            trace: None,
+            encoded: OnceCell::new(),
+        }
+    }
+
+    /// The frame as JPEG: the camera's own, or the pixels compressed once for
+    /// every sink
+    pub fn to_jpeg(&self) -> Result<&[u8]> {
+        if let Some(jpeg) = self.jpeg {
+            return Ok(jpeg);
+        }
+        if let Some(encoded) = self.encoded.get() {
+            return Ok(encoded);
+        }
+        let rgb = self
+            .rgb
+            .with_context(|| format!("Frame #{} has neither pixels nor JPEG", self.frame_no))?;
+        let image = turbojpeg::Image {
+            pixels: rgb,
+            width: self.width as usize,
+            pitch: self.width as usize * 3,
+            height: self.height as usize,
+            format: turbojpeg::PixelFormat::RGB,
+        };
+        let jpeg = turbojpeg::compress(image, JPEG_QUALITY, turbojpeg::Subsamp::Sub2x2)?;
+        Ok(self.encoded.get_or_init(|| jpeg.to_vec()))
+    }
+}
+
+/// An entry of `CAPTURE_SINKS`
+#[derive(Debug, Clone, PartialEq)]
+pub enum SinkSpec {
+    Shm,
+    MjpegFile(PathBuf),
+    RtspPush(String),
+}
+
+impl SinkSpec {
+    /// Parse a comma separated `CAPTURE_SINKS` list
+    pub fn parse_list(value: &str) -> Result<Vec<Self>> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+}
+
+impl FromStr for SinkSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.split_once(':') {
+            None if value == "shm" => Ok(Self::Shm),
+            Some(("mjpeg", path)) if !path.is_empty() => Ok(Self::MjpegFile(path.into())),
+            Some(("rtsp", url)) if url.starts_with("rtsp://") || url.starts_with("rtsps://") => {
+                Ok(Self::RtspPush(url.to_string()))
+            }
+            Some(("rtsp" | "rtsps", rest)) if rest.starts_with("//") => {
+                Ok(Self::RtspPush(value.to_string()))
+            }
+            _ => bail!(
+                "Invalid CAPTURE_SINKS entry {:?}, expected shm, mjpeg:<path> or rtsp:<url>",
+                value
+            ),
+        }
+    }
+}
+
+/// Writes each frame to the shared memory buffer and every extra sink
+pub struct FrameTee {
+    shm: ShmSink,
+    outputs: Vec<Output>,
+}
+
+/// An extra sink and its failures
+struct Output {
+    sink: Box<dyn FrameSink>,
+    /// Consecutive failed writes
+    failures: u32,
+    /// Frames are not written before then after a failure
+    retry_at: Option<Instant>,
+}
+
+impl FrameTee {
+    pub fn new(shm: ShmSink) -> Self {
+        Self {
+            shm,
+            outputs: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, sink: Box<dyn FrameSink>) {
+        tracing::info!(sink = sink.name(), "Frame sink added");
+        self.outputs.push(Output::new(sink));
+    }
+
+    pub fn shm(&mut self) -> &mut ShmSink {
+        &mut self.shm
+    }
+
+    /// Write `frame` to every sink. Only a failure of the shared memory buffer is
+    /// returned, the other sinks are retried later when they fail.
+    pub fn write(&mut self, frame: &CapturedFrame<'_>) -> Result<()> {
+        let written = self.shm.write(frame);
+        let now = Instant::now();
+        for output in &mut self.outputs {
+            output.write(frame, now);
+        }
+        written
+    }
+}
+
+impl Output {
+    fn new(sink: Box<dyn FrameSink>) -> Self {
+        Self {
+            sink,
+            failures: 0,
+            retry_at: None,
+        }
+    }
+
+    fn write(&mut self, frame: &CapturedFrame<'_>, now: Instant) {
+        if self.retry_at.is_some_and(|at| now < at) {
+            return;
+        }
+        match self.sink.write(frame) {
+            Ok(()) => {
+                if self.failures > 0 {
+                    tracing::info!(sink = self.sink.name(), "Frame sink recovered");
+                }
+                self.failures = 0;
+                self.retry_at = None;
+            }
+            Err(e) => {
+                self.failures += 1;
+                let backoff = Duration::from_secs(1)
+                    .saturating_mul(1 << (self.failures - 1).min(6))
+                    .min(MAX_BACKOFF);
+                self.retry_at = Some(now + backoff);
+                log_throttle!(
+                    warn,
+                    sink = self.sink.name(),
+                    error = %format!("{e:#}"),
+                    failures = self.failures,
+                    ?backoff,
+                    "Frame sink failed"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct Fake {
+        fail: Arc<Mutex<bool>>,
+        written: Arc<Mutex<Vec<u64>>>,
+    }
+
+    impl FrameSink for Fake {
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        fn write(&mut self, frame: &CapturedFrame<'_>) -> Result<()> {
+            if *self.fail.lock().unwrap() {
+                bail!("unreachable");
+            }
+            self.written.lock().unwrap().push(frame.frame_no);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_parses_sink_list() {
+        assert_eq!(
+            SinkSpec::parse_list("shm, mjpeg:/var/lib/capture/door.mjpeg,rtsp://nvr:8554/door")
+                .unwrap(),
+            [
+                SinkSpec::Shm,
+                SinkSpec::MjpegFile("/var/lib/capture/door.mjpeg".into()),
+                SinkSpec::RtspPush("rtsp://nvr:8554/door".to_string()),
+            ]
+        );
+        assert_eq!(
+            "rtsp:rtsps://nvr/door".parse::<SinkSpec>().unwrap(),
+            SinkSpec::RtspPush("rtsps://nvr/door".to_string())
+        );
+        assert!("mjpeg:".parse::<SinkSpec>().is_err());
+        assert!("rtsp:nvr".parse::<SinkSpec>().is_err());
+        assert!("nvr".parse::<SinkSpec>().is_err());
+    }
+
+    #[test]
+    fn test_failing_sink_is_skipped_then_retried() {
+        let fail = Arc::new(Mutex::new(true));
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let mut output = Output::new(Box::new(Fake {
+            fail: fail.clone(),
+            written: written.clone(),
+        }));
+        let start = Instant::now();
+        let frame = |n| CapturedFrame::new(0, n, 2, 2);
+
+        output.write(&frame(0), start);
+        assert_eq!(output.retry_at, Some(start + Duration::from_secs(1)));
+        output.write(&frame(1), start);
+        assert_eq!(output.failures, 1);
+
+        // Backs off exponentially
+        output.write(&frame(2), start + Duration::from_secs(1));
+        assert_eq!(output.retry_at, Some(start + Duration::from_secs(3)));
+
+        *fail.lock().unwrap() = false;
+        output.write(&frame(3), start + Duration::from_secs(2));
+        output.write(&frame(4), start + Duration::from_secs(3));
+        assert_eq!(*written.lock().unwrap(), [4]);
+        assert_eq!(output.failures, 0);
+    }
+
+    #[test]
+    fn test_pixels_are_compressed_once() {
+        let rgb = [128u8; 16 * 16 * 3];
+        let mut frame = CapturedFrame::new(0, 0, 16, 16);
+        frame.rgb = Some(&rgb);
+
+        let jpeg = frame.to_jpeg().unwrap();
+        assert_eq!(&jpeg[..2], [0xFF, 0xD8]);
+        assert!(std::ptr::eq(jpeg, frame.to_jpeg().unwrap()));
+    }
+}
@@ -0,0 +1,175 @@
+//! Pushing the camera to an RTSP server
+//!
+//! An `ffmpeg` child process reads the frames as MJPEG on its stdin, encodes them
+//! to H.264 and publishes them with RTSP `ANNOUNCE`/`RECORD`, which servers like
+//! MediaMTX or an NVR's ingest accept. A thread feeds the process so a slow or
+//! unreachable server never holds the capture loop: frames it has no room for
+//! are dropped. When ffmpeg exits the write fails, and the process is started
+//! again on the next frame the tee hands over.
+
+use super::{CapturedFrame, FrameSink};
+use anyhow::{Context, Result, bail};
+use std::io::Write;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{SyncSender, TrySendError, sync_channel};
+
+/// Frames waiting for ffmpeg before new ones are dropped
+const QUEUE: usize = 4;
+
+pub struct RtspPushSink {
+    url: String,
+    ffmpeg: String,
+    process: Option<Process>,
+    /// Frames dropped because ffmpeg was behind
+    dropped: u64,
+}
+
+struct Process {
+    child: Child,
+    frames: SyncSender<Vec<u8>>,
+}
+
+impl RtspPushSink {
+    /// Push to `url` with the `ffmpeg` executable
+    pub fn new(url: String, ffmpeg: String) -> Self {
+        Self {
+            url,
+            ffmpeg,
+            process: None,
+            dropped: 0,
+        }
+    }
+
+    fn start(&self) -> Result<Process> {
+        let mut child = Command::new(&self.ffmpeg)
+            .args(arguments(&self.url))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to start {}", self.ffmpeg))?;
+        let mut stdin = child.stdin.take().context("ffmpeg stdin not piped")?;
+
+        let (frames, queue) = sync_channel::<Vec<u8>>(QUEUE);
+        std::thread::Builder::new()
+            .name("rtsp-push".to_string())
+            .spawn(move || {
+                // Ends when ffmpeg exits or the sink is dropped
+                for jpeg in queue {
+                    if stdin.write_all(&jpeg).is_err() {
+                        return;
+                    }
+                }
+            })
+            .context("Failed to spawn RTSP push thread")?;
+        tracing::info!(url = %self.url, "Pushing frames over RTSP");
+
+        Ok(Process { child, frames })
+    }
+}
+
+impl FrameSink for RtspPushSink {
+    fn name(&self) -> &str {
+        "rtsp"
+    }
+
+    fn write(&mut self, frame: &CapturedFrame<'_>) -> Result<()> {
+        let jpeg = frame.to_jpeg()?.to_vec();
+        let process = match self.process.as_mut() {
+            Some(process) => process,
+            None => self.process.insert(self.start()?),
+        };
+        match process.frames.try_send(jpeg) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                self.dropped += 1;
+                common::log_throttle!(
+                    debug,
+                    nth = 100,
+                    dropped = self.dropped,
+                    "RTSP push is behind, frame dropped"
+                );
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                let status = process.child.try_wait().ok().flatten();
+                self.process = None;
+                match status {
+                    Some(status) => bail!("ffmpeg pushing to {} exited: {}", self.url, status),
+                    None => bail!("ffmpeg pushing to {} stopped reading frames", self.url),
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Command line reading MJPEG on stdin and publishing H.264 to `url`
+fn arguments(url: &str) -> Vec<String> {
+    [
+        "-hide_banner",
+        "-loglevel",
+        "error",
+        "-nostdin",
+        // Frames come at the pace of the capture loop, which changes with the
+        // sentry mode
+        "-use_wallclock_as_timestamps",
+        "1",
+        "-f",
+        "mjpeg",
+        "-i",
+        "-",
+        "-an",
+        "-c:v",
+        "libx264",
+        "-preset",
+        "ultrafast",
+        "-tune",
+        "zerolatency",
+        "-pix_fmt",
+        "yuv420p",
+        "-fps_mode",
+        "passthrough",
+        "-f",
+        "rtsp",
+        "-rtsp_transport",
+        "tcp",
+        url,
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_mjpeg_and_publishes_to_the_url() {
+        let args = arguments("rtsp://nvr:8554/door").join(" ");
+
+        assert!(args.contains("-f mjpeg -i -"));
+        assert!(args.contains("-c:v libx264"));
+        assert!(args.ends_with("-f rtsp -rtsp_transport tcp rtsp://nvr:8554/door"));
+    }
+
+    #[test]
+    fn test_missing_ffmpeg_fails_the_write() {
+        let mut sink = RtspPushSink::new(
+            "rtsp://nvr:8554/door".to_string(),
+            "/nonexistent/ffmpeg".to_string(),
+        );
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xD9];
+        let mut frame = CapturedFrame::new(0, 0, 640, 480);
+        frame.jpeg = Some(&jpeg);
+
+        assert!(sink.write(&frame).is_err());
+        assert!(sink.process.is_none());
+    }
+}
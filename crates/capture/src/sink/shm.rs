@@ -0,0 +1,290 @@
+//! The bridge's shared memory frame buffer, read by inference and the gateway
+
+use super::{CapturedFrame, FrameSink};
+use crate::downscale::Downscaler;
+use anyhow::{Result, bail};
+use bridge::{
+    FrameCompression, FrameFanout, FrameSigner, FrameWriter, HugePages, Provenance,
+    SnapshotControl, WriteGate, paths,
+};
+use schema::FrameEncoding;
+use std::time::Duration;
+
+pub struct ShmSink {
+    writer: FrameWriter,
+    /// Signals every registered frame consumer (inference, gateway, ...)
+    fanout: FrameFanout,
+    downscaler: Option<Downscaler>,
+    /// Longest side frames were configured to be downscaled to
+    max_dimension: Option<u32>,
+    /// Full resolution stills requested through the snapshot control
+    stills: Option<Stills>,
+    /// Write the camera's JPEG rather than the decoded pixels
+    jpeg_passthrough: bool,
+}
+
+struct Stills {
+    control: SnapshotControl,
+    /// Snapshot buffer writer, created on the first request
+    writer: Option<FrameWriter>,
+    /// Native frame size the buffer is sized for
+    size: (u32, u32),
+    /// Pending stills wait for a later frame, see `night_still`
+    held: bool,
+}
+
+impl ShmSink {
+    /// Sink for frames of at most `width` x `height`
+    pub fn new(width: u32, height: u32) -> Result<Self> {
+        let stills = SnapshotControl::build()
+            .inspect_err(|e| tracing::warn!(error = %e, "Still requests unavailable"))
+            .ok()
+            .map(|control| Stills {
+                control,
+                writer: None,
+                size: (width, height),
+                held: false,
+            });
+        Ok(Self {
+            writer: FrameWriter::build_for_resolution(width, height)?,
+            fanout: FrameFanout::build()?,
+            downscaler: None,
+            max_dimension: None,
+            stills,
+            jpeg_passthrough: false,
+        })
+    }
+
+    /// Downscale frames whose longest side exceeds `max_dimension` before writing them
+    pub fn set_max_dimension(&mut self, max_dimension: u32) {
+        self.max_dimension = Some(max_dimension);
+        self.downscaler = Some(Downscaler::new(max_dimension));
+    }
+
+    /// Follow the camera to `width` x `height` frames
+    ///
+    /// The frame buffer keeps its size so readers stay attached: frames it no
+    /// longer holds are downscaled until they fit. The snapshot buffer is sized
+    /// anew for the next still.
+    pub fn set_resolution(&mut self, width: u32, height: u32) {
+        let fit = self.writer.max_dimension_for(width, height);
+        if let Some(fit) = fit {
+            tracing::warn!(
+                width,
+                height,
+                max_dimension = fit,
+                "Frames no longer fit the frame buffer, downscaling them"
+            );
+        }
+        let max_dimension = match (self.max_dimension, fit) {
+            (Some(configured), Some(fit)) => Some(configured.min(fit)),
+            (configured, fit) => configured.or(fit),
+        };
+        self.downscaler = max_dimension.map(Downscaler::new);
+        if let Some(stills) = self.stills.as_mut() {
+            stills.size = (width, height);
+            stills.writer = None;
+        }
+    }
+
+    /// Write MJPEG frames as the camera delivered them instead of their pixels
+    pub fn set_jpeg_passthrough(&mut self, jpeg_passthrough: bool) {
+        self.jpeg_passthrough = jpeg_passthrough;
+    }
+
+    pub fn set_compression(&mut self, compression: FrameCompression) {
+        self.writer.set_compression(compression);
+    }
+
+    /// Wait at most `timeout` for the readers registered on the frame write gate
+    /// before replacing a frame they have not consumed
+    pub fn set_write_gate(&mut self, timeout: Duration) -> Result<()> {
+        let gate = WriteGate::new(paths::FRAME_WRITE_GATE_PATH, timeout)?;
+        self.writer.set_write_gate(Some(gate));
+        Ok(())
+    }
+
+    /// Frames written over a lagging lossless reader since the last call
+    pub fn take_write_gate_overruns(&mut self) -> u64 {
+        self.writer.take_write_gate_overruns()
+    }
+
+    pub fn set_provenance(&mut self, provenance: Provenance) {
+        self.writer.set_provenance(Some(provenance));
+    }
+
+    /// Sign every frame and still written, see `bridge::signing`
+    pub fn set_signer(&mut self, signer: FrameSigner) {
+        self.writer.set_signer(Some(signer));
+    }
+
+    /// Whether a pending still asked for a long exposure
+    pub fn night_still_pending(&self) -> bool {
+        self.stills
+            .as_ref()
+            .is_some_and(|stills| stills.control.pending_night_still())
+    }
+
+    /// Keep pending stills for a later frame, while the exposure of a night still settles
+    pub fn hold_stills(&mut self, held: bool) {
+        if let Some(stills) = self.stills.as_mut() {
+            stills.held = held;
+        }
+    }
+
+    fn write_rgb(
+        &mut self,
+        rgb: &[u8],
+        camera_id: u32,
+        frame_no: u64,
+        width: u32,
+        height: u32,
+        trace: Option<&schema::TraceContext>,
+    ) -> Result<()> {
+        self.write_still(
+            rgb,
+            FrameEncoding::Rgb,
+            camera_id,
+            frame_no,
+            (width, height),
+            trace,
+        );
+        let downscaled = match self.downscaler.as_mut() {
+            Some(downscaler) => downscaler.downscale(rgb, width, height)?,
+            None => None,
+        };
+        match downscaled {
+            Some((pixels, new_width, new_height)) => {
+                self.writer.set_next_original_size(width, height);
+                self.writer
+                    .write_frame(camera_id, pixels, frame_no, new_width, new_height, trace)?;
+            }
+            None => {
+                self.writer
+                    .write_frame(camera_id, rgb, frame_no, width, height, trace)?;
+            }
+        }
+        self.fanout.post();
+        Ok(())
+    }
+
+    /// Write an MJPEG frame as is, `width` x `height` being its decoded size
+    fn write_jpeg(
+        &mut self,
+        jpeg: &[u8],
+        camera_id: u32,
+        frame_no: u64,
+        width: u32,
+        height: u32,
+        trace: Option<&schema::TraceContext>,
+    ) -> Result<()> {
+        self.write_still(
+            jpeg,
+            FrameEncoding::Jpeg,
+            camera_id,
+            frame_no,
+            (width, height),
+            trace,
+        );
+        self.writer.set_next_encoding(FrameEncoding::Jpeg);
+        self.writer
+            .write_frame(camera_id, jpeg, frame_no, width, height, trace)?;
+        self.fanout.post();
+        Ok(())
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.writer.sequence()
+    }
+
+    /// Write the frame, as the camera delivered it, to the snapshot buffer when a
+    /// still was requested. Failures are logged, the frame itself is still written.
+    fn write_still(
+        &mut self,
+        payload: &[u8],
+        encoding: FrameEncoding,
+        camera_id: u32,
+        frame_no: u64,
+        (width, height): (u32, u32),
+        trace: Option<&schema::TraceContext>,
+    ) {
+        let Some(stills) = self.stills.as_mut().filter(|stills| !stills.held) else {
+            return;
+        };
+        let Some(request) = stills.control.pending_still() else {
+            return;
+        };
+
+        let writer = match stills.writer.as_mut() {
+            Some(writer) => writer,
+            None => {
+                let (native_width, native_height) = stills.size;
+                let size = paths::frame_buffer_size(native_width, native_height);
+                // Sized for this camera rather than whatever a previous run left
+                let _ = std::fs::remove_file(paths::SNAPSHOT_BUFFER_PATH);
+                match FrameWriter::build_with_options(
+                    paths::SNAPSHOT_BUFFER_PATH,
+                    size,
+                    HugePages::Off,
+                ) {
+                    Ok(writer) => stills.writer.insert(writer),
+                    Err(e) => {
+                        common::log_throttle!(warn, error = %e, "Failed to create the snapshot buffer");
+                        return;
+                    }
+                }
+            }
+        };
+        writer.set_provenance(self.writer.provenance().cloned());
+        writer.set_signer(self.writer.signer().cloned());
+        writer.set_next_encoding(encoding);
+        match writer.write_frame(camera_id, payload, frame_no, width, height, trace) {
+            Ok(()) => {
+                stills.control.fulfill_still(request, frame_no);
+                tracing::info!(
+                    request,
+                    frame_no,
+                    width,
+                    height,
+                    "Full resolution still written"
+                );
+            }
+            Err(e) => common::log_throttle!(warn, error = %e, "Failed to write still"),
+        }
+    }
+}
+
+impl FrameSink for ShmSink {
+    fn name(&self) -> &str {
+        "shm"
+    }
+
+    fn write(&mut self, frame: &CapturedFrame<'_>) -> Result<()> {
+        if let Some(timestamp) = frame.timestamp {
+            self.writer.set_next_timestamp(timestamp);
+        }
+        if let Some(burst) = frame.burst {
+            self.writer.set_next_burst(burst);
+        }
+        #[cfg(feature = "jetson")]
+        if let Some(index) = frame.nvmm_surface {
+            self.writer.set_next_nvmm_surface(index);
+        }
+
+        let CapturedFrame {
+            camera_id,
+            frame_no,
+            width,
+            height,
+            trace,
+            ..
+        } = *frame;
+        match (frame.rgb, frame.jpeg) {
+            (Some(rgb), _) if !self.jpeg_passthrough => {
+                self.write_rgb(rgb, camera_id, frame_no, width, height, trace)
+            }
+            (_, Some(jpeg)) => self.write_jpeg(jpeg, camera_id, frame_no, width, height, trace),
+            _ => bail!("Frame #{} has neither pixels nor JPEG", frame_no),
+        }
+    }
+}
@@ -1,37 +1,150 @@
 use anyhow::{Context, Result};
+use common::MonotonicNs;
+use std::io;
+use std::time::Duration;
 use v4l::{
     Device,
-    buffer::{Metadata, Type},
+    buffer::{Flags, Metadata, Type},
     io::{mmap::Stream, traits::CaptureStream},
 };
 
-const BUFFER_COUNT: u32 = 4;
-
 /// Number of frames to discard on mode transition to flush stale buffers
 const FLUSH_FRAME_COUNT: usize = 4;
 
 pub struct FrameSource<'a> {
-    stream: Stream<'a>,
+    device: &'a Device,
+    /// `None` only while the stream is being re-created
+    stream: Option<Stream<'a>>,
+    buffer_count: u32,
+    dequeue_timeout: Duration,
 }
 
 impl<'a> FrameSource<'a> {
-    pub fn new(device: &'a Device) -> Result<Self> {
-        let stream = Stream::with_buffers(device, Type::VideoCapture, BUFFER_COUNT)
+    /// Stream from `device` with `buffer_count` mmap buffers
+    ///
+    /// A dequeue waiting longer than `dequeue_timeout` for the driver fails with
+    /// [`io::ErrorKind::TimedOut`] instead of blocking forever.
+    pub fn new(device: &'a Device, buffer_count: u32, dequeue_timeout: Duration) -> Result<Self> {
+        let mut source = Self {
+            device,
+            stream: None,
+            buffer_count,
+            dequeue_timeout,
+        };
+        source.open()?;
+        Ok(source)
+    }
+
+    fn open(&mut self) -> Result<()> {
+        let mut stream = Stream::with_buffers(self.device, Type::VideoCapture, self.buffer_count)
             .context("Failed to create capture stream")?;
-        Ok(Self { stream })
+        stream.set_timeout(self.dequeue_timeout);
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    /// Tear the stream down and start a new one, for drivers that stopped delivering
+    pub fn restart(&mut self) -> Result<()> {
+        // The old buffers must be released before the driver accepts new ones
+        self.stream = None;
+        self.open()
     }
 
     /// Discard buffered frames to ensure fresh captures after mode transition.
     pub fn flush(&mut self) -> usize {
         (0..FLUSH_FRAME_COUNT)
-            .take_while(|_| self.stream.next().is_ok())
+            .take_while(|_| self.next_frame().is_ok())
             .count()
     }
 
-    pub fn next_frame(&mut self) -> Result<(&[u8], Metadata)> {
-        self.stream
-            .next()
-            .map(|(data, meta)| (data, *meta))
-            .map_err(Into::into)
+    pub fn next_frame(&mut self) -> io::Result<(&[u8], Metadata)> {
+        let stream = self
+            .stream
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "Capture stream closed"))?;
+        stream.next().map(|(data, meta)| (data, *meta))
+    }
+}
+
+/// When the driver took the frame, for drivers stamping buffers on `CLOCK_MONOTONIC`
+///
+/// Closer to the exposure than the time the frame was dequeued, and immune to
+/// wall clock steps. `None` for other timestamp sources.
+pub fn capture_time(meta: &Metadata) -> Option<MonotonicNs> {
+    let monotonic = meta.flags & Flags::TIMESTAMP_MASK == Flags::TIMESTAMP_MONOTONIC;
+    let stamped = meta.timestamp.sec > 0 || meta.timestamp.usec > 0;
+    // time_t is 32 bits on some targets
+    #[allow(clippy::unnecessary_cast)]
+    (monotonic && stamped)
+        .then(|| MonotonicNs::from_timeval(meta.timestamp.sec as i64, meta.timestamp.usec as i64))
+}
+
+/// Counts consecutive dequeue timeouts to tell a stalled driver from a slow frame
+#[derive(Debug)]
+pub struct StallDetector {
+    /// Consecutive timeouts before the stream is restarted
+    limit: u32,
+    timeouts: u32,
+}
+
+impl StallDetector {
+    pub fn new(limit: u32) -> Self {
+        Self {
+            limit: limit.max(1),
+            timeouts: 0,
+        }
+    }
+
+    /// Record a frame arriving
+    pub fn frame(&mut self) {
+        self.timeouts = 0;
+    }
+
+    /// Record a dequeue timeout. Returns true once the stream should be restarted.
+    pub fn timeout(&mut self) -> bool {
+        self.timeouts += 1;
+        if self.timeouts >= self.limit {
+            self.timeouts = 0;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_after_consecutive_timeouts() {
+        let mut stall = StallDetector::new(3);
+
+        assert!(!stall.timeout());
+        assert!(!stall.timeout());
+        stall.frame();
+        assert!(!stall.timeout());
+        assert!(!stall.timeout());
+        assert!(stall.timeout());
+        // Counting starts over after a restart
+        assert!(!stall.timeout());
+    }
+
+    #[test]
+    fn test_capture_time_needs_a_monotonic_stamp() {
+        let mut meta = Metadata {
+            flags: Flags::TIMESTAMP_MONOTONIC,
+            timestamp: v4l::Timestamp::new(12, 500),
+            ..Default::default()
+        };
+        assert_eq!(
+            capture_time(&meta),
+            Some(MonotonicNs::from_nanos(12_000_500_000))
+        );
+
+        meta.flags = Flags::TIMESTAMP_COPY;
+        assert_eq!(capture_time(&meta), None);
+        meta.flags = Flags::TIMESTAMP_MONOTONIC;
+        meta.timestamp = v4l::Timestamp::default();
+        assert_eq!(capture_time(&meta), None);
     }
 }
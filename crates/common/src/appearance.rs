@@ -0,0 +1,137 @@
+//! Appearance descriptor of a detection
+//!
+//! A coarse HSV histogram of the pixels in a detection box: [`HUE_BINS`] hue bins
+//! for colored pixels and [`GRAY_BINS`] brightness bins for the pixels too dark or
+//! too unsaturated for their hue to mean anything. Each bin holds its share of the
+//! sampled pixels scaled to 0-255. Clothing dominates a person crop, so two crops
+//! of the same person stay close under [`Appearance::similarity`] while the box
+//! itself changes shape and size.
+
+pub const HUE_BINS: usize = 12;
+pub const GRAY_BINS: usize = 4;
+/// Bytes of a descriptor
+pub const APPEARANCE_LEN: usize = HUE_BINS + GRAY_BINS;
+
+/// Pixels sampled per box at most, on a regular grid
+const MAX_SAMPLES: usize = 4096;
+/// Saturation and value under which a pixel counts as gray
+const MIN_SATURATION: f32 = 0.2;
+const MIN_VALUE: f32 = 0.15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Appearance(pub [u8; APPEARANCE_LEN]);
+
+impl Appearance {
+    /// Histogram of the RGB `pixels` (`width` x `height`) inside the `x1, y1, x2, y2`
+    /// box, `None` when the box holds no pixel of the frame
+    ///
+    /// Only the middle half of the box width is sampled, where the subject is
+    /// rather than the background around it.
+    pub fn of_box(pixels: &[u8], width: u32, height: u32, bbox: [f32; 4]) -> Option<Self> {
+        let [x1, y1, x2, y2] = bbox;
+        let quarter = (x2 - x1) / 4.0;
+        let clamp = |v: f32, max: u32| (v.max(0.0) as u32).min(max);
+        let (left, right) = (clamp(x1 + quarter, width), clamp(x2 - quarter, width));
+        let (top, bottom) = (clamp(y1, height), clamp(y2, height));
+        if left >= right || top >= bottom || pixels.len() < (width * height * 3) as usize {
+            return None;
+        }
+
+        let area = ((right - left) * (bottom - top)) as usize;
+        let step = ((area as f32 / MAX_SAMPLES as f32).sqrt().ceil() as usize).max(1);
+        let mut counts = [0u32; APPEARANCE_LEN];
+        let mut total = 0u32;
+        for y in (top..bottom).step_by(step) {
+            for x in (left..right).step_by(step) {
+                let i = ((y * width + x) * 3) as usize;
+                counts[bin(pixels[i], pixels[i + 1], pixels[i + 2])] += 1;
+                total += 1;
+            }
+        }
+
+        let mut histogram = [0u8; APPEARANCE_LEN];
+        for (share, count) in histogram.iter_mut().zip(counts) {
+            *share = (count as f32 * 255.0 / total as f32).round() as u8;
+        }
+        Some(Self(histogram))
+    }
+
+    /// Descriptor read back from a detection, `None` unless `bytes` is one
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        Some(Self(bytes.try_into().ok()?))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Histogram intersection: 1 for identical histograms, 0 for disjoint ones
+    pub fn similarity(&self, other: &Self) -> f32 {
+        let shared: u32 = self
+            .0
+            .iter()
+            .zip(&other.0)
+            .map(|(a, b)| *a.min(b) as u32)
+            .sum();
+        let total = |h: &Self| h.0.iter().map(|&v| v as u32).sum::<u32>();
+        shared as f32 / total(self).max(total(other)).max(1) as f32
+    }
+}
+
+/// Histogram bin of an RGB pixel
+fn bin(r: u8, g: u8, b: u8) -> usize {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = max - min;
+    let saturation = if max > 0.0 { chroma / max } else { 0.0 };
+    if saturation < MIN_SATURATION || max < MIN_VALUE {
+        return HUE_BINS + ((max * GRAY_BINS as f32) as usize).min(GRAY_BINS - 1);
+    }
+
+    let hue = if max == r {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    ((hue / 6.0 * HUE_BINS as f32) as usize).min(HUE_BINS - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `width` x `height` frame, left half `left` and right half `right`
+    fn frame(width: u32, height: u32, left: [u8; 3], right: [u8; 3]) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| if i % width < width / 2 { left } else { right })
+            .collect()
+    }
+
+    #[test]
+    fn test_colors_fall_in_their_bins() {
+        assert_eq!(bin(255, 0, 0), 0);
+        assert_eq!(bin(0, 255, 0), HUE_BINS / 3);
+        assert_eq!(bin(0, 0, 255), 2 * HUE_BINS / 3);
+        assert_eq!(bin(0, 0, 0), HUE_BINS);
+        assert_eq!(bin(255, 255, 255), HUE_BINS + GRAY_BINS - 1);
+    }
+
+    #[test]
+    fn test_same_clothes_match_across_boxes() {
+        let red_blue = frame(64, 64, [200, 30, 30], [30, 30, 200]);
+        let whole = Appearance::of_box(&red_blue, 64, 64, [0.0, 0.0, 64.0, 64.0]).unwrap();
+        let smaller = Appearance::of_box(&red_blue, 64, 64, [8.0, 10.0, 56.0, 60.0]).unwrap();
+        assert!(whole.similarity(&smaller) > 0.9);
+
+        let green = frame(64, 64, [30, 200, 30], [30, 200, 30]);
+        let other = Appearance::of_box(&green, 64, 64, [0.0, 0.0, 64.0, 64.0]).unwrap();
+        assert!(whole.similarity(&other) < 0.1);
+
+        assert_eq!(Appearance::from_bytes(whole.as_bytes()), Some(whole));
+        // Outside the frame
+        assert!(Appearance::of_box(&red_blue, 64, 64, [70.0, 0.0, 90.0, 64.0]).is_none());
+    }
+}
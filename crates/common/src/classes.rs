@@ -0,0 +1,321 @@
+//! Detection class names and groups
+//!
+//! Site rules name what they care about instead of listing model label indices:
+//! a COCO label (`person`, `fire hydrant` or `fire_hydrant`), a group of labels
+//! (`animal`, `vehicle`), or a raw class id. Groups come with defaults and are
+//! extended or redefined with `CLASS_GROUPS`, a `;` separated list of
+//! `<group>=<class>,<class>,...`:
+//!
+//! ```text
+//! CLASS_GROUPS="pets=dog,cat;vehicle=car,truck,bus"
+//! ```
+
+use crate::get_env_opt;
+use anyhow::{Context, Result, bail};
+
+/// COCO labels, indexed by the class id detections carry
+pub const COCO_CLASSES: [&str; 80] = [
+    "person",
+    "bicycle",
+    "car",
+    "motorcycle",
+    "airplane",
+    "bus",
+    "train",
+    "truck",
+    "boat",
+    "traffic light",
+    "fire hydrant",
+    "stop sign",
+    "parking meter",
+    "bench",
+    "bird",
+    "cat",
+    "dog",
+    "horse",
+    "sheep",
+    "cow",
+    "elephant",
+    "bear",
+    "zebra",
+    "giraffe",
+    "backpack",
+    "umbrella",
+    "handbag",
+    "tie",
+    "suitcase",
+    "frisbee",
+    "skis",
+    "snowboard",
+    "sports ball",
+    "kite",
+    "baseball bat",
+    "baseball glove",
+    "skateboard",
+    "surfboard",
+    "tennis racket",
+    "bottle",
+    "wine glass",
+    "cup",
+    "fork",
+    "knife",
+    "spoon",
+    "bowl",
+    "banana",
+    "apple",
+    "sandwich",
+    "orange",
+    "broccoli",
+    "carrot",
+    "hot dog",
+    "pizza",
+    "donut",
+    "cake",
+    "chair",
+    "couch",
+    "potted plant",
+    "bed",
+    "dining table",
+    "toilet",
+    "tv",
+    "laptop",
+    "mouse",
+    "remote",
+    "keyboard",
+    "cell phone",
+    "microwave",
+    "oven",
+    "toaster",
+    "sink",
+    "refrigerator",
+    "book",
+    "clock",
+    "vase",
+    "scissors",
+    "teddy bear",
+    "hair drier",
+    "toothbrush",
+];
+
+/// Groups available without configuration
+const DEFAULT_GROUPS: &str = "animal=bird,cat,dog,horse,sheep,cow,elephant,bear,zebra,giraffe;\
+                              vehicle=bicycle,car,motorcycle,bus,truck";
+
+/// Set of class ids, all below 128
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ClassSet(u128);
+
+impl ClassSet {
+    pub fn single(class_id: u16) -> Self {
+        Self::default().with(class_id)
+    }
+
+    pub fn with(self, class_id: u16) -> Self {
+        debug_assert!(class_id < 128, "class id {class_id} out of range");
+        Self(self.0 | 1u128.checked_shl(class_id as u32).unwrap_or(0))
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub fn contains(self, class_id: u16) -> bool {
+        class_id < 128 && self.0 & (1 << class_id) != 0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Class ids in ascending order
+    pub fn iter(self) -> impl Iterator<Item = u16> {
+        (0..128u16).filter(move |&id| self.contains(id))
+    }
+}
+
+impl FromIterator<u16> for ClassSet {
+    fn from_iter<I: IntoIterator<Item = u16>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::default(), Self::with)
+    }
+}
+
+/// Named groups of classes
+#[derive(Debug, Clone)]
+pub struct ClassGroups {
+    groups: Vec<(String, ClassSet)>,
+}
+
+impl Default for ClassGroups {
+    fn default() -> Self {
+        let mut groups = Self { groups: Vec::new() };
+        groups
+            .extend(DEFAULT_GROUPS)
+            .expect("default class groups are valid");
+        groups
+    }
+}
+
+impl ClassGroups {
+    /// Default groups, extended or overridden by `CLASS_GROUPS`
+    pub fn from_env() -> Result<Self> {
+        let mut groups = Self::default();
+        if let Some(spec) = get_env_opt::<String>("CLASS_GROUPS") {
+            groups.extend(&spec).context("Invalid CLASS_GROUPS")?;
+        }
+        Ok(groups)
+    }
+
+    /// Add the groups of a `<group>=<class>,...;...` list, replacing any of the same name
+    fn extend(&mut self, spec: &str) -> Result<()> {
+        for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (name, members) = entry
+                .split_once('=')
+                .with_context(|| format!("Group {entry:?} has no '='"))?;
+            let name = normalize(name);
+            if class_id(&name).is_some() {
+                bail!("Group {name:?} shadows a class");
+            }
+
+            // Members may name groups defined before this one
+            let members = members
+                .split(',')
+                .map(str::trim)
+                .filter(|m| !m.is_empty())
+                .map(|member| self.resolve(member))
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .fold(ClassSet::default(), ClassSet::union);
+            if members.is_empty() {
+                bail!("Group {name:?} has no class");
+            }
+
+            self.groups.retain(|(existing, _)| *existing != name);
+            self.groups.push((name, members));
+        }
+        Ok(())
+    }
+
+    /// Classes named by a class id, a COCO label or a group name
+    pub fn resolve(&self, name: &str) -> Result<ClassSet> {
+        let name = normalize(name);
+        if let Ok(id) = name.parse::<u16>() {
+            if id >= 128 {
+                bail!("Class id {id} is out of range");
+            }
+            return Ok(ClassSet::single(id));
+        }
+        if let Some(id) = class_id(&name) {
+            return Ok(ClassSet::single(id));
+        }
+        self.groups
+            .iter()
+            .find(|(group, _)| *group == name)
+            .map(|(_, members)| *members)
+            .with_context(|| format!("Unknown class or group {name:?}"))
+    }
+
+    /// Union of the classes named in a comma-separated list
+    pub fn resolve_list(&self, list: &str) -> Result<ClassSet> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .try_fold(ClassSet::default(), |set, name| {
+                Ok(set.union(self.resolve(name)?))
+            })
+    }
+
+    /// Names of the groups `class_id` belongs to
+    pub fn groups_of(&self, class_id: u16) -> impl Iterator<Item = &str> {
+        self.groups
+            .iter()
+            .filter(move |(_, members)| members.contains(class_id))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// COCO label of a class id
+pub fn class_name(class_id: u16) -> Option<&'static str> {
+    COCO_CLASSES.get(class_id as usize).copied()
+}
+
+fn class_id(name: &str) -> Option<u16> {
+    COCO_CLASSES
+        .iter()
+        .position(|label| *label == name)
+        .map(|id| id as u16)
+}
+
+/// Lower case with underscores read as spaces, so `Fire_Hydrant` names `fire hydrant`
+fn normalize(name: &str) -> String {
+    name.trim().to_ascii_lowercase().replace('_', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_names_ids_and_groups() {
+        let groups = ClassGroups::default();
+
+        assert_eq!(groups.resolve("person").unwrap(), ClassSet::single(0));
+        assert_eq!(
+            groups.resolve("Fire_Hydrant").unwrap(),
+            ClassSet::single(10)
+        );
+        assert_eq!(groups.resolve("21").unwrap(), ClassSet::single(21));
+        assert_eq!(
+            groups
+                .resolve("vehicle")
+                .unwrap()
+                .iter()
+                .collect::<Vec<_>>(),
+            [1, 2, 3, 5, 7]
+        );
+        assert!(groups.resolve("unicorn").is_err());
+        assert!(groups.resolve("200").is_err());
+    }
+
+    #[test]
+    fn test_configured_groups_extend_and_override_defaults() {
+        let mut groups = ClassGroups::default();
+        groups
+            .extend("pets=dog,cat; vehicle=car,truck; critters=pets,bird")
+            .unwrap();
+
+        assert_eq!(
+            groups
+                .resolve("vehicle")
+                .unwrap()
+                .iter()
+                .collect::<Vec<_>>(),
+            [2, 7]
+        );
+        assert_eq!(
+            groups
+                .resolve("critters")
+                .unwrap()
+                .iter()
+                .collect::<Vec<_>>(),
+            [14, 15, 16]
+        );
+        assert_eq!(
+            groups.groups_of(16).collect::<Vec<_>>(),
+            ["animal", "pets", "critters"]
+        );
+        assert!(groups.resolve("animal").unwrap().contains(21));
+
+        assert!(groups.clone().extend("person=dog").is_err());
+        assert!(groups.clone().extend("empty=").is_err());
+        assert!(groups.clone().extend("odd=dog,dragon").is_err());
+    }
+
+    #[test]
+    fn test_resolve_list() {
+        let groups = ClassGroups::default();
+
+        let set = groups.resolve_list("person, vehicle").unwrap();
+        assert!(set.contains(0) && set.contains(7) && !set.contains(16));
+        assert!(groups.resolve_list("").unwrap().is_empty());
+    }
+}
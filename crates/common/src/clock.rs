@@ -0,0 +1,185 @@
+//! Timestamps that know which clock they were read from
+//!
+//! Frames are stamped either on the wall clock (ns since the Unix epoch) or, when
+//! the driver provides it, with the V4L2 buffer time on `CLOCK_MONOTONIC`. The two
+//! are unrelated: a difference between them is meaningless, and as plain `u64`s
+//! nothing stops it. Timestamps are carried as [`WallClockNs`] or [`MonotonicNs`],
+//! and a [`Timestamp`] tags a value whose clock is only known at runtime, as read
+//! from the schema. Conversions happen at the edges, where a value is written or
+//! compared with the current time.
+
+use serde::{Deserialize, Serialize};
+use std::ops::Add;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Nanoseconds since the Unix epoch
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct WallClockNs(u64);
+
+/// Nanoseconds on `CLOCK_MONOTONIC`, shared by every process of the host and
+/// unaffected by wall clock adjustments
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MonotonicNs(u64);
+
+impl WallClockNs {
+    pub fn now() -> Self {
+        Self(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64),
+        )
+    }
+
+    pub const fn from_nanos(ns: u64) -> Self {
+        Self(ns)
+    }
+
+    pub const fn as_nanos(self) -> u64 {
+        self.0
+    }
+
+    /// Time from `earlier` to `self`, zero if `earlier` is later
+    pub fn saturating_duration_since(self, earlier: Self) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+}
+
+impl MonotonicNs {
+    pub fn now() -> Self {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: CLOCK_MONOTONIC is always available and ts is a valid timespec
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+        // time_t and c_long are 32 bits on some targets
+        #[allow(clippy::unnecessary_cast)]
+        Self::from_parts(ts.tv_sec as i64, ts.tv_nsec as i64)
+    }
+
+    pub const fn from_nanos(ns: u64) -> Self {
+        Self(ns)
+    }
+
+    /// From a `timeval`, the microsecond resolution V4L2 buffers are stamped with
+    pub fn from_timeval(sec: i64, usec: i64) -> Self {
+        Self::from_parts(sec, usec * 1_000)
+    }
+
+    fn from_parts(sec: i64, nsec: i64) -> Self {
+        Self((sec.max(0) as u64) * 1_000_000_000 + nsec.max(0) as u64)
+    }
+
+    pub const fn as_nanos(self) -> u64 {
+        self.0
+    }
+
+    /// Time from `earlier` to `self`, zero if `earlier` is later
+    pub fn saturating_duration_since(self, earlier: Self) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(earlier.0))
+    }
+
+    /// The same instant on the wall clock, from the current offset between the clocks
+    pub fn to_wall_clock(self) -> WallClockNs {
+        let (wall, monotonic) = (WallClockNs::now(), Self::now());
+        if self <= monotonic {
+            WallClockNs(wall.0.saturating_sub(monotonic.0 - self.0))
+        } else {
+            WallClockNs(wall.0 + (self.0 - monotonic.0))
+        }
+    }
+}
+
+impl Add<Duration> for WallClockNs {
+    type Output = Self;
+
+    fn add(self, duration: Duration) -> Self {
+        Self(self.0 + duration.as_nanos() as u64)
+    }
+}
+
+impl Add<Duration> for MonotonicNs {
+    type Output = Self;
+
+    fn add(self, duration: Duration) -> Self {
+        Self(self.0 + duration.as_nanos() as u64)
+    }
+}
+
+/// A timestamp tagged with its clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timestamp {
+    WallClock(WallClockNs),
+    Monotonic(MonotonicNs),
+}
+
+impl Timestamp {
+    /// The instant on the wall clock, for comparing with wall clock times or
+    /// reporting outside the host
+    pub fn to_wall_clock(self) -> WallClockNs {
+        match self {
+            Self::WallClock(ns) => ns,
+            Self::Monotonic(ns) => ns.to_wall_clock(),
+        }
+    }
+
+    /// Raw value, in ns on its own clock
+    pub fn as_nanos(self) -> u64 {
+        match self {
+            Self::WallClock(ns) => ns.as_nanos(),
+            Self::Monotonic(ns) => ns.as_nanos(),
+        }
+    }
+}
+
+impl From<WallClockNs> for Timestamp {
+    fn from(ns: WallClockNs) -> Self {
+        Self::WallClock(ns)
+    }
+}
+
+impl From<MonotonicNs> for Timestamp {
+    fn from(ns: MonotonicNs) -> Self {
+        Self::Monotonic(ns)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_monotonic_converts_to_wall_clock() {
+        let wall = WallClockNs::now();
+        let earlier = MonotonicNs::now().as_nanos() - 2_000_000_000;
+
+        let converted = Timestamp::Monotonic(MonotonicNs::from_nanos(earlier)).to_wall_clock();
+        let age = wall.saturating_duration_since(converted);
+        assert!(age >= Duration::from_millis(1_900) && age <= Duration::from_millis(2_100));
+
+        let stamp = Timestamp::WallClock(wall);
+        assert_eq!(stamp.to_wall_clock(), wall);
+        assert_eq!(stamp.as_nanos(), wall.as_nanos());
+    }
+
+    #[test]
+    fn test_timeval_and_durations() {
+        let stamp = MonotonicNs::from_timeval(3, 250_000);
+        assert_eq!(stamp.as_nanos(), 3_250_000_000);
+        assert_eq!(
+            (stamp + Duration::from_millis(750)).saturating_duration_since(stamp),
+            Duration::from_millis(750)
+        );
+        assert_eq!(
+            stamp.saturating_duration_since(stamp + Duration::from_secs(1)),
+            Duration::ZERO
+        );
+        assert_eq!(
+            serde_json::to_string(&WallClockNs::from_nanos(42)).unwrap(),
+            "42"
+        );
+    }
+}
@@ -1,16 +1,46 @@
-use std::{env, str::FromStr};
+use crate::config_check::{self, Source};
+use crate::profile::profile_value;
+use std::{env, fmt::Display, str::FromStr};
 
-/// Get an environment variable and parse it, returning a default if not set or parse fails.
-pub fn get_env<T: FromStr>(key: &str, default: T) -> T {
+/// Raw value of a setting, from the environment or else the loaded camera profile
+fn lookup(key: &str) -> Option<(String, Source)> {
     env::var(key)
         .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(default)
+        .map(|value| (value, Source::Env))
+        .or_else(|| profile_value(key).map(|value| (value.to_string(), Source::Profile)))
+}
+
+/// Get an environment variable and parse it, returning a default if not set or parse fails.
+pub fn get_env<T: FromStr + Display>(key: &str, default: T) -> T {
+    let raw = lookup(key);
+    let parsed = raw.as_ref().and_then(|(s, _)| s.parse().ok());
+
+    if config_check::recording() {
+        let (value, source, rejected) = match (raw, &parsed) {
+            (Some((raw, source)), Some(_)) => (raw, source, None),
+            (raw, _) => (
+                default.to_string(),
+                Source::Default,
+                raw.map(|(raw, _)| raw),
+            ),
+        };
+        config_check::record(key, Some(value), source, rejected);
+    }
+    parsed.unwrap_or(default)
 }
 
 /// Get an optional environment variable. Returns `None` if not set, `Some(T)` if set and parseable.
 pub fn get_env_opt<T: FromStr>(key: &str) -> Option<T> {
-    env::var(key).ok().and_then(|s| s.parse().ok())
+    let raw = lookup(key);
+    let parsed = raw.as_ref().and_then(|(s, _)| s.parse().ok());
+
+    if config_check::recording() {
+        match (raw, &parsed) {
+            (Some((raw, source)), Some(_)) => config_check::record(key, Some(raw), source, None),
+            (raw, _) => config_check::record(key, None, Source::Default, raw.map(|(raw, _)| raw)),
+        }
+    }
+    parsed
 }
 
 #[derive(Debug, Clone)]
@@ -28,8 +58,7 @@ impl Environment {
     }
 
     pub fn from_env() -> Self {
-        match env::var("ENVIRONMENT")
-            .unwrap_or_else(|_| "development".to_string())
+        match get_env("ENVIRONMENT", "development".to_string())
             .to_lowercase()
             .as_str()
         {
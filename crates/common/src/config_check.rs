@@ -0,0 +1,350 @@
+//! `--check-config`: validate a service's configuration without starting it
+//!
+//! While a check runs, every [`crate::get_env`] and [`crate::get_env_opt`] lookup
+//! is recorded with where its value came from. The check then prints the
+//! effective settings with their sources, followed by every problem found:
+//! values that failed to parse and silently fell back to their default,
+//! environment variables one typo away from a setting the service reads, and
+//! whatever the service's own `check` reports (missing files, unresolvable hosts,
+//! out of range thresholds). Those checks cover what `from_env` accepts but the
+//! service cannot run with, not what `from_env` already rejects. The process
+//! exits non-zero when there is any problem.
+
+use anyhow::Result;
+use std::fmt::Display;
+use std::net::ToSocketAddrs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub const FLAG: &str = "--check-config";
+
+/// Lookups recorded while a check runs, `None` otherwise
+static RECORDED: Mutex<Option<Vec<Lookup>>> = Mutex::new(None);
+/// Fast path for lookups outside a check
+static RECORDING: AtomicBool = AtomicBool::new(false);
+
+/// Settings whose value is never printed
+const SECRET_MARKERS: [&str; 4] = ["PASSWORD", "SECRET", "TOKEN", "KEY_ID"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Env,
+    Profile,
+    Default,
+}
+
+impl Source {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Env => "env",
+            Self::Profile => "profile",
+            Self::Default => "default",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Lookup {
+    key: String,
+    /// Effective value, `None` for an unset optional setting
+    value: Option<String>,
+    source: Source,
+    /// Raw value that failed to parse
+    rejected: Option<String>,
+}
+
+/// Whether lookups should be passed to [`record`]
+pub(crate) fn recording() -> bool {
+    RECORDING.load(Ordering::Relaxed)
+}
+
+/// Record a lookup of `key` resolved to `value`, after `rejected` failed to parse
+pub(crate) fn record(key: &str, value: Option<String>, source: Source, rejected: Option<String>) {
+    let mut recorded = RECORDED.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(lookups) = recorded.as_mut() {
+        lookups.push(Lookup {
+            key: key.to_string(),
+            value,
+            source,
+            rejected,
+        });
+    }
+}
+
+/// Whether the process was started with `--check-config`
+pub fn requested() -> bool {
+    std::env::args().skip(1).any(|arg| arg == FLAG)
+}
+
+/// Problems found by a service's checks
+#[derive(Debug, Default)]
+pub struct ConfigCheck {
+    problems: Vec<String>,
+}
+
+impl ConfigCheck {
+    pub fn problem(&mut self, message: impl Into<String>) {
+        self.problems.push(message.into());
+    }
+
+    /// Report `message` unless `ok`
+    pub fn ensure(&mut self, ok: bool, message: impl FnOnce() -> String) {
+        if !ok {
+            self.problem(message());
+        }
+    }
+
+    pub fn in_range<T: PartialOrd + Display>(
+        &mut self,
+        key: &str,
+        value: T,
+        range: RangeInclusive<T>,
+    ) {
+        if !range.contains(&value) {
+            self.problem(format!(
+                "{key}: {value} is outside {}..={}",
+                range.start(),
+                range.end()
+            ));
+        }
+    }
+
+    /// `path` exists and can be opened for reading
+    pub fn readable_file(&mut self, key: &str, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        if let Err(e) = std::fs::File::open(path) {
+            self.problem(format!("{key}: cannot read {}: {e}", path.display()));
+        }
+    }
+
+    /// Directory `path`, or the parent it would be created in, exists
+    pub fn creatable_dir(&mut self, key: &str, path: impl AsRef<Path>) {
+        let path = path.as_ref();
+        let existing = path.ancestors().find(|p| p.exists());
+        if !existing.is_some_and(Path::is_dir) {
+            self.problem(format!("{key}: {} cannot be created", path.display()));
+        }
+    }
+
+    /// `host` resolves to at least one address
+    pub fn resolvable(&mut self, key: &str, host: &str, port: u16) {
+        match (host, port).to_socket_addrs() {
+            Ok(addrs) if addrs.len() > 0 => {}
+            Ok(_) => self.problem(format!("{key}: {host} resolves to no address")),
+            Err(e) => self.problem(format!("{key}: cannot resolve {host}: {e}")),
+        }
+    }
+}
+
+/// Run a service's configuration checks, print the report and fail on problems
+///
+/// `check` loads the configuration and validates it; an error it returns counts
+/// as one more problem.
+pub fn run(check: impl FnOnce(&mut ConfigCheck) -> Result<()>) -> Result<()> {
+    *RECORDED.lock().unwrap_or_else(|e| e.into_inner()) = Some(Vec::new());
+    RECORDING.store(true, Ordering::Relaxed);
+    let mut report = ConfigCheck::default();
+    if let Err(e) = check(&mut report) {
+        report.problem(format!("{e:#}"));
+    }
+    RECORDING.store(false, Ordering::Relaxed);
+    let lookups = RECORDED
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+        .unwrap_or_default();
+
+    let lookups = dedup(lookups);
+    let mut problems: Vec<String> = lookups
+        .iter()
+        .filter_map(|lookup| {
+            let raw = lookup.rejected.as_ref()?;
+            Some(format!(
+                "{}: {:?} is not a valid value, using {}",
+                lookup.key,
+                raw,
+                lookup.value.as_deref().unwrap_or("nothing")
+            ))
+        })
+        .collect();
+    problems.extend(typos(&lookups, std::env::vars().map(|(key, _)| key)));
+    problems.extend(report.problems);
+
+    print!("{}", table(&lookups));
+    if problems.is_empty() {
+        println!("\nConfiguration OK");
+        return Ok(());
+    }
+    println!("\nProblems:");
+    for problem in &problems {
+        println!("  - {problem}");
+    }
+    anyhow::bail!("{} configuration problem(s)", problems.len())
+}
+
+/// First lookup of each key, in reading order
+fn dedup(lookups: Vec<Lookup>) -> Vec<Lookup> {
+    let mut seen = std::collections::HashSet::new();
+    lookups
+        .into_iter()
+        .filter(|lookup| seen.insert(lookup.key.clone()))
+        .collect()
+}
+
+fn table(lookups: &[Lookup]) -> String {
+    let rows: Vec<[&str; 3]> = lookups
+        .iter()
+        .map(|lookup| {
+            let value = match &lookup.value {
+                Some(_) if SECRET_MARKERS.iter().any(|m| lookup.key.contains(m)) => "<redacted>",
+                Some(value) => value.as_str(),
+                None => "(unset)",
+            };
+            [lookup.key.as_str(), value, lookup.source.as_str()]
+        })
+        .collect();
+
+    let header = ["VARIABLE", "VALUE", "SOURCE"];
+    let width = |column: usize| {
+        rows.iter()
+            .map(|row| row[column].len())
+            .chain([header[column].len()])
+            .max()
+            .unwrap_or(0)
+    };
+    let (key_width, value_width) = (width(0), width(1));
+
+    std::iter::once(header)
+        .chain(rows)
+        .map(|[key, value, source]| format!("{key:key_width$}  {value:value_width$}  {source}\n"))
+        .collect()
+}
+
+/// Variables in `env` that look like a misspelling of a setting that was read
+fn typos(lookups: &[Lookup], env: impl Iterator<Item = String>) -> Vec<String> {
+    let read: std::collections::HashSet<&str> = lookups.iter().map(|l| l.key.as_str()).collect();
+    let mut typos: Vec<String> = env
+        .filter(|var| var.len() >= 6 && !read.contains(var.as_str()))
+        .filter_map(|var| {
+            let closest = lookups
+                .iter()
+                .filter(|lookup| lookup.source != Source::Env)
+                .find(|lookup| edit_distance(&var, &lookup.key) <= 2)?;
+            Some(format!(
+                "{var} is not read by this service, did you mean {}?",
+                closest.key
+            ))
+        })
+        .collect();
+    typos.sort();
+    typos
+}
+
+/// Levenshtein distance between two ASCII names
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, &ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_env, get_env_opt};
+    use serial_test::serial;
+
+    fn lookup(key: &str, value: Option<&str>, source: Source) -> Lookup {
+        Lookup {
+            key: key.to_string(),
+            value: value.map(str::to_string),
+            source,
+            rejected: None,
+        }
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(
+            edit_distance("CONFIDENCE_THRESHOLD", "CONFIDENCE_THRESHOLD"),
+            0
+        );
+        assert_eq!(
+            edit_distance("CONFIDENCE_TRESHOLD", "CONFIDENCE_THRESHOLD"),
+            1
+        );
+        assert_eq!(edit_distance("MQTT_PORT", "MQTT_HOST"), 2);
+        assert_eq!(edit_distance("FPS", "SENTRY_MODE_FPS"), 12);
+    }
+
+    #[test]
+    fn test_typos_suggest_settings_left_at_default() {
+        let lookups = [
+            lookup("CONFIDENCE_THRESHOLD", Some("0.7"), Source::Default),
+            lookup("DEVICE_ID", Some("0"), Source::Env),
+        ];
+        let env = ["CONFIDENCE_TRESHOLD", "DEVICE_IDS", "HOME"].map(String::from);
+
+        assert_eq!(
+            typos(&lookups, env.into_iter()),
+            ["CONFIDENCE_TRESHOLD is not read by this service, did you mean CONFIDENCE_THRESHOLD?"]
+        );
+    }
+
+    #[test]
+    fn test_table_redacts_secrets() {
+        let table = table(&[
+            lookup("SMTP_HOST", Some("mail"), Source::Env),
+            lookup("SMTP_PASSWORD", Some("hunter2"), Source::Profile),
+            lookup("S3_BUCKET", None, Source::Default),
+        ]);
+
+        assert!(table.starts_with("VARIABLE       VALUE       SOURCE\n"));
+        assert!(table.contains("SMTP_HOST      mail        env\n"));
+        assert!(!table.contains("hunter2"));
+        assert!(table.contains("(unset)"));
+    }
+
+    #[test]
+    #[serial]
+    fn test_run_reports_rejected_values() {
+        unsafe {
+            std::env::set_var("TEST_CHECK_THRESHOLD", "0,7");
+            std::env::set_var("TEST_CHECK_FPS", "5");
+        }
+
+        let result = run(|_| {
+            let threshold = get_env("TEST_CHECK_THRESHOLD", 0.7f32);
+            let fps: Option<u32> = get_env_opt("TEST_CHECK_FPS");
+            assert_eq!((threshold, fps), (0.7, Some(5)));
+            Ok(())
+        });
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "1 configuration problem(s)"
+        );
+
+        let result = run(|check| {
+            get_env_opt::<u32>("TEST_CHECK_FPS");
+            check.in_range("TEST_CHECK_FPS", 5, 1..=3);
+            Ok(())
+        });
+        assert!(result.is_err());
+
+        unsafe {
+            std::env::remove_var("TEST_CHECK_THRESHOLD");
+            std::env::remove_var("TEST_CHECK_FPS");
+        }
+        // Nothing is recorded outside a check
+        assert!(RECORDED.lock().unwrap().is_none());
+    }
+}
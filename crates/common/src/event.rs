@@ -0,0 +1,331 @@
+//! Machine-readable events shared by every outbound channel
+//!
+//! MQTT notifications, occupancy changes, pipeline anomalies, webhooks and the
+//! gateway health endpoint all serialize an [`Event`], so an integration parses
+//! one JSON shape whatever transport it listens on:
+//!
+//! ```json
+//! {"version":1,"device_id":"front-door","timestamp":"2025-01-01T00:00:00+00:00",
+//!  "event_type":"human_detected","state":"Tracking","previous_state":"Validation"}
+//! ```
+//!
+//! `version`, `device_id`, `timestamp` and `event_type` are always present, the
+//! rest depends on `event_type`, see [`EventPayload`]. [`EVENT_VERSION`] is
+//! bumped when a field is removed or changes meaning; adding a field or an event
+//! type does not bump it, so consumers should ignore what they don't know.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Version of the event schema, `version` of every event
+pub const EVENT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventType {
+    /// The controller raised an alarm
+    HumanDetected,
+    ValidationStarted,
+    StandbyResumed,
+    /// Synthetic state change sent to check the alert chain
+    TestNotification,
+    OccupancyChanged,
+    /// Detections appeared in the stream
+    DetectionsStarted,
+    /// Detections have been gone for a while
+    DetectionsCleared,
+    /// Periodic batch of detections
+    Detections,
+    /// The camera view was covered, moved or blinded
+    Tamper,
+    Health,
+    /// A pipeline metric left its usual range, or came back to it
+    Anomaly,
+}
+
+impl EventType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::HumanDetected => "human_detected",
+            Self::ValidationStarted => "validation_started",
+            Self::StandbyResumed => "standby_resumed",
+            Self::TestNotification => "test_notification",
+            Self::OccupancyChanged => "occupancy_changed",
+            Self::DetectionsStarted => "detections_started",
+            Self::DetectionsCleared => "detections_cleared",
+            Self::Detections => "detections",
+            Self::Tamper => "tamper",
+            Self::Health => "health",
+            Self::Anomaly => "anomaly",
+        }
+    }
+}
+
+impl fmt::Display for EventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An event as sent on every channel, see the [module documentation](self)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub version: u32,
+    pub device_id: String,
+    /// RFC 3339 wall clock time the event was raised at
+    pub timestamp: String,
+    pub event_type: EventType,
+    #[serde(flatten)]
+    pub payload: EventPayload,
+}
+
+impl Event {
+    /// Event of the current schema version raised now
+    pub fn new(device_id: &str, event_type: EventType, payload: impl Into<EventPayload>) -> Self {
+        Self {
+            version: EVENT_VERSION,
+            device_id: device_id.to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+            event_type,
+            payload: payload.into(),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<Vec<u8>> {
+        serde_json::to_vec(self)
+    }
+}
+
+/// Fields of an event besides the common ones
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EventPayload {
+    StateChange(StateChange),
+    Occupancy(Occupancy),
+    Detections(DetectionSummary),
+    Tamper(Tamper),
+    Health(Health),
+    Anomaly(Anomaly),
+}
+
+/// Transition of the controller's alarm state machine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateChange {
+    pub state: String,
+    pub previous_state: Option<String>,
+    /// A subject of the previous alarm came back
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reentry: bool,
+}
+
+/// Change of the number of simultaneous alert-class detections
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Occupancy {
+    pub count: u32,
+    pub previous_count: u32,
+}
+
+/// Detections of a frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetectionSummary {
+    pub frame_number: u64,
+    pub timestamp_ns: u64,
+    /// Frame size the detection boxes are expressed in
+    pub width: u32,
+    pub height: u32,
+    pub detections: Vec<EventDetection>,
+    /// Where the frame can be fetched as JPEG, if it is served
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snapshot_url: Option<String>,
+}
+
+/// A detection box, in pixels of the frame
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EventDetection {
+    pub x1: f32,
+    pub y1: f32,
+    pub x2: f32,
+    pub y2: f32,
+    pub confidence: f32,
+    pub class_id: u16,
+}
+
+/// The camera's view can no longer be trusted
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tamper {
+    /// What was detected, e.g. `covered`, `moved`, `blinded`
+    pub reason: String,
+    /// How far the view is from normal, 0-1
+    pub score: f32,
+}
+
+/// Readiness of a service, as served by its health endpoint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    pub service: String,
+    pub ready: bool,
+    /// State of each startup dependency, by name
+    pub dependencies: BTreeMap<String, String>,
+    /// Outcome of the latest run of each periodic check, by name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checks: BTreeMap<String, String>,
+}
+
+/// A pipeline metric out of, or back in, its usual range
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Anomaly {
+    /// e.g. `fps`, `inference_latency_ms`, `detection_rate`
+    pub metric: String,
+    /// Whether the anomaly started or ended
+    pub active: bool,
+    pub value: f64,
+    /// Usual value learned for the current sentry mode, if learned yet
+    pub baseline: Option<f64>,
+    /// Configured limit crossed, when the anomaly is not relative to the baseline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<f64>,
+    pub sentry_mode: String,
+}
+
+impl From<StateChange> for EventPayload {
+    fn from(payload: StateChange) -> Self {
+        Self::StateChange(payload)
+    }
+}
+
+impl From<Occupancy> for EventPayload {
+    fn from(payload: Occupancy) -> Self {
+        Self::Occupancy(payload)
+    }
+}
+
+impl From<DetectionSummary> for EventPayload {
+    fn from(payload: DetectionSummary) -> Self {
+        Self::Detections(payload)
+    }
+}
+
+impl From<Tamper> for EventPayload {
+    fn from(payload: Tamper) -> Self {
+        Self::Tamper(payload)
+    }
+}
+
+impl From<Health> for EventPayload {
+    fn from(payload: Health) -> Self {
+        Self::Health(payload)
+    }
+}
+
+impl From<Anomaly> for EventPayload {
+    fn from(payload: Anomaly) -> Self {
+        Self::Anomaly(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_change_keeps_its_flat_shape() {
+        let mut event = Event::new(
+            "front-door",
+            EventType::HumanDetected,
+            StateChange {
+                state: "Tracking".to_string(),
+                previous_state: Some("Validation".to_string()),
+                reentry: false,
+            },
+        );
+        event.timestamp = "2025-01-01T00:00:00+00:00".to_string();
+
+        let json: serde_json::Value = serde_json::from_slice(&event.to_json().unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "version": EVENT_VERSION,
+                "device_id": "front-door",
+                "timestamp": "2025-01-01T00:00:00+00:00",
+                "event_type": "human_detected",
+                "state": "Tracking",
+                "previous_state": "Validation",
+            })
+        );
+    }
+
+    #[test]
+    fn test_events_round_trip() {
+        let payloads: [(EventType, EventPayload); 5] = [
+            (
+                EventType::OccupancyChanged,
+                Occupancy {
+                    count: 2,
+                    previous_count: 1,
+                }
+                .into(),
+            ),
+            (
+                EventType::DetectionsStarted,
+                DetectionSummary {
+                    frame_number: 42,
+                    timestamp_ns: 1_000,
+                    width: 640,
+                    height: 480,
+                    detections: vec![EventDetection {
+                        x1: 1.0,
+                        y1: 2.0,
+                        x2: 3.0,
+                        y2: 4.0,
+                        confidence: 0.9,
+                        class_id: 0,
+                    }],
+                    snapshot_url: None,
+                }
+                .into(),
+            ),
+            (
+                EventType::Tamper,
+                Tamper {
+                    reason: "covered".to_string(),
+                    score: 0.8,
+                }
+                .into(),
+            ),
+            (
+                EventType::Health,
+                Health {
+                    service: "gateway".to_string(),
+                    ready: true,
+                    dependencies: [("frame_buffer".to_string(), "ready".to_string())].into(),
+                    checks: BTreeMap::new(),
+                }
+                .into(),
+            ),
+            (
+                EventType::Anomaly,
+                Anomaly {
+                    metric: "fps".to_string(),
+                    active: true,
+                    value: 0.5,
+                    baseline: Some(3.0),
+                    limit: None,
+                    sentry_mode: "standby".to_string(),
+                }
+                .into(),
+            ),
+        ];
+
+        for (event_type, payload) in payloads {
+            let event = Event::new("cam", event_type, payload);
+            let parsed: Event = serde_json::from_slice(&event.to_json().unwrap()).unwrap();
+            assert_eq!(parsed, event);
+            assert_eq!(
+                serde_json::to_value(event_type).unwrap(),
+                event_type.as_str()
+            );
+        }
+    }
+}
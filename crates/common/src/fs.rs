@@ -0,0 +1,27 @@
+//! File writes readers can race with
+
+use std::path::Path;
+
+/// Replace `path` with `contents` through a temporary file renamed over it, so
+/// readers see the previous file or the new one, never a partial write
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomic_replaces_the_file() {
+        let path = std::env::temp_dir().join(format!("write_atomic_{}.json", std::process::id()));
+        write_atomic(&path, "{}").unwrap();
+        write_atomic(&path, b"[1]").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[1]");
+        assert!(!path.with_extension("tmp").exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,13 +1,34 @@
+pub mod appearance;
+pub mod classes;
+pub mod clock;
 pub mod config;
+pub mod config_check;
+pub mod event;
+pub mod fs;
 pub mod logging;
+pub mod profile;
+pub mod readiness;
+pub mod rect;
 pub mod retry;
+pub mod scheduling;
 pub mod telemetry;
-pub mod wait;
+pub mod template;
+pub mod thermal;
+pub mod throttle;
+pub mod watchdog;
 
+pub use appearance::Appearance;
+pub use clock::{MonotonicNs, Timestamp, WallClockNs};
 pub use config::{Environment, get_env, get_env_opt};
+pub use config_check::ConfigCheck;
+pub use event::{EVENT_VERSION, Event, EventPayload, EventType};
 pub use logging::setup_logging;
+pub use profile::load_profile;
+pub use readiness::{Dependency, Readiness};
+pub use rect::Rect;
 pub use retry::retry_with_backoff;
+pub use scheduling::Scheduling;
 pub use telemetry::TelemetryGuard;
-pub use wait::wait_for_resource;
-#[cfg(feature = "async")]
-pub use wait::wait_for_resource_async;
+pub use template::Template;
+pub use thermal::ThermalMonitor;
+pub use watchdog::Watchdog;
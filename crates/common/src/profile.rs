@@ -0,0 +1,156 @@
+//! Named per-camera configuration profiles
+//!
+//! A multi-camera setup runs one capture/inference/controller stack per camera,
+//! each needing dozens of environment variables. Profiles bundle those settings
+//! under a name in a JSON file, and each instance picks one with `CAMERA_PROFILE`:
+//!
+//! ```json
+//! {
+//!   "profiles": {
+//!     "doorbell": {
+//!       "capture": {"sentry_mode_fps": 2, "frame_max_dimension": 1280},
+//!       "inference": {"model_path": "/models/rfdetr_nano.onnx", "confidence_threshold": 0.6},
+//!       "controller": {"alert_classes": [0], "smtp_to": "me@example.com"}
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! Sections only group settings for readability: every leaf is the variable of
+//! the same name in upper case, arrays are joined with commas. Variables set in
+//! the environment always win over the profile.
+
+use anyhow::{Context, Result, bail};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Settings of the selected profile, keyed by variable name
+static PROFILE: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+const DEFAULT_PROFILES_PATH: &str = "/etc/detr-mmap/profiles.json";
+
+/// Load the profile named by `CAMERA_PROFILE` as fallback for [`crate::get_env`]
+///
+/// Call at the very start of `main`, before reading any configuration. Does
+/// nothing when `CAMERA_PROFILE` is unset.
+pub fn load_profile() -> Result<()> {
+    let Ok(name) = std::env::var("CAMERA_PROFILE") else {
+        return Ok(());
+    };
+    let path =
+        std::env::var("CAMERA_PROFILES_PATH").unwrap_or_else(|_| DEFAULT_PROFILES_PATH.to_string());
+
+    let json = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read camera profiles file {}", path))?;
+    let settings = parse_profile(&json, &name)
+        .with_context(|| format!("Invalid camera profiles file {}", path))?;
+
+    if PROFILE.set(settings).is_err() {
+        bail!("Camera profile already loaded");
+    }
+    Ok(())
+}
+
+/// Value of `key` in the loaded profile, if any
+pub(crate) fn profile_value(key: &str) -> Option<&'static str> {
+    PROFILE.get()?.get(key).map(String::as_str)
+}
+
+/// Flatten profile `name` from a profiles file into variable names and values
+pub fn parse_profile(json: &str, name: &str) -> Result<HashMap<String, String>> {
+    let root: Value = serde_json::from_str(json)?;
+    let profiles = root
+        .get("profiles")
+        .and_then(Value::as_object)
+        .context("Missing \"profiles\" object")?;
+    let Some(profile) = profiles.get(name) else {
+        let mut known: Vec<_> = profiles.keys().map(String::as_str).collect();
+        known.sort_unstable();
+        bail!(
+            "Unknown profile {:?} (available: {})",
+            name,
+            known.join(", ")
+        );
+    };
+
+    let mut settings = HashMap::new();
+    flatten(profile, None, &mut settings)?;
+    Ok(settings)
+}
+
+fn flatten(value: &Value, key: Option<&str>, settings: &mut HashMap<String, String>) -> Result<()> {
+    let scalar = |value: &Value| match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    };
+
+    let value = match (value, key) {
+        (Value::Object(fields), _) => {
+            for (field, value) in fields {
+                flatten(value, Some(field), settings)?;
+            }
+            return Ok(());
+        }
+        (Value::Null, _) => return Ok(()),
+        (_, None) => bail!("A profile must be an object"),
+        (Value::Array(items), Some(key)) => items
+            .iter()
+            .map(|item| scalar(item).with_context(|| format!("{key} must hold plain values")))
+            .collect::<Result<Vec<_>>>()?
+            .join(","),
+        (value, Some(key)) => {
+            scalar(value).with_context(|| format!("Unsupported value for {key}"))?
+        }
+    };
+
+    let key = key.unwrap_or_default().to_ascii_uppercase();
+    if settings.contains_key(&key) {
+        bail!("{} is set twice", key);
+    }
+    settings.insert(key, value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROFILES: &str = r#"{
+        "profiles": {
+            "doorbell": {
+                "capture": {"sentry_mode_fps": 2, "nvmm_export": false},
+                "inference": {"model_path": "/models/nano.onnx"},
+                "controller": {"alert_classes": [0, "21:10"], "SMTP_TO": "me@example.com"}
+            },
+            "garage": {"capture": {"sentry_mode_fps": 1}}
+        }
+    }"#;
+
+    #[test]
+    fn test_sections_flatten_to_variables() {
+        let settings = parse_profile(PROFILES, "doorbell").unwrap();
+
+        assert_eq!(settings["SENTRY_MODE_FPS"], "2");
+        assert_eq!(settings["NVMM_EXPORT"], "false");
+        assert_eq!(settings["MODEL_PATH"], "/models/nano.onnx");
+        assert_eq!(settings["ALERT_CLASSES"], "0,21:10");
+        assert_eq!(settings["SMTP_TO"], "me@example.com");
+        assert_eq!(settings.len(), 5);
+    }
+
+    #[test]
+    fn test_unknown_profile_lists_available_ones() {
+        let err = parse_profile(PROFILES, "porch").unwrap_err();
+        assert!(err.to_string().contains("doorbell, garage"), "{err}");
+    }
+
+    #[test]
+    fn test_duplicate_settings_are_rejected() {
+        let json =
+            r#"{"profiles": {"p": {"a": {"sentry_mode_fps": 1}, "b": {"SENTRY_MODE_FPS": 2}}}}"#;
+        assert!(parse_profile(json, "p").is_err());
+    }
+}
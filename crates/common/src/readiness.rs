@@ -0,0 +1,418 @@
+//! Startup dependency tracking
+//!
+//! Services start in any order and each one waits for the buffers and semaphores
+//! created by its peers. `Readiness` names those dependencies, waits for them with a
+//! shared startup deadline and keeps a machine-readable view of what is still pending.
+//!
+//! The view is exposed as JSON through [`Readiness::to_json`], written to
+//! `READINESS_FILE` on every change (for exec probes and supervisors) and served by
+//! the gateway health endpoint.
+//!
+//! Periodic checks (e.g. the inference self-test) report alongside the
+//! dependencies through [`Readiness::set_check`]; they do not affect readiness.
+//!
+//! `READINESS_DEADLINE_SECS` bounds the whole startup; without it services wait
+//! forever, as before.
+
+use crate::event::Health;
+use crate::fs::write_atomic;
+use crate::get_env_opt;
+use anyhow::{Result, bail};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Something a service needs before it can do useful work
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dependency {
+    FrameBuffer,
+    DetectionBuffer,
+    Semaphores,
+    ModelLoaded,
+}
+
+impl Dependency {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::FrameBuffer => "frame_buffer",
+            Self::DetectionBuffer => "detection_buffer",
+            Self::Semaphores => "semaphores",
+            Self::ModelLoaded => "model_loaded",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyState {
+    Pending,
+    Ready,
+    /// The startup deadline passed before the dependency became available
+    TimedOut,
+}
+
+impl DependencyState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Ready => "ready",
+            Self::TimedOut => "timed_out",
+        }
+    }
+}
+
+struct Inner {
+    dependencies: Vec<(Dependency, DependencyState)>,
+    /// Outcome of the latest run of each check, by name
+    checks: Vec<(&'static str, bool)>,
+    deadline: Option<Instant>,
+    state_file: Option<PathBuf>,
+}
+
+/// Shared readiness state of a service, cheap to clone
+#[derive(Clone)]
+pub struct Readiness {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Readiness {
+    /// Track `dependencies`, reading `READINESS_DEADLINE_SECS` and `READINESS_FILE`
+    pub fn from_env(dependencies: &[Dependency]) -> Self {
+        Self::new(
+            dependencies,
+            get_env_opt::<u64>("READINESS_DEADLINE_SECS").map(Duration::from_secs),
+            get_env_opt("READINESS_FILE"),
+        )
+    }
+
+    pub fn new(
+        dependencies: &[Dependency],
+        deadline: Option<Duration>,
+        state_file: Option<PathBuf>,
+    ) -> Self {
+        let readiness = Self {
+            inner: Arc::new(Mutex::new(Inner {
+                dependencies: dependencies
+                    .iter()
+                    .map(|dep| (*dep, DependencyState::Pending))
+                    .collect(),
+                checks: Vec::new(),
+                deadline: deadline.map(|d| Instant::now() + d),
+                state_file,
+            })),
+        };
+        readiness.publish();
+        readiness
+    }
+
+    /// Whether every dependency is ready
+    pub fn is_ready(&self) -> bool {
+        self.lock()
+            .dependencies
+            .iter()
+            .all(|(_, state)| *state == DependencyState::Ready)
+    }
+
+    pub fn state(&self, dependency: Dependency) -> Option<DependencyState> {
+        self.lock()
+            .dependencies
+            .iter()
+            .find(|(dep, _)| *dep == dependency)
+            .map(|(_, state)| *state)
+    }
+
+    /// Mark a dependency that is not waited on (e.g. a loaded model) as ready
+    pub fn mark_ready(&self, dependency: Dependency) {
+        self.set(dependency, DependencyState::Ready);
+        tracing::info!(dependency = dependency.name(), "Dependency ready");
+    }
+
+    /// Record whether the latest run of the check `name` passed
+    pub fn set_check(&self, name: &'static str, passed: bool) {
+        {
+            let mut inner = self.lock();
+            match inner.checks.iter_mut().find(|(check, _)| *check == name) {
+                Some(entry) => entry.1 = passed,
+                None => inner.checks.push((name, passed)),
+            }
+        }
+        self.publish();
+    }
+
+    /// Poll `connect` until it succeeds or the startup deadline passes
+    ///
+    /// Several resources may belong to one dependency (e.g. a service's semaphores);
+    /// the dependency is pending again while each of them is waited on.
+    pub fn wait_for<F, T, E>(
+        &self,
+        dependency: Dependency,
+        resource_name: &str,
+        poll_interval: Duration,
+        mut connect: F,
+    ) -> Result<T>
+    where
+        F: FnMut() -> Result<T, E>,
+        E: std::fmt::Display,
+    {
+        self.set(dependency, DependencyState::Pending);
+        loop {
+            match connect() {
+                Ok(resource) => {
+                    self.connected(dependency, resource_name);
+                    return Ok(resource);
+                }
+                Err(e) => {
+                    self.check_deadline(dependency, resource_name, &e)?;
+                    tracing::debug!("Waiting for {} ({})", resource_name, e);
+                    std::thread::sleep(poll_interval);
+                }
+            }
+        }
+    }
+
+    /// Async variant of [`Readiness::wait_for`]
+    #[cfg(feature = "async")]
+    pub async fn wait_for_async<F, T, E>(
+        &self,
+        dependency: Dependency,
+        resource_name: &str,
+        poll_interval: Duration,
+        mut connect: F,
+    ) -> Result<T>
+    where
+        F: FnMut() -> Result<T, E>,
+        E: std::fmt::Display,
+    {
+        self.set(dependency, DependencyState::Pending);
+        loop {
+            match connect() {
+                Ok(resource) => {
+                    self.connected(dependency, resource_name);
+                    return Ok(resource);
+                }
+                Err(e) => {
+                    self.check_deadline(dependency, resource_name, &e)?;
+                    tracing::debug!("Waiting for {} ({})", resource_name, e);
+                    tokio::time::sleep(poll_interval).await;
+                }
+            }
+        }
+    }
+
+    /// Readiness as JSON, e.g. `{"ready":false,"dependencies":{"frame_buffer":"pending"}}`
+    ///
+    /// Checks that ran are listed under `checks`, e.g. `"checks":{"self_test":"passed"}`.
+    pub fn to_json(&self) -> String {
+        let inner = self.lock();
+        let ready = inner
+            .dependencies
+            .iter()
+            .all(|(_, state)| *state == DependencyState::Ready);
+        let dependencies = inner
+            .dependencies
+            .iter()
+            .map(|(dep, state)| format!("\"{}\":\"{}\"", dep.name(), state.as_str()))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        let checks = match inner.checks.as_slice() {
+            [] => String::new(),
+            checks => {
+                let checks = checks
+                    .iter()
+                    .map(|(name, passed)| {
+                        let state = if *passed { "passed" } else { "failed" };
+                        format!("\"{}\":\"{}\"", name, state)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!(",\"checks\":{{{}}}", checks)
+            }
+        };
+
+        format!(
+            "{{\"ready\":{},\"dependencies\":{{{}}}{}}}",
+            ready, dependencies, checks
+        )
+    }
+
+    /// The view as the payload of a `health` [`Event`](crate::Event) of `service`
+    pub fn health(&self, service: &str) -> Health {
+        let inner = self.lock();
+        let outcome = |passed: bool| if passed { "passed" } else { "failed" };
+        Health {
+            service: service.to_string(),
+            ready: inner
+                .dependencies
+                .iter()
+                .all(|(_, state)| *state == DependencyState::Ready),
+            dependencies: inner
+                .dependencies
+                .iter()
+                .map(|(dep, state)| (dep.name().to_string(), state.as_str().to_string()))
+                .collect(),
+            checks: inner
+                .checks
+                .iter()
+                .map(|(name, passed)| (name.to_string(), outcome(*passed).to_string()))
+                .collect(),
+        }
+    }
+
+    fn connected(&self, dependency: Dependency, resource_name: &str) {
+        self.set(dependency, DependencyState::Ready);
+        tracing::info!("{} connected", resource_name);
+    }
+
+    fn check_deadline(
+        &self,
+        dependency: Dependency,
+        resource_name: &str,
+        error: &dyn std::fmt::Display,
+    ) -> Result<()> {
+        if self.lock().deadline.is_some_and(|d| Instant::now() >= d) {
+            self.set(dependency, DependencyState::TimedOut);
+            bail!(
+                "Startup deadline exceeded waiting for {} ({})",
+                resource_name,
+                error
+            );
+        }
+        Ok(())
+    }
+
+    fn set(&self, dependency: Dependency, state: DependencyState) {
+        {
+            let mut inner = self.lock();
+            match inner
+                .dependencies
+                .iter_mut()
+                .find(|(dep, _)| *dep == dependency)
+            {
+                Some(entry) => entry.1 = state,
+                None => inner.dependencies.push((dependency, state)),
+            }
+        }
+        self.publish();
+    }
+
+    /// Write the JSON state to `READINESS_FILE`, replacing it atomically
+    fn publish(&self) {
+        let Some(path) = self.lock().state_file.clone() else {
+            return;
+        };
+
+        if let Err(e) = write_atomic(&path, self.to_json()) {
+            tracing::warn!(path = %path.display(), error = %e, "Failed to write readiness file");
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ready_once_all_dependencies_are() {
+        let readiness = Readiness::new(
+            &[Dependency::FrameBuffer, Dependency::ModelLoaded],
+            None,
+            None,
+        );
+        assert!(!readiness.is_ready());
+
+        let value = readiness
+            .wait_for(
+                Dependency::FrameBuffer,
+                "Frame buffer",
+                Duration::ZERO,
+                || Ok::<_, String>(7),
+            )
+            .unwrap();
+        assert_eq!(value, 7);
+        assert!(!readiness.is_ready());
+
+        readiness.mark_ready(Dependency::ModelLoaded);
+        assert!(readiness.is_ready());
+        assert_eq!(
+            readiness.to_json(),
+            r#"{"ready":true,"dependencies":{"frame_buffer":"ready","model_loaded":"ready"}}"#
+        );
+    }
+
+    #[test]
+    fn test_checks_are_reported_without_affecting_readiness() {
+        let readiness = Readiness::new(&[Dependency::ModelLoaded], None, None);
+        readiness.mark_ready(Dependency::ModelLoaded);
+
+        readiness.set_check("self_test", true);
+        readiness.set_check("self_test", false);
+        assert!(readiness.is_ready());
+        assert_eq!(
+            readiness.to_json(),
+            r#"{"ready":true,"dependencies":{"model_loaded":"ready"},"checks":{"self_test":"failed"}}"#
+        );
+
+        let health = readiness.health("inference");
+        assert!(health.ready);
+        assert_eq!(health.checks["self_test"], "failed");
+    }
+
+    #[test]
+    fn test_wait_retries_until_available() {
+        let readiness = Readiness::new(&[Dependency::Semaphores], None, None);
+        let mut attempts = 0;
+        readiness
+            .wait_for(
+                Dependency::Semaphores,
+                "Semaphore",
+                Duration::from_millis(1),
+                || {
+                    attempts += 1;
+                    if attempts < 3 { Err("missing") } else { Ok(()) }
+                },
+            )
+            .unwrap();
+        assert_eq!(attempts, 3);
+        assert!(readiness.is_ready());
+    }
+
+    #[test]
+    fn test_deadline_marks_timed_out() {
+        let readiness = Readiness::new(&[Dependency::DetectionBuffer], Some(Duration::ZERO), None);
+        let result = readiness.wait_for(
+            Dependency::DetectionBuffer,
+            "Detection buffer",
+            Duration::from_millis(1),
+            || Err::<(), _>("missing"),
+        );
+        assert!(result.is_err());
+        assert_eq!(
+            readiness.state(Dependency::DetectionBuffer),
+            Some(DependencyState::TimedOut)
+        );
+    }
+
+    #[test]
+    fn test_state_file_tracks_changes() {
+        let path = std::env::temp_dir().join(format!("readiness-{}.json", std::process::id()));
+        let readiness = Readiness::new(&[Dependency::ModelLoaded], None, Some(path.clone()));
+        assert!(
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .contains(r#""model_loaded":"pending""#)
+        );
+
+        readiness.mark_ready(Dependency::ModelLoaded);
+        assert!(
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .starts_with(r#"{"ready":true"#)
+        );
+
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,113 @@
+//! Rectangles of the frame given in configuration
+//!
+//! Zones and masks are in coordinates normalized to the frame size, from `0,0` at
+//! its top left corner to `1,1` at its bottom right, so they keep covering the same
+//! part of the scene whatever resolution the camera runs at or frames are
+//! downscaled to. In environment variables they are written `x1,y1,x2,y2`, e.g.
+//! `0,0.5,1,1` for the lower half of the frame.
+
+use anyhow::{Context, Result, bail, ensure};
+
+/// Rectangle in coordinates normalized to the frame size, corners in order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x1: f32,
+    pub y1: f32,
+    pub x2: f32,
+    pub y2: f32,
+}
+
+impl Rect {
+    pub const FULL_FRAME: Self = Self {
+        x1: 0.0,
+        y1: 0.0,
+        x2: 1.0,
+        y2: 1.0,
+    };
+
+    /// Rectangle from its top left and bottom right corners, which must lie in the
+    /// frame
+    pub fn new(x1: f32, y1: f32, x2: f32, y2: f32) -> Result<Self> {
+        ensure!(
+            [x1, y1, x2, y2].iter().all(|v| (0.0..=1.0).contains(v)),
+            "Coordinates must be normalized to the frame size, between 0 and 1, got {x1},{y1},{x2},{y2}"
+        );
+        ensure!(
+            x1 < x2 && y1 < y2,
+            "Corners are inverted: {x1},{y1},{x2},{y2}"
+        );
+        Ok(Self { x1, y1, x2, y2 })
+    }
+
+    /// Parse `x1,y1,x2,y2`
+    pub fn parse(value: &str) -> Result<Self> {
+        let coords = value
+            .split(',')
+            .map(|v| v.trim().parse::<f32>())
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Invalid coordinates {value:?}"))?;
+        let [x1, y1, x2, y2] = coords[..] else {
+            bail!("Expected x1,y1,x2,y2, got {value:?}");
+        };
+        Self::new(x1, y1, x2, y2)
+    }
+
+    /// Whether the normalized point `x`, `y` lies in the rectangle, edges included
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x1 && x <= self.x2 && y >= self.y1 && y <= self.y2
+    }
+
+    /// Whether the center of the box `x1, y1, x2, y2`, in the pixel coordinates of a
+    /// `width` x `height` frame, lies in the rectangle
+    pub fn contains_center(&self, [x1, y1, x2, y2]: [f32; 4], (width, height): (u32, u32)) -> bool {
+        self.contains(
+            (x1 + x2) / 2.0 / width.max(1) as f32,
+            (y1 + y2) / 2.0 / height.max(1) as f32,
+        )
+    }
+
+    /// Pixel bounds in a `width` x `height` frame as (x1, y1, x2, y2)
+    pub fn pixels(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let scale = |v: f32, size: u32| ((v * size as f32) as u32).min(size);
+        (
+            scale(self.x1, width),
+            scale(self.y1, height),
+            scale(self.x2, width),
+            scale(self.y2, height),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let rect = Rect::parse("0.4, 0.3, 0.8,1").unwrap();
+        assert_eq!(rect, Rect::new(0.4, 0.3, 0.8, 1.0).unwrap());
+
+        assert!(Rect::parse("0.1,0.2,0.3").is_err());
+        assert!(Rect::parse("a,b,c,d").is_err());
+        assert!(Rect::parse("0.5,0.5,0.1,0.9").is_err(), "inverted");
+        assert!(Rect::parse("0,360,1280,720").is_err(), "pixels");
+        assert!(Rect::parse("0,0,NaN,1").is_err());
+    }
+
+    #[test]
+    fn test_contains() {
+        let rect = Rect::new(0.5, 0.0, 1.0, 0.5).unwrap();
+        assert!(rect.contains(0.5, 0.5));
+        assert!(!rect.contains(0.4, 0.2));
+
+        assert!(rect.contains_center([900.0, 100.0, 1000.0, 200.0], (1280, 720)));
+        assert!(!rect.contains_center([100.0, 100.0, 200.0, 200.0], (1280, 720)));
+    }
+
+    #[test]
+    fn test_pixels() {
+        let rect = Rect::new(0.5, 0.25, 1.0, 1.0).unwrap();
+        assert_eq!(rect.pixels(640, 480), (320, 120, 640, 480));
+        assert_eq!(Rect::FULL_FRAME.pixels(64, 36), (0, 0, 64, 36));
+    }
+}
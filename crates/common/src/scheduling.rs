@@ -0,0 +1,169 @@
+//! CPU affinity and real-time scheduling of a service's processing thread
+//!
+//! `CPU_AFFINITY` pins the thread to a list of cores (`2,3` or `2-3`), and
+//! `REALTIME_PRIORITY` (1-99) runs it under `SCHED_FIFO`. Threads the processing
+//! thread spawns afterwards inherit both. Real-time scheduling needs
+//! `CAP_SYS_NICE` (or an `RLIMIT_RTPRIO` allowing the priority); without it the
+//! service logs a warning and keeps the default policy.
+
+use crate::{ConfigCheck, get_env, get_env_opt};
+use anyhow::{Context, Result};
+use std::io;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Scheduling {
+    /// Cores the thread may run on, any core when empty
+    pub cpus: Vec<usize>,
+    /// `SCHED_FIFO` priority, the default policy when unset
+    pub realtime_priority: Option<i32>,
+}
+
+impl Scheduling {
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            cpus: parse_cpu_list(&get_env("CPU_AFFINITY", String::new()))?,
+            realtime_priority: get_env_opt("REALTIME_PRIORITY"),
+        })
+    }
+
+    /// Check `REALTIME_PRIORITY` is a valid priority and `CPU_AFFINITY` only names
+    /// cores this process may run on
+    pub fn check(&self, check: &mut ConfigCheck) {
+        if let Some(priority) = self.realtime_priority {
+            check.in_range("REALTIME_PRIORITY", priority, 1..=99);
+        }
+        if let Ok(allowed) = affinity() {
+            for cpu in self.cpus.iter().filter(|cpu| !allowed.contains(cpu)) {
+                check.problem(format!("CPU_AFFINITY: core {cpu} is not available"));
+            }
+        }
+    }
+
+    /// Apply both settings to the calling thread
+    pub fn apply(&self) {
+        self.apply_affinity();
+        self.apply_realtime();
+    }
+
+    /// Pin the calling thread to `cpus`, logging the cores it ends up allowed on
+    pub fn apply_affinity(&self) {
+        if self.cpus.is_empty() {
+            return;
+        }
+        match set_affinity(&self.cpus).and_then(|()| affinity()) {
+            Ok(cpus) => tracing::info!(?cpus, "CPU affinity applied"),
+            Err(e) => tracing::warn!(cpus = ?self.cpus, error = %e, "Failed to set CPU affinity"),
+        }
+    }
+
+    /// Switch the calling thread to `SCHED_FIFO`, keeping the default policy on failure
+    pub fn apply_realtime(&self) {
+        let Some(priority) = self.realtime_priority else {
+            return;
+        };
+        match set_fifo(priority) {
+            Ok(()) => tracing::info!(priority, "SCHED_FIFO scheduling applied"),
+            Err(e) if e.raw_os_error() == Some(libc::EPERM) => tracing::warn!(
+                priority,
+                "SCHED_FIFO not permitted (needs CAP_SYS_NICE), keeping default scheduling"
+            ),
+            Err(e) => tracing::warn!(priority, error = %e, "Failed to set SCHED_FIFO scheduling"),
+        }
+    }
+}
+
+/// Parse a list of cores such as `0,2-3`
+pub fn parse_cpu_list(value: &str) -> Result<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for part in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let parse = |s: &str| {
+            s.trim()
+                .parse::<usize>()
+                .with_context(|| format!("Invalid core {:?} in CPU_AFFINITY", s))
+        };
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (parse(first)?, parse(last)?),
+            None => (parse(part)?, parse(part)?),
+        };
+        anyhow::ensure!(first <= last, "CPU_AFFINITY range {:?} is inverted", part);
+        anyhow::ensure!(
+            last < libc::CPU_SETSIZE as usize,
+            "CPU_AFFINITY core {} is out of range",
+            last
+        );
+        cpus.extend(first..=last);
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+fn set_affinity(cpus: &[usize]) -> io::Result<()> {
+    // SAFETY: cpu_set_t is plain data, zeroed is an empty set
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for &cpu in cpus {
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    // pid 0 is the calling thread
+    let ret = unsafe { libc::sched_setaffinity(0, std::mem::size_of_val(&set), &set) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Cores the calling thread may run on
+fn affinity() -> io::Result<Vec<usize>> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    let ret = unsafe { libc::sched_getaffinity(0, std::mem::size_of_val(&set), &mut set) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((0..libc::CPU_SETSIZE as usize)
+        .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) })
+        .collect())
+}
+
+fn set_fifo(priority: i32) -> io::Result<()> {
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+    let ret = unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) };
+    if ret != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("").unwrap(), Vec::<usize>::new());
+        assert_eq!(parse_cpu_list("3, 1").unwrap(), vec![1, 3]);
+        assert_eq!(parse_cpu_list("0,2-4,3").unwrap(), vec![0, 2, 3, 4]);
+        assert!(parse_cpu_list("4-2").is_err());
+        assert!(parse_cpu_list("a").is_err());
+        assert!(parse_cpu_list("100000").is_err());
+    }
+
+    #[test]
+    fn test_affinity_applies_to_calling_thread() {
+        let allowed = affinity().unwrap();
+        let first = allowed[0];
+        std::thread::spawn(move || {
+            Scheduling {
+                cpus: vec![first],
+                realtime_priority: None,
+            }
+            .apply();
+            assert_eq!(affinity().unwrap(), vec![first]);
+        })
+        .join()
+        .unwrap();
+        // The test thread keeps its cores
+        assert_eq!(affinity().unwrap(), allowed);
+    }
+}
@@ -0,0 +1,301 @@
+//! Payload templates for outbound notifications
+//!
+//! Email, MQTT and webhook payloads can be rewritten with a small
+//! handlebars-like syntax over the fields of the event being sent:
+//!
+//! - `{{field}}` is the field's value, empty when the event has none
+//! - `{{json field}}` is the value as a JSON string, `null` when missing, for
+//!   JSON payloads
+//! - `{{#if field}}...{{else}}...{{/if}}` keeps the first part when the field
+//!   has a non-empty value and the `{{else}}` part, which is optional, otherwise.
+//!   Blocks nest.
+//! - `{field}` is the placeholder of the earlier email templates and still works;
+//!   a missing value reads `none` there, as it did
+//!
+//! Other braces are copied as is, so `{"state":"{{state}}"}` renders a JSON
+//! object. A template is parsed once, against the fields its channel knows, so a
+//! misspelt field or an unclosed block stops the service at startup instead of
+//! garbling every notification.
+
+use anyhow::{Result, anyhow, bail};
+use chrono::{DateTime, Local};
+
+/// How `local_time` fields read, in the host's time zone (`TZ`)
+pub const LOCAL_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S %:z";
+
+/// The RFC 3339 `timestamp` of an event as a `local_time` field
+pub fn local_time(timestamp: &str) -> Option<String> {
+    DateTime::parse_from_rfc3339(timestamp).ok().map(|t| {
+        t.with_timezone(&Local)
+            .format(LOCAL_TIME_FORMAT)
+            .to_string()
+    })
+}
+
+/// A parsed template, see the [module documentation](self)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Field {
+        name: String,
+        style: Style,
+    },
+    If {
+        name: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Style {
+    Plain,
+    Json,
+    /// Single-brace placeholder
+    Legacy,
+}
+
+/// An `{{#if}}` block being parsed, or the template itself at the bottom
+struct Block {
+    /// Field tested and offset of the opening tag, `None` for the template
+    open: Option<(String, usize)>,
+    then: Vec<Node>,
+    otherwise: Option<Vec<Node>>,
+}
+
+impl Block {
+    fn nodes(&mut self) -> &mut Vec<Node> {
+        self.otherwise.as_mut().unwrap_or(&mut self.then)
+    }
+}
+
+impl Template {
+    /// Parse `source`, which may only reference `fields`
+    pub fn parse(source: &str, fields: &[&str]) -> Result<Self> {
+        let known = |name: &str, offset: usize| {
+            if fields.contains(&name) {
+                Ok(name.to_string())
+            } else {
+                Err(anyhow!(
+                    "unknown field {name:?} at byte {offset}, expected one of {}",
+                    fields.join(", ")
+                ))
+            }
+        };
+
+        let mut blocks = vec![Block {
+            open: None,
+            then: Vec::new(),
+            otherwise: None,
+        }];
+        let mut text = String::new();
+        let mut rest = source;
+        while let Some(start) = rest.find('{') {
+            let offset = source.len() - rest.len() + start;
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let node = if let Some(tag) = rest.strip_prefix("{{") {
+                let end = tag
+                    .find("}}")
+                    .ok_or_else(|| anyhow!("unclosed {{{{ at byte {offset}"))?;
+                rest = &tag[end + 2..];
+                let tag = tag[..end].trim();
+
+                if let Some(name) = tag.strip_prefix("#if ") {
+                    flush(&mut text, &mut blocks);
+                    blocks.push(Block {
+                        open: Some((known(name.trim(), offset)?, offset)),
+                        then: Vec::new(),
+                        otherwise: None,
+                    });
+                    continue;
+                }
+                if tag == "else" || tag == "/if" {
+                    flush(&mut text, &mut blocks);
+                    let block = blocks
+                        .last_mut()
+                        .expect("the template block is never popped");
+                    if block.open.is_none() {
+                        bail!("{{{{{tag}}}}} at byte {offset} outside an {{{{#if}}}} block");
+                    }
+                    if tag == "else" {
+                        if block.otherwise.is_some() {
+                            bail!("second {{{{else}}}} at byte {offset}");
+                        }
+                        block.otherwise = Some(Vec::new());
+                        continue;
+                    }
+                    let block = blocks.pop().expect("checked above");
+                    let (name, _) = block.open.expect("checked above");
+                    Node::If {
+                        name,
+                        then: block.then,
+                        otherwise: block.otherwise.unwrap_or_default(),
+                    }
+                } else if let Some(name) = tag.strip_prefix("json ") {
+                    Node::Field {
+                        name: known(name.trim(), offset)?,
+                        style: Style::Json,
+                    }
+                } else {
+                    Node::Field {
+                        name: known(tag, offset)?,
+                        style: Style::Plain,
+                    }
+                }
+            } else if let Some(name) = legacy_placeholder(rest, fields) {
+                rest = &rest[name.len() + 2..];
+                Node::Field {
+                    name: name.to_string(),
+                    style: Style::Legacy,
+                }
+            } else {
+                text.push('{');
+                rest = &rest[1..];
+                continue;
+            };
+            flush(&mut text, &mut blocks);
+            blocks
+                .last_mut()
+                .expect("the template block is never popped")
+                .nodes()
+                .push(node);
+        }
+        text.push_str(rest);
+        flush(&mut text, &mut blocks);
+
+        let mut template = blocks.pop().expect("the template block is never popped");
+        if let Some((name, offset)) = template.open {
+            bail!("{{{{#if {name}}}}} at byte {offset} is never closed with {{{{/if}}}}");
+        }
+        Ok(Self {
+            nodes: std::mem::take(template.nodes()),
+        })
+    }
+
+    /// Render with `value` giving each field's value, `None` when the event has none
+    pub fn render(&self, value: impl Fn(&str) -> Option<String>) -> String {
+        let mut out = String::new();
+        render_nodes(&self.nodes, &value, &mut out);
+        out
+    }
+}
+
+fn flush(text: &mut String, blocks: &mut [Block]) {
+    if !text.is_empty() {
+        let block = blocks
+            .last_mut()
+            .expect("the template block is never popped");
+        block.nodes().push(Node::Text(std::mem::take(text)));
+    }
+}
+
+/// Name of the `{field}` placeholder `source` starts with, if it is one
+fn legacy_placeholder<'a>(source: &'a str, fields: &[&str]) -> Option<&'a str> {
+    let (name, _) = source.strip_prefix('{')?.split_once('}')?;
+    fields.contains(&name).then_some(name)
+}
+
+fn render_nodes(nodes: &[Node], value: &impl Fn(&str) -> Option<String>, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Field { name, style } => {
+                let value = value(name);
+                match style {
+                    Style::Plain => out.push_str(value.as_deref().unwrap_or_default()),
+                    Style::Json => out.push_str(&value.map_or_else(
+                        || "null".to_string(),
+                        |value| serde_json::to_string(&value).expect("strings always serialize"),
+                    )),
+                    Style::Legacy => out.push_str(value.as_deref().unwrap_or("none")),
+                }
+            }
+            Node::If {
+                name,
+                then,
+                otherwise,
+            } => {
+                let set = value(name).is_some_and(|value| !value.is_empty());
+                render_nodes(if set { then } else { otherwise }, value, out);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &[&str] = &["state", "zone", "confidence"];
+
+    fn render(source: &str) -> String {
+        Template::parse(source, FIELDS)
+            .unwrap()
+            .render(|name| match name {
+                "state" => Some("Tracking".to_string()),
+                "zone" => Some("porch \"east\"".to_string()),
+                _ => None,
+            })
+    }
+
+    #[test]
+    fn test_substitutes_fields_and_blocks() {
+        assert_eq!(
+            render("{{ state }} in {{zone}}"),
+            "Tracking in porch \"east\""
+        );
+        assert_eq!(render("[{{confidence}}]"), "[]");
+        assert_eq!(
+            render("{{#if zone}}in {{zone}}{{else}}somewhere{{/if}}"),
+            "in porch \"east\""
+        );
+        assert_eq!(
+            render("{{#if confidence}}{{confidence}}{{else}}n/a{{/if}}"),
+            "n/a"
+        );
+        assert_eq!(
+            render("{{#if state}}{{#if confidence}}sure{{/if}}{{state}}{{/if}}"),
+            "Tracking"
+        );
+    }
+
+    #[test]
+    fn test_json_values_and_literal_braces() {
+        assert_eq!(
+            render(r#"{"zone":{{json zone}},"confidence":{{json confidence}}}"#),
+            r#"{"zone":"porch \"east\"","confidence":null}"#
+        );
+    }
+
+    #[test]
+    fn test_single_brace_placeholders() {
+        assert_eq!(
+            render("{state} ({confidence}) {other}"),
+            "Tracking (none) {other}"
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_templates() {
+        for source in [
+            "{{stat}}",
+            "{{json}}",
+            "{{#if zone}}open",
+            "{{/if}}",
+            "{{#if zone}}a{{else}}b{{else}}c{{/if}}",
+            "{{state",
+        ] {
+            assert!(Template::parse(source, FIELDS).is_err(), "{source}");
+        }
+        let error = Template::parse("{{stat}}", FIELDS).unwrap_err().to_string();
+        assert!(error.contains("\"stat\""), "{error}");
+        assert!(error.contains("state, zone, confidence"), "{error}");
+    }
+}
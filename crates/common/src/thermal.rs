@@ -0,0 +1,148 @@
+//! SoC temperature from `/sys/class/thermal`
+//!
+//! Each `thermal_zone*/temp` holds the temperature of a zone (CPU, GPU, SoC...)
+//! in millidegrees Celsius. [`ThermalMonitor`] reads the hottest zone at most once
+//! per interval and reports the SoC as throttled from `throttle_celsius` until it
+//! cools below `recover_celsius`, so a temperature hovering around the limit
+//! does not flap.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const THERMAL_ROOT: &str = "/sys/class/thermal";
+
+pub struct ThermalMonitor {
+    root: PathBuf,
+    throttle_celsius: f32,
+    recover_celsius: f32,
+    interval: Duration,
+    last_read: Option<Instant>,
+    throttled: bool,
+}
+
+impl ThermalMonitor {
+    pub fn new(throttle_celsius: f32, recover_celsius: f32, interval: Duration) -> Self {
+        Self {
+            root: PathBuf::from(THERMAL_ROOT),
+            throttle_celsius,
+            recover_celsius,
+            interval,
+            last_read: None,
+            throttled: false,
+        }
+    }
+
+    /// Read the zones under `root` instead of `/sys/class/thermal`
+    pub fn with_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.root = root.into();
+        self
+    }
+
+    pub fn is_throttled(&self) -> bool {
+        self.throttled
+    }
+
+    /// Temperature of the hottest zone, `None` without readable zones
+    pub fn hottest_celsius(&self) -> io::Result<Option<f32>> {
+        let mut hottest: Option<f32> = None;
+        for entry in std::fs::read_dir(&self.root)? {
+            let entry = entry?;
+            if !entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("thermal_zone")
+            {
+                continue;
+            }
+            // Zones of powered-down devices fail to read, skip them
+            if let Some(celsius) = read_zone(&entry.path()) {
+                hottest = Some(hottest.map_or(celsius, |h| h.max(celsius)));
+            }
+        }
+        Ok(hottest)
+    }
+
+    /// Read the temperature if the interval has passed, returning the new state
+    /// when it changed
+    pub fn poll(&mut self) -> Option<bool> {
+        let now = Instant::now();
+        if self
+            .last_read
+            .is_some_and(|last| now.duration_since(last) < self.interval)
+        {
+            return None;
+        }
+        self.last_read = Some(now);
+
+        match self.hottest_celsius() {
+            Ok(Some(celsius)) => self.update(celsius),
+            Ok(None) => None,
+            Err(e) => {
+                crate::log_throttle!(warn, every = Duration::from_secs(300), error = %e, root = %self.root.display(), "Failed to read thermal zones");
+                None
+            }
+        }
+    }
+
+    /// Apply a temperature reading, returning the new state when it changed
+    fn update(&mut self, celsius: f32) -> Option<bool> {
+        let throttled = if self.throttled {
+            celsius >= self.recover_celsius
+        } else {
+            celsius >= self.throttle_celsius
+        };
+        if throttled == self.throttled {
+            return None;
+        }
+        self.throttled = throttled;
+        tracing::info!(celsius, throttled, "Thermal state changed");
+        Some(throttled)
+    }
+}
+
+fn read_zone(zone: &Path) -> Option<f32> {
+    let millidegrees: i64 = std::fs::read_to_string(zone.join("temp"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(millidegrees as f32 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttling_has_hysteresis() {
+        let mut monitor = ThermalMonitor::new(80.0, 70.0, Duration::ZERO);
+
+        assert_eq!(monitor.update(79.0), None);
+        assert_eq!(monitor.update(80.0), Some(true));
+        assert_eq!(monitor.update(75.0), None);
+        assert!(monitor.is_throttled());
+        assert_eq!(monitor.update(69.5), Some(false));
+        assert_eq!(monitor.update(75.0), None);
+    }
+
+    #[test]
+    fn test_hottest_zone_is_read() {
+        let root = std::env::temp_dir().join(format!("thermal_test_{}", std::process::id()));
+        for (zone, temp) in [("thermal_zone0", "45000\n"), ("thermal_zone1", "81500\n")] {
+            std::fs::create_dir_all(root.join(zone)).unwrap();
+            std::fs::write(root.join(zone).join("temp"), temp).unwrap();
+        }
+        // Not a zone, and a zone without a temperature
+        std::fs::create_dir_all(root.join("cooling_device0")).unwrap();
+        std::fs::create_dir_all(root.join("thermal_zone2")).unwrap();
+
+        let mut monitor = ThermalMonitor::new(80.0, 70.0, Duration::from_secs(60)).with_root(&root);
+        assert_eq!(monitor.hottest_celsius().unwrap(), Some(81.5));
+        assert_eq!(monitor.poll(), Some(true));
+        // Not read again before the interval
+        assert_eq!(monitor.poll(), None);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
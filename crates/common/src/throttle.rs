@@ -0,0 +1,133 @@
+//! Rate limiting for logs emitted from per-frame loops
+//!
+//! A failure that repeats every frame logs 30 lines a second and floods journald.
+//! [`log_throttle!`](crate::log_throttle) logs such an event at most once per
+//! period or once every N occurrences, reporting in a `suppressed` field how many
+//! were skipped since the last line. Each call site has its own throttle.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Period of [`log_throttle!`](crate::log_throttle) when none is given
+pub const DEFAULT_PERIOD: Duration = Duration::from_secs(10);
+
+/// State of one throttled call site
+pub struct LogThrottle {
+    state: Mutex<State>,
+}
+
+struct State {
+    last_logged: Option<Instant>,
+    suppressed: u64,
+}
+
+impl LogThrottle {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                last_logged: None,
+                suppressed: 0,
+            }),
+        }
+    }
+
+    /// Occurrences suppressed before this one if it should be logged, at most once
+    /// per `period`
+    pub fn every(&self, period: Duration) -> Option<u64> {
+        self.every_at(period, Instant::now())
+    }
+
+    fn every_at(&self, period: Duration, now: Instant) -> Option<u64> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state
+            .last_logged
+            .is_some_and(|last| now.duration_since(last) < period)
+        {
+            state.suppressed += 1;
+            return None;
+        }
+        state.last_logged = Some(now);
+        Some(std::mem::take(&mut state.suppressed))
+    }
+
+    /// Occurrences suppressed before this one if it should be logged, the first
+    /// of every `n`
+    pub fn every_nth(&self, n: u64) -> Option<u64> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.last_logged.is_some() && state.suppressed + 1 < n {
+            state.suppressed += 1;
+            return None;
+        }
+        state.last_logged = Some(Instant::now());
+        Some(std::mem::take(&mut state.suppressed))
+    }
+}
+
+impl Default for LogThrottle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Log a tracing event at most once per period (`every = Duration`, by default
+/// [`DEFAULT_PERIOD`]) or once every N occurrences (`nth = N`), with the skipped
+/// count as `suppressed`
+///
+/// ```ignore
+/// common::log_throttle!(warn, error = %e, "Decode failed");
+/// common::log_throttle!(warn, every = Duration::from_secs(60), "Camera is dark");
+/// common::log_throttle!(debug, nth = 300, frame_number, "Frame written");
+/// ```
+#[macro_export]
+macro_rules! log_throttle {
+    ($level:ident, every = $period:expr, $($arg:tt)+) => {{
+        static THROTTLE: $crate::throttle::LogThrottle = $crate::throttle::LogThrottle::new();
+        if let Some(suppressed) = THROTTLE.every($period) {
+            tracing::$level!(suppressed, $($arg)+);
+        }
+    }};
+    ($level:ident, nth = $n:expr, $($arg:tt)+) => {{
+        static THROTTLE: $crate::throttle::LogThrottle = $crate::throttle::LogThrottle::new();
+        if let Some(suppressed) = THROTTLE.every_nth($n) {
+            tracing::$level!(suppressed, $($arg)+);
+        }
+    }};
+    ($level:ident, $($arg:tt)+) => {
+        $crate::log_throttle!($level, every = $crate::throttle::DEFAULT_PERIOD, $($arg)+)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_period() {
+        let throttle = LogThrottle::new();
+        let start = Instant::now();
+        let period = Duration::from_secs(10);
+
+        assert_eq!(throttle.every_at(period, start), Some(0));
+        for i in 1..=5 {
+            assert_eq!(
+                throttle.every_at(period, start + Duration::from_secs(i)),
+                None
+            );
+        }
+        assert_eq!(
+            throttle.every_at(period, start + Duration::from_secs(10)),
+            Some(5)
+        );
+        assert_eq!(
+            throttle.every_at(period, start + Duration::from_secs(11)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_every_nth() {
+        let throttle = LogThrottle::new();
+        let logged: Vec<_> = (0..7).map(|_| throttle.every_nth(3)).collect();
+        assert_eq!(logged, [Some(0), None, None, Some(2), None, None, Some(2)]);
+    }
+}
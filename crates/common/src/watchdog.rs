@@ -0,0 +1,191 @@
+//! systemd readiness and watchdog notifications (sd_notify protocol)
+//!
+//! When a service runs under systemd with `Type=notify` and `WatchdogSec=`,
+//! systemd passes `NOTIFY_SOCKET` and `WATCHDOG_USEC` in the environment. Services
+//! report `READY=1` once initialized and then ping `WATCHDOG=1` from their main loop;
+//! if pings stop, systemd restarts the process even though it is still alive.
+//!
+//! Outside systemd (no `NOTIFY_SOCKET`) every call is a no-op.
+
+use std::env;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::{Duration, Instant};
+
+pub struct Watchdog {
+    socket: Option<(UnixDatagram, SocketAddr)>,
+    /// Time between keep-alive pings, `None` when systemd watchdog is not enabled
+    ping_interval: Option<Duration>,
+    last_ping: Option<Instant>,
+}
+
+impl Watchdog {
+    /// Build from the `NOTIFY_SOCKET`, `WATCHDOG_USEC` and `WATCHDOG_PID` variables set by systemd
+    pub fn from_env() -> Self {
+        let watchdog = Self::new(
+            env::var("NOTIFY_SOCKET").ok().as_deref(),
+            env::var("WATCHDOG_USEC").ok().and_then(|v| v.parse().ok()),
+            env::var("WATCHDOG_PID").ok().and_then(|v| v.parse().ok()),
+        );
+
+        if let Some(interval) = watchdog.ping_interval {
+            tracing::info!(?interval, "systemd watchdog enabled");
+        }
+
+        watchdog
+    }
+
+    fn new(
+        notify_socket: Option<&str>,
+        watchdog_usec: Option<u64>,
+        watchdog_pid: Option<u32>,
+    ) -> Self {
+        let socket = notify_socket.and_then(|path| match connect(path) {
+            Ok(socket) => Some(socket),
+            Err(e) => {
+                tracing::warn!(path, error = %e, "Failed to open systemd notify socket");
+                None
+            }
+        });
+
+        // WATCHDOG_PID names the process systemd expects pings from; ignore it if that is not us
+        let for_us = watchdog_pid.is_none_or(|pid| pid == std::process::id());
+
+        // Ping at half the timeout, as recommended by sd_watchdog_enabled(3)
+        let ping_interval = watchdog_usec
+            .filter(|usec| *usec > 0 && for_us && socket.is_some())
+            .map(|usec| Duration::from_micros(usec / 2));
+
+        Self {
+            socket,
+            ping_interval,
+            last_ping: None,
+        }
+    }
+
+    /// Whether a systemd watchdog is expecting pings from this process
+    pub fn is_enabled(&self) -> bool {
+        self.ping_interval.is_some()
+    }
+
+    /// Interval at which the main loop must call [`Watchdog::ping`]
+    ///
+    /// Loops blocking on IPC should bound their waits by this so an idle
+    /// pipeline is not mistaken for a hung process.
+    pub fn ping_interval(&self) -> Option<Duration> {
+        self.ping_interval
+    }
+
+    /// Report that initialization finished and the service is ready
+    pub fn ready(&self) {
+        self.notify("READY=1");
+    }
+
+    /// Report that the service is shutting down
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1");
+    }
+
+    /// Keep the watchdog alive, rate-limited to the ping interval
+    pub fn ping(&mut self) {
+        let Some(interval) = self.ping_interval else {
+            return;
+        };
+
+        let now = Instant::now();
+        if self
+            .last_ping
+            .is_some_and(|last| now.duration_since(last) < interval)
+        {
+            return;
+        }
+
+        self.notify("WATCHDOG=1");
+        self.last_ping = Some(now);
+    }
+
+    fn notify(&self, state: &str) {
+        let Some((socket, addr)) = &self.socket else {
+            return;
+        };
+
+        if let Err(e) = socket.send_to_addr(state.as_bytes(), addr) {
+            tracing::warn!(state, error = %e, "Failed to notify systemd");
+        }
+    }
+}
+
+/// Open an unbound datagram socket towards `NOTIFY_SOCKET`
+///
+/// A leading `@` denotes a Linux abstract socket.
+fn connect(path: &str) -> std::io::Result<(UnixDatagram, SocketAddr)> {
+    let addr = match path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            SocketAddr::from_abstract_name(name)?
+        }
+        _ => SocketAddr::from_pathname(path)?,
+    };
+
+    Ok((UnixDatagram::unbound()?, addr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn socket_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("watchdog-{}-{}.sock", name, std::process::id()))
+    }
+
+    fn recv(listener: &UnixDatagram) -> String {
+        let mut buf = [0u8; 64];
+        let n = listener.recv(&mut buf).unwrap();
+        String::from_utf8_lossy(&buf[..n]).into_owned()
+    }
+
+    #[test]
+    fn test_disabled_without_notify_socket() {
+        let mut watchdog = Watchdog::new(None, Some(10_000_000), None);
+        assert!(!watchdog.is_enabled());
+        watchdog.ready();
+        watchdog.ping();
+    }
+
+    #[test]
+    fn test_ready_and_ping() {
+        let path = socket_path("ping");
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixDatagram::bind(&path).unwrap();
+        listener.set_nonblocking(true).unwrap();
+
+        let mut watchdog = Watchdog::new(path.to_str(), Some(10_000_000), None);
+        assert_eq!(watchdog.ping_interval(), Some(Duration::from_secs(5)));
+
+        watchdog.ready();
+        assert_eq!(recv(&listener), "READY=1");
+
+        watchdog.ping();
+        assert_eq!(recv(&listener), "WATCHDOG=1");
+
+        // Second ping within the interval is suppressed
+        watchdog.ping();
+        let mut buf = [0u8; 16];
+        assert!(listener.recv(&mut buf).is_err());
+
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_ignores_watchdog_for_other_pid() {
+        let path = socket_path("pid");
+        let _ = std::fs::remove_file(&path);
+        let _listener = UnixDatagram::bind(&path).unwrap();
+
+        let watchdog = Watchdog::new(path.to_str(), Some(10_000_000), Some(u32::MAX));
+        assert!(!watchdog.is_enabled());
+
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,416 @@
+//! Anomalies in the pipeline's own metrics
+//!
+//! A camera that drops to one frame every few seconds, an inference backend
+//! falling back to the CPU or a detector hallucinating all night still look
+//! healthy to every probe, and only show when an intrusion is missed. With
+//! `ANOMALY_TOPIC` set, the controller aggregates over windows of
+//! `ANOMALY_WINDOW_SECS`:
+//! - `fps`: detection results reaching the controller per second
+//! - `inference_latency_ms`: mean time inference spent on a frame
+//! - `detection_rate`: share of results with an alert-class detection
+//!
+//! Each is compared with its EWMA baseline for the current sentry mode, frame
+//! rates differing by design between modes. A window deviating by more than
+//! `ANOMALY_SIGMAS` standard deviations in the harmful direction (fewer frames,
+//! slower inference, more detections), and by a margin significant for the
+//! metric, is anomalous. `ANOMALY_MIN_FPS` and `ANOMALY_MAX_LATENCY_MS` are hard
+//! limits checked even before the baselines are learned.
+//!
+//! An `anomaly` event is published once a metric was anomalous for
+//! `ANOMALY_PERSIST_WINDOWS` windows in a row, and again with `active: false`
+//! when it is back to normal. Windows spanning a sentry mode change are dropped,
+//! and anomalous windows are not learned.
+
+use crate::config::AnomalyConfig;
+use anyhow::{Context, Result};
+use bridge::SentryMode;
+use common::event::{Anomaly, Event, EventType};
+use rumqttc::{Client, QoS};
+use std::time::{Duration, Instant};
+
+/// Windows a baseline needs before deviations from it are flagged
+const WARMUP_WINDOWS: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Fps,
+    InferenceLatency,
+    DetectionRate,
+}
+
+impl Metric {
+    const ALL: [Self; 3] = [Self::Fps, Self::InferenceLatency, Self::DetectionRate];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Fps => "fps",
+            Self::InferenceLatency => "inference_latency_ms",
+            Self::DetectionRate => "detection_rate",
+        }
+    }
+
+    /// Whether `value` is worse than `reference` for this metric
+    fn worse(self, value: f64, reference: f64) -> bool {
+        match self {
+            Self::Fps => value < reference,
+            Self::InferenceLatency | Self::DetectionRate => value > reference,
+        }
+    }
+
+    /// Smallest deviation from `baseline` worth reporting, so a very stable
+    /// baseline does not flag noise
+    fn min_delta(self, baseline: f64) -> f64 {
+        match self {
+            Self::Fps => baseline * 0.3,
+            Self::InferenceLatency => (baseline * 0.5).max(5.0),
+            Self::DetectionRate => 0.25,
+        }
+    }
+}
+
+fn mode_name(mode: SentryMode) -> &'static str {
+    match mode {
+        SentryMode::Standby => "standby",
+        SentryMode::Alarmed => "alarmed",
+        SentryMode::Elevated => "elevated",
+    }
+}
+
+/// Exponentially weighted mean and variance of a metric
+#[derive(Debug, Clone, Copy, Default)]
+struct Baseline {
+    mean: f64,
+    variance: f64,
+    samples: u32,
+}
+
+impl Baseline {
+    fn update(&mut self, value: f64, smoothing: f64) {
+        if self.samples == 0 {
+            self.mean = value;
+        } else {
+            let diff = value - self.mean;
+            let increment = smoothing * diff;
+            self.mean += increment;
+            self.variance = (1.0 - smoothing) * (self.variance + diff * increment);
+        }
+        self.samples = self.samples.saturating_add(1);
+    }
+
+    fn learned(&self) -> bool {
+        self.samples >= WARMUP_WINDOWS
+    }
+
+    fn deviates(&self, metric: Metric, value: f64, sigmas: f64) -> bool {
+        let delta = (value - self.mean).abs();
+        self.learned()
+            && metric.worse(value, self.mean)
+            && delta > sigmas * self.variance.sqrt()
+            && delta > metric.min_delta(self.mean)
+    }
+}
+
+/// Totals of the window in progress
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    mode: SentryMode,
+    frames: u32,
+    latency_ms: f64,
+    latency_samples: u32,
+    frames_with_detections: u32,
+}
+
+impl Window {
+    fn new(started: Instant, mode: SentryMode) -> Self {
+        Self {
+            started,
+            mode,
+            frames: 0,
+            latency_ms: 0.0,
+            latency_samples: 0,
+            frames_with_detections: 0,
+        }
+    }
+
+    /// Value of `metric` over the window, `None` when it was not measured
+    fn value(&self, metric: Metric, elapsed: Duration) -> Option<f64> {
+        match metric {
+            Metric::Fps => Some(self.frames as f64 / elapsed.as_secs_f64()),
+            Metric::InferenceLatency => {
+                (self.latency_samples > 0).then(|| self.latency_ms / self.latency_samples as f64)
+            }
+            Metric::DetectionRate => {
+                (self.frames > 0).then(|| self.frames_with_detections as f64 / self.frames as f64)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct MetricState {
+    /// Consecutive anomalous windows
+    streak: u32,
+    active: bool,
+}
+
+/// Flags metrics leaving their usual range, see the module documentation
+pub struct AnomalyDetector {
+    config: AnomalyConfig,
+    window: Window,
+    /// By sentry mode, then metric
+    baselines: [[Baseline; 3]; 3],
+    states: [MetricState; 3],
+}
+
+impl AnomalyDetector {
+    pub fn new(config: AnomalyConfig, now: Instant) -> Self {
+        Self {
+            config,
+            window: Window::new(now, SentryMode::Standby),
+            baselines: Default::default(),
+            states: Default::default(),
+        }
+    }
+
+    /// Count a detection result and the inference latency of its frame
+    pub fn record(&mut self, latency: Option<Duration>, detected: bool) {
+        self.window.frames += 1;
+        if let Some(latency) = latency {
+            self.window.latency_ms += latency.as_secs_f64() * 1000.0;
+            self.window.latency_samples += 1;
+        }
+        if detected {
+            self.window.frames_with_detections += 1;
+        }
+    }
+
+    /// Close the window once it is due, returning the anomalies that started or
+    /// ended with it
+    pub fn tick(&mut self, now: Instant, mode: SentryMode) -> Vec<Anomaly> {
+        if mode != self.window.mode {
+            self.window = Window::new(now, mode);
+            return Vec::new();
+        }
+        let elapsed = now.duration_since(self.window.started);
+        if elapsed < self.config.window {
+            return Vec::new();
+        }
+        let window = std::mem::replace(&mut self.window, Window::new(now, mode));
+
+        let mut changes = Vec::new();
+        for (index, metric) in Metric::ALL.into_iter().enumerate() {
+            let Some(value) = window.value(metric, elapsed) else {
+                continue;
+            };
+            let baseline = &mut self.baselines[mode as usize][index];
+            let limit = match metric {
+                Metric::Fps => self.config.min_fps,
+                Metric::InferenceLatency => self.config.max_latency_ms,
+                Metric::DetectionRate => None,
+            }
+            .filter(|limit| metric.worse(value, *limit));
+            let anomalous = limit.is_some() || baseline.deviates(metric, value, self.config.sigmas);
+            let usual = baseline.learned().then_some(baseline.mean);
+            // Anomalous windows are not learned, or a slow degradation would become
+            // the new normal. Baselines start over with the process, so a config
+            // change does not leave an anomaly behind.
+            if !anomalous {
+                baseline.update(value, self.config.smoothing);
+            }
+
+            let state = &mut self.states[index];
+            state.streak = if anomalous { state.streak + 1 } else { 0 };
+            let active = match (state.active, anomalous) {
+                (false, true) => state.streak >= self.config.persist_windows,
+                (true, false) => false,
+                (active, _) => active,
+            };
+            if active != state.active {
+                state.active = active;
+                changes.push(Anomaly {
+                    metric: metric.name().to_string(),
+                    active,
+                    value,
+                    baseline: usual,
+                    limit,
+                    sentry_mode: mode_name(mode).to_string(),
+                });
+            }
+        }
+        changes
+    }
+}
+
+/// Publishes the detector's anomalies on `ANOMALY_TOPIC`
+pub struct AnomalyPublisher {
+    topic: String,
+    device_id: String,
+    mqtt: Client,
+    detector: AnomalyDetector,
+}
+
+impl AnomalyPublisher {
+    pub fn new(config: &AnomalyConfig, device_id: String, mqtt: Client) -> Self {
+        tracing::info!(topic = %config.topic, window = ?config.window, "Anomaly detection enabled");
+        Self {
+            topic: config.topic.clone(),
+            device_id,
+            mqtt,
+            detector: AnomalyDetector::new(config.clone(), Instant::now()),
+        }
+    }
+
+    pub fn record(&mut self, latency: Option<Duration>, detected: bool) {
+        self.detector.record(latency, detected);
+    }
+
+    /// Close the window once it is due, publishing what changed
+    pub fn tick(&mut self, mode: SentryMode) {
+        for anomaly in self.detector.tick(Instant::now(), mode) {
+            if anomaly.active {
+                tracing::warn!(
+                    metric = anomaly.metric,
+                    value = anomaly.value,
+                    baseline = ?anomaly.baseline,
+                    "Pipeline anomaly"
+                );
+            } else {
+                tracing::info!(
+                    metric = anomaly.metric,
+                    value = anomaly.value,
+                    "Pipeline anomaly cleared"
+                );
+            }
+            let event = Event::new(&self.device_id, EventType::Anomaly, anomaly);
+            if let Err(e) = self.publish(&event) {
+                tracing::error!(error = %e, "Failed to publish anomaly");
+            }
+        }
+    }
+
+    fn publish(&self, event: &Event) -> Result<()> {
+        let payload = event
+            .to_json()
+            .context("Failed to serialize anomaly event")?;
+        self.mqtt
+            .publish(&self.topic, QoS::AtLeastOnce, false, payload)
+            .context("Failed to publish MQTT message")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(10);
+
+    fn config() -> AnomalyConfig {
+        AnomalyConfig {
+            topic: "anomalies".to_string(),
+            window: WINDOW,
+            sigmas: 4.0,
+            smoothing: 0.1,
+            persist_windows: 2,
+            min_fps: None,
+            max_latency_ms: None,
+        }
+    }
+
+    /// Run a window of `fps` results taking `latency_ms`, a share `detected` of
+    /// them with detections
+    fn window(
+        detector: &mut AnomalyDetector,
+        now: &mut Instant,
+        mode: SentryMode,
+        fps: u32,
+        latency_ms: u64,
+        detected: f64,
+    ) -> Vec<Anomaly> {
+        let frames = fps * WINDOW.as_secs() as u32;
+        for i in 0..frames {
+            detector.record(
+                Some(Duration::from_millis(latency_ms)),
+                (i as f64) < detected * frames as f64,
+            );
+        }
+        *now += WINDOW;
+        detector.tick(*now, mode)
+    }
+
+    #[test]
+    fn test_flags_fps_collapse_after_warmup() {
+        let mut now = Instant::now();
+        let mut detector = AnomalyDetector::new(config(), now);
+
+        for i in 0..WARMUP_WINDOWS {
+            let fps = if i % 2 == 0 { 30 } else { 29 };
+            assert!(window(&mut detector, &mut now, SentryMode::Standby, fps, 40, 0.0).is_empty());
+        }
+        // Faster inference is never an anomaly
+        assert!(window(&mut detector, &mut now, SentryMode::Standby, 30, 10, 0.0).is_empty());
+
+        // Flagged on the second slow window in a row
+        assert!(window(&mut detector, &mut now, SentryMode::Standby, 5, 40, 0.0).is_empty());
+        let raised = window(&mut detector, &mut now, SentryMode::Standby, 5, 40, 0.0);
+        assert_eq!(raised.len(), 1);
+        assert_eq!(raised[0].metric, "fps");
+        assert!(raised[0].active);
+        assert_eq!(raised[0].value, 5.0);
+        assert!(raised[0].baseline.unwrap() > 25.0);
+
+        let cleared = window(&mut detector, &mut now, SentryMode::Standby, 30, 40, 0.0);
+        assert_eq!(cleared.len(), 1);
+        assert!(!cleared[0].active);
+    }
+
+    #[test]
+    fn test_modes_have_their_own_baseline() {
+        let mut now = Instant::now();
+        let mut detector = AnomalyDetector::new(config(), now);
+
+        for _ in 0..WARMUP_WINDOWS {
+            window(&mut detector, &mut now, SentryMode::Alarmed, 30, 40, 0.0);
+        }
+        // The window spanning the mode change is dropped
+        assert!(window(&mut detector, &mut now, SentryMode::Standby, 3, 40, 0.0).is_empty());
+        for _ in 0..3 {
+            assert!(window(&mut detector, &mut now, SentryMode::Standby, 3, 40, 0.0).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_limits_apply_before_warmup() {
+        let mut now = Instant::now();
+        let mut detector = AnomalyDetector::new(
+            AnomalyConfig {
+                max_latency_ms: Some(200.0),
+                persist_windows: 1,
+                ..config()
+            },
+            now,
+        );
+
+        let raised = window(&mut detector, &mut now, SentryMode::Standby, 3, 500, 0.0);
+        assert_eq!(raised.len(), 1);
+        assert_eq!(raised[0].metric, "inference_latency_ms");
+        assert_eq!(raised[0].limit, Some(200.0));
+        assert_eq!(raised[0].baseline, None);
+    }
+
+    #[test]
+    fn test_flags_detection_flood() {
+        let mut now = Instant::now();
+        let mut detector = AnomalyDetector::new(config(), now);
+
+        for _ in 0..WARMUP_WINDOWS {
+            window(&mut detector, &mut now, SentryMode::Standby, 3, 40, 0.0);
+        }
+        // A subject walking by is not enough
+        assert!(window(&mut detector, &mut now, SentryMode::Standby, 3, 40, 0.2).is_empty());
+        window(&mut detector, &mut now, SentryMode::Standby, 3, 40, 0.9);
+        let raised = window(&mut detector, &mut now, SentryMode::Standby, 3, 40, 0.9);
+        assert_eq!(raised.len(), 1);
+        assert_eq!(raised[0].metric, "detection_rate");
+    }
+}
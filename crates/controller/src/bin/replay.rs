@@ -0,0 +1,141 @@
+use anyhow::Context;
+use chrono::{DateTime, Local};
+use clap::Parser;
+use common::classes::{ClassGroups, class_name};
+use common::get_env;
+use controller::config::{ControllerConfig, parse_alert_classes};
+use controller::replay::{Alarm, Replay, ReplaySettings};
+use std::io::BufReader;
+use std::path::PathBuf;
+
+/// Replay recorded detections through the controller's alarm state machine.
+///
+/// Reads one detection result per line, as `common::event::DetectionSummary`
+/// JSON or `detections` events, and reports the alarms the controller would have
+/// raised. Settings not given on the command line are those of the controller's
+/// environment (`ALERT_CLASSES`, `VALIDATION_FRAMES`, ...), so a run with none
+/// replays the current configuration.
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Detection log, one JSON object per line, `-` for stdin
+    input: PathBuf,
+
+    /// Classes raising alarms, with optional validation frames (ALERT_CLASSES)
+    #[arg(long)]
+    alert_classes: Option<String>,
+
+    /// Consecutive frames before Tracking, for classes without their own (VALIDATION_FRAMES)
+    #[arg(long)]
+    validation_frames: Option<u32>,
+
+    /// Frames without alert classes before Tracking ends (TRACKING_EXIT_FRAMES)
+    #[arg(long)]
+    tracking_exit_frames: Option<u32>,
+
+    /// Lowest confidence counted toward an alarm (ALERT_CONFIDENCE)
+    #[arg(long)]
+    alert_confidence: Option<f32>,
+
+    /// Detections of an alert class required in a frame (ALERT_MIN_COUNT)
+    #[arg(long)]
+    min_count: Option<u32>,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+}
+
+fn main() -> anyhow::Result<()> {
+    common::load_profile()?;
+    let args = Args::parse();
+    let config = ControllerConfig::from_env()?;
+
+    let mut settings = ReplaySettings::from_config(&config);
+    if args.alert_classes.is_some() || args.validation_frames.is_some() {
+        let classes = args
+            .alert_classes
+            .clone()
+            .unwrap_or_else(|| get_env("ALERT_CLASSES", "0".to_string()));
+        let validation_frames = args.validation_frames.unwrap_or(config.validation_frames);
+        settings.alert_classes =
+            parse_alert_classes(&classes, validation_frames, &ClassGroups::from_env()?)?;
+    }
+    if let Some(frames) = args.tracking_exit_frames {
+        settings.tracking_exit_frames = frames;
+    }
+    if let Some(confidence) = args.alert_confidence {
+        settings.alert_confidence = confidence;
+    }
+    if let Some(min_count) = args.min_count {
+        settings.alert_count.min_count = min_count;
+    }
+
+    let mut replay = Replay::new(settings.clone());
+    if args.input.as_os_str() == "-" {
+        replay.feed_lines(std::io::stdin().lock())?;
+    } else {
+        let file = std::fs::File::open(&args.input)
+            .with_context(|| format!("Failed to open {}", args.input.display()))?;
+        replay.feed_lines(BufReader::new(file))?;
+    }
+    let report = replay.finish();
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!(
+        "Replayed {} frames with alert confidence {}, {} tracking exit frames, minimum count {}",
+        report.frames,
+        settings.alert_confidence,
+        settings.tracking_exit_frames,
+        settings.alert_count.min_count
+    );
+    for class in &settings.alert_classes {
+        println!(
+            "  {}: {} validation frames",
+            describe_class(Some(class.class_id)),
+            class.validation_frames
+        );
+    }
+    println!(
+        "{} alarms, {} validations abandoned",
+        report.alarms.len(),
+        report.abandoned_validations()
+    );
+    for (index, alarm) in report.alarms.iter().enumerate() {
+        println!("#{} {}", index + 1, describe_alarm(alarm));
+    }
+    Ok(())
+}
+
+fn describe_alarm(alarm: &Alarm) -> String {
+    let end = match (alarm.end_frame_number, alarm.end_timestamp_ns) {
+        (Some(frame), Some(timestamp)) => format!("frame {frame} at {}", local_time(timestamp)),
+        _ => "end of log".to_string(),
+    };
+    format!(
+        "{}: frame {} at {} until {end}, {} frames, peak confidence {:.2}",
+        describe_class(alarm.class_id),
+        alarm.frame_number,
+        local_time(alarm.timestamp_ns),
+        alarm.frames,
+        alarm.peak_confidence
+    )
+}
+
+fn describe_class(class_id: Option<u16>) -> String {
+    match class_id {
+        Some(id) => class_name(id).map_or_else(|| id.to_string(), String::from),
+        None => "unknown class".to_string(),
+    }
+}
+
+fn local_time(timestamp_ns: u64) -> String {
+    DateTime::from_timestamp_nanos(timestamp_ns as i64)
+        .with_timezone(&Local)
+        .format(common::template::LOCAL_TIME_FORMAT)
+        .to_string()
+}
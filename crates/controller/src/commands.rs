@@ -0,0 +1,303 @@
+//! Remote management over MQTT
+//!
+//! With `MQTT_COMMAND_TOPIC` set, the controller accepts JSON commands on that
+//! topic, e.g. `{"id":"42","command":"set_thresholds","alert_confidence":0.8}`:
+//! - `set_thresholds`: `confidence` (inference) and/or `alert_confidence`, within 0..=1
+//! - `arm` / `disarm`: alert notifications are sent only while armed; the gateway's
+//!   `/api/controller/arm` does the same through the bridge
+//! - `test_notification`: send a `test_notification` event through every notifier
+//! - `alert_drill`: check the whole alert chain, snapshot included, answered with a
+//!   `drill_report` of each step's outcome and latency, see `drill.rs`
+//! - `request_snapshot`: the gateway keeps its next frame, announced with a
+//!   `snapshot_ready` event once served from `/api/snapshots/<frame_number>`
+//! - `request_still`: capture writes its next frame at the camera's native
+//!   resolution, announced with a `still_ready` event once served from
+//!   `/api/stills/latest`
+//!
+//! A command is validated as a whole before any of it is applied. Every command is
+//! answered on `MQTT_ACK_TOPIC` (default `<command topic>/ack`) with the request id,
+//! whether it was applied and the config version after it; the version increases
+//! with every applied change.
+
+use crate::drill::{Drill, DrillReport};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use rumqttc::{Client, QoS};
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::Receiver;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    SetThresholds {
+        #[serde(default)]
+        confidence: Option<f32>,
+        #[serde(default)]
+        alert_confidence: Option<f32>,
+    },
+    Arm,
+    Disarm,
+    TestNotification,
+    AlertDrill,
+    RequestSnapshot,
+    RequestStill,
+}
+
+impl Command {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SetThresholds { .. } => "set_thresholds",
+            Self::Arm => "arm",
+            Self::Disarm => "disarm",
+            Self::TestNotification => "test_notification",
+            Self::AlertDrill => "alert_drill",
+            Self::RequestSnapshot => "request_snapshot",
+            Self::RequestStill => "request_still",
+        }
+    }
+
+    /// Reject the command before anything is applied
+    pub fn validate(&self) -> Result<(), String> {
+        if let Self::SetThresholds {
+            confidence,
+            alert_confidence,
+        } = self
+        {
+            if confidence.is_none() && alert_confidence.is_none() {
+                return Err("set_thresholds sets neither confidence nor alert_confidence".into());
+            }
+            let values = [
+                ("confidence", confidence),
+                ("alert_confidence", alert_confidence),
+            ];
+            for (name, value) in values {
+                if let Some(value) = value.filter(|v| !(0.0..=1.0).contains(v)) {
+                    return Err(format!("{name} must be within 0..=1, got {value}"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A command as received, with the id echoed in its acknowledgement
+#[derive(Debug, Deserialize)]
+pub struct CommandRequest {
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+/// Published on the ack topic for every command received
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandAck {
+    pub device_id: String,
+    pub timestamp: String,
+    pub id: Option<String>,
+    /// `None` when the payload was not a command
+    pub command: Option<&'static str>,
+    pub accepted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub config_version: u64,
+    /// Request number of a `request_snapshot`, `alert_drill` or `request_still`,
+    /// stills counted apart
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot_request: Option<u64>,
+}
+
+/// Published on the ack topic once a requested snapshot or still is available
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotReady {
+    pub device_id: String,
+    pub timestamp: String,
+    pub event: &'static str,
+    pub snapshot_request: u64,
+    pub frame_number: u64,
+    /// Gateway path serving the JPEG
+    pub path: String,
+}
+
+/// Runtime configuration changed by commands and the gateway
+#[derive(Debug)]
+pub struct RuntimeState {
+    /// Increases with every applied change
+    pub config_version: u64,
+    pub armed: bool,
+    /// Snapshot request waiting for the gateway
+    pub awaiting_snapshot: Option<u64>,
+    /// Still request waiting for capture
+    pub awaiting_still: Option<u64>,
+    /// Alert drill waiting for its snapshot
+    pub drill: Option<Drill>,
+}
+
+impl Default for RuntimeState {
+    fn default() -> Self {
+        Self {
+            config_version: 0,
+            armed: true,
+            awaiting_snapshot: None,
+            awaiting_still: None,
+            drill: None,
+        }
+    }
+}
+
+pub struct CommandChannel {
+    commands: Receiver<Vec<u8>>,
+    client: Client,
+    ack_topic: String,
+    device_id: String,
+}
+
+impl CommandChannel {
+    pub fn new(
+        commands: Receiver<Vec<u8>>,
+        client: Client,
+        ack_topic: String,
+        device_id: String,
+    ) -> Self {
+        Self {
+            commands,
+            client,
+            ack_topic,
+            device_id,
+        }
+    }
+
+    /// Next command received, without waiting
+    pub fn try_next(&self) -> Option<Result<CommandRequest, String>> {
+        let payload = self.commands.try_recv().ok()?;
+        Some(serde_json::from_slice(&payload).map_err(|e| format!("Invalid command: {e}")))
+    }
+
+    /// Answer a command with the config version after it, `snapshot_request` set
+    /// when one was issued
+    pub fn acknowledge(
+        &self,
+        request: Option<&CommandRequest>,
+        result: Result<(), String>,
+        config_version: u64,
+        snapshot_request: Option<u64>,
+    ) {
+        let ack = CommandAck {
+            device_id: self.device_id.clone(),
+            timestamp: Utc::now().to_rfc3339(),
+            id: request.and_then(|r| r.id.clone()),
+            command: request.map(|r| r.command.name()),
+            accepted: result.is_ok(),
+            error: result.err(),
+            config_version,
+            snapshot_request,
+        };
+        match &ack.error {
+            None => tracing::info!(command = ?ack.command, id = ?ack.id, "Command applied"),
+            Some(error) => {
+                tracing::warn!(command = ?ack.command, id = ?ack.id, %error, "Command rejected")
+            }
+        }
+        if let Err(e) = self.publish(&ack) {
+            tracing::warn!(error = %e, "Failed to publish command acknowledgement");
+        }
+    }
+
+    pub fn snapshot_ready(&self, snapshot_request: u64, frame_number: u64) {
+        self.publish_ready(
+            "snapshot_ready",
+            snapshot_request,
+            frame_number,
+            format!("/api/snapshots/{frame_number}"),
+        );
+    }
+
+    /// The snapshot buffer only holds the latest still
+    pub fn still_ready(&self, still_request: u64, frame_number: u64) {
+        self.publish_ready(
+            "still_ready",
+            still_request,
+            frame_number,
+            "/api/stills/latest".to_string(),
+        );
+    }
+
+    pub fn drill_report(&self, report: &DrillReport) {
+        if let Err(e) = self.publish(report) {
+            tracing::warn!(error = %e, "Failed to publish drill report");
+        }
+    }
+
+    fn publish_ready(&self, event: &'static str, request: u64, frame_number: u64, path: String) {
+        let event = SnapshotReady {
+            device_id: self.device_id.clone(),
+            timestamp: Utc::now().to_rfc3339(),
+            event,
+            snapshot_request: request,
+            frame_number,
+            path,
+        };
+        if let Err(e) = self.publish(&event) {
+            tracing::warn!(error = %e, "Failed to publish snapshot event");
+        }
+    }
+
+    fn publish(&self, message: &impl Serialize) -> Result<()> {
+        let payload = serde_json::to_vec(message).context("Failed to serialize message")?;
+        self.client
+            .try_publish(&self.ack_topic, QoS::AtLeastOnce, false, payload)
+            .context("Failed to publish MQTT message")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> CommandRequest {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let request = parse(r#"{"id":"7","command":"set_thresholds","alert_confidence":0.8}"#);
+        assert_eq!(request.id.as_deref(), Some("7"));
+        assert_eq!(
+            request.command,
+            Command::SetThresholds {
+                confidence: None,
+                alert_confidence: Some(0.8),
+            }
+        );
+        assert_eq!(parse(r#"{"command":"disarm"}"#).command, Command::Disarm);
+        assert_eq!(
+            parse(r#"{"command":"request_snapshot"}"#).command,
+            Command::RequestSnapshot
+        );
+        assert_eq!(
+            parse(r#"{"command":"request_still"}"#).command,
+            Command::RequestStill
+        );
+        assert_eq!(
+            parse(r#"{"command":"alert_drill"}"#).command,
+            Command::AlertDrill
+        );
+        assert!(serde_json::from_str::<CommandRequest>(r#"{"command":"reboot"}"#).is_err());
+    }
+
+    #[test]
+    fn test_thresholds_are_validated_together() {
+        let command = Command::SetThresholds {
+            confidence: Some(0.5),
+            alert_confidence: Some(1.5),
+        };
+        assert!(command.validate().is_err());
+
+        let empty = Command::SetThresholds {
+            confidence: None,
+            alert_confidence: None,
+        };
+        assert!(empty.validate().is_err());
+        assert!(Command::Arm.validate().is_ok());
+    }
+}
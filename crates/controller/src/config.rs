@@ -1,31 +1,849 @@
-use anyhow::Result;
-use common::{Environment, get_env, get_env_opt};
+use crate::notifier::TEMPLATE_FIELDS;
+use crate::zones::{Zone, parse_zones};
+use anyhow::{Context, Result};
+use chrono::NaiveTime;
+use common::classes::{ClassGroups, ClassSet};
+use common::{ConfigCheck, Environment, Template, get_env, get_env_opt};
+use std::path::Path;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct ControllerConfig {
     pub environment: Environment,
     pub validation_frames: u32,
     pub tracking_exit_frames: u32,
+    /// Classes that can raise an alarm, each with its own validation threshold
+    pub alert_classes: Vec<AlertClass>,
+    /// Minimum confidence for an alert class to count towards an alarm
+    pub alert_confidence: f32,
+    /// Simultaneous detections an alert class needs to count towards an alarm
+    pub alert_count: CountRule,
+    /// Elevated sentry level on weak detections, enabled when `ELEVATED_CONFIDENCE` is set
+    pub elevation: Option<ElevationConfig>,
     pub poll_interval_ms: u64,
+    /// Areas of the frame activity is counted in, the whole frame without `ZONES`
+    pub zones: Vec<Zone>,
+    /// Skip detection results whose frame is older than this, no limit when unset
+    pub detection_max_age: Option<Duration>,
     pub mqtt_broker_host: String,
     pub mqtt_broker_port: u16,
     pub mqtt_topic: String,
     pub mqtt_device_id: String,
+    /// Replaces the JSON event published on `mqtt_topic`, a `common::template`
+    /// over `notifier::TEMPLATE_FIELDS`
+    pub mqtt_payload_template: Option<String>,
+    /// Base URL the gateway is reachable at for notification recipients;
+    /// notifications then link the event frame in its frame history
+    pub gateway_public_url: Option<String>,
+    /// Ask capture for a long exposure still on alarm entry, taken when the
+    /// scene is dark (capture's `NIGHT_STILL_EXPOSURE`); notifications then link it
+    pub night_still_on_alarm: bool,
+    /// Queues and retries of the notifiers, see `dispatch.rs`
+    pub dispatch: DispatchConfig,
+    /// Remote management, enabled when `MQTT_COMMAND_TOPIC` is set
+    pub commands: Option<CommandConfig>,
     pub otel_endpoint: Option<String>,
+    /// Email alerts, enabled when `SMTP_HOST` is set
+    pub smtp: Option<SmtpConfig>,
+    /// Off-device copies of events, enabled when `S3_ENDPOINT` is set
+    pub s3: Option<S3Config>,
+    /// Siren or light driven on alarms, enabled when `GPIO_LINES` is set
+    pub gpio: Option<GpioConfig>,
+    /// Daily activity digest, enabled when `DAILY_SUMMARY_TIME` is set
+    pub summary: Option<SummaryConfig>,
+    /// Occupancy change events, enabled when `OCCUPANCY_TOPIC` is set
+    pub occupancy: Option<OccupancyConfig>,
+    /// Flags alarms of a subject back within a window, enabled when `REENTRY_WINDOW_SECS` is set
+    pub reentry: Option<ReentryConfig>,
+    /// Anomaly events on pipeline metrics, enabled when `ANOMALY_TOPIC` is set
+    pub anomaly: Option<AnomalyConfig>,
 }
 
 impl ControllerConfig {
     pub fn from_env() -> Result<Self> {
+        let validation_frames = get_env("VALIDATION_FRAMES", 3);
+        let alert_classes = parse_alert_classes(
+            &get_env("ALERT_CLASSES", "0".to_string()),
+            validation_frames,
+            &ClassGroups::from_env()?,
+        )?;
+
         Ok(Self {
             environment: Environment::from_env(),
-            validation_frames: get_env("VALIDATION_FRAMES", 3),
+            validation_frames,
             tracking_exit_frames: get_env("TRACKING_EXIT_FRAMES", 40),
+            alert_classes,
+            alert_confidence: get_env("ALERT_CONFIDENCE", 0.0),
+            alert_count: CountRule::from_env()?,
+            elevation: ElevationConfig::from_env(),
             poll_interval_ms: get_env("POLL_INTERVAL_MS", 500),
+            zones: parse_zones(&get_env("ZONES", String::new()))?,
+            detection_max_age: get_env_opt("DETECTION_MAX_AGE_MS").map(Duration::from_millis),
             mqtt_broker_host: get_env("MQTT_BROKER_HOST", "mosquitto".to_string()),
             mqtt_broker_port: get_env("MQTT_BROKER_PORT", 1883),
             mqtt_topic: get_env("MQTT_TOPIC", "detr-mmap/controller/state".to_string()),
             mqtt_device_id: get_env("MQTT_DEVICE_ID", "unknown".to_string()),
+            mqtt_payload_template: get_env_opt("MQTT_PAYLOAD_TEMPLATE"),
+            gateway_public_url: get_env_opt::<String>("GATEWAY_PUBLIC_URL")
+                .map(|url| url.trim_end_matches('/').to_string()),
+            night_still_on_alarm: get_env("NIGHT_STILL_ON_ALARM", false),
+            dispatch: DispatchConfig::from_env(),
+            commands: CommandConfig::from_env(),
             otel_endpoint: get_env_opt("OTEL_EXPORTER_OTLP_ENDPOINT"),
+            smtp: SmtpConfig::from_env(),
+            s3: S3Config::from_env()?,
+            gpio: GpioConfig::from_env()?,
+            summary: SummaryConfig::from_env()?,
+            occupancy: OccupancyConfig::from_env(validation_frames),
+            reentry: ReentryConfig::from_env(),
+            anomaly: AnomalyConfig::from_env(),
+        })
+    }
+
+    /// Check the alert, elevation and anomaly thresholds, that the MQTT and SMTP
+    /// hosts resolve, the templates parse and the notifier settings are usable
+    pub fn check(&self, check: &mut ConfigCheck) {
+        check.ensure(self.tracking_exit_frames > 0, || {
+            "TRACKING_EXIT_FRAMES must be at least 1".to_string()
+        });
+        for class in &self.alert_classes {
+            check.ensure(class.validation_frames > 0, || {
+                format!(
+                    "ALERT_CLASSES: class {} validates on 0 frames",
+                    class.class_id
+                )
+            });
+        }
+        check.in_range("ALERT_CONFIDENCE", self.alert_confidence, 0.0..=1.0);
+        check.ensure(self.alert_count.min_count > 0, || {
+            "ALERT_MIN_COUNT must be at least 1".to_string()
+        });
+        if let Some(occupancy) = &self.occupancy {
+            check.ensure(occupancy.stable_frames > 0, || {
+                "OCCUPANCY_STABLE_FRAMES must be at least 1".to_string()
+            });
+        }
+        if let Some(anomaly) = &self.anomaly {
+            check.ensure(!anomaly.window.is_zero(), || {
+                "ANOMALY_WINDOW_SECS must be at least 1".to_string()
+            });
+            check.in_range("ANOMALY_SMOOTHING", anomaly.smoothing, 0.001..=1.0);
+            check.ensure(anomaly.sigmas > 0.0, || {
+                "ANOMALY_SIGMAS must be above 0".to_string()
+            });
+        }
+        if let Some(reentry) = &self.reentry {
+            check.in_range("REENTRY_MIN_SIMILARITY", reentry.min_similarity, 0.0..=1.0);
+        }
+        if let Some(elevation) = &self.elevation {
+            check.in_range("ELEVATED_CONFIDENCE", elevation.enter_confidence, 0.0..=1.0);
+            check.ensure(
+                elevation.exit_confidence <= elevation.enter_confidence,
+                || "ELEVATED_EXIT_CONFIDENCE is above ELEVATED_CONFIDENCE".to_string(),
+            );
+            check.in_range("ELEVATED_SMOOTHING", elevation.smoothing, 0.01..=1.0);
+        }
+        check.resolvable(
+            "MQTT_BROKER_HOST",
+            &self.mqtt_broker_host,
+            self.mqtt_broker_port,
+        );
+        if let Some(template) = &self.mqtt_payload_template {
+            check_template(check, "MQTT_PAYLOAD_TEMPLATE", template);
+        }
+        if let Some(url) = &self.gateway_public_url {
+            check.ensure(
+                url.starts_with("http://") || url.starts_with("https://"),
+                || format!("GATEWAY_PUBLIC_URL: {url:?} is not an http(s) URL"),
+            );
+        }
+
+        check.ensure(self.dispatch.queue_capacity > 0, || {
+            "NOTIFY_QUEUE_CAPACITY must be at least 1".to_string()
+        });
+        check.ensure(self.dispatch.max_retries > 0, || {
+            "NOTIFY_MAX_RETRIES must be at least 1".to_string()
+        });
+
+        if let Some(smtp) = &self.smtp {
+            check.resolvable("SMTP_HOST", &smtp.host, smtp.port);
+            check.ensure(!smtp.to.is_empty(), || {
+                "SMTP_TO lists no recipient".to_string()
+            });
+            if let Some(path) = &smtp.snapshot_path {
+                check_snapshot_dir(check, "SMTP_SNAPSHOT_PATH", path);
+            }
+            check_template(check, "SMTP_SUBJECT_TEMPLATE", &smtp.subject_template);
+            check_template(check, "SMTP_BODY_TEMPLATE", &smtp.body_template);
+        }
+        if let Some(summary) = &self.summary {
+            check.ensure(!summary.email || self.smtp.is_some(), || {
+                "DAILY_SUMMARY_EMAIL needs SMTP_HOST".to_string()
+            });
+        }
+        if let Some(gpio) = &self.gpio {
+            check.ensure(cfg!(feature = "gpio"), || {
+                "GPIO_LINES needs a build with the gpio feature".to_string()
+            });
+            check.ensure(!gpio.lines.is_empty() && gpio.lines.len() <= 64, || {
+                format!("GPIO_LINES: {} lines, expected 1 to 64", gpio.lines.len())
+            });
+            check.readable_file("GPIO_CHIP", &gpio.chip);
+        }
+        if let Some(s3) = &self.s3 {
+            check.ensure(
+                s3.endpoint.starts_with("http://") || s3.endpoint.starts_with("https://"),
+                || format!("S3_ENDPOINT: {:?} is not an http(s) URL", s3.endpoint),
+            );
+            check.ensure(s3.queue_capacity > 0, || {
+                "S3_QUEUE_CAPACITY must be at least 1".to_string()
+            });
+            if let Some(path) = &s3.snapshot_path {
+                check_snapshot_dir(check, "S3_SNAPSHOT_PATH", path);
+            }
+        }
+    }
+}
+
+fn check_template(check: &mut ConfigCheck, key: &str, source: &str) {
+    if let Err(e) = Template::parse(source, TEMPLATE_FIELDS) {
+        check.problem(format!("{key}: {e}"));
+    }
+}
+
+/// The snapshot is written by another service, only its directory must exist
+fn check_snapshot_dir(check: &mut ConfigCheck, key: &str, path: &str) {
+    let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+    check.ensure(dir.as_os_str().is_empty() || dir.is_dir(), || {
+        format!("{key}: directory of {path} does not exist")
+    });
+}
+
+/// GPIO lines switched on alarms, see `gpio.rs`
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "gpio"), allow(dead_code))]
+pub struct GpioConfig {
+    /// GPIO character device, e.g. `/dev/gpiochip0`
+    pub chip: String,
+    /// Line offsets on the chip
+    pub lines: Vec<u32>,
+    /// Lines are driven low when on
+    pub active_low: bool,
+    /// Longest the lines stay on during one alarm
+    pub max_on: Duration,
+}
+
+impl GpioConfig {
+    fn from_env() -> Result<Option<Self>> {
+        let Some(lines) = get_env_opt::<String>("GPIO_LINES") else {
+            return Ok(None);
+        };
+        let lines = lines
+            .split(',')
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                line.parse()
+                    .with_context(|| format!("Invalid GPIO_LINES offset {:?}", line))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Some(Self {
+            chip: get_env("GPIO_CHIP", "/dev/gpiochip0".to_string()),
+            lines,
+            active_low: get_env("GPIO_ACTIVE_LOW", false),
+            max_on: Duration::from_secs(get_env("GPIO_MAX_ON_SECS", 120)),
+        }))
+    }
+}
+
+/// Schedule and destinations of the daily summary, see `summary.rs`
+#[derive(Debug, Clone)]
+pub struct SummaryConfig {
+    /// Local time of day the summary is sent at
+    pub at: NaiveTime,
+    pub topic: String,
+    /// Also email the summary through the SMTP settings
+    pub email: bool,
+}
+
+impl SummaryConfig {
+    fn from_env() -> Result<Option<Self>> {
+        let Some(at) = get_env_opt::<String>("DAILY_SUMMARY_TIME") else {
+            return Ok(None);
+        };
+        let at = NaiveTime::parse_from_str(at.trim(), "%H:%M")
+            .with_context(|| format!("Invalid DAILY_SUMMARY_TIME {:?}, expected HH:MM", at))?;
+        Ok(Some(Self {
+            at,
+            topic: get_env(
+                "DAILY_SUMMARY_TOPIC",
+                "detr-mmap/controller/summary".to_string(),
+            ),
+            email: get_env("DAILY_SUMMARY_EMAIL", false),
+        }))
+    }
+}
+
+/// Simultaneous detections an alert class needs before a frame counts towards an alarm
+///
+/// `ALERT_MIN_COUNT` applies all day, or only within `ALERT_MIN_COUNT_HOURS`
+/// (`HH:MM-HH:MM` local time, wrapping past midnight) with a single detection
+/// enough outside of it; `ALERT_MIN_COUNT=2 ALERT_MIN_COUNT_HOURS=22:00-06:00`
+/// alarms on two people at night and on anyone during the day.
+#[derive(Debug, Clone, Copy)]
+pub struct CountRule {
+    pub min_count: u32,
+    /// Local start and end of the time the rule applies in, all day when unset
+    pub hours: Option<(NaiveTime, NaiveTime)>,
+}
+
+impl CountRule {
+    fn from_env() -> Result<Self> {
+        let hours = get_env_opt::<String>("ALERT_MIN_COUNT_HOURS")
+            .map(|hours| parse_hours(&hours))
+            .transpose()?;
+        Ok(Self {
+            min_count: get_env("ALERT_MIN_COUNT", 1),
+            hours,
         })
     }
+
+    /// Detections required at local time `now`
+    pub fn min_count_at(&self, now: NaiveTime) -> u32 {
+        let applies = self.hours.is_none_or(|(start, end)| {
+            if start <= end {
+                now >= start && now < end
+            } else {
+                now >= start || now < end
+            }
+        });
+        if applies { self.min_count } else { 1 }
+    }
+}
+
+fn parse_hours(value: &str) -> Result<(NaiveTime, NaiveTime)> {
+    let parse = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M");
+    value
+        .split_once('-')
+        .and_then(|(start, end)| Some((parse(start).ok()?, parse(end).ok()?)))
+        .with_context(|| {
+            format!(
+                "Invalid ALERT_MIN_COUNT_HOURS {:?}, expected HH:MM-HH:MM",
+                value
+            )
+        })
+}
+
+/// Where and how eagerly occupancy changes are published, see `occupancy.rs`
+#[derive(Debug, Clone)]
+pub struct OccupancyConfig {
+    pub topic: String,
+    /// Consecutive frames a new count must hold before it is published
+    pub stable_frames: u32,
+}
+
+impl OccupancyConfig {
+    fn from_env(validation_frames: u32) -> Option<Self> {
+        Some(Self {
+            topic: get_env_opt("OCCUPANCY_TOPIC")?,
+            stable_frames: get_env("OCCUPANCY_STABLE_FRAMES", validation_frames),
+        })
+    }
+}
+
+/// Where anomalies are published and how sensitive they are, see `anomaly.rs`
+#[derive(Debug, Clone)]
+pub struct AnomalyConfig {
+    pub topic: String,
+    /// Period metrics are aggregated over
+    pub window: Duration,
+    /// Standard deviations from the baseline a window must be to be anomalous
+    pub sigmas: f64,
+    /// Weight of a new window in the baselines
+    pub smoothing: f64,
+    /// Consecutive anomalous windows before an anomaly is published
+    pub persist_windows: u32,
+    /// Results per second below which the frame rate is always anomalous
+    pub min_fps: Option<f64>,
+    /// Inference latency above which it is always anomalous
+    pub max_latency_ms: Option<f64>,
+}
+
+impl AnomalyConfig {
+    fn from_env() -> Option<Self> {
+        Some(Self {
+            topic: get_env_opt("ANOMALY_TOPIC")?,
+            window: Duration::from_secs(get_env("ANOMALY_WINDOW_SECS", 30)),
+            sigmas: get_env("ANOMALY_SIGMAS", 4.0),
+            smoothing: get_env("ANOMALY_SMOOTHING", 0.05),
+            persist_windows: get_env("ANOMALY_PERSIST_WINDOWS", 3u32).max(1),
+            min_fps: get_env_opt("ANOMALY_MIN_FPS"),
+            max_latency_ms: get_env_opt("ANOMALY_MAX_LATENCY_MS"),
+        })
+    }
+}
+
+/// How recent and how alike a subject must be to count as re-entering, see `reentry.rs`
+#[derive(Debug, Clone)]
+pub struct ReentryConfig {
+    /// Longest time between the end of an alarm and the next one
+    pub window: Duration,
+    /// Least histogram similarity between a new and a remembered subject
+    pub min_similarity: f32,
+}
+
+impl ReentryConfig {
+    fn from_env() -> Option<Self> {
+        Some(Self {
+            window: Duration::from_secs(get_env_opt("REENTRY_WINDOW_SECS")?),
+            min_similarity: get_env("REENTRY_MIN_SIMILARITY", 0.75),
+        })
+    }
+}
+
+/// Topics of the MQTT command interface
+#[derive(Debug, Clone)]
+pub struct CommandConfig {
+    pub topic: String,
+    /// Acknowledgements and snapshot events, `<topic>/ack` by default
+    pub ack_topic: String,
+}
+
+impl CommandConfig {
+    fn from_env() -> Option<Self> {
+        let topic: String = get_env_opt("MQTT_COMMAND_TOPIC")?;
+        Some(Self {
+            ack_topic: get_env("MQTT_ACK_TOPIC", format!("{topic}/ack")),
+            topic,
+        })
+    }
+}
+
+/// Thresholds on the smoothed alert-class confidence for the Elevated sentry level
+///
+/// Detections below `CONFIDENCE_THRESHOLD` never leave inference, so it must be lowered
+/// to at most `enter_confidence`, with `ALERT_CONFIDENCE` keeping alarms on confident
+/// detections only.
+#[derive(Debug, Clone, Copy)]
+pub struct ElevationConfig {
+    pub enter_confidence: f32,
+    /// Defaults to half of `enter_confidence`
+    pub exit_confidence: f32,
+    /// Weight of the newest frame in the moving average (0-1]
+    pub smoothing: f32,
+}
+
+impl ElevationConfig {
+    fn from_env() -> Option<Self> {
+        let enter_confidence: f32 = get_env_opt("ELEVATED_CONFIDENCE")?;
+        Some(Self {
+            enter_confidence,
+            exit_confidence: get_env("ELEVATED_EXIT_CONFIDENCE", enter_confidence / 2.0),
+            smoothing: get_env("ELEVATED_SMOOTHING", 0.3),
+        })
+    }
+}
+
+/// A detection class that drives the Standby -> Validation -> Tracking transitions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlertClass {
+    /// Lowest class of `members`, identifies the entry in streaks and logs
+    pub class_id: u16,
+    /// Consecutive frames this class must be seen before entering Tracking
+    pub validation_frames: u32,
+    /// Classes counted as this one, more than `class_id` for a group
+    pub members: ClassSet,
+}
+
+impl AlertClass {
+    #[cfg(test)]
+    pub fn new(class_id: u16, validation_frames: u32) -> Self {
+        Self {
+            class_id,
+            validation_frames,
+            members: ClassSet::single(class_id),
+        }
+    }
+
+    /// Whether any of the `detected` classes counts as this one
+    pub fn matches(&self, detected: &[u16]) -> bool {
+        detected.iter().any(|&id| self.members.contains(id))
+    }
+
+    /// Detections counting as this one, from per-class `counts`
+    pub fn count(&self, counts: &[(u16, u32)]) -> u32 {
+        counts
+            .iter()
+            .filter(|(id, _)| self.members.contains(*id))
+            .map(|(_, count)| count)
+            .sum()
+    }
+}
+
+/// Parse `ALERT_CLASSES`, a comma-separated list of `class[:validation_frames]`
+///
+/// A class is a class id, a COCO label or a group from `groups`; any member of a
+/// group extends the group's streak. Entries without an explicit threshold use
+/// `default_frames` (`VALIDATION_FRAMES`). For example `person,animal:10` alerts
+/// on persons after the default count and on any animal after 10 frames.
+pub fn parse_alert_classes(
+    value: &str,
+    default_frames: u32,
+    groups: &ClassGroups,
+) -> Result<Vec<AlertClass>> {
+    let classes = value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (name, frames) = match entry.split_once(':') {
+                Some((name, frames)) => (name, Some(frames)),
+                None => (entry, None),
+            };
+
+            let members = groups
+                .resolve(name)
+                .with_context(|| format!("Invalid class in ALERT_CLASSES: {:?}", entry))?;
+            let validation_frames = match frames {
+                Some(frames) => frames.trim().parse().with_context(|| {
+                    format!("Invalid validation frames in ALERT_CLASSES: {:?}", entry)
+                })?,
+                None => default_frames,
+            };
+
+            Ok(AlertClass {
+                class_id: members.iter().next().unwrap_or_default(),
+                validation_frames,
+                members,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    if classes.is_empty() {
+        anyhow::bail!("ALERT_CLASSES must list at least one class id");
+    }
+
+    Ok(classes)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SmtpTls {
+    /// TLS from the first byte (SMTPS, usually port 465)
+    Implicit,
+    /// Plain connection upgraded with STARTTLS (usually port 587)
+    #[default]
+    StartTls,
+    /// Unencrypted, only for local relays
+    None,
+}
+
+impl SmtpTls {
+    fn from_env() -> Self {
+        match get_env_opt::<String>("SMTP_TLS") {
+            Some(s) if s.eq_ignore_ascii_case("implicit") || s.eq_ignore_ascii_case("tls") => {
+                Self::Implicit
+            }
+            Some(s) if s.eq_ignore_ascii_case("none") => Self::None,
+            _ => Self::StartTls,
+        }
+    }
+
+    fn default_port(self) -> u16 {
+        match self {
+            Self::Implicit => 465,
+            Self::StartTls => 587,
+            Self::None => 25,
+        }
+    }
+}
+
+pub const DEFAULT_SUBJECT_TEMPLATE: &str = "[{{device_id}}] {{event_type}}";
+pub const DEFAULT_BODY_TEMPLATE: &str = "Device {{device_id}} changed from \
+     {{#if previous_state}}{{previous_state}}{{else}}none{{/if}} to {{state}} at {{timestamp}}.\
+     {{#if class}} Detected {{class}}{{#if zone}} in {{zone}}{{/if}}.{{/if}}\
+     {{#if snapshot_url}}\n\nSnapshot: {{snapshot_url}}{{/if}}";
+
+#[derive(Debug, Clone, Copy)]
+pub struct DispatchConfig {
+    /// Notifications waiting for each notifier before new ones are dropped
+    pub queue_capacity: usize,
+    /// Attempts at sending a notification, including the first
+    pub max_retries: u32,
+}
+
+impl DispatchConfig {
+    pub fn from_env() -> Self {
+        Self {
+            queue_capacity: get_env("NOTIFY_QUEUE_CAPACITY", 16),
+            max_retries: get_env("NOTIFY_MAX_RETRIES", 3),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Subject line, a `common::template` over `notifier::TEMPLATE_FIELDS`
+    pub subject_template: String,
+    /// Plain-text body, same syntax and fields as the subject
+    pub body_template: String,
+    /// JPEG attached to each alert when present
+    pub snapshot_path: Option<String>,
+}
+
+impl SmtpConfig {
+    pub fn from_env() -> Option<Self> {
+        let host: String = get_env_opt("SMTP_HOST")?;
+        let tls = SmtpTls::from_env();
+
+        Some(Self {
+            host,
+            port: get_env("SMTP_PORT", tls.default_port()),
+            tls,
+            username: get_env_opt("SMTP_USERNAME"),
+            password: get_env_opt("SMTP_PASSWORD"),
+            from: get_env("SMTP_FROM", "detr-mmap <detr-mmap@localhost>".to_string()),
+            to: get_env("SMTP_TO", String::new())
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect(),
+            subject_template: get_env(
+                "SMTP_SUBJECT_TEMPLATE",
+                DEFAULT_SUBJECT_TEMPLATE.to_string(),
+            ),
+            body_template: get_env("SMTP_BODY_TEMPLATE", DEFAULT_BODY_TEMPLATE.to_string()),
+            snapshot_path: get_env_opt("SMTP_SNAPSHOT_PATH"),
+        })
+    }
+}
+
+// Manual impl so credentials never end up in the startup config log
+impl std::fmt::Debug for SmtpConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SmtpConfig")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("tls", &self.tls)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("from", &self.from)
+            .field("to", &self.to)
+            .field("snapshot_path", &self.snapshot_path)
+            .finish_non_exhaustive()
+    }
+}
+
+/// S3 Object Lock mode applied to uploads when a retention period is set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RetentionMode {
+    /// Deletable only by users with `s3:BypassGovernanceRetention`
+    #[default]
+    Governance,
+    /// Not deletable by anyone, including the root account, until it expires
+    Compliance,
+}
+
+impl std::str::FromStr for RetentionMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "governance" => Ok(Self::Governance),
+            "compliance" => Ok(Self::Compliance),
+            other => anyhow::bail!("Invalid S3_RETENTION_MODE: {:?}", other),
+        }
+    }
+}
+
+impl RetentionMode {
+    pub fn as_header(self) -> &'static str {
+        match self {
+            Self::Governance => "GOVERNANCE",
+            Self::Compliance => "COMPLIANCE",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct S3Config {
+    /// Base URL of the S3-compatible API, e.g. `https://s3.eu-west-1.amazonaws.com`
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Key prefix, objects land under `<prefix>/<device_id>/<yyyy>/<mm>/<dd>/`
+    pub prefix: String,
+    /// Attempts per object before it is dropped
+    pub max_retries: u32,
+    /// Upload rate cap in bytes per second, unlimited when `None`
+    pub bandwidth_limit: Option<u64>,
+    /// Object Lock retention in days; the bucket must have Object Lock enabled
+    pub retention_days: Option<u32>,
+    pub retention_mode: RetentionMode,
+    /// Uploads waiting for the network before new ones are dropped
+    pub queue_capacity: usize,
+    /// JPEG uploaded alongside each event when present
+    pub snapshot_path: Option<String>,
+}
+
+impl S3Config {
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(endpoint) = get_env_opt::<String>("S3_ENDPOINT") else {
+            return Ok(None);
+        };
+
+        let required = |key: &str| {
+            get_env_opt::<String>(key)
+                .with_context(|| format!("{} is required with S3_ENDPOINT", key))
+        };
+
+        Ok(Some(Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            bucket: required("S3_BUCKET")?,
+            region: get_env("S3_REGION", "us-east-1".to_string()),
+            access_key_id: required("S3_ACCESS_KEY_ID")?,
+            secret_access_key: required("S3_SECRET_ACCESS_KEY")?,
+            prefix: get_env("S3_PREFIX", "detr-mmap".to_string()),
+            max_retries: get_env("S3_MAX_RETRIES", 5),
+            bandwidth_limit: get_env_opt::<u64>("S3_BANDWIDTH_LIMIT_KBPS").map(|kbps| kbps * 1024),
+            retention_days: get_env_opt("S3_RETENTION_DAYS"),
+            retention_mode: match get_env_opt::<String>("S3_RETENTION_MODE") {
+                Some(mode) => mode.parse()?,
+                None => RetentionMode::default(),
+            },
+            queue_capacity: get_env("S3_QUEUE_CAPACITY", 32),
+            snapshot_path: get_env_opt("S3_SNAPSHOT_PATH"),
+        }))
+    }
+}
+
+// Manual impl so credentials never end up in the startup config log
+impl std::fmt::Debug for S3Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3Config")
+            .field("endpoint", &self.endpoint)
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"<redacted>")
+            .field("prefix", &self.prefix)
+            .field("bandwidth_limit", &self.bandwidth_limit)
+            .field("retention_days", &self.retention_days)
+            .field("retention_mode", &self.retention_mode)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_alert_classes() {
+        let classes = parse_alert_classes("0, 21:10", 3, &ClassGroups::default()).unwrap();
+        assert_eq!(
+            classes,
+            vec![AlertClass::new(0, 3), AlertClass::new(21, 10)]
+        );
+    }
+
+    #[test]
+    fn test_parse_alert_classes_by_name_and_group() {
+        let classes = parse_alert_classes("person, vehicle:5", 3, &ClassGroups::default()).unwrap();
+
+        assert_eq!(classes[0], AlertClass::new(0, 3));
+        assert_eq!(classes[1].class_id, 1);
+        assert_eq!(classes[1].validation_frames, 5);
+        assert!(classes[1].matches(&[0, 7]));
+        assert!(!classes[1].matches(&[0, 16]));
+    }
+
+    #[test]
+    fn test_parse_alert_classes_rejects_invalid() {
+        let groups = ClassGroups::default();
+        assert!(parse_alert_classes("", 3, &groups).is_err());
+        assert!(parse_alert_classes("unicorn", 3, &groups).is_err());
+        assert!(parse_alert_classes("0:many", 3, &groups).is_err());
+    }
+
+    #[test]
+    fn test_check_rejects_inconsistent_elevation() {
+        let mut config = ControllerConfig::from_env().unwrap();
+        config.mqtt_broker_host = "localhost".to_string();
+        config.elevation = Some(ElevationConfig {
+            enter_confidence: 0.3,
+            exit_confidence: 0.5,
+            smoothing: 0.3,
+        });
+
+        let result = common::config_check::run(|check| {
+            config.check(check);
+            Ok(())
+        });
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "1 configuration problem(s)"
+        );
+    }
+
+    #[test]
+    fn test_check_rejects_invalid_templates() {
+        let mut config = ControllerConfig::from_env().unwrap();
+        config.mqtt_broker_host = "localhost".to_string();
+        config.mqtt_payload_template = Some(r#"{"zone":{{json zone}}}"#.to_string());
+        common::config_check::run(|check| {
+            config.check(check);
+            Ok(())
+        })
+        .unwrap();
+
+        config.mqtt_payload_template = Some("{{#if zone}}{{zone}}".to_string());
+        let result = common::config_check::run(|check| {
+            config.check(check);
+            Ok(())
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_count_rule_hours_wrap_midnight() {
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        let rule = CountRule {
+            min_count: 2,
+            hours: Some(parse_hours("22:00-06:00").unwrap()),
+        };
+        assert_eq!(rule.min_count_at(time(23, 30)), 2);
+        assert_eq!(rule.min_count_at(time(5, 59)), 2);
+        assert_eq!(rule.min_count_at(time(6, 0)), 1);
+        assert_eq!(rule.min_count_at(time(12, 0)), 1);
+
+        let all_day = CountRule {
+            min_count: 3,
+            hours: None,
+        };
+        assert_eq!(all_day.min_count_at(time(12, 0)), 3);
+        assert!(parse_hours("22:00").is_err());
+    }
+
+    #[test]
+    fn test_retention_mode_parsing() {
+        assert_eq!(
+            "COMPLIANCE".parse::<RetentionMode>().unwrap(),
+            RetentionMode::Compliance
+        );
+        assert_eq!(
+            "governance".parse::<RetentionMode>().unwrap().as_header(),
+            "GOVERNANCE"
+        );
+        assert!("forever".parse::<RetentionMode>().is_err());
+    }
 }
@@ -0,0 +1,334 @@
+//! Fan-out of state change notifications to the notifiers
+//!
+//! Every notifier runs on its own thread behind a bounded queue, so a slow SMTP
+//! server or an unreachable broker delays neither the other notifiers nor the
+//! state machine. A notification that fails is retried with backoff, up to
+//! `NOTIFY_MAX_RETRIES` attempts, and then dropped; one arriving while the
+//! notifier already has `NOTIFY_QUEUE_CAPACITY` waiting is dropped right away.
+//! Both are counted in `controller_notifications_dropped_total`, retries in
+//! `controller_notification_retries_total`.
+//!
+//! Each notifier sends notifications in the order they were dispatched.
+
+use crate::config::DispatchConfig;
+use crate::drill::NotifierOutcome;
+use crate::metrics::NotifierMetrics;
+use crate::notifier::{Notifier, StateChangeNotification};
+use anyhow::{Context, Result};
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError, sync_channel};
+use std::time::{Duration, Instant};
+
+const RETRY_BASE_DELAY_MS: u64 = 500;
+
+/// How long [`NotifierDispatcher::deliver`] waits for the notifiers
+pub const DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where a notifier reports the outcome, with its position among the notifiers
+type Reply = (usize, SyncSender<(usize, NotifierOutcome)>);
+
+struct Job {
+    notification: StateChangeNotification,
+    queued: Instant,
+    /// Set when the sender waits for the outcome
+    reply: Option<Reply>,
+}
+
+struct Queue {
+    name: &'static str,
+    jobs: SyncSender<Job>,
+}
+
+pub struct NotifierDispatcher {
+    queues: Vec<Queue>,
+    metrics: NotifierMetrics,
+}
+
+impl NotifierDispatcher {
+    /// Start a thread for each notifier
+    pub fn new(notifiers: Vec<Box<dyn Notifier>>, config: &DispatchConfig) -> Result<Self> {
+        let metrics = NotifierMetrics::new("controller");
+        let queues: Vec<Queue> = notifiers
+            .into_iter()
+            .map(|notifier| {
+                let name = notifier.name();
+                let (jobs, queue) = sync_channel(config.queue_capacity.max(1));
+                let worker = Worker {
+                    notifier,
+                    max_retries: config.max_retries.max(1),
+                    metrics: metrics.clone(),
+                };
+                std::thread::Builder::new()
+                    .name(format!("notify-{name}"))
+                    .spawn(move || worker.run(queue))
+                    .with_context(|| format!("Failed to spawn the {name} notifier thread"))?;
+                Ok(Queue { name, jobs })
+            })
+            .collect::<Result<_>>()?;
+
+        tracing::info!(
+            notifiers = ?queues.iter().map(|queue| queue.name).collect::<Vec<_>>(),
+            queue_capacity = config.queue_capacity,
+            max_retries = config.max_retries,
+            "Notifiers started"
+        );
+        Ok(Self { queues, metrics })
+    }
+
+    /// Queue `notification` for every notifier without waiting for them,
+    /// returning the names of those that had to drop it
+    pub fn dispatch(&self, notification: &StateChangeNotification) -> Vec<&'static str> {
+        self.queues
+            .iter()
+            .filter(|queue| self.enqueue(queue, notification, None).is_err())
+            .map(|queue| queue.name)
+            .collect()
+    }
+
+    /// Send `notification` through every notifier and wait for their outcomes,
+    /// for test notifications and drills. Latencies include the time spent
+    /// behind notifications queued earlier.
+    pub fn deliver(&self, notification: &StateChangeNotification) -> Vec<NotifierOutcome> {
+        let started = Instant::now();
+        let (reply, replies) = sync_channel(self.queues.len());
+        let mut outcomes: Vec<Option<NotifierOutcome>> = self
+            .queues
+            .iter()
+            .enumerate()
+            .map(|(index, queue)| {
+                self.enqueue(queue, notification, Some((index, reply.clone())))
+                    .err()
+                    .map(|error| NotifierOutcome {
+                        notifier: queue.name,
+                        ok: false,
+                        latency_ms: 0,
+                        error: Some(error.to_string()),
+                    })
+            })
+            .collect();
+        drop(reply);
+
+        let deadline = started + DELIVERY_TIMEOUT;
+        while outcomes.iter().any(Option::is_none) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match replies.recv_timeout(remaining) {
+                Ok((index, outcome)) => outcomes[index] = Some(outcome),
+                Err(_) => break,
+            }
+        }
+
+        outcomes
+            .into_iter()
+            .zip(&self.queues)
+            .map(|(outcome, queue)| {
+                outcome.unwrap_or_else(|| NotifierOutcome {
+                    notifier: queue.name,
+                    ok: false,
+                    latency_ms: started.elapsed().as_millis() as u64,
+                    error: Some(format!("no outcome within {DELIVERY_TIMEOUT:?}")),
+                })
+            })
+            .collect()
+    }
+
+    fn enqueue(
+        &self,
+        queue: &Queue,
+        notification: &StateChangeNotification,
+        reply: Option<Reply>,
+    ) -> Result<(), &'static str> {
+        let job = Job {
+            notification: notification.clone(),
+            queued: Instant::now(),
+            reply,
+        };
+        let (reason, error) = match queue.jobs.try_send(job) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Full(_)) => ("queue_full", "notification queue full"),
+            Err(TrySendError::Disconnected(_)) => ("stopped", "notifier thread stopped"),
+        };
+        self.metrics.record_dropped(queue.name, reason);
+        tracing::warn!(
+            notifier = queue.name,
+            event_type = %notification.event_type,
+            "{error}, dropping notification"
+        );
+        Err(error)
+    }
+}
+
+/// One notifier's thread
+struct Worker {
+    notifier: Box<dyn Notifier>,
+    max_retries: u32,
+    metrics: NotifierMetrics,
+}
+
+impl Worker {
+    fn run(self, jobs: Receiver<Job>) {
+        let name = self.notifier.name();
+        let operation = format!("{name} notification");
+        for job in jobs {
+            let mut attempts = 0u64;
+            let result = common::retry_with_backoff(
+                || {
+                    attempts += 1;
+                    self.notifier.notify(&job.notification)
+                },
+                self.max_retries,
+                RETRY_BASE_DELAY_MS,
+                &operation,
+            );
+            if attempts > 1 {
+                self.metrics.record_retries(name, attempts - 1);
+            }
+            match &result {
+                Ok(()) => tracing::debug!(
+                    notifier = name,
+                    event_type = %job.notification.event_type,
+                    latency_ms = job.queued.elapsed().as_millis() as u64,
+                    "Notification sent"
+                ),
+                // retry_with_backoff logged the error
+                Err(_) => self.metrics.record_dropped(name, "failed"),
+            }
+
+            if let Some((index, reply)) = job.reply {
+                let outcome = NotifierOutcome {
+                    notifier: name,
+                    ok: result.is_ok(),
+                    latency_ms: job.queued.elapsed().as_millis() as u64,
+                    error: result.err().map(|e| format!("{e:#}")),
+                };
+                // The sender may have stopped waiting
+                let _ = reply.send((index, outcome));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_machine::ControllerState;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::mpsc::{Sender, channel};
+
+    /// Reports each notification, after waiting for a go when gated
+    struct Fake {
+        name: &'static str,
+        sent: Sender<&'static str>,
+        gate: Option<Mutex<Receiver<()>>>,
+        failures: AtomicU32,
+    }
+
+    impl Fake {
+        fn new(name: &'static str, sent: &Sender<&'static str>) -> Self {
+            Self {
+                name,
+                sent: sent.clone(),
+                gate: None,
+                failures: AtomicU32::new(0),
+            }
+        }
+    }
+
+    impl Notifier for Fake {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn notify(&self, _: &StateChangeNotification) -> Result<()> {
+            if let Some(gate) = &self.gate {
+                self.sent.send("waiting").unwrap();
+                gate.lock().unwrap().recv()?;
+            }
+            if self
+                .failures
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+                .is_ok()
+            {
+                anyhow::bail!("connection refused");
+            }
+            self.sent.send(self.name).unwrap();
+            Ok(())
+        }
+    }
+
+    fn config(queue_capacity: usize, max_retries: u32) -> DispatchConfig {
+        DispatchConfig {
+            queue_capacity,
+            max_retries,
+        }
+    }
+
+    fn notification() -> StateChangeNotification {
+        StateChangeNotification::new("cam", ControllerState::Tracking, None)
+    }
+
+    #[test]
+    fn test_slow_notifier_delays_only_itself() {
+        let (sent, received) = channel();
+        let (go, gate) = channel();
+        let slow = Fake {
+            gate: Some(Mutex::new(gate)),
+            ..Fake::new("smtp", &sent)
+        };
+        let dispatcher = NotifierDispatcher::new(
+            vec![Box::new(slow), Box::new(Fake::new("mqtt", &sent))],
+            &config(1, 1),
+        )
+        .unwrap();
+        let recv = || received.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        assert!(dispatcher.dispatch(&notification()).is_empty());
+        let mut first = [recv(), recv()];
+        first.sort();
+        assert_eq!(first, ["mqtt", "waiting"]);
+
+        // The slow notifier holds one notification and queues one more
+        assert!(dispatcher.dispatch(&notification()).is_empty());
+        assert_eq!(recv(), "mqtt");
+        assert_eq!(dispatcher.dispatch(&notification()), ["smtp"]);
+        assert_eq!(recv(), "mqtt");
+
+        go.send(()).unwrap();
+        assert_eq!(recv(), "smtp");
+        assert_eq!(recv(), "waiting");
+        go.send(()).unwrap();
+        assert_eq!(recv(), "smtp");
+    }
+
+    #[test]
+    fn test_deliver_retries_and_reports_in_order() {
+        let (sent, _received) = channel();
+        let flaky = Fake {
+            failures: AtomicU32::new(1),
+            ..Fake::new("smtp", &sent)
+        };
+        let broken = Fake {
+            failures: AtomicU32::new(u32::MAX),
+            ..Fake::new("s3", &sent)
+        };
+        let dispatcher = NotifierDispatcher::new(
+            vec![
+                Box::new(broken),
+                Box::new(flaky),
+                Box::new(Fake::new("mqtt", &sent)),
+            ],
+            &config(4, 2),
+        )
+        .unwrap();
+
+        let outcomes = dispatcher.deliver(&notification());
+        let names: Vec<_> = outcomes.iter().map(|o| o.notifier).collect();
+        assert_eq!(names, ["s3", "smtp", "mqtt"]);
+        assert_eq!(
+            outcomes[0].error.as_deref(),
+            Some("connection refused"),
+            "out of retries"
+        );
+        assert!(outcomes[1].ok);
+        assert!(outcomes[2].ok);
+    }
+}
@@ -0,0 +1,209 @@
+//! End-to-end alert drills
+//!
+//! A drill checks the whole alert chain without anyone walking in front of the
+//! camera. It is started by the `alert_drill` command or the gateway's
+//! `POST /api/controller/drill`:
+//! 1. the gateway keeps its next frame as a snapshot, like `request_snapshot`
+//! 2. once it did, or after `DRILL_SNAPSHOT_TIMEOUT`, a `test_notification` event
+//!    goes through every notifier's queue (see `dispatch.rs`), each one timed
+//! 3. a `drill_report` is published on the ack topic and served by the gateway's
+//!    `GET /api/controller/drill`
+//!
+//! Drills run whether the controller is armed or not.
+
+use chrono::Utc;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// How long a drill waits for the gateway to keep its snapshot
+pub const DRILL_SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A drill waiting for its snapshot
+#[derive(Debug)]
+pub struct Drill {
+    /// Id of the command that started it, `http-<n>` for the gateway's requests
+    pub id: Option<String>,
+    /// Snapshot request number, `None` without snapshot control
+    pub snapshot_request: Option<u64>,
+    pub started: Instant,
+}
+
+impl Drill {
+    pub fn new(id: Option<String>, snapshot_request: Option<u64>) -> Self {
+        Self {
+            id,
+            snapshot_request,
+            started: Instant::now(),
+        }
+    }
+
+    /// Whether the notifiers should run, with the snapshot `fulfilled` so far
+    pub fn ready(&self, fulfilled: Option<(u64, u64)>, now: Instant) -> bool {
+        let kept = match (self.snapshot_request, fulfilled) {
+            (None, _) => true,
+            (Some(request), Some((done, _))) => done >= request,
+            (Some(_), None) => false,
+        };
+        kept || now.duration_since(self.started) >= DRILL_SNAPSHOT_TIMEOUT
+    }
+}
+
+/// Snapshot step of a drill
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotOutcome {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frame_number: Option<u64>,
+    /// Gateway path serving the JPEG
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub latency_ms: u64,
+}
+
+/// Outcome of one notifier in a drill
+#[derive(Debug, Clone, Serialize)]
+pub struct NotifierOutcome {
+    pub notifier: &'static str,
+    pub ok: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Published on the ack topic, and served by the gateway, once a drill ran
+#[derive(Debug, Clone, Serialize)]
+pub struct DrillReport {
+    pub device_id: String,
+    pub timestamp: String,
+    pub event: &'static str,
+    pub id: Option<String>,
+    /// Every step succeeded
+    pub passed: bool,
+    /// `None` without snapshot control
+    pub snapshot: Option<SnapshotOutcome>,
+    pub notifiers: Vec<NotifierOutcome>,
+    pub duration_ms: u64,
+}
+
+impl DrillReport {
+    pub fn new(
+        device_id: &str,
+        drill: &Drill,
+        snapshot: Option<SnapshotOutcome>,
+        notifiers: Vec<NotifierOutcome>,
+    ) -> Self {
+        let passed = snapshot.as_ref().is_none_or(|s| s.ok) && notifiers.iter().all(|n| n.ok);
+        Self {
+            device_id: device_id.to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+            event: "drill_report",
+            id: drill.id.clone(),
+            passed,
+            snapshot,
+            notifiers,
+            duration_ms: drill.started.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+/// Snapshot step of `drill`, the gateway having kept `fulfilled` so far
+pub fn snapshot_outcome(drill: &Drill, fulfilled: Option<(u64, u64)>) -> Option<SnapshotOutcome> {
+    let request = drill.snapshot_request?;
+    let latency_ms = drill.started.elapsed().as_millis() as u64;
+    Some(match fulfilled {
+        Some((done, frame_number)) if done >= request => SnapshotOutcome {
+            ok: true,
+            frame_number: Some(frame_number),
+            path: Some(format!("/api/snapshots/{frame_number}")),
+            latency_ms,
+        },
+        _ => SnapshotOutcome {
+            ok: false,
+            frame_number: None,
+            path: None,
+            latency_ms,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DispatchConfig;
+    use crate::dispatch::NotifierDispatcher;
+    use crate::notifier::{Notifier, StateChangeNotification};
+    use crate::state_machine::ControllerState;
+
+    struct Fake {
+        name: &'static str,
+        fails: bool,
+    }
+
+    impl Notifier for Fake {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn notify(&self, _: &StateChangeNotification) -> anyhow::Result<()> {
+            if self.fails {
+                anyhow::bail!("connection refused");
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_waits_for_the_snapshot_until_timeout() {
+        let drill = Drill::new(None, Some(3));
+        let now = drill.started;
+
+        assert!(!drill.ready(None, now));
+        // An earlier request being fulfilled does not count
+        assert!(!drill.ready(Some((2, 10)), now));
+        assert!(drill.ready(Some((3, 11)), now));
+        assert!(drill.ready(None, now + DRILL_SNAPSHOT_TIMEOUT));
+        assert!(Drill::new(None, None).ready(None, now));
+
+        let outcome = snapshot_outcome(&drill, Some((3, 11))).unwrap();
+        assert!(outcome.ok);
+        assert_eq!(outcome.path.as_deref(), Some("/api/snapshots/11"));
+        assert!(!snapshot_outcome(&drill, Some((2, 10))).unwrap().ok);
+    }
+
+    #[test]
+    fn test_report_lists_every_notifier() {
+        let notifiers: Vec<Box<dyn Notifier>> = vec![
+            Box::new(Fake {
+                name: "mqtt",
+                fails: false,
+            }),
+            Box::new(Fake {
+                name: "smtp",
+                fails: true,
+            }),
+        ];
+        let notification = StateChangeNotification::test("cam", ControllerState::Standby);
+        let drill = Drill::new(Some("7".to_string()), None);
+        let dispatcher = NotifierDispatcher::new(
+            notifiers,
+            &DispatchConfig {
+                queue_capacity: 1,
+                max_retries: 1,
+            },
+        )
+        .unwrap();
+
+        let report = DrillReport::new("cam", &drill, None, dispatcher.deliver(&notification));
+        assert!(!report.passed);
+        assert_eq!(report.notifiers.len(), 2);
+        assert!(report.notifiers[0].ok);
+        assert_eq!(
+            report.notifiers[1].error.as_deref(),
+            Some("connection refused")
+        );
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["event"], "drill_report");
+        assert_eq!(json["id"], "7");
+    }
+}
@@ -0,0 +1,149 @@
+//! Table-driven finite state machine
+//!
+//! A machine is a static table of transitions plus entry and exit actions, working
+//! on a context `C` that holds whatever counters the guards and actions need. New
+//! states or events are new table rows rather than new branches in an update
+//! function.
+//!
+//! On each event the first row matching the current state and event whose guard
+//! passes is taken. Leaving a state runs its exit actions, then the row's action,
+//! then the entry actions of the new state. A row whose target is its own source
+//! is internal: only its action runs.
+
+pub struct Transition<S: 'static, E: 'static, C: 'static> {
+    pub from: S,
+    pub event: E,
+    pub to: S,
+    /// Row is skipped unless this holds
+    pub guard: Option<fn(&C) -> bool>,
+    pub action: Option<fn(&mut C)>,
+}
+
+/// Action run when a state is entered or left
+pub struct StateAction<S: 'static, C: 'static> {
+    pub state: S,
+    pub action: fn(&mut C),
+}
+
+pub struct Fsm<S: 'static, E: 'static, C: 'static> {
+    pub transitions: &'static [Transition<S, E, C>],
+    pub on_entry: &'static [StateAction<S, C>],
+    pub on_exit: &'static [StateAction<S, C>],
+}
+
+impl<S: Copy + PartialEq, E: PartialEq, C> Fsm<S, E, C> {
+    /// State after `event` in `state`, running the matching guards and actions on `ctx`
+    pub fn fire(&self, state: S, event: E, ctx: &mut C) -> S {
+        let Some(transition) = self.transitions.iter().find(|t| {
+            t.from == state && t.event == event && t.guard.is_none_or(|guard| guard(ctx))
+        }) else {
+            return state;
+        };
+
+        let changed = transition.to != state;
+        if changed {
+            run_actions(self.on_exit, state, ctx);
+        }
+        if let Some(action) = transition.action {
+            action(ctx);
+        }
+        if changed {
+            run_actions(self.on_entry, transition.to, ctx);
+        }
+        transition.to
+    }
+}
+
+fn run_actions<S: PartialEq, C>(actions: &[StateAction<S, C>], state: S, ctx: &mut C) {
+    for entry in actions.iter().filter(|entry| entry.state == state) {
+        (entry.action)(ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Door {
+        Closed,
+        Open,
+        Locked,
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Push,
+        Lock,
+    }
+
+    #[derive(Default)]
+    struct Log {
+        has_key: bool,
+        pushes: u32,
+        trace: Vec<&'static str>,
+    }
+
+    static DOOR: Fsm<Door, Event, Log> = Fsm {
+        transitions: &[
+            Transition {
+                from: Door::Closed,
+                event: Event::Push,
+                to: Door::Open,
+                guard: None,
+                action: Some(|log| log.trace.push("swing")),
+            },
+            Transition {
+                from: Door::Open,
+                event: Event::Push,
+                to: Door::Open,
+                guard: None,
+                action: Some(|log| log.pushes += 1),
+            },
+            Transition {
+                from: Door::Closed,
+                event: Event::Lock,
+                to: Door::Locked,
+                guard: Some(|log| log.has_key),
+                action: None,
+            },
+        ],
+        on_entry: &[StateAction {
+            state: Door::Open,
+            action: |log| log.trace.push("enter open"),
+        }],
+        on_exit: &[StateAction {
+            state: Door::Closed,
+            action: |log| log.trace.push("exit closed"),
+        }],
+    };
+
+    #[test]
+    fn test_actions_run_exit_then_transition_then_entry() {
+        let mut log = Log::default();
+
+        assert_eq!(DOOR.fire(Door::Closed, Event::Push, &mut log), Door::Open);
+        assert_eq!(log.trace, ["exit closed", "swing", "enter open"]);
+    }
+
+    #[test]
+    fn test_internal_transition_skips_entry_and_exit() {
+        let mut log = Log::default();
+
+        assert_eq!(DOOR.fire(Door::Open, Event::Push, &mut log), Door::Open);
+        assert_eq!(log.pushes, 1);
+        assert!(log.trace.is_empty());
+    }
+
+    #[test]
+    fn test_guard_and_unmatched_event_keep_state() {
+        let mut log = Log::default();
+
+        assert_eq!(DOOR.fire(Door::Closed, Event::Lock, &mut log), Door::Closed);
+        assert_eq!(DOOR.fire(Door::Open, Event::Lock, &mut log), Door::Open);
+        assert!(log.trace.is_empty());
+
+        log.has_key = true;
+        assert_eq!(DOOR.fire(Door::Closed, Event::Lock, &mut log), Door::Locked);
+    }
+}
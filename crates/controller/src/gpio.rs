@@ -0,0 +1,252 @@
+//! Siren or light on GPIO lines
+//!
+//! With `GPIO_LINES` set the controller drives those lines of `GPIO_CHIP` when the
+//! alarm enters Tracking while armed, and releases them when the scene clears back
+//! to Standby or the system is disarmed. Lines are never held longer than
+//! `GPIO_MAX_ON_SECS`, so a scene that never clears cannot keep a siren going.
+//!
+//! Lines are requested through the GPIO character device (the v2 uAPI libgpiod
+//! uses) and released, switched off, when the controller exits.
+
+use crate::config::GpioConfig;
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::time::{Duration, Instant};
+
+/// Something that can be switched on and off
+pub trait Switch {
+    fn set(&mut self, on: bool) -> Result<()>;
+}
+
+/// Switch turned on by an alarm, with a cutoff
+pub struct Siren<S: Switch> {
+    switch: S,
+    max_on: Duration,
+    on_since: Option<Instant>,
+}
+
+impl<S: Switch> Siren<S> {
+    pub fn new(switch: S, max_on: Duration) -> Self {
+        Self {
+            switch,
+            max_on,
+            on_since: None,
+        }
+    }
+
+    pub fn on(&mut self, now: Instant) {
+        if self.on_since.is_none() && self.set(true) {
+            tracing::info!("Siren on");
+            self.on_since = Some(now);
+        }
+    }
+
+    pub fn off(&mut self) {
+        if self.on_since.is_some() && self.set(false) {
+            tracing::info!("Siren off");
+            self.on_since = None;
+        }
+    }
+
+    /// Switch off once on for longer than the cutoff
+    pub fn enforce_max_on(&mut self, now: Instant) {
+        if self
+            .on_since
+            .is_some_and(|since| now.duration_since(since) >= self.max_on)
+        {
+            tracing::warn!(max_on = ?self.max_on, "Siren on for too long, switching it off");
+            self.off();
+        }
+    }
+
+    fn set(&mut self, on: bool) -> bool {
+        self.switch
+            .set(on)
+            .inspect_err(|e| tracing::error!(error = %e, on, "Failed to switch siren"))
+            .is_ok()
+    }
+}
+
+impl<S: Switch> Drop for Siren<S> {
+    fn drop(&mut self) {
+        self.off();
+    }
+}
+
+const GPIO_V2_LINES_MAX: usize = 64;
+const GPIO_V2_LINE_FLAG_ACTIVE_LOW: u64 = 1 << 1;
+const GPIO_V2_LINE_FLAG_OUTPUT: u64 = 1 << 3;
+
+#[repr(C)]
+#[derive(Default)]
+struct LineAttribute {
+    id: u32,
+    padding: u32,
+    /// Union of the attribute's flags, values or debounce period
+    value: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct LineConfigAttribute {
+    attr: LineAttribute,
+    mask: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct LineConfig {
+    flags: u64,
+    num_attrs: u32,
+    padding: [u32; 5],
+    attrs: [LineConfigAttribute; 10],
+}
+
+#[repr(C)]
+struct LineRequest {
+    offsets: [u32; GPIO_V2_LINES_MAX],
+    consumer: [u8; 32],
+    config: LineConfig,
+    num_lines: u32,
+    event_buffer_size: u32,
+    padding: [u32; 5],
+    fd: i32,
+}
+
+#[repr(C)]
+struct LineValues {
+    bits: u64,
+    mask: u64,
+}
+
+// Layouts of `struct gpio_v2_line_request` and `struct gpio_v2_line_values`
+const _: () = assert!(std::mem::size_of::<LineRequest>() == 592);
+const _: () = assert!(std::mem::size_of::<LineValues>() == 16);
+
+/// `_IOWR(0xB4, nr, size)`
+const fn iowr(nr: u64, size: usize) -> u64 {
+    (3 << 30) | ((size as u64) << 16) | (0xB4 << 8) | nr
+}
+
+const GPIO_V2_GET_LINE_IOCTL: u64 = iowr(0x07, std::mem::size_of::<LineRequest>());
+const GPIO_V2_LINE_SET_VALUES_IOCTL: u64 = iowr(0x0F, std::mem::size_of::<LineValues>());
+
+/// Output lines of a GPIO chip, switched together
+pub struct GpioLines {
+    fd: OwnedFd,
+    mask: u64,
+}
+
+impl GpioLines {
+    /// Request the configured lines as outputs, initially off
+    pub fn open(config: &GpioConfig) -> Result<Self> {
+        let chip = File::options()
+            .read(true)
+            .write(true)
+            .open(&config.chip)
+            .with_context(|| format!("Failed to open GPIO chip {}", config.chip))?;
+
+        let mut request = LineRequest {
+            offsets: [0; GPIO_V2_LINES_MAX],
+            consumer: [0; 32],
+            config: LineConfig {
+                flags: GPIO_V2_LINE_FLAG_OUTPUT
+                    | if config.active_low {
+                        GPIO_V2_LINE_FLAG_ACTIVE_LOW
+                    } else {
+                        0
+                    },
+                ..Default::default()
+            },
+            num_lines: config.lines.len() as u32,
+            event_buffer_size: 0,
+            padding: [0; 5],
+            fd: -1,
+        };
+        request.offsets[..config.lines.len()].copy_from_slice(&config.lines);
+        request.consumer[..10].copy_from_slice(b"controller");
+
+        // SAFETY: the request matches `struct gpio_v2_line_request`, which the
+        // kernel reads and fills with the line fd
+        if unsafe { libc::ioctl(chip.as_raw_fd(), GPIO_V2_GET_LINE_IOCTL as _, &mut request) } < 0 {
+            return Err(std::io::Error::last_os_error()).with_context(|| {
+                format!(
+                    "Failed to request lines {:?} of {}",
+                    config.lines, config.chip
+                )
+            });
+        }
+        tracing::info!(chip = %config.chip, lines = ?config.lines, "GPIO lines requested");
+
+        Ok(Self {
+            // SAFETY: the kernel returned a new fd owned by nothing else
+            fd: unsafe { OwnedFd::from_raw_fd(request.fd) },
+            mask: u64::MAX >> (64 - config.lines.len()),
+        })
+    }
+}
+
+impl Switch for GpioLines {
+    fn set(&mut self, on: bool) -> Result<()> {
+        let mut values = LineValues {
+            bits: if on { self.mask } else { 0 },
+            mask: self.mask,
+        };
+        // SAFETY: the values match `struct gpio_v2_line_values`
+        if unsafe {
+            libc::ioctl(
+                self.fd.as_raw_fd(),
+                GPIO_V2_LINE_SET_VALUES_IOCTL as _,
+                &mut values,
+            )
+        } < 0
+        {
+            return Err(std::io::Error::last_os_error()).context("Failed to set GPIO lines");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct Recorder(Rc<RefCell<Vec<bool>>>);
+
+    impl Switch for Recorder {
+        fn set(&mut self, on: bool) -> Result<()> {
+            self.0.borrow_mut().push(on);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_siren_is_cut_off_after_max_on() {
+        let switched = Recorder::default();
+        let mut siren = Siren::new(switched.clone(), Duration::from_secs(60));
+        let start = Instant::now();
+
+        siren.on(start);
+        siren.on(start + Duration::from_secs(10));
+        siren.enforce_max_on(start + Duration::from_secs(59));
+        assert_eq!(*switched.0.borrow(), [true]);
+
+        siren.enforce_max_on(start + Duration::from_secs(60));
+        assert_eq!(*switched.0.borrow(), [true, false]);
+
+        // A new alarm switches it on again
+        siren.on(start + Duration::from_secs(70));
+        drop(siren);
+        assert_eq!(*switched.0.borrow(), [true, false, true, false]);
+    }
+
+    #[test]
+    fn test_ioctl_numbers() {
+        assert_eq!(GPIO_V2_GET_LINE_IOCTL, 0xC250_B407);
+        assert_eq!(GPIO_V2_LINE_SET_VALUES_IOCTL, 0xC010_B40F);
+    }
+}
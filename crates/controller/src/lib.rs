@@ -0,0 +1,21 @@
+pub mod anomaly;
+pub mod commands;
+pub mod config;
+pub mod dispatch;
+pub mod drill;
+pub mod fsm;
+#[cfg(feature = "gpio")]
+pub mod gpio;
+pub mod linkage;
+pub mod metrics;
+pub mod mqtt_notifier;
+pub mod notifier;
+pub mod occupancy;
+pub mod reentry;
+pub mod replay;
+pub mod s3_uploader;
+pub mod service;
+pub mod smtp_notifier;
+pub mod state_machine;
+pub mod summary;
+pub mod zones;
@@ -0,0 +1,140 @@
+//! Cross-check of a detection result against the ones already acted on
+//!
+//! The detection semaphore only says that inference wrote something. By the time
+//! the controller reads the buffer it may hold a result it already consumed, or one
+//! computed on a frame older than the last result (e.g. a slow inference run), or a
+//! result so old it no longer describes the scene. Those must not advance the state
+//! machine.
+
+use common::WallClockNs;
+use std::time::Duration;
+
+/// Why a detection result was skipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Staleness {
+    /// The detection buffer was not rewritten since the last result
+    Duplicate,
+    /// Computed on a frame captured before the last result's frame
+    OutOfOrder,
+    /// The frame is older than the configured maximum age
+    TooOld(Duration),
+}
+
+impl Staleness {
+    /// Metric attribute value
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::Duplicate => "duplicate",
+            Self::OutOfOrder => "out_of_order",
+            Self::TooOld(_) => "too_old",
+        }
+    }
+}
+
+/// Identity of a detection result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResultStamp {
+    /// Detection buffer sequence the result was read at
+    pub sequence: u64,
+    pub frame_number: u64,
+    /// Capture time of the frame
+    pub timestamp: WallClockNs,
+}
+
+pub struct FrameLinkage {
+    max_age: Option<Duration>,
+    last: Option<ResultStamp>,
+}
+
+impl FrameLinkage {
+    pub fn new(max_age: Option<Duration>) -> Self {
+        Self {
+            max_age,
+            last: None,
+        }
+    }
+
+    /// Check a result read at `now`, remembering it if it is fresh
+    pub fn check(&mut self, stamp: ResultStamp, now: WallClockNs) -> Result<(), Staleness> {
+        if let Some(last) = self.last {
+            if stamp.sequence == last.sequence {
+                return Err(Staleness::Duplicate);
+            }
+            // Frame numbers restart with capture, capture times do not
+            if stamp.frame_number <= last.frame_number && stamp.timestamp <= last.timestamp {
+                return Err(Staleness::OutOfOrder);
+            }
+        }
+
+        if let Some(max_age) = self.max_age {
+            let age = now.saturating_duration_since(stamp.timestamp);
+            if age > max_age {
+                return Err(Staleness::TooOld(age));
+            }
+        }
+
+        self.last = Some(stamp);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> WallClockNs {
+        WallClockNs::from_nanos(ms * 1_000_000)
+    }
+
+    fn stamp(sequence: u64, frame_number: u64, timestamp_ms: u64) -> ResultStamp {
+        ResultStamp {
+            sequence,
+            frame_number,
+            timestamp: ms(timestamp_ms),
+        }
+    }
+
+    #[test]
+    fn test_newer_results_are_fresh() {
+        let mut linkage = FrameLinkage::new(None);
+
+        assert_eq!(linkage.check(stamp(1, 10, 1000), ms(1000)), Ok(()));
+        assert_eq!(linkage.check(stamp(2, 12, 1066), ms(1066)), Ok(()));
+    }
+
+    #[test]
+    fn test_duplicate_and_older_results_are_stale() {
+        let mut linkage = FrameLinkage::new(None);
+        linkage.check(stamp(5, 10, 1000), ms(1000)).unwrap();
+
+        assert_eq!(
+            linkage.check(stamp(5, 10, 1000), ms(1100)),
+            Err(Staleness::Duplicate)
+        );
+        assert_eq!(
+            linkage.check(stamp(6, 9, 966), ms(1100)),
+            Err(Staleness::OutOfOrder)
+        );
+        // Stale results do not move the reference
+        assert_eq!(linkage.check(stamp(7, 11, 1033), ms(1100)), Ok(()));
+    }
+
+    #[test]
+    fn test_capture_restart_is_not_out_of_order() {
+        let mut linkage = FrameLinkage::new(None);
+        linkage.check(stamp(5, 5000, 1000), ms(1000)).unwrap();
+
+        assert_eq!(linkage.check(stamp(6, 1, 2000), ms(2000)), Ok(()));
+    }
+
+    #[test]
+    fn test_results_past_max_age_are_stale() {
+        let mut linkage = FrameLinkage::new(Some(Duration::from_millis(500)));
+
+        assert_eq!(
+            linkage.check(stamp(1, 10, 1000), ms(1600)),
+            Err(Staleness::TooOld(Duration::from_millis(600)))
+        );
+        assert_eq!(linkage.check(stamp(2, 11, 1200), ms(1600)), Ok(()));
+    }
+}
@@ -1,13 +1,15 @@
-mod config;
-mod mqtt_notifier;
-mod service;
-mod state_machine;
-
-use common::TelemetryGuard;
-use config::ControllerConfig;
-use service::ControllerService;
+use common::{Dependency, Readiness, TelemetryGuard};
+use controller::config::ControllerConfig;
+use controller::service::ControllerService;
 
 fn main() -> anyhow::Result<()> {
+    common::load_profile()?;
+    if common::config_check::requested() {
+        return common::config_check::run(|check| {
+            ControllerConfig::from_env()?.check(check);
+            Ok(())
+        });
+    }
     let config = ControllerConfig::from_env()?;
 
     // TelemetryGuard requires a Tokio runtime for async OTLP exporters.
@@ -32,6 +34,7 @@ fn main() -> anyhow::Result<()> {
 
     tracing::info!("Controller starting with config: {:?}", config);
 
-    let service = ControllerService::new(config)?;
+    let readiness = Readiness::from_env(&[Dependency::DetectionBuffer, Dependency::Semaphores]);
+    let service = ControllerService::new(config, &readiness)?;
     service.run()
 }
@@ -0,0 +1,115 @@
+//! OpenTelemetry instruments of the controller
+//!
+//! Exported through the OTLP pipeline set up by `common::TelemetryGuard`; without
+//! an endpoint the global meter is a no-op.
+
+use crate::linkage::Staleness;
+use crate::zones::{ZoneActivity, ZoneTracker};
+use common::classes::class_name;
+use opentelemetry::{KeyValue, global, metrics::Counter};
+
+pub struct ControllerMetrics {
+    stale_detections: Counter<u64>,
+    zone_entries: Counter<u64>,
+    zone_dwell: Counter<f64>,
+    zone_alarms: Counter<u64>,
+}
+
+impl ControllerMetrics {
+    pub fn new(meter_name: &'static str) -> Self {
+        let meter = global::meter(meter_name);
+
+        Self {
+            stale_detections: meter
+                .u64_counter("controller_stale_detections_total")
+                .with_description("Detection results skipped as stale, by reason")
+                .build(),
+            zone_entries: meter
+                .u64_counter("controller_zone_entries_total")
+                .with_description("Classes entering a zone, by zone and class")
+                .build(),
+            zone_dwell: meter
+                .f64_counter("controller_zone_dwell_seconds_total")
+                .with_description("Time classes were present in a zone, by zone and class")
+                .with_unit("s")
+                .build(),
+            zone_alarms: meter
+                .u64_counter("controller_zone_alarms_total")
+                .with_description("Alarms raised, by zone and class present in it")
+                .build(),
+        }
+    }
+
+    pub fn record_stale(&self, staleness: Staleness) {
+        self.stale_detections
+            .add(1, &[KeyValue::new("reason", staleness.reason())]);
+    }
+
+    pub fn record_zone_activity(&self, zones: &ZoneTracker, activity: &ZoneActivity) {
+        for &(zone, class_id) in &activity.entries {
+            self.zone_entries
+                .add(1, &zone_attributes(zones, zone, class_id));
+        }
+        for &(zone, class_id, dwell) in &activity.dwell {
+            self.zone_dwell
+                .add(dwell.as_secs_f64(), &zone_attributes(zones, zone, class_id));
+        }
+    }
+
+    /// Count an alarm raised by `class_id` in every zone it is present in
+    pub fn record_zone_alarm(&self, zones: &ZoneTracker, class_id: u16) {
+        for zone in zones.zones_of(class_id) {
+            self.zone_alarms
+                .add(1, &zone_attributes(zones, zone, class_id));
+        }
+    }
+}
+
+/// Instruments of the notifier queues, cloned into every notifier thread
+#[derive(Clone)]
+pub struct NotifierMetrics {
+    dropped: Counter<u64>,
+    retries: Counter<u64>,
+}
+
+impl NotifierMetrics {
+    pub fn new(meter_name: &'static str) -> Self {
+        let meter = global::meter(meter_name);
+
+        Self {
+            dropped: meter
+                .u64_counter("controller_notifications_dropped_total")
+                .with_description("Notifications a notifier never sent, by notifier and reason")
+                .build(),
+            retries: meter
+                .u64_counter("controller_notification_retries_total")
+                .with_description("Notification attempts repeated after a failure, by notifier")
+                .build(),
+        }
+    }
+
+    /// Count a notification `notifier` dropped, `reason` being `queue_full`,
+    /// `failed` once out of retries or `stopped` when its thread is gone
+    pub fn record_dropped(&self, notifier: &'static str, reason: &'static str) {
+        self.dropped.add(
+            1,
+            &[
+                KeyValue::new("notifier", notifier),
+                KeyValue::new("reason", reason),
+            ],
+        );
+    }
+
+    pub fn record_retries(&self, notifier: &'static str, retries: u64) {
+        self.retries
+            .add(retries, &[KeyValue::new("notifier", notifier)]);
+    }
+}
+
+fn zone_attributes(zones: &ZoneTracker, zone: usize, class_id: u16) -> [KeyValue; 2] {
+    let class = class_name(class_id).map_or_else(|| class_id.to_string(), str::to_string);
+    [
+        KeyValue::new("zone", zones.zone_name(zone).to_string()),
+        KeyValue::new("class", class),
+    ]
+}
@@ -1,21 +1,12 @@
 use anyhow::{Context, Result};
-use chrono::Utc;
+use common::Template;
 use rumqttc::{Client, ConnectionError, Event, MqttOptions, Packet, QoS};
-use serde::Serialize;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, TrySendError, sync_channel};
 use std::time::Duration;
 
-use crate::state_machine::ControllerState;
-
-#[derive(Debug, Serialize)]
-pub struct StateChangeNotification {
-    pub device_id: String,
-    pub timestamp: String,
-    pub state: String,
-    pub previous_state: Option<String>,
-    pub event_type: String,
-}
+use crate::notifier::{Notifier, StateChangeNotification};
 
 #[allow(dead_code)]
 pub struct MqttNotifier {
@@ -23,14 +14,21 @@ pub struct MqttNotifier {
     topic: String,
     device_id: String,
     connected: Arc<AtomicBool>,
+    commands: Option<Receiver<Vec<u8>>>,
+    /// Payload published instead of the JSON event
+    payload_template: Option<Template>,
 }
 
+/// Commands queued while the controller is busy with a frame
+const COMMAND_QUEUE_CAPACITY: usize = 16;
+
 impl MqttNotifier {
     pub fn new(
         broker_host: &str,
         broker_port: u16,
         topic: String,
         device_id: String,
+        command_topic: Option<String>,
     ) -> Result<Self> {
         let mut mqtt_options = MqttOptions::new("detr-mmap-controller", broker_host, broker_port);
         mqtt_options.set_keep_alive(Duration::from_secs(30));
@@ -39,6 +37,9 @@ impl MqttNotifier {
         let (client, mut connection) = Client::new(mqtt_options, 10);
         let connected = Arc::new(AtomicBool::new(false));
         let connected_clone = Arc::clone(&connected);
+        let subscriber = client.clone();
+        let (command_tx, commands) = sync_channel(COMMAND_QUEUE_CAPACITY);
+        let commands = command_topic.as_ref().map(|_| commands);
 
         std::thread::spawn(move || {
             let mut reconnect_attempts = 0u32;
@@ -50,6 +51,23 @@ impl MqttNotifier {
                             connected_clone.store(true, Ordering::Release);
                             reconnect_attempts = 0;
                             tracing::info!("MQTT connected to broker");
+                            // Clean sessions drop subscriptions, so renew it on every connect
+                            if let Some(topic) = &command_topic
+                                && let Err(e) = subscriber.try_subscribe(topic, QoS::AtLeastOnce)
+                            {
+                                tracing::error!(error = %e, "Failed to subscribe to commands");
+                            }
+                        }
+                        Ok(Event::Incoming(Packet::Publish(publish)))
+                            if command_topic.as_deref() == Some(publish.topic.as_str()) =>
+                        {
+                            match command_tx.try_send(publish.payload.to_vec()) {
+                                Ok(()) => {}
+                                Err(TrySendError::Full(_)) => {
+                                    tracing::warn!("Command queue full, dropping command");
+                                }
+                                Err(TrySendError::Disconnected(_)) => {}
+                            }
                         }
                         Ok(Event::Incoming(Packet::PingResp)) => {
                             tracing::trace!("MQTT ping response received");
@@ -103,44 +121,54 @@ impl MqttNotifier {
             topic,
             device_id,
             connected,
+            commands,
+            payload_template: None,
         })
     }
 
+    /// Commands received on the command topic, once; `None` without one
+    pub fn take_commands(&mut self) -> Option<Receiver<Vec<u8>>> {
+        self.commands.take()
+    }
+
+    /// Publish `template` rendered over each notification instead of its event
+    pub fn set_payload_template(&mut self, template: Template) {
+        self.payload_template = Some(template);
+    }
+
+    /// Client for publishing beside notifications
+    pub fn client(&self) -> Client {
+        self.client.clone()
+    }
+
     /// Returns true if currently connected to the MQTT broker
     #[allow(dead_code)]
     pub fn is_connected(&self) -> bool {
         self.connected.load(Ordering::Acquire)
     }
+}
 
-    pub fn notify_state_change(
-        &self,
-        new_state: ControllerState,
-        previous_state: Option<ControllerState>,
-    ) -> Result<()> {
-        let event_type = match new_state {
-            ControllerState::Tracking => "human_detected",
-            ControllerState::Standby => "standby_resumed",
-            ControllerState::Validation => "validation_started",
-        };
+impl Notifier for MqttNotifier {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
 
-        let notification = StateChangeNotification {
-            device_id: self.device_id.clone(),
-            timestamp: Utc::now().to_rfc3339(),
-            state: format!("{:?}", new_state),
-            previous_state: previous_state.map(|s| format!("{:?}", s)),
-            event_type: event_type.to_string(),
+    fn notify(&self, notification: &StateChangeNotification) -> Result<()> {
+        let payload = match &self.payload_template {
+            Some(template) => notification.render(template).into_bytes(),
+            None => notification
+                .event()
+                .to_json()
+                .context("Failed to serialize state change notification")?,
         };
 
-        let payload = serde_json::to_string(&notification)
-            .context("Failed to serialize state change notification")?;
-
         self.client
-            .publish(&self.topic, QoS::AtLeastOnce, false, payload.as_bytes())
+            .publish(&self.topic, QoS::AtLeastOnce, false, payload)
             .context("Failed to publish MQTT message")?;
 
         tracing::debug!(
-            state = ?new_state,
-            event_type = %event_type,
+            state = %notification.state,
+            event_type = %notification.event_type,
             "State change notification published"
         );
 
@@ -0,0 +1,192 @@
+use anyhow::Result;
+use chrono::Utc;
+use common::Template;
+use common::event::{Event, EventType, StateChange};
+
+use crate::state_machine::ControllerState;
+
+/// A controller state transition, shared by all notifiers
+#[derive(Debug, Clone)]
+pub struct StateChangeNotification {
+    pub device_id: String,
+    pub timestamp: String,
+    pub state: String,
+    pub previous_state: Option<String>,
+    pub event_type: EventType,
+    /// A subject of the previous alarm came back, see `reentry.rs`
+    pub reentry: bool,
+    /// Label of the class that raised the alarm
+    pub class: Option<String>,
+    /// Zones the alarm's subject is in, see `zones.rs`
+    pub zones: Vec<String>,
+    /// Highest alert-class confidence on the frame that changed the state
+    pub confidence: Option<f32>,
+    /// Where the gateway serves the frame of the event, with `GATEWAY_PUBLIC_URL`
+    pub snapshot_url: Option<String>,
+}
+
+/// Fields notification templates can reference, see `common::template`
+pub const TEMPLATE_FIELDS: &[&str] = &[
+    "device_id",
+    "event_type",
+    "state",
+    "previous_state",
+    "timestamp",
+    "local_time",
+    "reentry",
+    "class",
+    "zone",
+    "confidence",
+    "snapshot_url",
+];
+
+impl StateChangeNotification {
+    pub fn new(
+        device_id: &str,
+        new_state: ControllerState,
+        previous_state: Option<ControllerState>,
+    ) -> Self {
+        let event_type = match new_state {
+            ControllerState::Tracking => EventType::HumanDetected,
+            ControllerState::Standby => EventType::StandbyResumed,
+            ControllerState::Validation => EventType::ValidationStarted,
+        };
+
+        Self {
+            device_id: device_id.to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+            state: format!("{:?}", new_state),
+            previous_state: previous_state.map(|s| format!("{:?}", s)),
+            event_type,
+            reentry: false,
+            class: None,
+            zones: Vec::new(),
+            confidence: None,
+            snapshot_url: None,
+        }
+    }
+
+    /// Synthetic event for checking the alert chain, in the current `state`
+    pub fn test(device_id: &str, state: ControllerState) -> Self {
+        Self {
+            device_id: device_id.to_string(),
+            timestamp: Utc::now().to_rfc3339(),
+            state: format!("{:?}", state),
+            previous_state: None,
+            event_type: EventType::TestNotification,
+            reentry: false,
+            class: None,
+            zones: Vec::new(),
+            confidence: None,
+            snapshot_url: None,
+        }
+    }
+
+    /// The notification as sent on the wire
+    pub fn event(&self) -> Event {
+        Event {
+            timestamp: self.timestamp.clone(),
+            ..Event::new(
+                &self.device_id,
+                self.event_type,
+                StateChange {
+                    state: self.state.clone(),
+                    previous_state: self.previous_state.clone(),
+                    reentry: self.reentry,
+                },
+            )
+        }
+    }
+
+    /// Value of the template field `name`, `None` when this notification has none
+    pub fn field(&self, name: &str) -> Option<String> {
+        match name {
+            "device_id" => Some(self.device_id.clone()),
+            "event_type" => Some(self.event_type.to_string()),
+            "state" => Some(self.state.clone()),
+            "previous_state" => self.previous_state.clone(),
+            "timestamp" => Some(self.timestamp.clone()),
+            "local_time" => common::template::local_time(&self.timestamp),
+            "reentry" => self.reentry.then(|| "true".to_string()),
+            "class" => self.class.clone(),
+            "zone" => (!self.zones.is_empty()).then(|| self.zones.join(", ")),
+            "confidence" => self.confidence.map(|c| format!("{c:.2}")),
+            "snapshot_url" => self.snapshot_url.clone(),
+            _ => None,
+        }
+    }
+
+    /// `template`, parsed against [`TEMPLATE_FIELDS`], filled in with this notification
+    pub fn render(&self, template: &Template) -> String {
+        template.render(|name| self.field(name))
+    }
+}
+
+/// An outbound alert channel (MQTT, email, ...)
+pub trait Notifier: Send {
+    /// Short channel name used in logs
+    fn name(&self) -> &'static str;
+
+    fn notify(&self, notification: &StateChangeNotification) -> Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use common::template::LOCAL_TIME_FORMAT;
+
+    #[test]
+    fn test_event_type_mapping() {
+        let tracking = StateChangeNotification::new(
+            "cam",
+            ControllerState::Tracking,
+            Some(ControllerState::Validation),
+        );
+        assert_eq!(tracking.event_type, EventType::HumanDetected);
+        assert_eq!(tracking.state, "Tracking");
+        assert_eq!(tracking.previous_state.as_deref(), Some("Validation"));
+
+        let standby = StateChangeNotification::new("cam", ControllerState::Standby, None);
+        assert_eq!(standby.event_type, EventType::StandbyResumed);
+        assert!(standby.previous_state.is_none());
+
+        let event = tracking.event();
+        assert_eq!(event.version, common::EVENT_VERSION);
+        assert_eq!(event.timestamp, tracking.timestamp);
+        assert_eq!(event.event_type, EventType::HumanDetected);
+    }
+
+    #[test]
+    fn test_every_template_field_renders() {
+        let mut notification = StateChangeNotification::new(
+            "cam",
+            ControllerState::Tracking,
+            Some(ControllerState::Validation),
+        );
+        notification.timestamp = "2025-01-01T12:00:00+00:00".to_string();
+        notification.reentry = true;
+        notification.class = Some("person".to_string());
+        notification.zones = vec!["porch".to_string(), "drive".to_string()];
+        notification.confidence = Some(0.876);
+        notification.snapshot_url = Some("http://cam/api/frames?at=1".to_string());
+
+        for name in TEMPLATE_FIELDS {
+            assert!(notification.field(name).is_some(), "{name}");
+        }
+        let template = Template::parse(
+            "{{class}} in {{zone}} ({{confidence}}){{#if reentry}}, again{{/if}}",
+            TEMPLATE_FIELDS,
+        )
+        .unwrap();
+        assert_eq!(
+            notification.render(&template),
+            "person in porch, drive (0.88), again"
+        );
+        let local = notification.field("local_time").unwrap();
+        assert_eq!(
+            DateTime::parse_from_str(&local, LOCAL_TIME_FORMAT).unwrap(),
+            DateTime::parse_from_rfc3339(&notification.timestamp).unwrap()
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! Alarms and events on the number of simultaneous detections
+//!
+//! Occupancy is the number of alert-class detections reaching the alert confidence
+//! in a result. It drives two things:
+//! - `ALERT_MIN_COUNT`: an alert class only counts towards an alarm on frames with
+//!   at least that many of its detections, see `CountRule`
+//! - `OCCUPANCY_TOPIC`: each change of occupancy is published there as JSON, once
+//!   the new count has held for `OCCUPANCY_STABLE_FRAMES` frames (`VALIDATION_FRAMES`
+//!   by default) so a flickering detection does not publish every frame
+//!
+//! Occupancy events are published whether the controller is armed or not.
+
+use anyhow::{Context, Result};
+use common::event::{Event, EventType, Occupancy};
+use rumqttc::{Client, QoS};
+
+use crate::config::{AlertClass, OccupancyConfig};
+
+/// Classes of `counts` whose alert class has at least `min_count` detections
+pub fn counted_classes(
+    counts: &[(u16, u32)],
+    alert_classes: &[AlertClass],
+    min_count: u32,
+) -> Vec<u16> {
+    counts
+        .iter()
+        .map(|(class_id, _)| *class_id)
+        .filter(|&class_id| {
+            alert_classes
+                .iter()
+                .any(|class| class.members.contains(class_id) && class.count(counts) >= min_count)
+        })
+        .collect()
+}
+
+/// Occupancy as last published, and the count about to replace it
+#[derive(Debug, Default)]
+pub struct OccupancyTracker {
+    stable_frames: u32,
+    current: u32,
+    candidate: u32,
+    /// Consecutive frames `candidate` has been seen
+    streak: u32,
+}
+
+impl OccupancyTracker {
+    pub fn new(stable_frames: u32) -> Self {
+        Self {
+            stable_frames,
+            ..Self::default()
+        }
+    }
+
+    /// Count the occupancy of a frame, returning the previous one when it changed
+    pub fn update(&mut self, count: u32) -> Option<u32> {
+        if count == self.current {
+            self.streak = 0;
+            return None;
+        }
+        if count == self.candidate {
+            self.streak += 1;
+        } else {
+            self.candidate = count;
+            self.streak = 1;
+        }
+        if self.streak < self.stable_frames {
+            return None;
+        }
+
+        self.streak = 0;
+        Some(std::mem::replace(&mut self.current, count))
+    }
+}
+
+/// Publishes occupancy changes on `OCCUPANCY_TOPIC`
+pub struct OccupancyPublisher {
+    topic: String,
+    device_id: String,
+    mqtt: Client,
+    tracker: OccupancyTracker,
+}
+
+impl OccupancyPublisher {
+    pub fn new(config: &OccupancyConfig, device_id: String, mqtt: Client) -> Self {
+        tracing::info!(topic = %config.topic, stable_frames = config.stable_frames, "Occupancy events enabled");
+        Self {
+            topic: config.topic.clone(),
+            device_id,
+            mqtt,
+            tracker: OccupancyTracker::new(config.stable_frames),
+        }
+    }
+
+    /// Count the occupancy of a frame, publishing it if it changed
+    pub fn update(&mut self, count: u32) {
+        let Some(previous_count) = self.tracker.update(count) else {
+            return;
+        };
+        tracing::info!(count, previous_count, "Occupancy changed");
+        let event = Event::new(
+            &self.device_id,
+            EventType::OccupancyChanged,
+            Occupancy {
+                count,
+                previous_count,
+            },
+        );
+        if let Err(e) = self.publish(&event) {
+            tracing::error!(error = %e, "Failed to publish occupancy change");
+        }
+    }
+
+    fn publish(&self, event: &Event) -> Result<()> {
+        let payload = event
+            .to_json()
+            .context("Failed to serialize occupancy event")?;
+        self.mqtt
+            .publish(&self.topic, QoS::AtLeastOnce, false, payload)
+            .context("Failed to publish MQTT message")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counted_classes() {
+        let classes = [AlertClass::new(0, 3), AlertClass::new(21, 3)];
+        let counts = [(0, 1), (21, 2)];
+
+        assert_eq!(counted_classes(&counts, &classes, 1), vec![0, 21]);
+        assert_eq!(counted_classes(&counts, &classes, 2), vec![21]);
+        assert!(counted_classes(&counts, &classes, 3).is_empty());
+    }
+
+    #[test]
+    fn test_occupancy_changes_once_stable() {
+        let mut tracker = OccupancyTracker::new(2);
+
+        assert_eq!(tracker.update(0), None);
+        assert_eq!(tracker.update(2), None);
+        assert_eq!(tracker.update(2), Some(0));
+        assert_eq!(tracker.update(2), None);
+
+        // A single frame with a different count does not publish
+        assert_eq!(tracker.update(1), None);
+        assert_eq!(tracker.update(2), None);
+        assert_eq!(tracker.update(0), None);
+        assert_eq!(tracker.update(0), Some(2));
+    }
+}
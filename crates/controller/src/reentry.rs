@@ -0,0 +1,103 @@
+//! Recognizing a subject re-entering the frame
+//!
+//! With `REENTRY_WINDOW_SECS` set, the controller remembers the appearance
+//! descriptors (inference's `APPEARANCE_CLASSES`) seen during an alarm. When the
+//! next alarm starts less than the window after the last one ended and one of its
+//! detections looks like a remembered one (`REENTRY_MIN_SIMILARITY`), its
+//! `human_detected` notification is flagged `reentry`, so a person stepping out of
+//! view for a moment can be told apart from a new visitor.
+
+use common::Appearance;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::config::ReentryConfig;
+
+/// Descriptors remembered per alarm, the most recent ones
+const MAX_REMEMBERED: usize = 32;
+
+pub struct ReentryTracker {
+    config: ReentryConfig,
+    /// Descriptors seen during the current or last alarm
+    seen: VecDeque<Appearance>,
+    /// End of the last alarm, `None` during one
+    left_at: Option<Instant>,
+}
+
+impl ReentryTracker {
+    pub fn new(config: ReentryConfig) -> Self {
+        tracing::info!(window = ?config.window, min_similarity = config.min_similarity, "Re-entry detection enabled");
+        Self {
+            config,
+            seen: VecDeque::new(),
+            left_at: None,
+        }
+    }
+
+    /// Remember the descriptors of a frame during an alarm
+    pub fn observe(&mut self, appearances: &[Appearance]) {
+        self.seen.extend(appearances);
+        let excess = self.seen.len().saturating_sub(MAX_REMEMBERED);
+        self.seen.drain(..excess);
+    }
+
+    /// The alarm ended
+    pub fn left(&mut self, now: Instant) {
+        self.left_at = Some(now);
+    }
+
+    /// An alarm starts with `appearances` in the frame: whether one of them matches a
+    /// subject of the last alarm, ended less than the window ago
+    pub fn is_reentry(&mut self, appearances: &[Appearance], now: Instant) -> bool {
+        let recent = self
+            .left_at
+            .take()
+            .is_some_and(|left_at| now.duration_since(left_at) <= self.config.window);
+        let reentry = recent
+            && appearances.iter().any(|appearance| {
+                self.seen
+                    .iter()
+                    .any(|seen| appearance.similarity(seen) >= self.config.min_similarity)
+            });
+        self.seen.clear();
+        reentry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Descriptor with all pixels in one bin
+    fn solid(bin: usize) -> Appearance {
+        let mut histogram = [0; common::appearance::APPEARANCE_LEN];
+        histogram[bin] = 255;
+        Appearance(histogram)
+    }
+
+    #[test]
+    fn test_reentry_within_the_window() {
+        let mut tracker = ReentryTracker::new(ReentryConfig {
+            window: Duration::from_secs(60),
+            min_similarity: 0.8,
+        });
+        let start = Instant::now();
+
+        // The first alarm has nothing to match
+        assert!(!tracker.is_reentry(&[solid(0)], start));
+        tracker.observe(&[solid(0)]);
+        tracker.left(start);
+        assert!(tracker.is_reentry(&[solid(3), solid(0)], start + Duration::from_secs(30)));
+
+        // Someone else
+        tracker.observe(&[solid(0)]);
+        tracker.left(start + Duration::from_secs(40));
+        assert!(!tracker.is_reentry(&[solid(3)], start + Duration::from_secs(50)));
+
+        // Back too late
+        tracker.observe(&[solid(3)]);
+        tracker.left(start + Duration::from_secs(60));
+        assert!(!tracker.is_reentry(&[solid(3)], start + Duration::from_secs(121)));
+    }
+}
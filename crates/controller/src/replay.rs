@@ -0,0 +1,310 @@
+//! Offline replay of recorded detections, for tuning the alarm thresholds
+//!
+//! `controller-replay` reads detection results one JSON object per line: the
+//! `common::event::DetectionSummary` of a frame, or a whole `detections` event as
+//! webhooks send it. Other events are skipped, so a capture of a channel's
+//! traffic replays as is. Each frame goes through the counting and state machine
+//! the controller runs (alert confidence, `ALERT_MIN_COUNT`, per-class validation
+//! frames, `TRACKING_EXIT_FRAMES`), with whatever settings are being tried, and
+//! every alarm that would have fired is reported.
+//!
+//! Frames are replayed in file order. `timestamp_ns` is read as wall clock time:
+//! it places `ALERT_MIN_COUNT_HOURS` and the reported times.
+
+use crate::config::{AlertClass, ControllerConfig, CountRule};
+use crate::occupancy::counted_classes;
+use crate::state_machine::{ControllerState, StateContext};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use common::classes::ClassSet;
+use common::event::{DetectionSummary, Event, EventPayload, EventType};
+use serde::Serialize;
+use std::io::BufRead;
+
+/// Thresholds the detections are replayed with
+#[derive(Debug, Clone)]
+pub struct ReplaySettings {
+    pub alert_classes: Vec<AlertClass>,
+    pub alert_confidence: f32,
+    pub alert_count: CountRule,
+    pub tracking_exit_frames: u32,
+}
+
+impl ReplaySettings {
+    /// The settings the controller runs with
+    pub fn from_config(config: &ControllerConfig) -> Self {
+        Self {
+            alert_classes: config.alert_classes.clone(),
+            alert_confidence: config.alert_confidence,
+            alert_count: config.alert_count,
+            tracking_exit_frames: config.tracking_exit_frames,
+        }
+    }
+}
+
+/// An alarm the controller would have raised
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Alarm {
+    /// Frame Tracking was entered on
+    pub frame_number: u64,
+    pub timestamp_ns: u64,
+    /// Alert class that completed validation
+    pub class_id: Option<u16>,
+    /// Highest alert-class confidence while tracking
+    pub peak_confidence: f32,
+    /// Frames spent in Tracking
+    pub frames: u64,
+    /// Frame Standby was resumed on, `None` if the log ends in Tracking
+    pub end_frame_number: Option<u64>,
+    pub end_timestamp_ns: Option<u64>,
+}
+
+/// Outcome of a replay
+#[derive(Debug, Default, Serialize)]
+pub struct ReplayReport {
+    pub frames: u64,
+    /// Validations started, including those that became alarms
+    pub validations: u64,
+    pub alarms: Vec<Alarm>,
+}
+
+impl ReplayReport {
+    /// Validations that did not become an alarm
+    pub fn abandoned_validations(&self) -> u64 {
+        self.validations - self.alarms.len() as u64
+    }
+}
+
+pub struct Replay {
+    settings: ReplaySettings,
+    /// Every alert class member, as the controller reads them
+    class_ids: Vec<u16>,
+    state: StateContext,
+    report: ReplayReport,
+}
+
+impl Replay {
+    pub fn new(settings: ReplaySettings) -> Self {
+        let class_ids = settings
+            .alert_classes
+            .iter()
+            .fold(ClassSet::default(), |set, class| set.union(class.members))
+            .iter()
+            .collect();
+        Self {
+            settings,
+            class_ids,
+            state: StateContext::new(),
+            report: ReplayReport::default(),
+        }
+    }
+
+    /// Run one frame's detections through the state machine, returning the state
+    /// it moved to
+    pub fn feed(&mut self, frame: &DetectionSummary) -> Option<ControllerState> {
+        let alert_detections = || {
+            frame
+                .detections
+                .iter()
+                .filter(|det| self.class_ids.contains(&det.class_id))
+        };
+        let counts: Vec<(u16, u32)> = self
+            .class_ids
+            .iter()
+            .filter_map(|&class_id| {
+                let count = alert_detections()
+                    .filter(|det| {
+                        det.class_id == class_id && det.confidence >= self.settings.alert_confidence
+                    })
+                    .count() as u32;
+                (count > 0).then_some((class_id, count))
+            })
+            .collect();
+        let peak_confidence = alert_detections()
+            .map(|det| det.confidence)
+            .fold(0.0, f32::max);
+
+        let local_time = DateTime::from_timestamp_nanos(frame.timestamp_ns as i64)
+            .with_timezone(&Local)
+            .time();
+        let min_count = self.settings.alert_count.min_count_at(local_time);
+        let detected = counted_classes(&counts, &self.settings.alert_classes, min_count);
+
+        let previous = self.state.current_state();
+        let changed = self.state.update_classes(
+            &detected,
+            &self.settings.alert_classes,
+            self.settings.tracking_exit_frames,
+        );
+        self.report.frames += 1;
+
+        match changed {
+            Some(ControllerState::Validation) => self.report.validations += 1,
+            Some(ControllerState::Tracking) => {
+                self.report.alarms.push(Alarm {
+                    frame_number: frame.frame_number,
+                    timestamp_ns: frame.timestamp_ns,
+                    class_id: self.state.trigger_class(),
+                    peak_confidence: 0.0,
+                    frames: 0,
+                    end_frame_number: None,
+                    end_timestamp_ns: None,
+                });
+            }
+            Some(ControllerState::Standby) if previous == ControllerState::Tracking => {
+                if let Some(alarm) = self.report.alarms.last_mut() {
+                    alarm.end_frame_number = Some(frame.frame_number);
+                    alarm.end_timestamp_ns = Some(frame.timestamp_ns);
+                }
+            }
+            _ => {}
+        }
+        if self.state.current_state() == ControllerState::Tracking
+            && let Some(alarm) = self.report.alarms.last_mut()
+        {
+            alarm.frames += 1;
+            alarm.peak_confidence = alarm.peak_confidence.max(peak_confidence);
+        }
+        changed
+    }
+
+    /// Replay every frame of a JSONL log
+    pub fn feed_lines(&mut self, input: impl BufRead) -> Result<()> {
+        for (index, line) in input.lines().enumerate() {
+            let line = line.context("Failed to read the detection log")?;
+            let frame = parse_line(&line)
+                .with_context(|| format!("Invalid record on line {}", index + 1))?;
+            if let Some(frame) = frame {
+                self.feed(&frame);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> ReplayReport {
+        self.report
+    }
+}
+
+/// Detections of a log line, `None` for blank lines and other events
+pub fn parse_line(line: &str) -> Result<Option<DetectionSummary>> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let value: serde_json::Value = serde_json::from_str(line)?;
+    if value.get("event_type").is_none() {
+        return Ok(Some(serde_json::from_value(value)?));
+    }
+    let event: Event = serde_json::from_value(value)?;
+    Ok(match (event.event_type, event.payload) {
+        (EventType::Detections, EventPayload::Detections(frame)) => Some(frame),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::event::EventDetection;
+
+    fn settings(validation_frames: u32, tracking_exit_frames: u32) -> ReplaySettings {
+        ReplaySettings {
+            alert_classes: vec![AlertClass::new(0, validation_frames)],
+            alert_confidence: 0.5,
+            alert_count: CountRule {
+                min_count: 1,
+                hours: None,
+            },
+            tracking_exit_frames,
+        }
+    }
+
+    fn frame(frame_number: u64, detections: &[(u16, f32)]) -> DetectionSummary {
+        DetectionSummary {
+            frame_number,
+            timestamp_ns: 1_700_000_000_000_000_000 + frame_number * 100_000_000,
+            width: 640,
+            height: 480,
+            detections: detections
+                .iter()
+                .map(|&(class_id, confidence)| EventDetection {
+                    x1: 0.0,
+                    y1: 0.0,
+                    x2: 10.0,
+                    y2: 10.0,
+                    confidence,
+                    class_id,
+                })
+                .collect(),
+            snapshot_url: None,
+        }
+    }
+
+    fn replay(settings: ReplaySettings, frames: &[&[(u16, f32)]]) -> ReplayReport {
+        let mut replay = Replay::new(settings);
+        for (number, detections) in frames.iter().enumerate() {
+            replay.feed(&frame(number as u64, detections));
+        }
+        replay.finish()
+    }
+
+    #[test]
+    fn test_reports_alarms_with_their_span() {
+        let person: &[(u16, f32)] = &[(0, 0.9)];
+        let weak: &[(u16, f32)] = &[(0, 0.3)];
+        let report = replay(
+            settings(2, 2),
+            &[&[], person, person, weak, person, &[], &[], &[]],
+        );
+
+        assert_eq!(report.frames, 8);
+        assert_eq!(report.validations, 1);
+        assert_eq!(
+            report.alarms,
+            [Alarm {
+                frame_number: 2,
+                timestamp_ns: 1_700_000_000_200_000_000,
+                class_id: Some(0),
+                peak_confidence: 0.9,
+                frames: 4,
+                end_frame_number: Some(6),
+                end_timestamp_ns: Some(1_700_000_000_600_000_000),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_thresholds_change_the_outcome() {
+        let person: &[(u16, f32)] = &[(0, 0.9), (2, 0.95)];
+        let frames = [person, person, &[], person, person];
+
+        let strict = replay(settings(3, 1), &frames);
+        assert!(strict.alarms.is_empty());
+        assert_eq!(strict.abandoned_validations(), 2);
+
+        let lenient = replay(settings(1, 1), &frames);
+        assert_eq!(lenient.alarms.len(), 2);
+        assert_eq!(lenient.alarms[1].end_frame_number, None);
+        // Other classes do not count toward the peak
+        assert_eq!(lenient.alarms[0].peak_confidence, 0.9);
+    }
+
+    #[test]
+    fn test_parses_summaries_and_detection_events() {
+        let summary = serde_json::to_string(&frame(7, &[(0, 0.8)])).unwrap();
+        assert_eq!(parse_line(&summary).unwrap().unwrap().frame_number, 7);
+
+        let event = Event::new("door", EventType::Detections, frame(8, &[]));
+        let event = String::from_utf8(event.to_json().unwrap()).unwrap();
+        assert_eq!(parse_line(&event).unwrap().unwrap().frame_number, 8);
+
+        assert!(
+            parse_line(r#"{"version":1,"device_id":"door","timestamp":"2025-01-01T00:00:00+00:00","event_type":"occupancy_changed","count":1,"previous_count":0}"#)
+                .unwrap()
+                .is_none()
+        );
+        assert!(parse_line("  ").unwrap().is_none());
+        assert!(parse_line("{").is_err());
+    }
+}
@@ -1,32 +1,79 @@
-use crate::{config::ControllerConfig, mqtt_notifier::MqttNotifier, state_machine::StateContext};
-use anyhow::Result;
-use bridge::{BridgeSemaphore, DetectionReader, SemaphoreType, SentryControl};
-use common::wait_for_resource;
+#[cfg(feature = "gpio")]
+use crate::gpio::{GpioLines, Siren};
+use crate::{
+    anomaly::AnomalyPublisher,
+    commands::{Command, CommandChannel, CommandRequest, RuntimeState},
+    config::ControllerConfig,
+    dispatch::NotifierDispatcher,
+    drill::{self, Drill, DrillReport},
+    linkage::{FrameLinkage, ResultStamp},
+    metrics::ControllerMetrics,
+    mqtt_notifier::MqttNotifier,
+    notifier::{Notifier, StateChangeNotification, TEMPLATE_FIELDS},
+    occupancy::{OccupancyPublisher, counted_classes},
+    reentry::ReentryTracker,
+    s3_uploader::S3Uploader,
+    smtp_notifier::SmtpNotifier,
+    state_machine::{ControllerState, ElevationTracker, StateContext},
+    summary::SummaryPublisher,
+    zones::{Sighting, ZoneTracker},
+};
+use anyhow::{Context, Result};
+use bridge::{
+    AlarmPhase, BridgeSemaphore, CapturedAt, ControllerStatus, DetectionReader, SemaphoreType,
+    SentryControl, SnapshotControl, Threshold, ThresholdControl,
+};
+use chrono::Local;
+use common::classes::ClassSet;
+use common::{Dependency, Readiness, Template, WallClockNs, Watchdog};
 use std::{thread, time::Duration};
 
 pub struct ControllerService {
     config: ControllerConfig,
     state_context: StateContext,
+    elevation: Option<ElevationTracker>,
+    linkage: FrameLinkage,
+    zones: ZoneTracker,
     detection_reader: DetectionReader,
     detection_semaphore: BridgeSemaphore,
     mode_semaphore: BridgeSemaphore,
     sentry_control: SentryControl,
-    mqtt_notifier: MqttNotifier,
+    /// Runtime override of the alert confidence, set through the gateway
+    thresholds: Option<ThresholdControl>,
+    /// Snapshot requests to the gateway, from remote commands and alert drills
+    snapshots: Option<SnapshotControl>,
+    /// State served by the gateway, and its arm requests
+    status: Option<ControllerStatus>,
+    /// Sends notifications without holding up the state machine
+    notifiers: NotifierDispatcher,
+    commands: Option<CommandChannel>,
+    runtime: RuntimeState,
+    summary: Option<SummaryPublisher>,
+    occupancy: Option<OccupancyPublisher>,
+    reentry: Option<ReentryTracker>,
+    anomalies: Option<AnomalyPublisher>,
+    /// Siren or light on GPIO lines, switched on alarms
+    #[cfg(feature = "gpio")]
+    siren: Option<Siren<GpioLines>>,
 }
 
 impl ControllerService {
-    pub fn new(config: ControllerConfig) -> Result<Self> {
-        let detection_reader = wait_for_resource(
-            DetectionReader::build,
-            config.poll_interval_ms,
+    pub fn new(config: ControllerConfig, readiness: &Readiness) -> Result<Self> {
+        let poll_interval = Duration::from_millis(config.poll_interval_ms);
+
+        let detection_reader = readiness.wait_for(
+            Dependency::DetectionBuffer,
             "Detection buffer",
-        );
+            poll_interval,
+            DetectionReader::build,
+        )?;
 
-        let detection_semaphore = wait_for_resource(
-            || BridgeSemaphore::open(SemaphoreType::DetectionInferenceToController),
-            config.poll_interval_ms,
+        let detection_semaphore = readiness.wait_for(
+            Dependency::Semaphores,
             "Detection semaphore",
-        );
+            poll_interval,
+            || BridgeSemaphore::open(SemaphoreType::DetectionInferenceToController),
+        )?;
 
         let mode_semaphore = BridgeSemaphore::ensure(SemaphoreType::ModeChangeControllerToCapture)
             .map_err(|e| anyhow::anyhow!("Failed to create mode change semaphore: {}", e))?;
@@ -35,21 +82,117 @@ impl ControllerService {
         let sentry_control = SentryControl::build()?;
         tracing::info!("Sentry control connected");
 
-        let mqtt_notifier = MqttNotifier::new(
+        let thresholds = ThresholdControl::build()
+            .inspect_err(|e| tracing::warn!(error = %e, "Runtime threshold overrides unavailable"))
+            .ok();
+
+        let status = ControllerStatus::build()
+            .inspect_err(|e| tracing::warn!(error = %e, "Controller status unavailable"))
+            .ok();
+
+        let mut mqtt = MqttNotifier::new(
             &config.mqtt_broker_host,
             config.mqtt_broker_port,
             config.mqtt_topic.clone(),
             config.mqtt_device_id.clone(),
+            config
+                .commands
+                .as_ref()
+                .map(|commands| commands.topic.clone()),
         )?;
+        if let Some(template) = &config.mqtt_payload_template {
+            mqtt.set_payload_template(
+                Template::parse(template, TEMPLATE_FIELDS)
+                    .context("Invalid MQTT_PAYLOAD_TEMPLATE")?,
+            );
+        }
+        let commands = config.commands.as_ref().and_then(|commands| {
+            tracing::info!(topic = %commands.topic, "MQTT commands enabled");
+            Some(CommandChannel::new(
+                mqtt.take_commands()?,
+                mqtt.client(),
+                commands.ack_topic.clone(),
+                config.mqtt_device_id.clone(),
+            ))
+        });
+        let snapshots = (commands.is_some() || status.is_some() || config.night_still_on_alarm)
+            .then(|| {
+                SnapshotControl::build()
+                    .inspect_err(|e| tracing::warn!(error = %e, "Snapshot requests unavailable"))
+                    .ok()
+            })
+            .flatten();
+        let summary = config
+            .summary
+            .clone()
+            .map(|summary| {
+                let email = match (&config.smtp, summary.email) {
+                    (Some(smtp), true) => Some(SmtpNotifier::new(smtp)?),
+                    _ => None,
+                };
+                anyhow::Ok(SummaryPublisher::new(
+                    summary,
+                    config.mqtt_device_id.clone(),
+                    mqtt.client(),
+                    email,
+                ))
+            })
+            .transpose()?;
+        let occupancy = config.occupancy.as_ref().map(|occupancy| {
+            OccupancyPublisher::new(occupancy, config.mqtt_device_id.clone(), mqtt.client())
+        });
+        let anomalies = config.anomaly.as_ref().map(|anomaly| {
+            AnomalyPublisher::new(anomaly, config.mqtt_device_id.clone(), mqtt.client())
+        });
+        let mut notifiers: Vec<Box<dyn Notifier>> = vec![Box::new(mqtt)];
+
+        if let Some(smtp) = &config.smtp {
+            notifiers.push(Box::new(SmtpNotifier::new(smtp)?));
+        }
+
+        if let Some(s3) = &config.s3 {
+            notifiers.push(Box::new(S3Uploader::new(s3)?));
+        }
+        let notifiers = NotifierDispatcher::new(notifiers, &config.dispatch)?;
+
+        let reentry = config.reentry.clone().map(ReentryTracker::new);
+
+        let elevation = config
+            .elevation
+            .map(|e| ElevationTracker::new(e.enter_confidence, e.exit_confidence, e.smoothing));
+
+        #[cfg(feature = "gpio")]
+        let siren = config
+            .gpio
+            .as_ref()
+            .map(|gpio| anyhow::Ok(Siren::new(GpioLines::open(gpio)?, gpio.max_on)))
+            .transpose()?;
+
+        let linkage = FrameLinkage::new(config.detection_max_age);
+        let zones = ZoneTracker::new(config.zones.clone(), config.tracking_exit_frames);
 
         Ok(Self {
             config,
             state_context: StateContext::new(),
+            elevation,
+            linkage,
+            zones,
             detection_reader,
             detection_semaphore,
             mode_semaphore,
             sentry_control,
-            mqtt_notifier,
+            thresholds,
+            snapshots,
+            status,
+            notifiers,
+            commands,
+            runtime: RuntimeState::default(),
+            summary,
+            occupancy,
+            reentry,
+            anomalies,
+            #[cfg(feature = "gpio")]
+            siren,
         })
     }
 
@@ -61,69 +204,262 @@ impl ControllerService {
             self.config.tracking_exit_frames
         );
 
+        let alert_class_ids: Vec<u16> = self
+            .config
+            .alert_classes
+            .iter()
+            .fold(ClassSet::default(), |set, class| set.union(class.members))
+            .iter()
+            .collect();
+        tracing::info!(alert_classes = ?self.config.alert_classes, "Alert classes");
+
+        let metrics = ControllerMetrics::new("controller");
         let mut frames_processed = 0u64;
 
+        let mut watchdog = Watchdog::from_env();
+        // At least every second, the gateway reports the controller offline when
+        // its status is a few seconds old
+        let wait_timeout = watchdog
+            .ping_interval()
+            .map_or(Duration::from_secs(1), |i| i.min(Duration::from_secs(1)));
+        watchdog.ready();
+
         loop {
-            if let Err(e) = self.detection_semaphore.wait() {
-                tracing::error!(error = %e, "Semaphore wait failed");
-                thread::sleep(Duration::from_millis(self.config.poll_interval_ms));
+            watchdog.ping();
+            self.handle_arm_request();
+            self.handle_commands();
+            self.handle_drill();
+            self.publish_status();
+            if let Some(summary) = &mut self.summary {
+                summary.publish_if_due(Local::now());
+            }
+            if let Some(anomalies) = &mut self.anomalies {
+                anomalies.tick(self.sentry_control.get_mode());
+            }
+            #[cfg(feature = "gpio")]
+            self.update_siren(None);
+
+            // Wake up periodically even without detections to keep the watchdog alive
+            match self.detection_semaphore.wait_timeout_duration(wait_timeout) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    tracing::error!(error = %e, "Semaphore wait failed");
+                    thread::sleep(Duration::from_millis(self.config.poll_interval_ms));
+                    continue;
+                }
+            }
+
+            if let Some(pid) = self.detection_reader.writer_changed() {
+                tracing::warn!(pid, "Detection buffer claimed by another writer");
+            }
+
+            let stamp = match self.read_stamp() {
+                Ok(Some(stamp)) => stamp,
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::error!(error = %e, "Failed to read detections");
+                    continue;
+                }
+            };
+            if let Err(staleness) = self.linkage.check(stamp, WallClockNs::now()) {
+                tracing::debug!(
+                    frame_number = stamp.frame_number,
+                    ?staleness,
+                    "Skipping stale detection result"
+                );
+                metrics.record_stale(staleness);
+                self.detection_reader.mark_read();
                 continue;
             }
 
-            let person_detected = match self.detection_reader.check_person_detected() {
-                Ok(detected) => detected,
+            let confidences = match self.detection_reader.class_confidences(&alert_class_ids) {
+                Ok(confidences) => confidences,
                 Err(e) => {
                     tracing::error!(error = %e, "Failed to read detections");
                     continue;
                 }
             };
+            // A late result may lack detections because inference ran out of time,
+            // so it can confirm a presence but not an absence
+            if confidences.is_empty() && self.detection_reader.is_late().unwrap_or(false) {
+                tracing::trace!("Skipping late detection result");
+                self.detection_reader.mark_read();
+                continue;
+            }
+
+            let alert_confidence =
+                self.thresholds
+                    .as_ref()
+                    .map_or(self.config.alert_confidence, |thresholds| {
+                        thresholds.resolve(Threshold::AlertConfidence, self.config.alert_confidence)
+                    });
+            let counts = match self
+                .detection_reader
+                .class_counts(&alert_class_ids, alert_confidence)
+            {
+                Ok(counts) => counts,
+                Err(e) => {
+                    tracing::error!(error = %e, "Failed to read detections");
+                    continue;
+                }
+            };
+            let min_count = self.config.alert_count.min_count_at(Local::now().time());
+            let detected = counted_classes(&counts, &self.config.alert_classes, min_count);
+            if let Some(occupancy) = &mut self.occupancy {
+                occupancy.update(counts.iter().map(|(_, count)| count).sum());
+            }
+            if let Some(anomalies) = &mut self.anomalies {
+                let timing = self.detection_reader.inference_timing().ok().flatten();
+                anomalies.record(timing.map(|t| t.latency()), !confidences.is_empty());
+            }
+            if let Some(summary) = &mut self.summary {
+                let counted: Vec<f32> = confidences
+                    .iter()
+                    .map(|(_, confidence)| *confidence)
+                    .filter(|confidence| *confidence >= alert_confidence)
+                    .collect();
+                summary.activity().record_detections(&counted, Local::now());
+            }
+            match self.sightings(alert_confidence) {
+                Ok(sightings) => {
+                    let activity = self.zones.update(sightings, stamp.timestamp);
+                    metrics.record_zone_activity(&self.zones, &activity);
+                }
+                Err(e) => tracing::warn!(error = %e, "Failed to read detection boxes"),
+            }
+            let appearances = match &self.reentry {
+                Some(_) => self
+                    .detection_reader
+                    .appearances(&alert_class_ids, alert_confidence)
+                    .unwrap_or_else(|e| {
+                        tracing::warn!(error = %e, "Failed to read appearance descriptors");
+                        Vec::new()
+                    }),
+                None => Vec::new(),
+            };
+            let peak_confidence = confidences
+                .iter()
+                .map(|(_, confidence)| *confidence)
+                .fold(0.0, f32::max);
+            let elevation_changed = self
+                .elevation
+                .as_mut()
+                .is_some_and(|elevation| elevation.update(peak_confidence));
 
             let previous_state = self.state_context.current_state();
 
-            let state_changed = self.state_context.update(
-                person_detected,
-                self.config.validation_frames,
+            let state_changed = self.state_context.update_classes(
+                &detected,
+                &self.config.alert_classes,
                 self.config.tracking_exit_frames,
             );
 
-            if let Some(new_state) = state_changed {
-                let sentry_mode = self.state_context.to_sentry_mode();
-                self.sentry_control.set_mode(sentry_mode);
+            if state_changed.is_some() || elevation_changed {
+                let elevated = self.elevation.as_ref().is_some_and(|e| e.is_elevated());
+                let sentry_mode = self.state_context.to_sentry_mode(elevated);
 
                 // Signal capture to wake up immediately for mode change
-                if let Err(e) = self.mode_semaphore.post() {
+                if self.sentry_control.try_set_mode(sentry_mode)
+                    && let Err(e) = self.mode_semaphore.post()
+                {
                     tracing::warn!(error = %e, "Failed to signal mode change to capture");
                 }
+                if state_changed.is_none() {
+                    tracing::info!(sentry_mode = ?sentry_mode, peak_confidence, "Elevation changed");
+                }
+            }
+
+            let now = std::time::Instant::now();
+            let reentry = match (&mut self.reentry, state_changed) {
+                (Some(tracker), Some(ControllerState::Tracking)) => {
+                    tracker.is_reentry(&appearances, now)
+                }
+                (Some(tracker), Some(ControllerState::Standby))
+                    if previous_state == ControllerState::Tracking =>
+                {
+                    tracker.left(now);
+                    false
+                }
+                _ => false,
+            };
+            if let Some(tracker) = &mut self.reentry
+                && self.state_context.current_state() == ControllerState::Tracking
+            {
+                tracker.observe(&appearances);
+            }
 
+            if let Some(new_state) = state_changed {
+                if new_state == ControllerState::Tracking
+                    && let Some(summary) = &mut self.summary
+                {
+                    summary.activity().record_alarm();
+                }
+                if new_state == ControllerState::Tracking
+                    && let Some(alert) = self
+                        .state_context
+                        .trigger_class()
+                        .and_then(|id| self.config.alert_classes.iter().find(|c| c.class_id == id))
+                {
+                    for class_id in alert.members.iter() {
+                        metrics.record_zone_alarm(&self.zones, class_id);
+                    }
+                }
                 tracing::info!(
                     state = ?new_state,
-                    sentry_mode = ?sentry_mode,
+                    sentry_mode = ?self.sentry_control.get_mode(),
+                    trigger_class = ?self.state_context.trigger_class(),
                     "State transition"
                 );
 
-                // Send MQTT notifications only for:
+                // Send notifications only for:
                 // 1. Entering Tracking state (human presence validated)
                 // 2. Tracking -> Standby transition (human left)
-                use crate::state_machine::ControllerState;
                 let should_notify = matches!(new_state, ControllerState::Tracking)
                     || (matches!(new_state, ControllerState::Standby)
                         && matches!(previous_state, ControllerState::Tracking));
+                let armed = self.runtime.armed;
+                #[cfg(feature = "gpio")]
+                self.update_siren(Some(new_state));
 
-                if should_notify
-                    && let Err(e) = self
-                        .mqtt_notifier
-                        .notify_state_change(new_state, Some(previous_state))
-                {
-                    tracing::error!(error = %e, "Failed to send MQTT notification");
+                if should_notify && !armed {
+                    tracing::info!(state = ?new_state, "Disarmed, notification suppressed");
+                } else if should_notify {
+                    let mut notification = StateChangeNotification::new(
+                        &self.config.mqtt_device_id,
+                        new_state,
+                        Some(previous_state),
+                    );
+                    if reentry {
+                        tracing::info!("Subject of the last alarm is back");
+                        notification.reentry = true;
+                    }
+                    let mut night_still = false;
+                    if new_state == ControllerState::Tracking {
+                        self.describe_alarm(&mut notification, peak_confidence);
+                        night_still = self.request_night_still();
+                    }
+                    notification.snapshot_url =
+                        self.config.gateway_public_url.as_ref().map(|base| {
+                            if night_still {
+                                return format!("{base}/api/stills/latest");
+                            }
+                            let at_ms = stamp.timestamp.as_nanos() / 1_000_000;
+                            format!("{base}/api/frames?at={at_ms}")
+                        });
+                    self.notifiers.dispatch(&notification);
                 }
             }
 
             frames_processed += 1;
             if frames_processed.is_multiple_of(30) {
+                let timing = self.detection_reader.inference_timing().ok().flatten();
                 tracing::debug!(
                     frames_processed,
                     current_state = ?self.state_context.current_state(),
-                    person_detected,
+                    ?detected,
+                    inference_latency = ?timing.map(|t| t.latency()),
+                    detection_age = ?timing.map(|t| t.age_at(WallClockNs::now())),
                     "Controller status"
                 );
             }
@@ -131,4 +467,303 @@ impl ControllerService {
             self.detection_reader.mark_read();
         }
     }
+
+    /// Ask capture for a long exposure still of the alarm with `NIGHT_STILL_ON_ALARM`,
+    /// announced with `still_ready` like a `request_still` command. Returns
+    /// whether one was requested.
+    fn request_night_still(&mut self) -> bool {
+        let Some(snapshots) = self
+            .snapshots
+            .as_ref()
+            .filter(|_| self.config.night_still_on_alarm)
+        else {
+            return false;
+        };
+        let request = snapshots.request_night_still();
+        tracing::info!(request, "Night still requested for the alarm");
+        self.runtime.awaiting_still = Some(request);
+        true
+    }
+
+    /// Fill in what raised the alarm, for notification templates
+    fn describe_alarm(&self, notification: &mut StateChangeNotification, peak_confidence: f32) {
+        if let Some(class_id) = self.state_context.trigger_class() {
+            notification.class = Some(
+                common::classes::class_name(class_id)
+                    .map_or_else(|| class_id.to_string(), String::from),
+            );
+            let members = self
+                .config
+                .alert_classes
+                .iter()
+                .find(|c| c.class_id == class_id)
+                .map_or_else(|| ClassSet::single(class_id), |alert| alert.members);
+            let mut zones: Vec<usize> = members
+                .iter()
+                .flat_map(|class_id| self.zones.zones_of(class_id))
+                .filter(|zone| !self.zones.is_whole_frame(*zone))
+                .collect();
+            zones.sort_unstable();
+            zones.dedup();
+            notification.zones = zones
+                .into_iter()
+                .map(|zone| self.zones.zone_name(zone).to_string())
+                .collect();
+        }
+        notification.confidence = (peak_confidence > 0.0).then_some(peak_confidence);
+    }
+
+    /// Switch the siren on entering Tracking while armed and off on Standby, and
+    /// cut it off when disarmed or on for too long
+    #[cfg(feature = "gpio")]
+    fn update_siren(&mut self, new_state: Option<ControllerState>) {
+        let armed = self.runtime.armed;
+        let Some(siren) = &mut self.siren else {
+            return;
+        };
+        let now = std::time::Instant::now();
+        match new_state {
+            _ if !armed => siren.off(),
+            Some(ControllerState::Tracking) => siren.on(now),
+            Some(ControllerState::Standby) => siren.off(),
+            _ => {}
+        }
+        siren.enforce_max_on(now);
+    }
+
+    /// Apply the arm request the gateway left since the last frame
+    fn handle_arm_request(&mut self) {
+        let Some(armed) = self.status.as_ref().and_then(|s| s.take_arm_request()) else {
+            return;
+        };
+        tracing::info!(armed, "Arm request from the gateway");
+        self.set_armed(armed);
+    }
+
+    /// Share the arming and alarm state with the gateway
+    fn publish_status(&self) {
+        let Some(status) = &self.status else {
+            return;
+        };
+        let phase = match self.state_context.current_state() {
+            ControllerState::Standby => AlarmPhase::Standby,
+            ControllerState::Validation => AlarmPhase::Validation,
+            ControllerState::Tracking => AlarmPhase::Tracking,
+        };
+        status.publish(self.runtime.armed, phase, self.runtime.config_version);
+    }
+
+    /// Arm or disarm, a change increasing the config version
+    fn set_armed(&mut self, armed: bool) {
+        if self.runtime.armed != armed {
+            self.runtime.armed = armed;
+            self.runtime.config_version += 1;
+        }
+    }
+
+    /// Apply the commands received since the last frame
+    fn handle_commands(&mut self) {
+        let Some(commands) = self.commands.take() else {
+            return;
+        };
+
+        while let Some(request) = commands.try_next() {
+            match request {
+                Ok(request) => {
+                    let result = request
+                        .command
+                        .validate()
+                        .and_then(|()| self.apply_command(&request));
+                    let snapshot_request = match request.command {
+                        Command::RequestSnapshot => self.runtime.awaiting_snapshot,
+                        Command::RequestStill => self.runtime.awaiting_still,
+                        Command::AlertDrill => self
+                            .runtime
+                            .drill
+                            .as_ref()
+                            .and_then(|drill| drill.snapshot_request),
+                        _ => None,
+                    };
+                    let version = self.runtime.config_version;
+                    commands.acknowledge(Some(&request), result, version, snapshot_request);
+                }
+                Err(e) => commands.acknowledge(None, Err(e), self.runtime.config_version, None),
+            }
+        }
+
+        if let (Some(awaiting), Some(snapshots)) = (self.runtime.awaiting_snapshot, &self.snapshots)
+            && let Some((request, frame_number)) = snapshots.fulfilled()
+            && request >= awaiting
+        {
+            self.runtime.awaiting_snapshot = None;
+            commands.snapshot_ready(request, frame_number);
+        }
+        if let (Some(awaiting), Some(snapshots)) = (self.runtime.awaiting_still, &self.snapshots)
+            && let Some((request, frame_number)) = snapshots.still_fulfilled()
+            && request >= awaiting
+        {
+            self.runtime.awaiting_still = None;
+            commands.still_ready(request, frame_number);
+        }
+
+        self.commands = Some(commands);
+    }
+
+    /// Apply a validated command
+    fn apply_command(&mut self, request: &CommandRequest) -> Result<(), String> {
+        match request.command {
+            Command::SetThresholds {
+                confidence,
+                alert_confidence,
+            } => {
+                let thresholds = self
+                    .thresholds
+                    .as_ref()
+                    .ok_or("Threshold control unavailable")?;
+                let changes = [
+                    (Threshold::Confidence, confidence),
+                    (Threshold::AlertConfidence, alert_confidence),
+                ];
+                for (threshold, value) in changes {
+                    if value.is_some() {
+                        thresholds.request(threshold, value);
+                    }
+                }
+                self.runtime.config_version += 1;
+            }
+            Command::Arm | Command::Disarm => self.set_armed(request.command == Command::Arm),
+            Command::TestNotification => {
+                let notification = StateChangeNotification::test(
+                    &self.config.mqtt_device_id,
+                    self.state_context.current_state(),
+                );
+                let failed: Vec<_> = self
+                    .notifiers
+                    .deliver(&notification)
+                    .into_iter()
+                    .filter(|outcome| !outcome.ok)
+                    .map(|outcome| outcome.notifier)
+                    .collect();
+                if !failed.is_empty() {
+                    return Err(format!("Notifiers failed: {}", failed.join(", ")));
+                }
+            }
+            Command::AlertDrill => self.start_drill(request.id.clone())?,
+            Command::RequestSnapshot => {
+                let snapshots = self
+                    .snapshots
+                    .as_ref()
+                    .ok_or("Snapshot requests unavailable")?;
+                self.runtime.awaiting_snapshot = Some(snapshots.request());
+            }
+            Command::RequestStill => {
+                let snapshots = self
+                    .snapshots
+                    .as_ref()
+                    .ok_or("Still requests unavailable")?;
+                self.runtime.awaiting_still = Some(snapshots.request_still());
+            }
+        }
+        Ok(())
+    }
+
+    /// Ask the gateway for the drill's snapshot, the notifiers run once it is kept
+    fn start_drill(&mut self, id: Option<String>) -> Result<(), String> {
+        if self.runtime.drill.is_some() {
+            return Err("An alert drill is already running".to_string());
+        }
+        let snapshot_request = self.snapshots.as_ref().map(SnapshotControl::request);
+        tracing::info!(?id, ?snapshot_request, "Alert drill started");
+        self.runtime.drill = Some(Drill::new(id, snapshot_request));
+        Ok(())
+    }
+
+    /// Start the drill the gateway asked for, and finish the running one once its
+    /// snapshot is kept or overdue
+    fn handle_drill(&mut self) {
+        if let Some(request) = self.status.as_ref().and_then(|s| s.take_drill_request())
+            && let Err(e) = self.start_drill(Some(format!("http-{request}")))
+        {
+            tracing::warn!(error = %e, "Alert drill request ignored");
+        }
+
+        let fulfilled = self.snapshots.as_ref().and_then(|s| s.fulfilled());
+        let now = std::time::Instant::now();
+        let Some(drill) = self
+            .runtime
+            .drill
+            .take_if(|drill| drill.ready(fulfilled, now))
+        else {
+            return;
+        };
+
+        let notification = StateChangeNotification::test(
+            &self.config.mqtt_device_id,
+            self.state_context.current_state(),
+        );
+        let report = DrillReport::new(
+            &self.config.mqtt_device_id,
+            &drill,
+            drill::snapshot_outcome(&drill, fulfilled),
+            self.notifiers.deliver(&notification),
+        );
+        tracing::info!(
+            id = ?report.id,
+            passed = report.passed,
+            duration_ms = report.duration_ms,
+            "Alert drill finished"
+        );
+
+        if let Some(commands) = &self.commands {
+            commands.drill_report(&report);
+        }
+        if let Some(status) = &self.status {
+            let published = serde_json::to_vec(&report)
+                .map_err(anyhow::Error::from)
+                .and_then(|json| Ok(status.publish_drill_report(&json)?));
+            if let Err(e) = published {
+                tracing::warn!(error = %e, "Failed to share drill report with the gateway");
+            }
+        }
+    }
+
+    /// Box centers of the detections reaching `min_confidence`, normalized to the
+    /// frame size
+    fn sightings(&self, min_confidence: f32) -> Result<Vec<Sighting>> {
+        let Some(result) = self.detection_reader.get_detections()? else {
+            return Ok(Vec::new());
+        };
+        let (width, height) = (result.frame_width(), result.frame_height());
+        Ok(result
+            .detections()
+            .iter()
+            .flatten()
+            .filter(|det| det.confidence() >= min_confidence)
+            .filter_map(|det| {
+                let bbox = det.box_()?;
+                Some(Sighting {
+                    class_id: det.class_id(),
+                    position: (width > 0 && height > 0).then(|| {
+                        (
+                            (bbox.x1() + bbox.x2()) / 2.0 / width as f32,
+                            (bbox.y1() + bbox.y2()) / 2.0 / height as f32,
+                        )
+                    }),
+                })
+            })
+            .collect())
+    }
+
+    /// Identity of the result currently in the detection buffer
+    fn read_stamp(&self) -> Result<Option<ResultStamp>> {
+        let sequence = self.detection_reader.current_sequence();
+        Ok(self
+            .detection_reader
+            .get_detections()?
+            .map(|result| ResultStamp {
+                sequence,
+                frame_number: result.frame_number(),
+                timestamp: result.captured_at().to_wall_clock(),
+            }))
+    }
 }
@@ -0,0 +1,275 @@
+use anyhow::{Context, Result};
+use common::Template;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, Mailbox, MessageBuilder, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use std::path::Path;
+
+use crate::config::{SmtpConfig, SmtpTls};
+use crate::notifier::{Notifier, StateChangeNotification, TEMPLATE_FIELDS};
+
+pub struct SmtpNotifier {
+    transport: SmtpTransport,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+    subject_template: Template,
+    body_template: Template,
+    snapshot_path: Option<String>,
+}
+
+impl SmtpNotifier {
+    pub fn new(config: &SmtpConfig) -> Result<Self> {
+        let builder = match config.tls {
+            SmtpTls::Implicit => SmtpTransport::relay(&config.host)
+                .context("Failed to configure SMTP relay with implicit TLS")?,
+            SmtpTls::StartTls => SmtpTransport::starttls_relay(&config.host)
+                .context("Failed to configure SMTP relay with STARTTLS")?,
+            SmtpTls::None => SmtpTransport::builder_dangerous(&config.host),
+        };
+
+        let builder = builder.port(config.port);
+        let builder = match (&config.username, &config.password) {
+            (Some(user), Some(pass)) => {
+                builder.credentials(Credentials::new(user.clone(), pass.clone()))
+            }
+            _ => builder,
+        };
+
+        let from = config
+            .from
+            .parse::<Mailbox>()
+            .with_context(|| format!("Invalid SMTP_FROM address: {}", config.from))?;
+
+        let to = config
+            .to
+            .iter()
+            .map(|addr| {
+                addr.parse::<Mailbox>()
+                    .with_context(|| format!("Invalid SMTP_TO address: {}", addr))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if to.is_empty() {
+            anyhow::bail!("SMTP_TO must list at least one recipient");
+        }
+
+        let subject_template = Template::parse(&config.subject_template, TEMPLATE_FIELDS)
+            .context("Invalid SMTP_SUBJECT_TEMPLATE")?;
+        let body_template = Template::parse(&config.body_template, TEMPLATE_FIELDS)
+            .context("Invalid SMTP_BODY_TEMPLATE")?;
+
+        tracing::info!(
+            host = %config.host,
+            port = config.port,
+            tls = ?config.tls,
+            recipients = to.len(),
+            "SMTP notifier initialized"
+        );
+
+        Ok(Self {
+            transport: builder.build(),
+            from,
+            to,
+            subject_template,
+            body_template,
+            snapshot_path: config.snapshot_path.clone(),
+        })
+    }
+
+    /// Message addressed to the configured recipients
+    fn envelope(&self, subject: String) -> MessageBuilder {
+        let mut builder = Message::builder().from(self.from.clone()).subject(subject);
+        for to in &self.to {
+            builder = builder.to(to.clone());
+        }
+        builder
+    }
+
+    fn build_message(&self, notification: &StateChangeNotification) -> Result<Message> {
+        let builder = self.envelope(notification.render(&self.subject_template));
+
+        let body = SinglePart::plain(notification.render(&self.body_template));
+
+        let message = match self.snapshot_path.as_deref().and_then(read_snapshot) {
+            Some(jpeg) => {
+                let attachment = Attachment::new("snapshot.jpg".to_string())
+                    .body(jpeg, ContentType::parse("image/jpeg")?);
+                builder.multipart(MultiPart::mixed().singlepart(body).singlepart(attachment))
+            }
+            None => builder.singlepart(body),
+        };
+
+        message.context("Failed to build email message")
+    }
+
+    /// Send a plain-text email outside the alert templates
+    pub fn send_plain(&self, subject: &str, body: &str) -> Result<()> {
+        let message = self
+            .envelope(subject.to_string())
+            .singlepart(SinglePart::plain(body.to_string()))
+            .context("Failed to build email message")?;
+        self.transport
+            .send(&message)
+            .context("Failed to send email")?;
+        Ok(())
+    }
+}
+
+impl Notifier for SmtpNotifier {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    fn notify(&self, notification: &StateChangeNotification) -> Result<()> {
+        let message = self.build_message(notification)?;
+
+        self.transport
+            .send(&message)
+            .context("Failed to send email notification")?;
+
+        tracing::debug!(
+            state = %notification.state,
+            event_type = %notification.event_type,
+            "Email notification sent"
+        );
+
+        Ok(())
+    }
+}
+
+/// Read the snapshot to attach, skipping it if missing so the alert still goes out
+fn read_snapshot(path: &str) -> Option<Vec<u8>> {
+    match std::fs::read(Path::new(path)) {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            tracing::warn!(path, error = %e, "Snapshot unavailable, sending without attachment");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{DEFAULT_BODY_TEMPLATE, DEFAULT_SUBJECT_TEMPLATE};
+    use common::EventType;
+
+    fn notification() -> StateChangeNotification {
+        StateChangeNotification {
+            device_id: "front-door".to_string(),
+            timestamp: "2025-01-01T00:00:00+00:00".to_string(),
+            state: "Tracking".to_string(),
+            previous_state: Some("Validation".to_string()),
+            event_type: EventType::HumanDetected,
+            reentry: false,
+            class: Some("person".to_string()),
+            zones: vec!["porch".to_string()],
+            confidence: Some(0.9),
+            snapshot_url: None,
+        }
+    }
+
+    fn config() -> SmtpConfig {
+        SmtpConfig {
+            host: "localhost".to_string(),
+            port: 2525,
+            tls: SmtpTls::None,
+            username: None,
+            password: None,
+            from: "detr <alerts@example.com>".to_string(),
+            to: vec!["owner@example.com".to_string()],
+            subject_template: "[{device_id}] {event_type}".to_string(),
+            body_template: "{previous_state} -> {state} at {timestamp}".to_string(),
+            snapshot_path: None,
+        }
+    }
+
+    fn render_template(template: &str, notification: &StateChangeNotification) -> String {
+        notification.render(&Template::parse(template, TEMPLATE_FIELDS).unwrap())
+    }
+
+    #[test]
+    fn test_render_template_substitutes_fields() {
+        let rendered = render_template(
+            "{device_id}: {previous_state} -> {state} ({event_type}) at {timestamp}",
+            &notification(),
+        );
+        assert_eq!(
+            rendered,
+            "front-door: Validation -> Tracking (human_detected) at 2025-01-01T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn test_render_template_without_previous_state() {
+        let mut n = notification();
+        n.previous_state = None;
+        assert_eq!(render_template("{previous_state}", &n), "none");
+        assert_eq!(
+            render_template(
+                "{{#if previous_state}}{{previous_state}}{{else}}none{{/if}}",
+                &n
+            ),
+            "none"
+        );
+    }
+
+    #[test]
+    fn test_default_templates() {
+        let config = SmtpConfig {
+            subject_template: DEFAULT_SUBJECT_TEMPLATE.to_string(),
+            body_template: DEFAULT_BODY_TEMPLATE.to_string(),
+            ..config()
+        };
+        let notifier = SmtpNotifier::new(&config).unwrap();
+        let raw = String::from_utf8(notifier.build_message(&notification()).unwrap().formatted())
+            .unwrap();
+        assert!(raw.contains("Subject: [front-door] human_detected"));
+        assert!(raw.contains("from Validation to Tracking"), "{raw}");
+        assert!(raw.contains("Detected person in porch."), "{raw}");
+    }
+
+    #[test]
+    fn test_rejects_invalid_template() {
+        let mut cfg = config();
+        cfg.body_template = "{{state}} in {{zones}}".to_string();
+        let error = SmtpNotifier::new(&cfg).err().unwrap();
+        assert!(format!("{error:#}").contains("SMTP_BODY_TEMPLATE"));
+    }
+
+    #[test]
+    fn test_build_message_plain() {
+        let notifier = SmtpNotifier::new(&config()).unwrap();
+        let message = notifier.build_message(&notification()).unwrap();
+        let raw = String::from_utf8(message.formatted()).unwrap();
+        assert!(raw.contains("Subject: [front-door] human_detected"));
+        assert!(raw.contains("Validation -> Tracking"));
+        assert!(!raw.contains("snapshot.jpg"));
+    }
+
+    #[test]
+    fn test_build_message_with_snapshot() {
+        let dir = std::env::temp_dir().join(format!("smtp-snapshot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let snapshot = dir.join("latest.jpg");
+        std::fs::write(&snapshot, [0xFF, 0xD8, 0xFF, 0xD9]).unwrap();
+
+        let mut cfg = config();
+        cfg.snapshot_path = Some(snapshot.to_string_lossy().into_owned());
+        let notifier = SmtpNotifier::new(&cfg).unwrap();
+        let message = notifier.build_message(&notification()).unwrap();
+        let raw = String::from_utf8(message.formatted()).unwrap();
+        assert!(raw.contains("snapshot.jpg"));
+        assert!(raw.contains("image/jpeg"));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_rejects_invalid_recipient() {
+        let mut cfg = config();
+        cfg.to = vec!["not-an-address".to_string()];
+        assert!(SmtpNotifier::new(&cfg).is_err());
+    }
+}
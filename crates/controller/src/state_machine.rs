@@ -1,4 +1,7 @@
+use crate::config::AlertClass;
+use crate::fsm::{Fsm, StateAction, Transition};
 use bridge::SentryMode;
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ControllerState {
@@ -7,10 +10,116 @@ pub enum ControllerState {
     Tracking,
 }
 
+/// What a frame tells the state machine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameEvent {
+    /// At least one alert class is in the frame
+    Alert,
+    Clear,
+}
+
+/// Any alert class starts validation, and Tracking is entered once a single class
+/// has been seen for its own number of consecutive frames. Tracking ends after
+/// `tracking_exit_threshold` frames without any alert class.
+static CONTROLLER_FSM: Fsm<ControllerState, FrameEvent, StateContext> = Fsm {
+    transitions: &[
+        Transition {
+            from: ControllerState::Standby,
+            event: FrameEvent::Alert,
+            to: ControllerState::Validation,
+            guard: None,
+            action: None,
+        },
+        Transition {
+            from: ControllerState::Validation,
+            event: FrameEvent::Alert,
+            to: ControllerState::Tracking,
+            guard: Some(|ctx| ctx.validated.is_some()),
+            action: None,
+        },
+        Transition {
+            from: ControllerState::Validation,
+            event: FrameEvent::Alert,
+            to: ControllerState::Validation,
+            guard: None,
+            action: Some(|ctx| ctx.validation_count += 1),
+        },
+        Transition {
+            from: ControllerState::Validation,
+            event: FrameEvent::Clear,
+            to: ControllerState::Standby,
+            guard: None,
+            action: None,
+        },
+        Transition {
+            from: ControllerState::Tracking,
+            event: FrameEvent::Alert,
+            to: ControllerState::Tracking,
+            guard: None,
+            action: Some(|ctx| ctx.no_person_count = 0),
+        },
+        Transition {
+            from: ControllerState::Tracking,
+            event: FrameEvent::Clear,
+            to: ControllerState::Standby,
+            // This miss is the last one before leaving
+            guard: Some(|ctx| ctx.no_person_count + 1 >= ctx.tracking_exit_threshold),
+            action: None,
+        },
+        Transition {
+            from: ControllerState::Tracking,
+            event: FrameEvent::Clear,
+            to: ControllerState::Tracking,
+            guard: None,
+            action: Some(|ctx| ctx.no_person_count += 1),
+        },
+    ],
+    on_entry: &[
+        StateAction {
+            state: ControllerState::Standby,
+            action: |ctx| {
+                ctx.validation_count = 0;
+                ctx.no_person_count = 0;
+            },
+        },
+        StateAction {
+            state: ControllerState::Validation,
+            action: |ctx| {
+                ctx.validation_count = 1;
+                ctx.no_person_count = 0;
+            },
+        },
+        StateAction {
+            state: ControllerState::Tracking,
+            action: |ctx| {
+                ctx.trigger_class = ctx.validated;
+                ctx.no_person_count = 0;
+            },
+        },
+    ],
+    on_exit: &[StateAction {
+        state: ControllerState::Tracking,
+        action: |ctx| ctx.trigger_class = None,
+    }],
+};
+
 pub struct StateContext {
     current_state: ControllerState,
     validation_count: u32,
     no_person_count: u32,
+    /// Consecutive frames each alert class has been seen
+    class_counts: HashMap<u16, u32>,
+    /// Alert class whose streak reached its threshold on the current frame
+    validated: Option<u16>,
+    /// Alert class that completed validation for the current Tracking period
+    trigger_class: Option<u16>,
+    tracking_exit_threshold: u32,
+}
+
+impl Default for StateContext {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl StateContext {
@@ -19,50 +128,31 @@ impl StateContext {
             current_state: ControllerState::Standby,
             validation_count: 0,
             no_person_count: 0,
+            class_counts: HashMap::new(),
+            validated: None,
+            trigger_class: None,
+            tracking_exit_threshold: 0,
         }
     }
 
-    pub fn update(
+    /// Advance the state machine given which alert classes are present in the frame
+    pub fn update_classes(
         &mut self,
-        person_detected: bool,
-        validation_threshold: u32,
+        detected: &[u16],
+        alert_classes: &[AlertClass],
         tracking_exit_threshold: u32,
     ) -> Option<ControllerState> {
         let old_state = self.current_state;
+        let alert_detected = alert_classes.iter().any(|class| class.matches(detected));
 
-        match self.current_state {
-            ControllerState::Standby => {
-                if person_detected {
-                    self.current_state = ControllerState::Validation;
-                    self.validation_count = 1;
-                    self.no_person_count = 0;
-                }
-            }
-            ControllerState::Validation => {
-                if person_detected {
-                    self.validation_count += 1;
-                    if self.validation_count >= validation_threshold {
-                        self.current_state = ControllerState::Tracking;
-                        self.no_person_count = 0;
-                    }
-                } else {
-                    self.current_state = ControllerState::Standby;
-                    self.validation_count = 0;
-                }
-            }
-            ControllerState::Tracking => {
-                if person_detected {
-                    self.no_person_count = 0;
-                } else {
-                    self.no_person_count += 1;
-                    if self.no_person_count >= tracking_exit_threshold {
-                        self.current_state = ControllerState::Standby;
-                        self.validation_count = 0;
-                        self.no_person_count = 0;
-                    }
-                }
-            }
-        }
+        self.validated = self.count_classes(detected, alert_classes);
+        self.tracking_exit_threshold = tracking_exit_threshold;
+        let event = if alert_detected {
+            FrameEvent::Alert
+        } else {
+            FrameEvent::Clear
+        };
+        self.current_state = CONTROLLER_FSM.fire(old_state, event, self);
 
         if old_state != self.current_state {
             Some(self.current_state)
@@ -71,22 +161,107 @@ impl StateContext {
         }
     }
 
+    /// Update per-class streaks, returning the first class that reached its threshold
+    fn count_classes(&mut self, detected: &[u16], alert_classes: &[AlertClass]) -> Option<u16> {
+        let mut validated = None;
+
+        for class in alert_classes {
+            let count = self.class_counts.entry(class.class_id).or_insert(0);
+            if class.matches(detected) {
+                *count += 1;
+                if *count >= class.validation_frames && validated.is_none() {
+                    validated = Some(class.class_id);
+                }
+            } else {
+                *count = 0;
+            }
+        }
+
+        validated
+    }
+
+    /// Alert class that triggered the current Tracking period
+    pub fn trigger_class(&self) -> Option<u16> {
+        self.trigger_class
+    }
+
     pub fn current_state(&self) -> ControllerState {
         self.current_state
     }
 
-    pub fn to_sentry_mode(&self) -> SentryMode {
+    /// Capture pacing for the current state, `elevated` only matters in Standby
+    pub fn to_sentry_mode(&self, elevated: bool) -> SentryMode {
         match self.current_state {
+            ControllerState::Standby if elevated => SentryMode::Elevated,
             ControllerState::Standby => SentryMode::Standby,
             ControllerState::Validation | ControllerState::Tracking => SentryMode::Alarmed,
         }
     }
 }
 
+/// Smoothed alert-class confidence driving the Elevated sentry level
+///
+/// The peak confidence of each frame (0 without alert classes) feeds an exponential
+/// moving average. Elevation starts once the average reaches `enter_confidence` and
+/// ends when it falls below `exit_confidence`, so a single weak detection neither
+/// raises the frame rate nor makes it flap.
+pub struct ElevationTracker {
+    enter_confidence: f32,
+    exit_confidence: f32,
+    smoothing: f32,
+    average: f32,
+    elevated: bool,
+}
+
+impl ElevationTracker {
+    pub fn new(enter_confidence: f32, exit_confidence: f32, smoothing: f32) -> Self {
+        Self {
+            enter_confidence,
+            exit_confidence: exit_confidence.min(enter_confidence),
+            smoothing: smoothing.clamp(f32::EPSILON, 1.0),
+            average: 0.0,
+            elevated: false,
+        }
+    }
+
+    /// Feed the peak confidence of a frame, returning true if elevation changed
+    pub fn update(&mut self, peak_confidence: f32) -> bool {
+        self.average += self.smoothing * (peak_confidence - self.average);
+
+        let elevated = if self.elevated {
+            self.average >= self.exit_confidence
+        } else {
+            self.average >= self.enter_confidence
+        };
+        let changed = elevated != self.elevated;
+        self.elevated = elevated;
+        changed
+    }
+
+    pub fn is_elevated(&self) -> bool {
+        self.elevated
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    impl StateContext {
+        /// Single-class shorthand with person (class 0) as the only alert class
+        fn update(
+            &mut self,
+            person_detected: bool,
+            validation_threshold: u32,
+            tracking_exit_threshold: u32,
+        ) -> Option<ControllerState> {
+            let person = [AlertClass::new(0, validation_threshold)];
+            let detected: &[u16] = if person_detected { &[0] } else { &[] };
+
+            self.update_classes(detected, &person, tracking_exit_threshold)
+        }
+    }
+
     // ========== Initial State Tests ==========
 
     #[test]
@@ -340,12 +515,80 @@ mod tests {
         assert_eq!(ctx.current_state(), ControllerState::Standby);
     }
 
+    // ========== Alert Class Tests ==========
+
+    fn alert_classes() -> [AlertClass; 2] {
+        [AlertClass::new(0, 2), AlertClass::new(21, 4)]
+    }
+
+    #[test]
+    fn non_alert_class_is_ignored() {
+        let mut ctx = StateContext::new();
+        assert!(ctx.update_classes(&[2, 7], &alert_classes(), 5).is_none());
+        assert_eq!(ctx.current_state(), ControllerState::Standby);
+    }
+
+    #[test]
+    fn each_alert_class_uses_its_own_threshold() {
+        let classes = alert_classes();
+
+        let mut ctx = StateContext::new();
+        ctx.update_classes(&[21], &classes, 5);
+        ctx.update_classes(&[21], &classes, 5);
+        ctx.update_classes(&[21], &classes, 5);
+        assert_eq!(ctx.current_state(), ControllerState::Validation);
+        assert_eq!(
+            ctx.update_classes(&[21], &classes, 5),
+            Some(ControllerState::Tracking)
+        );
+        assert_eq!(ctx.trigger_class(), Some(21));
+
+        let mut ctx = StateContext::new();
+        ctx.update_classes(&[0], &classes, 5);
+        assert_eq!(
+            ctx.update_classes(&[0], &classes, 5),
+            Some(ControllerState::Tracking)
+        );
+        assert_eq!(ctx.trigger_class(), Some(0));
+    }
+
+    #[test]
+    fn alternating_classes_keep_validating_without_tracking() {
+        let classes = alert_classes();
+        let mut ctx = StateContext::new();
+
+        // Each class streak is broken by the other, so neither reaches its threshold
+        for detected in [[0], [21], [0], [21], [0]] {
+            ctx.update_classes(&detected, &classes, 5);
+        }
+
+        assert_eq!(ctx.current_state(), ControllerState::Validation);
+        assert_eq!(ctx.validation_count, 5);
+    }
+
+    #[test]
+    fn any_alert_class_keeps_tracking_alive() {
+        let classes = alert_classes();
+        let mut ctx = StateContext::new();
+        ctx.update_classes(&[0], &classes, 2);
+        ctx.update_classes(&[0], &classes, 2); // Now in Tracking
+
+        ctx.update_classes(&[21], &classes, 2);
+        ctx.update_classes(&[21], &classes, 2);
+        assert_eq!(ctx.current_state(), ControllerState::Tracking);
+
+        ctx.update_classes(&[], &classes, 2);
+        ctx.update_classes(&[], &classes, 2);
+        assert_eq!(ctx.current_state(), ControllerState::Standby);
+        assert!(ctx.trigger_class().is_none());
+    }
+
     // ========== to_sentry_mode Tests ==========
 
     #[test]
     fn sentry_mode_standby() {
         let ctx = StateContext::new();
-        assert_eq!(ctx.to_sentry_mode(), SentryMode::Standby);
+        assert_eq!(ctx.to_sentry_mode(false), SentryMode::Standby);
     }
 
     #[test]
@@ -353,7 +596,7 @@ mod tests {
         let mut ctx = StateContext::new();
         ctx.update(true, 5, 5); // Enter Validation
 
-        assert_eq!(ctx.to_sentry_mode(), SentryMode::Alarmed);
+        assert_eq!(ctx.to_sentry_mode(false), SentryMode::Alarmed);
     }
 
     #[test]
@@ -362,6 +605,45 @@ mod tests {
         ctx.update(true, 1, 5);
         ctx.update(true, 1, 5); // Enter Tracking
 
-        assert_eq!(ctx.to_sentry_mode(), SentryMode::Alarmed);
+        assert_eq!(ctx.to_sentry_mode(false), SentryMode::Alarmed);
+    }
+
+    #[test]
+    fn sentry_mode_elevated_only_in_standby() {
+        let mut ctx = StateContext::new();
+        assert_eq!(ctx.to_sentry_mode(true), SentryMode::Elevated);
+
+        ctx.update(true, 5, 5); // Enter Validation
+        assert_eq!(ctx.to_sentry_mode(true), SentryMode::Alarmed);
+    }
+
+    // ========== ElevationTracker Tests ==========
+
+    #[test]
+    fn elevation_needs_a_sustained_trend() {
+        let mut tracker = ElevationTracker::new(0.3, 0.15, 0.5);
+
+        // A single weak detection is smoothed out
+        assert!(!tracker.update(0.5)); // average 0.25
+        assert!(!tracker.update(0.0)); // average 0.125
+        assert!(!tracker.is_elevated());
+
+        // Repeated ones raise the average past the entry threshold
+        assert!(tracker.update(0.5)); // average 0.3125
+        assert!(tracker.is_elevated());
+    }
+
+    #[test]
+    fn elevation_exit_has_hysteresis() {
+        let mut tracker = ElevationTracker::new(0.3, 0.15, 0.5);
+        assert!(tracker.update(0.6)); // average 0.3
+        assert!(!tracker.update(0.6)); // average 0.45
+
+        // Below the entry threshold but above the exit threshold: stays elevated
+        assert!(!tracker.update(0.1)); // average 0.275
+        assert!(tracker.is_elevated());
+
+        assert!(tracker.update(0.0)); // average 0.1375
+        assert!(!tracker.is_elevated());
     }
 }
@@ -0,0 +1,259 @@
+//! Daily activity digest
+//!
+//! With `DAILY_SUMMARY_TIME` set (`HH:MM`, local time), the controller publishes a
+//! summary of the past day on `DAILY_SUMMARY_TOPIC` at that time, and emails it
+//! through the SMTP settings with `DAILY_SUMMARY_EMAIL=true`. It covers:
+//! - `detections`: alert-class detections reaching `ALERT_CONFIDENCE`, one per class
+//!   per result, and their `average_confidence`
+//! - `alarms`: entries into Tracking, armed or not
+//! - `busiest_hour`: local hour with the most detections
+//! - `uptime_secs`: time since the controller started
+//!
+//! Counts are kept in memory since the previous summary, or since start for the
+//! first one, so a restart loses the day so far.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveTime, TimeDelta, Timelike};
+use rumqttc::{Client, QoS};
+use serde::Serialize;
+use std::time::Instant;
+
+use crate::config::SummaryConfig;
+use crate::smtp_notifier::SmtpNotifier;
+
+/// Activity since the last summary
+#[derive(Debug)]
+pub struct Activity {
+    since: DateTime<Local>,
+    detections: u64,
+    confidence_sum: f64,
+    alarms: u64,
+    /// Detections per local hour of the day
+    hourly: [u64; 24],
+}
+
+impl Activity {
+    pub fn new(since: DateTime<Local>) -> Self {
+        Self {
+            since,
+            detections: 0,
+            confidence_sum: 0.0,
+            alarms: 0,
+            hourly: [0; 24],
+        }
+    }
+
+    /// Count the confidences of the alert classes detected in one result
+    pub fn record_detections(&mut self, confidences: &[f32], at: DateTime<Local>) {
+        self.detections += confidences.len() as u64;
+        self.confidence_sum += confidences.iter().map(|&c| c as f64).sum::<f64>();
+        self.hourly[at.hour() as usize] += confidences.len() as u64;
+    }
+
+    pub fn record_alarm(&mut self) {
+        self.alarms += 1;
+    }
+
+    /// Summary of the activity up to `now`, starting over from there
+    pub fn finish(
+        &mut self,
+        device_id: &str,
+        now: DateTime<Local>,
+        uptime_secs: u64,
+    ) -> DailySummary {
+        let activity = std::mem::replace(self, Self::new(now));
+        let busiest_hour = (0..24u8)
+            .filter(|&hour| activity.hourly[hour as usize] > 0)
+            .max_by_key(|&hour| (activity.hourly[hour as usize], std::cmp::Reverse(hour)));
+        DailySummary {
+            device_id: device_id.to_string(),
+            period_start: activity.since.to_rfc3339(),
+            period_end: now.to_rfc3339(),
+            detections: activity.detections,
+            average_confidence: (activity.detections > 0)
+                .then(|| (activity.confidence_sum / activity.detections as f64) as f32),
+            alarms: activity.alarms,
+            busiest_hour,
+            uptime_secs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DailySummary {
+    pub device_id: String,
+    pub period_start: String,
+    pub period_end: String,
+    pub detections: u64,
+    pub average_confidence: Option<f32>,
+    pub alarms: u64,
+    /// Local hour, 0-23, none without detections
+    pub busiest_hour: Option<u8>,
+    pub uptime_secs: u64,
+}
+
+impl DailySummary {
+    pub fn subject(&self) -> String {
+        format!("[{}] Daily summary", self.device_id)
+    }
+
+    /// Plain-text body of the email
+    pub fn body(&self) -> String {
+        let average_confidence = self
+            .average_confidence
+            .map_or("-".to_string(), |c| format!("{c:.2}"));
+        let busiest_hour = self
+            .busiest_hour
+            .map_or("-".to_string(), |h| format!("{h:02}:00-{h:02}:59"));
+        let uptime = self.uptime_secs;
+        format!(
+            "Activity of {} from {} to {}\n\n\
+             Detections: {}\n\
+             Average confidence: {}\n\
+             Alarms: {}\n\
+             Busiest hour: {}\n\
+             Uptime: {}d {:02}h {:02}m\n",
+            self.device_id,
+            self.period_start,
+            self.period_end,
+            self.detections,
+            average_confidence,
+            self.alarms,
+            busiest_hour,
+            uptime / 86_400,
+            uptime % 86_400 / 3600,
+            uptime % 3600 / 60,
+        )
+    }
+}
+
+/// First time of day `at` strictly after `now`
+fn next_occurrence(at: NaiveTime, now: DateTime<Local>) -> DateTime<Local> {
+    let mut day = now.date_naive();
+    loop {
+        // A time skipped by a DST change falls back to the next day
+        if let Some(due) = day.and_time(at).and_local_timezone(Local).earliest()
+            && due > now
+        {
+            return due;
+        }
+        day += TimeDelta::days(1);
+    }
+}
+
+/// Collects activity and sends the summary once a day
+pub struct SummaryPublisher {
+    config: SummaryConfig,
+    device_id: String,
+    mqtt: Client,
+    email: Option<SmtpNotifier>,
+    started: Instant,
+    activity: Activity,
+    next_due: DateTime<Local>,
+}
+
+impl SummaryPublisher {
+    pub fn new(
+        config: SummaryConfig,
+        device_id: String,
+        mqtt: Client,
+        email: Option<SmtpNotifier>,
+    ) -> Self {
+        let now = Local::now();
+        let next_due = next_occurrence(config.at, now);
+        tracing::info!(topic = %config.topic, %next_due, email = email.is_some(), "Daily summary enabled");
+        Self {
+            config,
+            device_id,
+            mqtt,
+            email,
+            started: Instant::now(),
+            activity: Activity::new(now),
+            next_due,
+        }
+    }
+
+    pub fn activity(&mut self) -> &mut Activity {
+        &mut self.activity
+    }
+
+    /// Send the summary if its time has come
+    pub fn publish_if_due(&mut self, now: DateTime<Local>) {
+        if now < self.next_due {
+            return;
+        }
+        self.next_due = next_occurrence(self.config.at, now);
+        let summary = self
+            .activity
+            .finish(&self.device_id, now, self.started.elapsed().as_secs());
+
+        if let Err(e) = self.publish(&summary) {
+            tracing::error!(error = %e, "Failed to publish daily summary");
+        }
+        if let Some(email) = &self.email
+            && let Err(e) = email.send_plain(&summary.subject(), &summary.body())
+        {
+            tracing::error!(error = %e, "Failed to email daily summary");
+        }
+        tracing::info!(
+            detections = summary.detections,
+            alarms = summary.alarms,
+            next_due = %self.next_due,
+            "Daily summary sent"
+        );
+    }
+
+    fn publish(&self, summary: &DailySummary) -> Result<()> {
+        let payload = serde_json::to_vec(summary).context("Failed to serialize daily summary")?;
+        self.mqtt
+            .publish(&self.config.topic, QoS::AtLeastOnce, false, payload)
+            .context("Failed to publish MQTT message")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local
+            .with_ymd_and_hms(2025, 3, day, hour, minute, 0)
+            .single()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_activity_summary() {
+        let mut activity = Activity::new(at(1, 8, 0));
+        activity.record_detections(&[0.8, 0.6], at(1, 9, 15));
+        activity.record_detections(&[0.7], at(1, 14, 0));
+        activity.record_detections(&[0.9], at(1, 14, 30));
+        activity.record_detections(&[], at(1, 15, 0));
+        activity.record_alarm();
+
+        let summary = activity.finish("front-door", at(2, 8, 0), 90_061);
+        assert_eq!(summary.detections, 4);
+        assert!((summary.average_confidence.unwrap() - 0.75).abs() < 1e-6);
+        assert_eq!(summary.alarms, 1);
+        // Ties go to the earlier hour
+        assert_eq!(summary.busiest_hour, Some(9));
+        assert!(summary.body().contains("Busiest hour: 09:00-09:59"));
+        assert!(summary.body().contains("Uptime: 1d 01h 01m"));
+
+        // Counting starts over
+        let empty = activity.finish("front-door", at(3, 8, 0), 0);
+        assert_eq!(empty.period_start, at(2, 8, 0).to_rfc3339());
+        assert_eq!(empty.detections, 0);
+        assert_eq!(empty.average_confidence, None);
+        assert_eq!(empty.busiest_hour, None);
+    }
+
+    #[test]
+    fn test_next_occurrence() {
+        let eight = NaiveTime::from_hms_opt(8, 0, 0).unwrap();
+        assert_eq!(next_occurrence(eight, at(10, 7, 59)), at(10, 8, 0));
+        assert_eq!(next_occurrence(eight, at(10, 8, 0)), at(11, 8, 0));
+        assert_eq!(next_occurrence(eight, at(10, 23, 0)), at(11, 8, 0));
+    }
+}
@@ -0,0 +1,249 @@
+//! Per-zone, per-class activity counters
+//!
+//! `ZONES` names rectangles of the frame, in coordinates normalized to the frame size
+//! (see [`common::rect`]), e.g. `driveway:0,0.5,1,1;porch:0.7,0,1,0.5`. Without it
+//! the whole frame is a single `frame` zone. A detection at or above the alert
+//! confidence is in every zone holding the center of its box.
+//!
+//! A class enters a zone on the first frame it is seen there, and leaves it after
+//! `TRACKING_EXIT_FRAMES` frames without it, so a missed detection does not count as
+//! a new entry. It dwells in the zone for the time between frames it is seen in.
+
+use anyhow::{Context, Result};
+use common::{Rect, WallClockNs};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Longer gaps between results (stalls, skipped results) do not count as dwell time
+const MAX_FRAME_GAP: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Zone {
+    pub name: String,
+    /// `None` for the whole frame
+    pub rect: Option<Rect>,
+}
+
+impl Zone {
+    fn contains(&self, position: Option<(f32, f32)>) -> bool {
+        self.rect
+            .is_none_or(|rect| position.is_some_and(|(x, y)| rect.contains(x, y)))
+    }
+}
+
+/// Parse `ZONES`, a `;`-separated list of `name:x1,y1,x2,y2`
+pub fn parse_zones(value: &str) -> Result<Vec<Zone>> {
+    let mut zones: Vec<Zone> = Vec::new();
+    for entry in value.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+        let (name, rect) = entry
+            .split_once(':')
+            .with_context(|| format!("ZONES entry must be name:x1,y1,x2,y2, got {:?}", entry))?;
+        let name = name.trim();
+        anyhow::ensure!(!name.is_empty(), "ZONES entry without a name: {:?}", entry);
+        anyhow::ensure!(
+            zones.iter().all(|zone| zone.name != name),
+            "ZONES names {:?} twice",
+            name
+        );
+
+        let rect = Rect::parse(rect).with_context(|| format!("Invalid ZONES entry {:?}", entry))?;
+
+        zones.push(Zone {
+            name: name.to_string(),
+            rect: Some(rect),
+        });
+    }
+
+    if zones.is_empty() {
+        zones.push(Zone {
+            name: "frame".to_string(),
+            rect: None,
+        });
+    }
+    Ok(zones)
+}
+
+/// A detection of `class_id`
+#[derive(Debug, Clone, Copy)]
+pub struct Sighting {
+    pub class_id: u16,
+    /// Center of its box normalized to the frame size, `None` when the result does
+    /// not give the frame size, counting it in the whole frame zone only
+    pub position: Option<(f32, f32)>,
+}
+
+/// What happened in the zones on one frame, zones given by index
+#[derive(Debug, Default, PartialEq)]
+pub struct ZoneActivity {
+    /// Classes that entered a zone
+    pub entries: Vec<(usize, u16)>,
+    /// Classes seen in a zone, with the time they dwelt there since the previous frame
+    pub dwell: Vec<(usize, u16, Duration)>,
+}
+
+pub struct ZoneTracker {
+    zones: Vec<Zone>,
+    exit_frames: u32,
+    /// Frames each class present in a zone has been missing for
+    present: HashMap<(usize, u16), u32>,
+    last_timestamp: Option<WallClockNs>,
+}
+
+impl ZoneTracker {
+    pub fn new(zones: Vec<Zone>, exit_frames: u32) -> Self {
+        Self {
+            zones,
+            exit_frames,
+            present: HashMap::new(),
+            last_timestamp: None,
+        }
+    }
+
+    pub fn zone_name(&self, zone: usize) -> &str {
+        &self.zones[zone].name
+    }
+
+    /// The zone is the whole frame, `ZONES` being unset
+    pub fn is_whole_frame(&self, zone: usize) -> bool {
+        self.zones[zone].rect.is_none()
+    }
+
+    /// Update presence from the sightings of the frame taken at `timestamp`
+    pub fn update(
+        &mut self,
+        sightings: impl IntoIterator<Item = Sighting>,
+        timestamp: WallClockNs,
+    ) -> ZoneActivity {
+        let elapsed = self
+            .last_timestamp
+            .map(|last| timestamp.saturating_duration_since(last))
+            .filter(|elapsed| *elapsed <= MAX_FRAME_GAP)
+            .unwrap_or_default();
+        self.last_timestamp = Some(timestamp);
+
+        let mut seen: Vec<(usize, u16)> = Vec::new();
+        for sighting in sightings {
+            for (zone, _) in self
+                .zones
+                .iter()
+                .enumerate()
+                .filter(|(_, zone)| zone.contains(sighting.position))
+            {
+                if !seen.contains(&(zone, sighting.class_id)) {
+                    seen.push((zone, sighting.class_id));
+                }
+            }
+        }
+
+        let mut activity = ZoneActivity::default();
+        for key in &seen {
+            if self.present.insert(*key, 0).is_none() {
+                activity.entries.push(*key);
+            } else {
+                activity.dwell.push((key.0, key.1, elapsed));
+            }
+        }
+        let exit_frames = self.exit_frames;
+        self.present.retain(|key, missed| {
+            if !seen.contains(key) {
+                *missed += 1;
+            }
+            *missed < exit_frames.max(1)
+        });
+        activity
+    }
+
+    /// Zones where `class_id` is currently present, in zone order
+    pub fn zones_of(&self, class_id: u16) -> Vec<usize> {
+        let mut zones: Vec<usize> = self
+            .present
+            .keys()
+            .filter(|(_, class)| *class == class_id)
+            .map(|(zone, _)| *zone)
+            .collect();
+        zones.sort_unstable();
+        zones
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seconds(seconds: u64) -> WallClockNs {
+        WallClockNs::from_nanos(seconds * 1_000_000_000)
+    }
+
+    fn person_at(x: f32, y: f32) -> Sighting {
+        Sighting {
+            class_id: 0,
+            position: Some((x, y)),
+        }
+    }
+
+    #[test]
+    fn test_parse_zones() {
+        let zones = parse_zones("driveway:0,0.5,1,1; porch:0.7,0,1,0.5").unwrap();
+        assert_eq!(zones.len(), 2);
+        assert_eq!(zones[1].name, "porch");
+        assert_eq!(zones[1].rect, Some(Rect::new(0.7, 0.0, 1.0, 0.5).unwrap()));
+
+        assert_eq!(parse_zones("").unwrap()[0].name, "frame");
+        assert!(parse_zones("a:0,0,1,1;a:0,0,0.5,0.5").is_err());
+        assert!(parse_zones("a:0.5,0,0.1,1").is_err());
+        assert!(
+            parse_zones("a:0,360,1280,720").is_err(),
+            "pixel coordinates"
+        );
+        assert!(parse_zones("0,0,1,1").is_err());
+    }
+
+    #[test]
+    fn test_entries_and_dwell_per_zone() {
+        let zones = parse_zones("left:0,0,0.5,1;right:0.5,0,1,1").unwrap();
+        let mut tracker = ZoneTracker::new(zones, 2);
+
+        let activity = tracker.update([person_at(0.25, 0.5)], seconds(0));
+        assert_eq!(activity.entries, vec![(0, 0)]);
+        assert!(activity.dwell.is_empty());
+
+        // Two persons in the same zone enter and dwell once
+        let activity = tracker.update([person_at(0.2, 0.5), person_at(0.3, 0.5)], seconds(1));
+        assert!(activity.entries.is_empty());
+        assert_eq!(activity.dwell, vec![(0, 0, Duration::from_secs(1))]);
+
+        // A single missed frame is not an exit
+        tracker.update([], seconds(2));
+        let activity = tracker.update([person_at(0.25, 0.5)], seconds(3));
+        assert!(activity.entries.is_empty());
+        assert_eq!(tracker.zones_of(0), vec![0]);
+
+        // Moving right enters the other zone, the left one is left after 2 frames
+        tracker.update([person_at(0.75, 0.5)], seconds(4));
+        tracker.update([person_at(0.75, 0.5)], seconds(5));
+        assert_eq!(tracker.zones_of(0), vec![1]);
+        let activity = tracker.update([person_at(0.25, 0.5)], seconds(6));
+        assert_eq!(activity.entries, vec![(0, 0)]);
+    }
+
+    #[test]
+    fn test_unplaced_sightings_count_in_the_whole_frame_only() {
+        let unplaced = Sighting {
+            class_id: 0,
+            position: None,
+        };
+        let mut tracker = ZoneTracker::new(parse_zones("left:0,0,0.5,1").unwrap(), 2);
+        assert!(tracker.update([unplaced], seconds(0)).entries.is_empty());
+
+        let mut tracker = ZoneTracker::new(parse_zones("").unwrap(), 2);
+        assert_eq!(tracker.update([unplaced], seconds(0)).entries, vec![(0, 0)]);
+    }
+
+    #[test]
+    fn test_long_gaps_do_not_count_as_dwell() {
+        let mut tracker = ZoneTracker::new(parse_zones("").unwrap(), 3);
+        tracker.update([person_at(0.5, 0.5)], seconds(0));
+        let activity = tracker.update([person_at(0.5, 0.5)], seconds(60));
+        assert_eq!(activity.dwell, vec![(0, 0, Duration::ZERO)]);
+    }
+}
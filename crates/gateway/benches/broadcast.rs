@@ -0,0 +1,85 @@
+use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
+use gateway::polling::pixels_to_jpeg;
+use gateway::state::{FrameMessage, FramePacket};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Budget of one frame at 30 fps, for reading the results
+const FRAME_BUDGET_MS: f64 = 1000.0 / 30.0;
+
+/// A 1080p JPEG with some structure, close to what the gateway broadcasts
+fn full_hd_jpeg() -> Vec<u8> {
+    let (width, height) = (1920u32, 1080u32);
+    let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            pixels.push(((x * 255) / width) as u8);
+            pixels.push(((y * 255) / height) as u8);
+            pixels.push((((x ^ y) & 0xFF) as u8) / 2);
+        }
+    }
+    pixels_to_jpeg(&pixels, width, height).expect("Failed to encode test frame")
+}
+
+fn metadata() -> FrameMessage {
+    FrameMessage {
+        frame_number: 1,
+        timestamp_ns: 0,
+        width: 1920,
+        height: 1080,
+        detections: Some(Vec::new()),
+        inference_started_ns: None,
+        inference_completed_ns: None,
+        status: "complete".to_string(),
+        signature: None,
+    }
+}
+
+/// Send one frame and receive it on every subscriber, as the WebSocket tasks do
+fn fan_out<T: Clone>(
+    tx: &broadcast::Sender<T>,
+    receivers: &mut [broadcast::Receiver<T>],
+    value: T,
+) {
+    let _ = tx.send(value);
+    for rx in receivers.iter_mut() {
+        black_box(rx.try_recv().expect("frame should be queued"));
+    }
+}
+
+fn benchmark_broadcast(c: &mut Criterion) {
+    let jpeg = full_hd_jpeg();
+    println!(
+        "1080p JPEG is {} bytes, frame budget at 30 fps is {:.1} ms",
+        jpeg.len(),
+        FRAME_BUDGET_MS
+    );
+
+    let mut group = c.benchmark_group("broadcast_1080p");
+    for subscribers in [1usize, 4, 16] {
+        group.throughput(Throughput::Elements(subscribers as u64));
+
+        // Previous packet layout: every receiver clones the JPEG and metadata
+        let (tx, _) = broadcast::channel::<(FrameMessage, Vec<u8>)>(4);
+        let mut receivers: Vec<_> = (0..subscribers).map(|_| tx.subscribe()).collect();
+        group.bench_with_input(BenchmarkId::new("owned", subscribers), &jpeg, |b, jpeg| {
+            b.iter(|| fan_out(&tx, &mut receivers, (metadata(), jpeg.clone())))
+        });
+
+        let (tx, _) = broadcast::channel::<FramePacket>(4);
+        let mut receivers: Vec<_> = (0..subscribers).map(|_| tx.subscribe()).collect();
+        group.bench_with_input(BenchmarkId::new("shared", subscribers), &jpeg, |b, jpeg| {
+            b.iter(|| {
+                let packet = FramePacket {
+                    metadata: Arc::new(metadata()),
+                    jpeg_data: Arc::from(jpeg.as_slice()),
+                };
+                fan_out(&tx, &mut receivers, packet)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_broadcast);
+criterion_main!(benches);
@@ -0,0 +1,94 @@
+//! Snapshot of each alarm burst
+//!
+//! Capture takes a rapid burst of frames when the alarm goes off. The sharpest of
+//! them is kept as a snapshot, served from `GET /api/snapshots/:frame_number`, and
+//! with `BURST_SNAPSHOT_PATH` set also written to that file, which the controller's
+//! email and S3 notifiers attach when pointed at it.
+
+use crate::state::FramePacket;
+use crate::webhook::Snapshots;
+use bridge::BurstSelector;
+use common::fs::write_atomic;
+use schema::Burst;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub struct BurstSnapshots {
+    selector: BurstSelector<(u64, Arc<[u8]>)>,
+    snapshots: Snapshots,
+    path: Option<PathBuf>,
+}
+
+impl BurstSnapshots {
+    pub fn new(snapshots: Snapshots, path: Option<PathBuf>) -> Self {
+        Self {
+            selector: BurstSelector::default(),
+            snapshots,
+            path,
+        }
+    }
+
+    /// Observe a broadcast frame, `burst` being its burst tag
+    pub fn observe(&mut self, burst: Option<&Burst>, packet: &FramePacket) {
+        if packet.jpeg_data.is_empty() {
+            return;
+        }
+        let picked = self.selector.observe(burst, || {
+            (packet.metadata.frame_number, packet.jpeg_data.clone())
+        });
+        let Some((frame_number, jpeg)) = picked else {
+            return;
+        };
+
+        tracing::info!(frame_number, "Burst snapshot kept");
+        if let Some(path) = &self.path
+            && let Err(e) = write_atomic(path, &jpeg)
+        {
+            tracing::warn!(path = %path.display(), error = %e, "Failed to write burst snapshot");
+        }
+        self.snapshots.insert(frame_number, jpeg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::FrameMessage;
+
+    fn packet(frame_number: u64, jpeg: &[u8]) -> FramePacket {
+        FramePacket {
+            metadata: Arc::new(FrameMessage {
+                frame_number,
+                timestamp_ns: 0,
+                width: 4,
+                height: 4,
+                detections: None,
+                inference_started_ns: None,
+                inference_completed_ns: None,
+                status: "frame_only".to_string(),
+                signature: None,
+            }),
+            jpeg_data: Arc::from(jpeg),
+        }
+    }
+
+    #[test]
+    fn test_sharpest_burst_frame_is_written() {
+        let dir = std::env::temp_dir().join(format!("burst-snapshot-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("latest.jpg");
+        let snapshots = Snapshots::default();
+        let mut bursts = BurstSnapshots::new(snapshots.clone(), Some(path.clone()));
+
+        bursts.observe(None, &packet(9, b"idle"));
+        bursts.observe(Some(&Burst::new(10, 0, 2, 5.0)), &packet(10, b"blurred"));
+        assert!(!path.exists());
+        bursts.observe(Some(&Burst::new(10, 1, 2, 8.0)), &packet(11, b"sharp"));
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"sharp");
+        assert_eq!(snapshots.get(11).as_deref(), Some(b"sharp".as_slice()));
+        assert!(snapshots.get(9).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,303 @@
+//! Connected viewers and the admin endpoints listing and disconnecting them
+//!
+//! Every WebSocket client and playing RTSP session registers here for as long as
+//! it is connected. `GET /api/clients` reports what each one costs in upload
+//! bandwidth, `DELETE /api/clients/:id` disconnects one. Both need the admin token.
+
+use crate::metrics::metrics;
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::Notify;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientKind {
+    WebSocket,
+    Rtsp,
+}
+
+/// Snapshot of a client, as served by `GET /api/clients`
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub kind: ClientKind,
+    pub addr: SocketAddr,
+    /// Stream the client receives
+    pub subscription: String,
+    pub connected_secs: u64,
+    pub frames_sent: u64,
+    /// Frames skipped because the client could not keep up
+    pub frames_dropped: u64,
+    /// Frames waiting for the client after the last send
+    pub lag: u64,
+    pub bytes_sent: u64,
+}
+
+#[derive(Default)]
+struct ClientStats {
+    frames_sent: AtomicU64,
+    frames_dropped: AtomicU64,
+    lag: AtomicU64,
+    bytes_sent: AtomicU64,
+}
+
+struct Entry {
+    kind: ClientKind,
+    addr: SocketAddr,
+    subscription: String,
+    connected_at: Instant,
+    stats: Arc<ClientStats>,
+    kick: Arc<Notify>,
+}
+
+#[derive(Default)]
+struct Registry {
+    next_id: u64,
+    clients: BTreeMap<u64, Entry>,
+}
+
+/// Shared registry of connected clients
+#[derive(Clone, Default)]
+pub struct Clients {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Clients {
+    /// Register a client until the returned handle is dropped
+    pub fn register(
+        &self,
+        kind: ClientKind,
+        addr: SocketAddr,
+        subscription: impl Into<String>,
+    ) -> ClientHandle {
+        let stats = Arc::new(ClientStats::default());
+        let kick = Arc::new(Notify::new());
+
+        let mut registry = self.registry.lock().unwrap();
+        registry.next_id += 1;
+        let id = registry.next_id;
+        registry.clients.insert(
+            id,
+            Entry {
+                kind,
+                addr,
+                subscription: subscription.into(),
+                connected_at: Instant::now(),
+                stats: stats.clone(),
+                kick: kick.clone(),
+            },
+        );
+
+        ClientHandle {
+            id,
+            clients: self.clone(),
+            stats,
+            kick,
+        }
+    }
+
+    pub fn list(&self) -> Vec<ClientInfo> {
+        let registry = self.registry.lock().unwrap();
+        registry
+            .clients
+            .iter()
+            .map(|(&id, entry)| ClientInfo {
+                id,
+                kind: entry.kind,
+                addr: entry.addr,
+                subscription: entry.subscription.clone(),
+                connected_secs: entry.connected_at.elapsed().as_secs(),
+                frames_sent: entry.stats.frames_sent.load(Ordering::Relaxed),
+                frames_dropped: entry.stats.frames_dropped.load(Ordering::Relaxed),
+                lag: entry.stats.lag.load(Ordering::Relaxed),
+                bytes_sent: entry.stats.bytes_sent.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    /// Ask client `id` to disconnect. Returns false if it is not connected.
+    pub fn kick(&self, id: u64) -> bool {
+        let registry = self.registry.lock().unwrap();
+        let Some(entry) = registry.clients.get(&id) else {
+            return false;
+        };
+        entry.kick.notify_one();
+        true
+    }
+}
+
+/// Registration of one client, removed from the registry on drop
+pub struct ClientHandle {
+    id: u64,
+    clients: Clients,
+    stats: Arc<ClientStats>,
+    kick: Arc<Notify>,
+}
+
+impl ClientHandle {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Record a frame of `bytes` sent, with `lag` frames still queued for the client
+    pub fn record_sent(&self, bytes: usize, lag: usize) {
+        self.stats.frames_sent.fetch_add(1, Ordering::Relaxed);
+        self.stats
+            .bytes_sent
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        self.stats.lag.store(lag as u64, Ordering::Relaxed);
+        metrics().record_lag(self.id, lag);
+    }
+
+    pub fn record_dropped(&self, frames: u64) {
+        self.stats
+            .frames_dropped
+            .fetch_add(frames, Ordering::Relaxed);
+        metrics().record_lagged(self.id, frames);
+    }
+
+    /// Resolves once an admin kicked the client
+    ///
+    /// Does not borrow the handle, so it can race a receive on the same client.
+    pub fn kicked(&self) -> impl Future<Output = ()> + use<> {
+        let kick = self.kick.clone();
+        async move { kick.notified().await }
+    }
+}
+
+impl Drop for ClientHandle {
+    fn drop(&mut self) {
+        self.clients
+            .registry
+            .lock()
+            .unwrap()
+            .clients
+            .remove(&self.id);
+        metrics().record_lag(self.id, 0);
+    }
+}
+
+type ApiResult<T> = Result<T, (StatusCode, String)>;
+
+/// `GET /api/clients`
+pub async fn list(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> ApiResult<Json<Vec<ClientInfo>>> {
+    state.tuning.authorize(&headers)?;
+    Ok(Json(state.clients.list()))
+}
+
+/// `DELETE /api/clients/:id`
+pub async fn kick(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> ApiResult<StatusCode> {
+    state.tuning.authorize(&headers)?;
+    if state.clients.kick(id) {
+        tracing::info!(client = id, "Client kicked");
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Ok(StatusCode::NOT_FOUND)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn addr() -> SocketAddr {
+        "192.168.1.20:51000".parse().unwrap()
+    }
+
+    #[test]
+    fn test_clients_are_listed_until_dropped() {
+        let clients = Clients::default();
+        let viewer = clients.register(ClientKind::WebSocket, addr(), "/ws");
+        let nvr = clients.register(ClientKind::Rtsp, addr(), "stream");
+
+        viewer.record_sent(1000, 2);
+        viewer.record_sent(500, 0);
+        viewer.record_dropped(3);
+
+        let listed = clients.list();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].id, viewer.id());
+        assert_eq!(listed[0].frames_sent, 2);
+        assert_eq!(listed[0].bytes_sent, 1500);
+        assert_eq!(listed[0].frames_dropped, 3);
+        assert_eq!(listed[0].lag, 0);
+        assert_eq!(listed[1].kind, ClientKind::Rtsp);
+
+        drop(nvr);
+        assert_eq!(clients.list().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_kick_wakes_the_client() {
+        let clients = Clients::default();
+        let viewer = clients.register(ClientKind::WebSocket, addr(), "/ws");
+        let kicked = viewer.kicked();
+
+        assert!(clients.kick(viewer.id()));
+        tokio::time::timeout(Duration::from_secs(1), kicked)
+            .await
+            .expect("client should be kicked");
+
+        assert!(!clients.kick(viewer.id() + 1));
+    }
+
+    #[tokio::test]
+    async fn test_endpoints_need_the_admin_token() {
+        use axum::http::{HeaderValue, header};
+
+        let (tx, _rx) = tokio::sync::broadcast::channel(1);
+        let mut state = AppState {
+            tx: Arc::new(tx),
+            readiness: common::Readiness::new(&[], None, None),
+            device_id: "test".into(),
+            clients: Default::default(),
+            snapshots: Default::default(),
+            tuning: Default::default(),
+            controller: None,
+            stills: None,
+            frame_history: None,
+            share: None,
+        };
+        state.tuning.admin_token = Some("s3cret".to_string());
+        let viewer = state.clients.register(ClientKind::WebSocket, addr(), "/ws");
+
+        let anonymous = HeaderMap::new();
+        let denied = list(State(state.clone()), anonymous.clone()).await;
+        assert_eq!(denied.unwrap_err().0, StatusCode::UNAUTHORIZED);
+        let denied = kick(State(state.clone()), anonymous, Path(viewer.id())).await;
+        assert_eq!(denied.unwrap_err().0, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            state.clients.list().len(),
+            1,
+            "client should stay connected"
+        );
+
+        let mut admin = HeaderMap::new();
+        admin.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer s3cret"),
+        );
+        let listed = list(State(state.clone()), admin.clone()).await.unwrap();
+        assert_eq!(listed.0.len(), 1);
+        let kicked = kick(State(state), admin, Path(viewer.id())).await;
+        assert_eq!(kicked.unwrap(), StatusCode::NO_CONTENT);
+    }
+}
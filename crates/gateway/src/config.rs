@@ -1,22 +1,340 @@
-use common::{Environment, get_env, get_env_opt};
+use crate::video_encode::{Backend, Capabilities, Codec, EncoderPaths, EncoderSettings, Latency};
+use crate::webhook;
+use common::classes::{ClassGroups, ClassSet};
+use common::{ConfigCheck, Environment, Template, get_env, get_env_opt};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct GatewayConfig {
     pub environment: Environment,
+    /// Identifies this camera in webhook and health events, e.g. the controller's
+    /// `MQTT_DEVICE_ID`
+    pub device_id: String,
     pub poll_interval_ms: u64,
     pub ws_addr: String,
     pub channel_capacity: usize,
     pub otel_endpoint: Option<String>,
+    /// RTSP re-streaming, disabled when `RTSP_ADDR` is unset
+    pub rtsp: Option<RtspConfig>,
+    /// Crop of the broadcast frame around detections, disabled when unset
+    pub auto_crop: Option<AutoCropConfig>,
+    /// Only detections of these classes are broadcast, all when unset
+    pub classes: Option<ClassSet>,
+    /// Outbound webhooks on detection activity, disabled when `WEBHOOK_URLS` is unset
+    pub webhook: Option<WebhookConfig>,
+    /// Bearer token allowing clients to change thresholds, changes are refused when unset
+    pub admin_token: Option<String>,
+    /// File the sharpest frame of each alarm burst is written to, e.g. the
+    /// controller's `SMTP_SNAPSHOT_PATH`
+    pub burst_snapshot_path: Option<String>,
+    /// Recent frames served by `GET /api/frames`, disabled when
+    /// `FRAME_HISTORY_SECONDS` is unset
+    pub frame_history: Option<FrameHistoryConfig>,
+    /// Links served under `/share`, disabled when `SHARE_LINK_SECRET` is unset
+    pub share: Option<ShareConfig>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RtspConfig {
+    pub addr: String,
+    /// Stream path, served as `rtsp://<addr>/<path>`
+    pub path: String,
+    pub bitrate_kbps: u32,
+    /// Frames between IDR frames
+    pub keyframe_interval: u32,
+    /// `None` picks the best encoder available at startup (`RTSP_ENCODER=auto`)
+    pub encoder: Option<Backend>,
+    /// No B-frames or lookahead, for live viewing
+    pub low_latency: bool,
+    /// OpenH264 shared library, loaded at startup
+    pub openh264_library: String,
+    /// `ffmpeg` executable running the other encoders
+    pub ffmpeg: String,
+    /// DRM render node of the VAAPI encoder
+    pub vaapi_device: String,
+    /// Register on the frame write gate so capture waits for every frame to be
+    /// encoded, within its `FRAME_WRITE_GATE_MS`
+    pub lossless: bool,
+}
+
+impl RtspConfig {
+    fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(addr) = get_env_opt("RTSP_ADDR") else {
+            return Ok(None);
+        };
+        let encoder = match get_env("RTSP_ENCODER", "openh264".to_string()).as_str() {
+            "auto" => None,
+            name => Some(name.parse()?),
+        };
+
+        Ok(Some(Self {
+            addr,
+            path: get_env("RTSP_PATH", "stream".to_string())
+                .trim_matches('/')
+                .to_string(),
+            bitrate_kbps: get_env("RTSP_BITRATE_KBPS", 2000),
+            keyframe_interval: get_env("RTSP_KEYFRAME_INTERVAL", 30),
+            encoder,
+            low_latency: get_env("RTSP_LOW_LATENCY", true),
+            openh264_library: get_env("OPENH264_LIBRARY", "libopenh264.so.7".to_string()),
+            ffmpeg: get_env("FFMPEG_PATH", "ffmpeg".to_string()),
+            vaapi_device: get_env("VAAPI_DEVICE", "/dev/dri/renderD128".to_string()),
+            lossless: get_env("RTSP_LOSSLESS", false),
+        }))
+    }
+
+    pub fn encoder_settings(&self) -> EncoderSettings {
+        EncoderSettings {
+            codec: Codec::H264,
+            bitrate_kbps: self.bitrate_kbps,
+            keyframe_interval: self.keyframe_interval,
+            latency: if self.low_latency {
+                Latency::Low
+            } else {
+                Latency::Normal
+            },
+        }
+    }
+
+    pub fn encoder_paths(&self) -> EncoderPaths {
+        EncoderPaths {
+            ffmpeg: self.ffmpeg.clone(),
+            vaapi_device: self.vaapi_device.clone(),
+            openh264_library: self.openh264_library.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AutoCropConfig {
+    /// Margin added around the detections, as a fraction of their size
+    pub padding: f32,
+    /// Smallest crop, as a fraction of the frame width
+    pub min_size: f32,
+    /// Share of the way to the target the crop moves each frame, 0-1
+    pub smoothing: f32,
+}
+
+impl AutoCropConfig {
+    fn from_env() -> Option<Self> {
+        if !get_env("AUTO_CROP", false) {
+            return None;
+        }
+        Some(Self {
+            padding: get_env("AUTO_CROP_PADDING", 0.2f32).max(0.0),
+            min_size: get_env("AUTO_CROP_MIN_SIZE", 0.35f32).clamp(0.05, 1.0),
+            smoothing: get_env("AUTO_CROP_SMOOTHING", 0.15f32).clamp(0.01, 1.0),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FrameHistoryConfig {
+    /// How far back frames are kept
+    pub retention: Duration,
+    /// Shortest time between two kept frames
+    pub interval: Duration,
+}
+
+impl FrameHistoryConfig {
+    fn from_env() -> Option<Self> {
+        let seconds: u64 = get_env_opt("FRAME_HISTORY_SECONDS")?;
+        (seconds > 0).then(|| Self {
+            retention: Duration::from_secs(seconds),
+            interval: Duration::from_millis(get_env("FRAME_HISTORY_INTERVAL_MS", 1000u64).max(1)),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ShareConfig {
+    /// Key signing the links, changing it revokes every link
+    pub secret: String,
+    /// Longest a link can be valid for
+    pub max_duration: Duration,
+    /// Requests a client address can make under `/share` per minute
+    pub rate_limit: u32,
+}
+
+impl ShareConfig {
+    fn from_env() -> Option<Self> {
+        Some(Self {
+            secret: get_env_opt("SHARE_LINK_SECRET")?,
+            max_duration: Duration::from_secs(get_env("SHARE_LINK_MAX_MINUTES", 24 * 60u64) * 60),
+            rate_limit: get_env("SHARE_LINK_RATE_LIMIT", 60),
+        })
+    }
+}
+
+/// When webhooks fire
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookTrigger {
+    /// When detections appear in the stream and when they clear
+    StateChange,
+    /// On the first and then every `interval`th batch holding detections
+    Batches { interval: u64 },
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub urls: Vec<String>,
+    pub trigger: WebhookTrigger,
+    /// Consecutive empty detection batches before the scene counts as clear
+    pub clear_batches: u32,
+    /// Base URL clients reach the gateway at, e.g. `http://camera.local:8080`.
+    /// Events carry no snapshot URL without it.
+    pub public_url: Option<String>,
+    /// Key of the `X-Webhook-Signature` HMAC, bodies are unsigned without it
+    pub secret: Option<String>,
+    pub timeout: Duration,
+    /// Attempts per URL before an event is dropped
+    pub max_retries: u32,
+    /// Events waiting to be posted before new ones are dropped
+    pub queue_capacity: usize,
+    /// Replaces the JSON event body, a `common::template` over
+    /// `webhook::TEMPLATE_FIELDS`
+    pub body_template: Option<String>,
+}
+
+impl WebhookConfig {
+    fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(urls) = get_env_opt::<String>("WEBHOOK_URLS") else {
+            return Ok(None);
+        };
+        let trigger = match get_env("WEBHOOK_TRIGGER", "state_change".to_string()).as_str() {
+            "state_change" => WebhookTrigger::StateChange,
+            "batch" => WebhookTrigger::Batches {
+                interval: get_env("WEBHOOK_BATCH_INTERVAL", 30u64).max(1),
+            },
+            other => anyhow::bail!(
+                "Invalid WEBHOOK_TRIGGER {:?}, expected state_change or batch",
+                other
+            ),
+        };
+
+        Ok(Some(Self {
+            urls: urls
+                .split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(String::from)
+                .collect(),
+            trigger,
+            clear_batches: get_env("WEBHOOK_CLEAR_BATCHES", 30u32).max(1),
+            public_url: get_env_opt::<String>("WEBHOOK_PUBLIC_URL")
+                .map(|url| url.trim_end_matches('/').to_string()),
+            secret: get_env_opt("WEBHOOK_SECRET"),
+            timeout: Duration::from_millis(get_env("WEBHOOK_TIMEOUT_MS", 5000)),
+            max_retries: get_env("WEBHOOK_MAX_RETRIES", 3u32).max(1),
+            queue_capacity: get_env("WEBHOOK_QUEUE_CAPACITY", 32usize).max(1),
+            body_template: get_env_opt("WEBHOOK_BODY_TEMPLATE"),
+        }))
+    }
 }
 
 impl GatewayConfig {
-    pub fn from_env() -> Self {
-        Self {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
             environment: Environment::from_env(),
+            device_id: get_env("DEVICE_ID", "unknown".to_string()),
             poll_interval_ms: get_env("GATEWAY_POLL_INTERVAL_MS", 16), // ~60fps
             ws_addr: get_env("GATEWAY_WS_ADDR", "0.0.0.0:8080".to_string()),
             channel_capacity: get_env("GATEWAY_CHANNEL_CAPACITY", 10),
             otel_endpoint: get_env_opt("OTEL_EXPORTER_OTLP_ENDPOINT"),
+            rtsp: RtspConfig::from_env()?,
+            auto_crop: AutoCropConfig::from_env(),
+            // Class ids, COCO labels or groups, e.g. `person,vehicle`
+            classes: get_env_opt::<String>("GATEWAY_CLASSES")
+                .map(|list| ClassGroups::from_env()?.resolve_list(&list))
+                .transpose()?,
+            webhook: WebhookConfig::from_env()?,
+            admin_token: get_env_opt("GATEWAY_ADMIN_TOKEN"),
+            burst_snapshot_path: get_env_opt("BURST_SNAPSHOT_PATH"),
+            frame_history: FrameHistoryConfig::from_env(),
+            share: ShareConfig::from_env(),
+        })
+    }
+
+    /// Check the listen addresses, that RTSP has an H.264 encoder, and the admin
+    /// token, share link, burst snapshot and webhook settings
+    pub fn check(&self, check: &mut ConfigCheck) {
+        check.ensure(self.ws_addr.parse::<SocketAddr>().is_ok(), || {
+            format!("GATEWAY_WS_ADDR: {:?} is not an address", self.ws_addr)
+        });
+        check.ensure(self.channel_capacity > 0, || {
+            "GATEWAY_CHANNEL_CAPACITY must be at least 1".to_string()
+        });
+        if let Some(rtsp) = &self.rtsp {
+            check.ensure(rtsp.addr.parse::<SocketAddr>().is_ok(), || {
+                format!("RTSP_ADDR: {:?} is not an address", rtsp.addr)
+            });
+            check.ensure(rtsp.keyframe_interval > 0, || {
+                "RTSP_KEYFRAME_INTERVAL must be at least 1".to_string()
+            });
+            let capabilities = Capabilities::detect(&rtsp.encoder_paths());
+            match rtsp.encoder {
+                Some(Backend::OpenH264) => {
+                    if let Err(e) = unsafe { libloading::Library::new(&rtsp.openh264_library) } {
+                        check.problem(format!(
+                            "OPENH264_LIBRARY: cannot load {}: {e}",
+                            rtsp.openh264_library
+                        ));
+                    }
+                }
+                Some(backend) => check.ensure(capabilities.supports(backend, Codec::H264), || {
+                    format!("RTSP_ENCODER: {backend} is not available on this machine")
+                }),
+                None => check.ensure(capabilities.best(Codec::H264).is_some(), || {
+                    "RTSP_ENCODER: no H.264 encoder available, install ffmpeg or OpenH264"
+                        .to_string()
+                }),
+            }
+        }
+        if let Some(token) = &self.admin_token {
+            check.ensure(!token.is_empty(), || {
+                "GATEWAY_ADMIN_TOKEN must not be empty".to_string()
+            });
+        }
+        if let Some(share) = &self.share {
+            check.ensure(share.secret.len() >= 16, || {
+                "SHARE_LINK_SECRET must be at least 16 characters".to_string()
+            });
+            check.ensure(self.admin_token.is_some(), || {
+                "SHARE_LINK_SECRET needs GATEWAY_ADMIN_TOKEN to create links".to_string()
+            });
+            check.ensure(!share.max_duration.is_zero(), || {
+                "SHARE_LINK_MAX_MINUTES must be at least 1".to_string()
+            });
+        }
+        if let Some(path) = &self.burst_snapshot_path {
+            let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+            check.ensure(dir.as_os_str().is_empty() || dir.is_dir(), || {
+                format!("BURST_SNAPSHOT_PATH: directory of {path} does not exist")
+            });
+        }
+        if let Some(webhook) = &self.webhook {
+            check.ensure(!webhook.urls.is_empty(), || {
+                "WEBHOOK_URLS lists no URL".to_string()
+            });
+            let urls = webhook.urls.iter().map(|url| ("WEBHOOK_URLS", url));
+            for (key, url) in urls.chain(
+                webhook
+                    .public_url
+                    .iter()
+                    .map(|url| ("WEBHOOK_PUBLIC_URL", url)),
+            ) {
+                check.ensure(
+                    url.starts_with("http://") || url.starts_with("https://"),
+                    || format!("{key}: {url:?} is not an http(s) URL"),
+                );
+            }
+            if let Some(template) = &webhook.body_template
+                && let Err(e) = Template::parse(template, webhook::TEMPLATE_FIELDS)
+            {
+                check.problem(format!("WEBHOOK_BODY_TEMPLATE: {e}"));
+            }
         }
     }
 
@@ -24,10 +342,19 @@ impl GatewayConfig {
     pub fn test_default() -> Self {
         Self {
             environment: Environment::Development,
+            device_id: "test".to_string(),
             poll_interval_ms: 16,
             ws_addr: "0.0.0.0:8080".to_string(),
             channel_capacity: 10,
             otel_endpoint: None,
+            rtsp: None,
+            auto_crop: None,
+            classes: None,
+            webhook: None,
+            admin_token: None,
+            burst_snapshot_path: None,
+            frame_history: None,
+            share: None,
         }
     }
 }
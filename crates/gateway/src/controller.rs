@@ -0,0 +1,143 @@
+//! Controller state and arming
+//!
+//! `GET /api/controller/state` reports whether the controller is armed, the state
+//! of its alarm state machine and its config version, as it last published them
+//! through the bridge. Clients holding `GATEWAY_ADMIN_TOKEN` arm or disarm it with
+//! `POST /api/controller/arm` and `{"armed": true}`; the controller applies the
+//! request on its next loop, within about a second.
+//!
+//! `POST /api/controller/drill` (same token) starts an alert drill, which sends a
+//! test event through every notifier of the controller, and
+//! `GET /api/controller/drill` serves the report of the last one.
+
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use bridge::ControllerStatus;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The controller publishes at least every second, it is offline past this
+const OFFLINE_AFTER: Duration = Duration::from_secs(5);
+
+/// Body of `GET /api/controller/state`
+#[derive(Debug, Serialize)]
+pub struct ControllerState {
+    /// The controller published within the last few seconds
+    pub online: bool,
+    /// Fields below are `None` until the controller first published
+    pub armed: Option<bool>,
+    pub state: Option<&'static str>,
+    pub config_version: Option<u64>,
+    pub age_ms: Option<u64>,
+    /// Arm request the controller has not applied yet
+    pub pending_arm: Option<bool>,
+}
+
+/// Body of `POST /api/controller/drill`
+#[derive(Debug, Serialize)]
+pub struct DrillRequested {
+    /// The report's `id` is `http-<request>`
+    pub request: u64,
+}
+
+/// Body of `POST /api/controller/arm`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArmRequest {
+    pub armed: bool,
+}
+
+type ApiResult<T> = Result<T, (StatusCode, String)>;
+
+fn status(state: &AppState) -> ApiResult<&ControllerStatus> {
+    state.controller.as_deref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Controller status unavailable".to_string(),
+    ))
+}
+
+/// `GET /api/controller/state`
+pub async fn get(State(state): State<AppState>) -> ApiResult<Json<ControllerState>> {
+    Ok(Json(controller_state(status(&state)?)))
+}
+
+/// `POST /api/controller/arm`
+pub async fn arm(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ArmRequest>,
+) -> ApiResult<(StatusCode, Json<ControllerState>)> {
+    state.tuning.authorize(&headers)?;
+    let status = status(&state)?;
+    tracing::info!(armed = request.armed, "Arm request sent to the controller");
+    status.request_arm(request.armed);
+    Ok((StatusCode::ACCEPTED, Json(controller_state(status))))
+}
+
+/// `POST /api/controller/drill`
+pub async fn drill(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> ApiResult<(StatusCode, Json<DrillRequested>)> {
+    state.tuning.authorize(&headers)?;
+    let request = status(&state)?.request_drill();
+    tracing::info!(request, "Alert drill requested");
+    Ok((StatusCode::ACCEPTED, Json(DrillRequested { request })))
+}
+
+/// `GET /api/controller/drill`
+pub async fn drill_report(State(state): State<AppState>) -> ApiResult<Response> {
+    let report = status(&state)?
+        .drill_report()
+        .ok_or((StatusCode::NOT_FOUND, "No drill run yet".to_string()))?;
+    Ok(([(header::CONTENT_TYPE, "application/json")], report).into_response())
+}
+
+fn controller_state(status: &ControllerStatus) -> ControllerState {
+    let snapshot = status.snapshot();
+    ControllerState {
+        online: snapshot.is_some_and(|s| s.age <= OFFLINE_AFTER),
+        armed: snapshot.map(|s| s.armed),
+        state: snapshot.map(|s| s.phase.name()),
+        config_version: snapshot.map(|s| s.config_version),
+        age_ms: snapshot.map(|s| s.age.as_millis() as u64),
+        pending_arm: status.pending_arm(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bridge::AlarmPhase;
+
+    #[test]
+    fn test_state_reflects_the_controller() {
+        let path = &format!("{}/test_gateway_controller_status", bridge::paths::SHM_DIR);
+        let _ = std::fs::remove_file(path);
+        let gateway = ControllerStatus::new(path).unwrap();
+        let controller = ControllerStatus::new(path).unwrap();
+
+        let state = controller_state(&gateway);
+        assert!(!state.online);
+        assert_eq!(state.armed, None);
+
+        gateway.request_arm(false);
+        assert_eq!(controller_state(&gateway).pending_arm, Some(false));
+        assert_eq!(controller.take_arm_request(), Some(false));
+        controller.publish(false, AlarmPhase::Validation, 1);
+
+        let state = controller_state(&gateway);
+        assert!(state.online);
+        assert_eq!(state.armed, Some(false));
+        assert_eq!(state.state, Some("validation"));
+        assert_eq!(state.config_version, Some(1));
+        assert_eq!(state.pending_arm, None);
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,249 @@
+//! "Follow the action" crop of the broadcast frame
+//!
+//! Small screens cannot show much of a wide camera frame, so this mode crops it
+//! around the detections before encoding. The crop keeps the frame's aspect ratio,
+//! never zooms past `min_size` of the frame, and eases towards its target so boxes
+//! jittering between frames do not shake the picture. Without detections it
+//! eases back out to the full frame.
+
+use crate::config::AutoCropConfig;
+use bridge::Detection;
+
+/// Crop rectangle in frame pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    fn full(width: u32, height: u32) -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            width: width as f32,
+            height: height as f32,
+        }
+    }
+
+    /// Move each edge `alpha` of the way towards `target`
+    fn ease(self, target: Rect, alpha: f32) -> Self {
+        let lerp = |from: f32, to: f32| from + (to - from) * alpha;
+        Self {
+            x: lerp(self.x, target.x),
+            y: lerp(self.y, target.y),
+            width: lerp(self.width, target.width),
+            height: lerp(self.height, target.height),
+        }
+    }
+
+    /// Whole pixels, even sized for chroma subsampling, inside a `width` x `height` frame
+    pub fn to_pixels(self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let even =
+            |size: f32, limit: u32| ((size.round() as u32).min(limit) & !1).max(limit.min(2));
+        let (w, h) = (even(self.width, width), even(self.height, height));
+        let x = (self.x.round().max(0.0) as u32).min(width - w);
+        let y = (self.y.round().max(0.0) as u32).min(height - h);
+        (x, y, w, h)
+    }
+}
+
+pub struct AutoCrop {
+    config: AutoCropConfig,
+    window: Option<Rect>,
+    /// Frame size `window` was computed for
+    frame_size: (u32, u32),
+}
+
+impl AutoCrop {
+    pub fn new(config: AutoCropConfig) -> Self {
+        Self {
+            config,
+            window: None,
+            frame_size: (0, 0),
+        }
+    }
+
+    /// Crop for the next frame, given its detections in frame pixels
+    pub fn update(&mut self, detections: &[Detection], width: u32, height: u32) -> Rect {
+        let target = self.target(detections, width, height);
+        let window = match self.window {
+            Some(window) if self.frame_size == (width, height) => {
+                window.ease(target, self.config.smoothing)
+            }
+            // First frame or resolution change: jump straight to the target
+            _ => target,
+        };
+        self.window = Some(window);
+        self.frame_size = (width, height);
+        window
+    }
+
+    /// Smallest frame-shaped rectangle around the padded detections
+    fn target(&self, detections: &[Detection], width: u32, height: u32) -> Rect {
+        let Some((x1, y1, x2, y2)) = detections.iter().fold(None, |bounds, det| {
+            let (x1, y1, x2, y2) = bounds.unwrap_or((det.x1, det.y1, det.x2, det.y2));
+            Some((
+                x1.min(det.x1),
+                y1.min(det.y1),
+                x2.max(det.x2),
+                y2.max(det.y2),
+            ))
+        }) else {
+            return Rect::full(width, height);
+        };
+
+        let (frame_w, frame_h) = (width as f32, height as f32);
+        let pad = self.config.padding;
+        let box_w = (x2 - x1) * (1.0 + 2.0 * pad);
+        let box_h = (y2 - y1) * (1.0 + 2.0 * pad);
+
+        // Grow the short side to the frame's aspect ratio, then the whole crop to
+        // the minimum size
+        let aspect = frame_w / frame_h;
+        let crop_w = box_w
+            .max(box_h * aspect)
+            .max(frame_w * self.config.min_size)
+            .min(frame_w);
+        let crop_h = crop_w / aspect;
+
+        let center_x = (x1 + x2) / 2.0;
+        let center_y = (y1 + y2) / 2.0;
+        Rect {
+            x: (center_x - crop_w / 2.0).clamp(0.0, frame_w - crop_w),
+            y: (center_y - crop_h / 2.0).clamp(0.0, frame_h - crop_h),
+            width: crop_w,
+            height: crop_h,
+        }
+    }
+}
+
+/// Copy a `(x, y, w, h)` region out of a packed RGB frame `width` pixels wide
+pub fn crop_rgb(pixels: &[u8], width: u32, (x, y, w, h): (u32, u32, u32, u32)) -> Vec<u8> {
+    let stride = width as usize * 3;
+    let row_len = w as usize * 3;
+    let mut cropped = Vec::with_capacity(row_len * h as usize);
+    for row in pixels
+        .chunks_exact(stride)
+        .skip(y as usize)
+        .take(h as usize)
+    {
+        let start = x as usize * 3;
+        cropped.extend_from_slice(&row[start..start + row_len]);
+    }
+    cropped
+}
+
+/// Express detections in the cropped frame, dropping those outside it
+pub fn crop_detections(
+    detections: Vec<Detection>,
+    (x, y, w, h): (u32, u32, u32, u32),
+) -> Vec<Detection> {
+    let (x, y, w, h) = (x as f32, y as f32, w as f32, h as f32);
+    detections
+        .into_iter()
+        .filter_map(|det| {
+            let det = Detection {
+                x1: (det.x1 - x).clamp(0.0, w),
+                y1: (det.y1 - y).clamp(0.0, h),
+                x2: (det.x2 - x).clamp(0.0, w),
+                y2: (det.y2 - y).clamp(0.0, h),
+                ..det
+            };
+            (det.x2 > det.x1 && det.y2 > det.y1).then_some(det)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AutoCropConfig {
+        AutoCropConfig {
+            padding: 0.0,
+            min_size: 0.25,
+            smoothing: 0.5,
+        }
+    }
+
+    fn det(x1: f32, y1: f32, x2: f32, y2: f32) -> Detection {
+        Detection {
+            x1,
+            y1,
+            x2,
+            y2,
+            confidence: 0.9,
+            class_id: 0,
+        }
+    }
+
+    #[test]
+    fn test_no_detections_shows_full_frame() {
+        let mut crop = AutoCrop::new(config());
+
+        assert_eq!(crop.update(&[], 1920, 1080), Rect::full(1920, 1080));
+    }
+
+    #[test]
+    fn test_target_keeps_aspect_ratio_and_stays_in_frame() {
+        let crop = AutoCrop::new(config());
+
+        // Tall person near the right edge
+        let rect = crop.target(&[det(1800.0, 100.0, 1900.0, 700.0)], 1920, 1080);
+        assert!((rect.width / rect.height - 1920.0 / 1080.0).abs() < 1e-3);
+        assert!((rect.height - 600.0).abs() < 1e-3);
+        assert!((rect.x + rect.width - 1920.0).abs() < 1e-3);
+
+        // A tiny box is not zoomed past the minimum size
+        let rect = crop.target(&[det(100.0, 100.0, 110.0, 110.0)], 1920, 1080);
+        assert!((rect.width - 480.0).abs() < 1e-3);
+        assert_eq!((rect.x, rect.y), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_window_eases_towards_target() {
+        let mut crop = AutoCrop::new(config());
+        crop.update(&[], 1000, 1000);
+
+        let rect = crop.update(&[det(0.0, 0.0, 500.0, 500.0)], 1000, 1000);
+        assert_eq!(rect.width, 750.0);
+        assert_eq!((rect.x, rect.y), (0.0, 0.0));
+
+        // A new resolution resets the window
+        let rect = crop.update(&[], 500, 500);
+        assert_eq!(rect, Rect::full(500, 500));
+    }
+
+    #[test]
+    fn test_crop_pixels_and_detections() {
+        // 4x2 frame, each pixel's red channel is its index
+        let pixels: Vec<u8> = (0..8).flat_map(|i| [i, 0, 0]).collect();
+        let cropped = crop_rgb(&pixels, 4, (1, 1, 2, 1));
+        assert_eq!(cropped, [5, 0, 0, 6, 0, 0]);
+
+        let detections = crop_detections(
+            vec![det(10.0, 10.0, 30.0, 30.0), det(0.0, 0.0, 5.0, 5.0)],
+            (20, 20, 100, 100),
+        );
+        assert_eq!(detections.len(), 1);
+        assert_eq!(
+            (detections[0].x1, detections[0].y1, detections[0].x2),
+            (0.0, 0.0, 10.0)
+        );
+    }
+
+    #[test]
+    fn test_pixel_rect_is_even_and_inside_frame() {
+        let rect = Rect {
+            x: 1501.3,
+            y: -2.0,
+            width: 421.7,
+            height: 237.1,
+        };
+
+        assert_eq!(rect.to_pixels(1920, 1080), (1498, 0, 422, 236));
+    }
+}
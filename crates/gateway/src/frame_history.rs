@@ -0,0 +1,275 @@
+//! Recent frames for scrubbing back in time
+//!
+//! With `FRAME_HISTORY_SECONDS` set the gateway keeps a broadcast frame every
+//! `FRAME_HISTORY_INTERVAL_MS` for that long, and `GET /api/frames?at=<ms>` serves
+//! the one captured nearest `at`, in Unix milliseconds. Enough for a scrubber over
+//! the last minutes without recording video.
+//!
+//! When capture signs its frames (`FRAME_SIGNING_KEY_FILE`) the signature is kept
+//! with each frame and served in the `X-Frame-Signature*` headers, so a saved
+//! frame can later be checked against the key, see `bridge::signing`.
+
+use crate::config::FrameHistoryConfig;
+use crate::state::{AppState, FramePacket};
+use axum::{
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use bridge::FrameSignature;
+use common::WallClockNs;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone)]
+pub struct StoredFrame {
+    pub timestamp: WallClockNs,
+    pub frame_number: u64,
+    pub jpeg: Arc<[u8]>,
+    pub signature: Option<FrameSignature>,
+}
+
+#[derive(Clone)]
+pub struct FrameHistory {
+    config: FrameHistoryConfig,
+    /// Oldest first
+    frames: Arc<Mutex<VecDeque<StoredFrame>>>,
+}
+
+impl FrameHistory {
+    pub fn new(config: FrameHistoryConfig) -> Self {
+        Self {
+            config,
+            frames: Default::default(),
+        }
+    }
+
+    /// Keep a broadcast frame if the interval since the last kept one has passed
+    pub fn record(&self, packet: &FramePacket) {
+        if packet.jpeg_data.is_empty() {
+            return;
+        }
+        let timestamp = WallClockNs::from_nanos(packet.metadata.timestamp_ns);
+        let mut frames = self.frames.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(last) = frames.back() {
+            if timestamp < last.timestamp {
+                // The wall clock stepped back, the stored times no longer line up
+                frames.clear();
+            } else if timestamp.saturating_duration_since(last.timestamp) < self.config.interval {
+                return;
+            }
+        }
+
+        frames.push_back(StoredFrame {
+            timestamp,
+            frame_number: packet.metadata.frame_number,
+            jpeg: packet.jpeg_data.clone(),
+            signature: packet.metadata.signature.clone(),
+        });
+        while frames.front().is_some_and(|oldest| {
+            timestamp.saturating_duration_since(oldest.timestamp) > self.config.retention
+        }) {
+            frames.pop_front();
+        }
+    }
+
+    /// Stored frame captured nearest `at`
+    pub fn nearest(&self, at: WallClockNs) -> Option<StoredFrame> {
+        let frames = self.frames.lock().unwrap_or_else(|e| e.into_inner());
+        frames
+            .iter()
+            .min_by_key(|frame| {
+                frame
+                    .timestamp
+                    .saturating_duration_since(at)
+                    .max(at.saturating_duration_since(frame.timestamp))
+            })
+            .cloned()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FrameQuery {
+    /// Unix time in milliseconds
+    pub at: u64,
+}
+
+/// `GET /api/frames?at=<unix ms>`
+pub async fn frame_at(State(state): State<AppState>, Query(query): Query<FrameQuery>) -> Response {
+    let at = WallClockNs::from_nanos(query.at.saturating_mul(1_000_000));
+    match state
+        .frame_history
+        .as_ref()
+        .and_then(|history| history.nearest(at))
+    {
+        Some(frame) => {
+            let mut response = (
+                [
+                    (header::CONTENT_TYPE, "image/jpeg".to_string()),
+                    (
+                        header::HeaderName::from_static("x-frame-number"),
+                        frame.frame_number.to_string(),
+                    ),
+                    (
+                        header::HeaderName::from_static("x-frame-timestamp-ns"),
+                        frame.timestamp.as_nanos().to_string(),
+                    ),
+                ],
+                frame.jpeg.to_vec(),
+            )
+                .into_response();
+            if let Some(signature) = &frame.signature {
+                add_signature_headers(&mut response, signature);
+            }
+            response
+        }
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Serve capture's signature of the frame in `response` with it
+///
+/// `X-Frame-Signature-Timestamp-Ns` is the timestamp as signed, in the clock capture
+/// stamped the frame with, which may differ from `X-Frame-Timestamp-Ns`.
+pub fn add_signature_headers(response: &mut Response, signature: &FrameSignature) {
+    let headers = response.headers_mut();
+    for (name, value) in [
+        ("x-frame-signature", hex::encode(signature.signature)),
+        ("x-frame-signature-key", signature.key_id.clone()),
+        (
+            "x-frame-signature-timestamp-ns",
+            signature.timestamp_ns.to_string(),
+        ),
+        (
+            "x-frame-payload-sha256",
+            hex::encode(signature.payload_hash),
+        ),
+    ] {
+        if let Ok(value) = header::HeaderValue::try_from(value) {
+            headers.insert(header::HeaderName::from_static(name), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::FrameMessage;
+    use std::time::Duration;
+
+    const MS: u64 = 1_000_000;
+
+    fn packet(frame_number: u64, timestamp_ms: u64) -> FramePacket {
+        FramePacket {
+            metadata: Arc::new(FrameMessage {
+                frame_number,
+                timestamp_ns: timestamp_ms * MS,
+                width: 4,
+                height: 4,
+                detections: None,
+                inference_started_ns: None,
+                inference_completed_ns: None,
+                status: "frame_only".to_string(),
+                signature: None,
+            }),
+            jpeg_data: Arc::from(frame_number.to_string().as_bytes()),
+        }
+    }
+
+    fn history() -> FrameHistory {
+        FrameHistory::new(FrameHistoryConfig {
+            retention: Duration::from_secs(10),
+            interval: Duration::from_secs(1),
+        })
+    }
+
+    fn nearest(history: &FrameHistory, at_ms: u64) -> Option<u64> {
+        history
+            .nearest(WallClockNs::from_nanos(at_ms * MS))
+            .map(|frame| frame.frame_number)
+    }
+
+    #[test]
+    fn test_frames_are_sampled_and_expire() {
+        let history = history();
+        assert_eq!(nearest(&history, 0), None);
+
+        // 10 fps for 15 s, one frame kept per second
+        for i in 0..150 {
+            history.record(&packet(i, 100_000 + i * 100));
+        }
+        assert_eq!(nearest(&history, 114_400), Some(140));
+        assert_eq!(nearest(&history, 112_600), Some(130));
+        // Only the last 10 s are kept
+        assert_eq!(nearest(&history, 0), Some(40));
+    }
+
+    #[test]
+    fn test_clock_step_back_restarts_history() {
+        let history = history();
+        history.record(&packet(1, 100_000));
+        history.record(&packet(2, 50_000));
+
+        assert_eq!(nearest(&history, 100_000), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_frame_at_serves_nearest_jpeg() {
+        let (tx, _rx) = tokio::sync::broadcast::channel(1);
+        let history = history();
+        history.record(&packet(7, 100_000));
+        let state = AppState {
+            tx: Arc::new(tx),
+            readiness: common::Readiness::new(&[], None, None),
+            device_id: "test".into(),
+            clients: Default::default(),
+            snapshots: Default::default(),
+            tuning: Default::default(),
+            controller: None,
+            stills: None,
+            share: None,
+            frame_history: Some(history),
+        };
+
+        let response = frame_at(State(state.clone()), Query(FrameQuery { at: 100_300 })).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-frame-number"], "7");
+        assert_eq!(
+            response.headers()["x-frame-timestamp-ns"],
+            (100_000 * MS).to_string()
+        );
+
+        let disabled = AppState {
+            frame_history: None,
+            ..state
+        };
+        let response = frame_at(State(disabled), Query(FrameQuery { at: 100_300 })).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_signature_is_served_with_the_frame() {
+        let signer = bridge::FrameSigner::new("door", vec![5; 32]).unwrap();
+        let signature = signer.sign(7, 12_000_500_000, b"7");
+        let history = history();
+        let mut signed = packet(7, 100_000);
+        Arc::make_mut(&mut signed.metadata).signature = Some(signature.clone());
+        history.record(&signed);
+
+        let frame = history
+            .nearest(WallClockNs::from_nanos(100_000 * MS))
+            .unwrap();
+        let mut response = StatusCode::OK.into_response();
+        add_signature_headers(&mut response, frame.signature.as_ref().unwrap());
+        let headers = response.headers();
+        assert_eq!(headers["x-frame-signature-key"], "door");
+        assert_eq!(headers["x-frame-signature-timestamp-ns"], "12000500000");
+        assert_eq!(
+            headers["x-frame-signature"],
+            hex::encode(signature.signature).as_str()
+        );
+        // The stored JPEG is the signed payload
+        signer.verify(&signature, Some(&frame.jpeg)).unwrap();
+    }
+}
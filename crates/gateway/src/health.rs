@@ -0,0 +1,62 @@
+//! Liveness and readiness endpoints for orchestrator probes
+
+use crate::state::AppState;
+use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
+use common::{Event, EventType};
+
+/// The HTTP server is up
+pub async fn live() -> StatusCode {
+    StatusCode::OK
+}
+
+/// 200 once every startup dependency is connected, 503 with the pending ones otherwise,
+/// with a `health` event as body
+pub async fn ready(State(state): State<AppState>) -> impl IntoResponse {
+    let status = if state.readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    let health = state.readiness.health("gateway");
+    (
+        status,
+        Json(Event::new(&state.device_id, EventType::Health, health)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::{Dependency, Readiness};
+    use std::sync::Arc;
+    use tokio::sync::broadcast;
+
+    fn state(readiness: Readiness) -> AppState {
+        let (tx, _rx) = broadcast::channel(1);
+        AppState {
+            tx: Arc::new(tx),
+            readiness,
+            device_id: "test".into(),
+            clients: Default::default(),
+            snapshots: Default::default(),
+            tuning: Default::default(),
+            controller: None,
+            stills: None,
+            share: None,
+            frame_history: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ready_reflects_dependencies() {
+        let readiness = Readiness::new(&[Dependency::FrameBuffer], None, None);
+
+        let response = ready(State(state(readiness.clone()))).await.into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        readiness.mark_ready(Dependency::FrameBuffer);
+        let response = ready(State(state(readiness))).await.into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
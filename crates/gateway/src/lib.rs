@@ -1,5 +1,20 @@
+pub mod burst;
+pub mod clients;
 pub mod config;
+pub mod controller;
+pub mod crop;
+pub mod frame_history;
+pub mod health;
 pub mod logging;
+pub mod metrics;
+pub mod pacing;
 pub mod polling;
+pub mod rtsp;
+pub mod share;
 pub mod state;
+pub mod stills;
+pub mod tuning;
+pub mod ui;
+pub mod video_encode;
+pub mod webhook;
 pub mod ws;
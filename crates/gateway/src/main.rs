@@ -1,13 +1,23 @@
-use common::TelemetryGuard;
+use bridge::{ControllerStatus, SnapshotControl, ThresholdControl};
+use common::{Dependency, Readiness, TelemetryGuard};
 use gateway::{
-    config::GatewayConfig, logging::setup_logging, polling::BufferPoller, state::AppState, ws,
+    burst::BurstSnapshots, clients::Clients, config::GatewayConfig, frame_history::FrameHistory,
+    logging::setup_logging, polling::BufferPoller, share::ShareLinks, state::AppState,
+    tuning::Tuning, webhook::Webhooks, ws,
 };
 use std::sync::Arc;
 use tokio::sync::broadcast;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let config = GatewayConfig::from_env();
+    common::load_profile()?;
+    if common::config_check::requested() {
+        return common::config_check::run(|check| {
+            GatewayConfig::from_env()?.check(check);
+            Ok(())
+        });
+    }
+    let config = GatewayConfig::from_env()?;
 
     // TelemetryGuard initializes the tracing subscriber, so only call setup_logging if not using telemetry
     let _telemetry = if let Some(endpoint) = config.otel_endpoint.as_ref() {
@@ -25,12 +35,76 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!("WebSocket endpoint: ws://{}/ws", config.ws_addr);
 
     let (tx, _rx) = broadcast::channel(config.channel_capacity);
-    let state = AppState { tx: Arc::new(tx) };
+    let readiness = Readiness::from_env(&[
+        Dependency::FrameBuffer,
+        Dependency::DetectionBuffer,
+        Dependency::Semaphores,
+    ]);
+    let state = AppState {
+        tx: Arc::new(tx),
+        readiness: readiness.clone(),
+        device_id: config.device_id.as_str().into(),
+        clients: Clients::default(),
+        snapshots: Default::default(),
+        tuning: Tuning {
+            control: ThresholdControl::build()
+                .inspect_err(|e| tracing::warn!(error = %e, "Threshold tuning unavailable"))
+                .ok()
+                .map(Arc::new),
+            history: Default::default(),
+            admin_token: config.admin_token.clone(),
+        },
+        controller: ControllerStatus::build()
+            .inspect_err(|e| tracing::warn!(error = %e, "Controller state unavailable"))
+            .ok()
+            .map(Arc::new),
+        stills: SnapshotControl::build()
+            .inspect_err(|e| tracing::warn!(error = %e, "Still requests unavailable"))
+            .ok()
+            .map(Arc::new),
+        frame_history: config.frame_history.clone().map(FrameHistory::new),
+        share: config.share.clone().map(ShareLinks::new),
+    };
     let poll_tx = state.tx.clone();
+    let poll_config = config.clone();
+    let history = state.tuning.history.clone();
+    let snapshots = state.snapshots.clone();
+    let frame_history = state.frame_history.clone();
+    let burst_snapshots = BurstSnapshots::new(
+        state.snapshots.clone(),
+        config.burst_snapshot_path.clone().map(Into::into),
+    );
+    let webhooks = config
+        .webhook
+        .as_ref()
+        .map(|webhook| Webhooks::new(webhook, &config.device_id, state.snapshots.clone()))
+        .transpose()?;
+
+    if let Some(rtsp) = config.rtsp.clone() {
+        let readiness = readiness.clone();
+        let clients = state.clients.clone();
+        tokio::spawn(async move {
+            if let Err(e) = gateway::rtsp::run(rtsp, readiness, clients).await {
+                tracing::error!("RTSP server error: {}", e);
+            }
+        });
+    }
 
     tokio::spawn(async move {
-        match BufferPoller::build(poll_tx).await {
-            Ok(poller) => {
+        match BufferPoller::build(poll_tx, &readiness, &poll_config).await {
+            Ok(mut poller) => {
+                if let Some(webhooks) = webhooks {
+                    poller.set_webhooks(webhooks);
+                }
+                poller.set_detection_history(history);
+                poller.set_burst_snapshots(burst_snapshots);
+                if let Some(frame_history) = frame_history {
+                    poller.set_frame_history(frame_history);
+                }
+                match SnapshotControl::build() {
+                    Ok(control) => poller.set_snapshot_requests(control, snapshots),
+                    Err(e) => tracing::warn!(error = %e, "Snapshot requests unavailable"),
+                }
                 if let Err(e) = poller.run().await {
                     tracing::error!("Buffer polling error: {}", e);
                 }
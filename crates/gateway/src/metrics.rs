@@ -0,0 +1,82 @@
+//! OpenTelemetry instruments of the gateway
+//!
+//! Exported through the OTLP pipeline set up by `common::TelemetryGuard`; without
+//! an endpoint the global meter is a no-op. Frames are encoded in plain function
+//! callbacks of the synced reader, so the instruments are shared through
+//! `metrics()` rather than handed around.
+
+use opentelemetry::{
+    KeyValue, global,
+    metrics::{Counter, Gauge, Histogram},
+};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+pub struct GatewayMetrics {
+    encode_duration: Histogram<f64>,
+    dropped: Counter<u64>,
+    lag: Gauge<u64>,
+}
+
+static METRICS: LazyLock<GatewayMetrics> = LazyLock::new(|| GatewayMetrics::new("gateway"));
+
+/// Instruments of this process, created on first use
+pub fn metrics() -> &'static GatewayMetrics {
+    &METRICS
+}
+
+impl GatewayMetrics {
+    pub fn new(meter_name: &'static str) -> Self {
+        let meter = global::meter(meter_name);
+        let encode_buckets = [
+            0.001, 0.002, 0.005, 0.01, 0.015, 0.02, 0.03, 0.05, 0.075, 0.1, 0.2, 0.5,
+        ];
+
+        Self {
+            encode_duration: meter
+                .f64_histogram("gateway_jpeg_encode_seconds")
+                .with_description("Time to JPEG-encode a frame for the stream")
+                .with_unit("s")
+                .with_boundaries(encode_buckets.to_vec())
+                .build(),
+            dropped: meter
+                .u64_counter("gateway_packets_dropped_total")
+                .with_description(
+                    "Packets not delivered, by reason: no_receivers when the broadcast \
+                     send failed, lagged when a client fell behind the channel",
+                )
+                .build(),
+            lag: meter
+                .u64_gauge("gateway_broadcast_lag")
+                .with_description("Packets queued in the broadcast channel, by client")
+                .build(),
+        }
+    }
+
+    pub fn record_encode(&self, duration: Duration) {
+        self.encode_duration.record(duration.as_secs_f64(), &[]);
+    }
+
+    /// A broadcast send failed for lack of receivers
+    pub fn record_unsent(&self) {
+        self.dropped
+            .add(1, &[KeyValue::new("reason", "no_receivers")]);
+    }
+
+    /// `packets` overwritten before client `client` received them
+    pub fn record_lagged(&self, client: u64, packets: u64) {
+        self.dropped.add(
+            packets,
+            &[
+                KeyValue::new("reason", "lagged"),
+                KeyValue::new("client", client as i64),
+            ],
+        );
+    }
+
+    /// Packets queued for client `client`, recorded as 0 once it leaves
+    pub fn record_lag(&self, client: u64, queued: usize) {
+        self.lag
+            .record(queued as u64, &[KeyValue::new("client", client as i64)]);
+    }
+}
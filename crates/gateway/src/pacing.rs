@@ -0,0 +1,88 @@
+//! Per-client frame rate limiting
+//!
+//! WebSocket clients can ask for fewer frames than the camera produces with
+//! `/ws?fps=<rate>`. Frames over the rate are skipped for that client before its
+//! message is built, so a viewer on a slow link costs its own rate rather than the
+//! capture rate. Pacing follows the frames' capture timestamps, so it keeps an even
+//! cadence whatever the delivery jitter.
+
+/// Admits frames at no more than a given rate
+#[derive(Debug)]
+pub struct FramePacer {
+    interval_ns: u64,
+    /// Capture time from which the next frame is admitted
+    next_ns: Option<u64>,
+}
+
+impl FramePacer {
+    /// `None` unless `fps` is a positive rate
+    pub fn new(fps: f64) -> Option<Self> {
+        (fps.is_finite() && fps > 0.0).then(|| Self {
+            interval_ns: (1e9 / fps) as u64,
+            next_ns: None,
+        })
+    }
+
+    /// Whether the frame captured at `timestamp_ns` should be sent
+    pub fn admit(&mut self, timestamp_ns: u64) -> bool {
+        let next = match self.next_ns {
+            // A timestamp before the last admitted frame means capture restarted
+            Some(next) if timestamp_ns + self.interval_ns < next => timestamp_ns,
+            Some(next) if timestamp_ns < next => return false,
+            Some(next) => next,
+            None => timestamp_ns,
+        };
+        // Keep the cadence when on time, restart it after a gap
+        let late_by = timestamp_ns - next;
+        self.next_ns = Some(if late_by < self.interval_ns {
+            next + self.interval_ns
+        } else {
+            timestamp_ns + self.interval_ns
+        });
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    fn admitted(pacer: &mut FramePacer, timestamps: impl IntoIterator<Item = u64>) -> Vec<u64> {
+        timestamps
+            .into_iter()
+            .filter(|&ts| pacer.admit(ts))
+            .collect()
+    }
+
+    #[test]
+    fn test_rate_is_limited_without_drift() {
+        let mut pacer = FramePacer::new(5.0).unwrap();
+        // One second of 30 fps frames
+        let frames = (0..30).map(|i| i * 100 * MS / 3);
+        assert_eq!(
+            admitted(&mut pacer, frames),
+            vec![0, 200 * MS, 400 * MS, 600 * MS, 800 * MS]
+        );
+    }
+
+    #[test]
+    fn test_gaps_and_restarts_reset_the_cadence() {
+        let mut pacer = FramePacer::new(10.0).unwrap();
+        assert!(pacer.admit(1_000 * MS));
+        // Frames resume after a stall
+        assert!(pacer.admit(5_000 * MS));
+        assert!(!pacer.admit(5_050 * MS));
+        // Capture restarted with an earlier clock
+        assert!(pacer.admit(10 * MS));
+        assert!(!pacer.admit(20 * MS));
+    }
+
+    #[test]
+    fn test_invalid_rates_are_rejected() {
+        assert!(FramePacer::new(0.0).is_none());
+        assert!(FramePacer::new(-1.0).is_none());
+        assert!(FramePacer::new(f64::NAN).is_none());
+    }
+}
@@ -1,123 +1,260 @@
+use crate::burst::BurstSnapshots;
+use crate::config::GatewayConfig;
+use crate::crop::{self, AutoCrop};
+use crate::frame_history::FrameHistory;
+use crate::metrics::metrics;
 use crate::state::{FrameMessage, FramePacket};
+use crate::tuning::DetectionHistory;
+use crate::webhook::{Snapshots, Webhooks};
 use bridge::{
-    BridgeSemaphore, Detection, DetectionReader, FrameReader, SemaphoreType, set_trace_parent,
+    CapturedAt, Detection, DetectionReader, FormatWatch, FrameFormat, FramePair, FrameReader,
+    FrameSignature, FrameSubscription, InferenceTiming, SnapshotControl, SyncedReader,
+    set_trace_parent,
 };
-use common::{span, wait_for_resource_async};
+use common::classes::ClassSet;
+use common::{Dependency, Readiness, WallClockNs, Watchdog, log_throttle, span};
+use preprocess::LetterboxTransform;
+use schema::FrameEncoding;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::broadcast;
 use tokio::time;
 
 /// Frame metadata extracted from shared memory
 struct FrameMetadata {
     frame_number: u64,
-    timestamp_ns: u64,
+    timestamp: WallClockNs,
     width: u32,
     height: u32,
+    /// Bumped by capture each time the frame size changes
+    format_generation: u32,
+    /// Camera frame size detections are reported in, when capture downscaled the frame
+    original_size: Option<(u32, u32)>,
+    burst: Option<schema::Burst>,
+    signature: Option<FrameSignature>,
 }
 
 /// Result of processing a frame: metadata + encoded JPEG
 struct ProcessedFrame {
     metadata: FrameMetadata,
-    jpeg_data: Vec<u8>,
-}
-
-/// Detection data with status information
-struct DetectionData {
-    detections: Vec<Detection>,
-    has_jpeg: bool,
+    jpeg_data: Arc<[u8]>,
+    /// RGB frame held back for auto-crop, encoded once its detections are in
+    rgb: Option<Vec<u8>>,
+    trace: Option<schema::TraceContext>,
 }
 
 pub struct BufferPoller {
-    frame_reader: FrameReader,
-    detection_reader: DetectionReader,
-    frame_semaphore: Arc<BridgeSemaphore>,
+    reader: SyncedReader<ProcessedFrame>,
+    frame_semaphore: Arc<FrameSubscription>,
     tx: Arc<broadcast::Sender<FramePacket>>,
+    auto_crop: Option<AutoCrop>,
+    classes: Option<ClassSet>,
+    webhooks: Option<Webhooks>,
+    history: Option<DetectionHistory>,
+    snapshot_requests: Option<(SnapshotControl, Snapshots)>,
+    burst_snapshots: Option<BurstSnapshots>,
+    frame_history: Option<FrameHistory>,
+    /// Size of the frames, changing when capture renegotiates its resolution
+    format: FormatWatch,
 }
 
 const POLL_INTERVAL_MS: u64 = 500;
 
 impl BufferPoller {
     /// Build a new BufferPoller by connecting to shared memory buffers with retries
-    pub async fn build(tx: Arc<broadcast::Sender<FramePacket>>) -> anyhow::Result<Self> {
-        let frame_reader =
-            wait_for_resource_async(FrameReader::build, POLL_INTERVAL_MS, "Frame buffer").await;
-        let detection_reader =
-            wait_for_resource_async(DetectionReader::build, POLL_INTERVAL_MS, "Detection buffer")
-                .await;
-        let frame_semaphore = Arc::new(
-            wait_for_resource_async(
-                || BridgeSemaphore::open(SemaphoreType::FrameCaptureToGateway),
-                POLL_INTERVAL_MS,
-                "Gateway semaphore",
+    pub async fn build(
+        tx: Arc<broadcast::Sender<FramePacket>>,
+        readiness: &Readiness,
+        config: &GatewayConfig,
+    ) -> anyhow::Result<Self> {
+        let poll_interval = Duration::from_millis(POLL_INTERVAL_MS);
+
+        let frame_reader = readiness
+            .wait_for_async(
+                Dependency::FrameBuffer,
+                "Frame buffer",
+                poll_interval,
+                FrameReader::build,
             )
-            .await,
+            .await?;
+        let detection_reader = readiness
+            .wait_for_async(
+                Dependency::DetectionBuffer,
+                "Detection buffer",
+                poll_interval,
+                DetectionReader::build,
+            )
+            .await?;
+        let frame_semaphore = Arc::new(
+            readiness
+                .wait_for_async(
+                    Dependency::Semaphores,
+                    "Gateway frame subscription",
+                    poll_interval,
+                    || FrameSubscription::register("gateway"),
+                )
+                .await?,
         );
 
+        // Frames are JPEG-encoded straight from the mmap as they arrive, so only the
+        // compressed copy waits for its detections. Auto-crop needs the detections
+        // before encoding, so it keeps the pixels instead.
+        let convert = match config.auto_crop {
+            Some(_) => keep_frame,
+            None => process_frame,
+        };
+        let reader = SyncedReader::new(frame_reader, detection_reader, convert);
+
         Ok(Self {
-            frame_reader,
-            detection_reader,
+            reader,
             frame_semaphore,
             tx,
+            auto_crop: config.auto_crop.clone().map(AutoCrop::new),
+            classes: config.classes,
+            webhooks: None,
+            history: None,
+            snapshot_requests: None,
+            burst_snapshots: None,
+            frame_history: None,
+            format: FormatWatch::new(),
         })
     }
 
+    /// Fire webhooks from the broadcast frames
+    pub fn set_webhooks(&mut self, webhooks: Webhooks) {
+        self.webhooks = Some(webhooks);
+    }
+
+    /// Record detections for threshold previews
+    pub fn set_detection_history(&mut self, history: DetectionHistory) {
+        self.history = Some(history);
+    }
+
+    /// Keep a broadcast frame in `snapshots` for each request made through `control`
+    pub fn set_snapshot_requests(&mut self, control: SnapshotControl, snapshots: Snapshots) {
+        self.snapshot_requests = Some((control, snapshots));
+    }
+
+    /// Keep the sharpest frame of each alarm burst
+    pub fn set_burst_snapshots(&mut self, burst_snapshots: BurstSnapshots) {
+        self.burst_snapshots = Some(burst_snapshots);
+    }
+
+    /// Keep recent frames for `GET /api/frames`
+    pub fn set_frame_history(&mut self, frame_history: FrameHistory) {
+        self.frame_history = Some(frame_history);
+    }
+
     /// Main polling loop
     pub async fn run(mut self) -> anyhow::Result<()> {
+        let mut watchdog = Watchdog::from_env();
+        let wait_timeout = watchdog.ping_interval().unwrap_or(Duration::from_secs(1));
+        watchdog.ready();
+
         tracing::info!("Starting event-driven buffer processing (synchronized to camera)");
 
         loop {
-            // Wait for frame ready signal
-            if let Err(e) = self.wait_for_frame().await {
-                tracing::error!(error = %e, "Frame wait failed");
-                time::sleep(Duration::from_millis(100)).await;
-                continue;
-            }
-
-            let trace_ctx = self.peek_trace_context();
+            watchdog.ping();
 
-            let span = tracing::info_span!("gateway_process_frame");
-            if let Some(ref ctx) = trace_ctx {
-                set_trace_parent(ctx, &span);
-            }
-            let _guard = span.entered();
-
-            let processed = match self.process_frame() {
-                Ok(data) => data,
+            // Wait for frame ready signal, waking up periodically to keep the watchdog alive
+            match self.wait_for_frame(wait_timeout).await {
+                Ok(true) => {}
+                Ok(false) => continue,
                 Err(e) => {
-                    tracing::error!(error = %e, "Failed to process frame - skipping");
+                    tracing::error!(error = %e, "Frame wait failed");
+                    time::sleep(Duration::from_millis(100)).await;
                     continue;
                 }
-            };
-
-            // Read detections if available
-            let detection_data = self.read_detections(!processed.jpeg_data.is_empty());
+            }
 
-            // Build and broadcast packet
-            let packet = self.build_packet(processed, detection_data);
-            self.broadcast_packet(packet);
+            if let Some(pid) = self.reader.frame_writer_changed() {
+                tracing::warn!(pid, "Frame buffer claimed by another writer");
+            }
+            if let Some(pid) = self.reader.detection_writer_changed() {
+                tracing::warn!(pid, "Detection buffer claimed by another writer");
+            }
 
-            // Mark buffers as read
-            self.frame_reader.mark_read();
-            self.detection_reader.mark_read();
+            // Broadcast every frame whose detections are in, or that inference skipped
+            loop {
+                match self.reader.next_pair(Duration::ZERO) {
+                    Ok(Some(pair)) => self.publish(pair),
+                    Ok(None) => break,
+                    Err(e) => {
+                        log_throttle!(error, error = %e, "Failed to read buffers - skipping");
+                        break;
+                    }
+                }
+            }
         }
     }
 
-    fn peek_trace_context(&self) -> Option<schema::TraceContext> {
-        self.frame_reader
-            .get_frame()
-            .ok()
-            .flatten()
-            .and_then(|f| f.trace().copied())
+    fn publish(&mut self, mut pair: FramePair<ProcessedFrame>) {
+        let span = tracing::info_span!("gateway_process_frame");
+        if let Some(ref ctx) = pair.frame.trace {
+            set_trace_parent(ctx, &span);
+        }
+        let _guard = span.entered();
+
+        let metadata = &pair.frame.metadata;
+        let format = FrameFormat {
+            width: metadata.width,
+            height: metadata.height,
+            generation: metadata.format_generation,
+        };
+        // Packets carry their own size, clients and the crop follow it per frame
+        if let Some(previous) = self.format.observe(format) {
+            tracing::info!(
+                previous_width = previous.width,
+                previous_height = previous.height,
+                width = format.width,
+                height = format.height,
+                generation = format.generation,
+                "Frame size changed"
+            );
+        }
+
+        // Before class filtering, as thresholds apply to every class
+        if let (Some(history), Some(detections)) = (&self.history, pair.detections.as_ref()) {
+            history.record(detections);
+        }
+        if let (Some(classes), Some(detections)) = (self.classes, pair.detections.as_mut()) {
+            detections.retain(|det| classes.contains(det.class_id));
+        }
+        if let Some(auto_crop) = self.auto_crop.as_mut() {
+            pair.detections = crop_frame(auto_crop, &mut pair.frame, pair.detections);
+        }
+        let burst = pair.frame.metadata.burst;
+        let packet = build_packet(pair.frame, pair.detections, pair.timing);
+        if let Some(burst_snapshots) = self.burst_snapshots.as_mut() {
+            burst_snapshots.observe(burst.as_ref(), &packet);
+        }
+        if let Some(webhooks) = self.webhooks.as_mut() {
+            webhooks.observe(&packet);
+        }
+        if let Some(frame_history) = &self.frame_history {
+            frame_history.record(&packet);
+        }
+        if let Some((control, snapshots)) = &self.snapshot_requests
+            && let Some(request) = control.pending()
+            && !packet.jpeg_data.is_empty()
+        {
+            let frame_number = packet.metadata.frame_number;
+            snapshots.insert(frame_number, packet.jpeg_data.clone());
+            control.fulfill(request, frame_number);
+            tracing::info!(request, frame_number, "Snapshot kept");
+        }
+        self.broadcast_packet(packet);
     }
 
-    /// Wait for frame ready signal from camera
-    async fn wait_for_frame(&self) -> anyhow::Result<()> {
+    /// Wait at most `timeout` for the frame ready signal from camera, returning
+    /// whether it came
+    async fn wait_for_frame(&self, timeout: Duration) -> anyhow::Result<bool> {
         let sem = self.frame_semaphore.clone();
-        let wait_result = tokio::task::spawn_blocking(move || sem.wait()).await;
+        let wait_result =
+            tokio::task::spawn_blocking(move || sem.wait_timeout_duration(timeout)).await;
 
         match wait_result {
-            Ok(Ok(())) => Ok(()),
+            Ok(Ok(signaled)) => Ok(signaled),
             Ok(Err(e)) => {
                 anyhow::bail!("Semaphore wait failed: {}", e)
             }
@@ -130,180 +267,234 @@ impl BufferPoller {
         }
     }
 
-    /// Process frame: read from shared memory and encode to JPEG in one step.
-    /// This avoids copying pixel data by encoding while holding the mmap borrow.
-    fn process_frame(&mut self) -> anyhow::Result<ProcessedFrame> {
-        let _s = span!("process_frame");
-
-        let frame_seq = self.frame_reader.current_sequence();
+    /// Broadcast packet to WebSocket clients
+    fn broadcast_packet(&self, packet: FramePacket) {
+        let _s = span!("broadcast_packet");
 
-        let frame = match self.frame_reader.get_frame() {
-            Ok(Some(data)) => data,
-            Ok(None) => {
-                anyhow::bail!("No frame available")
-            }
-            Err(e) => {
-                // Mark as read even on error to prevent getting stuck
-                self.frame_reader.mark_read();
-                anyhow::bail!(
-                    "Failed to read frame buffer (sequence {}): {}",
-                    frame_seq,
-                    e
-                )
-            }
-        };
+        let det_count = packet
+            .metadata
+            .detections
+            .as_ref()
+            .map(|d| d.len())
+            .unwrap_or(0);
 
-        // Extract metadata
-        let frame_number = frame.frame_number();
-        let timestamp_ns = frame.timestamp_ns();
-        let width = frame.width();
-        let height = frame.height();
-
-        // Encode to JPEG directly from mmap'd pixel data (zero-copy read)
-        let jpeg_data = if let Some(pixels) = frame.pixels() {
-            let pixel_data = pixels.bytes(); // &[u8] borrowed from mmap
-            self.encode_pixels_to_jpeg(pixel_data, width, height)
-        } else {
-            Vec::new()
-        };
+        tracing::debug!(
+            frame_number = packet.metadata.frame_number,
+            detections = det_count,
+            status = packet.metadata.status,
+            "Frame processed"
+        );
 
-        Ok(ProcessedFrame {
-            metadata: FrameMetadata {
-                frame_number,
-                timestamp_ns,
-                width,
-                height,
-            },
-            jpeg_data,
-        })
+        if self.tx.send(packet).is_err() {
+            metrics().record_unsent();
+        }
     }
+}
 
-    /// Read detections from shared memory if available.
-    /// Converts from zero-copy FlatBuffers to owned BoundingBox for serialization.
-    fn read_detections(&mut self, has_jpeg: bool) -> Option<DetectionData> {
-        let _s = span!("read_detections");
-
-        let detection_seq = self.detection_reader.current_sequence();
+/// Process frame: encode to JPEG while holding the mmap borrow.
+/// This avoids copying pixel data into the pairing buffer.
+fn process_frame(frame: &schema::Frame<'_>) -> anyhow::Result<ProcessedFrame> {
+    let _s = span!("process_frame");
+
+    let width = frame.width();
+    let height = frame.height();
+
+    // Encode to JPEG directly from mmap'd pixel data (zero-copy read unless
+    // capture compressed it)
+    let jpeg_data = match bridge::frame_payload(frame)? {
+        // Capture wrote the camera's JPEG as is, no need to re-encode it
+        (FrameEncoding::Jpeg, payload) => Arc::from(&payload[..]),
+        (_, pixels) if pixels.is_empty() => Arc::default(),
+        (_, pixels) => encode_pixels_to_jpeg(&pixels, width, height).into(),
+    };
 
-        if detection_seq == 0 {
-            return None;
-        }
+    Ok(ProcessedFrame {
+        metadata: frame_metadata(frame),
+        jpeg_data,
+        rgb: None,
+        trace: frame.trace().copied(),
+    })
+}
 
-        match self.detection_reader.get_detections() {
-            Ok(Some(detection_result)) => {
-                // Convert FlatBuffers detections to owned Detection at serialization boundary
-                let detections = detection_result
-                    .detections()
-                    .map(|dets| {
-                        dets.iter()
-                            .filter_map(|d| Detection::try_from(&d).ok())
-                            .collect()
-                    })
-                    .unwrap_or_default();
-
-                Some(DetectionData {
-                    detections,
-                    has_jpeg,
-                })
-            }
-            Ok(None) => None,
-            Err(e) => {
-                tracing::error!(
-                    error = %e,
-                    sequence = detection_seq,
-                    "Failed to deserialize detection buffer - skipping detections"
-                );
-                None
+/// Copy the frame as RGB for auto-crop, decoding capture's JPEG if needed
+fn keep_frame(frame: &schema::Frame<'_>) -> anyhow::Result<ProcessedFrame> {
+    let _s = span!("keep_frame");
+
+    let expected_size = (frame.width() * frame.height() * 3) as usize;
+    let rgb = match bridge::frame_payload(frame)? {
+        (FrameEncoding::Jpeg, payload) => {
+            match turbojpeg::decompress(&payload, turbojpeg::PixelFormat::RGB) {
+                Ok(image) if image.pixels.len() >= expected_size => Some(image.pixels),
+                Ok(_) => {
+                    log_throttle!(error, "Decoded JPEG frame is smaller than its header says");
+                    None
+                }
+                Err(e) => {
+                    log_throttle!(error, error = %e, "Failed to decode JPEG frame for auto-crop");
+                    None
+                }
             }
         }
+        (_, pixels) if pixels.len() >= expected_size => {
+            let mut pixels = pixels.into_owned();
+            pixels.truncate(expected_size);
+            Some(pixels)
+        }
+        _ => None,
+    };
+
+    Ok(ProcessedFrame {
+        metadata: frame_metadata(frame),
+        jpeg_data: Arc::default(),
+        rgb,
+        trace: frame.trace().copied(),
+    })
+}
+
+fn frame_metadata(frame: &schema::Frame<'_>) -> FrameMetadata {
+    FrameMetadata {
+        frame_number: frame.frame_number(),
+        timestamp: frame.captured_at().to_wall_clock(),
+        width: frame.width(),
+        height: frame.height(),
+        format_generation: frame.format_generation(),
+        original_size: match (frame.original_width(), frame.original_height()) {
+            (0, _) | (_, 0) => None,
+            size => Some(size),
+        },
+        burst: frame.burst().copied(),
+        signature: FrameSignature::from_frame(frame).unwrap_or_else(|e| {
+            log_throttle!(warn, error = %e, "Unreadable frame signature");
+            None
+        }),
     }
+}
 
-    /// Encode RGB pixel data to JPEG
-    fn encode_pixels_to_jpeg(&self, pixel_data: &[u8], width: u32, height: u32) -> Vec<u8> {
-        let _s = span!("encode_pixels_to_jpeg");
+/// Crop a kept frame around its detections and encode it
+///
+/// Returns the detections in the cropped frame's coordinates.
+fn crop_frame(
+    auto_crop: &mut AutoCrop,
+    frame: &mut ProcessedFrame,
+    detections: Option<Vec<Detection>>,
+) -> Option<Vec<Detection>> {
+    let _s = span!("crop_frame");
+
+    let Some(pixels) = frame.rgb.take() else {
+        return detections;
+    };
+    let metadata = &mut frame.metadata;
+    let (width, height) = (metadata.width, metadata.height);
 
-        if pixel_data.is_empty() {
-            return Vec::new();
+    let detections = match (detections, metadata.original_size.take()) {
+        (Some(detections), Some(original_size)) => {
+            Some(scale_detections(detections, original_size, (width, height)))
         }
+        (detections, _) => detections,
+    };
 
-        // Validate pixel data size
-        let expected_size = (width * height * 3) as usize;
-        if pixel_data.len() < expected_size {
-            tracing::error!(
-                expected = expected_size,
-                actual = pixel_data.len(),
-                "Pixel buffer size mismatch - skipping JPEG encoding"
-            );
-            return Vec::new();
-        }
+    let rect = auto_crop
+        .update(detections.as_deref().unwrap_or_default(), width, height)
+        .to_pixels(width, height);
+    let (_, _, crop_width, crop_height) = rect;
+    let cropped = crop::crop_rgb(&pixels, width, rect);
+    frame.jpeg_data = encode_pixels_to_jpeg(&cropped, crop_width, crop_height).into();
+    metadata.width = crop_width;
+    metadata.height = crop_height;
 
-        match pixels_to_jpeg(pixel_data, width, height) {
-            Ok(data) => data,
-            Err(e) => {
-                tracing::error!("Image encoding error: {}", e);
-                Vec::new()
-            }
-        }
-    }
+    detections.map(|detections| crop::crop_detections(detections, rect))
+}
 
-    /// Build packet for broadcast
-    fn build_packet(
-        &self,
-        processed: ProcessedFrame,
-        detection_data: Option<DetectionData>,
-    ) -> FramePacket {
-        let _s = span!("build_packet");
-
-        let (detections, status) = match detection_data {
-            Some(DetectionData {
-                detections,
-                has_jpeg,
-            }) => {
-                let status = if has_jpeg {
-                    "complete"
-                } else {
-                    "detection_only"
-                };
-                (Some(detections), status.to_string())
-            }
-            None => (None, "frame_only".to_string()),
-        };
+/// Encode RGB pixel data to JPEG
+fn encode_pixels_to_jpeg(pixel_data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let _s = span!("encode_pixels_to_jpeg");
 
-        let metadata = FrameMessage {
-            frame_number: processed.metadata.frame_number,
-            timestamp_ns: processed.metadata.timestamp_ns,
-            width: processed.metadata.width,
-            height: processed.metadata.height,
-            detections,
-            status,
-        };
+    if pixel_data.is_empty() {
+        return Vec::new();
+    }
 
-        FramePacket {
-            metadata,
-            jpeg_data: processed.jpeg_data,
+    // Validate pixel data size
+    let expected_size = (width * height * 3) as usize;
+    if pixel_data.len() < expected_size {
+        log_throttle!(
+            error,
+            expected = expected_size,
+            actual = pixel_data.len(),
+            "Pixel buffer size mismatch - skipping JPEG encoding"
+        );
+        return Vec::new();
+    }
+
+    let start = Instant::now();
+    match pixels_to_jpeg(pixel_data, width, height) {
+        Ok(data) => {
+            metrics().record_encode(start.elapsed());
+            data
+        }
+        Err(e) => {
+            log_throttle!(error, "Image encoding error: {}", e);
+            Vec::new()
         }
     }
+}
 
-    /// Broadcast packet to WebSocket clients
-    fn broadcast_packet(&self, packet: FramePacket) {
-        let _s = span!("broadcast_packet");
+/// Build packet for broadcast
+fn build_packet(
+    processed: ProcessedFrame,
+    detections: Option<Vec<Detection>>,
+    timing: Option<InferenceTiming>,
+) -> FramePacket {
+    let _s = span!("build_packet");
+
+    let status = match (&detections, processed.jpeg_data.is_empty()) {
+        (Some(_), false) => "complete",
+        (Some(_), true) => "detection_only",
+        (None, _) => "frame_only",
+    };
 
-        let det_count = packet
-            .metadata
-            .detections
-            .as_ref()
-            .map(|d| d.len())
-            .unwrap_or(0);
+    // Boxes are drawn over the JPEG, so bring them down to its size
+    let detections = match (detections, processed.metadata.original_size) {
+        (Some(detections), Some(original_size)) => Some(scale_detections(
+            detections,
+            original_size,
+            (processed.metadata.width, processed.metadata.height),
+        )),
+        (detections, _) => detections,
+    };
 
-        tracing::debug!(
-            frame_number = packet.metadata.frame_number,
-            detections = det_count,
-            status = packet.metadata.status,
-            "Frame processed"
-        );
+    let metadata = FrameMessage {
+        frame_number: processed.metadata.frame_number,
+        timestamp_ns: processed.metadata.timestamp.as_nanos(),
+        width: processed.metadata.width,
+        height: processed.metadata.height,
+        detections,
+        inference_started_ns: timing.map(|t| t.inference_started_ns.as_nanos()),
+        inference_completed_ns: timing.map(|t| t.inference_completed_ns.as_nanos()),
+        status: status.to_string(),
+        signature: processed.metadata.signature,
+    };
+
+    FramePacket {
+        metadata: Arc::new(metadata),
+        jpeg_data: processed.jpeg_data,
+    }
+}
 
-        let _ = self.tx.send(packet);
+/// Map boxes from a `from` sized frame to a `to` sized one
+///
+/// Capture downscales keeping the aspect ratio, so this is a letterbox without
+/// padding.
+fn scale_detections(
+    mut detections: Vec<Detection>,
+    from: (u32, u32),
+    to: (u32, u32),
+) -> Vec<Detection> {
+    let transform = LetterboxTransform::new(from.0, from.1, to);
+    for det in &mut detections {
+        (det.x1, det.y1) = transform.to_model((det.x1, det.y1));
+        (det.x2, det.y2) = transform.to_model((det.x2, det.y2));
     }
+    detections
 }
 
 /// JPEG encoding quality (0-100)
@@ -350,6 +541,25 @@ mod tests {
         data
     }
 
+    #[test]
+    fn detections_follow_downscaled_frames() {
+        let detections = vec![Detection {
+            x1: 1920.0,
+            y1: 1080.0,
+            x2: 3840.0,
+            y2: 2160.0,
+            confidence: 0.9,
+            class_id: 0,
+        }];
+        let scaled = scale_detections(detections, (3840, 2160), (640, 360));
+
+        let det = &scaled[0];
+        assert_eq!(
+            (det.x1, det.y1, det.x2, det.y2),
+            (320.0, 180.0, 640.0, 360.0)
+        );
+    }
+
     #[test]
     fn rgb_produces_valid_jpeg() {
         let pixels = solid_color_pixels(64, 64, 255, 0, 0); // Red
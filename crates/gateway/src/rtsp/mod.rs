@@ -0,0 +1,238 @@
+//! RTSP re-streaming of the camera as H.264, for NVRs (Frigate, Blue Iris, ...)
+//!
+//! A dedicated frame subscription feeds an encoder thread (OpenH264 by default, or
+//! any `video_encode` backend with `RTSP_ENCODER`), which only encodes while a
+//! client is playing or waiting for the stream description.
+//! Access units are fanned out to the RTSP sessions, which packetize them with
+//! RTP timestamps taken from the capture clock.
+
+pub mod rtp;
+mod server;
+
+use crate::clients::Clients;
+use crate::config::RtspConfig;
+use crate::video_encode::{Encoders, VideoEncoder};
+use bridge::{CapturedAt, FrameReader, FrameSubscription, GateSubscription, paths};
+use common::{Dependency, Readiness, log_throttle};
+use schema::FrameEncoding;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+const NAL_TYPE_SPS: u8 = 7;
+const NAL_TYPE_PPS: u8 = 8;
+
+/// Access units buffered per session before it is considered lagging
+const CHANNEL_CAPACITY: usize = 32;
+
+/// One encoded frame
+pub struct AccessUnit {
+    pub nals: Vec<Vec<u8>>,
+    pub timestamp_ns: u64,
+    pub keyframe: bool,
+}
+
+/// Latest SPS and PPS, announced in the SDP
+#[derive(Clone)]
+pub struct ParameterSets {
+    pub sps: Vec<u8>,
+    pub pps: Vec<u8>,
+}
+
+/// Encoded stream shared between the encoder thread and the RTSP sessions
+pub struct Stream {
+    tx: broadcast::Sender<Arc<AccessUnit>>,
+    parameter_sets: Mutex<Option<ParameterSets>>,
+    keyframe_requested: AtomicBool,
+}
+
+impl Stream {
+    fn new() -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            tx,
+            parameter_sets: Mutex::new(None),
+            keyframe_requested: AtomicBool::new(false),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<AccessUnit>> {
+        self.tx.subscribe()
+    }
+
+    /// Ask the encoder for an IDR frame, which also wakes it up when idle
+    pub fn request_keyframe(&self) {
+        self.keyframe_requested.store(true, Ordering::Relaxed);
+    }
+
+    pub fn parameter_sets(&self) -> Option<ParameterSets> {
+        self.parameter_sets.lock().unwrap().clone()
+    }
+
+    /// Whether anyone is waiting for encoded frames
+    fn wanted(&self) -> bool {
+        self.tx.receiver_count() > 0 || self.keyframe_requested.load(Ordering::Relaxed)
+    }
+
+    fn publish(&self, unit: AccessUnit) {
+        if unit.keyframe {
+            let find = |nal_type| {
+                unit.nals
+                    .iter()
+                    .find(|nal| nal.first().is_some_and(|h| h & 0x1F == nal_type))
+                    .cloned()
+            };
+            if let (Some(sps), Some(pps)) = (find(NAL_TYPE_SPS), find(NAL_TYPE_PPS)) {
+                *self.parameter_sets.lock().unwrap() = Some(ParameterSets { sps, pps });
+            }
+        }
+        let _ = self.tx.send(Arc::new(unit));
+    }
+}
+
+/// Encode frames and serve them over RTSP until the server fails
+pub async fn run(config: RtspConfig, readiness: Readiness, clients: Clients) -> anyhow::Result<()> {
+    // Fail at startup rather than on the first client
+    let encoders = Encoders::new(
+        config.encoder,
+        config.encoder_settings(),
+        config.encoder_paths(),
+    )?;
+
+    let poll_interval = Duration::from_millis(500);
+    let reader = readiness
+        .wait_for_async(
+            Dependency::FrameBuffer,
+            "Frame buffer",
+            poll_interval,
+            FrameReader::build,
+        )
+        .await?;
+    let subscription = readiness
+        .wait_for_async(
+            Dependency::Semaphores,
+            "RTSP frame subscription",
+            poll_interval,
+            || FrameSubscription::register("rtsp"),
+        )
+        .await?;
+
+    let gate = if config.lossless {
+        tracing::info!("Capture waits for every frame to be encoded");
+        Some(GateSubscription::register(
+            paths::FRAME_WRITE_GATE_PATH,
+            "rtsp",
+        )?)
+    } else {
+        None
+    };
+
+    let stream = Arc::new(Stream::new());
+    let encoder_stream = stream.clone();
+    std::thread::Builder::new()
+        .name("rtsp-encoder".to_string())
+        .spawn(move || {
+            if let Err(e) = encode_loop(&encoders, reader, subscription, gate, &encoder_stream) {
+                tracing::error!(error = %e, "RTSP encoder stopped");
+            }
+        })?;
+
+    server::run_server(&config, stream, clients).await
+}
+
+fn encode_loop(
+    encoders: &Encoders,
+    mut reader: FrameReader,
+    subscription: FrameSubscription,
+    gate: Option<GateSubscription>,
+    stream: &Stream,
+) -> anyhow::Result<()> {
+    let mut encoder: Option<Box<dyn VideoEncoder>> = None;
+    // Frame handled by the previous iteration, whichever way it ended
+    let mut consumed = None;
+
+    loop {
+        if let (Some(gate), Some(sequence)) = (&gate, consumed.take()) {
+            gate.acknowledge(sequence);
+        }
+        subscription.wait()?;
+        consumed = Some(reader.current_sequence());
+        if let Some(pid) = reader.writer_changed() {
+            tracing::warn!(pid, "Frame buffer claimed by another writer");
+        }
+        if !stream.wanted() {
+            continue;
+        }
+
+        let Some(frame) = reader.get_frame()? else {
+            continue;
+        };
+        let (width, height) = (frame.width(), frame.height());
+        let Some(payload) = frame.pixels() else {
+            continue;
+        };
+        let decoded;
+        let pixels = match frame.encoding() {
+            FrameEncoding::Lz4 => match bridge::frame_payload(&frame) {
+                Ok((_, pixels)) => {
+                    decoded = pixels.into_owned();
+                    &decoded[..]
+                }
+                Err(e) => {
+                    log_throttle!(warn, error = %e, "Failed to decompress frame for RTSP");
+                    continue;
+                }
+            },
+            FrameEncoding::Jpeg => {
+                match turbojpeg::decompress(payload.bytes(), turbojpeg::PixelFormat::RGB) {
+                    Ok(image) => {
+                        decoded = image.pixels;
+                        &decoded[..]
+                    }
+                    Err(e) => {
+                        log_throttle!(warn, error = %e, "Failed to decode JPEG frame for RTSP");
+                        continue;
+                    }
+                }
+            }
+            _ if frame.channels() == 3 => payload.bytes(),
+            _ => continue,
+        };
+
+        if encoder.as_ref().map(|e| e.size()) != Some((width & !1, height & !1)) {
+            encoder = None;
+            match encoders.open(width, height) {
+                Ok(e) => {
+                    tracing::info!(width, height, backend = %encoders.backend(), "RTSP encoder opened");
+                    encoder = Some(e);
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, "Failed to open RTSP encoder");
+                    continue;
+                }
+            }
+        }
+        let Some(encoder) = encoder.as_mut() else {
+            continue;
+        };
+
+        if stream.keyframe_requested.swap(false, Ordering::Relaxed) {
+            encoder.force_keyframe();
+        }
+
+        let timestamp_ns = frame.captured_at().to_wall_clock().as_nanos();
+        match encoder.encode(pixels, width as usize * 3, timestamp_ns) {
+            Ok(encoded) => {
+                for frame in encoded {
+                    stream.publish(AccessUnit {
+                        nals: frame.nals,
+                        timestamp_ns: frame.timestamp_ns,
+                        keyframe: frame.keyframe,
+                    });
+                }
+            }
+            Err(e) => log_throttle!(warn, error = %e, "Failed to encode frame for RTSP"),
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! RTP packetization of H.264 access units (RFC 6184, packetization mode 1)
+
+/// Dynamic payload type announced in the SDP
+pub const PAYLOAD_TYPE: u8 = 96;
+
+/// H.264 RTP clock rate
+pub const CLOCK_RATE: u64 = 90_000;
+
+/// Largest RTP payload, leaving room for IP/UDP/RTP headers on a 1500-byte MTU
+const MAX_PAYLOAD: usize = 1400;
+
+const HEADER_LEN: usize = 12;
+const NAL_TYPE_FU_A: u8 = 28;
+
+/// Per-session RTP state
+pub struct Packetizer {
+    ssrc: u32,
+    sequence: u16,
+    /// Random offset added to media timestamps
+    timestamp_base: u32,
+}
+
+impl Packetizer {
+    pub fn new(ssrc: u32, sequence: u16, timestamp_base: u32) -> Self {
+        Self {
+            ssrc,
+            sequence,
+            timestamp_base,
+        }
+    }
+
+    /// Sequence number of the next packet
+    pub fn sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    /// RTP timestamp of a frame captured at `timestamp_ns`
+    pub fn timestamp(&self, timestamp_ns: u64) -> u32 {
+        let ticks = (timestamp_ns as u128 * CLOCK_RATE as u128 / 1_000_000_000) as u32;
+        self.timestamp_base.wrapping_add(ticks)
+    }
+
+    /// Packetize one access unit, setting the marker bit on its last packet
+    pub fn packetize(&mut self, nals: &[Vec<u8>], timestamp_ns: u64) -> Vec<Vec<u8>> {
+        let timestamp = self.timestamp(timestamp_ns);
+        let mut packets = Vec::new();
+
+        for (i, nal) in nals.iter().enumerate() {
+            let Some((&header, payload)) = nal.split_first() else {
+                continue;
+            };
+            let last_nal = i == nals.len() - 1;
+
+            if nal.len() <= MAX_PAYLOAD {
+                packets.push(self.packet(timestamp, last_nal, &[nal]));
+                continue;
+            }
+
+            // FU-A: the NAL header is split into indicator and FU header bits
+            let indicator = (header & 0xE0) | NAL_TYPE_FU_A;
+            let chunks: Vec<&[u8]> = payload.chunks(MAX_PAYLOAD - 2).collect();
+            for (j, chunk) in chunks.iter().enumerate() {
+                let start = if j == 0 { 0x80 } else { 0 };
+                let end = if j == chunks.len() - 1 { 0x40 } else { 0 };
+                let fu_header = start | end | (header & 0x1F);
+                packets.push(self.packet(
+                    timestamp,
+                    last_nal && end != 0,
+                    &[&[indicator, fu_header], chunk],
+                ));
+            }
+        }
+
+        packets
+    }
+
+    fn packet(&mut self, timestamp: u32, marker: bool, parts: &[&[u8]]) -> Vec<u8> {
+        let len = HEADER_LEN + parts.iter().map(|p| p.len()).sum::<usize>();
+        let mut packet = Vec::with_capacity(len);
+
+        packet.push(0x80); // version 2, no padding, extension or CSRC
+        packet.push(if marker { 0x80 } else { 0 } | PAYLOAD_TYPE);
+        packet.extend_from_slice(&self.sequence.to_be_bytes());
+        packet.extend_from_slice(&timestamp.to_be_bytes());
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+        for part in parts {
+            packet.extend_from_slice(part);
+        }
+
+        self.sequence = self.sequence.wrapping_add(1);
+        packet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_nals_are_sent_whole() {
+        let mut packetizer = Packetizer::new(0xDEADBEEF, 65535, 0);
+        let packets = packetizer.packetize(&[vec![0x67, 1, 2], vec![0x68, 3], vec![0x65, 4]], 0);
+
+        assert_eq!(packets.len(), 3);
+        assert_eq!(&packets[0][HEADER_LEN..], &[0x67, 1, 2]);
+        assert_eq!(&packets[0][8..12], &0xDEADBEEFu32.to_be_bytes());
+        // Sequence wraps around
+        assert_eq!(&packets[1][2..4], &[0, 0]);
+        // Only the last packet of the access unit carries the marker
+        assert_eq!(packets[0][1], PAYLOAD_TYPE);
+        assert_eq!(packets[2][1], 0x80 | PAYLOAD_TYPE);
+    }
+
+    #[test]
+    fn large_nals_are_fragmented() {
+        let mut nal = vec![0x65];
+        nal.extend((0..3000).map(|i| i as u8));
+        let mut packetizer = Packetizer::new(1, 0, 0);
+        let packets = packetizer.packetize(std::slice::from_ref(&nal), 0);
+
+        assert_eq!(packets.len(), 3);
+        let fu = |p: &Vec<u8>| (p[HEADER_LEN], p[HEADER_LEN + 1]);
+        assert_eq!(fu(&packets[0]), (0x60 | NAL_TYPE_FU_A, 0x80 | 5));
+        assert_eq!(fu(&packets[1]), (0x60 | NAL_TYPE_FU_A, 5));
+        assert_eq!(fu(&packets[2]), (0x60 | NAL_TYPE_FU_A, 0x40 | 5));
+        assert_eq!(packets[2][1], 0x80 | PAYLOAD_TYPE);
+
+        let reassembled: Vec<u8> = packets
+            .iter()
+            .flat_map(|p| p[HEADER_LEN + 2..].iter().copied())
+            .collect();
+        assert_eq!(reassembled, nal[1..]);
+    }
+
+    #[test]
+    fn timestamps_follow_the_capture_clock() {
+        let packetizer = Packetizer::new(1, 0, u32::MAX);
+        assert_eq!(packetizer.timestamp(0), u32::MAX);
+        // 1 second later, wrapping around
+        assert_eq!(packetizer.timestamp(1_000_000_000), 89_999);
+    }
+}
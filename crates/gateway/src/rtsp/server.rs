@@ -0,0 +1,586 @@
+//! Minimal RTSP 1.0 server (RFC 2326) with RTP interleaved over the RTSP connection
+//!
+//! Serves a single H.264 track. Only TCP transport is offered: clients asking for
+//! UDP get `461 Unsupported Transport` and retry over TCP (ffmpeg and Frigate do
+//! this on their own, Blue Iris needs its RTSP transport set to TCP).
+
+use super::rtp::{CLOCK_RATE, PAYLOAD_TYPE, Packetizer};
+use super::{AccessUnit, ParameterSets, Stream};
+use crate::clients::{ClientHandle, ClientKind, Clients};
+use crate::config::RtspConfig;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use common::WallClockNs;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+
+/// Requests larger than this close the connection
+const MAX_REQUEST_LEN: usize = 64 * 1024;
+
+/// Session timeout announced to clients, which keep the session alive with GET_PARAMETER
+const SESSION_TIMEOUT_SECS: u32 = 60;
+
+/// How long DESCRIBE waits for the encoder to produce SPS/PPS
+const DESCRIBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+const PUBLIC_METHODS: &str =
+    "OPTIONS, DESCRIBE, SETUP, PLAY, TEARDOWN, GET_PARAMETER, SET_PARAMETER";
+
+pub async fn run_server(
+    config: &RtspConfig,
+    stream: Arc<Stream>,
+    clients: Clients,
+) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(&config.addr).await?;
+    tracing::info!(
+        "RTSP stream available at rtsp://{}/{}",
+        config.addr,
+        config.path
+    );
+
+    loop {
+        let (socket, peer) = listener.accept().await?;
+        let connection = Connection::new(
+            socket,
+            peer,
+            config.path.clone(),
+            stream.clone(),
+            clients.clone(),
+        );
+        tokio::spawn(async move {
+            if let Err(e) = connection.run().await {
+                tracing::debug!(%peer, error = %e, "RTSP connection closed");
+            }
+        });
+    }
+}
+
+/// A parsed RTSP request
+#[derive(Debug)]
+struct Request {
+    method: String,
+    uri: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug)]
+enum Message {
+    Request(Request),
+    /// RTCP or other data the client interleaves on the connection
+    Interleaved,
+}
+
+/// Parse the message at the start of `buf`, returning it with its length
+///
+/// Returns `Ok(None)` until the whole message has been received.
+fn parse_message(buf: &[u8]) -> anyhow::Result<Option<(Message, usize)>> {
+    if buf.first() == Some(&b'$') {
+        if buf.len() < 4 {
+            return Ok(None);
+        }
+        let len = 4 + u16::from_be_bytes([buf[2], buf[3]]) as usize;
+        return Ok((buf.len() >= len).then_some((Message::Interleaved, len)));
+    }
+
+    let Some(head_len) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+        anyhow::ensure!(buf.len() < MAX_REQUEST_LEN, "RTSP request too large");
+        return Ok(None);
+    };
+    let head = std::str::from_utf8(&buf[..head_len])?;
+    let mut lines = head.split("\r\n");
+
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(uri), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        anyhow::bail!("Malformed RTSP request line {:?}", request_line);
+    };
+    anyhow::ensure!(
+        version.starts_with("RTSP/"),
+        "Not an RTSP request: {:?}",
+        request_line
+    );
+
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let request = Request {
+        method: method.to_string(),
+        uri: uri.to_string(),
+        headers,
+    };
+
+    // Bodies (SET_PARAMETER, ANNOUNCE) are not used, only skipped
+    let body_len: usize = request
+        .header("Content-Length")
+        .and_then(|len| len.parse().ok())
+        .unwrap_or(0);
+    let len = body_len.saturating_add(head_len + 4);
+    anyhow::ensure!(len <= MAX_REQUEST_LEN, "RTSP request too large");
+    Ok((buf.len() >= len).then_some((Message::Request(request), len)))
+}
+
+/// Path of an `rtsp://host[:port]/path` URI, without surrounding slashes
+fn uri_path(uri: &str) -> &str {
+    let without_scheme = uri.split_once("://").map_or(uri, |(_, rest)| rest);
+    without_scheme
+        .split_once('/')
+        .map_or("", |(_, path)| path)
+        .trim_matches('/')
+}
+
+/// Interleaved channel pair requested in a `Transport` header, if TCP is offered
+fn interleaved_channels(transport: &str) -> Option<(u8, u8)> {
+    transport.split(',').find_map(|spec| {
+        let mut params = spec.split(';');
+        if !params.next()?.trim().eq_ignore_ascii_case("RTP/AVP/TCP") {
+            return None;
+        }
+        let channels = params
+            .find_map(|p| p.trim().strip_prefix("interleaved="))
+            .unwrap_or("0-1");
+        match channels.split_once('-') {
+            Some((rtp, rtcp)) => Some((rtp.parse().ok()?, rtcp.parse().ok()?)),
+            None => {
+                let rtp: u8 = channels.parse().ok()?;
+                Some((rtp, rtp.wrapping_add(1)))
+            }
+        }
+    })
+}
+
+/// Session description for the H.264 track
+fn sdp(session_id: u64, local_ip: &str, parameter_sets: &ParameterSets) -> String {
+    let profile_level_id = parameter_sets
+        .sps
+        .get(1..4)
+        .map(|b| format!("{:02X}{:02X}{:02X}", b[0], b[1], b[2]))
+        .unwrap_or_else(|| "42E01F".to_string());
+    let ip_version = if local_ip.contains(':') { "IP6" } else { "IP4" };
+
+    format!(
+        "v=0\r\n\
+         o=- {session_id} 1 IN {ip_version} {local_ip}\r\n\
+         s=detr-mmap\r\n\
+         c=IN {ip_version} {local_ip}\r\n\
+         t=0 0\r\n\
+         a=control:*\r\n\
+         a=range:npt=now-\r\n\
+         m=video 0 RTP/AVP {PAYLOAD_TYPE}\r\n\
+         a=rtpmap:{PAYLOAD_TYPE} H264/{CLOCK_RATE}\r\n\
+         a=fmtp:{PAYLOAD_TYPE} packetization-mode=1;profile-level-id={profile_level_id};sprop-parameter-sets={},{}\r\n\
+         a=control:trackID=0\r\n",
+        BASE64.encode(&parameter_sets.sps),
+        BASE64.encode(&parameter_sets.pps),
+    )
+}
+
+/// Unpredictable value for session ids and RTP sequence and timestamp bases
+fn random_u64() -> u64 {
+    getrandom::u64().expect("OS random number generator unavailable")
+}
+
+struct Response {
+    status: u16,
+    reason: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Option<(&'static str, String)>,
+}
+
+impl Response {
+    fn new(status: u16, reason: &'static str) -> Self {
+        Self {
+            status,
+            reason,
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    fn ok() -> Self {
+        Self::new(200, "OK")
+    }
+
+    fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    fn encode(&self, cseq: &str) -> Vec<u8> {
+        let mut out = format!(
+            "RTSP/1.0 {} {}\r\nCSeq: {}\r\nServer: detr-mmap\r\n",
+            self.status, self.reason, cseq
+        );
+        for (name, value) in &self.headers {
+            out.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        match &self.body {
+            Some((content_type, body)) => out.push_str(&format!(
+                "Content-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+                content_type,
+                body.len(),
+                body
+            )),
+            None => out.push_str("\r\n"),
+        }
+        out.into_bytes()
+    }
+}
+
+/// Playback state once SETUP succeeded
+struct Session {
+    id: String,
+    channel: u8,
+    packetizer: Packetizer,
+    rx: Option<broadcast::Receiver<Arc<AccessUnit>>>,
+    /// Frames are dropped until an IDR frame, so decoding starts cleanly
+    waiting_for_keyframe: bool,
+    /// Registration in the client list while playing
+    client: Option<ClientHandle>,
+}
+
+struct Connection {
+    socket: TcpStream,
+    peer: SocketAddr,
+    path: String,
+    stream: Arc<Stream>,
+    clients: Clients,
+    session: Option<Session>,
+}
+
+impl Connection {
+    fn new(
+        socket: TcpStream,
+        peer: SocketAddr,
+        path: String,
+        stream: Arc<Stream>,
+        clients: Clients,
+    ) -> Self {
+        Self {
+            socket,
+            peer,
+            path,
+            stream,
+            clients,
+            session: None,
+        }
+    }
+
+    async fn run(mut self) -> anyhow::Result<()> {
+        let mut buf = Vec::with_capacity(4096);
+        let mut chunk = [0u8; 4096];
+
+        loop {
+            let playing = self.session.as_ref().is_some_and(|s| s.rx.is_some());
+            let kicked = self
+                .session
+                .as_ref()
+                .and_then(|s| s.client.as_ref())
+                .map(ClientHandle::kicked);
+            tokio::select! {
+                read = self.socket.read(&mut chunk) => {
+                    let n = read?;
+                    if n == 0 {
+                        return Ok(());
+                    }
+                    buf.extend_from_slice(&chunk[..n]);
+
+                    while let Some((message, len)) = parse_message(&buf)? {
+                        buf.drain(..len);
+                        let Message::Request(request) = message else {
+                            continue;
+                        };
+                        let cseq = request.header("CSeq").unwrap_or("0").to_string();
+                        let response = self.handle(&request).await;
+                        self.socket.write_all(&response.encode(&cseq)).await?;
+                        if request.method == "TEARDOWN" {
+                            return Ok(());
+                        }
+                    }
+                }
+                unit = recv(&mut self.session), if playing => {
+                    self.send(unit).await?;
+                }
+                _ = async {
+                    match kicked {
+                        Some(kicked) => kicked.await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    tracing::info!(peer = %self.peer, "RTSP client kicked");
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    async fn handle(&mut self, request: &Request) -> Response {
+        let known_path = uri_path(&request.uri)
+            .strip_prefix(self.path.as_str())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+        let session_matches = |session: &Session| {
+            request
+                .header("Session")
+                .and_then(|s| s.split(';').next())
+                .is_some_and(|id| id.trim() == session.id)
+        };
+
+        match request.method.as_str() {
+            "OPTIONS" => Response::ok().header("Public", PUBLIC_METHODS),
+            "GET_PARAMETER" | "SET_PARAMETER" => Response::ok(),
+            _ if !known_path && request.uri != "*" => Response::new(404, "Not Found"),
+            "DESCRIBE" => self.describe(request).await,
+            "SETUP" => {
+                let Some((channel, rtcp_channel)) =
+                    request.header("Transport").and_then(interleaved_channels)
+                else {
+                    return Response::new(461, "Unsupported Transport");
+                };
+                let session = self.session.get_or_insert_with(|| Session {
+                    id: format!("{:016X}", random_u64()),
+                    channel,
+                    packetizer: Packetizer::new(
+                        random_u64() as u32,
+                        random_u64() as u16,
+                        random_u64() as u32,
+                    ),
+                    rx: None,
+                    waiting_for_keyframe: true,
+                    client: None,
+                });
+                session.channel = channel;
+
+                Response::ok()
+                    .header(
+                        "Transport",
+                        format!(
+                            "RTP/AVP/TCP;unicast;interleaved={}-{}",
+                            channel, rtcp_channel
+                        ),
+                    )
+                    .header(
+                        "Session",
+                        format!("{};timeout={}", session.id, SESSION_TIMEOUT_SECS),
+                    )
+            }
+            "PLAY" => {
+                let Some(session) = self.session.as_mut().filter(|s| session_matches(s)) else {
+                    return Response::new(454, "Session Not Found");
+                };
+                if session.rx.is_none() {
+                    session.rx = Some(self.stream.subscribe());
+                    session.waiting_for_keyframe = true;
+                    session.client = Some(self.clients.register(
+                        ClientKind::Rtsp,
+                        self.peer,
+                        &self.path,
+                    ));
+                    self.stream.request_keyframe();
+                    tracing::info!(peer = %self.peer, "RTSP client started playing");
+                }
+
+                let rtptime = session.packetizer.timestamp(WallClockNs::now().as_nanos());
+                Response::ok()
+                    .header("Session", session.id.clone())
+                    .header("Range", "npt=0.000-")
+                    .header(
+                        "RTP-Info",
+                        format!(
+                            "url={}/trackID=0;seq={};rtptime={}",
+                            request.uri.trim_end_matches('/'),
+                            session.packetizer.sequence(),
+                            rtptime
+                        ),
+                    )
+            }
+            "TEARDOWN" => {
+                if self.session.take().is_some() {
+                    tracing::info!(peer = %self.peer, "RTSP client stopped playing");
+                }
+                Response::ok()
+            }
+            _ => Response::new(405, "Method Not Allowed").header("Allow", PUBLIC_METHODS),
+        }
+    }
+
+    async fn describe(&self, request: &Request) -> Response {
+        let deadline = tokio::time::Instant::now() + DESCRIBE_TIMEOUT;
+        let parameter_sets = loop {
+            if let Some(parameter_sets) = self.stream.parameter_sets() {
+                break parameter_sets;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Response::new(503, "Service Unavailable");
+            }
+            // Wakes the encoder up when nobody is playing yet
+            self.stream.request_keyframe();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        };
+
+        let local_ip = self
+            .socket
+            .local_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_else(|_| "0.0.0.0".to_string());
+        let mut response = Response::ok().header(
+            "Content-Base",
+            format!("{}/", request.uri.trim_end_matches('/')),
+        );
+        response.body = Some((
+            "application/sdp",
+            sdp(random_u64() >> 1, &local_ip, &parameter_sets),
+        ));
+        response
+    }
+
+    async fn send(&mut self, unit: Option<Arc<AccessUnit>>) -> anyhow::Result<()> {
+        let Some(session) = self.session.as_mut() else {
+            return Ok(());
+        };
+        let Some(unit) = unit else {
+            // Lagged behind the encoder, resynchronize on the next IDR frame
+            session.waiting_for_keyframe = true;
+            self.stream.request_keyframe();
+            return Ok(());
+        };
+
+        if session.waiting_for_keyframe {
+            if !unit.keyframe {
+                return Ok(());
+            }
+            session.waiting_for_keyframe = false;
+        }
+
+        let mut out = Vec::new();
+        for packet in session.packetizer.packetize(&unit.nals, unit.timestamp_ns) {
+            out.push(b'$');
+            out.push(session.channel);
+            out.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+            out.extend_from_slice(&packet);
+        }
+        self.socket.write_all(&out).await?;
+        if let (Some(client), Some(rx)) = (&session.client, &session.rx) {
+            client.record_sent(out.len(), rx.len());
+        }
+        Ok(())
+    }
+}
+
+/// Next access unit for a playing session, `None` if it lagged behind
+async fn recv(session: &mut Option<Session>) -> Option<Arc<AccessUnit>> {
+    let session = session.as_mut()?;
+    let rx = session.rx.as_mut()?;
+    match rx.recv().await {
+        Ok(unit) => Some(unit),
+        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+            if let Some(client) = &session.client {
+                client.record_dropped(skipped);
+            }
+            None
+        }
+        // The encoder never drops its sender while the server runs
+        Err(broadcast::error::RecvError::Closed) => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(raw: &str) -> Request {
+        match parse_message(raw.as_bytes()).unwrap() {
+            Some((Message::Request(request), len)) => {
+                assert_eq!(len, raw.len());
+                request
+            }
+            other => panic!("expected a request, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_requests_and_interleaved_data() {
+        let setup = request(
+            "SETUP rtsp://cam:8554/stream/trackID=0 RTSP/1.0\r\nCSeq: 3\r\ntransport: RTP/AVP/TCP;unicast;interleaved=2-3\r\n\r\n",
+        );
+        assert_eq!(setup.method, "SETUP");
+        assert_eq!(uri_path(&setup.uri), "stream/trackID=0");
+        assert_eq!(setup.header("CSeq"), Some("3"));
+        assert_eq!(
+            setup.header("Transport").and_then(interleaved_channels),
+            Some((2, 3))
+        );
+
+        // Incomplete messages wait for more data
+        assert!(
+            parse_message(b"OPTIONS * RTSP/1.0\r\nCSeq: 1\r\n")
+                .unwrap()
+                .is_none()
+        );
+        assert!(parse_message(&[b'$', 1, 0, 4, 0]).unwrap().is_none());
+        assert!(matches!(
+            parse_message(&[b'$', 1, 0, 2, 0, 0, b'O']).unwrap(),
+            Some((Message::Interleaved, 6))
+        ));
+
+        assert!(parse_message(b"GET / HTTP/1.1\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn oversized_bodies_are_refused() {
+        let declaring = |len: usize| {
+            format!(
+                "SET_PARAMETER rtsp://cam/stream RTSP/1.0\r\nCSeq: 4\r\nContent-Length: {len}\r\n\r\n"
+            )
+        };
+        // Same number of digits as the lengths below
+        let head = declaring(MAX_REQUEST_LEN).len();
+
+        assert!(parse_message(declaring(MAX_REQUEST_LEN - head).as_bytes()).is_ok());
+        assert!(parse_message(declaring(MAX_REQUEST_LEN).as_bytes()).is_err());
+        assert!(parse_message(declaring(usize::MAX).as_bytes()).is_err());
+    }
+
+    #[test]
+    fn session_ids_differ() {
+        assert_ne!(random_u64(), random_u64());
+    }
+
+    #[test]
+    fn udp_only_transports_are_refused() {
+        assert_eq!(
+            interleaved_channels("RTP/AVP;unicast;client_port=5000-5001"),
+            None
+        );
+        assert_eq!(
+            interleaved_channels("RTP/AVP;unicast;client_port=5000-5001,RTP/AVP/TCP;unicast"),
+            Some((0, 1))
+        );
+    }
+
+    #[test]
+    fn sdp_announces_parameter_sets() {
+        let parameter_sets = ParameterSets {
+            sps: vec![0x67, 0x42, 0xC0, 0x1F, 0xAB],
+            pps: vec![0x68, 0xCE, 0x3C, 0x80],
+        };
+        let sdp = sdp(42, "192.168.1.10", &parameter_sets);
+
+        assert!(sdp.contains("o=- 42 1 IN IP4 192.168.1.10\r\n"));
+        assert!(sdp.contains("a=rtpmap:96 H264/90000\r\n"));
+        assert!(sdp.contains("profile-level-id=42C01F;sprop-parameter-sets=Z0LAH6s=,aM48gA==\r\n"));
+    }
+}
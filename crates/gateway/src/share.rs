@@ -0,0 +1,268 @@
+//! Time-limited public share links
+//!
+//! With `SHARE_LINK_SECRET` set, `POST /api/share` (admin token required) creates
+//! a link to `/share/<token>` giving read-only access to the live stream, or to
+//! one snapshot, for a number of minutes. Opened in a browser a live link shows
+//! the viewer, which streams from the same path; the stream closes when the link
+//! expires. Meant to be the only gateway path exposed to the people a link is
+//! sent to, e.g. through a reverse proxy.
+//!
+//! Tokens carry their target and expiry, signed with HMAC-SHA256: nothing is
+//! stored, so single links cannot be revoked, but rotating the secret revokes them
+//! all. Requests under `/share` are limited per client address.
+
+use crate::config::ShareConfig;
+use crate::state::AppState;
+use crate::ui;
+use crate::ws;
+use axum::{
+    Json,
+    extract::{ConnectInfo, Path, State, WebSocketUpgrade},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use common::WallClockNs;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Window of the per-address request limit
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// What a link gives access to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareTarget {
+    Live,
+    Snapshot(u64),
+}
+
+impl ShareTarget {
+    fn encode(self) -> String {
+        match self {
+            Self::Live => "live".to_string(),
+            Self::Snapshot(frame_number) => format!("snapshot-{frame_number}"),
+        }
+    }
+
+    fn decode(s: &str) -> Option<Self> {
+        match s {
+            "live" => Some(Self::Live),
+            _ => s
+                .strip_prefix("snapshot-")?
+                .parse()
+                .ok()
+                .map(Self::Snapshot),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ShareLinks {
+    config: ShareConfig,
+    /// Start of the current window and requests in it, per client address
+    requests: Arc<Mutex<HashMap<IpAddr, (Instant, u32)>>>,
+}
+
+impl ShareLinks {
+    pub fn new(config: ShareConfig) -> Self {
+        Self {
+            config,
+            requests: Default::default(),
+        }
+    }
+
+    /// Token for `target` valid until `expires_at`, in Unix seconds
+    fn sign(&self, target: ShareTarget, expires_at: u64) -> String {
+        let claims = format!("{}.{}", target.encode(), expires_at);
+        let signature = hex::encode(self.mac(&claims).finalize().into_bytes());
+        format!("{claims}.{signature}")
+    }
+
+    /// Target of `token` if its signature holds and it has not expired at `now`,
+    /// in Unix seconds
+    fn verify(&self, token: &str, now: u64) -> Option<(ShareTarget, u64)> {
+        let (claims, signature) = token.rsplit_once('.')?;
+        self.mac(claims)
+            .verify_slice(&hex::decode(signature).ok()?)
+            .ok()?;
+        let (target, expires_at) = claims.split_once('.')?;
+        let expires_at: u64 = expires_at.parse().ok()?;
+        (now < expires_at).then_some((ShareTarget::decode(target)?, expires_at))
+    }
+
+    fn mac(&self, claims: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.config.secret.as_bytes())
+            .expect("HMAC accepts any key length");
+        mac.update(claims.as_bytes());
+        mac
+    }
+
+    /// Count a request from `ip`, false once it is over the limit for the window
+    fn admit(&self, ip: IpAddr, now: Instant) -> bool {
+        let mut requests = self.requests.lock().unwrap_or_else(|e| e.into_inner());
+        requests.retain(|_, (start, _)| now.duration_since(*start) < RATE_WINDOW);
+        let (_, count) = requests.entry(ip).or_insert((now, 0));
+        *count += 1;
+        *count <= self.config.rate_limit
+    }
+}
+
+/// Body of `POST /api/share`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShareRequest {
+    /// How long the link is valid
+    pub minutes: u64,
+    /// Snapshot shared instead of the live stream
+    pub snapshot: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShareLink {
+    /// Path of the link on the gateway, `/share/<token>`
+    pub path: String,
+    /// Unix time in seconds
+    pub expires_at: u64,
+}
+
+fn unix_seconds() -> u64 {
+    WallClockNs::now().as_nanos() / 1_000_000_000
+}
+
+/// `POST /api/share`
+pub async fn create(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ShareRequest>,
+) -> Result<Json<ShareLink>, (StatusCode, String)> {
+    let links = state.share.as_ref().ok_or((
+        StatusCode::NOT_FOUND,
+        "Share links are disabled, set SHARE_LINK_SECRET".to_string(),
+    ))?;
+    state.tuning.authorize(&headers)?;
+
+    let duration = Duration::from_secs(request.minutes.saturating_mul(60));
+    if duration.is_zero() || duration > links.config.max_duration {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "minutes must be between 1 and {}",
+                links.config.max_duration.as_secs() / 60
+            ),
+        ));
+    }
+    let target = match request.snapshot {
+        Some(frame_number) if state.snapshots.get(frame_number).is_none() => {
+            return Err((StatusCode::NOT_FOUND, "No such snapshot".to_string()));
+        }
+        Some(frame_number) => ShareTarget::Snapshot(frame_number),
+        None => ShareTarget::Live,
+    };
+
+    let expires_at = unix_seconds() + duration.as_secs();
+    tracing::info!(?target, expires_at, "Share link created");
+    Ok(Json(ShareLink {
+        path: format!("/share/{}", links.sign(target, expires_at)),
+        expires_at,
+    }))
+}
+
+/// `GET /share/:token`, the viewer, its stream or the shared snapshot
+pub async fn open(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Path(token): Path<String>,
+    upgrade: Option<WebSocketUpgrade>,
+) -> Response {
+    let Some(links) = &state.share else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !links.admit(addr.ip(), Instant::now()) {
+        return StatusCode::TOO_MANY_REQUESTS.into_response();
+    }
+    let now = unix_seconds();
+    let Some((target, expires_at)) = links.verify(&token, now) else {
+        return (StatusCode::FORBIDDEN, "Link expired or invalid").into_response();
+    };
+
+    match (target, upgrade) {
+        (ShareTarget::Snapshot(frame_number), _) => match state.snapshots.get(frame_number) {
+            Some(jpeg) => (
+                [
+                    (header::CONTENT_TYPE, "image/jpeg"),
+                    (header::CACHE_CONTROL, "no-store"),
+                ],
+                jpeg.to_vec(),
+            )
+                .into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        },
+        (ShareTarget::Live, Some(upgrade)) => {
+            let until = tokio::time::Instant::now() + Duration::from_secs(expires_at - now);
+            upgrade
+                .on_upgrade(move |socket| {
+                    ws::handle_socket(socket, addr, state, None, "/share", Some(until))
+                })
+                .into_response()
+        }
+        (ShareTarget::Live, None) => ui::index().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links(rate_limit: u32) -> ShareLinks {
+        ShareLinks::new(ShareConfig {
+            secret: "0123456789abcdef".to_string(),
+            max_duration: Duration::from_secs(3600),
+            rate_limit,
+        })
+    }
+
+    #[test]
+    fn test_tokens_expire_and_cannot_be_forged() {
+        let links = links(10);
+        let token = links.sign(ShareTarget::Snapshot(42), 1_000);
+
+        assert_eq!(
+            links.verify(&token, 999),
+            Some((ShareTarget::Snapshot(42), 1_000))
+        );
+        assert_eq!(links.verify(&token, 1_000), None);
+
+        // Extending the expiry or changing the target breaks the signature
+        let signature = token.rsplit_once('.').unwrap().1;
+        assert_eq!(links.verify(&format!("live.1000.{signature}"), 0), None);
+        assert_eq!(
+            links.verify(&format!("snapshot-42.9999.{signature}"), 0),
+            None
+        );
+        assert_eq!(links.verify("live.9999", 0), None);
+
+        let other = ShareLinks::new(ShareConfig {
+            secret: "another secret!!".to_string(),
+            ..links.config.clone()
+        });
+        assert_eq!(other.verify(&token, 0), None);
+    }
+
+    #[test]
+    fn test_requests_are_limited_per_address() {
+        let links = links(2);
+        let (a, b): (IpAddr, IpAddr) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+        let start = Instant::now();
+
+        assert!(links.admit(a, start));
+        assert!(links.admit(a, start));
+        assert!(!links.admit(a, start + Duration::from_secs(1)));
+        assert!(links.admit(b, start + Duration::from_secs(1)));
+
+        assert!(links.admit(a, start + RATE_WINDOW));
+    }
+}
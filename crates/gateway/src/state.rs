@@ -1,4 +1,10 @@
-use bridge::Detection;
+use crate::clients::Clients;
+use crate::frame_history::FrameHistory;
+use crate::share::ShareLinks;
+use crate::tuning::Tuning;
+use crate::webhook::Snapshots;
+use bridge::{ControllerStatus, Detection, FrameSignature, SnapshotControl};
+use common::Readiness;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tokio::sync::broadcast;
@@ -11,16 +17,43 @@ pub struct FrameMessage {
     pub height: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub detections: Option<Vec<Detection>>,
+    /// Wall clock bounds of the inference pass that produced `detections`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inference_started_ns: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inference_completed_ns: Option<u64>,
     pub status: String,
+    /// Capture's signature of the frame, when it signs them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<FrameSignature>,
 }
 
+/// Frame broadcast to every WebSocket client
+///
+/// Reference counted, so each subscriber's copy of a packet shares the JPEG and
+/// metadata instead of cloning them.
 #[derive(Clone)]
 pub struct FramePacket {
-    pub metadata: FrameMessage,
-    pub jpeg_data: Vec<u8>,
+    pub metadata: Arc<FrameMessage>,
+    pub jpeg_data: Arc<[u8]>,
 }
 
 #[derive(Clone)]
 pub struct AppState {
     pub tx: Arc<broadcast::Sender<FramePacket>>,
+    pub readiness: Readiness,
+    /// `DEVICE_ID`, identifying this camera in events
+    pub device_id: Arc<str>,
+    pub clients: Clients,
+    pub snapshots: Snapshots,
+    pub tuning: Tuning,
+    /// `None` when the controller status could not be opened
+    pub controller: Option<Arc<ControllerStatus>>,
+    /// Full resolution still requests, `None` when the snapshot control could not
+    /// be opened
+    pub stills: Option<Arc<SnapshotControl>>,
+    /// Recent frames to scrub through, disabled when unset
+    pub frame_history: Option<FrameHistory>,
+    /// Time-limited public links, disabled when unset
+    pub share: Option<ShareLinks>,
 }
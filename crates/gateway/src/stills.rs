@@ -0,0 +1,143 @@
+//! Full resolution stills
+//!
+//! Frames reaching the gateway may be downscaled by capture (`FRAME_MAX_DIMENSION`).
+//! Clients holding `GATEWAY_ADMIN_TOKEN` ask for a still with `POST /api/stills`:
+//! capture writes its next frame at the camera's native resolution to the
+//! snapshot buffer, and `GET /api/stills/latest` serves the latest one as JPEG.
+//! The controller's `request_still` command goes through the same buffer.
+//! Signed stills carry capture's signature in `X-Frame-Signature*` headers, see
+//! `frame_history`.
+
+use crate::frame_history::add_signature_headers;
+use crate::polling::pixels_to_jpeg;
+use crate::state::AppState;
+use anyhow::Context;
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use bridge::{FrameReader, FrameSignature, SnapshotControl, paths};
+use schema::FrameEncoding;
+use serde::Serialize;
+
+/// Body of `POST /api/stills`
+#[derive(Debug, Serialize)]
+pub struct StillRequested {
+    /// Request number, `X-Still-Request` of the still fulfilling it
+    pub request: u64,
+}
+
+type ApiResult<T> = Result<T, (StatusCode, String)>;
+
+fn control(state: &AppState) -> ApiResult<&SnapshotControl> {
+    state.stills.as_deref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Still requests unavailable".to_string(),
+    ))
+}
+
+/// `POST /api/stills`
+pub async fn request(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> ApiResult<(StatusCode, Json<StillRequested>)> {
+    state.tuning.authorize(&headers)?;
+    let request = control(&state)?.request_still();
+    tracing::info!(request, "Full resolution still requested");
+    Ok((StatusCode::ACCEPTED, Json(StillRequested { request })))
+}
+
+/// `GET /api/stills/latest`
+pub async fn latest(State(state): State<AppState>) -> ApiResult<Response> {
+    let Some((request, _)) = control(&state)?.still_fulfilled() else {
+        return Err((StatusCode::NOT_FOUND, "No still taken yet".to_string()));
+    };
+    let Still {
+        jpeg,
+        frame_number,
+        signature,
+    } = tokio::task::spawn_blocking(|| read_still(paths::SNAPSHOT_BUFFER_PATH))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")))?;
+
+    let mut response = (
+        [
+            (header::CONTENT_TYPE, "image/jpeg".to_string()),
+            (
+                header::HeaderName::from_static("x-still-request"),
+                request.to_string(),
+            ),
+            (
+                header::HeaderName::from_static("x-frame-number"),
+                frame_number.to_string(),
+            ),
+        ],
+        jpeg,
+    )
+        .into_response();
+    if let Some(signature) = &signature {
+        add_signature_headers(&mut response, signature);
+    }
+    Ok(response)
+}
+
+#[derive(Debug, PartialEq)]
+struct Still {
+    jpeg: Vec<u8>,
+    frame_number: u64,
+    signature: Option<FrameSignature>,
+}
+
+/// The still in the snapshot buffer at `path`
+fn read_still(path: &str) -> anyhow::Result<Still> {
+    let reader = FrameReader::with_path(path).context("Failed to open the snapshot buffer")?;
+    let frame = reader
+        .get_frame()?
+        .context("The snapshot buffer holds no still")?;
+    let (encoding, payload) = bridge::frame_payload(&frame)?;
+    let jpeg = match encoding {
+        FrameEncoding::Jpeg => payload.into_owned(),
+        _ => pixels_to_jpeg(&payload, frame.width(), frame.height())?,
+    };
+    Ok(Still {
+        jpeg,
+        frame_number: frame.frame_number(),
+        signature: FrameSignature::from_frame(&frame)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bridge::FrameWriter;
+
+    #[test]
+    fn test_reads_the_latest_still() {
+        let path = &format!("{}/test_gateway_snapshot_buffer", paths::SHM_DIR);
+        let _ = std::fs::remove_file(path);
+        let mut writer = FrameWriter::build_with_path(path, 64 * 1024).unwrap();
+
+        let jpeg = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10];
+        writer.set_next_encoding(FrameEncoding::Jpeg);
+        writer.write_frame(0, &jpeg, 42, 3840, 2160, None).unwrap();
+
+        let still = read_still(path).unwrap();
+        assert_eq!((still.jpeg, still.frame_number), (jpeg.to_vec(), 42));
+        assert!(still.signature.is_none());
+
+        // The passthrough JPEG served is the signed payload
+        let signer = bridge::FrameSigner::new("door", vec![5; 32]).unwrap();
+        writer.set_signer(Some(signer.clone()));
+        writer.set_next_encoding(FrameEncoding::Jpeg);
+        writer.write_frame(0, &jpeg, 43, 3840, 2160, None).unwrap();
+        let still = read_still(path).unwrap();
+        signer
+            .verify(&still.signature.unwrap(), Some(&still.jpeg))
+            .unwrap();
+
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,291 @@
+//! Live threshold tuning
+//!
+//! `GET /api/thresholds` reports the confidence thresholds inference and the
+//! controller apply, and any override set at runtime. Clients holding
+//! `GATEWAY_ADMIN_TOKEN` change them with `PUT /api/thresholds` (a JSON object of
+//! the thresholds to override) and return to the configured values with
+//! `DELETE /api/thresholds`. Overrides go through the bridge threshold control and
+//! take effect on the services' next frame.
+//!
+//! Before applying a value, `GET /api/thresholds/preview?confidence=<value>` tells
+//! how many of the detections broadcast over the last minute it would have kept.
+//! Inference never emits detections under its current threshold, so a preview of a
+//! lower value counts those it did emit only.
+
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode, header},
+};
+use bridge::{Detection, Threshold, ThresholdControl};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Detections kept for previews
+const HISTORY_WINDOW: Duration = Duration::from_secs(60);
+/// Bound on the history, about 30 detections per frame at 30 fps
+const HISTORY_CAPACITY: usize = 60 * 30 * 30;
+
+/// Confidences of the detections broadcast over the last minute
+#[derive(Clone, Default)]
+pub struct DetectionHistory {
+    confidences: Arc<Mutex<VecDeque<(Instant, f32)>>>,
+}
+
+impl DetectionHistory {
+    pub fn record(&self, detections: &[Detection]) {
+        self.record_at(Instant::now(), detections);
+    }
+
+    fn record_at(&self, now: Instant, detections: &[Detection]) {
+        let mut confidences = self.confidences.lock().unwrap_or_else(|e| e.into_inner());
+        confidences.extend(detections.iter().map(|det| (now, det.confidence)));
+        prune(&mut confidences, now);
+    }
+
+    /// Detections in the window, and how many reach `threshold`
+    fn preview_at(&self, now: Instant, threshold: f32) -> (usize, usize) {
+        let mut confidences = self.confidences.lock().unwrap_or_else(|e| e.into_inner());
+        prune(&mut confidences, now);
+        let kept = confidences
+            .iter()
+            .filter(|(_, confidence)| *confidence >= threshold)
+            .count();
+        (confidences.len(), kept)
+    }
+}
+
+fn prune(confidences: &mut VecDeque<(Instant, f32)>, now: Instant) {
+    while confidences
+        .front()
+        .is_some_and(|(at, _)| now.duration_since(*at) > HISTORY_WINDOW)
+    {
+        confidences.pop_front();
+    }
+    let excess = confidences.len().saturating_sub(HISTORY_CAPACITY);
+    confidences.drain(..excess);
+}
+
+/// Shared state of the tuning endpoints
+#[derive(Clone, Default)]
+pub struct Tuning {
+    /// `None` when the threshold control could not be opened
+    pub control: Option<Arc<ThresholdControl>>,
+    pub history: DetectionHistory,
+    /// Bearer token required to change thresholds, changes are refused without one
+    pub admin_token: Option<String>,
+}
+
+impl Tuning {
+    /// Check the request carries the admin token, needed for any change
+    pub(crate) fn authorize(&self, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+        let Some(token) = &self.admin_token else {
+            return Err((
+                StatusCode::FORBIDDEN,
+                "Changes are disabled, set GATEWAY_ADMIN_TOKEN".to_string(),
+            ));
+        };
+        let presented = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match presented {
+            Some(presented) if constant_time_eq(presented.as_bytes(), token.as_bytes()) => Ok(()),
+            _ => Err((StatusCode::UNAUTHORIZED, "Invalid admin token".to_string())),
+        }
+    }
+
+    fn control(&self) -> Result<&ThresholdControl, (StatusCode, String)> {
+        self.control.as_deref().ok_or((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Threshold control unavailable".to_string(),
+        ))
+    }
+}
+
+/// A threshold as reported by `GET /api/thresholds`
+#[derive(Debug, Serialize)]
+pub struct ThresholdState {
+    /// Value the owning service applies, `None` until it has started
+    pub effective: Option<f32>,
+    /// Runtime override, `None` when the configured value applies
+    #[serde(rename = "override")]
+    pub override_value: Option<f32>,
+}
+
+/// Body of `PUT /api/thresholds`, thresholds left out keep their current value
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThresholdUpdate {
+    pub confidence: Option<f32>,
+    pub alert_confidence: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewQuery {
+    pub confidence: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Preview {
+    pub window_secs: u64,
+    /// Detections broadcast in the window
+    pub detections: usize,
+    /// Of which at or above the previewed confidence
+    pub kept: usize,
+}
+
+type ApiResult<T> = Result<Json<T>, (StatusCode, String)>;
+
+/// `GET /api/thresholds`
+pub async fn get(
+    State(state): State<AppState>,
+) -> ApiResult<BTreeMap<&'static str, ThresholdState>> {
+    let control = state.tuning.control()?;
+    Ok(Json(thresholds(control)))
+}
+
+/// `PUT /api/thresholds`
+pub async fn put(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(update): Json<ThresholdUpdate>,
+) -> ApiResult<BTreeMap<&'static str, ThresholdState>> {
+    state.tuning.authorize(&headers)?;
+    let control = state.tuning.control()?;
+
+    let changes = [
+        (Threshold::Confidence, update.confidence),
+        (Threshold::AlertConfidence, update.alert_confidence),
+    ];
+    for (threshold, value) in changes {
+        if let Some(value) = value.filter(|v| !(0.0..=1.0).contains(v)) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("{} must be within 0..=1, got {}", threshold.name(), value),
+            ));
+        }
+    }
+    for (threshold, value) in changes {
+        if let Some(value) = value {
+            tracing::info!(
+                threshold = threshold.name(),
+                value,
+                "Threshold override set"
+            );
+            control.request(threshold, Some(value));
+        }
+    }
+    Ok(Json(thresholds(control)))
+}
+
+/// `DELETE /api/thresholds`
+pub async fn clear(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> ApiResult<BTreeMap<&'static str, ThresholdState>> {
+    state.tuning.authorize(&headers)?;
+    let control = state.tuning.control()?;
+    for threshold in Threshold::ALL {
+        control.request(threshold, None);
+    }
+    tracing::info!("Threshold overrides cleared");
+    Ok(Json(thresholds(control)))
+}
+
+/// `GET /api/thresholds/preview?confidence=<value>`
+pub async fn preview(
+    State(state): State<AppState>,
+    Query(query): Query<PreviewQuery>,
+) -> Json<Preview> {
+    let (detections, kept) = state
+        .tuning
+        .history
+        .preview_at(Instant::now(), query.confidence);
+    Json(Preview {
+        window_secs: HISTORY_WINDOW.as_secs(),
+        detections,
+        kept,
+    })
+}
+
+fn thresholds(control: &ThresholdControl) -> BTreeMap<&'static str, ThresholdState> {
+    Threshold::ALL
+        .into_iter()
+        .map(|threshold| {
+            let state = ThresholdState {
+                effective: control.effective(threshold),
+                override_value: control.requested(threshold),
+            };
+            (threshold.name(), state)
+        })
+        .collect()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn det(confidence: f32) -> Detection {
+        Detection {
+            x1: 0.0,
+            y1: 0.0,
+            x2: 1.0,
+            y2: 1.0,
+            confidence,
+            class_id: 0,
+        }
+    }
+
+    #[test]
+    fn test_preview_counts_last_minute() {
+        let history = DetectionHistory::default();
+        let start = Instant::now();
+
+        history.record_at(start, &[det(0.9), det(0.4)]);
+        history.record_at(start + Duration::from_secs(30), &[det(0.6)]);
+        assert_eq!(
+            history.preview_at(start + Duration::from_secs(31), 0.5),
+            (3, 2)
+        );
+
+        // The first batch has left the window
+        assert_eq!(
+            history.preview_at(start + Duration::from_secs(61), 0.5),
+            (1, 1)
+        );
+    }
+
+    #[test]
+    fn test_changes_need_the_admin_token() {
+        let mut tuning = Tuning::default();
+        let mut headers = HeaderMap::new();
+
+        let denied = tuning.authorize(&headers).unwrap_err();
+        assert_eq!(denied.0, StatusCode::FORBIDDEN);
+
+        tuning.admin_token = Some("s3cret".to_string());
+        assert_eq!(
+            tuning.authorize(&headers).unwrap_err().0,
+            StatusCode::UNAUTHORIZED
+        );
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer nope"),
+        );
+        assert!(tuning.authorize(&headers).is_err());
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer s3cret"),
+        );
+        assert!(tuning.authorize(&headers).is_ok());
+    }
+}
@@ -0,0 +1,57 @@
+//! Single-page web UI embedded in the gateway binary
+//!
+//! Everything under `ui/` is compiled in, so the gateway serves a working viewer
+//! at `/` without a separate web server or a checkout of the repository.
+
+use axum::{
+    http::{StatusCode, Uri, header},
+    response::{IntoResponse, Response},
+};
+use rust_embed::RustEmbed;
+
+#[derive(RustEmbed)]
+#[folder = "ui/"]
+struct Assets;
+
+pub async fn index() -> Response {
+    serve("index.html")
+}
+
+/// Serve any other embedded file by path, 404 otherwise
+pub async fn asset(uri: Uri) -> Response {
+    serve(uri.path().trim_start_matches('/'))
+}
+
+fn serve(path: &str) -> Response {
+    match Assets::get(path) {
+        Some(file) => (
+            [(header::CONTENT_TYPE, file.metadata.mimetype().to_string())],
+            file.data,
+        )
+            .into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_index_is_html() {
+        let response = index().await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            response.headers()[header::CONTENT_TYPE]
+                .to_str()
+                .unwrap()
+                .starts_with("text/html")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_asset_is_not_found() {
+        let response = asset(Uri::from_static("/missing.js")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,395 @@
+//! Encoding in an `ffmpeg` child process
+//!
+//! Raw RGB frames are written to the process' stdin and it writes an Annex B
+//! elementary stream to its stdout, with an access unit delimiter in front of
+//! every frame and the parameter sets repeated on keyframes. A reader thread
+//! splits the stream back into frames. The delimiter of frame N+1 is what ends
+//! frame N, so frames come out one encode call late at best.
+//!
+//! ffmpeg cannot be asked for a keyframe mid-stream, [`VideoEncoder::force_keyframe`]
+//! restarts the process instead: its first frame is always one. Keep
+//! `keyframe_interval` short rather than relying on it.
+
+use super::{Backend, Codec, EncodedFrame, EncoderPaths, EncoderSettings, Latency, VideoEncoder};
+use anyhow::{Context, Result, bail};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{Receiver, TryRecvError, channel};
+
+/// Frames queued in the encoder before new ones are refused
+const MAX_IN_FLIGHT: usize = 8;
+
+const H264_NAL_IDR: u8 = 5;
+const H264_NAL_AUD: u8 = 9;
+const H265_NAL_IRAP: std::ops::RangeInclusive<u8> = 16..=23;
+const H265_NAL_AUD: u8 = 35;
+
+pub struct FfmpegEncoder {
+    command: Vec<String>,
+    program: String,
+    codec: Codec,
+    width: u32,
+    height: u32,
+    process: Option<Process>,
+    /// Tightly packed copy of the frame, as ffmpeg reads it
+    frame: Vec<u8>,
+}
+
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    /// Access units split by the reader thread, without their timestamp
+    units: Receiver<(Vec<Vec<u8>>, bool)>,
+    /// Timestamps of the frames written and not out yet
+    in_flight: VecDeque<u64>,
+}
+
+impl FfmpegEncoder {
+    /// Start `encoder` (e.g. `h264_vaapi`) of `backend` for `width` x `height`
+    /// frames (rounded down to even sizes)
+    pub fn spawn(
+        paths: &EncoderPaths,
+        backend: Backend,
+        encoder: &str,
+        width: u32,
+        height: u32,
+        settings: &EncoderSettings,
+    ) -> Result<Self> {
+        let (width, height) = (width & !1, height & !1);
+        if width == 0 || height == 0 {
+            bail!("Cannot encode {}x{} frames", width, height);
+        }
+
+        let mut this = Self {
+            command: arguments(paths, backend, encoder, width, height, settings),
+            program: paths.ffmpeg.clone(),
+            codec: settings.codec,
+            width,
+            height,
+            process: None,
+            frame: Vec::with_capacity((width * height * 3) as usize),
+        };
+        this.process = Some(this.start()?);
+        Ok(this)
+    }
+
+    fn start(&self) -> Result<Process> {
+        let mut child = Command::new(&self.program)
+            .args(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to start {}", self.program))?;
+        let stdin = child.stdin.take().context("ffmpeg stdin not piped")?;
+        let mut stdout = child.stdout.take().context("ffmpeg stdout not piped")?;
+
+        let (tx, units) = channel();
+        let codec = self.codec;
+        std::thread::Builder::new()
+            .name("ffmpeg-reader".to_string())
+            .spawn(move || {
+                let mut splitter = AccessUnits::new(codec);
+                let mut buf = vec![0; 64 * 1024];
+                // Ends when ffmpeg exits or the encoder is dropped
+                while let Ok(n @ 1..) = stdout.read(&mut buf) {
+                    for unit in splitter.push(&buf[..n]) {
+                        if tx.send(unit).is_err() {
+                            return;
+                        }
+                    }
+                }
+            })
+            .context("Failed to spawn ffmpeg reader thread")?;
+
+        Ok(Process {
+            child,
+            stdin,
+            units,
+            in_flight: VecDeque::new(),
+        })
+    }
+}
+
+impl VideoEncoder for FfmpegEncoder {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn force_keyframe(&mut self) {
+        self.process = None;
+    }
+
+    fn encode(
+        &mut self,
+        rgb: &[u8],
+        stride: usize,
+        timestamp_ns: u64,
+    ) -> Result<Vec<EncodedFrame>> {
+        if self.process.is_none() {
+            self.process = Some(self.start()?);
+        }
+        let process = self.process.as_mut().expect("started above");
+
+        let mut frames = Vec::new();
+        loop {
+            match process.units.try_recv() {
+                Ok((nals, keyframe)) => frames.push(EncodedFrame {
+                    nals,
+                    keyframe,
+                    timestamp_ns: process.in_flight.pop_front().unwrap_or(timestamp_ns),
+                }),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.process = None;
+                    bail!("ffmpeg exited");
+                }
+            }
+        }
+        if process.in_flight.len() >= MAX_IN_FLIGHT {
+            // The encoder is behind, drop the frame rather than block the caller
+            return Ok(frames);
+        }
+
+        let row = self.width as usize * 3;
+        self.frame.clear();
+        for y in 0..self.height as usize {
+            self.frame
+                .extend_from_slice(&rgb[y * stride..y * stride + row]);
+        }
+        if let Err(e) = process.stdin.write_all(&self.frame) {
+            self.process = None;
+            return Err(e).context("Failed to write frame to ffmpeg");
+        }
+        process.in_flight.push_back(timestamp_ns);
+        Ok(frames)
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Command line reading `width` x `height` RGB frames on stdin and writing the
+/// stream on stdout
+fn arguments(
+    paths: &EncoderPaths,
+    backend: Backend,
+    encoder: &str,
+    width: u32,
+    height: u32,
+    settings: &EncoderSettings,
+) -> Vec<String> {
+    let mut args: Vec<String> = ["-hide_banner", "-loglevel", "error", "-nostdin"]
+        .map(String::from)
+        .to_vec();
+    if backend == Backend::Vaapi {
+        args.extend(["-vaapi_device".to_string(), paths.vaapi_device.clone()]);
+    }
+    args.extend(
+        [
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            "rgb24",
+            "-video_size",
+            &format!("{width}x{height}"),
+            "-framerate",
+            "30",
+            "-i",
+            "-",
+        ]
+        .map(String::from),
+    );
+    // VAAPI encodes surfaces uploaded to the device, V4L2 M2M encoders mostly only
+    // take NV12
+    match backend {
+        Backend::Vaapi => args.extend(["-vf", "format=nv12,hwupload"].map(String::from)),
+        Backend::V4l2M2m => args.extend(["-pix_fmt", "nv12"].map(String::from)),
+        _ => args.extend(["-pix_fmt", "yuv420p"].map(String::from)),
+    }
+    args.extend([
+        "-c:v".to_string(),
+        encoder.to_string(),
+        "-b:v".to_string(),
+        format!("{}k", settings.bitrate_kbps),
+        "-g".to_string(),
+        settings.keyframe_interval.max(1).to_string(),
+    ]);
+    if settings.latency == Latency::Low {
+        args.extend(["-bf", "0"].map(String::from));
+        match backend {
+            Backend::X264 => {
+                args.extend(["-preset", "ultrafast", "-tune", "zerolatency"].map(String::from))
+            }
+            Backend::Nvenc => {
+                args.extend(["-preset", "p1", "-tune", "ll", "-delay", "0"].map(String::from))
+            }
+            _ => {}
+        }
+    }
+    let (filter, format) = match settings.codec {
+        Codec::H264 => ("h264_metadata=aud=insert,dump_extra", "h264"),
+        Codec::H265 => ("hevc_metadata=aud=insert,dump_extra", "hevc"),
+    };
+    args.extend(
+        [
+            "-bsf:v",
+            filter,
+            "-fps_mode",
+            "passthrough",
+            "-flush_packets",
+            "1",
+            "-f",
+            format,
+            "-",
+        ]
+        .map(String::from),
+    );
+    args
+}
+
+/// Splits an Annex B stream into access units at their delimiters
+struct AccessUnits {
+    codec: Codec,
+    buffer: Vec<u8>,
+    nals: Vec<Vec<u8>>,
+    keyframe: bool,
+}
+
+impl AccessUnits {
+    fn new(codec: Codec) -> Self {
+        Self {
+            codec,
+            buffer: Vec::new(),
+            nals: Vec::new(),
+            keyframe: false,
+        }
+    }
+
+    /// Feed stream bytes, returning the access units they completed
+    fn push(&mut self, bytes: &[u8]) -> Vec<(Vec<Vec<u8>>, bool)> {
+        self.buffer.extend_from_slice(bytes);
+        let starts = start_codes(&self.buffer);
+
+        let mut units = Vec::new();
+        // The last NAL unit may not be complete yet, it stays in the buffer
+        for pair in starts.windows(2) {
+            let (start, end) = (pair[0].1, pair[1].0);
+            let mut nal = &self.buffer[start..end];
+            while let [rest @ .., 0] = nal {
+                nal = rest;
+            }
+            let Some(&header) = nal.first() else {
+                continue;
+            };
+            let (delimiter, keyframe) = match self.codec {
+                Codec::H264 => {
+                    let t = header & 0x1F;
+                    (t == H264_NAL_AUD, t == H264_NAL_IDR)
+                }
+                Codec::H265 => {
+                    let t = (header >> 1) & 0x3F;
+                    (t == H265_NAL_AUD, H265_NAL_IRAP.contains(&t))
+                }
+            };
+            if delimiter {
+                if !self.nals.is_empty() {
+                    units.push((std::mem::take(&mut self.nals), self.keyframe));
+                }
+                self.keyframe = false;
+                continue;
+            }
+            self.keyframe |= keyframe;
+            self.nals.push(nal.to_vec());
+        }
+        if let Some(&(last, _)) = starts.last() {
+            self.buffer.drain(..last);
+        }
+        units
+    }
+}
+
+/// `(start code offset, NAL unit offset)` of each start code in `stream`
+fn start_codes(stream: &[u8]) -> Vec<(usize, usize)> {
+    let mut starts = Vec::new();
+    let mut i = 0;
+    while i + 3 <= stream.len() {
+        if stream[i..i + 3] == [0, 0, 1] {
+            starts.push((i, i + 3));
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    starts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annex_b(nals: &[&[u8]]) -> Vec<u8> {
+        nals.iter()
+            .flat_map(|nal| [&[0, 0, 0, 1][..], nal].concat())
+            .collect()
+    }
+
+    #[test]
+    fn test_splits_access_units_at_delimiters() {
+        let stream = annex_b(&[
+            &[0x09, 0xF0],
+            &[0x67, 1, 2],
+            &[0x68, 3],
+            &[0x65, 4, 5],
+            &[0x09, 0xF0],
+            &[0x41, 6],
+            &[0x09, 0xF0],
+            // Starts the next frame, which stays buffered
+            &[0x41, 7],
+        ]);
+        let mut units = AccessUnits::new(Codec::H264);
+
+        // Split anywhere, even inside a start code
+        let (head, tail) = stream.split_at(15);
+        let mut out = units.push(head);
+        assert!(out.is_empty());
+        out.extend(units.push(tail));
+
+        assert_eq!(
+            out,
+            [
+                (
+                    vec![vec![0x67, 1, 2], vec![0x68, 3], vec![0x65, 4, 5]],
+                    true
+                ),
+                (vec![vec![0x41, 6]], false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_vaapi_uploads_to_the_device() {
+        let paths = EncoderPaths {
+            ffmpeg: "ffmpeg".to_string(),
+            vaapi_device: "/dev/dri/renderD128".to_string(),
+            openh264_library: String::new(),
+        };
+        let settings = EncoderSettings {
+            codec: Codec::H264,
+            bitrate_kbps: 2000,
+            keyframe_interval: 30,
+            latency: Latency::Low,
+        };
+        let args = arguments(&paths, Backend::Vaapi, "h264_vaapi", 640, 480, &settings).join(" ");
+
+        assert!(args.contains("-vaapi_device /dev/dri/renderD128"));
+        assert!(args.contains("-video_size 640x480"));
+        assert!(args.contains("-vf format=nv12,hwupload -c:v h264_vaapi -b:v 2000k -g 30 -bf 0"));
+        assert!(args.ends_with("-f h264 -"));
+    }
+}
@@ -0,0 +1,375 @@
+//! Video encoding behind one interface, in software or on a hardware encoder
+//!
+//! [`VideoEncoder`] turns RGB frames into H.264 or H.265 access units. Two kinds
+//! of backend implement it:
+//! - OpenH264, loaded at runtime (see `openh264.rs`), the software default
+//! - an `ffmpeg` child process (see `ffmpeg.rs`) driving VAAPI, NVENC, V4L2 M2M or
+//!   libx264/libx265, so hardware encoders need no bindings in the gateway
+//!
+//! [`Capabilities::detect`] looks at what the machine offers (the encoders of the
+//! installed ffmpeg, GPU device nodes, the OpenH264 library) and [`Encoders`]
+//! picks the first usable backend of [`Backend::PREFERENCE`] when none is forced,
+//! falling back to software.
+
+mod ffmpeg;
+mod openh264;
+
+use anyhow::{Context, Result, bail};
+use libloading::Library;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// An encoded frame, as NAL units without start codes
+pub struct EncodedFrame {
+    pub nals: Vec<Vec<u8>>,
+    pub keyframe: bool,
+    /// Timestamp the frame was given to the encoder with
+    pub timestamp_ns: u64,
+}
+
+/// An encoder opened for one frame size
+pub trait VideoEncoder: Send {
+    /// Frame size the encoder was opened for, the requested one rounded down to
+    /// even sizes
+    fn size(&self) -> (u32, u32);
+
+    /// Make the next frame a keyframe carrying the parameter sets
+    fn force_keyframe(&mut self);
+
+    /// Encode an RGB frame of at least the encoder's size, returning the frames
+    /// completed so far: none when rate control skipped it or the encoder is still
+    /// working on it, earlier ones when the encoder is pipelined
+    fn encode(&mut self, rgb: &[u8], stride: usize, timestamp_ns: u64)
+    -> Result<Vec<EncodedFrame>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    H264,
+    H265,
+}
+
+/// Trade-off between encoding delay and quality per bit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Latency {
+    /// No B-frames or lookahead, each frame leaves the encoder as soon as possible
+    Low,
+    /// The encoder's defaults, for recordings
+    Normal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderSettings {
+    pub codec: Codec,
+    pub bitrate_kbps: u32,
+    /// Frames between keyframes
+    pub keyframe_interval: u32,
+    pub latency: Latency,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    OpenH264,
+    /// libx264 or libx265 through ffmpeg
+    X264,
+    Vaapi,
+    Nvenc,
+    V4l2M2m,
+}
+
+impl Backend {
+    /// Order backends are tried in when none is forced: hardware first
+    pub const PREFERENCE: [Backend; 5] = [
+        Self::Nvenc,
+        Self::Vaapi,
+        Self::V4l2M2m,
+        Self::X264,
+        Self::OpenH264,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::OpenH264 => "openh264",
+            Self::X264 => "x264",
+            Self::Vaapi => "vaapi",
+            Self::Nvenc => "nvenc",
+            Self::V4l2M2m => "v4l2m2m",
+        }
+    }
+
+    pub fn hardware(self) -> bool {
+        !matches!(self, Self::OpenH264 | Self::X264)
+    }
+
+    /// ffmpeg encoder of `codec`, `None` for OpenH264 which does not go through
+    /// ffmpeg
+    fn ffmpeg_encoder(self, codec: Codec) -> Option<&'static str> {
+        Some(match (self, codec) {
+            (Self::OpenH264, _) => return None,
+            (Self::X264, Codec::H264) => "libx264",
+            (Self::X264, Codec::H265) => "libx265",
+            (Self::Vaapi, Codec::H264) => "h264_vaapi",
+            (Self::Vaapi, Codec::H265) => "hevc_vaapi",
+            (Self::Nvenc, Codec::H264) => "h264_nvenc",
+            (Self::Nvenc, Codec::H265) => "hevc_nvenc",
+            (Self::V4l2M2m, Codec::H264) => "h264_v4l2m2m",
+            (Self::V4l2M2m, Codec::H265) => "hevc_v4l2m2m",
+        })
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "openh264" => Self::OpenH264,
+            "x264" => Self::X264,
+            "vaapi" => Self::Vaapi,
+            "nvenc" => Self::Nvenc,
+            "v4l2m2m" => Self::V4l2M2m,
+            other => bail!(
+                "Unknown encoder {:?}, expected auto, openh264, x264, vaapi, nvenc or v4l2m2m",
+                other
+            ),
+        })
+    }
+}
+
+/// Where the encoders' external pieces are
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncoderPaths {
+    /// `ffmpeg` executable, looked up in `PATH` without a directory
+    pub ffmpeg: String,
+    /// DRM render node VAAPI encodes on
+    pub vaapi_device: String,
+    pub openh264_library: String,
+}
+
+/// What the machine offers for encoding
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Encoders of the installed ffmpeg, empty without ffmpeg
+    pub ffmpeg_encoders: Vec<String>,
+    pub openh264: bool,
+    pub vaapi_device: bool,
+    pub nvidia_device: bool,
+    /// A V4L2 device node exists, M2M encoders are among them
+    pub video_device: bool,
+}
+
+impl Capabilities {
+    pub fn detect(paths: &EncoderPaths) -> Self {
+        let ffmpeg_encoders = match std::process::Command::new(&paths.ffmpeg)
+            .args(["-hide_banner", "-encoders"])
+            .stdin(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .output()
+        {
+            Ok(output) if output.status.success() => {
+                parse_encoders(&String::from_utf8_lossy(&output.stdout))
+            }
+            Ok(output) => {
+                tracing::debug!(status = %output.status, "ffmpeg failed to list its encoders");
+                Vec::new()
+            }
+            Err(e) => {
+                tracing::debug!(ffmpeg = %paths.ffmpeg, error = %e, "ffmpeg unavailable");
+                Vec::new()
+            }
+        };
+        let video_device = std::fs::read_dir("/dev").is_ok_and(|entries| {
+            entries
+                .flatten()
+                .any(|entry| entry.file_name().to_string_lossy().starts_with("video"))
+        });
+
+        Self {
+            ffmpeg_encoders,
+            openh264: unsafe { Library::new(&paths.openh264_library) }.is_ok(),
+            vaapi_device: Path::new(&paths.vaapi_device).exists(),
+            nvidia_device: Path::new("/dev/nvidiactl").exists(),
+            video_device,
+        }
+    }
+
+    /// Whether `backend` can encode `codec` here
+    pub fn supports(&self, backend: Backend, codec: Codec) -> bool {
+        let device = match backend {
+            Backend::OpenH264 => return self.openh264 && codec == Codec::H264,
+            Backend::X264 => true,
+            Backend::Vaapi => self.vaapi_device,
+            Backend::Nvenc => self.nvidia_device,
+            Backend::V4l2M2m => self.video_device,
+        };
+        device
+            && backend
+                .ffmpeg_encoder(codec)
+                .is_some_and(|name| self.ffmpeg_encoders.iter().any(|e| e == name))
+    }
+
+    /// First backend of [`Backend::PREFERENCE`] able to encode `codec`
+    pub fn best(&self, codec: Codec) -> Option<Backend> {
+        Backend::PREFERENCE
+            .into_iter()
+            .find(|backend| self.supports(*backend, codec))
+    }
+}
+
+/// Names listed by `ffmpeg -encoders`
+///
+/// Each encoder is a line of six capability flags and its name, e.g.
+/// ` V....D h264_vaapi           H.264/AVC (VAAPI) (codec h264)`, after a legend
+/// ending with a ` ------` line.
+fn parse_encoders(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let flags = fields.next()?;
+            (flags.len() == 6 && flags.starts_with('V'))
+                .then(|| fields.next())
+                .flatten()
+                .map(str::to_string)
+        })
+        .collect()
+}
+
+/// Opens encoders of the backend picked at startup, one per frame size
+pub struct Encoders {
+    backend: Backend,
+    settings: EncoderSettings,
+    paths: EncoderPaths,
+    /// Loaded once, kept mapped while encoders use it
+    openh264: Option<Arc<Library>>,
+}
+
+impl Encoders {
+    /// Use `backend`, or the best one available when `None`
+    pub fn new(
+        backend: Option<Backend>,
+        settings: EncoderSettings,
+        paths: EncoderPaths,
+    ) -> Result<Self> {
+        let capabilities = Capabilities::detect(&paths);
+        tracing::debug!(?capabilities, "Encoding capabilities");
+        let backend = match backend {
+            Some(backend) => {
+                if !capabilities.supports(backend, settings.codec) {
+                    // Detection can miss a device in a container, let opening tell
+                    tracing::warn!(%backend, codec = ?settings.codec, "Encoder looks unavailable, trying it anyway");
+                }
+                backend
+            }
+            None => capabilities.best(settings.codec).with_context(|| {
+                format!(
+                    "No {:?} encoder available: install ffmpeg or {}",
+                    settings.codec, paths.openh264_library
+                )
+            })?,
+        };
+
+        let openh264 = match backend {
+            Backend::OpenH264 => {
+                if settings.codec != Codec::H264 {
+                    bail!("OpenH264 only encodes H.264");
+                }
+                Some(Arc::new(
+                    unsafe { Library::new(&paths.openh264_library) }
+                        .with_context(|| format!("Failed to load {}", paths.openh264_library))?,
+                ))
+            }
+            _ => None,
+        };
+        tracing::info!(%backend, hardware = backend.hardware(), "Video encoder selected");
+
+        Ok(Self {
+            backend,
+            settings,
+            paths,
+            openh264,
+        })
+    }
+
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    /// Open an encoder for `width` x `height` frames
+    pub fn open(&self, width: u32, height: u32) -> Result<Box<dyn VideoEncoder>> {
+        match (
+            &self.openh264,
+            self.backend.ffmpeg_encoder(self.settings.codec),
+        ) {
+            (Some(library), _) => Ok(Box::new(openh264::OpenH264Encoder::new(
+                library.clone(),
+                width,
+                height,
+                &self.settings,
+            )?)),
+            (None, Some(encoder)) => Ok(Box::new(ffmpeg::FfmpegEncoder::spawn(
+                &self.paths,
+                self.backend,
+                encoder,
+                width,
+                height,
+                &self.settings,
+            )?)),
+            (None, None) => bail!("{} encoder not loaded", self.backend),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LISTING: &str = "Encoders:
+ V..... = Video
+ A..... = Audio
+ ------
+ V....D libx264              libx264 H.264 / AVC / MPEG-4 AVC (codec h264)
+ V....D h264_vaapi           H.264/AVC (VAAPI) (codec h264)
+ V....D hevc_vaapi           H.265/HEVC (VAAPI) (codec hevc)
+ A....D aac                  AAC (Advanced Audio Coding)
+";
+
+    #[test]
+    fn test_parses_video_encoders() {
+        assert_eq!(
+            parse_encoders(LISTING),
+            ["libx264", "h264_vaapi", "hevc_vaapi"]
+        );
+        assert!(parse_encoders("ffmpeg version 6.1").is_empty());
+    }
+
+    #[test]
+    fn test_prefers_hardware_then_software() {
+        let mut capabilities = Capabilities {
+            ffmpeg_encoders: parse_encoders(LISTING),
+            openh264: true,
+            ..Default::default()
+        };
+        // VAAPI listed but no render node
+        assert_eq!(capabilities.best(Codec::H264), Some(Backend::X264));
+        assert!(!capabilities.supports(Backend::OpenH264, Codec::H265));
+
+        capabilities.vaapi_device = true;
+        assert_eq!(capabilities.best(Codec::H264), Some(Backend::Vaapi));
+        assert_eq!(capabilities.best(Codec::H265), Some(Backend::Vaapi));
+
+        capabilities.ffmpeg_encoders.clear();
+        assert_eq!(capabilities.best(Codec::H264), Some(Backend::OpenH264));
+        assert_eq!(capabilities.best(Codec::H265), None);
+    }
+}
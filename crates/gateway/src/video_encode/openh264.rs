@@ -0,0 +1,311 @@
+//! H.264 encoding through OpenH264, loaded at runtime
+//!
+//! Cisco ships OpenH264 as a binary whose patent license only covers their own
+//! builds, so the library is opened with `dlopen` instead of being linked. The
+//! bindings cover the C interface of `codec_api.h` (OpenH264 2.x).
+
+use super::{EncodedFrame, EncoderSettings, VideoEncoder};
+use anyhow::{Context, Result, bail};
+use libloading::Library;
+use std::ffi::{c_int, c_uchar, c_void};
+
+mod ffi {
+    use super::*;
+
+    pub const CAMERA_VIDEO_REAL_TIME: c_int = 0;
+    pub const RC_BITRATE_MODE: c_int = 1;
+    pub const VIDEO_FORMAT_I420: c_int = 23;
+    pub const ENCODER_OPTION_IDR_INTERVAL: c_int = 1;
+    pub const VIDEO_FRAME_TYPE_IDR: c_int = 1;
+    pub const VIDEO_FRAME_TYPE_SKIP: c_int = 4;
+    pub const MAX_LAYER_NUM_OF_FRAME: usize = 128;
+
+    #[repr(C)]
+    pub struct SEncParamBase {
+        pub usage_type: c_int,
+        pub pic_width: c_int,
+        pub pic_height: c_int,
+        pub target_bitrate: c_int,
+        pub rc_mode: c_int,
+        pub max_frame_rate: f32,
+    }
+
+    #[repr(C)]
+    pub struct SSourcePicture {
+        pub color_format: c_int,
+        pub stride: [c_int; 4],
+        pub data: [*mut c_uchar; 4],
+        pub pic_width: c_int,
+        pub pic_height: c_int,
+        pub timestamp: i64,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    pub struct SLayerBSInfo {
+        pub temporal_id: c_uchar,
+        pub spatial_id: c_uchar,
+        pub quality_id: c_uchar,
+        pub frame_type: c_int,
+        pub layer_type: c_uchar,
+        pub sub_seq_id: c_int,
+        pub nal_count: c_int,
+        pub nal_length_in_byte: *mut c_int,
+        pub bs_buf: *mut c_uchar,
+    }
+
+    #[repr(C)]
+    pub struct SFrameBSInfo {
+        pub layer_num: c_int,
+        pub layer_info: [SLayerBSInfo; MAX_LAYER_NUM_OF_FRAME],
+        pub frame_type: c_int,
+        pub frame_size_in_bytes: c_int,
+        pub timestamp: i64,
+    }
+
+    pub type Encoder = *const EncoderVtbl;
+
+    /// `ISVCEncoderVtbl`, the C view of the `ISVCEncoder` interface
+    #[repr(C)]
+    pub struct EncoderVtbl {
+        pub initialize: unsafe extern "C" fn(*mut Encoder, *const SEncParamBase) -> c_int,
+        pub initialize_ext: unsafe extern "C" fn(*mut Encoder, *const c_void) -> c_int,
+        pub get_default_params: unsafe extern "C" fn(*mut Encoder, *mut c_void) -> c_int,
+        pub uninitialize: unsafe extern "C" fn(*mut Encoder) -> c_int,
+        pub encode_frame:
+            unsafe extern "C" fn(*mut Encoder, *const SSourcePicture, *mut SFrameBSInfo) -> c_int,
+        pub encode_parameter_sets: unsafe extern "C" fn(*mut Encoder, *mut SFrameBSInfo) -> c_int,
+        pub force_intra_frame: unsafe extern "C" fn(*mut Encoder, bool) -> c_int,
+        pub set_option: unsafe extern "C" fn(*mut Encoder, c_int, *mut c_void) -> c_int,
+        pub get_option: unsafe extern "C" fn(*mut Encoder, c_int, *mut c_void) -> c_int,
+    }
+
+    pub type CreateEncoder = unsafe extern "C" fn(*mut *mut Encoder) -> c_int;
+    pub type DestroyEncoder = unsafe extern "C" fn(*mut Encoder);
+}
+
+/// OpenH264 encoder for one frame size
+pub struct OpenH264Encoder {
+    encoder: *mut ffi::Encoder,
+    destroy: ffi::DestroyEncoder,
+    width: u32,
+    height: u32,
+    /// I420 planes, reused across frames
+    yuv: Vec<u8>,
+    /// Keeps the library mapped while the encoder is alive
+    _library: std::sync::Arc<Library>,
+}
+
+// The encoder is only driven from the thread that owns it
+unsafe impl Send for OpenH264Encoder {}
+
+impl OpenH264Encoder {
+    /// Open an encoder for `width` x `height` frames (rounded down to even sizes)
+    pub fn new(
+        library: std::sync::Arc<Library>,
+        width: u32,
+        height: u32,
+        settings: &EncoderSettings,
+    ) -> Result<Self> {
+        let bitrate_kbps = settings.bitrate_kbps;
+        let (width, height) = (width & !1, height & !1);
+        if width == 0 || height == 0 {
+            bail!("Cannot encode {}x{} frames", width, height);
+        }
+
+        let (create, destroy) = unsafe {
+            (
+                *library
+                    .get::<ffi::CreateEncoder>(b"WelsCreateSVCEncoder\0")
+                    .context("OpenH264 library has no WelsCreateSVCEncoder")?,
+                *library
+                    .get::<ffi::DestroyEncoder>(b"WelsDestroySVCEncoder\0")
+                    .context("OpenH264 library has no WelsDestroySVCEncoder")?,
+            )
+        };
+
+        let mut encoder = std::ptr::null_mut();
+        if unsafe { create(&mut encoder) } != 0 || encoder.is_null() {
+            bail!("WelsCreateSVCEncoder failed");
+        }
+        // Destroyed by Drop from here on
+        let this = Self {
+            encoder,
+            destroy,
+            width,
+            height,
+            yuv: vec![0; (width * height * 3 / 2) as usize],
+            _library: library,
+        };
+
+        let params = ffi::SEncParamBase {
+            usage_type: ffi::CAMERA_VIDEO_REAL_TIME,
+            pic_width: width as c_int,
+            pic_height: height as c_int,
+            target_bitrate: (bitrate_kbps * 1000) as c_int,
+            rc_mode: ffi::RC_BITRATE_MODE,
+            max_frame_rate: 30.0,
+        };
+        let mut idr_interval = settings.keyframe_interval.max(1) as c_int;
+        unsafe {
+            if (this.vtbl().initialize)(this.encoder, &params) != 0 {
+                bail!(
+                    "OpenH264 rejected {}x{} at {} kbps",
+                    width,
+                    height,
+                    bitrate_kbps
+                );
+            }
+            (this.vtbl().set_option)(
+                this.encoder,
+                ffi::ENCODER_OPTION_IDR_INTERVAL,
+                &mut idr_interval as *mut c_int as *mut c_void,
+            );
+        }
+
+        Ok(this)
+    }
+
+    fn vtbl(&self) -> &ffi::EncoderVtbl {
+        unsafe { &**self.encoder }
+    }
+}
+
+impl VideoEncoder for OpenH264Encoder {
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Make the next frame an IDR frame carrying SPS and PPS
+    fn force_keyframe(&mut self) {
+        unsafe {
+            (self.vtbl().force_intra_frame)(self.encoder, true);
+        }
+    }
+
+    /// Encodes synchronously, returning nothing when rate control skips the frame
+    fn encode(
+        &mut self,
+        rgb: &[u8],
+        stride: usize,
+        timestamp_ns: u64,
+    ) -> Result<Vec<EncodedFrame>> {
+        rgb_to_i420(rgb, stride, self.width, self.height, &mut self.yuv);
+
+        let (w, h) = (self.width as usize, self.height as usize);
+        let (y, uv) = self.yuv.split_at_mut(w * h);
+        let (u, v) = uv.split_at_mut(w * h / 4);
+        let picture = ffi::SSourcePicture {
+            color_format: ffi::VIDEO_FORMAT_I420,
+            stride: [w as c_int, (w / 2) as c_int, (w / 2) as c_int, 0],
+            data: [
+                y.as_mut_ptr(),
+                u.as_mut_ptr(),
+                v.as_mut_ptr(),
+                std::ptr::null_mut(),
+            ],
+            pic_width: w as c_int,
+            pic_height: h as c_int,
+            timestamp: (timestamp_ns / 1_000_000) as i64,
+        };
+
+        let mut info = std::mem::MaybeUninit::<ffi::SFrameBSInfo>::zeroed();
+        let ret = unsafe { (self.vtbl().encode_frame)(self.encoder, &picture, info.as_mut_ptr()) };
+        if ret != 0 {
+            bail!("OpenH264 EncodeFrame failed ({})", ret);
+        }
+        let info = unsafe { info.assume_init() };
+        if info.frame_type == ffi::VIDEO_FRAME_TYPE_SKIP {
+            return Ok(Vec::new());
+        }
+
+        let mut nals = Vec::new();
+        for layer in &info.layer_info[..info.layer_num as usize] {
+            let lengths = unsafe {
+                std::slice::from_raw_parts(layer.nal_length_in_byte, layer.nal_count as usize)
+            };
+            let mut offset = 0usize;
+            for &len in lengths {
+                let nal =
+                    unsafe { std::slice::from_raw_parts(layer.bs_buf.add(offset), len as usize) };
+                nals.push(strip_start_code(nal).to_vec());
+                offset += len as usize;
+            }
+        }
+
+        Ok(vec![EncodedFrame {
+            nals,
+            keyframe: info.frame_type == ffi::VIDEO_FRAME_TYPE_IDR,
+            timestamp_ns,
+        }])
+    }
+}
+
+impl Drop for OpenH264Encoder {
+    fn drop(&mut self) {
+        unsafe {
+            (self.vtbl().uninitialize)(self.encoder);
+            (self.destroy)(self.encoder);
+        }
+    }
+}
+
+/// NAL unit payload without its Annex B start code
+fn strip_start_code(nal: &[u8]) -> &[u8] {
+    match nal {
+        [0, 0, 0, 1, rest @ ..] | [0, 0, 1, rest @ ..] => rest,
+        _ => nal,
+    }
+}
+
+/// Convert the top-left `width` x `height` RGB pixels to I420 (BT.601, limited range)
+///
+/// `width` and `height` must be even. Chroma is taken from the top-left pixel of
+/// each 2x2 block, which is plenty for a surveillance re-stream.
+pub fn rgb_to_i420(rgb: &[u8], stride: usize, width: u32, height: u32, yuv: &mut [u8]) {
+    let (w, h) = (width as usize, height as usize);
+    let (y_plane, uv) = yuv.split_at_mut(w * h);
+    let (u_plane, v_plane) = uv.split_at_mut(w * h / 4);
+
+    for row in 0..h {
+        let line = &rgb[row * stride..row * stride + w * 3];
+        for (col, px) in line.chunks_exact(3).enumerate() {
+            let (r, g, b) = (px[0] as i32, px[1] as i32, px[2] as i32);
+            y_plane[row * w + col] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+
+            if row % 2 == 0 && col % 2 == 0 {
+                let i = (row / 2) * (w / 2) + col / 2;
+                u_plane[i] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+                v_plane[i] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgb_to_i420_primaries() {
+        // 2x2 white, 2x2 red side by side
+        let rgb = [
+            255, 255, 255, 255, 255, 255, 255, 0, 0, 255, 0, 0, //
+            255, 255, 255, 255, 255, 255, 255, 0, 0, 255, 0, 0,
+        ];
+        let mut yuv = vec![0; 4 * 2 * 3 / 2];
+        rgb_to_i420(&rgb, 12, 4, 2, &mut yuv);
+
+        assert_eq!(&yuv[..4], &[235, 235, 82, 82]);
+        // White has neutral chroma, red is low on U and high on V
+        assert_eq!(&yuv[8..10], &[128, 90]);
+        assert_eq!(&yuv[10..12], &[128, 240]);
+    }
+
+    #[test]
+    fn strips_both_start_code_lengths() {
+        assert_eq!(strip_start_code(&[0, 0, 0, 1, 0x67, 1]), &[0x67, 1]);
+        assert_eq!(strip_start_code(&[0, 0, 1, 0x68]), &[0x68]);
+        assert_eq!(strip_start_code(&[0x65, 2]), &[0x65, 2]);
+    }
+}
@@ -0,0 +1,422 @@
+//! Outbound webhooks on detection activity
+//!
+//! Integrations that cannot hold a WebSocket open (serverless functions, chat
+//! bots) get a JSON `POST` to every `WEBHOOK_URLS` entry instead: by default when
+//! detections appear in the stream and once they have been gone for
+//! `WEBHOOK_CLEAR_BATCHES` batches, or with `WEBHOOK_TRIGGER=batch` on every
+//! `WEBHOOK_BATCH_INTERVAL`th batch holding detections.
+//!
+//! With `WEBHOOK_PUBLIC_URL` set, events link the frame that triggered them,
+//! served from `GET /api/snapshots/:frame_number` for the last few events. With
+//! `WEBHOOK_SECRET` set, bodies are signed with HMAC-SHA256 in
+//! `X-Webhook-Signature: sha256=<hex>`.
+//!
+//! Bodies are `common::Event`s, the shape MQTT notifications use, identified by
+//! `DEVICE_ID`, or `WEBHOOK_BODY_TEMPLATE` rendered over the event, see
+//! `common::template` and [`TEMPLATE_FIELDS`]. Posts run on a background thread,
+//! so a slow endpoint never holds up the stream.
+
+use crate::config::{WebhookConfig, WebhookTrigger};
+use crate::state::{AppState, FramePacket};
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use common::Template;
+use common::event::{DetectionSummary, Event, EventPayload, EventType};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::VecDeque;
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError, sync_channel};
+use std::sync::{Arc, Mutex};
+
+const RETRY_BASE_DELAY_MS: u64 = 500;
+/// Event frames kept for their snapshot URL
+const SNAPSHOT_CAPACITY: usize = 16;
+
+/// Fields `WEBHOOK_BODY_TEMPLATE` can reference; `class` and `confidence` are
+/// those of the most confident detection
+pub const TEMPLATE_FIELDS: &[&str] = &[
+    "device_id",
+    "event_type",
+    "timestamp",
+    "local_time",
+    "frame_number",
+    "count",
+    "class",
+    "confidence",
+    "snapshot_url",
+];
+
+/// Decides which detection batches fire an event
+#[derive(Debug)]
+struct Trigger {
+    trigger: WebhookTrigger,
+    clear_batches: u32,
+    /// Detections are in the stream
+    active: bool,
+    empty_batches: u32,
+    /// Batches with detections seen so far
+    batches: u64,
+}
+
+impl Trigger {
+    fn new(trigger: WebhookTrigger, clear_batches: u32) -> Self {
+        Self {
+            trigger,
+            clear_batches: clear_batches.max(1),
+            active: false,
+            empty_batches: 0,
+            batches: 0,
+        }
+    }
+
+    /// Event fired by a batch of `detections` detections, if any
+    fn observe(&mut self, detections: usize) -> Option<EventType> {
+        match self.trigger {
+            WebhookTrigger::StateChange if detections > 0 => {
+                self.empty_batches = 0;
+                (!std::mem::replace(&mut self.active, true)).then_some(EventType::DetectionsStarted)
+            }
+            WebhookTrigger::StateChange => {
+                if !self.active {
+                    return None;
+                }
+                self.empty_batches += 1;
+                if self.empty_batches < self.clear_batches {
+                    return None;
+                }
+                self.active = false;
+                self.empty_batches = 0;
+                Some(EventType::DetectionsCleared)
+            }
+            WebhookTrigger::Batches { interval } if detections > 0 => {
+                self.batches += 1;
+                (self.batches - 1)
+                    .is_multiple_of(interval)
+                    .then_some(EventType::Detections)
+            }
+            WebhookTrigger::Batches { .. } => None,
+        }
+    }
+}
+
+/// JPEG of an event frame, by frame number
+type Snapshot = (u64, Arc<[u8]>);
+
+/// JPEGs of the latest event and requested frames, served by
+/// `GET /api/snapshots/:frame_number`
+#[derive(Clone, Default)]
+pub struct Snapshots {
+    frames: Arc<Mutex<VecDeque<Snapshot>>>,
+}
+
+impl Snapshots {
+    pub fn insert(&self, frame_number: u64, jpeg: Arc<[u8]>) {
+        let mut frames = self.frames.lock().unwrap_or_else(|e| e.into_inner());
+        if frames.len() == SNAPSHOT_CAPACITY {
+            frames.pop_front();
+        }
+        frames.push_back((frame_number, jpeg));
+    }
+
+    pub fn get(&self, frame_number: u64) -> Option<Arc<[u8]>> {
+        let frames = self.frames.lock().unwrap_or_else(|e| e.into_inner());
+        frames
+            .iter()
+            .rev()
+            .find(|(number, _)| *number == frame_number)
+            .map(|(_, jpeg)| jpeg.clone())
+    }
+}
+
+/// `GET /api/snapshots/:frame_number`
+pub async fn snapshot(State(state): State<AppState>, Path(frame_number): Path<u64>) -> Response {
+    match state.snapshots.get(frame_number) {
+        Some(jpeg) => ([(header::CONTENT_TYPE, "image/jpeg")], jpeg.to_vec()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+pub struct Webhooks {
+    trigger: Trigger,
+    queue: SyncSender<Event>,
+    device_id: String,
+    snapshots: Snapshots,
+    public_url: Option<String>,
+}
+
+impl Webhooks {
+    pub fn new(config: &WebhookConfig, device_id: &str, snapshots: Snapshots) -> Result<Self> {
+        let client = WebhookClient {
+            agent: ureq::AgentBuilder::new().timeout(config.timeout).build(),
+            urls: config.urls.clone(),
+            secret: config.secret.clone(),
+        };
+        let template = config
+            .body_template
+            .as_deref()
+            .map(|template| Template::parse(template, TEMPLATE_FIELDS))
+            .transpose()
+            .context("Invalid WEBHOOK_BODY_TEMPLATE")?;
+        let (queue, events) = sync_channel(config.queue_capacity);
+        let max_retries = config.max_retries;
+
+        std::thread::Builder::new()
+            .name("webhooks".to_string())
+            .spawn(move || run_worker(client, events, template, max_retries))
+            .context("Failed to spawn webhook thread")?;
+
+        tracing::info!(
+            urls = config.urls.len(),
+            trigger = ?config.trigger,
+            "Webhooks enabled"
+        );
+
+        Ok(Self {
+            trigger: Trigger::new(config.trigger, config.clear_batches),
+            queue,
+            device_id: device_id.to_string(),
+            snapshots,
+            public_url: config.public_url.clone(),
+        })
+    }
+
+    /// Queue an event if this broadcast frame triggers one
+    pub fn observe(&mut self, packet: &FramePacket) {
+        // Frames inference skipped are not a detection batch
+        let Some(detections) = packet.metadata.detections.as_ref() else {
+            return;
+        };
+        let Some(event_type) = self.trigger.observe(detections.len()) else {
+            return;
+        };
+
+        let frame_number = packet.metadata.frame_number;
+        let snapshot_url = match &self.public_url {
+            Some(base) if !packet.jpeg_data.is_empty() => {
+                self.snapshots
+                    .insert(frame_number, packet.jpeg_data.clone());
+                Some(format!("{base}/api/snapshots/{frame_number}"))
+            }
+            _ => None,
+        };
+
+        let event = Event::new(
+            &self.device_id,
+            event_type,
+            DetectionSummary {
+                frame_number,
+                timestamp_ns: packet.metadata.timestamp_ns,
+                width: packet.metadata.width,
+                height: packet.metadata.height,
+                detections: detections.iter().map(Into::into).collect(),
+                snapshot_url,
+            },
+        );
+        match self.queue.try_send(event) {
+            Ok(()) => {}
+            Err(TrySendError::Full(event)) => {
+                tracing::warn!(event = %event.event_type, "Webhook queue full, dropping event");
+            }
+            Err(TrySendError::Disconnected(_)) => {
+                tracing::error!("Webhook thread stopped");
+            }
+        }
+    }
+}
+
+struct WebhookClient {
+    agent: ureq::Agent,
+    urls: Vec<String>,
+    secret: Option<String>,
+}
+
+impl WebhookClient {
+    fn post(&self, url: &str, body: &[u8]) -> Result<()> {
+        let mut request = self.agent.post(url).set("Content-Type", "application/json");
+        if let Some(secret) = &self.secret {
+            request = request.set("X-Webhook-Signature", &signature(secret, body));
+        }
+
+        match request.send_bytes(body) {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(code, _)) => anyhow::bail!("{} returned {}", url, code),
+            Err(e) => Err(e).with_context(|| format!("Webhook request to {} failed", url)),
+        }
+    }
+}
+
+fn run_worker(
+    client: WebhookClient,
+    events: Receiver<Event>,
+    template: Option<Template>,
+    max_retries: u32,
+) {
+    for event in events {
+        let body = match &template {
+            Some(template) => template
+                .render(|name| template_field(&event, name))
+                .into_bytes(),
+            None => match event.to_json() {
+                Ok(body) => body,
+                Err(e) => {
+                    tracing::error!(error = %e, "Failed to serialize webhook event");
+                    continue;
+                }
+            },
+        };
+
+        for url in &client.urls {
+            let result = common::retry_with_backoff(
+                || client.post(url, &body),
+                max_retries,
+                RETRY_BASE_DELAY_MS,
+                "Webhook post",
+            );
+            match result {
+                Ok(()) => tracing::debug!(url, event = %event.event_type, "Webhook delivered"),
+                Err(e) => tracing::error!(url, error = %e, "Dropping webhook event"),
+            }
+        }
+    }
+}
+
+/// Value of the template field `name` for `event`
+fn template_field(event: &Event, name: &str) -> Option<String> {
+    let summary = match &event.payload {
+        EventPayload::Detections(summary) => Some(summary),
+        _ => None,
+    };
+    let top = summary.and_then(|summary| {
+        summary
+            .detections
+            .iter()
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+    });
+    match name {
+        "device_id" => Some(event.device_id.clone()),
+        "event_type" => Some(event.event_type.to_string()),
+        "timestamp" => Some(event.timestamp.clone()),
+        "local_time" => common::template::local_time(&event.timestamp),
+        "frame_number" => summary.map(|summary| summary.frame_number.to_string()),
+        "count" => summary.map(|summary| summary.detections.len().to_string()),
+        "class" => top.map(|d| {
+            common::classes::class_name(d.class_id)
+                .map_or_else(|| d.class_id.to_string(), String::from)
+        }),
+        "confidence" => top.map(|d| format!("{:.2}", d.confidence)),
+        "snapshot_url" => summary.and_then(|summary| summary.snapshot_url.clone()),
+        _ => None,
+    }
+}
+
+/// `sha256=<hex>` HMAC of `body` keyed with `secret`
+fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::event::EventDetection;
+
+    #[test]
+    fn test_state_change_fires_on_start_and_clear() {
+        let mut trigger = Trigger::new(WebhookTrigger::StateChange, 2);
+
+        let events: Vec<_> = [0, 1, 2, 0, 1, 0, 0, 0]
+            .into_iter()
+            .map(|count| trigger.observe(count))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                None,
+                Some(EventType::DetectionsStarted),
+                None,
+                // A single empty batch does not clear the scene
+                None,
+                None,
+                None,
+                Some(EventType::DetectionsCleared),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_batches_fire_every_interval() {
+        let mut trigger = Trigger::new(WebhookTrigger::Batches { interval: 3 }, 1);
+
+        let fired: Vec<bool> = [1, 1, 0, 1, 1, 1, 1]
+            .into_iter()
+            .map(|count| trigger.observe(count).is_some())
+            .collect();
+        assert_eq!(fired, [true, false, false, false, true, false, false]);
+    }
+
+    #[test]
+    fn test_snapshots_keep_latest_frames() {
+        let snapshots = Snapshots::default();
+        for frame_number in 0..SNAPSHOT_CAPACITY as u64 + 2 {
+            snapshots.insert(frame_number, Arc::from([frame_number as u8].as_slice()));
+        }
+
+        assert!(snapshots.get(1).is_none());
+        assert_eq!(snapshots.get(2).as_deref(), Some([2u8].as_slice()));
+        assert!(snapshots.get(100).is_none());
+    }
+
+    #[test]
+    fn test_template_renders_event_fields() {
+        let detection = |class_id, confidence| EventDetection {
+            x1: 0.0,
+            y1: 0.0,
+            x2: 10.0,
+            y2: 10.0,
+            confidence,
+            class_id,
+        };
+        let event = Event::new(
+            "door",
+            EventType::DetectionsStarted,
+            DetectionSummary {
+                frame_number: 7,
+                timestamp_ns: 0,
+                width: 640,
+                height: 480,
+                detections: vec![detection(2, 0.4), detection(0, 0.91)],
+                snapshot_url: None,
+            },
+        );
+        let template = Template::parse(
+            r#"{"text":"{{count}} on {{device_id}}, {{class}} ({{confidence}}){{#if snapshot_url}} {{snapshot_url}}{{/if}}"}"#,
+            TEMPLATE_FIELDS,
+        )
+        .unwrap();
+
+        assert_eq!(
+            template.render(|name| template_field(&event, name)),
+            r#"{"text":"2 on door, person (0.91)"}"#
+        );
+        for name in TEMPLATE_FIELDS {
+            let set = template_field(&event, name).is_some();
+            assert_eq!(set, *name != "snapshot_url", "{name}");
+        }
+    }
+
+    #[test]
+    fn test_signature() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -1,38 +1,150 @@
+use crate::clients::{self, ClientKind};
 use crate::config::GatewayConfig;
+use crate::controller;
+use crate::frame_history;
+use crate::health;
+use crate::pacing::FramePacer;
+use crate::share;
 use crate::state::AppState;
+use crate::stills;
+use crate::tuning;
+use crate::ui;
+use crate::webhook;
 use axum::{
     Router,
-    extract::{State, WebSocketUpgrade, ws::WebSocket},
-    response::IntoResponse,
-    routing::get,
+    extract::{
+        ConnectInfo, Query, State, WebSocketUpgrade,
+        ws::{Message, WebSocket},
+    },
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
 };
+use serde::Deserialize;
+use std::net::SocketAddr;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{self, Instant};
 use tower_http::cors::CorsLayer;
 
 pub async fn run_server(config: GatewayConfig, state: AppState) -> anyhow::Result<()> {
     let app = Router::new()
+        .route("/", get(ui::index))
         .route("/ws", get(ws_handler))
+        .route("/health/live", get(health::live))
+        .route("/health/ready", get(health::ready))
+        .route("/api/clients", get(clients::list))
+        .route("/api/clients/:id", delete(clients::kick))
+        .route("/api/snapshots/:frame_number", get(webhook::snapshot))
+        .route("/api/stills", post(stills::request))
+        .route("/api/stills/latest", get(stills::latest))
+        .route("/api/frames", get(frame_history::frame_at))
+        .route("/api/share", post(share::create))
+        .route("/share/:token", get(share::open))
+        .route(
+            "/api/thresholds",
+            get(tuning::get).put(tuning::put).delete(tuning::clear),
+        )
+        .route("/api/thresholds/preview", get(tuning::preview))
+        .route("/api/controller/state", get(controller::get))
+        .route("/api/controller/arm", post(controller::arm))
+        .route(
+            "/api/controller/drill",
+            get(controller::drill_report).post(controller::drill),
+        )
+        .fallback(ui::asset)
         .layer(CorsLayer::permissive())
         .with_state(state);
 
     let listener = tokio::net::TcpListener::bind(&config.ws_addr).await?;
     tracing::info!("WebSocket server listening on {}", config.ws_addr);
+    tracing::info!("Web UI available at http://{}/", config.ws_addr);
 
-    axum::serve(listener, app).await?;
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     Ok(())
 }
 
-async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_socket(socket, state))
+#[derive(Debug, Deserialize)]
+struct StreamParams {
+    /// Highest frame rate the client wants, every frame when unset
+    fps: Option<f64>,
 }
 
-async fn handle_socket(mut socket: WebSocket, state: AppState) {
-    tracing::info!("New WebSocket connection established");
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Query(params): Query<StreamParams>,
+    State(state): State<AppState>,
+) -> Response {
+    if params.fps.is_some_and(|fps| FramePacer::new(fps).is_none()) {
+        return (StatusCode::BAD_REQUEST, "fps must be a positive rate").into_response();
+    }
+    ws.on_upgrade(move |socket| handle_socket(socket, addr, state, params.fps, "/ws", None))
+        .into_response()
+}
+
+/// Stream frames to `socket` from `path`, until the client leaves, is kicked or
+/// `until` passes
+pub async fn handle_socket(
+    mut socket: WebSocket,
+    addr: SocketAddr,
+    state: AppState,
+    fps: Option<f64>,
+    path: &'static str,
+    until: Option<Instant>,
+) {
+    let subscription = match fps {
+        Some(fps) => format!("{path}?fps={fps}"),
+        None => path.to_string(),
+    };
+    let client = state
+        .clients
+        .register(ClientKind::WebSocket, addr, subscription);
+    let mut pacer = fps.and_then(FramePacer::new);
+    tracing::info!(%addr, client = client.id(), "New WebSocket connection established");
 
     let mut rx = state.tx.subscribe();
+    let expired = async move {
+        match until {
+            Some(until) => time::sleep_until(until).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(expired);
+
+    loop {
+        let packet = tokio::select! {
+            received = rx.recv() => match received {
+                Ok(packet) => packet,
+                Err(RecvError::Lagged(skipped)) => {
+                    client.record_dropped(skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = client.kicked() => {
+                let _ = socket.send(Message::Close(None)).await;
+                tracing::info!(%addr, client = client.id(), "WebSocket client kicked");
+                break;
+            }
+            _ = &mut expired => {
+                let _ = socket.send(Message::Close(None)).await;
+                tracing::info!(%addr, client = client.id(), "Share link expired, WebSocket closed");
+                break;
+            }
+        };
+
+        if let Some(pacer) = pacer.as_mut()
+            && !pacer.admit(packet.metadata.timestamp_ns)
+        {
+            continue;
+        }
 
-    while let Ok(packet) = rx.recv().await {
-        let json = match serde_json::to_vec(&packet.metadata) {
+        let json = match serde_json::to_vec(&*packet.metadata) {
             Ok(j) => j,
             Err(e) => {
                 tracing::error!("JSON serialization error: {}", e);
@@ -44,14 +156,12 @@ async fn handle_socket(mut socket: WebSocket, state: AppState) {
         binary_msg.extend_from_slice(&(json.len() as u32).to_le_bytes());
         binary_msg.extend_from_slice(&json);
         binary_msg.extend_from_slice(&packet.jpeg_data);
+        let len = binary_msg.len();
 
-        if socket
-            .send(axum::extract::ws::Message::Binary(binary_msg))
-            .await
-            .is_err()
-        {
-            tracing::info!("WebSocket client disconnected");
+        if socket.send(Message::Binary(binary_msg)).await.is_err() {
+            tracing::info!(%addr, client = client.id(), "WebSocket client disconnected");
             break;
         }
+        client.record_sent(len, rx.len());
     }
 }
@@ -32,6 +32,10 @@ public:
     /// Load TensorRT engine from file
     bool load_engine(const char* engine_path);
 
+    /// Load TensorRT engine from a serialized engine in host memory
+    /// data: [size] bytes, only read during the call
+    bool load_engine_from_memory(const uint8_t* data, size_t size);
+
     /// Run inference with raw output pointers (FFI friendly)
     /// images: [1, 3, 512, 512] float32 on host
     /// out_dets: [num_queries * 4] float32 - cxcywh boxes (normalized 0-1)
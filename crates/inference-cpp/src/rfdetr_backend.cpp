@@ -92,13 +92,17 @@ bool RFDetrBackend::load_engine(const char* engine_path) {
     file.read(engine_data.data(), size);
     file.close();
 
+    return load_engine_from_memory(reinterpret_cast<const uint8_t*>(engine_data.data()), size);
+}
+
+bool RFDetrBackend::load_engine_from_memory(const uint8_t* data, size_t size) {
     runtime_ = nvinfer1::createInferRuntime(logger_);
     if (!runtime_) {
         LOG_ERROR("Failed to create TensorRT runtime");
         return false;
     }
 
-    engine_ = runtime_->deserializeCudaEngine(engine_data.data(), size);
+    engine_ = runtime_->deserializeCudaEngine(data, size);
     if (!engine_) {
         LOG_ERROR("Failed to deserialize CUDA engine");
         return false;
@@ -9,7 +9,7 @@ use preprocess::CpuPreProcessor;
 use std::path::Path;
 
 #[cfg(feature = "ort-backend")]
-use inference::backend::ort::OrtBackend;
+use inference::{OrtSessionConfig, backend::ort::OrtBackend};
 
 #[cfg(feature = "trt-backend")]
 use inference::backend::trt::TrtBackend;
@@ -27,12 +27,21 @@ fn create_test_frame(width: u32, height: u32) -> Vec<u8> {
         &schema::FrameArgs {
             frame_number: 1,
             timestamp_ns: 0,
+            timestamp_clock: schema::ClockDomain::WallClock,
+            signature: None,
+            format_generation: 0,
             camera_id: 0,
             width,
             height,
             channels: 3,
             pixels: Some(pixel_vector),
+            original_width: 0,
+            original_height: 0,
+            nvmm_surface: -1,
             trace: None,
+            provenance: None,
+            encoding: schema::FrameEncoding::Rgb,
+            burst: None,
         },
     );
 
@@ -125,6 +134,7 @@ fn benchmark_inference(c: &mut Criterion) {
             if let Ok(mut cpu_backend) = OrtBackend::load_model_with_provider(
                 onnx_model_path,
                 inference::config::ExecutionProvider::Cpu,
+                &OrtSessionConfig::default(),
             ) {
                 group.bench_function("ort_cpu", |b| {
                     b.iter(|| cpu_backend.infer(black_box(&preprocessed)).unwrap());
@@ -137,6 +147,7 @@ fn benchmark_inference(c: &mut Criterion) {
             if let Ok(mut cuda_backend) = OrtBackend::load_model_with_provider(
                 onnx_model_path,
                 inference::config::ExecutionProvider::Cuda,
+                &OrtSessionConfig::default(),
             ) {
                 group.bench_function("ort_cuda", |b| {
                     b.iter(|| cuda_backend.infer(black_box(&preprocessed)).unwrap());
@@ -175,6 +186,77 @@ fn benchmark_inference(c: &mut Criterion) {
     group.finish();
 }
 
+/// IoBinding against a plain `Session::run` at 640x640, the size where the per-run
+/// input copy and output allocations weigh the most
+///
+/// Needs a 640x640 export at `models/inference_model_640.onnx`.
+#[cfg(any(feature = "ort-backend", feature = "trt-backend"))]
+fn benchmark_io_binding(c: &mut Criterion) {
+    let mut group = c.benchmark_group("io_binding_640");
+
+    #[cfg(feature = "ort-backend")]
+    {
+        use inference::config::ExecutionProvider;
+        use ort::session::Session;
+        use ort::value::TensorRef;
+
+        let onnx_model_path = "../../models/inference_model_640.onnx";
+        if !Path::new(onnx_model_path).exists() {
+            eprintln!(
+                "Skipping IoBinding benchmark: model not found at {}",
+                onnx_model_path
+            );
+            return;
+        }
+
+        let preprocessed = Array::<f32, _>::zeros(IxDyn(&[1, 3, 640, 640]));
+
+        for (name, provider) in [
+            ("cpu", ExecutionProvider::Cpu),
+            ("cuda", ExecutionProvider::Cuda),
+        ] {
+            let Ok(mut backend) = OrtBackend::load_model_with_provider(
+                onnx_model_path,
+                provider,
+                &OrtSessionConfig::default(),
+            ) else {
+                eprintln!("Failed to load ONNX model with {} provider", name);
+                continue;
+            };
+            group.bench_function(format!("io_binding_{}", name), |b| {
+                b.iter(|| backend.infer(black_box(&preprocessed)).unwrap());
+            });
+            drop(backend);
+
+            // Baseline: a fresh input value and fresh outputs on every run
+            let mut builder = Session::builder().unwrap();
+            if provider == ExecutionProvider::Cuda {
+                builder = builder
+                    .with_execution_providers([
+                        ort::execution_providers::CUDAExecutionProvider::default().build(),
+                    ])
+                    .unwrap();
+            }
+            let mut session = builder.commit_from_file(onnx_model_path).unwrap();
+            group.bench_function(format!("session_run_{}", name), |b| {
+                b.iter(|| {
+                    let outputs = session
+                        .run(ort::inputs![
+                            "input" => TensorRef::from_array_view(black_box(&preprocessed).view()).unwrap()
+                        ])
+                        .unwrap();
+                    (
+                        outputs["dets"].try_extract_array::<f32>().unwrap().into_owned(),
+                        outputs["labels"].try_extract_array::<f32>().unwrap().into_owned(),
+                    )
+                });
+            });
+        }
+    }
+
+    group.finish();
+}
+
 /// End-to-end pipeline benchmark: preprocessing -> inference -> postprocessing
 #[cfg(any(feature = "ort-backend", feature = "trt-backend"))]
 fn benchmark_full_pipeline(c: &mut Criterion) {
@@ -196,6 +278,7 @@ fn benchmark_full_pipeline(c: &mut Criterion) {
             if let Ok(mut cpu_backend) = OrtBackend::load_model_with_provider(
                 onnx_model_path,
                 inference::config::ExecutionProvider::Cpu,
+                &OrtSessionConfig::default(),
             ) {
                 group.bench_function("ort_cpu", |b| {
                     b.iter(|| {
@@ -239,6 +322,7 @@ fn benchmark_full_pipeline(c: &mut Criterion) {
             if let Ok(mut cuda_backend) = OrtBackend::load_model_with_provider(
                 onnx_model_path,
                 inference::config::ExecutionProvider::Cuda,
+                &OrtSessionConfig::default(),
             ) {
                 group.bench_function("ort_cuda", |b| {
                     b.iter(|| {
@@ -345,6 +429,7 @@ criterion_group!(
     benches,
     benchmark_postprocessing,
     benchmark_inference,
+    benchmark_io_binding,
     benchmark_full_pipeline
 );
 
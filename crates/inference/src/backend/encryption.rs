@@ -0,0 +1,262 @@
+//! Models encrypted at rest
+//!
+//! An encrypted model file is [`MAGIC`], a 12-byte nonce and the model sealed with
+//! AES-256-GCM (the magic bytes as associated data), as written by the
+//! `encrypt_model` binary. Backends decrypt it in memory when
+//! loading it, so the plaintext never reaches the disk, and zero their copy once
+//! the runtime holds the model. Plain model files load as before.
+//!
+//! The key is 32 bytes, raw or hex, read from the first of:
+//! - `MODEL_KEY_FILE`: a file, e.g. a key unsealed from the TPM to a tmpfs
+//! - `MODEL_KEY_KEYRING`: the description of a `user` key in the kernel keyring
+//! - `MODEL_SECRET_KEY`: the variable itself
+
+use anyhow::{Context, Result, bail};
+use common::get_env_opt;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fs::File;
+use std::io::Read;
+use std::ops::Deref;
+
+/// First bytes of an encrypted model file
+pub const MAGIC: &[u8; 8] = b"DETRENC1";
+
+const KEY_LEN: usize = 32;
+
+/// Where the model key is read from
+#[derive(Clone, PartialEq, Eq)]
+pub enum KeySource {
+    File(String),
+    Keyring(String),
+    Env(String),
+}
+
+impl std::fmt::Debug for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(path) => f.debug_tuple("File").field(path).finish(),
+            Self::Keyring(description) => f.debug_tuple("Keyring").field(description).finish(),
+            Self::Env(_) => f.write_str("Env(<redacted>)"),
+        }
+    }
+}
+
+impl KeySource {
+    pub fn from_env() -> Option<Self> {
+        get_env_opt("MODEL_KEY_FILE")
+            .map(Self::File)
+            .or_else(|| get_env_opt("MODEL_KEY_KEYRING").map(Self::Keyring))
+            .or_else(|| get_env_opt("MODEL_SECRET_KEY").map(Self::Env))
+    }
+
+    fn read(&self) -> Result<Secret> {
+        let bytes = match self {
+            Self::File(path) => Secret(
+                std::fs::read(path)
+                    .with_context(|| format!("Failed to read MODEL_KEY_FILE {}", path))?,
+            ),
+            Self::Keyring(description) => read_keyring(description).with_context(|| {
+                format!("Failed to read key {:?} from the keyring", description)
+            })?,
+            Self::Env(hex) => Secret(hex.as_bytes().to_vec()),
+        };
+        parse_key(&bytes)
+    }
+}
+
+/// Bytes zeroed when dropped
+pub struct Secret(Vec<u8>);
+
+impl Deref for Secret {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        // Truncated buffers keep their tail past the length
+        let capacity = self.0.capacity();
+        self.0.clear();
+        self.0.resize(capacity, 0);
+        // Keep the writes from being optimized away as dead stores
+        std::hint::black_box(&self.0);
+    }
+}
+
+/// A key of 32 raw bytes, or 64 hex digits
+fn parse_key(bytes: &[u8]) -> Result<Secret> {
+    if bytes.len() == KEY_LEN {
+        return Ok(Secret(bytes.to_vec()));
+    }
+    let key =
+        Secret(hex::decode(bytes.trim_ascii()).context("Model key is neither 32 bytes nor hex")?);
+    if key.len() != KEY_LEN {
+        bail!("Model key is {} bytes, expected {}", key.len(), KEY_LEN);
+    }
+    Ok(key)
+}
+
+/// Encrypted file of `model`, sealed with the key from `source`
+pub fn encrypt_model(model: &[u8], source: &KeySource) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| anyhow::anyhow!("Failed to generate a nonce"))?;
+    seal(&source.read()?, nonce, model)
+}
+
+fn seal(key: &[u8], nonce: [u8; NONCE_LEN], model: &[u8]) -> Result<Vec<u8>> {
+    let key = LessSafeKey::new(
+        UnboundKey::new(&AES_256_GCM, key).map_err(|_| anyhow::anyhow!("Invalid model key"))?,
+    );
+    let mut sealed = [MAGIC.as_slice(), &nonce, model].concat();
+    let tag = key
+        .seal_in_place_separate_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(MAGIC),
+            &mut sealed[MAGIC.len() + NONCE_LEN..],
+        )
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt model"))?;
+    sealed.extend_from_slice(tag.as_ref());
+    Ok(sealed)
+}
+
+/// Decrypted model at `path`, `None` when the file is not encrypted
+pub fn decrypt_model(path: &str) -> Result<Option<Secret>> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open model {}", path))?;
+    let mut magic = [0u8; MAGIC.len()];
+    if file.read_exact(&mut magic).is_err() || &magic != MAGIC {
+        return Ok(None);
+    }
+
+    let source = KeySource::from_env().with_context(|| {
+        format!(
+            "Model {} is encrypted, set MODEL_KEY_FILE, MODEL_KEY_KEYRING or MODEL_SECRET_KEY",
+            path
+        )
+    })?;
+    let mut sealed = Vec::new();
+    file.read_to_end(&mut sealed)
+        .with_context(|| format!("Failed to read model {}", path))?;
+    let model = open(&source.read()?, sealed)
+        .with_context(|| format!("Failed to decrypt model {}", path))?;
+    tracing::info!(path, key = ?source, "Decrypted model");
+    Ok(Some(model))
+}
+
+/// Decrypt the nonce, ciphertext and tag following the magic bytes
+fn open(key: &[u8], sealed: Vec<u8>) -> Result<Secret> {
+    // Zeroed on failure too, the buffer may then hold part of the plaintext
+    let mut sealed = Secret(sealed);
+    if sealed.len() < NONCE_LEN + AES_256_GCM.tag_len() {
+        bail!("Encrypted model is truncated");
+    }
+    let key = LessSafeKey::new(
+        UnboundKey::new(&AES_256_GCM, key).map_err(|_| anyhow::anyhow!("Invalid model key"))?,
+    );
+    let nonce = Nonce::try_assume_unique_for_key(&sealed[..NONCE_LEN])
+        .map_err(|_| anyhow::anyhow!("Invalid nonce"))?;
+    let plaintext_len = key
+        .open_in_place(nonce, Aad::from(MAGIC), &mut sealed.0[NONCE_LEN..])
+        .map_err(|_| anyhow::anyhow!("Wrong key or corrupted model"))?
+        .len();
+    sealed
+        .0
+        .copy_within(NONCE_LEN..NONCE_LEN + plaintext_len, 0);
+    sealed.0.truncate(plaintext_len);
+    Ok(sealed)
+}
+
+/// Payload of a `user` key in the calling process's keyrings
+#[cfg(target_os = "linux")]
+fn read_keyring(description: &str) -> Result<Secret> {
+    const KEYCTL_READ: libc::c_long = 11;
+
+    let description = std::ffi::CString::new(description)?;
+    // SAFETY: both strings are NUL terminated, no callout info is passed and
+    // the found key is not linked into any keyring
+    let id = unsafe {
+        libc::syscall(
+            libc::SYS_request_key,
+            c"user".as_ptr(),
+            description.as_ptr(),
+            std::ptr::null::<libc::c_char>(),
+            0,
+        )
+    };
+    if id < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    let mut payload = Secret(vec![0u8; 2 * KEY_LEN + 1]);
+    // SAFETY: the buffer is valid for writes of its length
+    let len = unsafe {
+        libc::syscall(
+            libc::SYS_keyctl,
+            KEYCTL_READ,
+            id,
+            payload.0.as_mut_ptr(),
+            payload.0.len(),
+        )
+    };
+    if len < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    if len as usize > payload.len() {
+        bail!("Key payload of {} bytes is too long", len);
+    }
+    payload.0.truncate(len as usize);
+    Ok(payload)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_keyring(_description: &str) -> Result<Secret> {
+    bail!("MODEL_KEY_KEYRING needs the Linux kernel keyring")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_model_roundtrip() {
+        let key = [7u8; KEY_LEN];
+        let file = encrypt_model(b"onnx model", &KeySource::Env("07".repeat(KEY_LEN))).unwrap();
+        assert!(file.starts_with(MAGIC));
+        let sealed = file[MAGIC.len()..].to_vec();
+
+        assert_eq!(&*open(&key, sealed.clone()).unwrap(), b"onnx model");
+        assert!(open(&[8u8; KEY_LEN], sealed.clone()).is_err());
+        let mut tampered = sealed;
+        tampered[NONCE_LEN] ^= 1;
+        assert!(open(&key, tampered).is_err());
+        assert!(open(&key, vec![0; NONCE_LEN]).is_err());
+    }
+
+    #[test]
+    fn test_plain_model_is_not_decrypted() {
+        let path = std::env::temp_dir().join(format!("plain-model-{}.onnx", std::process::id()));
+        std::fs::write(&path, b"\x08\x07onnx").unwrap();
+
+        assert!(decrypt_model(path.to_str().unwrap()).unwrap().is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_key_parsing() {
+        let raw = [0xab; KEY_LEN];
+        assert_eq!(&*parse_key(&raw).unwrap(), raw);
+        let hex = format!("{}\n", "ab".repeat(KEY_LEN));
+        assert_eq!(&*parse_key(hex.as_bytes()).unwrap(), raw);
+        assert!(parse_key(b"abcd").is_err());
+        assert!(parse_key(b"not hex").is_err());
+        assert_eq!(
+            format!("{:?}", KeySource::Env("secret".to_string())),
+            "Env(<redacted>)"
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! Synthetic detections without a model
+//!
+//! Selected with `INFERENCE_BACKEND=mock`, it ignores the input tensor and emits
+//! RF-DETR shaped outputs holding at most one box, so the controller, gateway and
+//! MQTT stack can run on machines without a model or a GPU. Boxes are
+//! `cx,cy,w,h` normalized to the model input, like the real model's.
+//!
+//! The box follows either a scripted timeline (`MOCK_SCRIPT`) or a random walk.
+//! A script is a `;` separated list of `<frames>:<cx>,<cy>,<w>,<h>` or
+//! `<frames>:empty` segments that loops forever, e.g.
+//! `30:empty;90:0.5,0.6,0.2,0.5;60:empty` alternates an empty scene with a
+//! person standing in the middle.
+
+use super::{InferenceBackend, InferenceOutput};
+use anyhow::Context;
+use common::{get_env, get_env_opt};
+use ndarray::{Array, ArrayD, IxDyn};
+use preprocess::PreprocessOutput;
+use std::str::FromStr;
+
+/// Queries emitted per run, as in the RF-DETR export
+const NUM_QUERIES: usize = 300;
+/// COCO classes plus background
+const NUM_CLASSES: usize = 91;
+/// Logit of the empty queries, well under any confidence threshold
+const EMPTY_LOGIT: f32 = -10.0;
+
+/// Box as `cx, cy, w, h` normalized to the model input
+pub type MockBox = [f32; 4];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptSegment {
+    pub frames: u64,
+    pub bbox: Option<MockBox>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockMode {
+    /// Looping timeline of segments
+    Script(Vec<ScriptSegment>),
+    /// A person box wandering around the frame
+    RandomWalk {
+        /// Largest move of the center per frame, normalized
+        step: f32,
+    },
+}
+
+impl FromStr for MockMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let segments = s
+            .split(';')
+            .map(str::trim)
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| {
+                let (frames, bbox) = segment
+                    .split_once(':')
+                    .with_context(|| format!("Script segment {segment:?} has no ':'"))?;
+                let frames: u64 = frames
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Invalid frame count in {segment:?}"))?;
+                anyhow::ensure!(frames > 0, "Script segment {segment:?} lasts no frame");
+
+                let bbox = match bbox.trim() {
+                    "empty" => None,
+                    coords => {
+                        let values = coords
+                            .split(',')
+                            .map(|v| v.trim().parse::<f32>())
+                            .collect::<Result<Vec<_>, _>>()
+                            .with_context(|| format!("Invalid box in {segment:?}"))?;
+                        let bbox: MockBox = values.try_into().map_err(|_| {
+                            anyhow::anyhow!("Box in {segment:?} needs 4 values: cx,cy,w,h")
+                        })?;
+                        Some(bbox)
+                    }
+                };
+                Ok(ScriptSegment { frames, bbox })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        anyhow::ensure!(!segments.is_empty(), "Mock script is empty");
+        Ok(Self::Script(segments))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MockConfig {
+    pub mode: MockMode,
+    /// Confidence of the emitted box
+    pub confidence: f32,
+    /// COCO class of the emitted box
+    pub class_id: u16,
+    /// Seed of the random walk, random when unset
+    pub seed: Option<u64>,
+}
+
+impl MockConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mode = match get_env_opt::<String>("MOCK_SCRIPT") {
+            Some(script) => script.parse()?,
+            None => MockMode::RandomWalk {
+                step: get_env("MOCK_WALK_STEP", 0.01f32),
+            },
+        };
+        let class_id = get_env("MOCK_CLASS_ID", 0u16);
+        anyhow::ensure!(
+            (class_id as usize) < NUM_CLASSES - 1,
+            "MOCK_CLASS_ID {} is not a COCO class",
+            class_id
+        );
+
+        Ok(Self {
+            mode,
+            confidence: get_env("MOCK_CONFIDENCE", 0.9f32).clamp(0.01, 0.99),
+            class_id,
+            seed: get_env_opt("MOCK_SEED"),
+        })
+    }
+}
+
+pub struct MockBackend {
+    config: MockConfig,
+    rng: fastrand::Rng,
+    /// Runs so far
+    frame: u64,
+    /// Current random walk box
+    walker: MockBox,
+}
+
+impl MockBackend {
+    pub fn new(config: MockConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => fastrand::Rng::with_seed(seed),
+            None => fastrand::Rng::new(),
+        };
+        tracing::warn!(
+            mode = ?config.mode,
+            "Mock inference backend enabled, detections are synthetic"
+        );
+        Self {
+            config,
+            rng,
+            frame: 0,
+            walker: [0.5, 0.5, 0.2, 0.5],
+        }
+    }
+
+    /// Box for the next run
+    fn next_box(&mut self) -> Option<MockBox> {
+        let frame = self.frame;
+        self.frame += 1;
+
+        match &self.config.mode {
+            MockMode::Script(segments) => {
+                let period: u64 = segments.iter().map(|s| s.frames).sum();
+                let mut at = frame % period;
+                segments.iter().find_map(|segment| {
+                    if at < segment.frames {
+                        Some(segment.bbox)
+                    } else {
+                        at -= segment.frames;
+                        None
+                    }
+                })?
+            }
+            MockMode::RandomWalk { step } => {
+                let [cx, cy, w, h] = &mut self.walker;
+                let mut wander = |center: &mut f32, size: f32| {
+                    let moved = *center + (self.rng.f32() * 2.0 - 1.0) * step;
+                    *center = moved.clamp(size / 2.0, 1.0 - size / 2.0);
+                };
+                wander(cx, *w);
+                wander(cy, *h);
+                Some(self.walker)
+            }
+        }
+    }
+
+    fn output(&self, bbox: Option<MockBox>) -> InferenceOutput {
+        let dets = ArrayD::zeros(IxDyn(&[1, NUM_QUERIES, 4]));
+        let logits = Array::from_elem(IxDyn(&[1, NUM_QUERIES, NUM_CLASSES]), EMPTY_LOGIT);
+        let mut output = InferenceOutput { dets, logits };
+
+        if let Some(bbox) = bbox {
+            for (i, value) in bbox.into_iter().enumerate() {
+                output.dets[[0, 0, i]] = value;
+            }
+            // Inverse of the sigmoid applied in postprocessing; RF-DETR classes
+            // are 1-indexed
+            let confidence = self.config.confidence;
+            output.logits[[0, 0, self.config.class_id as usize + 1]] =
+                (confidence / (1.0 - confidence)).ln();
+        }
+        output
+    }
+}
+
+impl InferenceBackend for MockBackend {
+    /// Ignores the model path, the behaviour comes from the `MOCK_*` variables
+    fn load_model(_path: &str) -> anyhow::Result<Self> {
+        Ok(Self::new(MockConfig::from_env()?))
+    }
+
+    fn infer(&mut self, _images: &Array<f32, IxDyn>) -> anyhow::Result<InferenceOutput> {
+        let bbox = self.next_box();
+        Ok(self.output(bbox))
+    }
+
+    /// Accepts GPU tensors too, the input is never read
+    fn infer_preprocessed(&mut self, _input: &PreprocessOutput) -> anyhow::Result<InferenceOutput> {
+        let bbox = self.next_box();
+        Ok(self.output(bbox))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processing::post::{PostProcessor, TransformParams};
+
+    fn backend(mode: MockMode) -> MockBackend {
+        MockBackend::new(MockConfig {
+            mode,
+            confidence: 0.9,
+            class_id: 0,
+            seed: Some(7),
+        })
+    }
+
+    #[test]
+    fn test_parse_script() {
+        let mode: MockMode = "2:empty; 3:0.5,0.5,0.2,0.4".parse().unwrap();
+        assert_eq!(
+            mode,
+            MockMode::Script(vec![
+                ScriptSegment {
+                    frames: 2,
+                    bbox: None
+                },
+                ScriptSegment {
+                    frames: 3,
+                    bbox: Some([0.5, 0.5, 0.2, 0.4])
+                },
+            ])
+        );
+
+        assert!("".parse::<MockMode>().is_err());
+        assert!("0:empty".parse::<MockMode>().is_err());
+        assert!("5:0.5,0.5".parse::<MockMode>().is_err());
+        assert!("five:empty".parse::<MockMode>().is_err());
+    }
+
+    #[test]
+    fn test_script_loops() {
+        let mut mock = backend("1:empty;2:0.5,0.5,0.2,0.4".parse().unwrap());
+
+        let present: Vec<bool> = (0..6).map(|_| mock.next_box().is_some()).collect();
+        assert_eq!(present, [false, true, true, false, true, true]);
+    }
+
+    #[test]
+    fn test_random_walk_stays_in_frame() {
+        let mut mock = backend(MockMode::RandomWalk { step: 0.2 });
+
+        for _ in 0..1000 {
+            let [cx, cy, w, h] = mock.next_box().unwrap();
+            assert!(cx - w / 2.0 >= 0.0 && cx + w / 2.0 <= 1.0);
+            assert!(cy - h / 2.0 >= 0.0 && cy + h / 2.0 <= 1.0);
+        }
+    }
+
+    #[test]
+    fn test_output_decodes_to_one_person() {
+        let mut mock = backend("1:0.5,0.5,0.25,0.5;1:empty".parse().unwrap());
+        let postprocessor = PostProcessor::new(0.5);
+        let transform = TransformParams {
+            orig_width: 640,
+            orig_height: 640,
+            input_width: 640,
+            input_height: 640,
+            scale: 1.0,
+            offset_x: 0.0,
+            offset_y: 0.0,
+        };
+        let decode = |output: InferenceOutput| -> Vec<bridge::Detection> {
+            postprocessor.detections(&output.dets.view(), &output.logits.view(), &transform)
+        };
+
+        let input = Array::zeros(IxDyn(&[1, 3, 640, 640]));
+        let detections = decode(mock.infer(&input).unwrap());
+        assert_eq!(detections.len(), 1);
+        let det = &detections[0];
+        assert_eq!(det.class_id, 0);
+        assert!((det.confidence - 0.9).abs() < 1e-4);
+        assert_eq!(
+            (det.x1, det.y1, det.x2, det.y2),
+            (240.0, 160.0, 400.0, 480.0)
+        );
+
+        assert!(decode(mock.infer(&input).unwrap()).is_empty());
+    }
+}
@@ -1,5 +1,10 @@
 use ndarray::{Array, IxDyn};
 use preprocess::PreprocessOutput;
+use std::time::Duration;
+
+pub mod encryption;
+pub mod mock;
+pub mod switchable;
 
 #[cfg(feature = "ort-backend")]
 pub mod ort;
@@ -27,8 +32,27 @@ pub trait InferenceBackend {
             }
         }
     }
+
+    /// Abort the following runs once they take longer than `timeout`
+    ///
+    /// Aborted runs fail with [`DeadlineExceeded`]. Backends that cannot cancel a
+    /// run ignore it and always run to completion.
+    fn set_run_timeout(&mut self, _timeout: Option<Duration>) {}
+
+    /// Run the lighter fallback model instead of the primary one, or back
+    ///
+    /// Returns whether the backend has a fallback; those without one keep running
+    /// their only model.
+    fn set_fallback(&mut self, _active: bool) -> bool {
+        false
+    }
 }
 
+/// A run was aborted by the timeout set with [`InferenceBackend::set_run_timeout`]
+#[derive(Debug, thiserror::Error)]
+#[error("Inference run aborted after {0:?}")]
+pub struct DeadlineExceeded(pub Duration);
+
 pub struct InferenceOutput {
     pub dets: ndarray::ArrayD<f32>, // [1, 300, 4] cxcywh (normalized 0-1)
     pub logits: ndarray::ArrayD<f32>, // [1, 300, num_classes] class logits
@@ -1,28 +1,141 @@
-use super::{InferenceBackend, InferenceOutput};
-use crate::config::ExecutionProvider;
+use super::{DeadlineExceeded, InferenceBackend, InferenceOutput, encryption};
+use crate::config::{ExecutionProvider, GraphOptimization, OrtSessionConfig};
 use ndarray::{Array, IxDyn};
 use ort::{
-    session::{Session, builder::GraphOptimizationLevel},
-    value::TensorRef,
+    io_binding::IoBinding,
+    memory::{AllocationDevice, Allocator, AllocatorType, MemoryInfo, MemoryType},
+    session::{RunOptions, Session, builder::GraphOptimizationLevel},
+    value::{Tensor, TensorRef, TensorRefMut, ValueType},
 };
+use preprocess::PreprocessOutput;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
 
+const INPUT_NAME: &str = "input";
+const DETS_NAME: &str = "dets";
+const LOGITS_NAME: &str = "labels";
+
+/// ONNX Runtime backend running through an [`IoBinding`]
+///
+/// Outputs are bound once to preallocated tensors that ORT writes into on every run.
+/// With the CPU provider the input array is bound in place. With the CUDA provider it
+/// is copied into a page-locked buffer so the host-to-device transfer is a single DMA,
+/// outputs land in page-locked memory, and GPU-preprocessed input is bound straight
+/// from the device pointer.
 pub struct OrtBackend {
     session: Session,
+    binding: IoBinding,
+    provider: ExecutionProvider,
+    /// Page-locked host memory, only with the CUDA provider
+    pinned: Option<Allocator>,
+    /// Reused input buffer for the CUDA provider, reallocated if the input shape changes
+    input: Option<Tensor<f32>>,
+    /// Aborts runs that overrun the timeout, started with the first timeout
+    timer: Option<RunTimer>,
+    timeout: Option<Duration>,
+}
+
+/// Watcher thread terminating a run through its [`RunOptions`] once it is due
+///
+/// The thread sleeps until a run is armed, then until its deadline or disarm.
+struct RunTimer {
+    options: Arc<RunOptions>,
+    state: Arc<(Mutex<TimerState>, Condvar)>,
+}
+
+#[derive(Default)]
+struct TimerState {
+    /// Deadline of the current run, `None` between runs and once it fired
+    due: Option<Instant>,
+    stopped: bool,
+}
+
+impl RunTimer {
+    fn start() -> anyhow::Result<Self> {
+        let options = Arc::new(RunOptions::new()?);
+        let state = Arc::new((Mutex::new(TimerState::default()), Condvar::new()));
+
+        let (watched_options, watched) = (options.clone(), state.clone());
+        std::thread::Builder::new()
+            .name("ort-run-timer".to_string())
+            .spawn(move || {
+                let (lock, cvar) = &*watched;
+                let mut state = lock.lock().unwrap();
+                while !state.stopped {
+                    match state.due {
+                        None => state = cvar.wait(state).unwrap(),
+                        Some(due) if Instant::now() >= due => {
+                            if let Err(e) = watched_options.terminate() {
+                                tracing::warn!(error = %e, "Failed to abort inference run");
+                            }
+                            state.due = None;
+                        }
+                        Some(due) => {
+                            state = cvar.wait_timeout(state, due - Instant::now()).unwrap().0
+                        }
+                    }
+                }
+            })?;
+
+        Ok(Self { options, state })
+    }
+
+    fn arm(&self, timeout: Duration) -> anyhow::Result<()> {
+        self.options.unterminate()?;
+        let (lock, cvar) = &*self.state;
+        lock.lock().unwrap().due = Some(Instant::now() + timeout);
+        cvar.notify_one();
+        Ok(())
+    }
+
+    /// Stop watching the current run. Returns true if it was aborted.
+    fn disarm(&self) -> bool {
+        let (lock, cvar) = &*self.state;
+        let aborted = lock.lock().unwrap().due.take().is_none();
+        cvar.notify_one();
+        aborted
+    }
+}
+
+impl Drop for RunTimer {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.state;
+        lock.lock().unwrap().stopped = true;
+        cvar.notify_one();
+    }
 }
 
 impl OrtBackend {
-    /// Load model with specified execution provider
+    /// Load model with specified execution provider and session options
     pub fn load_model_with_provider(
         path: &str,
         provider: ExecutionProvider,
+        options: &OrtSessionConfig,
     ) -> anyhow::Result<Self> {
         // Initialize ORT environment (idempotent)
         let _ = ort::init().commit();
 
+        let optimization = match options.optimization {
+            GraphOptimization::Disable => GraphOptimizationLevel::Disable,
+            GraphOptimization::Basic => GraphOptimizationLevel::Level1,
+            GraphOptimization::Extended => GraphOptimizationLevel::Level2,
+            GraphOptimization::All => GraphOptimizationLevel::Level3,
+        };
         let mut builder = Session::builder()?
-            .with_optimization_level(GraphOptimizationLevel::Level3)?
-            .with_intra_threads(4)?;
+            .with_optimization_level(optimization)?
+            .with_intra_threads(options.intra_threads)?
+            .with_deterministic_compute(options.deterministic)?;
+        if let Some(inter_threads) = options.inter_threads {
+            builder = builder
+                .with_parallel_execution(true)?
+                .with_inter_threads(inter_threads)?;
+        }
+        tracing::info!(?options, "ONNX Runtime session options");
 
+        // Registered last so it only takes the nodes the CUDA provider leaves
+        let cpu = ort::execution_providers::CPUExecutionProvider::default()
+            .with_arena_allocator(options.memory_arena)
+            .build();
         match provider {
             ExecutionProvider::Cuda => {
                 tracing::info!("Initializing ONNX Runtime with CUDA execution provider");
@@ -31,39 +144,186 @@ impl OrtBackend {
                         .with_device_id(0)
                         .build()
                         .error_on_failure(),
+                    cpu,
                 ])?;
             }
             ExecutionProvider::Cpu => {
                 tracing::info!("Initializing ONNX Runtime with CPU execution provider");
+                builder = builder.with_execution_providers([cpu])?;
             }
         }
 
-        let session = builder.commit_from_file(path)?;
+        // An encrypted model is only ever decrypted in memory
+        let session = match encryption::decrypt_model(path)? {
+            Some(model) => builder.commit_from_memory(&model)?,
+            None => builder.commit_from_file(path)?,
+        };
+
+        let pinned = match provider {
+            ExecutionProvider::Cuda => Some(Allocator::new(
+                &session,
+                MemoryInfo::new(
+                    AllocationDevice::CUDA_PINNED,
+                    0,
+                    AllocatorType::Device,
+                    MemoryType::CPUOutput,
+                )?,
+            )?),
+            ExecutionProvider::Cpu => None,
+        };
+        let binding = bind_outputs(&session, pinned.as_ref().unwrap_or(session.allocator()))?;
 
         tracing::info!("RF-DETR model loaded from {}", path);
-        Ok(Self { session })
+        Ok(Self {
+            session,
+            binding,
+            provider,
+            pinned,
+            input: None,
+            timer: None,
+            timeout: None,
+        })
+    }
+
+    /// Run the bound session and copy the outputs out of the bound buffers
+    fn run(&mut self) -> anyhow::Result<InferenceOutput> {
+        let outputs = match (&self.timer, self.timeout) {
+            (Some(timer), Some(timeout)) => {
+                timer.arm(timeout)?;
+                let outputs = self
+                    .session
+                    .run_binding_with_options(&self.binding, &timer.options);
+                if timer.disarm() {
+                    return Err(DeadlineExceeded(timeout).into());
+                }
+                outputs?
+            }
+            _ => self.session.run_binding(&self.binding)?,
+        };
+
+        let dets = outputs[DETS_NAME].try_extract_array::<f32>()?;
+        let logits = outputs[LOGITS_NAME].try_extract_array::<f32>()?;
+
+        Ok(InferenceOutput {
+            dets: dets.into_owned(),
+            logits: logits.into_owned(),
+        })
     }
 }
 
+/// Bind each output to a preallocated tensor, or let ORT allocate it on first run
+/// when the model leaves a dimension dynamic
+fn bind_outputs(session: &Session, allocator: &Allocator) -> anyhow::Result<IoBinding> {
+    let mut binding = session.create_binding()?;
+
+    for output in session.outputs() {
+        match output.dtype() {
+            ValueType::Tensor { shape, .. } if shape.iter().all(|&d| d > 0) => {
+                let tensor = Tensor::<f32>::new(allocator, shape.clone())?;
+                binding.bind_output(output.name(), tensor)?;
+            }
+            _ => binding.bind_output_to_device(output.name(), allocator.memory_info())?,
+        }
+    }
+
+    Ok(binding)
+}
+
 impl InferenceBackend for OrtBackend {
     fn load_model(path: &str) -> anyhow::Result<Self> {
         // Runtime execution provider selection via environment variable
-        Self::load_model_with_provider(path, ExecutionProvider::from_env())
+        Self::load_model_with_provider(
+            path,
+            ExecutionProvider::from_env(),
+            &OrtSessionConfig::from_env()?,
+        )
+    }
+
+    fn set_run_timeout(&mut self, timeout: Option<Duration>) {
+        if timeout.is_some() && self.timer.is_none() {
+            match RunTimer::start() {
+                Ok(timer) => self.timer = Some(timer),
+                Err(e) => {
+                    tracing::warn!(error = %e, "Failed to start inference run timer, runs won't be aborted");
+                    return;
+                }
+            }
+        }
+        self.timeout = timeout;
     }
 
     #[tracing::instrument(skip(self, images))]
     fn infer(&mut self, images: &Array<f32, IxDyn>) -> anyhow::Result<InferenceOutput> {
         // RF-DETR: input -> dets, labels (logits)
-        let outputs = self.session.run(ort::inputs![
-            "input" => TensorRef::from_array_view(images.view())?
-        ])?;
+        match &self.pinned {
+            Some(pinned) => {
+                let input = match &mut self.input {
+                    Some(input)
+                        if input
+                            .shape()
+                            .iter()
+                            .copied()
+                            .eq(images.shape().iter().map(|&d| d as i64)) =>
+                    {
+                        input
+                    }
+                    slot => slot.insert(Tensor::<f32>::new(pinned, images.shape().to_vec())?),
+                };
+                input.extract_array_mut().assign(images);
+                self.binding.bind_input(INPUT_NAME, input)?;
+                self.run()
+            }
+            None => {
+                // The view is only bound for this run
+                let view = TensorRef::from_array_view(images.view())?;
+                self.binding.bind_input(INPUT_NAME, &*view)?;
+                let output = self.run();
+                self.binding.clear_inputs();
+                output
+            }
+        }
+    }
 
-        let dets = outputs["dets"].try_extract_array()?;
-        let logits = outputs["labels"].try_extract_array()?;
+    fn infer_preprocessed(&mut self, input: &PreprocessOutput) -> anyhow::Result<InferenceOutput> {
+        match input {
+            PreprocessOutput::Cpu(arr) => self.infer(arr),
+            PreprocessOutput::Gpu { ptr, len } => {
+                if self.provider != ExecutionProvider::Cuda {
+                    anyhow::bail!("GPU input requires the CUDA execution provider");
+                }
 
-        Ok(InferenceOutput {
-            dets: dets.into_owned(),
-            logits: logits.into_owned(),
-        })
+                let shape = match self.session.inputs()[0].dtype() {
+                    ValueType::Tensor { shape, .. } if shape.iter().all(|&d| d > 0) => {
+                        shape.clone()
+                    }
+                    _ => anyhow::bail!("GPU input requires a model with a static input shape"),
+                };
+                anyhow::ensure!(
+                    shape.num_elements() == *len,
+                    "GPU input of {} elements does not match model input {:?}",
+                    len,
+                    shape
+                );
+
+                // SAFETY: the preprocessor owns the device buffer for its whole lifetime
+                // and only rewrites it on the next frame, after this run returns
+                let tensor = unsafe {
+                    TensorRefMut::<f32>::from_raw(
+                        MemoryInfo::new(
+                            AllocationDevice::CUDA,
+                            0,
+                            AllocatorType::Device,
+                            MemoryType::Default,
+                        )?,
+                        *ptr as *mut _,
+                        shape,
+                    )?
+                };
+                self.binding.bind_input(INPUT_NAME, &tensor)?;
+                let output = self.run();
+                self.binding.clear_inputs();
+                output
+            }
+        }
     }
 }
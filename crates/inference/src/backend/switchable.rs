@@ -0,0 +1,110 @@
+//! Two sessions of the same backend, one of them running at a time
+//!
+//! The fallback is a lighter variant of the primary model (FP16 instead of FP32,
+//! a smaller model) taking the same input and emitting the same outputs. The
+//! service switches to it while the SoC is thermally throttled, see
+//! [`PrecisionFallbackConfig`](crate::config::PrecisionFallbackConfig). Both
+//! sessions stay loaded so a switch costs nothing on the next frame.
+
+use super::{InferenceBackend, InferenceOutput};
+use ndarray::{Array, IxDyn};
+use preprocess::PreprocessOutput;
+use std::time::Duration;
+
+pub struct SwitchableBackend<B> {
+    primary: B,
+    fallback: B,
+    fallback_active: bool,
+}
+
+impl<B: InferenceBackend> SwitchableBackend<B> {
+    pub fn new(primary: B, fallback: B) -> Self {
+        Self {
+            primary,
+            fallback,
+            fallback_active: false,
+        }
+    }
+
+    /// Load `primary_path`, running it until the fallback is switched to, and
+    /// `fallback_path`
+    pub fn load(primary_path: &str, fallback_path: &str) -> anyhow::Result<Self> {
+        Ok(Self::new(
+            B::load_model(primary_path)?,
+            B::load_model(fallback_path)?,
+        ))
+    }
+
+    fn active(&mut self) -> &mut B {
+        if self.fallback_active {
+            &mut self.fallback
+        } else {
+            &mut self.primary
+        }
+    }
+}
+
+impl<B: InferenceBackend> InferenceBackend for SwitchableBackend<B> {
+    /// Fails, both models are loaded with [`SwitchableBackend::load`]
+    fn load_model(path: &str) -> anyhow::Result<Self> {
+        anyhow::bail!(
+            "SwitchableBackend needs a fallback model besides {:?}, use SwitchableBackend::load",
+            path
+        )
+    }
+
+    fn infer(&mut self, images: &Array<f32, IxDyn>) -> anyhow::Result<InferenceOutput> {
+        self.active().infer(images)
+    }
+
+    fn infer_preprocessed(&mut self, input: &PreprocessOutput) -> anyhow::Result<InferenceOutput> {
+        self.active().infer_preprocessed(input)
+    }
+
+    fn set_run_timeout(&mut self, timeout: Option<Duration>) {
+        self.primary.set_run_timeout(timeout);
+        self.fallback.set_run_timeout(timeout);
+    }
+
+    fn set_fallback(&mut self, active: bool) -> bool {
+        self.fallback_active = active;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::mock::{MockBackend, MockConfig, MockMode, ScriptSegment};
+
+    fn mock(class_id: u16) -> MockBackend {
+        MockBackend::new(MockConfig {
+            mode: MockMode::Script(vec![ScriptSegment {
+                frames: 1,
+                bbox: Some([0.5, 0.5, 0.2, 0.2]),
+            }]),
+            confidence: 0.9,
+            class_id,
+            seed: None,
+        })
+    }
+
+    /// Class with the highest logit on the first query
+    fn top_class(output: &InferenceOutput) -> usize {
+        (0..output.logits.shape()[2])
+            .max_by(|&a, &b| output.logits[[0, 0, a]].total_cmp(&output.logits[[0, 0, b]]))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_runs_the_selected_session() {
+        let mut backend = SwitchableBackend::new(mock(0), mock(5));
+        let input = Array::zeros(IxDyn(&[1, 3, 4, 4]));
+
+        assert_eq!(top_class(&backend.infer(&input).unwrap()), 1);
+        assert!(backend.set_fallback(true));
+        assert_eq!(top_class(&backend.infer(&input).unwrap()), 6);
+        backend.set_fallback(false);
+        assert_eq!(top_class(&backend.infer(&input).unwrap()), 1);
+    }
+}
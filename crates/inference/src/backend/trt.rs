@@ -1,4 +1,4 @@
-use super::{InferenceBackend, InferenceOutput};
+use super::{InferenceBackend, InferenceOutput, encryption};
 use ndarray::{Array, IxDyn};
 use preprocess::PreprocessOutput;
 
@@ -23,6 +23,14 @@ mod ffi {
         #[namespace = "bridge"]
         unsafe fn load_engine(self: Pin<&mut RFDetrBackend>, path: *const c_char) -> bool;
 
+        // Maps to RFDetrBackend::load_engine_from_memory
+        #[namespace = "bridge"]
+        unsafe fn load_engine_from_memory(
+            self: Pin<&mut RFDetrBackend>,
+            data: *const u8,
+            size: usize,
+        ) -> bool;
+
         // Maps to RFDetrBackend::infer_raw (host-to-device copy)
         #[namespace = "bridge"]
         unsafe fn infer_raw(
@@ -66,9 +74,20 @@ impl InferenceBackend for TrtBackend {
             ));
         }
 
-        let c_path = CString::new(path)?;
+        // An encrypted engine is only ever decrypted in memory
+        let loaded = match encryption::decrypt_model(path)? {
+            Some(engine) => unsafe {
+                inner
+                    .pin_mut()
+                    .load_engine_from_memory(engine.as_ptr(), engine.len())
+            },
+            None => {
+                let c_path = CString::new(path)?;
+                unsafe { inner.pin_mut().load_engine(c_path.as_ptr()) }
+            }
+        };
 
-        if !unsafe { inner.pin_mut().load_engine(c_path.as_ptr()) } {
+        if !loaded {
             return Err(anyhow::anyhow!(
                 "Failed to load RF-DETR TensorRT engine from {}",
                 path
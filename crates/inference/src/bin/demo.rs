@@ -0,0 +1,117 @@
+//! The pipeline as a single binary, without `/dev/shm` or a model
+//!
+//! `demo [frames]` runs capture, inference and a detection consumer as threads
+//! sharing in-process buffers: synthetic frames at `DEMO_FPS` (default 10) go
+//! through preprocessing and the mock backend (`MOCK_SCRIPT` and the other
+//! `MOCK_*` variables apply), and each result is printed as it is read back.
+//! Stops after `frames` frames, 300 by default.
+
+use anyhow::{Context, Result};
+use bridge::{
+    Detection, DetectionReader, DetectionWriter, FrameReader, FrameWriter, InProcessBuffer,
+};
+use common::get_env;
+use inference::backend::mock::MockBackend;
+use inference::{InferenceBackend, InferenceConfig, InferenceService, logging::setup_logging};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 480;
+
+fn main() -> Result<()> {
+    let frames: u64 = match std::env::args().nth(1) {
+        Some(frames) => frames.parse().context("Usage: demo [frames]")?,
+        None => 300,
+    };
+    let interval = Duration::from_secs_f64(1.0 / get_env("DEMO_FPS", 10.0f64).max(0.1));
+
+    let config = InferenceConfig::from_env()?;
+    setup_logging(&config);
+
+    let frame_buffer = InProcessBuffer::frames()?;
+    let detection_buffer = InProcessBuffer::detections()?;
+    let mut frame_writer = FrameWriter::in_process(&frame_buffer)?;
+    let frame_reader = FrameReader::in_process(&frame_buffer)?;
+    let mut detection_writer = DetectionWriter::in_process(&detection_buffer)?;
+    let detection_reader = DetectionReader::in_process(&detection_buffer)?;
+
+    let mut service = InferenceService::new(MockBackend::load_model("")?, config);
+
+    // Channels stand in for the semaphores between the stages
+    let (frame_ready, frames_in) = mpsc::sync_channel::<()>(1);
+    let (detections_ready, detections_in) = mpsc::channel::<()>();
+
+    thread::scope(|s| {
+        s.spawn(move || {
+            for frame_number in 1..=frames {
+                let pixels = synthetic_frame(frame_number);
+                if let Err(e) =
+                    frame_writer.write_frame(0, &pixels, frame_number, WIDTH, HEIGHT, None)
+                {
+                    tracing::error!(error = %e, "Failed to write frame");
+                    break;
+                }
+                // Skipped while inference is busy, like drained frame signals
+                let _ = frame_ready.try_send(());
+                thread::sleep(interval);
+            }
+        });
+
+        s.spawn(move || {
+            for () in frames_in {
+                match service.process(&frame_reader, &mut detection_writer) {
+                    Ok(_) => {
+                        if detections_ready.send(()).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => tracing::error!(error = %e, "Failed to process frame"),
+                }
+            }
+        });
+
+        for () in detections_in {
+            match detection_reader.get_detections() {
+                Ok(Some(result)) => print_result(&result),
+                Ok(None) => {}
+                Err(e) => tracing::error!(error = %e, "Failed to read detections"),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Gray gradient scrolling one pixel per frame
+fn synthetic_frame(frame_number: u64) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity((WIDTH * HEIGHT * 3) as usize);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let value = ((x + y + frame_number as u32) % 256) as u8;
+            pixels.extend_from_slice(&[value, value, value]);
+        }
+    }
+    pixels
+}
+
+fn print_result(result: &schema::DetectionResult<'_>) {
+    let detections: Vec<String> = result
+        .detections()
+        .into_iter()
+        .flatten()
+        .filter_map(|d| Detection::try_from(&d).ok())
+        .map(|d| {
+            format!(
+                "class {} {:.2} [{:.0},{:.0},{:.0},{:.0}]",
+                d.class_id, d.confidence, d.x1, d.y1, d.x2, d.y2
+            )
+        })
+        .collect();
+    if detections.is_empty() {
+        println!("frame {}: empty", result.frame_number());
+    } else {
+        println!("frame {}: {}", result.frame_number(), detections.join(", "));
+    }
+}
@@ -0,0 +1,22 @@
+//! Encrypt a model or TensorRT engine for loading with the same key
+//!
+//! `encrypt_model <input> <output>`, the key being read from `MODEL_KEY_FILE`,
+//! `MODEL_KEY_KEYRING` or `MODEL_SECRET_KEY` as by the inference service.
+
+use anyhow::{Context, Result};
+use inference::backend::encryption::{self, KeySource};
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [input, output] = args.as_slice() else {
+        anyhow::bail!("Usage: encrypt_model <input> <output>");
+    };
+    let source = KeySource::from_env()
+        .context("Set MODEL_KEY_FILE, MODEL_KEY_KEYRING or MODEL_SECRET_KEY")?;
+
+    let model = std::fs::read(input).with_context(|| format!("Failed to read {}", input))?;
+    let encrypted = encryption::encrypt_model(&model, &source)?;
+    std::fs::write(output, encrypted).with_context(|| format!("Failed to write {}", output))?;
+    println!("Encrypted {} to {}", input, output);
+    Ok(())
+}
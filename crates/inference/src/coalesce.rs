@@ -0,0 +1,72 @@
+//! Fewer controller wakeups on idle scenes
+//!
+//! Every result is written to the detection buffer, but waking the controller
+//! for each empty one in an idle scene only has it count another empty frame.
+//! With `CONTROLLER_HEARTBEAT_MS` set, empty results are posted only while they
+//! matter:
+//! - the first empty result after one with detections, which ends a presence
+//! - every result while capture is not in Standby, so the controller can count
+//!   the empty frames that take it out of Tracking and decay the elevated trend
+//! - one per heartbeat otherwise, so the controller still sees fresh results
+//!
+//! Results with detections are always posted.
+
+use std::time::{Duration, Instant};
+
+pub struct PostCoalescer {
+    heartbeat: Duration,
+    last_post: Option<Instant>,
+    /// The previous result had detections
+    last_detected: bool,
+}
+
+impl PostCoalescer {
+    pub fn new(heartbeat: Duration) -> Self {
+        Self {
+            heartbeat,
+            last_post: None,
+            last_detected: false,
+        }
+    }
+
+    /// Whether to wake the controller for a result, `idle` while capture is in Standby
+    pub fn should_post(&mut self, detected: bool, idle: bool, now: Instant) -> bool {
+        let heartbeat_due = self
+            .last_post
+            .is_none_or(|last| now.duration_since(last) >= self.heartbeat);
+        let post = detected || self.last_detected || !idle || heartbeat_due;
+
+        self.last_detected = detected;
+        if post {
+            self.last_post = Some(now);
+        }
+        post
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_empty_results_wait_for_the_heartbeat() {
+        let mut coalescer = PostCoalescer::new(Duration::from_secs(1));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        // The first result goes out, then empties wait for the heartbeat
+        assert!(coalescer.should_post(false, true, at(0)));
+        assert!(!coalescer.should_post(false, true, at(100)));
+        assert!(!coalescer.should_post(false, true, at(900)));
+        assert!(coalescer.should_post(false, true, at(1000)));
+
+        // Detections, and the empty result ending them
+        assert!(coalescer.should_post(true, true, at(1100)));
+        assert!(coalescer.should_post(false, true, at(1200)));
+        assert!(!coalescer.should_post(false, true, at(1300)));
+
+        // Everything while capture is out of Standby
+        assert!(coalescer.should_post(false, false, at(1400)));
+        assert!(coalescer.should_post(false, false, at(1500)));
+    }
+}
@@ -1,5 +1,17 @@
-use common::{Environment, get_env, get_env_opt};
-use preprocess::DEFAULT_INPUT_SIZE;
+use crate::backend::encryption::KeySource;
+use crate::backend::mock::MockConfig;
+use crate::debug_dump::DebugDumpConfig;
+use crate::plates::PlateConfig;
+use crate::processing::{
+    calibration::ConfidenceCalibration, change::DetectionCacheConfig, filter::DetectionFilter,
+    masks::SceneMaskConfig, post::BoxFormat,
+};
+use crate::self_test::SelfTestConfig;
+use common::classes::{ClassGroups, ClassSet};
+use common::{ConfigCheck, Environment, Scheduling, get_env, get_env_opt};
+use preprocess::{ChannelOrder, DenoiseConfig, PreprocessProfile};
+use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum ExecutionProvider {
@@ -10,7 +22,7 @@ pub enum ExecutionProvider {
 
 impl ExecutionProvider {
     pub fn from_env() -> Self {
-        match std::env::var("EXECUTION_PROVIDER").ok() {
+        match get_env_opt::<String>("EXECUTION_PROVIDER") {
             Some(s) if s.eq_ignore_ascii_case("cuda") || s.eq_ignore_ascii_case("gpu") => {
                 Self::Cuda
             }
@@ -19,53 +31,441 @@ impl ExecutionProvider {
     }
 }
 
+/// Graph optimizations ONNX Runtime applies when loading the model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GraphOptimization {
+    Disable,
+    /// Constant folding and redundant node removal
+    Basic,
+    /// Basic plus node fusions
+    Extended,
+    /// Extended plus layout optimizations
+    #[default]
+    All,
+}
+
+impl FromStr for GraphOptimization {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "disable" | "none" => Ok(Self::Disable),
+            "basic" => Ok(Self::Basic),
+            "extended" => Ok(Self::Extended),
+            "all" => Ok(Self::All),
+            other => anyhow::bail!(
+                "Unknown graph optimization level {:?} (expected disable, basic, extended or all)",
+                other
+            ),
+        }
+    }
+}
+
+/// ONNX Runtime session options, ignored by the other backends
+///
+/// Capture and encoding share the SoC with inference, so the thread counts are
+/// worth tuning on edge devices rather than letting ORT take every core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrtSessionConfig {
+    /// Threads running one operator, 0 lets ORT use one per core
+    pub intra_threads: usize,
+    /// Threads running independent branches of the graph in parallel, the graph
+    /// runs sequentially when unset
+    pub inter_threads: Option<usize>,
+    pub optimization: GraphOptimization,
+    /// Pool CPU allocations across runs; disabling it lowers the resident memory
+    /// at the cost of an allocation per run
+    pub memory_arena: bool,
+    /// Pick deterministic kernels, so repeated runs give bit-identical outputs
+    pub deterministic: bool,
+}
+
+impl Default for OrtSessionConfig {
+    fn default() -> Self {
+        Self {
+            intra_threads: 4,
+            inter_threads: None,
+            optimization: GraphOptimization::All,
+            memory_arena: true,
+            deterministic: false,
+        }
+    }
+}
+
+impl OrtSessionConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let defaults = Self::default();
+        Ok(Self {
+            intra_threads: get_env("ORT_INTRA_THREADS", defaults.intra_threads),
+            inter_threads: get_env_opt("ORT_INTER_THREADS"),
+            optimization: get_env_opt::<String>("ORT_GRAPH_OPTIMIZATION")
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or(defaults.optimization),
+            memory_arena: get_env("ORT_MEMORY_ARENA", defaults.memory_arena),
+            deterministic: get_env("ORT_DETERMINISTIC", defaults.deterministic),
+        })
+    }
+
+    fn check(&self, check: &mut ConfigCheck) {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let threads = self.intra_threads + self.inter_threads.unwrap_or(0);
+        check.ensure(threads <= cores, || {
+            format!(
+                "ORT_INTRA_THREADS + ORT_INTER_THREADS ({threads}) exceed the {cores} cores, \
+                 inference would compete with capture for them"
+            )
+        });
+        if let Some(inter_threads) = self.inter_threads {
+            check.ensure(inter_threads > 0, || {
+                "ORT_INTER_THREADS of 0, leave it unset to run the graph sequentially".to_string()
+            });
+        }
+    }
+}
+
+/// Which backend runs inference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackendKind {
+    /// The model backend compiled in (ONNX Runtime or TensorRT)
+    #[default]
+    Model,
+    /// Synthetic detections, no model needed
+    Mock,
+}
+
+impl BackendKind {
+    pub fn from_env() -> anyhow::Result<Self> {
+        match get_env_opt::<String>("INFERENCE_BACKEND") {
+            None => Ok(Self::Model),
+            Some(s) => match s.to_ascii_lowercase().as_str() {
+                "model" | "ort" | "trt" => Ok(Self::Model),
+                "mock" => Ok(Self::Mock),
+                other => anyhow::bail!(
+                    "Unknown INFERENCE_BACKEND {:?} (expected model or mock)",
+                    other
+                ),
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct InferenceConfig {
     pub environment: Environment,
+    pub backend: BackendKind,
     pub model_path: String,
+    /// Normalization, resize mode and channel order the model expects
+    pub preprocess_profile: PreprocessProfile,
+    /// Model input size, the profile's unless overridden
     pub input_size: (u32, u32),
+    /// Channel order the model was trained on, the profile's unless overridden
+    pub model_color_order: ChannelOrder,
     pub poll_interval_ms: u64,
     pub confidence_threshold: f32,
+    /// Layout of the boxes emitted by the model
+    pub box_format: BoxFormat,
+    /// Confidence calibration fitted for the model, identity when unset
+    pub confidence_calibration: ConfidenceCalibration,
+    /// Area, aspect-ratio and border limits on detections, none when unset
+    pub detection_filter: DetectionFilter,
+    /// Regions of permanent false positives, learned or drawn, disabled when unset
+    pub scene_masks: Option<SceneMaskConfig>,
     pub otel_endpoint: Option<String>,
     /// Use GPU preprocessing (requires gpu-preprocess feature)
     pub use_gpu_preprocess: bool,
+    /// Preprocess capture's NVMM surfaces on Jetson (requires jetson feature)
+    pub use_nvmm_preprocess: bool,
     /// Maximum expected input image size for GPU preprocessing
     pub max_input_size: (u32, u32),
+    /// License-plate stage, disabled when unset
+    pub plates: Option<PlateConfig>,
+    /// Budget for preprocess + inference; results past it are marked late
+    pub frame_deadline: Option<Duration>,
+    /// Write late results without detections instead of postprocessing them
+    pub skip_late_postprocess: bool,
+    /// Longest an idle controller goes without a result when empty results are
+    /// coalesced, every result is posted when unset
+    pub controller_heartbeat: Option<Duration>,
+    /// Republish detections on unchanged frames instead of running the model,
+    /// disabled when unset
+    pub detection_cache: Option<DetectionCacheConfig>,
+    /// Temporal denoise of dark frames before inference, disabled when unset
+    pub denoise: Option<DenoiseConfig>,
+    /// Saves sampled frames, tensors and detections, unavailable when unset
+    pub debug_dump: Option<DebugDumpConfig>,
+    /// Scheduled reference image check, disabled when unset
+    pub self_test: Option<SelfTestConfig>,
+    /// Cores of the inference threads and real-time priority of the processing loop
+    pub scheduling: Scheduling,
+    /// Lighter model run while the SoC is thermally throttled, disabled when unset
+    pub precision_fallback: Option<PrecisionFallbackConfig>,
+    /// Classes whose detections carry an appearance descriptor, none when unset
+    pub appearance_classes: Option<ClassSet>,
+    /// Threads, graph optimizations and memory arena of the ONNX Runtime session
+    pub ort_session: OrtSessionConfig,
 }
 
 impl InferenceConfig {
     /// Load configuration from environment variables with sensible defaults
     pub fn from_env() -> anyhow::Result<Self> {
+        let preprocess_profile: PreprocessProfile = get_env_opt::<String>("PREPROCESS_PROFILE")
+            .map(|s| s.parse())
+            .transpose()?
+            .unwrap_or_default();
         Ok(Self {
             environment: Environment::from_env(),
+            backend: BackendKind::from_env()?,
             model_path: get_env("MODEL_PATH", "/models/rfdetr_int8.engine".to_string()),
             input_size: (
-                get_env("INPUT_WIDTH", DEFAULT_INPUT_SIZE.0),
-                get_env("INPUT_HEIGHT", DEFAULT_INPUT_SIZE.1),
+                get_env("INPUT_WIDTH", preprocess_profile.input_size.0),
+                get_env("INPUT_HEIGHT", preprocess_profile.input_size.1),
             ),
+            model_color_order: get_env_opt::<String>("MODEL_COLOR_ORDER")
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or(preprocess_profile.channel_order),
+            preprocess_profile,
             poll_interval_ms: get_env("POLL_INTERVAL_MS", 100),
             confidence_threshold: get_env("CONFIDENCE_THRESHOLD", 0.7),
+            box_format: get_env_opt::<String>("BOX_FORMAT")
+                .map(|s| s.parse())
+                .transpose()?
+                .unwrap_or_default(),
+            confidence_calibration: get_env_opt::<String>("CONFIDENCE_CALIBRATION_PATH")
+                .map(ConfidenceCalibration::load)
+                .transpose()?
+                .unwrap_or_default(),
+            detection_filter: get_env_opt::<String>("DETECTION_FILTERS_PATH")
+                .map(DetectionFilter::load)
+                .transpose()?
+                .unwrap_or_default(),
+            scene_masks: SceneMaskConfig::from_env(),
             otel_endpoint: get_env_opt("OTEL_EXPORTER_OTLP_ENDPOINT"),
             use_gpu_preprocess: get_env("GPU_PREPROCESS", false),
+            use_nvmm_preprocess: get_env("NVMM_PREPROCESS", false),
             max_input_size: (
                 get_env("MAX_INPUT_WIDTH", 3840),
                 get_env("MAX_INPUT_HEIGHT", 2160),
             ),
+            plates: PlateConfig::from_env()?,
+            frame_deadline: get_env_opt("FRAME_DEADLINE_MS").map(Duration::from_millis),
+            skip_late_postprocess: get_env("SKIP_LATE_POSTPROCESS", false),
+            controller_heartbeat: get_env_opt("CONTROLLER_HEARTBEAT_MS").map(Duration::from_millis),
+            detection_cache: DetectionCacheConfig::from_env(),
+            denoise: denoise_from_env(),
+            debug_dump: DebugDumpConfig::from_env(),
+            self_test: SelfTestConfig::from_env()?,
+            scheduling: Scheduling::from_env()?,
+            precision_fallback: PrecisionFallbackConfig::from_env(),
+            // Class ids, COCO labels or groups, e.g. `person`
+            appearance_classes: get_env_opt::<String>("APPEARANCE_CLASSES")
+                .map(|list| ClassGroups::from_env()?.resolve_list(&list))
+                .transpose()?,
+            ort_session: OrtSessionConfig::from_env()?,
         })
     }
 
+    /// Check the models and reference images are readable, the thresholds are in
+    /// range and the preprocessing path is built in and supports the profile
+    pub fn check(&self, check: &mut ConfigCheck) {
+        match self.backend {
+            BackendKind::Model => {
+                check.readable_file("MODEL_PATH", &self.model_path);
+                if cfg!(feature = "ort-backend") {
+                    self.ort_session.check(check);
+                }
+                if let Some(KeySource::File(path)) = KeySource::from_env() {
+                    check.readable_file("MODEL_KEY_FILE", path);
+                }
+            }
+            BackendKind::Mock => {
+                if let Err(e) = MockConfig::from_env() {
+                    check.problem(format!("{e:#}"));
+                }
+            }
+        }
+        check.in_range(
+            "CONFIDENCE_THRESHOLD",
+            self.confidence_threshold,
+            0.01..=1.0,
+        );
+        let (width, height) = self.input_size;
+        check.ensure(width > 0 && height > 0, || {
+            format!("INPUT_WIDTH x INPUT_HEIGHT {width}x{height} is empty")
+        });
+        let (max_width, max_height) = self.max_input_size;
+        check.ensure(max_width > 0 && max_height > 0, || {
+            format!("MAX_INPUT_WIDTH x MAX_INPUT_HEIGHT {max_width}x{max_height} is empty")
+        });
+        if let Some(deadline) = self.frame_deadline {
+            check.ensure(!deadline.is_zero(), || {
+                "FRAME_DEADLINE_MS of 0 marks every result late".to_string()
+            });
+        }
+        if let Some(heartbeat) = self.controller_heartbeat {
+            check.ensure(!heartbeat.is_zero(), || {
+                "CONTROLLER_HEARTBEAT_MS of 0 posts every result, leave it unset instead"
+                    .to_string()
+            });
+        }
+        if let Some(cache) = &self.detection_cache {
+            check.in_range("DETECTION_CACHE_THRESHOLD", cache.threshold, 0.0..=255.0);
+            check.ensure(!cache.max_age.is_zero(), || {
+                "DETECTION_CACHE_MAX_AGE_MS of 0 never reuses detections".to_string()
+            });
+        }
+        if let Some(masks) = &self.scene_masks {
+            check.in_range("SCENE_MASK_COVERAGE", masks.coverage, 0.05..=1.0);
+            check.ensure(!masks.dwell.is_zero(), || {
+                "SCENE_MASK_DWELL_SECS of 0 counts every detection as persistent".to_string()
+            });
+            if let Some(dir) = masks.path.parent().filter(|d| !d.as_os_str().is_empty()) {
+                check.creatable_dir("SCENE_MASKS_PATH", dir);
+            }
+        }
+        if self.use_nvmm_preprocess {
+            check.ensure(cfg!(feature = "jetson"), || {
+                "NVMM_PREPROCESS needs a build with the jetson feature".to_string()
+            });
+        }
+        if self.use_gpu_preprocess {
+            check.ensure(cfg!(feature = "gpu-preprocess"), || {
+                "GPU_PREPROCESS needs a build with the gpu-preprocess feature".to_string()
+            });
+        }
+        if self.use_gpu_preprocess || self.use_nvmm_preprocess {
+            check.ensure(self.preprocess_profile.gpu_compatible(), || {
+                format!(
+                    "PREPROCESS_PROFILE {} is only supported by CPU preprocessing",
+                    self.preprocess_profile
+                )
+            });
+        }
+        if let Some(plates) = &self.plates {
+            check.readable_file("PLATE_DETECTOR_MODEL_PATH", &plates.detector_model_path);
+            check.readable_file("PLATE_OCR_MODEL_PATH", &plates.ocr_model_path);
+        }
+        if let Some(debug_dump) = &self.debug_dump {
+            check.creatable_dir("DEBUG_DUMP_DIR", &debug_dump.dir);
+        }
+        if let Some(self_test) = &self.self_test {
+            check.readable_file("SELF_TEST_IMAGE", &self_test.image_path);
+            check.in_range(
+                "SELF_TEST_MIN_CONFIDENCE",
+                self_test.min_confidence,
+                0.0..=1.0,
+            );
+            check.in_range("SELF_TEST_MIN_IOU", self_test.min_iou, 0.0..=1.0);
+        }
+        self.scheduling.check(check);
+        if let Some(fallback) = &self.precision_fallback {
+            if self.backend == BackendKind::Model {
+                check.readable_file("FALLBACK_MODEL_PATH", &fallback.model_path);
+            }
+            check.ensure(fallback.recover_celsius < fallback.throttle_celsius, || {
+                "THERMAL_RECOVER_CELSIUS must be below THERMAL_THROTTLE_CELSIUS".to_string()
+            });
+        }
+    }
+
     /// Create default configuration for testing
     #[cfg(test)]
     pub fn test_default() -> Self {
         Self {
             environment: Environment::Development,
+            backend: BackendKind::Model,
             model_path: "/models/rfdetr.onnx".to_string(),
-            input_size: DEFAULT_INPUT_SIZE,
+            preprocess_profile: PreprocessProfile::default(),
+            input_size: PreprocessProfile::RFDETR.input_size,
+            model_color_order: ChannelOrder::Rgb,
             poll_interval_ms: 100,
             confidence_threshold: 0.7,
+            box_format: BoxFormat::default(),
+            confidence_calibration: ConfidenceCalibration::default(),
+            detection_filter: DetectionFilter::default(),
+            scene_masks: None,
             otel_endpoint: None,
             use_gpu_preprocess: false,
+            use_nvmm_preprocess: false,
             max_input_size: (1920, 1080),
+            plates: None,
+            frame_deadline: None,
+            skip_late_postprocess: false,
+            controller_heartbeat: None,
+            detection_cache: None,
+            denoise: None,
+            debug_dump: None,
+            self_test: None,
+            scheduling: Scheduling::default(),
+            precision_fallback: None,
+            appearance_classes: None,
+            ort_session: OrtSessionConfig::default(),
         }
     }
 }
+
+/// Switch to a lighter model (FP16, smaller) while the SoC runs hot
+///
+/// The hottest zone of `/sys/class/thermal` is read every `poll_interval`; the
+/// fallback runs from `throttle_celsius` until the SoC cools below
+/// `recover_celsius`. It must take the same input size as `MODEL_PATH` and emit
+/// the same outputs.
+#[derive(Debug, Clone)]
+pub struct PrecisionFallbackConfig {
+    pub model_path: String,
+    pub throttle_celsius: f32,
+    /// Defaults to 10 degrees below `throttle_celsius`
+    pub recover_celsius: f32,
+    pub poll_interval: Duration,
+}
+
+impl PrecisionFallbackConfig {
+    /// Enabled when `FALLBACK_MODEL_PATH` is set
+    fn from_env() -> Option<Self> {
+        let throttle_celsius = get_env("THERMAL_THROTTLE_CELSIUS", 85.0);
+        Some(Self {
+            model_path: get_env_opt("FALLBACK_MODEL_PATH")?,
+            throttle_celsius,
+            recover_celsius: get_env("THERMAL_RECOVER_CELSIUS", throttle_celsius - 10.0),
+            poll_interval: Duration::from_millis(get_env("THERMAL_POLL_MS", 5000)),
+        })
+    }
+}
+
+fn denoise_from_env() -> Option<DenoiseConfig> {
+    if !get_env("DENOISE", false) {
+        return None;
+    }
+    let defaults = DenoiseConfig::default();
+    Some(DenoiseConfig {
+        strength: get_env("DENOISE_STRENGTH", defaults.strength).clamp(0.0, 1.0),
+        motion_threshold: get_env("DENOISE_MOTION_THRESHOLD", defaults.motion_threshold),
+        // Denoise every frame instead of only dark ones
+        low_light_brightness: if get_env("DENOISE_ALWAYS", false) {
+            None
+        } else {
+            defaults
+                .low_light_brightness
+                .map(|default| get_env("DENOISE_LOW_LIGHT_BRIGHTNESS", default))
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_graph_optimization_parsing() {
+        assert_eq!(
+            "basic".parse::<GraphOptimization>().unwrap(),
+            GraphOptimization::Basic
+        );
+        assert_eq!(
+            "ALL".parse::<GraphOptimization>().unwrap(),
+            GraphOptimization::All
+        );
+        assert!("level3".parse::<GraphOptimization>().is_err());
+    }
+}
@@ -0,0 +1,263 @@
+//! Debug dump of frames as the model saw them
+//!
+//! Every `DEBUG_DUMP_EVERY`th processed frame is saved to `DEBUG_DUMP_DIR` as three
+//! files sharing a `<camera_id>_<frame_number>` id, for investigating
+//! preprocessing or model discrepancies offline:
+//! - `<id>_frame.ppm`: the RGB frame read from shared memory
+//! - `<id>_tensor.npy`: the preprocessed input tensor, float32 NCHW
+//! - `<id>_detections.json`: the detections and the letterbox transform
+//!
+//! Dumping starts enabled with `DEBUG_DUMP=true`, SIGUSR1 toggles it at runtime.
+
+use crate::processing::post::TransformParams;
+use anyhow::{Context, Result};
+use bridge::Detection;
+use common::{get_env, get_env_opt};
+use ndarray::ArrayD;
+use preprocess::PreprocessOutput;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug, Clone)]
+pub struct DebugDumpConfig {
+    pub dir: PathBuf,
+    /// Dump one frame out of this many
+    pub every: u64,
+    /// Dump from startup instead of waiting for SIGUSR1
+    pub enabled: bool,
+}
+
+impl DebugDumpConfig {
+    /// Available when `DEBUG_DUMP_DIR` is set
+    pub fn from_env() -> Option<Self> {
+        let dir = get_env_opt::<String>("DEBUG_DUMP_DIR")?;
+        Some(Self {
+            dir: PathBuf::from(dir),
+            every: get_env("DEBUG_DUMP_EVERY", 30u64).max(1),
+            enabled: get_env("DEBUG_DUMP", false),
+        })
+    }
+}
+
+/// What was computed on a dumped frame
+pub struct DumpRecord<'a> {
+    pub camera_id: u32,
+    pub frame_number: u64,
+    pub pixels: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+    pub tensor: &'a PreprocessOutput,
+    pub transform: &'a TransformParams,
+    pub detections: &'a [Detection],
+    pub late: bool,
+}
+
+pub struct DebugDump {
+    config: DebugDumpConfig,
+    enabled: bool,
+    /// Set by the SIGUSR1 handler
+    toggle: Arc<AtomicBool>,
+    /// Frames seen since dumping was enabled
+    frames: u64,
+}
+
+impl DebugDump {
+    pub fn new(config: DebugDumpConfig) -> Result<Self> {
+        fs::create_dir_all(&config.dir)
+            .with_context(|| format!("Failed to create debug dump dir {}", config.dir.display()))?;
+
+        let toggle = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(signal_hook::consts::SIGUSR1, toggle.clone())
+            .context("Failed to register SIGUSR1 handler")?;
+
+        tracing::info!(
+            dir = %config.dir.display(),
+            every = config.every,
+            enabled = config.enabled,
+            "Debug dump available, toggle it with SIGUSR1"
+        );
+        Ok(Self {
+            enabled: config.enabled,
+            config,
+            toggle,
+            frames: 0,
+        })
+    }
+
+    /// Whether the current frame should be dumped. Call once per processed frame.
+    pub fn due(&mut self) -> bool {
+        if self.toggle.swap(false, Ordering::Relaxed) {
+            self.enabled = !self.enabled;
+            self.frames = 0;
+            tracing::info!(enabled = self.enabled, "Debug dump toggled");
+        }
+        if !self.enabled {
+            return false;
+        }
+
+        self.frames += 1;
+        (self.frames - 1).is_multiple_of(self.config.every)
+    }
+
+    /// Save a frame's files. Failures are logged, never fatal to inference.
+    pub fn write(&self, record: &DumpRecord<'_>) {
+        if let Err(e) = self.try_write(record) {
+            tracing::warn!(
+                frame_number = record.frame_number,
+                "Failed to dump frame: {:#}",
+                e
+            );
+        }
+    }
+
+    fn try_write(&self, record: &DumpRecord<'_>) -> Result<()> {
+        let _s = common::span!("debug_dump");
+        let id = format!("{}_{}", record.camera_id, record.frame_number);
+
+        write_ppm(
+            &self.config.dir.join(format!("{id}_frame.ppm")),
+            record.pixels,
+            record.width,
+            record.height,
+        )?;
+
+        match record.tensor {
+            PreprocessOutput::Cpu(tensor) => {
+                write_npy(&self.config.dir.join(format!("{id}_tensor.npy")), tensor)?
+            }
+            PreprocessOutput::Gpu { .. } => {
+                tracing::debug!(
+                    frame_number = record.frame_number,
+                    "Tensor is on the GPU, not dumped"
+                )
+            }
+        }
+
+        let transform = record.transform;
+        let detections = serde_json::json!({
+            "camera_id": record.camera_id,
+            "frame_number": record.frame_number,
+            "late": record.late,
+            "transform": {
+                "orig_width": transform.orig_width,
+                "orig_height": transform.orig_height,
+                "input_width": transform.input_width,
+                "input_height": transform.input_height,
+                "scale": transform.scale,
+                "offset_x": transform.offset_x,
+                "offset_y": transform.offset_y,
+            },
+            "detections": record.detections,
+        });
+        fs::write(
+            self.config.dir.join(format!("{id}_detections.json")),
+            serde_json::to_vec_pretty(&detections)?,
+        )?;
+
+        tracing::debug!(id, "Dumped frame");
+        Ok(())
+    }
+}
+
+/// Binary PPM (P6) of an RGB frame
+fn write_ppm(path: &Path, rgb: &[u8], width: u32, height: u32) -> Result<()> {
+    let len = (width * height * 3) as usize;
+    let pixels = rgb
+        .get(..len)
+        .with_context(|| format!("Frame has {} bytes, expected {}", rgb.len(), len))?;
+
+    let mut file = BufWriter::new(fs::File::create(path)?);
+    write!(file, "P6\n{width} {height}\n255\n")?;
+    file.write_all(pixels)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// NumPy `.npy` header for a little-endian float32 array of `shape`
+fn npy_header(shape: &[usize]) -> Vec<u8> {
+    let shape = match shape {
+        [n] => format!("({n},)"),
+        _ => format!(
+            "({})",
+            shape
+                .iter()
+                .map(usize::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let mut dict = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {shape}, }}");
+
+    // Magic, version and length take 10 bytes; the whole header ends on a 64 byte
+    // boundary with a newline
+    let unpadded = 10 + dict.len() + 1;
+    dict.push_str(&" ".repeat(unpadded.next_multiple_of(64) - unpadded));
+    dict.push('\n');
+
+    let mut header = b"\x93NUMPY\x01\x00".to_vec();
+    header.extend_from_slice(&(dict.len() as u16).to_le_bytes());
+    header.extend_from_slice(dict.as_bytes());
+    header
+}
+
+fn write_npy(path: &Path, tensor: &ArrayD<f32>) -> Result<()> {
+    let mut file = BufWriter::new(fs::File::create(path)?);
+    file.write_all(&npy_header(tensor.shape()))?;
+    for value in tensor.iter() {
+        file.write_all(&value.to_le_bytes())?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_npy_header_is_aligned() {
+        let header = npy_header(&[1, 3, 640, 640]);
+
+        assert_eq!(header.len() % 64, 0);
+        assert!(header.starts_with(b"\x93NUMPY\x01\x00"));
+        let dict = std::str::from_utf8(&header[10..]).unwrap();
+        assert!(
+            dict.starts_with(
+                "{'descr': '<f4', 'fortran_order': False, 'shape': (1, 3, 640, 640), }"
+            )
+        );
+        assert!(dict.ends_with('\n'));
+
+        assert!(
+            std::str::from_utf8(&npy_header(&[5])[10..])
+                .unwrap()
+                .contains("'shape': (5,)")
+        );
+    }
+
+    #[test]
+    fn test_dumps_every_nth_frame_while_enabled() {
+        let mut dump = DebugDump {
+            config: DebugDumpConfig {
+                dir: PathBuf::new(),
+                every: 3,
+                enabled: false,
+            },
+            enabled: false,
+            toggle: Arc::new(AtomicBool::new(false)),
+            frames: 0,
+        };
+
+        assert!(!dump.due());
+
+        dump.toggle.store(true, Ordering::Relaxed);
+        let due: Vec<bool> = (0..5).map(|_| dump.due()).collect();
+        assert_eq!(due, [true, false, false, true, false]);
+
+        dump.toggle.store(true, Ordering::Relaxed);
+        assert!(!dump.due());
+    }
+}
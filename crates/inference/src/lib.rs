@@ -1,9 +1,14 @@
 pub mod backend;
+pub mod coalesce;
 pub mod config;
+pub mod debug_dump;
 pub mod logging;
+pub mod metrics;
+pub mod plates;
 pub mod processing;
+pub mod self_test;
 pub mod service;
 
 pub use backend::{InferenceBackend, InferenceOutput};
-pub use config::{ExecutionProvider, InferenceConfig};
+pub use config::{BackendKind, ExecutionProvider, InferenceConfig, OrtSessionConfig};
 pub use service::InferenceService;
@@ -1,5 +1,9 @@
-use common::TelemetryGuard;
-use inference::{InferenceBackend, InferenceConfig, InferenceService, logging::setup_logging};
+use common::{Dependency, Readiness, TelemetryGuard};
+use inference::backend::mock::MockBackend;
+use inference::backend::switchable::SwitchableBackend;
+use inference::{
+    BackendKind, InferenceBackend, InferenceConfig, InferenceService, logging::setup_logging,
+};
 
 #[cfg(all(feature = "ort-backend", not(feature = "trt-backend")))]
 use inference::backend::ort::OrtBackend as Backend;
@@ -12,6 +16,13 @@ compile_error!("At least one backend feature must be enabled: 'ort-backend' or '
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    common::load_profile()?;
+    if common::config_check::requested() {
+        return common::config_check::run(|check| {
+            InferenceConfig::from_env()?.check(check);
+            Ok(())
+        });
+    }
     let config = InferenceConfig::from_env()?;
 
     // TelemetryGuard initializes the tracing subscriber, so only call setup_logging if not using telemetry
@@ -31,10 +42,40 @@ async fn main() -> anyhow::Result<()> {
         "Loaded configuration"
     );
 
+    let readiness = Readiness::from_env(&[
+        Dependency::ModelLoaded,
+        Dependency::FrameBuffer,
+        Dependency::Semaphores,
+    ]);
+
+    match config.backend {
+        BackendKind::Model => run::<Backend>(config, &readiness),
+        BackendKind::Mock => run::<MockBackend>(config, &readiness),
+    }
+}
+
+fn run<B: InferenceBackend>(config: InferenceConfig, readiness: &Readiness) -> anyhow::Result<()> {
+    // Runtime threads created by the model load inherit the affinity, but only the
+    // processing loop on this thread runs under SCHED_FIFO
+    config.scheduling.apply_affinity();
     tracing::info!("Loading inference model");
-    let backend = Backend::load_model(&config.model_path)?;
-    tracing::info!("Model loaded successfully");
+    if let Some(fallback) = &config.precision_fallback {
+        tracing::info!(fallback_model_path = %fallback.model_path, "Loading fallback model");
+        let backend = SwitchableBackend::<B>::load(&config.model_path, &fallback.model_path)?;
+        return serve(backend, config, readiness);
+    }
+    let backend = B::load_model(&config.model_path)?;
+    serve(backend, config, readiness)
+}
+
+fn serve<B: InferenceBackend>(
+    backend: B,
+    config: InferenceConfig,
+    readiness: &Readiness,
+) -> anyhow::Result<()> {
+    readiness.mark_ready(Dependency::ModelLoaded);
+    config.scheduling.apply_realtime();
 
     let service = InferenceService::new(backend, config);
-    service.run()
+    service.run(readiness)
 }
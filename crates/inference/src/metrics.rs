@@ -0,0 +1,175 @@
+//! OpenTelemetry instruments of the inference loop
+//!
+//! Exported through the OTLP pipeline set up by `common::TelemetryGuard`; without
+//! an endpoint the global meter is a no-op.
+
+use opentelemetry::{
+    KeyValue, global,
+    metrics::{Counter, Gauge, Histogram},
+};
+use std::collections::BTreeMap;
+
+/// COCO class id of a person
+const PERSON_CLASS_ID: u16 = 0;
+
+pub struct InferenceMetrics {
+    duration: Histogram<f64>,
+    frames: Counter<u64>,
+    skipped: Counter<u64>,
+    late: Counter<u64>,
+    cached: Counter<u64>,
+    wakeups_skipped: Counter<u64>,
+    truncated: Counter<u64>,
+    detections: Counter<u64>,
+    class_detections: Counter<u64>,
+    persons: Gauge<u64>,
+    self_tests: Counter<u64>,
+    fallback: Gauge<u64>,
+}
+
+impl InferenceMetrics {
+    pub fn new(meter_name: &'static str) -> Self {
+        let meter = global::meter(meter_name);
+        let latency_buckets = [
+            0.001, 0.002, 0.005, 0.007, 0.01, 0.015, 0.02, 0.025, 0.03, 0.04, 0.05, 0.075, 0.1,
+            0.15, 0.2, 0.5,
+        ];
+
+        Self {
+            duration: meter
+                .f64_histogram("inference_duration_seconds")
+                .with_description(
+                    "Time to process a single frame (preprocess + infer + postprocess)",
+                )
+                .with_unit("s")
+                .with_boundaries(latency_buckets.to_vec())
+                .build(),
+            frames: meter
+                .u64_counter("inference_frames_total")
+                .with_description("Total frames processed")
+                .build(),
+            skipped: meter
+                .u64_counter("inference_frames_skipped_total")
+                .with_description("Total frames skipped (processing too slow)")
+                .build(),
+            late: meter
+                .u64_counter("inference_frames_late_total")
+                .with_description("Total frames that overran the per-frame deadline")
+                .build(),
+            cached: meter
+                .u64_counter("inference_frames_cached_total")
+                .with_description(
+                    "Total unchanged frames given the previous detections without running the model",
+                )
+                .build(),
+            wakeups_skipped: meter
+                .u64_counter("inference_controller_wakeups_skipped_total")
+                .with_description("Empty results written without waking the controller")
+                .build(),
+            truncated: meter
+                .u64_counter("inference_detections_truncated_total")
+                .with_description(
+                    "Least confident detections left out of crowded results to fit the buffer",
+                )
+                .build(),
+            detections: meter
+                .u64_counter("inference_detections_total")
+                .with_description("Total detections produced")
+                .build(),
+            class_detections: meter
+                .u64_counter("inference_class_detections_total")
+                .with_description("Detections produced, by COCO class id")
+                .build(),
+            persons: meter
+                .u64_gauge("inference_persons_detected")
+                .with_description("Persons detected in the latest frame")
+                .build(),
+            self_tests: meter
+                .u64_counter("inference_self_tests_total")
+                .with_description("Self-test runs, by outcome")
+                .build(),
+            fallback: meter
+                .u64_gauge("inference_fallback_active")
+                .with_description("1 while the fallback model runs because the SoC is throttled")
+                .build(),
+        }
+    }
+
+    pub fn record_skipped(&self, skipped: u64) {
+        self.skipped.add(skipped, &[]);
+    }
+
+    pub fn record_self_test(&self, passed: bool) {
+        self.self_tests.add(1, &[KeyValue::new("passed", passed)]);
+    }
+
+    /// Record a processed frame and the classes of its detections
+    pub fn record_frame(&self, elapsed_secs: f64, class_ids: &[u16], late: bool) {
+        self.duration.record(elapsed_secs, &[]);
+        self.frames.add(1, &[]);
+        if late {
+            self.late.add(1, &[]);
+        }
+        self.record_detections(class_ids);
+    }
+
+    /// Record a frame given the detections of an earlier one, which took no
+    /// inference time worth reporting
+    pub fn record_cached(&self, class_ids: &[u16]) {
+        self.frames.add(1, &[]);
+        self.cached.add(1, &[]);
+        self.record_detections(class_ids);
+    }
+
+    pub fn record_truncated(&self, detections: usize) {
+        if detections > 0 {
+            self.truncated.add(detections as u64, &[]);
+        }
+    }
+
+    pub fn record_fallback(&self, active: bool) {
+        self.fallback.record(active as u64, &[]);
+    }
+
+    pub fn record_wakeup_skipped(&self) {
+        self.wakeups_skipped.add(1, &[]);
+    }
+
+    fn record_detections(&self, class_ids: &[u16]) {
+        self.detections.add(class_ids.len() as u64, &[]);
+
+        let counts = class_counts(class_ids);
+        for (&class_id, &count) in &counts {
+            self.class_detections
+                .add(count, &[KeyValue::new("class_id", class_id as i64)]);
+        }
+        // Recorded on every frame so an empty scene reads 0 rather than the last count
+        self.persons
+            .record(counts.get(&PERSON_CLASS_ID).copied().unwrap_or(0), &[]);
+    }
+}
+
+/// Number of detections per class id
+fn class_counts(class_ids: &[u16]) -> BTreeMap<u16, u64> {
+    let mut counts = BTreeMap::new();
+    for &class_id in class_ids {
+        *counts.entry(class_id).or_default() += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_class_counts() {
+        let counts = class_counts(&[0, 2, 0, 16, 0]);
+
+        assert_eq!(
+            counts.into_iter().collect::<Vec<_>>(),
+            [(0, 3), (2, 1), (16, 1)]
+        );
+        assert!(class_counts(&[]).is_empty());
+    }
+}
@@ -0,0 +1,478 @@
+//! License-plate reading on detected vehicles
+//!
+//! Optional cascaded stage for gate automation: vehicles whose box center lies in
+//! the configured zone are cropped from the frame, a plate detector locates the
+//! plate inside the crop and an OCR model reads it. Plates are published with the
+//! detections in `DetectionResult.plates`.
+//!
+//! Model contract (ONNX, RGB NCHW scaled to 0-1, plain resize without letterbox):
+//! - detector: input `input` [1, 3, H, W], output `boxes` [1, N, 5] with x1, y1, x2,
+//!   y2 normalized to the crop followed by the score
+//! - OCR: input `input` [1, 3, H, W], output `logits` [1, T, C], greedy CTC with the
+//!   blank at class 0 and class `c` mapping to `charset[c - 1]`
+
+use anyhow::{Context, Result};
+use bridge::Detection;
+use common::{Rect, get_env, get_env_opt};
+use ndarray::Array4;
+
+/// COCO car, motorcycle, bus and truck
+const DEFAULT_VEHICLE_CLASSES: &str = "2,3,5,7";
+const DEFAULT_CHARSET: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+#[derive(Debug, Clone)]
+pub struct PlateConfig {
+    pub detector_model_path: String,
+    pub ocr_model_path: String,
+    /// Detector input as (width, height)
+    pub detector_input_size: (u32, u32),
+    /// OCR input as (width, height)
+    pub ocr_input_size: (u32, u32),
+    /// Classes the stage runs on
+    pub vehicle_classes: Vec<u16>,
+    /// Only vehicles centered in this zone are read, the whole frame when unset
+    pub zone: Option<Rect>,
+    pub min_plate_confidence: f32,
+    pub charset: Vec<char>,
+}
+
+impl PlateConfig {
+    /// Enabled when `PLATE_DETECTOR_MODEL_PATH` is set
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(detector_model_path) = get_env_opt::<String>("PLATE_DETECTOR_MODEL_PATH") else {
+            return Ok(None);
+        };
+
+        let vehicle_classes = get_env("PLATE_VEHICLE_CLASSES", DEFAULT_VEHICLE_CLASSES.to_string())
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse()
+                    .with_context(|| format!("Invalid class id in PLATE_VEHICLE_CLASSES: {:?}", s))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Some(Self {
+            detector_model_path,
+            ocr_model_path: get_env_opt("PLATE_OCR_MODEL_PATH")
+                .context("PLATE_OCR_MODEL_PATH is required with PLATE_DETECTOR_MODEL_PATH")?,
+            detector_input_size: parse_size(&get_env(
+                "PLATE_DETECTOR_INPUT_SIZE",
+                "320x320".to_string(),
+            ))?,
+            ocr_input_size: parse_size(&get_env("PLATE_OCR_INPUT_SIZE", "94x24".to_string()))?,
+            vehicle_classes,
+            zone: get_env_opt::<String>("PLATE_ZONE")
+                .map(|z| Rect::parse(&z).with_context(|| format!("Invalid PLATE_ZONE {z:?}")))
+                .transpose()?,
+            min_plate_confidence: get_env("PLATE_MIN_CONFIDENCE", 0.5),
+            charset: get_env("PLATE_OCR_CHARSET", DEFAULT_CHARSET.to_string())
+                .chars()
+                .collect(),
+        }))
+    }
+
+    /// Vehicles the stage should read, as indices into `detections`
+    pub fn candidates(&self, detections: &[Detection], width: u32, height: u32) -> Vec<usize> {
+        detections
+            .iter()
+            .enumerate()
+            .filter(|(_, det)| self.vehicle_classes.contains(&det.class_id))
+            .filter(|(_, det)| {
+                self.zone.is_none_or(|zone| {
+                    zone.contains_center([det.x1, det.y1, det.x2, det.y2], (width, height))
+                })
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+/// Parse `WIDTHxHEIGHT`
+fn parse_size(value: &str) -> Result<(u32, u32)> {
+    let (w, h) = value
+        .split_once('x')
+        .with_context(|| format!("Invalid size {:?}, expected WIDTHxHEIGHT", value))?;
+    Ok((
+        w.trim().parse().context("Invalid width")?,
+        h.trim().parse().context("Invalid height")?,
+    ))
+}
+
+/// Plate read on a vehicle, in the coordinates of the detections it was read on
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlateRead {
+    pub detection_index: usize,
+    pub x1: f32,
+    pub y1: f32,
+    pub x2: f32,
+    pub y2: f32,
+    pub text: String,
+    pub confidence: f32,
+}
+
+impl PlateRead {
+    /// Same plate with its box mapped from a `from` sized frame onto a `to` sized one
+    #[cfg_attr(not(feature = "ort-backend"), allow(dead_code))]
+    pub fn rescaled(self, from: (u32, u32), to: (u32, u32)) -> Self {
+        let sx = to.0 as f32 / from.0.max(1) as f32;
+        let sy = to.1 as f32 / from.1.max(1) as f32;
+        Self {
+            x1: self.x1 * sx,
+            y1: self.y1 * sy,
+            x2: self.x2 * sx,
+            y2: self.y2 * sy,
+            ..self
+        }
+    }
+}
+
+/// Pixel region of an RGB frame
+#[cfg_attr(not(feature = "ort-backend"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Crop {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+#[cfg_attr(not(feature = "ort-backend"), allow(dead_code))]
+impl Crop {
+    /// Region covered by a box, clamped to the frame; `None` if empty
+    fn from_box(x1: f32, y1: f32, x2: f32, y2: f32, width: u32, height: u32) -> Option<Self> {
+        let left = (x1.max(0.0) as u32).min(width);
+        let top = (y1.max(0.0) as u32).min(height);
+        let right = (x2.ceil().max(0.0) as u32).min(width);
+        let bottom = (y2.ceil().max(0.0) as u32).min(height);
+        (right > left && bottom > top).then(|| Self {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        })
+    }
+
+    /// Bilinear resize of the region into a [1, 3, H, W] tensor scaled to 0-1
+    fn to_tensor(self, pixels: &[u8], frame_width: u32, size: (u32, u32)) -> Array4<f32> {
+        let (out_w, out_h) = size;
+        let mut tensor = Array4::<f32>::zeros((1, 3, out_h as usize, out_w as usize));
+        let stride = frame_width as usize * 3;
+        let sx = self.width as f32 / out_w as f32;
+        let sy = self.height as f32 / out_h as f32;
+
+        for oy in 0..out_h as usize {
+            let fy = ((oy as f32 + 0.5) * sy - 0.5).clamp(0.0, (self.height - 1) as f32);
+            let y0 = fy as usize;
+            let y1 = (y0 + 1).min(self.height as usize - 1);
+            let wy = fy - y0 as f32;
+            for ox in 0..out_w as usize {
+                let fx = ((ox as f32 + 0.5) * sx - 0.5).clamp(0.0, (self.width - 1) as f32);
+                let x0 = fx as usize;
+                let x1 = (x0 + 1).min(self.width as usize - 1);
+                let wx = fx - x0 as f32;
+
+                let at = |x: usize, y: usize, c: usize| {
+                    pixels[(self.y as usize + y) * stride + (self.x as usize + x) * 3 + c] as f32
+                };
+                for c in 0..3 {
+                    let top = at(x0, y0, c) * (1.0 - wx) + at(x1, y0, c) * wx;
+                    let bottom = at(x0, y1, c) * (1.0 - wx) + at(x1, y1, c) * wx;
+                    tensor[[0, c, oy, ox]] = (top * (1.0 - wy) + bottom * wy) / 255.0;
+                }
+            }
+        }
+        tensor
+    }
+}
+
+/// Greedy CTC decode of [T, C] logits, returning the text and mean character confidence
+#[cfg_attr(not(feature = "ort-backend"), allow(dead_code))]
+fn ctc_decode(logits: ndarray::ArrayView2<f32>, charset: &[char]) -> (String, f32) {
+    let mut text = String::new();
+    let mut confidences = Vec::new();
+    let mut previous = 0usize;
+
+    for step in logits.rows() {
+        let (class, max_logit) =
+            step.iter()
+                .enumerate()
+                .fold((0, f32::NEG_INFINITY), |best, (i, &v)| {
+                    if v > best.1 { (i, v) } else { best }
+                });
+        if class != 0
+            && class != previous
+            && let Some(&ch) = charset.get(class - 1)
+        {
+            // Softmax probability of the chosen class
+            let sum: f32 = step.iter().map(|&v| (v - max_logit).exp()).sum();
+            text.push(ch);
+            confidences.push(1.0 / sum);
+        }
+        previous = class;
+    }
+
+    let confidence = if confidences.is_empty() {
+        0.0
+    } else {
+        confidences.iter().sum::<f32>() / confidences.len() as f32
+    };
+    (text, confidence)
+}
+
+#[cfg(feature = "ort-backend")]
+pub use reader::PlateReader;
+
+#[cfg(feature = "ort-backend")]
+mod reader {
+    use super::*;
+    use crate::config::ExecutionProvider;
+    use common::span;
+    use ort::{session::Session, value::TensorRef};
+
+    /// Plate detector and OCR sessions
+    pub struct PlateReader {
+        config: PlateConfig,
+        detector: Session,
+        ocr: Session,
+    }
+
+    fn session(path: &str, provider: ExecutionProvider) -> Result<Session> {
+        let mut builder = Session::builder()?.with_intra_threads(2)?;
+        if provider == ExecutionProvider::Cuda {
+            builder = builder.with_execution_providers([
+                ort::execution_providers::CUDAExecutionProvider::default().build(),
+            ])?;
+        }
+        builder
+            .commit_from_file(path)
+            .with_context(|| format!("Failed to load plate model {}", path))
+    }
+
+    impl PlateReader {
+        pub fn new(config: PlateConfig) -> Result<Self> {
+            let _ = ort::init().commit();
+            let provider = ExecutionProvider::from_env();
+            let detector = session(&config.detector_model_path, provider)?;
+            let ocr = session(&config.ocr_model_path, provider)?;
+
+            tracing::info!(
+                detector = %config.detector_model_path,
+                ocr = %config.ocr_model_path,
+                classes = ?config.vehicle_classes,
+                zone = ?config.zone,
+                "License plate stage enabled"
+            );
+            Ok(Self {
+                config,
+                detector,
+                ocr,
+            })
+        }
+
+        /// Read the plates of the vehicles among `detections` in an RGB frame
+        pub fn read(
+            &mut self,
+            pixels: &[u8],
+            width: u32,
+            height: u32,
+            detections: &[Detection],
+        ) -> Result<Vec<PlateRead>> {
+            let _s = span!("license_plates");
+
+            let mut plates = Vec::new();
+            for index in self.config.candidates(detections, width, height) {
+                let det = &detections[index];
+                let Some(vehicle) = Crop::from_box(det.x1, det.y1, det.x2, det.y2, width, height)
+                else {
+                    continue;
+                };
+                if let Some(plate) = self.read_vehicle(pixels, width, height, vehicle)? {
+                    plates.push(PlateRead {
+                        detection_index: index,
+                        ..plate
+                    });
+                }
+            }
+            Ok(plates)
+        }
+
+        fn read_vehicle(
+            &mut self,
+            pixels: &[u8],
+            width: u32,
+            height: u32,
+            vehicle: Crop,
+        ) -> Result<Option<PlateRead>> {
+            let input = vehicle.to_tensor(pixels, width, self.config.detector_input_size);
+            let outputs = self
+                .detector
+                .run(ort::inputs!["input" => TensorRef::from_array_view(input.view())?])?;
+            let boxes = outputs["boxes"].try_extract_array::<f32>()?;
+
+            // Best plate in the vehicle, mapped back to frame pixels
+            let best = boxes
+                .rows()
+                .into_iter()
+                .filter(|b| b.len() >= 5 && b[4] >= self.config.min_plate_confidence)
+                .max_by(|a, b| a[4].total_cmp(&b[4]));
+            let Some(b) = best else {
+                return Ok(None);
+            };
+            let x1 = vehicle.x as f32 + b[0] * vehicle.width as f32;
+            let y1 = vehicle.y as f32 + b[1] * vehicle.height as f32;
+            let x2 = vehicle.x as f32 + b[2] * vehicle.width as f32;
+            let y2 = vehicle.y as f32 + b[3] * vehicle.height as f32;
+            let Some(plate) = Crop::from_box(x1, y1, x2, y2, width, height) else {
+                return Ok(None);
+            };
+
+            let input = plate.to_tensor(pixels, width, self.config.ocr_input_size);
+            let outputs = self
+                .ocr
+                .run(ort::inputs!["input" => TensorRef::from_array_view(input.view())?])?;
+            let logits = outputs["logits"].try_extract_array::<f32>()?;
+            let logits = logits
+                .into_dimensionality::<ndarray::Ix3>()
+                .context("OCR logits must be [1, T, C]")?;
+            let (text, confidence) =
+                ctc_decode(logits.index_axis(ndarray::Axis(0), 0), &self.config.charset);
+            if text.is_empty() {
+                return Ok(None);
+            }
+
+            Ok(Some(PlateRead {
+                detection_index: 0,
+                x1,
+                y1,
+                x2,
+                y2,
+                text,
+                confidence,
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    fn vehicle(class_id: u16, x1: f32, y1: f32, x2: f32, y2: f32) -> Detection {
+        Detection {
+            x1,
+            y1,
+            x2,
+            y2,
+            confidence: 0.9,
+            class_id,
+        }
+    }
+
+    fn config(zone: Option<Rect>) -> PlateConfig {
+        PlateConfig {
+            detector_model_path: String::new(),
+            ocr_model_path: String::new(),
+            detector_input_size: (320, 320),
+            ocr_input_size: (94, 24),
+            vehicle_classes: vec![2, 7],
+            zone,
+            min_plate_confidence: 0.5,
+            charset: DEFAULT_CHARSET.chars().collect(),
+        }
+    }
+
+    #[test]
+    fn test_candidates_filter_class_and_zone() {
+        let detections = [
+            vehicle(2, 0.0, 0.0, 100.0, 100.0),     // car, top left
+            vehicle(0, 500.0, 500.0, 600.0, 600.0), // person
+            vehicle(7, 500.0, 300.0, 700.0, 500.0), // truck, centered
+        ];
+
+        assert_eq!(config(None).candidates(&detections, 1000, 800), vec![0, 2]);
+
+        let zone = Rect::parse("0.4, 0.3, 0.8, 0.8").unwrap();
+        assert_eq!(
+            config(Some(zone)).candidates(&detections, 1000, 800),
+            vec![2]
+        );
+    }
+
+    #[test]
+    fn test_candidates_index_the_published_detections() {
+        use crate::processing::post::{keep_most_confident, rescale_detections};
+
+        let detection = |class_id, confidence| Detection {
+            confidence,
+            ..vehicle(class_id, 200.0, 200.0, 600.0, 600.0)
+        };
+        // The model output: a person, a low confidence truck, then a car
+        let mut published = vec![detection(0, 0.9), detection(7, 0.3), detection(2, 0.6)];
+        // The truck is left out of the buffer, moving the car up
+        assert_eq!(keep_most_confident(&mut published, 2), 1);
+
+        // Plates are read in the decoded frame, half the camera resolution
+        let in_frame = rescale_detections(&published, (1000, 800), (500, 400));
+        let candidates = config(None).candidates(&in_frame, 500, 400);
+        assert_eq!(candidates, vec![1]);
+        assert_eq!(published[candidates[0]].class_id, 2);
+
+        let plate = PlateRead {
+            detection_index: candidates[0],
+            x1: 100.0,
+            y1: 150.0,
+            x2: 140.0,
+            y2: 160.0,
+            text: "AB123".to_string(),
+            confidence: 0.8,
+        };
+        let plate = plate.rescaled((500, 400), (1000, 800));
+        assert_eq!(
+            (plate.x1, plate.y1, plate.x2, plate.y2),
+            (200.0, 300.0, 280.0, 320.0)
+        );
+    }
+
+    #[test]
+    fn test_crop_clamps_and_resizes() {
+        assert_eq!(Crop::from_box(50.0, 50.0, 40.0, 60.0, 100, 100), None);
+        let crop = Crop::from_box(-10.0, 90.0, 20.0, 200.0, 100, 100).unwrap();
+        assert_eq!(
+            crop,
+            Crop {
+                x: 0,
+                y: 90,
+                width: 20,
+                height: 10
+            }
+        );
+
+        // Uniform region resizes to the same normalized value
+        let pixels = vec![255u8; 100 * 100 * 3];
+        let tensor = crop.to_tensor(&pixels, 100, (8, 4));
+        assert_eq!(tensor.shape(), &[1, 3, 4, 8]);
+        assert!(tensor.iter().all(|&v| (v - 1.0).abs() < 1e-6));
+    }
+
+    #[test]
+    fn test_ctc_decode_collapses_repeats_and_blanks() {
+        let charset: Vec<char> = "AB1".chars().collect();
+        // blank, A, A, blank, A, B, B, 1
+        let logits = array![
+            [5.0, 0.0, 0.0, 0.0],
+            [0.0, 5.0, 0.0, 0.0],
+            [0.0, 5.0, 0.0, 0.0],
+            [5.0, 0.0, 0.0, 0.0],
+            [0.0, 5.0, 0.0, 0.0],
+            [0.0, 0.0, 5.0, 0.0],
+            [0.0, 0.0, 5.0, 0.0],
+            [0.0, 0.0, 0.0, 5.0],
+        ];
+        let (text, confidence) = ctc_decode(logits.view(), &charset);
+        assert_eq!(text, "AAB1");
+        assert!(confidence > 0.95);
+    }
+}
@@ -0,0 +1,72 @@
+//! Appearance descriptors attached to detections
+//!
+//! With `APPEARANCE_CLASSES` set (e.g. `person`), each detection of those classes
+//! carries the HSV histogram of its box (`common::Appearance`), which the
+//! controller compares to recognize a person re-entering the frame.
+
+use bridge::Detection;
+use common::Appearance;
+use common::classes::ClassSet;
+
+/// Computes the descriptors of a frame's detections
+pub struct AppearanceSampler<'a> {
+    classes: ClassSet,
+    pixels: &'a [u8],
+    width: u32,
+    height: u32,
+    /// Detection coordinates to `pixels` coordinates
+    scale: f32,
+}
+
+impl<'a> AppearanceSampler<'a> {
+    /// Sampler of the RGB `pixels` (`width` x `height`) for detections in the
+    /// coordinates of a `detection_size` frame
+    pub fn new(
+        classes: ClassSet,
+        pixels: &'a [u8],
+        (width, height): (u32, u32),
+        detection_size: (u32, u32),
+    ) -> Self {
+        Self {
+            classes,
+            pixels,
+            width,
+            height,
+            scale: width as f32 / detection_size.0.max(1) as f32,
+        }
+    }
+
+    /// Descriptor of `det`, `None` for other classes
+    pub fn describe(&self, det: &Detection) -> Option<Appearance> {
+        if !self.classes.contains(det.class_id) {
+            return None;
+        }
+        let bbox = [det.x1, det.y1, det.x2, det.y2].map(|v| v * self.scale);
+        Appearance::of_box(self.pixels, self.width, self.height, bbox)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_selected_classes_are_described() {
+        // Frame downscaled to half the detection coordinates
+        let pixels = vec![128u8; 32 * 32 * 3];
+        let sampler = AppearanceSampler::new(ClassSet::single(0), &pixels, (32, 32), (64, 64));
+        let det = |class_id, x1| Detection {
+            x1,
+            y1: 0.0,
+            x2: x1 + 20.0,
+            y2: 40.0,
+            confidence: 0.9,
+            class_id,
+        };
+
+        assert!(sampler.describe(&det(0, 10.0)).is_some());
+        assert!(sampler.describe(&det(2, 10.0)).is_none());
+        // Inside the detection frame, outside the downscaled one once mapped
+        assert!(sampler.describe(&det(0, 70.0)).is_none());
+    }
+}
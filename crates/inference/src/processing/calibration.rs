@@ -0,0 +1,155 @@
+//! Confidence calibration
+//!
+//! INT8 quantization shifts the logit distribution, so a `CONFIDENCE_THRESHOLD` tuned
+//! on one model variant means something else on another. A calibration maps the raw
+//! max-class logit to a calibrated probability before thresholding.
+//!
+//! Calibrations are fitted offline by `scripts/model-pipeline/fit_confidence_calibration.py`
+//! and loaded from JSON:
+//!
+//! ```json
+//! {"method": "temperature", "temperature": 1.35}
+//! {"method": "isotonic", "x": [0.05, 0.4, 0.9], "y": [0.01, 0.3, 0.95]}
+//! ```
+//!
+//! Isotonic tables map the uncalibrated sigmoid confidence (`x`) to a calibrated one
+//! (`y`), interpolating linearly between points and clamping outside them.
+
+use anyhow::{Context, ensure};
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum ConfidenceCalibration {
+    /// Plain sigmoid of the logit
+    #[default]
+    Identity,
+    /// `sigmoid(logit / temperature)`
+    Temperature { temperature: f32 },
+    /// Piecewise-linear lookup from sigmoid confidence to calibrated confidence
+    Isotonic { x: Vec<f32>, y: Vec<f32> },
+}
+
+impl ConfidenceCalibration {
+    /// Load and validate a calibration JSON file
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read calibration file {}", path.display()))?;
+        let calibration: Self = serde_json::from_str(&json)
+            .with_context(|| format!("Invalid calibration file {}", path.display()))?;
+        calibration.validate()?;
+        Ok(calibration)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            Self::Identity => {}
+            Self::Temperature { temperature } => {
+                ensure!(
+                    temperature.is_finite() && *temperature > 0.0,
+                    "Calibration temperature must be positive, got {}",
+                    temperature
+                );
+            }
+            Self::Isotonic { x, y } => {
+                ensure!(
+                    x.len() == y.len() && x.len() >= 2,
+                    "Isotonic calibration needs matching x/y with at least 2 points"
+                );
+                ensure!(
+                    x.windows(2).all(|w| w[0] < w[1]),
+                    "Isotonic calibration x must be strictly increasing"
+                );
+                ensure!(
+                    y.windows(2).all(|w| w[0] <= w[1]) && y.iter().all(|v| (0.0..=1.0).contains(v)),
+                    "Isotonic calibration y must be non-decreasing within 0-1"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Calibrated confidence for a raw class logit
+    #[inline]
+    pub fn confidence(&self, logit: f32) -> f32 {
+        match self {
+            Self::Identity => sigmoid(logit),
+            Self::Temperature { temperature } => sigmoid(logit / temperature),
+            Self::Isotonic { x, y } => interpolate(x, y, sigmoid(logit)),
+        }
+    }
+}
+
+/// Sigmoid activation function
+#[inline]
+pub(crate) fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// Linear interpolation in a sorted table, clamped to its end points
+fn interpolate(x: &[f32], y: &[f32], value: f32) -> f32 {
+    let i = x.partition_point(|&p| p <= value);
+    if i == 0 {
+        return y[0];
+    }
+    if i == x.len() {
+        return y[y.len() - 1];
+    }
+
+    let t = (value - x[i - 1]) / (x[i] - x[i - 1]);
+    y[i - 1] + t * (y[i] - y[i - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temperature_softens_confidence() {
+        let calibration: ConfidenceCalibration =
+            serde_json::from_str(r#"{"method": "temperature", "temperature": 2.0}"#).unwrap();
+        calibration.validate().unwrap();
+
+        assert!((calibration.confidence(0.0) - 0.5).abs() < 1e-6);
+        assert!((calibration.confidence(4.0) - sigmoid(2.0)).abs() < 1e-6);
+        assert!(calibration.confidence(4.0) < ConfidenceCalibration::Identity.confidence(4.0));
+    }
+
+    #[test]
+    fn test_isotonic_interpolates_and_clamps() {
+        let calibration: ConfidenceCalibration = serde_json::from_str(
+            r#"{"method": "isotonic", "x": [0.2, 0.6, 0.9], "y": [0.1, 0.5, 0.8]}"#,
+        )
+        .unwrap();
+        calibration.validate().unwrap();
+
+        // sigmoid(0) = 0.5, a quarter of the way from 0.6 back to 0.2
+        assert!((calibration.confidence(0.0) - 0.4).abs() < 1e-6);
+        assert_eq!(calibration.confidence(-10.0), 0.1);
+        assert_eq!(calibration.confidence(10.0), 0.8);
+    }
+
+    #[test]
+    fn test_rejects_invalid_calibrations() {
+        let invalid = [
+            ConfidenceCalibration::Temperature { temperature: 0.0 },
+            ConfidenceCalibration::Isotonic {
+                x: vec![0.5],
+                y: vec![0.5],
+            },
+            ConfidenceCalibration::Isotonic {
+                x: vec![0.6, 0.2],
+                y: vec![0.1, 0.5],
+            },
+            ConfidenceCalibration::Isotonic {
+                x: vec![0.2, 0.6],
+                y: vec![0.5, 0.1],
+            },
+        ];
+        for calibration in invalid {
+            assert!(calibration.validate().is_err(), "{:?}", calibration);
+        }
+    }
+}
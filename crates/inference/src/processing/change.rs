@@ -0,0 +1,203 @@
+//! Reuse of detections on unchanged frames
+//!
+//! A static scene gives the same detections frame after frame. With
+//! `DETECTION_CACHE_THRESHOLD` set, each frame is compared with the last one the
+//! model ran on, as a coarse grid of mean luma. When the cells changed by less than
+//! the threshold on average the earlier detections are published again, flagged
+//! `is_cached`, instead of running the model, so downstream still gets a result
+//! per frame.
+//!
+//! The model runs again at least every `DETECTION_CACHE_MAX_AGE_MS`, so slow
+//! changes the comparison misses are picked up, and whenever the confidence
+//! threshold changes. Late results are never reused.
+
+use bridge::Detection;
+use common::{get_env, get_env_opt};
+use std::time::{Duration, Instant};
+
+/// Cells of the comparison grid along each axis
+const GRID: usize = 16;
+/// Pixels sampled along each axis of a cell
+const CELL_SAMPLES: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectionCacheConfig {
+    /// Mean absolute change of the grid's luma, 0-255, below which a frame is
+    /// treated as unchanged
+    pub threshold: f32,
+    /// Longest detections are reused before the model runs again
+    pub max_age: Duration,
+}
+
+impl DetectionCacheConfig {
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            threshold: get_env_opt("DETECTION_CACHE_THRESHOLD")?,
+            max_age: Duration::from_millis(get_env("DETECTION_CACHE_MAX_AGE_MS", 1000)),
+        })
+    }
+}
+
+/// Mean luma of a frame over a coarse grid
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameSignature {
+    size: (u32, u32),
+    cells: Vec<f32>,
+}
+
+impl FrameSignature {
+    /// Signature of packed RGB `pixels`, sampled rather than read in full
+    pub fn of_rgb(pixels: &[u8], width: u32, height: u32) -> Self {
+        let (w, h) = (width as usize, height as usize);
+        let samples = GRID * CELL_SAMPLES;
+        let mut cells = vec![0f32; GRID * GRID];
+        if w > 0 && h > 0 && pixels.len() >= w * h * 3 {
+            for sy in 0..samples {
+                let y = (2 * sy + 1) * h / (2 * samples);
+                for sx in 0..samples {
+                    let x = (2 * sx + 1) * w / (2 * samples);
+                    let i = (y * w + x) * 3;
+                    // BT.601 weights in integers
+                    let luma = (77 * pixels[i] as u32
+                        + 150 * pixels[i + 1] as u32
+                        + 29 * pixels[i + 2] as u32)
+                        >> 8;
+                    cells[(sy / CELL_SAMPLES) * GRID + sx / CELL_SAMPLES] += luma as f32;
+                }
+            }
+        }
+        let per_cell = (CELL_SAMPLES * CELL_SAMPLES) as f32;
+        cells.iter_mut().for_each(|cell| *cell /= per_cell);
+        Self {
+            size: (width, height),
+            cells,
+        }
+    }
+
+    /// Mean absolute difference of the cells, none between frames of different sizes
+    pub fn distance(&self, other: &Self) -> Option<f32> {
+        (self.size == other.size).then(|| {
+            let total: f32 = self
+                .cells
+                .iter()
+                .zip(&other.cells)
+                .map(|(a, b)| (a - b).abs())
+                .sum();
+            total / self.cells.len() as f32
+        })
+    }
+}
+
+struct Entry {
+    signature: FrameSignature,
+    detections: Vec<Detection>,
+    computed_at: Instant,
+}
+
+/// Detections of the last frame the model ran on, reusable while frames match it
+pub struct DetectionCache {
+    config: DetectionCacheConfig,
+    last: Option<Entry>,
+}
+
+impl DetectionCache {
+    pub fn new(config: DetectionCacheConfig) -> Self {
+        Self { config, last: None }
+    }
+
+    /// Detections to republish for a frame with `signature`, if it is unchanged from
+    /// the last frame the model ran on and those are recent enough
+    pub fn lookup(&self, signature: &FrameSignature, now: Instant) -> Option<&[Detection]> {
+        let entry = self.last.as_ref()?;
+        let fresh = now.duration_since(entry.computed_at) < self.config.max_age;
+        let unchanged = signature
+            .distance(&entry.signature)
+            .is_some_and(|distance| distance < self.config.threshold);
+        (fresh && unchanged).then_some(entry.detections.as_slice())
+    }
+
+    /// Remember what the model found on a frame
+    pub fn store(&mut self, signature: FrameSignature, detections: Vec<Detection>, now: Instant) {
+        self.last = Some(Entry {
+            signature,
+            detections,
+            computed_at: now,
+        });
+    }
+
+    /// Run the model on the next frame whatever it shows
+    pub fn clear(&mut self) {
+        self.last = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: u32, height: u32, value: u8) -> Vec<u8> {
+        vec![value; (width * height * 3) as usize]
+    }
+
+    fn person() -> Detection {
+        Detection {
+            x1: 10.0,
+            y1: 20.0,
+            x2: 50.0,
+            y2: 120.0,
+            confidence: 0.9,
+            class_id: 0,
+        }
+    }
+
+    #[test]
+    fn test_signature_distance() {
+        let gray = FrameSignature::of_rgb(&frame(640, 480, 100), 640, 480);
+        let brighter = FrameSignature::of_rgb(&frame(640, 480, 110), 640, 480);
+        assert_eq!(gray.distance(&gray), Some(0.0));
+        assert!((gray.distance(&brighter).unwrap() - 10.0).abs() < 1.0);
+
+        // A change confined to one cell is diluted over the grid
+        let mut pixels = frame(640, 480, 100);
+        for y in 0..30 {
+            for x in 0..40 {
+                pixels[(y * 640 + x) * 3..][..3].fill(255);
+            }
+        }
+        let corner = FrameSignature::of_rgb(&pixels, 640, 480);
+        let distance = gray.distance(&corner).unwrap();
+        assert!(distance > 0.0 && distance < 1.0, "distance {distance}");
+
+        let resized = FrameSignature::of_rgb(&frame(320, 240, 100), 320, 240);
+        assert_eq!(gray.distance(&resized), None);
+    }
+
+    #[test]
+    fn test_cache_reuses_detections_of_unchanged_recent_frames() {
+        let mut cache = DetectionCache::new(DetectionCacheConfig {
+            threshold: 2.0,
+            max_age: Duration::from_secs(1),
+        });
+        let signature = FrameSignature::of_rgb(&frame(64, 48, 100), 64, 48);
+        let start = Instant::now();
+        assert!(cache.lookup(&signature, start).is_none());
+
+        cache.store(signature.clone(), vec![person()], start);
+        let reused = cache.lookup(&signature, start + Duration::from_millis(500));
+        assert_eq!(reused.map(<[_]>::len), Some(1));
+
+        // Too old
+        assert!(
+            cache
+                .lookup(&signature, start + Duration::from_secs(1))
+                .is_none()
+        );
+
+        // Changed
+        let changed = FrameSignature::of_rgb(&frame(64, 48, 120), 64, 48);
+        assert!(cache.lookup(&changed, start).is_none());
+
+        cache.clear();
+        assert!(cache.lookup(&signature, start).is_none());
+    }
+}
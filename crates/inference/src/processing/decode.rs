@@ -0,0 +1,75 @@
+//! RGB pixels of a shared memory frame
+//!
+//! Capture writes MJPEG frames without decoding them when `JPEG_PASSTHROUGH` is
+//! set, and LZ4-compressed RGB when `FRAME_COMPRESSION=lz4`; both are decoded here
+//! before preprocessing.
+
+use anyhow::{Result, bail};
+use common::span;
+use schema::{Frame, FrameEncoding};
+
+#[derive(Default)]
+pub struct FrameDecoder {
+    /// Created on the first JPEG frame
+    decompressor: Option<turbojpeg::Decompressor>,
+    /// Decoded or decompressed frame, reused across frames
+    rgb: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// RGB pixels of `frame`, borrowed from shared memory unless they had to be decoded
+    pub fn pixels<'a>(&'a mut self, frame: &Frame<'a>) -> Result<&'a [u8]> {
+        let Some(payload) = frame.pixels() else {
+            bail!("No pixel data");
+        };
+
+        match frame.encoding() {
+            FrameEncoding::Rgb => Ok(payload.bytes()),
+            FrameEncoding::Jpeg => self.decode_jpeg(payload.bytes(), frame.width(), frame.height()),
+            FrameEncoding::Lz4 => {
+                let _s = span!("decompress_lz4");
+                let rgb_size = frame.width() as usize * frame.height() as usize * 3;
+                bridge::decompress_into(payload.bytes(), rgb_size, &mut self.rgb)?;
+                Ok(&self.rgb)
+            }
+            other => bail!("Unsupported frame encoding {:?}", other),
+        }
+    }
+
+    fn decode_jpeg(&mut self, jpeg: &[u8], width: u32, height: u32) -> Result<&[u8]> {
+        let _s = span!("decode_jpeg");
+
+        let decompressor = match self.decompressor.as_mut() {
+            Some(decompressor) => decompressor,
+            None => self.decompressor.insert(turbojpeg::Decompressor::new()?),
+        };
+
+        let header = decompressor.read_header(jpeg)?;
+        if (header.width, header.height) != (width as usize, height as usize) {
+            bail!(
+                "JPEG is {}x{}, frame says {}x{}",
+                header.width,
+                header.height,
+                width,
+                height
+            );
+        }
+
+        let rgb_size = header.width * header.height * 3;
+        self.rgb.resize(rgb_size, 0);
+        let output = turbojpeg::Image {
+            pixels: &mut self.rgb[..],
+            width: header.width,
+            pitch: header.width * 3,
+            height: header.height,
+            format: turbojpeg::PixelFormat::RGB,
+        };
+        decompressor.decompress(jpeg, output)?;
+
+        Ok(&self.rgb)
+    }
+}
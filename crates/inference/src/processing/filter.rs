@@ -0,0 +1,204 @@
+//! Geometric sanity filters on decoded detections
+//!
+//! Drops boxes no real object produces (slivers, specks, boxes covering the whole
+//! frame) before they reach the controller. Filters are loaded from JSON, with
+//! per-class overrides falling back field by field to the defaults:
+//!
+//! ```json
+//! {
+//!   "default": {"min_area_fraction": 0.0005, "max_aspect_ratio": 6.0},
+//!   "classes": {"0": {"min_aspect_ratio": 0.15, "max_aspect_ratio": 1.5, "border_margin": 2}}
+//! }
+//! ```
+//!
+//! Aspect ratio is width over height, areas are in original frame pixels or as a
+//! fraction of the frame, and `border_margin` drops boxes within that many pixels
+//! of a frame edge.
+
+use anyhow::{Context, ensure};
+use bridge::Detection;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Limits applied to one class, unset fields do not filter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilterRules {
+    pub min_area: Option<f32>,
+    pub max_area: Option<f32>,
+    pub min_area_fraction: Option<f32>,
+    pub max_area_fraction: Option<f32>,
+    pub min_aspect_ratio: Option<f32>,
+    pub max_aspect_ratio: Option<f32>,
+    pub border_margin: Option<f32>,
+}
+
+impl FilterRules {
+    /// Fields of `self`, falling back to `defaults` where unset
+    fn or(self, defaults: &Self) -> Self {
+        Self {
+            min_area: self.min_area.or(defaults.min_area),
+            max_area: self.max_area.or(defaults.max_area),
+            min_area_fraction: self.min_area_fraction.or(defaults.min_area_fraction),
+            max_area_fraction: self.max_area_fraction.or(defaults.max_area_fraction),
+            min_aspect_ratio: self.min_aspect_ratio.or(defaults.min_aspect_ratio),
+            max_aspect_ratio: self.max_aspect_ratio.or(defaults.max_aspect_ratio),
+            border_margin: self.border_margin.or(defaults.border_margin),
+        }
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let values = [
+            self.min_area,
+            self.max_area,
+            self.min_area_fraction,
+            self.max_area_fraction,
+            self.min_aspect_ratio,
+            self.max_aspect_ratio,
+            self.border_margin,
+        ];
+        ensure!(
+            values.iter().flatten().all(|v| v.is_finite() && *v >= 0.0),
+            "Detection filter limits must be non-negative"
+        );
+        Ok(())
+    }
+
+    /// Whether `det` passes these limits in a `frame_width` x `frame_height` frame
+    pub fn accepts(&self, det: &Detection, frame_width: f32, frame_height: f32) -> bool {
+        let width = det.x2 - det.x1;
+        let height = det.y2 - det.y1;
+        let area = width * height;
+        let fraction = area / (frame_width * frame_height);
+        let aspect_ratio = width / height;
+
+        let within = |value: f32, min: Option<f32>, max: Option<f32>| {
+            min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+        };
+        if !within(area, self.min_area, self.max_area)
+            || !within(fraction, self.min_area_fraction, self.max_area_fraction)
+            || !within(aspect_ratio, self.min_aspect_ratio, self.max_aspect_ratio)
+        {
+            return false;
+        }
+
+        self.border_margin.is_none_or(|margin| {
+            det.x1 > margin
+                && det.y1 > margin
+                && det.x2 < frame_width - margin
+                && det.y2 < frame_height - margin
+        })
+    }
+}
+
+/// Default rules with per-class overrides, accepting everything when empty
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DetectionFilter {
+    #[serde(default)]
+    default: FilterRules,
+    /// Overrides keyed by COCO class id, already merged with `default`
+    #[serde(default)]
+    classes: HashMap<u16, FilterRules>,
+}
+
+impl DetectionFilter {
+    pub fn new(default: FilterRules, classes: HashMap<u16, FilterRules>) -> Self {
+        let classes = classes
+            .into_iter()
+            .map(|(class_id, rules)| (class_id, rules.or(&default)))
+            .collect();
+        Self { default, classes }
+    }
+
+    /// Load and validate a filter JSON file
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read detection filter file {}", path.display()))?;
+        let filter: Self = serde_json::from_str(&json)
+            .with_context(|| format!("Invalid detection filter file {}", path.display()))?;
+        filter.default.validate()?;
+        for rules in filter.classes.values() {
+            rules.validate()?;
+        }
+        Ok(Self::new(filter.default, filter.classes))
+    }
+
+    /// Whether `det` passes the rules of its class
+    #[inline]
+    pub fn accepts(&self, det: &Detection, frame_width: f32, frame_height: f32) -> bool {
+        self.classes
+            .get(&det.class_id)
+            .unwrap_or(&self.default)
+            .accepts(det, frame_width, frame_height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detection(x1: f32, y1: f32, x2: f32, y2: f32, class_id: u16) -> Detection {
+        Detection {
+            x1,
+            y1,
+            x2,
+            y2,
+            confidence: 0.9,
+            class_id,
+        }
+    }
+
+    #[test]
+    fn test_area_and_aspect_limits() {
+        let rules = FilterRules {
+            min_area: Some(100.0),
+            max_area_fraction: Some(0.5),
+            max_aspect_ratio: Some(4.0),
+            ..Default::default()
+        };
+
+        // 20x20 in a 100x100 frame
+        assert!(rules.accepts(&detection(10.0, 10.0, 30.0, 30.0, 0), 100.0, 100.0));
+        // 5x5 is below the minimum area
+        assert!(!rules.accepts(&detection(10.0, 10.0, 15.0, 15.0, 0), 100.0, 100.0));
+        // 80x80 covers more than half the frame
+        assert!(!rules.accepts(&detection(10.0, 10.0, 90.0, 90.0, 0), 100.0, 100.0));
+        // 50x10 is wider than 4:1
+        assert!(!rules.accepts(&detection(10.0, 10.0, 60.0, 20.0, 0), 100.0, 100.0));
+        // Zero-width boxes have no area
+        assert!(!rules.accepts(&detection(10.0, 10.0, 10.0, 40.0, 0), 100.0, 100.0));
+    }
+
+    #[test]
+    fn test_border_margin() {
+        let rules = FilterRules {
+            border_margin: Some(2.0),
+            ..Default::default()
+        };
+
+        assert!(rules.accepts(&detection(3.0, 3.0, 97.0, 97.0, 0), 100.0, 100.0));
+        assert!(!rules.accepts(&detection(0.0, 30.0, 20.0, 60.0, 0), 100.0, 100.0));
+        assert!(!rules.accepts(&detection(30.0, 30.0, 60.0, 100.0, 0), 100.0, 100.0));
+    }
+
+    #[test]
+    fn test_class_overrides_fall_back_to_defaults() {
+        let filter: DetectionFilter = serde_json::from_str(
+            r#"{
+                "default": {"min_area": 100.0, "max_aspect_ratio": 4.0},
+                "classes": {"0": {"max_aspect_ratio": 1.0}}
+            }"#,
+        )
+        .unwrap();
+        let filter = DetectionFilter::new(filter.default, filter.classes);
+
+        // A 30x15 box is too wide for a person but fine for other classes
+        assert!(!filter.accepts(&detection(10.0, 10.0, 40.0, 25.0, 0), 100.0, 100.0));
+        assert!(filter.accepts(&detection(10.0, 10.0, 40.0, 25.0, 2), 100.0, 100.0));
+        // The default minimum area still applies to people
+        assert!(!filter.accepts(&detection(10.0, 10.0, 15.0, 20.0, 0), 100.0, 100.0));
+    }
+}
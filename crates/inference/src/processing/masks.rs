@@ -0,0 +1,497 @@
+//! Masks over permanent false positives
+//!
+//! Some things are detected forever at the same place: a mannequin, a poster of a
+//! person, a statue by the door. With `SCENE_MASKS_PATH` set, detections lying
+//! mostly (`SCENE_MASK_COVERAGE` of their box) inside a confirmed mask of their
+//! class are dropped after postprocessing, like those failing the sanity filters.
+//!
+//! With `SCENE_MASK_LEARNING=true` inference also finds these places. A box that
+//! stays put (same class, IoU of at least 0.8 with where it was first seen) for
+//! `SCENE_MASK_DWELL_SECS` counts one occurrence there, and the count goes on
+//! while it stays. After `SCENE_MASK_SUGGEST_AFTER` occurrences the place,
+//! slightly enlarged, is written to the masks file as a suggestion:
+//!
+//! ```json
+//! {"masks": [{"class_id": 0, "rect": [0.317, 0.09, 0.419, 0.563], "occurrences": 10, "confirmed": false}]}
+//! ```
+//!
+//! Like the other zones, `rect` is normalized to the frame size, see [`common::rect`].
+//!
+//! Suggestions suppress nothing until `confirmed` is set to `true`. The file is
+//! read again when it changes, so masks are confirmed, edited or removed without
+//! restarting inference.
+
+use anyhow::{Context, Result};
+use bridge::Detection;
+use common::{Rect, get_env, get_env_opt};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// IoU with its first box under which a box is taken to have moved
+const STATIONARY_IOU: f32 = 0.8;
+
+/// A place not seen for this long starts its dwell over
+const ABSENCE_GRACE: Duration = Duration::from_secs(10);
+
+/// Places without occurrences are forgotten first beyond this many
+const MAX_CANDIDATES: usize = 256;
+
+/// How often the masks file is checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Fraction of the box size added around a suggested mask
+const SUGGESTION_MARGIN: f32 = 0.05;
+
+#[derive(Debug, Clone)]
+pub struct SceneMaskConfig {
+    pub path: PathBuf,
+    /// Suggest masks where boxes stay put
+    pub learning: bool,
+    /// Time a box stays put for one occurrence
+    pub dwell: Duration,
+    /// Occurrences before a place is suggested
+    pub suggest_after: u32,
+    /// Fraction of a detection's box inside a mask for it to be dropped
+    pub coverage: f32,
+}
+
+impl SceneMaskConfig {
+    /// Enabled when `SCENE_MASKS_PATH` is set
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            path: PathBuf::from(get_env_opt::<String>("SCENE_MASKS_PATH")?),
+            learning: get_env("SCENE_MASK_LEARNING", false),
+            dwell: Duration::from_secs(get_env("SCENE_MASK_DWELL_SECS", 60)),
+            suggest_after: get_env("SCENE_MASK_SUGGEST_AFTER", 10u32).max(1),
+            coverage: get_env("SCENE_MASK_COVERAGE", 0.8),
+        })
+    }
+}
+
+/// A region where detections of one class are false positives
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SceneMask {
+    /// COCO class id
+    pub class_id: u16,
+    /// `x1, y1, x2, y2` normalized to the frame size
+    pub rect: [f32; 4],
+    /// Occurrences learned there, 0 for masks drawn by hand
+    #[serde(default)]
+    pub occurrences: u32,
+    /// Only confirmed masks suppress detections
+    #[serde(default)]
+    pub confirmed: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MaskFile {
+    #[serde(default)]
+    masks: Vec<SceneMask>,
+}
+
+/// A place a box of `class_id` was seen staying put
+#[derive(Debug)]
+struct Candidate {
+    class_id: u16,
+    rect: [f32; 4],
+    /// Start of the current dwell
+    since: Instant,
+    last_seen: Instant,
+    occurrences: u32,
+}
+
+pub struct SceneMasks {
+    config: SceneMaskConfig,
+    masks: Vec<SceneMask>,
+    /// Modification time of the masks file as last read or written
+    modified: Option<SystemTime>,
+    checked: Instant,
+    candidates: Vec<Candidate>,
+}
+
+impl SceneMasks {
+    pub fn new(config: SceneMaskConfig) -> Result<Self> {
+        let (masks, modified) = load(&config.path)?;
+        tracing::info!(
+            path = %config.path.display(),
+            confirmed = masks.iter().filter(|m| m.confirmed).count(),
+            suggested = masks.iter().filter(|m| !m.confirmed).count(),
+            learning = config.learning,
+            "Scene masks loaded"
+        );
+        Ok(Self {
+            config,
+            masks,
+            modified,
+            checked: Instant::now(),
+            candidates: Vec::new(),
+        })
+    }
+
+    pub fn masks(&self) -> &[SceneMask] {
+        &self.masks
+    }
+
+    /// Drop the detections, in the coordinates of a `frame_size` frame, under a
+    /// confirmed mask, and learn from the others
+    pub fn apply(&mut self, detections: &mut Vec<Detection>, frame_size: (u32, u32), now: Instant) {
+        if now.duration_since(self.checked) >= RELOAD_INTERVAL {
+            self.checked = now;
+            self.reload();
+        }
+        detections.retain(|det| !self.masked(det, frame_size));
+        if self.config.learning {
+            self.learn(detections, frame_size, now);
+        }
+    }
+
+    fn masked(&self, det: &Detection, frame_size: (u32, u32)) -> bool {
+        let rect = normalized(det, frame_size);
+        self.masks.iter().any(|mask| {
+            mask.confirmed
+                && mask.class_id == det.class_id
+                && coverage(rect, mask.rect) >= self.config.coverage
+        })
+    }
+
+    fn learn(&mut self, detections: &[Detection], frame_size: (u32, u32), now: Instant) {
+        for det in detections {
+            let rect = normalized(det, frame_size);
+            let candidate = self
+                .candidates
+                .iter_mut()
+                .find(|c| c.class_id == det.class_id && iou(c.rect, rect) >= STATIONARY_IOU);
+            let Some(candidate) = candidate else {
+                self.candidates.push(Candidate {
+                    class_id: det.class_id,
+                    rect,
+                    since: now,
+                    last_seen: now,
+                    occurrences: 0,
+                });
+                continue;
+            };
+
+            if now.duration_since(candidate.last_seen) > ABSENCE_GRACE {
+                candidate.since = now;
+            }
+            candidate.last_seen = now;
+            if now.duration_since(candidate.since) < self.config.dwell {
+                continue;
+            }
+            candidate.since = now;
+            candidate.occurrences += 1;
+            if candidate.occurrences >= self.config.suggest_after {
+                let (class_id, rect, occurrences) =
+                    (candidate.class_id, candidate.rect, candidate.occurrences);
+                self.suggest(class_id, rect, occurrences);
+            }
+        }
+
+        // Passers-by leave a trail of places seen once
+        self.candidates
+            .retain(|c| c.occurrences > 0 || now.duration_since(c.last_seen) <= ABSENCE_GRACE);
+        if self.candidates.len() > MAX_CANDIDATES {
+            self.candidates
+                .sort_by_key(|c| std::cmp::Reverse((c.occurrences, c.last_seen)));
+            self.candidates.truncate(MAX_CANDIDATES);
+        }
+    }
+
+    /// Add or update the suggested mask over `rect`
+    fn suggest(&mut self, class_id: u16, rect: [f32; 4], occurrences: u32) {
+        let existing = self.masks.iter_mut().find(|mask| {
+            mask.class_id == class_id && coverage(rect, mask.rect) >= self.config.coverage
+        });
+        match existing {
+            // Confirmed masks keep the count they were confirmed with
+            Some(mask) if mask.confirmed => return,
+            Some(mask) => mask.occurrences = occurrences,
+            None => {
+                tracing::info!(
+                    class_id,
+                    ?rect,
+                    occurrences,
+                    path = %self.config.path.display(),
+                    "Persistent detection, scene mask suggested"
+                );
+                self.masks.push(SceneMask {
+                    class_id,
+                    rect: enlarge(rect, SUGGESTION_MARGIN),
+                    occurrences,
+                    confirmed: false,
+                });
+            }
+        }
+        if let Err(e) = self.save() {
+            tracing::warn!(error = %format!("{e:#}"), "Failed to save scene masks");
+        }
+    }
+
+    /// Pick up masks edited in the file
+    fn reload(&mut self) {
+        let modified = modified(&self.config.path);
+        if modified == self.modified {
+            return;
+        }
+        match load(&self.config.path) {
+            Ok((masks, modified)) => {
+                tracing::info!(
+                    confirmed = masks.iter().filter(|m| m.confirmed).count(),
+                    suggested = masks.iter().filter(|m| !m.confirmed).count(),
+                    "Scene masks reloaded"
+                );
+                self.masks = masks;
+                self.modified = modified;
+            }
+            Err(e) => {
+                // Retried once the file changes again
+                self.modified = modified;
+                tracing::warn!(error = %format!("{e:#}"), "Keeping the previous scene masks");
+            }
+        }
+    }
+
+    fn save(&mut self) -> Result<()> {
+        let path = &self.config.path;
+        let json = serde_json::to_string_pretty(&MaskFile {
+            masks: self.masks.clone(),
+        })?;
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, json)
+            .and_then(|()| std::fs::rename(&tmp, path))
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        // Not read back as an edit
+        self.modified = modified(path);
+        Ok(())
+    }
+}
+
+/// Masks in the file at `path`, none when it does not exist yet
+fn load(path: &Path) -> Result<(Vec<SceneMask>, Option<SystemTime>)> {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((Vec::new(), None)),
+        Err(e) => {
+            return Err(e)
+                .with_context(|| format!("Failed to read scene masks {}", path.display()));
+        }
+    };
+    let file: MaskFile = serde_json::from_str(&json)
+        .with_context(|| format!("Invalid scene masks file {}", path.display()))?;
+    for mask in &file.masks {
+        let [x1, y1, x2, y2] = mask.rect;
+        Rect::new(x1, y1, x2, y2).with_context(|| format!("Invalid scene mask {:?}", mask.rect))?;
+    }
+    Ok((file.masks, modified(path)))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Box of `det` normalized to the size of its frame
+fn normalized(det: &Detection, (width, height): (u32, u32)) -> [f32; 4] {
+    let (width, height) = (width.max(1) as f32, height.max(1) as f32);
+    [
+        det.x1 / width,
+        det.y1 / height,
+        det.x2 / width,
+        det.y2 / height,
+    ]
+}
+
+fn area([x1, y1, x2, y2]: [f32; 4]) -> f32 {
+    (x2 - x1).max(0.0) * (y2 - y1).max(0.0)
+}
+
+fn intersection(a: [f32; 4], b: [f32; 4]) -> f32 {
+    area([
+        a[0].max(b[0]),
+        a[1].max(b[1]),
+        a[2].min(b[2]),
+        a[3].min(b[3]),
+    ])
+}
+
+fn iou(a: [f32; 4], b: [f32; 4]) -> f32 {
+    let intersection = intersection(a, b);
+    let union = area(a) + area(b) - intersection;
+    if union > 0.0 {
+        intersection / union
+    } else {
+        0.0
+    }
+}
+
+/// Fraction of `inner` inside `outer`
+fn coverage(inner: [f32; 4], outer: [f32; 4]) -> f32 {
+    let area = area(inner);
+    if area > 0.0 {
+        intersection(inner, outer) / area
+    } else {
+        0.0
+    }
+}
+
+fn enlarge([x1, y1, x2, y2]: [f32; 4], margin: f32) -> [f32; 4] {
+    let (dx, dy) = ((x2 - x1) * margin, (y2 - y1) * margin);
+    [
+        (x1 - dx).max(0.0),
+        (y1 - dy).max(0.0),
+        (x2 + dx).min(1.0),
+        (y2 + dy).min(1.0),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detection([x1, y1, x2, y2]: [f32; 4], class_id: u16) -> Detection {
+        Detection {
+            x1,
+            y1,
+            x2,
+            y2,
+            confidence: 0.9,
+            class_id,
+        }
+    }
+
+    const FRAME: (u32, u32) = (1280, 720);
+
+    fn masks(name: &str, learning: bool) -> SceneMasks {
+        let path =
+            std::env::temp_dir().join(format!("scene_masks_{}_{}.json", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        SceneMasks::new(SceneMaskConfig {
+            path,
+            learning,
+            dwell: Duration::from_secs(60),
+            suggest_after: 3,
+            coverage: 0.8,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_stationary_box_is_suggested_then_suppressed_once_confirmed() {
+        let mut masks = masks("learn", true);
+        let mannequin = [410.0, 80.0, 530.0, 390.0];
+        let start = Instant::now();
+
+        // Seen every 5 s for 3 minutes, with the box jittering a little
+        for i in 0..=36u64 {
+            let jitter = (i % 3) as f32;
+            let mut detections = vec![detection(
+                [
+                    mannequin[0] + jitter,
+                    mannequin[1],
+                    mannequin[2] + jitter,
+                    mannequin[3],
+                ],
+                0,
+            )];
+            masks.apply(&mut detections, FRAME, start + Duration::from_secs(i * 5));
+            // Suggestions suppress nothing
+            assert_eq!(detections.len(), 1);
+        }
+        assert_eq!(masks.masks().len(), 1);
+        let suggestion = &masks.masks()[0];
+        assert_eq!((suggestion.class_id, suggestion.occurrences), (0, 3));
+        assert!(!suggestion.confirmed);
+        let in_frame = normalized(&detection(mannequin, 0), FRAME);
+        assert!(coverage(in_frame, suggestion.rect) == 1.0);
+
+        // Confirmed in the file by hand
+        let path = masks.config.path.clone();
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        std::fs::write(
+            &path,
+            json.replace("\"confirmed\": false", "\"confirmed\": true"),
+        )
+        .unwrap();
+        masks.modified = None;
+        masks.reload();
+
+        let mut detections = vec![
+            detection(mannequin, 0),
+            // Another class at the same place, and someone walking past it
+            detection(mannequin, 2),
+            detection([300.0, 60.0, 560.0, 470.0], 0),
+        ];
+        masks.apply(&mut detections, FRAME, start + Duration::from_secs(200));
+        assert_eq!(detections.len(), 2);
+        assert_eq!(detections[0].class_id, 2);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_moving_and_intermittent_boxes_are_not_suggested() {
+        let mut masks = masks("moving", true);
+        let start = Instant::now();
+
+        // Someone walking across the frame for 10 minutes
+        for i in 0..600u64 {
+            let x = (i * 7 % 1200) as f32;
+            let mut detections = vec![detection([x, 100.0, x + 80.0, 300.0], 0)];
+            masks.apply(&mut detections, FRAME, start + Duration::from_secs(i));
+        }
+        // Someone standing in the same place for 30 s every 5 minutes
+        for visit in 0..6u64 {
+            for s in 0..30u64 {
+                let mut detections = vec![detection([100.0, 100.0, 180.0, 300.0], 0)];
+                masks.apply(
+                    &mut detections,
+                    FRAME,
+                    start + Duration::from_secs(1000 + visit * 300 + s),
+                );
+            }
+        }
+
+        assert!(masks.masks().is_empty());
+        assert!(masks.candidates.len() <= MAX_CANDIDATES);
+    }
+
+    #[test]
+    fn test_invalid_file_keeps_previous_masks() {
+        let mut masks = masks("invalid", false);
+        let path = masks.config.path.clone();
+        std::fs::write(
+            &path,
+            r#"{"masks": [{"class_id": 0, "rect": [0, 0, 0.5, 0.5], "confirmed": true}]}"#,
+        )
+        .unwrap();
+        masks.reload();
+        assert_eq!(masks.masks().len(), 1);
+
+        std::fs::write(
+            &path,
+            r#"{"masks": [{"class_id": 0, "rect": [0.5, 0, 0, 0.5]}]}"#,
+        )
+        .unwrap();
+        masks.modified = None;
+        masks.reload();
+        assert_eq!(masks.masks().len(), 1);
+
+        // Pixel coordinates
+        std::fs::write(
+            &path,
+            r#"{"masks": [{"class_id": 0, "rect": [0, 0, 640, 360]}, {"class_id": 2, "rect": [0, 0, 1, 1]}]}"#,
+        )
+        .unwrap();
+        masks.modified = None;
+        masks.reload();
+        assert_eq!(masks.masks().len(), 1);
+
+        let mut detections = vec![detection([10.0, 10.0, 600.0, 300.0], 0)];
+        masks.apply(&mut detections, FRAME, Instant::now());
+        assert!(detections.is_empty());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
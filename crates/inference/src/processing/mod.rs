@@ -1,3 +1,15 @@
+pub mod appearance;
+pub mod calibration;
+pub mod change;
+pub mod decode;
+pub mod filter;
+pub mod masks;
 pub mod post;
 
+pub use appearance::AppearanceSampler;
+pub use calibration::ConfidenceCalibration;
+pub use change::{DetectionCache, DetectionCacheConfig, FrameSignature};
+pub use decode::FrameDecoder;
+pub use filter::DetectionFilter;
+pub use masks::SceneMasks;
 pub use post::*;
@@ -1,47 +1,209 @@
+use super::appearance::AppearanceSampler;
+use super::calibration::ConfidenceCalibration;
+use super::filter::DetectionFilter;
+use bridge::Detection;
 use common::span;
 use flatbuffers::{FlatBufferBuilder, ForwardsUOffset, Vector, WIPOffset};
+use std::str::FromStr;
+
+/// Layout of the boxes emitted by the model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BoxFormat {
+    /// Corners in model input pixels
+    XyxyAbs,
+    /// Corners normalized to 0-1
+    XyxyNorm,
+    /// Center, width and height normalized to 0-1 (RF-DETR default export)
+    #[default]
+    CxcywhNorm,
+}
+
+impl FromStr for BoxFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "xyxy_abs" => Ok(Self::XyxyAbs),
+            "xyxy_norm" => Ok(Self::XyxyNorm),
+            "cxcywh_norm" => Ok(Self::CxcywhNorm),
+            other => anyhow::bail!(
+                "Unknown box format {:?} (expected xyxy_abs, xyxy_norm or cxcywh_norm)",
+                other
+            ),
+        }
+    }
+}
+
+impl BoxFormat {
+    /// Convert a raw model box to corners in model input pixels
+    #[inline]
+    fn to_input_xyxy(
+        self,
+        raw: [f32; 4],
+        input_width: f32,
+        input_height: f32,
+    ) -> (f32, f32, f32, f32) {
+        let [a, b, c, d] = raw;
+        let (x1, y1, x2, y2) = match self {
+            Self::XyxyAbs => return (a, b, c, d),
+            Self::XyxyNorm => (a, b, c, d),
+            Self::CxcywhNorm => cxcywh_to_xyxy(a, b, c, d),
+        };
+        (
+            x1 * input_width,
+            y1 * input_height,
+            x2 * input_width,
+            y2 * input_height,
+        )
+    }
+}
 
-pub struct TransformParams {
-    pub orig_width: u32,
-    pub orig_height: u32,
-    pub input_width: u32,
-    pub input_height: u32,
-    pub scale: f32,
-    pub offset_x: f32,
-    pub offset_y: f32,
+// Re-export the letterbox mapping under its former name for backwards compatibility
+pub use preprocess::LetterboxTransform as TransformParams;
+
+/// Detections vector being built into a `DetectionResult`
+pub type DetectionsOffset<'a> = WIPOffset<Vector<'a, ForwardsUOffset<schema::Detection<'a>>>>;
+
+/// Build `detections` into a `DetectionResult` detections vector, with their
+/// appearance descriptors when `appearance` is given
+///
+/// Also returns the class id of every detection, in order.
+pub fn build_detections<'a>(
+    builder: &mut FlatBufferBuilder<'a>,
+    detections: impl IntoIterator<Item = Detection>,
+    appearance: Option<&AppearanceSampler>,
+) -> (DetectionsOffset<'a>, Vec<u16>) {
+    let mut detection_offsets = Vec::new();
+    let mut class_ids = Vec::new();
+
+    for det in detections {
+        let bbox = schema::BoundingBox::new(det.x1, det.y1, det.x2, det.y2);
+        let appearance = appearance
+            .and_then(|sampler| sampler.describe(&det))
+            .map(|appearance| builder.create_vector(appearance.as_bytes()));
+        let detection = schema::Detection::create(
+            builder,
+            &schema::DetectionArgs {
+                box_: Some(&bbox),
+                confidence: det.confidence,
+                class_id: det.class_id,
+                appearance,
+            },
+        );
+        detection_offsets.push(detection);
+        class_ids.push(det.class_id);
+    }
+
+    (builder.create_vector(&detection_offsets), class_ids)
+}
+
+/// Keep the `limit` most confident of `detections`, returning how many were left out
+///
+/// Detections are reordered by confidence only when some are left out.
+pub fn keep_most_confident(detections: &mut Vec<Detection>, limit: usize) -> usize {
+    if detections.len() <= limit {
+        return 0;
+    }
+    detections.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    let left_out = detections.len() - limit;
+    detections.truncate(limit);
+    left_out
+}
+
+/// `detections` of a `from` sized frame mapped onto a `to` sized resize of it,
+/// in the same order
+pub fn rescale_detections(
+    detections: &[Detection],
+    from: (u32, u32),
+    to: (u32, u32),
+) -> Vec<Detection> {
+    let sx = to.0 as f32 / from.0.max(1) as f32;
+    let sy = to.1 as f32 / from.1.max(1) as f32;
+    detections
+        .iter()
+        .map(|det| Detection {
+            x1: det.x1 * sx,
+            y1: det.y1 * sy,
+            x2: det.x2 * sx,
+            y2: det.y2 * sy,
+            ..det.clone()
+        })
+        .collect()
 }
 
 pub struct PostProcessor {
     pub confidence_threshold: f32,
+    pub box_format: BoxFormat,
+    /// Mapping from max-class logit to the confidence compared against the threshold
+    pub calibration: ConfidenceCalibration,
+    /// Geometric sanity limits applied after mapping boxes to the original frame
+    pub filter: DetectionFilter,
 }
 
 impl PostProcessor {
     pub fn new(confidence_threshold: f32) -> Self {
         Self {
             confidence_threshold,
+            box_format: BoxFormat::default(),
+            calibration: ConfidenceCalibration::default(),
+            filter: DetectionFilter::default(),
         }
     }
 
+    pub fn with_box_format(mut self, box_format: BoxFormat) -> Self {
+        self.box_format = box_format;
+        self
+    }
+
+    pub fn with_calibration(mut self, calibration: ConfidenceCalibration) -> Self {
+        self.calibration = calibration;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: DetectionFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
     /// Parse detections from RF-DETR output
+    ///
+    /// Also returns the class id of every written detection, in order.
     pub fn parse_detections<'a>(
         &self,
         builder: &mut FlatBufferBuilder<'a>,
-        dets: &ndarray::ArrayViewD<f32>, // [1, 300, 4] - boxes in cxcywh format (normalized 0-1)
+        dets: &ndarray::ArrayViewD<f32>, // [1, 300, 4] - boxes laid out as `self.box_format`
         logits: &ndarray::ArrayViewD<f32>, // [1, 300, 91] - class logits
         transform: &TransformParams,
-    ) -> anyhow::Result<(
-        WIPOffset<Vector<'a, ForwardsUOffset<schema::Detection<'a>>>>,
-        usize,
-    )> {
+    ) -> anyhow::Result<(DetectionsOffset<'a>, Vec<u16>)> {
         let _s = span!("parse_detections");
+        Ok(build_detections(
+            builder,
+            self.decode(dets, logits, transform),
+            None,
+        ))
+    }
+
+    /// Same detections as [`Self::parse_detections`], in the order they are written,
+    /// for stages that post-process them further
+    pub fn detections(
+        &self,
+        dets: &ndarray::ArrayViewD<f32>,
+        logits: &ndarray::ArrayViewD<f32>,
+        transform: &TransformParams,
+    ) -> Vec<Detection> {
+        self.decode(dets, logits, transform).collect()
+    }
 
+    fn decode<'s>(
+        &'s self,
+        dets: &'s ndarray::ArrayViewD<f32>,
+        logits: &'s ndarray::ArrayViewD<f32>,
+        transform: &'s TransformParams,
+    ) -> impl Iterator<Item = Detection> + 's {
         let num_queries = dets.shape()[1];
         let num_classes = logits.shape()[2];
 
-        // First pass: collect detection offsets
-        let mut detection_offsets = Vec::new();
-
-        for i in 0..num_queries {
+        (0..num_queries).filter_map(move |i| {
             // Find max logit and its index (argmax for class_id)
             // RF-DETR uses 1-indexed classes (0=background, 1=person, 2=bicycle, ...)
             // Skip index 0 (background) and convert to 0-indexed COCO IDs
@@ -58,68 +220,48 @@ impl PostProcessor {
             // Convert RF-DETR 1-indexed class to 0-indexed COCO class
             let class_id = (class_idx - 1) as u16;
 
-            // Apply sigmoid to max logit for confidence
-            let confidence = sigmoid(max_logit);
+            // Calibration is monotonic, so the argmax class is unchanged
+            let confidence = self.calibration.confidence(max_logit);
 
             if confidence < self.confidence_threshold {
-                continue;
+                return None;
             }
 
-            // Get box in cxcywh format (normalized 0-1)
-            let cx = dets[[0, i, 0]];
-            let cy = dets[[0, i, 1]];
-            let w = dets[[0, i, 2]];
-            let h = dets[[0, i, 3]];
-
-            // Convert cxcywh to xyxy (still normalized)
-            let (x1_norm, y1_norm, x2_norm, y2_norm) = cxcywh_to_xyxy(cx, cy, w, h);
-
-            // Denormalize to input_size (e.g., 512x512)
-            let x1_input = x1_norm * transform.input_width as f32;
-            let y1_input = y1_norm * transform.input_height as f32;
-            let x2_input = x2_norm * transform.input_width as f32;
-            let y2_input = y2_norm * transform.input_height as f32;
-
-            // Apply inverse letterbox transform to original image coordinates
-            let x1 = ((x1_input - transform.offset_x) / transform.scale)
-                .max(0.0)
-                .min(transform.orig_width as f32);
-            let y1 = ((y1_input - transform.offset_y) / transform.scale)
-                .max(0.0)
-                .min(transform.orig_height as f32);
-            let x2 = ((x2_input - transform.offset_x) / transform.scale)
-                .max(0.0)
-                .min(transform.orig_width as f32);
-            let y2 = ((y2_input - transform.offset_y) / transform.scale)
-                .max(0.0)
-                .min(transform.orig_height as f32);
-
-            // Build Detection directly into FlatBuffer
-            let bbox = schema::BoundingBox::new(x1, y1, x2, y2);
-            let detection = schema::Detection::create(
-                builder,
-                &schema::DetectionArgs {
-                    box_: Some(&bbox),
-                    confidence,
-                    class_id,
-                },
+            // Convert the raw box to xyxy in input_size pixels (e.g., 512x512)
+            let raw = [
+                dets[[0, i, 0]],
+                dets[[0, i, 1]],
+                dets[[0, i, 2]],
+                dets[[0, i, 3]],
+            ];
+            let (x1_input, y1_input, x2_input, y2_input) = self.box_format.to_input_xyxy(
+                raw,
+                transform.input_width as f32,
+                transform.input_height as f32,
             );
-            detection_offsets.push(detection);
-        }
 
-        let count = detection_offsets.len();
-        let detections_vector = builder.create_vector(&detection_offsets);
-
-        Ok((detections_vector, count))
+            // Apply inverse letterbox transform to original image coordinates
+            let [x1, y1, x2, y2] = transform.to_image([x1_input, y1_input, x2_input, y2_input]);
+
+            let det = Detection {
+                x1,
+                y1,
+                x2,
+                y2,
+                confidence,
+                class_id,
+            };
+            self.filter
+                .accepts(
+                    &det,
+                    transform.orig_width as f32,
+                    transform.orig_height as f32,
+                )
+                .then_some(det)
+        })
     }
 }
 
-/// Sigmoid activation function
-#[inline]
-fn sigmoid(x: f32) -> f32 {
-    1.0 / (1.0 + (-x).exp())
-}
-
 /// Convert bounding box from center-width-height format to corner format
 #[inline]
 fn cxcywh_to_xyxy(cx: f32, cy: f32, w: f32, h: f32) -> (f32, f32, f32, f32) {
@@ -133,6 +275,8 @@ fn cxcywh_to_xyxy(cx: f32, cy: f32, w: f32, h: f32) -> (f32, f32, f32, f32) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::processing::calibration::sigmoid;
+    use crate::processing::filter;
     use ndarray::{Array, IxDyn};
 
     /// Detection struct for test verification
@@ -149,6 +293,9 @@ mod tests {
     fn test_postprocessor() -> PostProcessor {
         PostProcessor {
             confidence_threshold: 0.7,
+            box_format: BoxFormat::CxcywhNorm,
+            calibration: ConfidenceCalibration::Identity,
+            filter: DetectionFilter::default(),
         }
     }
 
@@ -179,7 +326,7 @@ mod tests {
         transform: &TransformParams,
     ) -> anyhow::Result<Vec<TestDetection>> {
         let mut builder = FlatBufferBuilder::new();
-        let (detections_vector, count) =
+        let (detections_vector, class_ids) =
             post_processor.parse_detections(&mut builder, dets, logits, transform)?;
 
         // Build a DetectionResult to finish the buffer
@@ -189,8 +336,18 @@ mod tests {
                 camera_id: 0,
                 frame_number: 0,
                 timestamp_ns: 0,
+                timestamp_clock: schema::ClockDomain::WallClock,
                 detections: Some(detections_vector),
+                inference_started_ns: 0,
+                inference_completed_ns: 0,
+                late: false,
                 trace: None,
+                provenance: None,
+                plates: None,
+                is_cached: false,
+                truncated_count: 0,
+                frame_width: 0,
+                frame_height: 0,
             },
         );
         builder.finish(result, None);
@@ -199,7 +356,7 @@ mod tests {
         let buf = builder.finished_data();
         let detection_result = flatbuffers::root::<schema::DetectionResult>(buf)?;
 
-        let mut results = Vec::with_capacity(count);
+        let mut results = Vec::with_capacity(class_ids.len());
         if let Some(detections) = detection_result.detections() {
             for det in detections {
                 let bbox = det.box_().unwrap();
@@ -214,6 +371,12 @@ mod tests {
             }
         }
 
+        // The returned class ids follow the written detections
+        assert_eq!(
+            results.iter().map(|d| d.class_id).collect::<Vec<_>>(),
+            class_ids
+        );
+
         Ok(results)
     }
 
@@ -307,6 +470,76 @@ mod tests {
         assert_eq!(detections[1].class_id, 2, "Class ID should match (car)");
     }
 
+    /// Test the threshold applies to the calibrated confidence
+    #[test]
+    fn test_calibration_applies_before_threshold() {
+        // sigmoid(1.39) ≈ 0.8 passes uncalibrated, sigmoid(1.39 / 2) ≈ 0.67 does not
+        let (dets, logits) = create_rfdetr_test_data(
+            vec![[0.3, 0.3, 0.1, 0.1], [0.5, 0.5, 0.1, 0.1]],
+            vec![(1, 1.39), (1, 4.0)],
+            91,
+        );
+        let post_processor = test_postprocessor()
+            .with_calibration(ConfidenceCalibration::Temperature { temperature: 2.0 });
+        let transform = test_transform(512, 512, 1.0, 0.0, 0.0);
+        let detections =
+            run_parse_detections(&post_processor, &dets.view(), &logits.view(), &transform)
+                .unwrap();
+
+        assert_eq!(detections.len(), 1);
+        assert!((detections[0].confidence - sigmoid(2.0)).abs() < 1e-6);
+    }
+
+    /// Test every box format maps the same box to the same coordinates
+    #[test]
+    fn test_box_formats_agree() {
+        // Same box in each layout: xyxy (0.4, 0.4, 0.6, 0.6) normalized on a 512x512 input
+        let cases = [
+            (BoxFormat::CxcywhNorm, [0.5, 0.5, 0.2, 0.2]),
+            (BoxFormat::XyxyNorm, [0.4, 0.4, 0.6, 0.6]),
+            (BoxFormat::XyxyAbs, [204.8, 204.8, 307.2, 307.2]),
+        ];
+
+        for (format, raw) in cases {
+            let (dets, logits) = create_rfdetr_test_data(vec![raw], vec![(1, 5.0)], 91);
+            let post_processor = test_postprocessor().with_box_format(format);
+            let transform = test_transform(800, 600, 0.64, 0.0, 64.0);
+            let detections =
+                run_parse_detections(&post_processor, &dets.view(), &logits.view(), &transform)
+                    .unwrap();
+
+            let det = &detections[0];
+            for (got, expected) in [
+                (det.x1, 320.0),
+                (det.y1, 220.0),
+                (det.x2, 480.0),
+                (det.y2, 380.0),
+            ] {
+                assert!(
+                    (got - expected).abs() < 0.1,
+                    "{:?}: expected {}, got {}",
+                    format,
+                    expected,
+                    got
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_box_format_parsing() {
+        assert_eq!("xyxy_abs".parse::<BoxFormat>().unwrap(), BoxFormat::XyxyAbs);
+        assert_eq!(
+            "XYXY_NORM".parse::<BoxFormat>().unwrap(),
+            BoxFormat::XyxyNorm
+        );
+        assert_eq!(
+            "cxcywh_norm".parse::<BoxFormat>().unwrap(),
+            BoxFormat::CxcywhNorm
+        );
+        assert!("xywh".parse::<BoxFormat>().is_err());
+    }
+
     /// Test coordinate inverse transformation with known values
     /// RF-DETR uses normalized cxcywh coordinates
     #[test]
@@ -363,6 +596,36 @@ mod tests {
         );
     }
 
+    /// Boxes from a downscaled frame map back to the camera frame
+    #[test]
+    fn test_rescaled_transform_maps_to_original_frame() {
+        let boxes = vec![[0.5, 0.5, 0.2, 0.2]];
+        let class_logits = vec![(1, 5.0)];
+        let (dets, logits) = create_rfdetr_test_data(boxes, class_logits, 91);
+
+        // Same scene as above, written to shm at 400x300 instead of 800x600
+        let transform = test_transform(400, 300, 1.28, 0.0, 64.0).rescaled_to((800, 600));
+        assert_eq!((transform.orig_width, transform.orig_height), (800, 600));
+
+        let detections = run_parse_detections(
+            &test_postprocessor(),
+            &dets.view(),
+            &logits.view(),
+            &transform,
+        )
+        .unwrap();
+
+        let det = &detections[0];
+        for (actual, expected) in [
+            (det.x1, 320.0),
+            (det.y1, 220.0),
+            (det.x2, 480.0),
+            (det.y2, 380.0),
+        ] {
+            assert!((actual - expected).abs() < 0.1, "{actual} != {expected}");
+        }
+    }
+
     /// Test that coordinates are clamped to image bounds
     #[test]
     fn test_coordinates_clamped_to_image_bounds() {
@@ -400,6 +663,39 @@ mod tests {
         );
     }
 
+    /// Test that sanity filters see boxes in original frame pixels, after clamping
+    #[test]
+    fn test_detection_filter_applies_after_inverse_transform() {
+        let boxes = vec![
+            [0.05, 0.05, 0.2, 0.2], // Clamped against the top-left corner
+            [0.5, 0.5, 0.2, 0.2],   // 102x102 in the middle of the frame
+            [0.5, 0.5, 0.02, 0.02], // 10x10 speck
+        ];
+        let class_logits = vec![(1, 5.0), (1, 5.0), (1, 5.0)];
+        let (dets, logits) = create_rfdetr_test_data(boxes, class_logits, 91);
+
+        let mut post_processor = test_postprocessor();
+        post_processor.filter = DetectionFilter::new(
+            Default::default(),
+            [(
+                0,
+                filter::FilterRules {
+                    min_area: Some(1000.0),
+                    border_margin: Some(1.0),
+                    ..Default::default()
+                },
+            )]
+            .into(),
+        );
+        let transform = test_transform(400, 400, 1.0, 50.0, 50.0);
+        let detections =
+            run_parse_detections(&post_processor, &dets.view(), &logits.view(), &transform)
+                .unwrap();
+
+        assert_eq!(detections.len(), 1);
+        assert!((detections[0].x1 - 154.8).abs() < 0.1);
+    }
+
     /// Test that no detections are returned when all are below threshold
     #[test]
     fn test_zero_detections_when_all_below_threshold() {
@@ -555,4 +851,59 @@ mod tests {
             "Car should have good confidence"
         );
     }
+
+    #[test]
+    fn test_keep_most_confident() {
+        let detection = |confidence| Detection {
+            x1: 0.0,
+            y1: 0.0,
+            x2: 1.0,
+            y2: 1.0,
+            confidence,
+            class_id: 0,
+        };
+        let confidences =
+            |detections: &[Detection]| detections.iter().map(|d| d.confidence).collect::<Vec<_>>();
+
+        let mut detections = vec![detection(0.7), detection(0.9), detection(0.8)];
+        assert_eq!(keep_most_confident(&mut detections, 3), 0);
+        assert_eq!(confidences(&detections), [0.7, 0.9, 0.8]);
+
+        assert_eq!(keep_most_confident(&mut detections, 2), 1);
+        assert_eq!(confidences(&detections), [0.9, 0.8]);
+
+        assert_eq!(keep_most_confident(&mut detections, 0), 2);
+        assert!(detections.is_empty());
+    }
+
+    #[test]
+    fn test_rescale_detections_keeps_order() {
+        let detections = [
+            Detection {
+                x1: 100.0,
+                y1: 50.0,
+                x2: 300.0,
+                y2: 150.0,
+                confidence: 0.9,
+                class_id: 2,
+            },
+            Detection {
+                x1: 0.0,
+                y1: 0.0,
+                x2: 1920.0,
+                y2: 1080.0,
+                confidence: 0.5,
+                class_id: 0,
+            },
+        ];
+
+        let scaled = rescale_detections(&detections, (1920, 1080), (960, 540));
+        assert_eq!(scaled.len(), 2);
+        assert_eq!(
+            (scaled[0].x1, scaled[0].y1, scaled[0].x2, scaled[0].y2),
+            (50.0, 25.0, 150.0, 75.0)
+        );
+        assert_eq!((scaled[0].class_id, scaled[0].confidence), (2, 0.9));
+        assert_eq!((scaled[1].x2, scaled[1].y2), (960.0, 540.0));
+    }
 }
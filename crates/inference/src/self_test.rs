@@ -0,0 +1,374 @@
+//! Scheduled model self-test
+//!
+//! Unattended devices can regress silently: a driver update breaks GPU
+//! preprocessing, a model file gets swapped, and the stream keeps flowing with no
+//! detections. With `SELF_TEST_IMAGE` set, inference runs a stored reference JPEG
+//! through preprocessing and the model on `SELF_TEST_SCHEDULE` (cron, local time,
+//! nightly at 03:00 by default) and checks that the expected object is found:
+//! a detection of `SELF_TEST_CLASS` with at least `SELF_TEST_MIN_CONFIDENCE`
+//! overlapping `SELF_TEST_EXPECTED_BOX` (`x1,y1,x2,y2` in reference image pixels)
+//! by at least `SELF_TEST_MIN_IOU`.
+//!
+//! Results are published (retained) on `SELF_TEST_MQTT_TOPIC` and reported as the
+//! `self_test` check of the readiness state.
+
+use anyhow::{Context, Result, bail};
+use bridge::Detection;
+use chrono::{DateTime, Local};
+use common::classes::{ClassGroups, ClassSet};
+use common::{get_env, get_env_opt};
+use rumqttc::{Client, MqttOptions, QoS};
+use serde::Serialize;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Name of the check in the readiness state
+pub const CHECK_NAME: &str = "self_test";
+
+#[derive(Debug, Clone)]
+pub struct SelfTestConfig {
+    pub schedule: cron::Schedule,
+    pub image_path: String,
+    /// Where the expected object is, in reference image pixels
+    pub expected_box: [f32; 4],
+    pub classes: ClassSet,
+    pub min_confidence: f32,
+    pub min_iou: f32,
+    pub mqtt: SelfTestMqttConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct SelfTestMqttConfig {
+    pub broker_host: String,
+    pub broker_port: u16,
+    pub topic: String,
+    pub device_id: String,
+}
+
+impl SelfTestConfig {
+    /// Enabled when `SELF_TEST_IMAGE` is set
+    pub fn from_env() -> Result<Option<Self>> {
+        let Some(image_path) = get_env_opt::<String>("SELF_TEST_IMAGE") else {
+            return Ok(None);
+        };
+        let expected_box = get_env_opt::<String>("SELF_TEST_EXPECTED_BOX")
+            .context("SELF_TEST_EXPECTED_BOX is required with SELF_TEST_IMAGE")
+            .and_then(|s| parse_box(&s))?;
+
+        Ok(Some(Self {
+            schedule: parse_schedule(&get_env("SELF_TEST_SCHEDULE", "0 3 * * *".to_string()))?,
+            image_path,
+            expected_box,
+            classes: ClassGroups::from_env()?
+                .resolve_list(&get_env("SELF_TEST_CLASS", "person".to_string()))?,
+            min_confidence: get_env("SELF_TEST_MIN_CONFIDENCE", 0.5),
+            min_iou: get_env("SELF_TEST_MIN_IOU", 0.5),
+            mqtt: SelfTestMqttConfig {
+                broker_host: get_env("MQTT_BROKER_HOST", "mosquitto".to_string()),
+                broker_port: get_env("MQTT_BROKER_PORT", 1883),
+                topic: get_env(
+                    "SELF_TEST_MQTT_TOPIC",
+                    "detr-mmap/inference/self_test".to_string(),
+                ),
+                device_id: get_env("MQTT_DEVICE_ID", "unknown".to_string()),
+            },
+        }))
+    }
+}
+
+/// Cron schedule, with or without the leading seconds field
+fn parse_schedule(expression: &str) -> Result<cron::Schedule> {
+    let expression = match expression.split_whitespace().count() {
+        5 => format!("0 {expression}"),
+        _ => expression.to_string(),
+    };
+    cron::Schedule::from_str(&expression)
+        .with_context(|| format!("SELF_TEST_SCHEDULE: invalid cron expression {expression:?}"))
+}
+
+fn parse_box(s: &str) -> Result<[f32; 4]> {
+    let coords = s
+        .split(',')
+        .map(|v| v.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("SELF_TEST_EXPECTED_BOX: {s:?} is not x1,y1,x2,y2"))?;
+    match coords[..] {
+        [x1, y1, x2, y2] if x2 > x1 && y2 > y1 => Ok([x1, y1, x2, y2]),
+        _ => bail!("SELF_TEST_EXPECTED_BOX: {s:?} is not x1,y1,x2,y2 with x1 < x2, y1 < y2"),
+    }
+}
+
+/// Reference image decoded to RGB
+pub struct ReferenceImage {
+    pub rgb: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ReferenceImage {
+    pub fn load(path: &str) -> Result<Self> {
+        let jpeg = std::fs::read(path)
+            .with_context(|| format!("Failed to read self-test image {path}"))?;
+        let image: turbojpeg::Image<Vec<u8>> =
+            turbojpeg::decompress(&jpeg, turbojpeg::PixelFormat::RGB)
+                .with_context(|| format!("Failed to decode self-test image {path}"))?;
+        Ok(Self {
+            width: image.width as u32,
+            height: image.height as u32,
+            rgb: image.pixels,
+        })
+    }
+}
+
+/// Published on every run
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestResult {
+    pub device_id: String,
+    pub timestamp: String,
+    pub passed: bool,
+    /// Why the test failed, or which detection passed it
+    pub detail: String,
+    /// Best matching detection, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iou: Option<f32>,
+    pub duration_ms: u64,
+}
+
+/// Outcome of a run, before it is stamped for publishing
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verdict {
+    pub passed: bool,
+    pub detail: String,
+    pub confidence: Option<f32>,
+    pub iou: Option<f32>,
+}
+
+impl Verdict {
+    pub fn failed(detail: String) -> Self {
+        Self {
+            passed: false,
+            detail,
+            confidence: None,
+            iou: None,
+        }
+    }
+}
+
+pub struct SelfTest {
+    config: SelfTestConfig,
+    reference: ReferenceImage,
+    next_run: Option<DateTime<Local>>,
+    client: Client,
+}
+
+impl SelfTest {
+    pub fn new(config: SelfTestConfig) -> Result<Self> {
+        let reference = ReferenceImage::load(&config.image_path)?;
+
+        let mut options = MqttOptions::new(
+            format!("detr-mmap-inference-{}", config.mqtt.device_id),
+            &config.mqtt.broker_host,
+            config.mqtt.broker_port,
+        );
+        options.set_keep_alive(Duration::from_secs(30));
+        let (client, mut connection) = Client::new(options, 10);
+        // Results are rare, so the event loop only needs to keep the connection alive
+        std::thread::Builder::new()
+            .name("self-test-mqtt".to_string())
+            .spawn(move || {
+                for event in connection.iter() {
+                    if let Err(e) = event {
+                        tracing::debug!(error = %e, "Self-test MQTT connection error");
+                        std::thread::sleep(Duration::from_secs(5));
+                    }
+                }
+            })
+            .context("Failed to spawn self-test MQTT thread")?;
+
+        let next_run = config.schedule.upcoming(Local).next();
+        tracing::info!(
+            image = %config.image_path,
+            next_run = ?next_run,
+            topic = %config.mqtt.topic,
+            "Self-test scheduled"
+        );
+
+        Ok(Self {
+            config,
+            reference,
+            next_run,
+            client,
+        })
+    }
+
+    pub fn reference(&self) -> &ReferenceImage {
+        &self.reference
+    }
+
+    /// Whether a run is due, scheduling the next one if so
+    pub fn due(&mut self) -> bool {
+        let now = Local::now();
+        if self.next_run.is_none_or(|next| next > now) {
+            return false;
+        }
+        self.next_run = self.config.schedule.after(&now).next();
+        true
+    }
+
+    /// Check the detections found on the reference image
+    pub fn evaluate(&self, detections: &[Detection]) -> Verdict {
+        evaluate(
+            detections,
+            self.config.expected_box,
+            self.config.classes,
+            self.config.min_confidence,
+            self.config.min_iou,
+        )
+    }
+
+    /// Publish the outcome of a run
+    pub fn report(&self, verdict: Verdict, duration: Duration) {
+        let result = SelfTestResult {
+            device_id: self.config.mqtt.device_id.clone(),
+            timestamp: Local::now().to_rfc3339(),
+            passed: verdict.passed,
+            detail: verdict.detail,
+            confidence: verdict.confidence,
+            iou: verdict.iou,
+            duration_ms: duration.as_millis() as u64,
+        };
+        if result.passed {
+            tracing::info!(detail = %result.detail, "Self-test passed");
+        } else {
+            tracing::error!(detail = %result.detail, "Self-test failed");
+        }
+
+        let payload = match serde_json::to_vec(&result) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to serialize self-test result");
+                return;
+            }
+        };
+        // Retained, so a dashboard connecting later still sees the last outcome
+        if let Err(e) =
+            self.client
+                .try_publish(&self.config.mqtt.topic, QoS::AtLeastOnce, true, payload)
+        {
+            tracing::warn!(error = %e, "Failed to publish self-test result");
+        }
+    }
+}
+
+fn evaluate(
+    detections: &[Detection],
+    expected: [f32; 4],
+    classes: ClassSet,
+    min_confidence: f32,
+    min_iou: f32,
+) -> Verdict {
+    let best = detections
+        .iter()
+        .filter(|det| classes.contains(det.class_id))
+        .map(|det| (det, iou(det, expected)))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    let Some((det, overlap)) = best else {
+        return Verdict::failed(format!(
+            "No detection of the expected class among {}",
+            detections.len()
+        ));
+    };
+    let passed = det.confidence >= min_confidence && overlap >= min_iou;
+    let detail = if passed {
+        format!(
+            "Found at confidence {:.2}, IoU {:.2}",
+            det.confidence, overlap
+        )
+    } else {
+        format!(
+            "Best match at confidence {:.2} (min {:.2}), IoU {:.2} (min {:.2})",
+            det.confidence, min_confidence, overlap, min_iou
+        )
+    };
+    Verdict {
+        passed,
+        detail,
+        confidence: Some(det.confidence),
+        iou: Some(overlap),
+    }
+}
+
+fn iou(det: &Detection, [x1, y1, x2, y2]: [f32; 4]) -> f32 {
+    let width = (det.x2.min(x2) - det.x1.max(x1)).max(0.0);
+    let height = (det.y2.min(y2) - det.y1.max(y1)).max(0.0);
+    let intersection = width * height;
+    let union = (det.x2 - det.x1) * (det.y2 - det.y1) + (x2 - x1) * (y2 - y1) - intersection;
+    if union > 0.0 {
+        intersection / union
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn det(x1: f32, y1: f32, x2: f32, y2: f32, confidence: f32, class_id: u16) -> Detection {
+        Detection {
+            x1,
+            y1,
+            x2,
+            y2,
+            confidence,
+            class_id,
+        }
+    }
+
+    #[test]
+    fn test_evaluate_matches_expected_person() {
+        let person = ClassSet::single(0);
+        let expected = [100.0, 100.0, 200.0, 300.0];
+
+        let detections = [
+            det(0.0, 0.0, 50.0, 50.0, 0.9, 2),
+            det(105.0, 95.0, 200.0, 310.0, 0.8, 0),
+        ];
+        let verdict = evaluate(&detections, expected, person, 0.5, 0.5);
+        assert!(verdict.passed, "{}", verdict.detail);
+        assert!(verdict.iou.unwrap() > 0.85);
+
+        // Found, but in the wrong place
+        let detections = [det(300.0, 100.0, 400.0, 300.0, 0.9, 0)];
+        let verdict = evaluate(&detections, expected, person, 0.5, 0.5);
+        assert!(!verdict.passed);
+        assert_eq!(verdict.iou, Some(0.0));
+
+        // Only other classes
+        let verdict = evaluate(
+            &[det(100.0, 100.0, 200.0, 300.0, 0.9, 2)],
+            expected,
+            person,
+            0.5,
+            0.5,
+        );
+        assert_eq!(verdict.confidence, None);
+        assert!(!verdict.passed);
+    }
+
+    #[test]
+    fn test_schedule_accepts_five_fields() {
+        let schedule = parse_schedule("30 3 * * *").unwrap();
+        let after = Local.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+        let next = schedule.after(&after).next().unwrap();
+        assert_eq!(next, Local.with_ymd_and_hms(2025, 1, 2, 3, 30, 0).unwrap());
+
+        assert!(parse_schedule("not a schedule").is_err());
+        assert!(parse_box("10,10,5,20").is_err());
+        assert_eq!(parse_box("1, 2, 3, 4").unwrap(), [1.0, 2.0, 3.0, 4.0]);
+    }
+}
@@ -1,26 +1,62 @@
 use crate::{
-    backend::{InferenceBackend, InferenceOutput},
+    backend::{DeadlineExceeded, InferenceBackend, InferenceOutput},
+    coalesce::PostCoalescer,
     config::InferenceConfig,
-    processing::post::{PostProcessor, TransformParams},
+    debug_dump::{DebugDump, DumpRecord},
+    metrics::InferenceMetrics,
+    processing::{
+        AppearanceSampler,
+        change::{DetectionCache, FrameSignature},
+        decode::FrameDecoder,
+        masks::SceneMasks,
+        post::{PostProcessor, build_detections, keep_most_confident, rescale_detections},
+    },
+    self_test::{self, SelfTest, Verdict},
 };
-use bridge::{BridgeSemaphore, DetectionWriter, FrameReader, SemaphoreType, set_trace_parent};
-use common::wait_for_resource;
-use opentelemetry::{
-    global,
-    metrics::{Counter, Histogram},
+use bridge::{
+    BridgeSemaphore, CapturedAt, Detection, DetectionWriter, FormatWatch, FrameFormat, FrameReader,
+    FrameSubscription, Provenance, SemaphoreType, SentryControl, SentryMode, Threshold,
+    ThresholdControl, set_trace_parent,
 };
-use preprocess::{CpuPreProcessor, Preprocess, PreprocessResult};
+use common::{Dependency, Readiness, ThermalMonitor, WallClockNs, Watchdog, log_throttle};
+use preprocess::{CpuPreProcessor, Preprocess, PreprocessProfile, PreprocessResult};
 use std::thread;
 use std::time::{Duration, Instant};
 
+#[cfg(feature = "ort-backend")]
+use crate::plates::PlateReader;
 #[cfg(feature = "gpu-preprocess")]
 use preprocess::GpuPreProcessor;
+#[cfg(feature = "jetson")]
+use preprocess::JetsonPreProcessor;
 
 /// Enum to hold either CPU or GPU preprocessor
 enum PreprocessorVariant {
     Cpu(CpuPreProcessor),
     #[cfg(feature = "gpu-preprocess")]
     Gpu(GpuPreProcessor),
+    #[cfg(feature = "jetson")]
+    Jetson(JetsonPreProcessor),
+}
+
+impl PreprocessorVariant {
+    /// Preprocess the frame from its NVMM surface when both sides support it
+    fn preprocess_frame(
+        &mut self,
+        frame: &schema::Frame<'_>,
+        pixels: &[u8],
+    ) -> anyhow::Result<PreprocessResult> {
+        #[cfg(feature = "jetson")]
+        if let (PreprocessorVariant::Jetson(p), Ok(index)) =
+            (&mut *self, u32::try_from(frame.nvmm_surface()))
+        {
+            // The surface holds the camera frame as captured, before any downscale
+            let (width, height) = original_size(frame);
+            return p.preprocess_surface(index, width, height);
+        }
+
+        self.preprocess(pixels, frame.width(), frame.height())
+    }
 }
 
 impl Preprocess for PreprocessorVariant {
@@ -34,6 +70,8 @@ impl Preprocess for PreprocessorVariant {
             PreprocessorVariant::Cpu(p) => p.preprocess(pixels, width, height),
             #[cfg(feature = "gpu-preprocess")]
             PreprocessorVariant::Gpu(p) => p.preprocess(pixels, width, height),
+            #[cfg(feature = "jetson")]
+            PreprocessorVariant::Jetson(p) => p.preprocess(pixels, width, height),
         }
     }
 
@@ -42,6 +80,18 @@ impl Preprocess for PreprocessorVariant {
             PreprocessorVariant::Cpu(p) => p.input_size(),
             #[cfg(feature = "gpu-preprocess")]
             PreprocessorVariant::Gpu(p) => p.input_size(),
+            #[cfg(feature = "jetson")]
+            PreprocessorVariant::Jetson(p) => p.input_size(),
+        }
+    }
+
+    fn frame_size_changed(&mut self, width: u32, height: u32) -> anyhow::Result<()> {
+        match self {
+            PreprocessorVariant::Cpu(p) => p.frame_size_changed(width, height),
+            #[cfg(feature = "gpu-preprocess")]
+            PreprocessorVariant::Gpu(p) => p.frame_size_changed(width, height),
+            #[cfg(feature = "jetson")]
+            PreprocessorVariant::Jetson(p) => p.frame_size_changed(width, height),
         }
     }
 }
@@ -51,61 +101,131 @@ pub struct InferenceService<B: InferenceBackend> {
     config: InferenceConfig,
     postprocessor: PostProcessor,
     preprocessor: PreprocessorVariant,
-}
-
-fn init_metrics(
-    meter_name: &'static str,
-) -> (Histogram<f64>, Counter<u64>, Counter<u64>, Counter<u64>) {
-    let meter = global::meter(meter_name);
-    let latency_buckets = [
-        0.001, 0.002, 0.005, 0.007, 0.01, 0.015, 0.02, 0.025, 0.03, 0.04, 0.05, 0.075, 0.1, 0.15,
-        0.2, 0.5,
-    ];
-    let duration_histogram: Histogram<f64> = meter
-        .f64_histogram("inference_duration_seconds")
-        .with_description("Time to process a single frame (preprocess + infer + postprocess)")
-        .with_unit("s")
-        .with_boundaries(latency_buckets.to_vec())
-        .build();
-    let frames_counter: Counter<u64> = meter
-        .u64_counter("inference_frames_total")
-        .with_description("Total frames processed")
-        .build();
-    let skipped_counter: Counter<u64> = meter
-        .u64_counter("inference_frames_skipped_total")
-        .with_description("Total frames skipped (processing too slow)")
-        .build();
-    let detections_counter: Counter<u64> = meter
-        .u64_counter("inference_detections_total")
-        .with_description("Total detections produced")
-        .build();
-
-    (
-        duration_histogram,
-        frames_counter,
-        skipped_counter,
-        detections_counter,
-    )
+    decoder: FrameDecoder,
+    detection_cache: Option<DetectionCache>,
+    /// Size of the frames, changing when capture renegotiates its resolution
+    format: FormatWatch,
+    debug_dump: Option<DebugDump>,
+    scene_masks: Option<SceneMasks>,
+    self_test: Option<SelfTest>,
+    /// Switches the backend to its fallback model while the SoC is throttled
+    thermal: Option<ThermalMonitor>,
+    #[cfg(feature = "ort-backend")]
+    plate_reader: Option<PlateReader>,
 }
 
 impl<B: InferenceBackend> InferenceService<B> {
     pub fn new(backend: B, config: InferenceConfig) -> Self {
-        let postprocessor = PostProcessor::new(config.confidence_threshold);
+        let postprocessor = PostProcessor::new(config.confidence_threshold)
+            .with_box_format(config.box_format)
+            .with_calibration(config.confidence_calibration.clone())
+            .with_filter(config.detection_filter.clone());
 
         let preprocessor = Self::create_preprocessor(&config);
 
+        let debug_dump = config.debug_dump.clone().and_then(|dump| {
+            DebugDump::new(dump)
+                .inspect_err(|e| tracing::warn!("Debug dump disabled: {:#}", e))
+                .ok()
+        });
+
+        let scene_masks = config.scene_masks.clone().and_then(|masks| {
+            SceneMasks::new(masks)
+                .inspect_err(|e| tracing::warn!("Scene masks disabled: {:#}", e))
+                .ok()
+        });
+
+        let self_test = config.self_test.clone().and_then(|self_test| {
+            SelfTest::new(self_test)
+                .inspect_err(|e| tracing::warn!("Self-test disabled: {:#}", e))
+                .ok()
+        });
+
+        #[cfg(feature = "ort-backend")]
+        let plate_reader = config.plates.clone().and_then(|plates| {
+            PlateReader::new(plates)
+                .inspect_err(|e| {
+                    tracing::warn!(error = %e, "Failed to load plate models, plate stage disabled")
+                })
+                .ok()
+        });
+
+        #[cfg(not(feature = "ort-backend"))]
+        if config.plates.is_some() {
+            tracing::warn!("Plate stage configured but ort-backend feature not enabled");
+        }
+
+        let thermal = config.precision_fallback.as_ref().map(|fallback| {
+            ThermalMonitor::new(
+                fallback.throttle_celsius,
+                fallback.recover_celsius,
+                fallback.poll_interval,
+            )
+        });
+
         Self {
             backend,
+            detection_cache: config.detection_cache.clone().map(DetectionCache::new),
             config,
             postprocessor,
             preprocessor,
+            format: FormatWatch::new(),
+            decoder: FrameDecoder::new(),
+            debug_dump,
+            scene_masks,
+            self_test,
+            thermal,
+            #[cfg(feature = "ort-backend")]
+            plate_reader,
         }
     }
 
     fn create_preprocessor(config: &InferenceConfig) -> PreprocessorVariant {
+        let gpu_compatible = config.preprocess_profile.gpu_compatible();
+        if (config.use_gpu_preprocess || config.use_nvmm_preprocess) && !gpu_compatible {
+            tracing::warn!(
+                profile = %config.preprocess_profile,
+                "Preprocess profile not supported on the GPU, using CPU"
+            );
+        }
+
+        #[cfg(feature = "jetson")]
+        if config.use_nvmm_preprocess && gpu_compatible {
+            match JetsonPreProcessor::new(
+                config.input_size,
+                config.max_input_size,
+                config.model_color_order,
+            ) {
+                Ok(jetson_preprocessor) => {
+                    tracing::info!("NVMM preprocessing enabled");
+                    if config.denoise.is_some() {
+                        tracing::warn!("Denoise is not supported on NVMM surfaces, disabled");
+                    }
+                    return PreprocessorVariant::Jetson(jetson_preprocessor);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        error = %e,
+                        "Failed to initialize NVMM preprocessor, falling back"
+                    );
+                }
+            }
+        }
+
+        #[cfg(not(feature = "jetson"))]
+        if config.use_nvmm_preprocess {
+            tracing::warn!("NVMM preprocessing requested but jetson feature not enabled");
+        }
+
         #[cfg(feature = "gpu-preprocess")]
-        if config.use_gpu_preprocess {
-            match GpuPreProcessor::new(config.input_size, config.max_input_size) {
+        if config.use_gpu_preprocess && gpu_compatible {
+            let gpu_preprocessor = GpuPreProcessor::new(config.input_size, config.max_input_size)
+                .map(|gpu| gpu.with_model_order(config.model_color_order))
+                .and_then(|gpu| match config.denoise {
+                    Some(denoise) => gpu.with_denoise(denoise),
+                    None => Ok(gpu),
+                });
+            match gpu_preprocessor {
                 Ok(gpu_preprocessor) => {
                     tracing::info!("GPU preprocessing enabled");
                     return PreprocessorVariant::Gpu(gpu_preprocessor);
@@ -126,38 +246,72 @@ impl<B: InferenceBackend> InferenceService<B> {
             );
         }
 
-        tracing::info!("Using CPU preprocessing");
-        PreprocessorVariant::Cpu(CpuPreProcessor::new(config.input_size))
+        tracing::info!(
+            profile = %config.preprocess_profile,
+            color_order = %config.model_color_order,
+            "Using CPU preprocessing"
+        );
+        let mut cpu_preprocessor =
+            CpuPreProcessor::new(config.input_size).with_profile(PreprocessProfile {
+                channel_order: config.model_color_order,
+                ..config.preprocess_profile
+            });
+        if let Some(denoise) = config.denoise {
+            cpu_preprocessor = cpu_preprocessor.with_denoise(denoise);
+        }
+        PreprocessorVariant::Cpu(cpu_preprocessor)
     }
 
-    pub fn run(mut self) -> anyhow::Result<()> {
+    pub fn run(mut self, readiness: &Readiness) -> anyhow::Result<()> {
         tracing::info!(
             model_path = %self.config.model_path,
             "Inference service starting"
         );
 
-        let mut frame_reader = wait_for_resource(
-            FrameReader::build,
-            self.config.poll_interval_ms,
+        let poll_interval = Duration::from_millis(self.config.poll_interval_ms);
+
+        let mut frame_reader = readiness.wait_for(
+            Dependency::FrameBuffer,
             "Frame buffer",
-        );
+            poll_interval,
+            FrameReader::build,
+        )?;
 
         let mut detection_writer = DetectionWriter::build()?;
 
-        let frame_semaphore = wait_for_resource(
-            || BridgeSemaphore::open(SemaphoreType::FrameCaptureToInference),
-            self.config.poll_interval_ms,
-            "Inference semaphore",
-        );
+        let frame_semaphore = readiness.wait_for(
+            Dependency::Semaphores,
+            "Inference frame subscription",
+            poll_interval,
+            || FrameSubscription::register("inference"),
+        )?;
 
-        let controller_semaphore = wait_for_resource(
-            || BridgeSemaphore::ensure(SemaphoreType::DetectionInferenceToController),
-            self.config.poll_interval_ms,
+        let controller_semaphore = readiness.wait_for(
+            Dependency::Semaphores,
             "Controller semaphore",
-        );
+            poll_interval,
+            || BridgeSemaphore::ensure(SemaphoreType::DetectionInferenceToController),
+        )?;
+
+        let metrics = InferenceMetrics::new("inference");
 
-        let (duration_histogram, frames_counter, skipped_counter, detections_counter) =
-            init_metrics("inference");
+        let thresholds = ThresholdControl::build()
+            .inspect_err(|e| tracing::warn!(error = %e, "Runtime threshold overrides unavailable"))
+            .ok();
+
+        let mut coalescer = self.config.controller_heartbeat.map(PostCoalescer::new);
+        // Without the sentry mode every empty result counts as state-relevant
+        let sentry = coalescer.as_ref().and_then(|_| {
+            SentryControl::build()
+                .inspect_err(
+                    |e| tracing::warn!(error = %e, "Sentry mode unavailable, posting every result"),
+                )
+                .ok()
+        });
+
+        let mut watchdog = Watchdog::from_env();
+        let wait_timeout = watchdog.ping_interval().unwrap_or(Duration::from_secs(1));
+        watchdog.ready();
 
         tracing::info!("Starting inference loop (event-driven)");
 
@@ -166,11 +320,24 @@ impl<B: InferenceBackend> InferenceService<B> {
         let mut frames_skipped = 0u64;
 
         loop {
-            // Wait for frame ready signal
-            if let Err(e) = frame_semaphore.wait() {
-                tracing::error!(error = %e, "Semaphore wait failed");
-                thread::sleep(Duration::from_millis(self.config.poll_interval_ms));
-                continue;
+            watchdog.ping();
+
+            if self.self_test.as_mut().is_some_and(SelfTest::due) {
+                self.run_self_test(readiness, &metrics);
+            }
+            if let Some(throttled) = self.thermal.as_mut().and_then(ThermalMonitor::poll) {
+                self.switch_precision(throttled, &metrics);
+            }
+
+            // Wait for frame ready signal, waking up periodically to keep the watchdog alive
+            match frame_semaphore.wait_timeout_duration(wait_timeout) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    tracing::error!(error = %e, "Semaphore wait failed");
+                    thread::sleep(Duration::from_millis(self.config.poll_interval_ms));
+                    continue;
+                }
             }
 
             // Drain any additional pending signals to skip to the latest frame
@@ -178,28 +345,52 @@ impl<B: InferenceBackend> InferenceService<B> {
                 Ok(skipped) => {
                     if skipped > 0 {
                         frames_skipped += skipped as u64;
-                        skipped_counter.add(skipped as u64, &[]);
+                        metrics.record_skipped(skipped as u64);
                         tracing::trace!(skipped, "Skipped frames to process latest");
                     }
                 }
                 Err(e) => {
-                    tracing::warn!(error = %e, "Failed to drain semaphore");
+                    log_throttle!(warn, error = %e, "Failed to drain semaphore");
                 }
             }
 
+            if let Some(thresholds) = &thresholds {
+                self.apply_thresholds(thresholds);
+            }
+
+            if let Some(pid) = frame_reader.writer_changed() {
+                tracing::warn!(pid, "Frame buffer claimed by another writer");
+            }
+
             let start = Instant::now();
             match self.process_frame(&frame_reader, &mut detection_writer) {
-                Ok(detections) => {
-                    let elapsed = start.elapsed().as_secs_f64();
-                    duration_histogram.record(elapsed, &[]);
-                    frames_counter.add(1, &[]);
-                    detections_counter.add(detections as u64, &[]);
+                Ok(FrameOutcome {
+                    class_ids,
+                    late,
+                    cached,
+                    truncated,
+                }) => {
+                    metrics.record_truncated(truncated);
+                    if cached {
+                        metrics.record_cached(&class_ids);
+                    } else {
+                        metrics.record_frame(start.elapsed().as_secs_f64(), &class_ids, late);
+                    }
+                    let detections = class_ids.len();
 
                     frames_processed += 1;
                     total_detections += detections;
 
-                    if let Err(e) = controller_semaphore.post() {
-                        tracing::warn!(error = %e, "Failed to signal controller");
+                    let idle = sentry
+                        .as_ref()
+                        .is_some_and(|sentry| sentry.get_mode() == SentryMode::Standby);
+                    let post = coalescer.as_mut().is_none_or(|coalescer| {
+                        coalescer.should_post(detections > 0, idle, Instant::now())
+                    });
+                    if !post {
+                        metrics.record_wakeup_skipped();
+                    } else if let Err(e) = controller_semaphore.post() {
+                        log_throttle!(warn, error = %e, "Failed to signal controller");
                     }
 
                     if frames_processed.is_multiple_of(10) {
@@ -213,7 +404,7 @@ impl<B: InferenceBackend> InferenceService<B> {
                     }
                 }
                 Err(e) => {
-                    tracing::error!(error = %e, "Failed to process frame");
+                    log_throttle!(error, error = %e, "Failed to process frame");
                 }
             }
 
@@ -221,14 +412,113 @@ impl<B: InferenceBackend> InferenceService<B> {
         }
     }
 
-    fn process_frame(
+    /// Process the current frame once outside of `run`, as the single-binary demo
+    /// does with in-process buffers. Returns the number of detections written.
+    pub fn process(
         &mut self,
         frame_reader: &FrameReader,
         detection_writer: &mut DetectionWriter,
     ) -> anyhow::Result<usize> {
+        self.process_frame(frame_reader, detection_writer)
+            .map(|outcome| outcome.class_ids.len())
+    }
+
+    /// Run the fallback model while `throttled`, the primary one otherwise
+    fn switch_precision(&mut self, throttled: bool, metrics: &InferenceMetrics) {
+        if !self.backend.set_fallback(throttled) {
+            tracing::warn!(
+                throttled,
+                "Backend has no fallback model, precision unchanged"
+            );
+            return;
+        }
+        metrics.record_fallback(throttled);
+        if throttled {
+            tracing::warn!("SoC throttled, switching to the fallback model");
+        } else {
+            tracing::info!("SoC cooled down, switching back to the primary model");
+        }
+    }
+
+    /// Run the reference image through the model and report whether it still detects
+    fn run_self_test(&mut self, readiness: &Readiness, metrics: &InferenceMetrics) {
+        let start = Instant::now();
+        let verdict = match self.detect_reference() {
+            Ok(detections) => self.self_test.as_ref().map(|t| t.evaluate(&detections)),
+            Err(e) => Some(Verdict::failed(format!("Inference failed: {e:#}"))),
+        };
+        let (Some(self_test), Some(verdict)) = (self.self_test.as_ref(), verdict) else {
+            return;
+        };
+
+        readiness.set_check(self_test::CHECK_NAME, verdict.passed);
+        metrics.record_self_test(verdict.passed);
+        self_test.report(verdict, start.elapsed());
+    }
+
+    fn detect_reference(&mut self) -> anyhow::Result<Vec<Detection>> {
+        let Some(reference) = self.self_test.as_ref().map(SelfTest::reference) else {
+            return Ok(Vec::new());
+        };
+        let PreprocessResult { data, transform } =
+            self.preprocessor
+                .preprocess(&reference.rgb, reference.width, reference.height)?;
+        self.backend.set_run_timeout(None);
+        let InferenceOutput { dets, logits } = self.backend.infer_preprocessed(&data)?;
+        Ok(self
+            .postprocessor
+            .detections(&dets.view(), &logits.view(), &transform))
+    }
+
+    /// Pick up a confidence threshold changed at runtime
+    fn apply_thresholds(&mut self, thresholds: &ThresholdControl) {
+        let threshold = thresholds.resolve(Threshold::Confidence, self.config.confidence_threshold);
+        if threshold != self.postprocessor.confidence_threshold {
+            tracing::info!(
+                from = self.postprocessor.confidence_threshold,
+                to = threshold,
+                "Confidence threshold changed"
+            );
+            self.postprocessor.confidence_threshold = threshold;
+            // Cached detections were filtered with the previous threshold
+            if let Some(cache) = &mut self.detection_cache {
+                cache.clear();
+            }
+        }
+    }
+
+    /// Drop what was kept for frames of another size
+    fn observe_format(&mut self, format: FrameFormat) -> anyhow::Result<()> {
+        let Some(previous) = self.format.observe(format) else {
+            return Ok(());
+        };
+        tracing::info!(
+            previous_width = previous.width,
+            previous_height = previous.height,
+            width = format.width,
+            height = format.height,
+            generation = format.generation,
+            "Frame size changed"
+        );
+        self.preprocessor
+            .frame_size_changed(format.width, format.height)?;
+        if let Some(cache) = &mut self.detection_cache {
+            cache.clear();
+        }
+        Ok(())
+    }
+
+    fn process_frame(
+        &mut self,
+        frame_reader: &FrameReader,
+        detection_writer: &mut DetectionWriter,
+    ) -> anyhow::Result<FrameOutcome> {
+        let start = Instant::now();
+        let started_ns = WallClockNs::now();
         let frame = frame_reader
             .get_frame()?
             .ok_or_else(|| anyhow::anyhow!("No frame available"))?;
+        self.observe_format(FrameFormat::of(&frame))?;
 
         // Extract trace context (Copy type, 25 bytes) for later use
         let trace_ctx = frame.trace().copied();
@@ -242,59 +532,291 @@ impl<B: InferenceBackend> InferenceService<B> {
 
         let camera_id = frame.camera_id();
         let frame_number = frame.frame_number();
-        let timestamp_ns = frame.timestamp_ns();
+        let timestamp = frame.captured_at();
         let width = frame.width();
         let height = frame.height();
 
-        let pixels = frame
-            .pixels()
-            .ok_or_else(|| anyhow::anyhow!("No pixel data"))?;
+        let pixels = self.decoder.pixels(&frame)?;
+        let appearance = self.config.appearance_classes.map(|classes| {
+            AppearanceSampler::new(classes, pixels, (width, height), original_size(&frame))
+        });
+
+        // An unchanged frame gets the detections of the last frame the model ran on
+        let signature = self
+            .detection_cache
+            .as_ref()
+            .map(|_| FrameSignature::of_rgb(pixels, width, height));
+        if let Some((cache, signature)) = self.detection_cache.as_ref().zip(signature.as_ref())
+            && let Some(detections) = cache.lookup(signature, Instant::now())
+        {
+            let mut detections = detections.to_vec();
+            if let Some(masks) = &mut self.scene_masks {
+                masks.apply(&mut detections, original_size(&frame), Instant::now());
+            }
+            let truncated = keep_most_confident(&mut detections, detection_writer.max_detections());
+            let builder = detection_writer.builder();
+            builder.reset();
+            let (detections_offset, class_ids) =
+                build_detections(builder, detections, appearance.as_ref());
+            let provenance = frame
+                .provenance()
+                .map(|p| Provenance::copy_into(builder, &p));
+            let (original_width, original_height) = original_size(&frame);
+            detection_writer.set_next_frame_size(original_width, original_height);
+            detection_writer.set_next_cached();
+            detection_writer.set_next_truncated(truncated as u32);
+            detection_writer.set_next_inference_timing(started_ns, WallClockNs::now());
+            detection_writer.write_detections(
+                camera_id,
+                frame_number,
+                timestamp,
+                detections_offset,
+                trace_ctx.as_ref(),
+                provenance,
+            )?;
+            return Ok(FrameOutcome {
+                class_ids,
+                late: false,
+                cached: true,
+                truncated,
+            });
+        }
+
+        let dump_due = self.debug_dump.as_mut().is_some_and(DebugDump::due);
 
         // Preprocess frame (CPU or GPU based on config)
         let PreprocessResult {
             data: preprocessed,
-            scale,
-            offset_x,
-            offset_y,
+            transform,
         } = {
             let _s = common::span!("preprocessing");
-            self.preprocessor
-                .preprocess(pixels.bytes(), width, height)?
+            self.preprocessor.preprocess_frame(&frame, pixels)?
         };
 
-        let InferenceOutput { dets, logits } = {
-            let _s = common::span!("model_inference");
-            self.backend.infer_preprocessed(&preprocessed)?
+        // Whatever is left of the deadline bounds the model run
+        let budget = self
+            .config
+            .frame_deadline
+            .map(|deadline| deadline.saturating_sub(start.elapsed()));
+        let output = match budget {
+            Some(Duration::ZERO) => None,
+            _ => {
+                let _s = common::span!("model_inference");
+                self.backend.set_run_timeout(budget);
+                match self.backend.infer_preprocessed(&preprocessed) {
+                    Ok(output) => Some(output),
+                    Err(e) if e.is::<DeadlineExceeded>() => None,
+                    Err(e) => return Err(e),
+                }
+            }
         };
+        let late = output.is_none()
+            || self
+                .config
+                .frame_deadline
+                .is_some_and(|deadline| start.elapsed() > deadline);
 
-        let transform = TransformParams {
-            orig_width: width,
-            orig_height: height,
-            input_width: self.config.input_size.0,
-            input_height: self.config.input_size.1,
-            scale,
-            offset_x,
-            offset_y,
-        };
+        // Boxes are reported in the camera frame, even when capture downscaled it
+        let transform = transform.rescaled_to(original_size(&frame));
 
+        let max_detections = detection_writer.max_detections();
         let builder = detection_writer.builder();
         builder.reset();
 
-        let (detections_offset, count) = self.postprocessor.parse_detections(
-            builder,
-            &dets.view(),
-            &logits.view(),
-            &transform,
-        )?;
+        let output = output.filter(|_| !(late && self.config.skip_late_postprocess));
+        let Some(InferenceOutput { dets, logits }) = output else {
+            log_throttle!(
+                debug,
+                frame_number,
+                "Frame overran its deadline, writing it without detections"
+            );
+            let detections_offset = builder.create_vector::<flatbuffers::ForwardsUOffset<_>>(&[]);
+            let provenance = frame
+                .provenance()
+                .map(|p| Provenance::copy_into(builder, &p));
+            let (original_width, original_height) = original_size(&frame);
+            detection_writer.set_next_frame_size(original_width, original_height);
+            detection_writer.set_next_late();
+            detection_writer.set_next_inference_timing(started_ns, WallClockNs::now());
+            detection_writer.write_detections(
+                camera_id,
+                frame_number,
+                timestamp,
+                detections_offset,
+                trace_ctx.as_ref(),
+                provenance,
+            )?;
+            if let Some(dump) = self.debug_dump.as_ref().filter(|_| dump_due) {
+                dump.write(&DumpRecord {
+                    camera_id,
+                    frame_number,
+                    pixels,
+                    width,
+                    height,
+                    tensor: &preprocessed,
+                    transform: &transform.rescaled_to((width, height)),
+                    detections: &[],
+                    late: true,
+                });
+            }
+            return Ok(FrameOutcome {
+                class_ids: Vec::new(),
+                late: true,
+                cached: false,
+                truncated: 0,
+            });
+        };
+
+        let mut detections =
+            self.postprocessor
+                .detections(&dets.view(), &logits.view(), &transform);
+        // Late results may be incomplete, they are not reused
+        if let (Some(cache), Some(signature)) = (self.detection_cache.as_mut(), signature)
+            && !late
+        {
+            cache.store(signature, detections.clone(), Instant::now());
+        }
+        // After the cache, which keeps what the model saw as masks may change
+        if let Some(masks) = &mut self.scene_masks {
+            masks.apply(&mut detections, original_size(&frame), Instant::now());
+        }
+        // A crowded scene keeps its most confident detections rather than overflowing the buffer
+        let truncated = keep_most_confident(&mut detections, max_detections);
+        if truncated > 0 {
+            log_throttle!(
+                warn,
+                frame_number,
+                truncated,
+                "Too many detections for the detection buffer, keeping the most confident"
+            );
+        }
+
+        // The published detections in the decoded frame's pixels, in the same order
+        // so plates index the published list
+        let needs_frame_boxes = dump_due;
+        #[cfg(feature = "ort-backend")]
+        let needs_frame_boxes = needs_frame_boxes || self.plate_reader.is_some();
+        let frame_detections = needs_frame_boxes
+            .then(|| rescale_detections(&detections, original_size(&frame), (width, height)));
+
+        if let (Some(dump), Some(frame_detections)) = (
+            self.debug_dump.as_ref().filter(|_| dump_due),
+            &frame_detections,
+        ) {
+            dump.write(&DumpRecord {
+                camera_id,
+                frame_number,
+                pixels,
+                width,
+                height,
+                tensor: &preprocessed,
+                transform: &transform.rescaled_to((width, height)),
+                detections: frame_detections,
+                late,
+            });
+        }
+
+        #[cfg(feature = "ort-backend")]
+        let plates = match (self.plate_reader.as_mut(), &frame_detections) {
+            (Some(plate_reader), Some(frame_detections)) => {
+                match plate_reader.read(pixels, width, height, frame_detections) {
+                    Ok(plates) if !plates.is_empty() => {
+                        // Published like the detections, in the camera frame
+                        let plates: Vec<_> = plates
+                            .into_iter()
+                            .map(|plate| plate.rescaled((width, height), original_size(&frame)))
+                            .collect();
+                        Some(build_plates(builder, &plates))
+                    }
+                    Ok(_) => None,
+                    Err(e) => {
+                        log_throttle!(warn, error = %e, "Plate reading failed");
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        let (detections_offset, class_ids) =
+            build_detections(builder, detections, appearance.as_ref());
+
+        // Carry the capture provenance over so detections stay self-describing
+        let provenance = frame
+            .provenance()
+            .map(|p| Provenance::copy_into(builder, &p));
+
+        #[cfg(feature = "ort-backend")]
+        if let Some(plates) = plates {
+            detection_writer.set_next_plates(plates);
+        }
+        if late {
+            detection_writer.set_next_late();
+        }
+        let (original_width, original_height) = original_size(&frame);
+        detection_writer.set_next_frame_size(original_width, original_height);
+        detection_writer.set_next_truncated(truncated as u32);
+        detection_writer.set_next_inference_timing(started_ns, WallClockNs::now());
 
         detection_writer.write_detections(
             camera_id,
             frame_number,
-            timestamp_ns,
+            timestamp,
             detections_offset,
             trace_ctx.as_ref(),
+            provenance,
         )?;
 
-        Ok(count)
+        Ok(FrameOutcome {
+            class_ids,
+            late,
+            cached: false,
+            truncated,
+        })
+    }
+}
+
+/// What a processed frame produced, for metrics
+struct FrameOutcome {
+    /// Class of every written detection
+    class_ids: Vec<u16>,
+    /// The frame overran `frame_deadline`
+    late: bool,
+    /// The detections of an earlier, unchanged frame were republished
+    cached: bool,
+    /// Detections left out to fit the detection buffer
+    truncated: usize,
+}
+
+/// Size of the camera frame before capture downscaled it
+fn original_size(frame: &schema::Frame<'_>) -> (u32, u32) {
+    match (frame.original_width(), frame.original_height()) {
+        (0, _) | (_, 0) => (frame.width(), frame.height()),
+        size => size,
     }
 }
+
+#[cfg(feature = "ort-backend")]
+fn build_plates<'a>(
+    builder: &mut flatbuffers::FlatBufferBuilder<'a>,
+    plates: &[crate::plates::PlateRead],
+) -> flatbuffers::WIPOffset<flatbuffers::Vector<'a, flatbuffers::ForwardsUOffset<schema::Plate<'a>>>>
+{
+    let offsets: Vec<_> = plates
+        .iter()
+        .map(|plate| {
+            let bbox = schema::BoundingBox::new(plate.x1, plate.y1, plate.x2, plate.y2);
+            let text = builder.create_string(&plate.text);
+            schema::Plate::create(
+                builder,
+                &schema::PlateArgs {
+                    detection_index: plate.detection_index as u32,
+                    box_: Some(&bbox),
+                    text: Some(text),
+                    confidence: plate.confidence,
+                },
+            )
+        })
+        .collect();
+    builder.create_vector(&offsets)
+}
@@ -0,0 +1,306 @@
+use clap::Parser;
+use common::classes::{ClassGroups, ClassSet};
+use indicatif::{ProgressBar, ProgressStyle};
+use inference::InferenceBackend;
+use inference::processing::PostProcessor;
+use preprocess::{PreProcessor, Preprocess, PreprocessProfile, PreprocessResult};
+use scoring::{BBox, ImageResult, Scores};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+mod scoring;
+
+/// Detections below this are dropped before scoring, so average precision covers
+/// nearly the whole confidence range
+const SCORING_FLOOR: f32 = 0.01;
+
+/// Compare the accuracy of two models on a labeled image folder.
+///
+/// Typically a reference FP32 ONNX model against its INT8 or FP16 build, after
+/// calibrating with the `calibration` tool. Images go through the same
+/// preprocessing and postprocessing as inference. Every `<name>.jpg` is labeled
+/// by `<name>.txt` in YOLO format: one `class cx cy w h` line per object, with
+/// COCO class ids and coordinates normalized to the image size.
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Directory containing the labeled JPEG images
+    #[arg(long)]
+    images: PathBuf,
+
+    /// Reference model (.onnx, or .engine with the trt-backend feature)
+    #[arg(long)]
+    baseline: PathBuf,
+
+    /// Model compared against the reference
+    #[arg(long)]
+    candidate: PathBuf,
+
+    /// Class, COCO label or group evaluated
+    #[arg(long, default_value = "person")]
+    class: String,
+
+    /// Confidence at which recall and precision are reported (CONFIDENCE_THRESHOLD)
+    #[arg(long, default_value = "0.7")]
+    confidence: f32,
+
+    /// Overlap for a detection to match a labeled object
+    #[arg(long, default_value = "0.5")]
+    iou: f32,
+
+    /// Preprocessing profile of both models (PREPROCESS_PROFILE)
+    #[arg(long, default_value = "rfdetr")]
+    profile: PreprocessProfile,
+
+    /// Run ONNX models on CUDA
+    #[arg(long)]
+    cuda: bool,
+
+    /// Number of images to evaluate (0 = all)
+    #[arg(long, default_value = "0")]
+    count: usize,
+
+    /// Exit with an error when the candidate's AP50 is lower by more than this
+    #[arg(long)]
+    max_ap_drop: Option<f32>,
+}
+
+struct Evaluation {
+    scores: Scores,
+    mean_latency: Duration,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let classes = ClassGroups::from_env()?.resolve(&args.class)?;
+
+    let labeled = labeled_images(&args.images, args.count)?;
+    println!(
+        "Evaluating {} on {} images from {}",
+        args.class,
+        labeled.len(),
+        args.images.display()
+    );
+
+    let baseline = evaluate(&args, &args.baseline, &labeled, classes)?;
+    let candidate = evaluate(&args, &args.candidate, &labeled, classes)?;
+    report(&args, &baseline, &candidate);
+
+    if let Some(max_drop) = args.max_ap_drop {
+        let drop = baseline.scores.average_precision - candidate.scores.average_precision;
+        anyhow::ensure!(
+            drop <= max_drop,
+            "Candidate AP50 is {drop:.4} below the baseline, more than {max_drop}"
+        );
+    }
+    Ok(())
+}
+
+/// JPEG images of `dir` with their label file, up to `count` of them (0 = all)
+fn labeled_images(dir: &Path, count: usize) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+    let pattern = dir
+        .join("*.jpg")
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("Invalid path encoding"))?
+        .to_owned();
+
+    let mut labeled = Vec::new();
+    for image in glob::glob(&pattern)?.filter_map(|p| p.ok()) {
+        let labels = image.with_extension("txt");
+        if labels.exists() {
+            labeled.push((image, labels));
+        } else {
+            eprintln!("Skipping {}: no {}", image.display(), labels.display());
+        }
+    }
+    if labeled.is_empty() {
+        anyhow::bail!("No labeled JPEG images found in {}", dir.display());
+    }
+    if count > 0 {
+        labeled.truncate(count);
+    }
+    Ok(labeled)
+}
+
+/// Ground truth boxes of `classes` in a YOLO label file, in image pixels
+fn read_labels(
+    path: &Path,
+    classes: ClassSet,
+    width: u32,
+    height: u32,
+) -> anyhow::Result<Vec<BBox>> {
+    let (width, height) = (width as f32, height as f32);
+    let mut truths = Vec::new();
+    for (line_number, line) in std::fs::read_to_string(path)?.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            continue;
+        }
+        let class = fields[0].parse::<u16>().ok();
+        let coords: Option<Vec<f32>> = fields[1..].iter().map(|v| v.parse().ok()).collect();
+        let (Some(class), Some(&[cx, cy, w, h])) = (class, coords.as_deref()) else {
+            anyhow::bail!(
+                "{}:{}: expected `class cx cy w h`",
+                path.display(),
+                line_number + 1
+            );
+        };
+        if classes.contains(class) {
+            truths.push([
+                (cx - w / 2.0) * width,
+                (cy - h / 2.0) * height,
+                (cx + w / 2.0) * width,
+                (cy + h / 2.0) * height,
+            ]);
+        }
+    }
+    Ok(truths)
+}
+
+fn evaluate(
+    args: &Args,
+    model: &Path,
+    labeled: &[(PathBuf, PathBuf)],
+    classes: ClassSet,
+) -> anyhow::Result<Evaluation> {
+    println!("\nLoading {}", model.display());
+    let mut backend = load_backend(model, args.cuda)?;
+    let mut preprocessor = PreProcessor::new(args.profile.input_size).with_profile(args.profile);
+    let postprocessor = PostProcessor::new(SCORING_FLOOR);
+
+    let pb = ProgressBar::new(labeled.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} ({eta})")?
+            .progress_chars("#>-"),
+    );
+
+    let mut images = Vec::with_capacity(labeled.len());
+    let mut inference_time = Duration::ZERO;
+    for (image_path, labels_path) in labeled {
+        let img = image::open(image_path)?.to_rgb8();
+        let (width, height) = img.dimensions();
+        let PreprocessResult { data, transform } = preprocessor.preprocess(&img, width, height)?;
+
+        let start = Instant::now();
+        let output = backend.infer_preprocessed(&data)?;
+        inference_time += start.elapsed();
+
+        let detections = postprocessor
+            .detections(&output.dets.view(), &output.logits.view(), &transform)
+            .into_iter()
+            .filter(|det| classes.contains(det.class_id))
+            .map(|det| (det.confidence, [det.x1, det.y1, det.x2, det.y2]))
+            .collect();
+        images.push(ImageResult {
+            detections,
+            truths: read_labels(labels_path, classes, width, height)?,
+        });
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+
+    Ok(Evaluation {
+        scores: scoring::score(&images, args.iou, args.confidence),
+        mean_latency: inference_time / labeled.len() as u32,
+    })
+}
+
+#[cfg_attr(not(feature = "ort-backend"), allow(unused_variables))]
+fn load_backend(model: &Path, cuda: bool) -> anyhow::Result<Box<dyn InferenceBackend>> {
+    let path = model
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("Invalid path encoding"))?;
+    match model.extension().and_then(|ext| ext.to_str()) {
+        #[cfg(feature = "ort-backend")]
+        Some("onnx") => {
+            let provider = if cuda {
+                inference::ExecutionProvider::Cuda
+            } else {
+                inference::ExecutionProvider::Cpu
+            };
+            Ok(Box::new(
+                inference::backend::ort::OrtBackend::load_model_with_provider(
+                    path,
+                    provider,
+                    &inference::OrtSessionConfig::from_env()?,
+                )?,
+            ))
+        }
+        #[cfg(feature = "trt-backend")]
+        Some("engine") => Ok(Box::new(inference::backend::trt::TrtBackend::load_model(
+            path,
+        )?)),
+        _ => anyhow::bail!(
+            "No backend for {}: use .onnx (ort-backend) or .engine (trt-backend)",
+            model.display()
+        ),
+    }
+}
+
+fn report(args: &Args, baseline: &Evaluation, candidate: &Evaluation) {
+    let rows = [
+        (
+            "AP50".to_string(),
+            baseline.scores.average_precision,
+            candidate.scores.average_precision,
+        ),
+        (
+            format!("Recall @ {}", args.confidence),
+            baseline.scores.recall,
+            candidate.scores.recall,
+        ),
+        (
+            format!("Precision @ {}", args.confidence),
+            baseline.scores.precision,
+            candidate.scores.precision,
+        ),
+    ];
+
+    println!(
+        "\n{} labeled {} objects, IoU >= {}\n",
+        baseline.scores.truths, args.class, args.iou
+    );
+    println!(
+        "{:<18} {:>10} {:>10} {:>10}",
+        "", "baseline", "candidate", "delta"
+    );
+    for (name, base, cand) in rows {
+        println!(
+            "{:<18} {:>10.4} {:>10.4} {:>+10.4}",
+            name,
+            base,
+            cand,
+            cand - base
+        );
+    }
+    println!(
+        "{:<18} {:>10.1} {:>10.1} {:>+10.1}",
+        "Latency (ms)",
+        baseline.mean_latency.as_secs_f64() * 1000.0,
+        candidate.mean_latency.as_secs_f64() * 1000.0,
+        (candidate.mean_latency.as_secs_f64() - baseline.mean_latency.as_secs_f64()) * 1000.0
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_yolo_labels() {
+        let dir = std::env::temp_dir().join(format!("model_eval_labels_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("street.txt");
+        std::fs::write(&path, "0 0.5 0.5 0.25 0.5\n2 0.1 0.1 0.1 0.1\n\n").unwrap();
+
+        let persons = ClassSet::single(0);
+        let truths = read_labels(&path, persons, 100, 200).unwrap();
+        assert_eq!(truths, vec![[37.5, 50.0, 62.5, 150.0]]);
+
+        std::fs::write(&path, "0 0.5 0.5 0.2\n").unwrap();
+        assert!(read_labels(&path, persons, 100, 200).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
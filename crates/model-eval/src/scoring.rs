@@ -0,0 +1,162 @@
+//! Detection accuracy against ground truth boxes
+
+/// `x1, y1, x2, y2` in image pixels
+pub type BBox = [f32; 4];
+
+/// Detections of the evaluated class on one image, with its ground truth
+#[derive(Debug, Default)]
+pub struct ImageResult {
+    /// `(confidence, box)`
+    pub detections: Vec<(f32, BBox)>,
+    pub truths: Vec<BBox>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scores {
+    /// Average precision over all confidences, all-point interpolated
+    pub average_precision: f32,
+    /// At the operating confidence
+    pub recall: f32,
+    pub precision: f32,
+    pub truths: usize,
+}
+
+/// Score `images`, a detection matching an unmatched truth by `min_iou` being a
+/// true positive, with recall and precision taken at `operating_confidence`
+pub fn score(images: &[ImageResult], min_iou: f32, operating_confidence: f32) -> Scores {
+    let truths: usize = images.iter().map(|image| image.truths.len()).sum();
+
+    // (confidence, true positive) of every detection, matched per image from the
+    // most confident down
+    let mut ranked: Vec<(f32, bool)> = Vec::new();
+    for image in images {
+        let mut detections = image.detections.clone();
+        detections.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut matched = vec![false; image.truths.len()];
+        for (confidence, det) in detections {
+            let best = image
+                .truths
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !matched[*i])
+                .map(|(i, truth)| (i, iou(&det, truth)))
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            let hit = match best {
+                Some((i, overlap)) if overlap >= min_iou => {
+                    matched[i] = true;
+                    true
+                }
+                _ => false,
+            };
+            ranked.push((confidence, hit));
+        }
+    }
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut true_positives = 0usize;
+    let mut curve: Vec<(f32, f32)> = Vec::with_capacity(ranked.len());
+    for (rank, (_, hit)) in ranked.iter().enumerate() {
+        true_positives += usize::from(*hit);
+        curve.push((
+            ratio(true_positives, truths),
+            ratio(true_positives, rank + 1),
+        ));
+    }
+
+    let operating: Vec<bool> = ranked
+        .iter()
+        .take_while(|(confidence, _)| *confidence >= operating_confidence)
+        .map(|(_, hit)| *hit)
+        .collect();
+    let operating_hits = operating.iter().filter(|hit| **hit).count();
+
+    Scores {
+        average_precision: average_precision(&curve),
+        recall: ratio(operating_hits, truths),
+        precision: ratio(operating_hits, operating.len()),
+        truths,
+    }
+}
+
+/// Area under the precision envelope of `(recall, precision)` points by rank
+fn average_precision(curve: &[(f32, f32)]) -> f32 {
+    let mut area = 0.0;
+    let mut previous_recall = 0.0;
+    for (i, &(recall, _)) in curve.iter().enumerate() {
+        if recall > previous_recall {
+            let envelope = curve[i..].iter().map(|p| p.1).fold(0.0, f32::max);
+            area += (recall - previous_recall) * envelope;
+            previous_recall = recall;
+        }
+    }
+    area
+}
+
+fn ratio(count: usize, total: usize) -> f32 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f32 / total as f32
+    }
+}
+
+pub fn iou(a: &BBox, b: &BBox) -> f32 {
+    let width = (a[2].min(b[2]) - a[0].max(b[0])).max(0.0);
+    let height = (a[3].min(b[3]) - a[1].max(b[1])).max(0.0);
+    let intersection = width * height;
+    let union = (a[2] - a[0]) * (a[3] - a[1]) + (b[2] - b[0]) * (b[3] - b[1]) - intersection;
+    if union > 0.0 {
+        intersection / union
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERSON: BBox = [10.0, 10.0, 50.0, 110.0];
+    const OTHER: BBox = [200.0, 10.0, 240.0, 110.0];
+
+    #[test]
+    fn test_perfect_detections_score_one() {
+        let images = [ImageResult {
+            detections: vec![(0.9, PERSON), (0.8, OTHER)],
+            truths: vec![PERSON, OTHER],
+        }];
+        let scores = score(&images, 0.5, 0.5);
+        assert_eq!(scores.average_precision, 1.0);
+        assert_eq!((scores.recall, scores.precision), (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_misses_and_false_positives() {
+        let images = [
+            ImageResult {
+                // The confident detection is a false positive, a duplicate does not
+                // match twice
+                detections: vec![(0.95, OTHER), (0.9, PERSON), (0.85, PERSON)],
+                truths: vec![PERSON],
+            },
+            ImageResult {
+                // Found only below the operating confidence
+                detections: vec![(0.3, PERSON)],
+                truths: vec![PERSON],
+            },
+        ];
+        let scores = score(&images, 0.5, 0.5);
+        assert_eq!(scores.truths, 2);
+        assert_eq!(scores.recall, 0.5);
+        assert!((scores.precision - 1.0 / 3.0).abs() < 1e-6);
+        // Each truth is found at a precision of 1/2
+        assert!((scores.average_precision - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_empty_set_scores_zero() {
+        let scores = score(&[ImageResult::default()], 0.5, 0.5);
+        assert_eq!(scores.average_precision, 0.0);
+        assert_eq!(scores.recall, 0.0);
+    }
+}
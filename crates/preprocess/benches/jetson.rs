@@ -0,0 +1,58 @@
+//! NVMM zero-copy path against the generic preprocessing paths (Jetson only)
+//!
+//! Per frame, the generic GPU path uploads the RGB frame read from the frame buffer
+//! and letterboxes it in CUDA, while the NVMM path has capture copy the raw YUYV
+//! frame into a surface and inference convert and letterbox it on the VIC. Capture's
+//! YUYV to RGB decode, which the generic path also pays, is not included.
+//!
+//! Run on the device with `cargo bench -p preprocess --features jetson --bench jetson`.
+
+use bridge::nvmm::{NvmmFormat, NvmmSurface};
+use bridge::{SurfaceExporter, paths};
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use preprocess::{ChannelOrder, CpuPreProcessor, GpuPreProcessor, JetsonPreProcessor, Preprocess};
+
+const INPUT_SIZE: (u32, u32) = (512, 512);
+
+fn benchmark_jetson_vs_generic(c: &mut Criterion) {
+    let mut group = c.benchmark_group("jetson_comparison");
+
+    for (width, height) in [(1280u32, 720u32), (1920, 1080)] {
+        let label = format!("{}x{}", width, height);
+        let rgb = vec![128u8; (width * height * 3) as usize];
+        let yuyv = vec![128u8; (width * height * 2) as usize];
+
+        let mut cpu = CpuPreProcessor::new(INPUT_SIZE);
+        group.bench_function(format!("cpu_{}", label), |b| {
+            b.iter(|| cpu.preprocess(black_box(&rgb), width, height).unwrap());
+        });
+
+        let mut gpu = GpuPreProcessor::new(INPUT_SIZE, (width, height)).unwrap();
+        group.bench_function(format!("gpu_{}", label), |b| {
+            b.iter(|| gpu.preprocess(black_box(&rgb), width, height).unwrap());
+        });
+
+        // Stand in for capture: one surface served where inference looks for it
+        let mut surface = NvmmSurface::allocate(width, height, NvmmFormat::Yuyv).unwrap();
+        let _exporter = SurfaceExporter::serve(
+            paths::NVMM_SURFACES_SOCKET_PATH,
+            vec![surface.export_fd().unwrap()],
+        )
+        .unwrap();
+        let mut jetson =
+            JetsonPreProcessor::new(INPUT_SIZE, (width, height), ChannelOrder::Rgb).unwrap();
+        group.bench_function(format!("nvmm_{}", label), |b| {
+            b.iter(|| {
+                surface
+                    .write_rows(black_box(&yuyv), width as usize * 2, width as usize * 2)
+                    .unwrap();
+                jetson.preprocess_surface(0, width, height).unwrap()
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_jetson_vs_generic);
+criterion_main!(benches);
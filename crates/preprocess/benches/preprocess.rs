@@ -3,7 +3,7 @@ use flatbuffers::FlatBufferBuilder;
 use preprocess::CpuPreProcessor;
 
 #[cfg(feature = "cuda")]
-use preprocess::GpuPreProcessor;
+use preprocess::{ChannelOrder, GpuPreProcessor};
 
 /// Helper function to create a FlatBuffers Frame for benchmarking
 fn create_test_frame(width: u32, height: u32) -> Vec<u8> {
@@ -18,12 +18,21 @@ fn create_test_frame(width: u32, height: u32) -> Vec<u8> {
         &schema::FrameArgs {
             frame_number: 1,
             timestamp_ns: 0,
+            timestamp_clock: schema::ClockDomain::WallClock,
+            signature: None,
+            format_generation: 0,
             camera_id: 0,
             width,
             height,
             channels: 3,
             pixels: Some(pixel_vector),
+            original_width: 0,
+            original_height: 0,
+            nvmm_surface: -1,
             trace: None,
+            provenance: None,
+            encoding: schema::FrameEncoding::Rgb,
+            burst: None,
         },
     );
 
@@ -76,6 +85,85 @@ fn benchmark_cpu_preprocess(c: &mut Criterion) {
     group.finish();
 }
 
+/// Frames at or proportional to the model input take the skip-resize fast path
+fn benchmark_cpu_fast_path(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cpu_fast_path");
+
+    let input_size = (512, 512);
+    let mut preprocessor = CpuPreProcessor::new(input_size);
+
+    // 512x512 skips resize, 1024x1024 skips letterbox, 640x480 is the full path for reference
+    let cases = [
+        ("passthrough", 512, 512),
+        ("resize_only", 1024, 1024),
+        ("letterbox", 640, 480),
+    ];
+
+    for (name, width, height) in cases.iter() {
+        let pixels = create_test_pixels(*width, *height);
+
+        group.bench_with_input(
+            BenchmarkId::new(*name, format!("{}x{}", width, height)),
+            &pixels,
+            |b, pixels| {
+                b.iter(|| {
+                    preprocessor
+                        .preprocess_from_u8_slice(
+                            black_box(pixels),
+                            black_box(*width),
+                            black_box(*height),
+                        )
+                        .unwrap()
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+/// Pooled output tensors vs detaching each one, which allocates a fresh tensor per frame
+fn benchmark_cpu_output_pool(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cpu_output_pool");
+
+    let input_size = (640, 640);
+    let (width, height) = input_size;
+    let pixels = create_test_pixels(width, height);
+
+    for pooled in [true, false] {
+        let name = if pooled { "pooled" } else { "allocating" };
+        let mut preprocessor = CpuPreProcessor::new(input_size);
+
+        group.bench_function(
+            BenchmarkId::new(name, format!("{}x{}", width, height)),
+            |b| {
+                b.iter(|| {
+                    let (tensor, ..) = preprocessor
+                        .preprocess_from_u8_slice(
+                            black_box(&pixels),
+                            black_box(width),
+                            black_box(height),
+                        )
+                        .unwrap();
+                    if pooled {
+                        black_box(&tensor);
+                    } else {
+                        black_box(tensor.into_array());
+                    }
+                });
+            },
+        );
+
+        eprintln!(
+            "cpu_output_pool/{}: {} tensor allocations",
+            name,
+            preprocessor.pool().allocations()
+        );
+    }
+
+    group.finish();
+}
+
 fn benchmark_cpu_preprocess_frame(c: &mut Criterion) {
     let mut group = c.benchmark_group("cpu_preprocess_frame");
 
@@ -133,7 +221,7 @@ fn benchmark_gpu_vs_cpu(c: &mut Criterion) {
         group.bench_function("gpu_1080p", |b| {
             b.iter(|| {
                 gpu_preprocessor
-                    .run_kernel(black_box(width), black_box(height))
+                    .run_kernel(black_box(width), black_box(height), ChannelOrder::Rgb)
                     .unwrap()
             });
         });
@@ -174,7 +262,50 @@ fn benchmark_gpu_preprocess(c: &mut Criterion) {
             |b, &(w, h)| {
                 b.iter(|| {
                     gpu_preprocessor
-                        .run_kernel(black_box(w), black_box(h))
+                        .run_kernel(black_box(w), black_box(h), ChannelOrder::Rgb)
+                        .unwrap()
+                });
+            },
+        );
+    }
+
+    group.finish();
+}
+
+#[cfg(feature = "cuda")]
+fn benchmark_gpu_fast_path(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gpu_fast_path");
+
+    let input_size = (512, 512);
+    let gpu_result = GpuPreProcessor::new(input_size, (1024, 1024));
+    if let Err(e) = &gpu_result {
+        eprintln!("Skipping GPU fast path benchmark: {}", e);
+        group.finish();
+        return;
+    }
+    let mut gpu_preprocessor = gpu_result.unwrap();
+
+    let cases = [
+        ("passthrough", 512, 512),
+        ("resize_only", 1024, 1024),
+        ("letterbox", 640, 480),
+    ];
+
+    for (name, width, height) in cases.iter() {
+        let pixels = create_test_pixels(*width, *height);
+
+        // Pre-upload data to device (not timed)
+        gpu_preprocessor
+            .upload_to_device(&pixels, *width, *height)
+            .unwrap();
+
+        group.bench_with_input(
+            BenchmarkId::new(*name, format!("{}x{}", width, height)),
+            &(*width, *height),
+            |b, &(w, h)| {
+                b.iter(|| {
+                    gpu_preprocessor
+                        .run_kernel(black_box(w), black_box(h), ChannelOrder::Rgb)
                         .unwrap()
                 });
             },
@@ -189,7 +320,10 @@ criterion_group!(
     benches,
     benchmark_cpu_preprocess,
     benchmark_cpu_preprocess_frame,
+    benchmark_cpu_fast_path,
+    benchmark_cpu_output_pool,
     benchmark_gpu_preprocess,
+    benchmark_gpu_fast_path,
     benchmark_gpu_vs_cpu
 );
 
@@ -197,7 +331,9 @@ criterion_group!(
 criterion_group!(
     benches,
     benchmark_cpu_preprocess,
-    benchmark_cpu_preprocess_frame
+    benchmark_cpu_preprocess_frame,
+    benchmark_cpu_fast_path,
+    benchmark_cpu_output_pool
 );
 
 criterion_main!(benches);
@@ -7,7 +7,7 @@
  * 3. ImageNet normalization
  * 4. HWC -> CHW transpose
  *
- * Input:  RGB u8 image in HWC format [H, W, 3]
+ * Input:  RGB or BGR u8 image in HWC format [H, W, 3], BGR swizzled on read
  * Output: Normalized f32 image in CHW format [3, target_H, target_W]
  */
 
@@ -21,7 +21,7 @@ __device__ constexpr float STD_B = 0.225f;
 __device__ constexpr int LETTERBOX_GRAY = 114;
 
 extern "C" __global__ void preprocess_kernel(
-    const unsigned char* __restrict__ input,  // Input RGB image [src_h, src_w, 3]
+    const unsigned char* __restrict__ input,  // Input RGB/BGR image [src_h, src_w, 3]
     float* __restrict__ output,               // Output CHW image [3, dst_h, dst_w]
     int src_w,                                // Source image width
     int src_h,                                // Source image height
@@ -31,7 +31,9 @@ extern "C" __global__ void preprocess_kernel(
     int resized_h,                            // Height after resize (before padding)
     int offset_x,                             // X offset for letterbox centering
     int offset_y,                             // Y offset for letterbox centering
-    float scale                               // Scale factor applied during resize
+    float scale,                              // Scale factor applied during resize
+    int bgr,                                  // 1 if the input is BGR rather than RGB
+    int bgr_output                            // 1 if the model expects BGR planes
 ) {
     // Each thread handles one output pixel
     int idx = blockIdx.x * blockDim.x + threadIdx.x;
@@ -76,6 +78,10 @@ extern "C" __global__ void preprocess_kernel(
         float dx = src_x - (float)x0;
         float dy = src_y - (float)y0;
 
+        // Byte offsets of red and blue within a pixel
+        int ri = bgr ? 2 : 0;
+        int bi = bgr ? 0 : 2;
+
         // Sample 4 corners (input is HWC format)
         int idx00 = (y0 * src_w + x0) * 3;
         int idx01 = (y0 * src_w + x1) * 3;
@@ -83,20 +89,20 @@ extern "C" __global__ void preprocess_kernel(
         int idx11 = (y1 * src_w + x1) * 3;
 
         // Bilinear interpolation for each channel
-        float r00 = input[idx00 + 0];
-        float r01 = input[idx01 + 0];
-        float r10 = input[idx10 + 0];
-        float r11 = input[idx11 + 0];
+        float r00 = input[idx00 + ri];
+        float r01 = input[idx01 + ri];
+        float r10 = input[idx10 + ri];
+        float r11 = input[idx11 + ri];
 
         float g00 = input[idx00 + 1];
         float g01 = input[idx01 + 1];
         float g10 = input[idx10 + 1];
         float g11 = input[idx11 + 1];
 
-        float b00 = input[idx00 + 2];
-        float b01 = input[idx01 + 2];
-        float b10 = input[idx10 + 2];
-        float b11 = input[idx11 + 2];
+        float b00 = input[idx00 + bi];
+        float b01 = input[idx01 + bi];
+        float b10 = input[idx10 + bi];
+        float b11 = input[idx11 + bi];
 
         // Interpolate
         float w00 = (1.0f - dx) * (1.0f - dy);
@@ -115,10 +121,101 @@ extern "C" __global__ void preprocess_kernel(
     b = (b - MEAN_B) / STD_B;
 
     // Write to output in CHW format
-    // Channel 0 (R): offset 0
+    // Channel 0 (R, or B for BGR models): offset 0
     // Channel 1 (G): offset total_pixels
-    // Channel 2 (B): offset 2 * total_pixels
-    output[idx] = r;
+    // Channel 2 (B, or R for BGR models): offset 2 * total_pixels
+    output[idx + (bgr_output ? 2 * total_pixels : 0)] = r;
     output[idx + total_pixels] = g;
-    output[idx + 2 * total_pixels] = b;
+    output[idx + (bgr_output ? 0 : 2 * total_pixels)] = b;
+}
+
+/**
+ * Fast path for frames already at the model input size.
+ *
+ * Skips resampling and letterboxing: only ImageNet normalization, the
+ * HWC -> CHW transpose and, for BGR input or models, the channel swap are
+ * applied.
+ */
+extern "C" __global__ void normalize_kernel(
+    const unsigned char* __restrict__ input,  // Input RGB/BGR image [dst_h, dst_w, 3]
+    float* __restrict__ output,               // Output CHW image [3, dst_h, dst_w]
+    int dst_w,                                // Image and output width
+    int dst_h,                                // Image and output height
+    int bgr,                                  // 1 if the input is BGR rather than RGB
+    int bgr_output                            // 1 if the model expects BGR planes
+) {
+    int idx = blockIdx.x * blockDim.x + threadIdx.x;
+    int total_pixels = dst_w * dst_h;
+
+    if (idx >= total_pixels) return;
+
+    int src = idx * 3;
+    float r = input[src + (bgr ? 2 : 0)] / 255.0f;
+    float g = input[src + 1] / 255.0f;
+    float b = input[src + (bgr ? 0 : 2)] / 255.0f;
+
+    output[idx + (bgr_output ? 2 * total_pixels : 0)] = (r - MEAN_R) / STD_R;
+    output[idx + total_pixels] = (g - MEAN_G) / STD_G;
+    output[idx + (bgr_output ? 0 : 2 * total_pixels)] = (b - MEAN_B) / STD_B;
+}
+
+/**
+ * Normalize a letterboxed pitch-linear RGBA surface (Jetson NVMM path).
+ *
+ * The VIC has already converted, resized and padded the frame to the model
+ * input size; only ImageNet normalization and the HWC -> CHW transpose remain.
+ */
+extern "C" __global__ void normalize_rgba_kernel(
+    const unsigned char* __restrict__ input,  // Input RGBA surface [dst_h, pitch]
+    int pitch,                                // Bytes per input row
+    float* __restrict__ output,               // Output CHW image [3, dst_h, dst_w]
+    int dst_w,                                // Image and output width
+    int dst_h,                                // Image and output height
+    int bgr_output                            // 1 if the model expects BGR planes
+) {
+    int idx = blockIdx.x * blockDim.x + threadIdx.x;
+    int total_pixels = dst_w * dst_h;
+
+    if (idx >= total_pixels) return;
+
+    int y = idx / dst_w;
+    int x = idx % dst_w;
+    const unsigned char* px = input + y * pitch + x * 4;
+
+    output[idx + (bgr_output ? 2 * total_pixels : 0)] = (px[0] / 255.0f - MEAN_R) / STD_R;
+    output[idx + total_pixels] = (px[1] / 255.0f - MEAN_G) / STD_G;
+    output[idx + (bgr_output ? 0 : 2 * total_pixels)] = (px[2] / 255.0f - MEAN_B) / STD_B;
+}
+
+/**
+ * Motion-adaptive temporal denoise of an RGB frame, in place.
+ *
+ * Each channel value is blended with the previous denoised frame kept in
+ * `history`, unless it moved by more than `motion_threshold`. The result is
+ * written to both `input` (consumed by the resize kernels) and `history`.
+ * Matches `preprocess::denoise::blend`.
+ */
+extern "C" __global__ void temporal_denoise_kernel(
+    unsigned char* __restrict__ input,        // RGB frame [h, w, 3], denoised in place
+    unsigned char* __restrict__ history,      // Previous denoised frame, same layout
+    int len,                                  // Bytes in the frame (w * h * 3)
+    float strength,                           // Weight of the previous frame, 0-1
+    int motion_threshold,                     // Changes above this are kept as is
+    int primed                                // 0 if history does not hold a frame yet
+) {
+    int idx = blockIdx.x * blockDim.x + threadIdx.x;
+
+    if (idx >= len) return;
+
+    int current = input[idx];
+    int out = current;
+    if (primed) {
+        int previous = history[idx];
+        if (abs(previous - current) <= motion_threshold) {
+            out = (int)roundf(previous * strength + current * (1.0f - strength));
+        }
+    }
+
+    history[idx] = (unsigned char)out;
+    input[idx] = (unsigned char)out;
 }
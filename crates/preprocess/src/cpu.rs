@@ -1,4 +1,8 @@
 use crate::config::DEFAULT_INPUT_SIZE;
+use crate::denoise::{DenoiseConfig, TemporalDenoiser};
+use crate::letterbox::{Letterbox, LetterboxTransform, ResizePath};
+use crate::pool::{PooledTensor, TensorPool};
+use crate::profile::{ChannelOrder, PreprocessProfile, ResizeMode};
 use crate::{Preprocess, PreprocessOutput, PreprocessResult};
 use common::span;
 use fast_image_resize::{
@@ -8,13 +12,20 @@ use fast_image_resize::{
 use ndarray::{Array, IxDyn};
 use std::default::Default;
 
+#[cfg(test)]
+use crate::profile::{IMAGENET_MEAN, IMAGENET_STD};
+
 const LETTERBOX_COLOR: u8 = 114;
-const IMAGENET_MEAN: [f32; 3] = [0.485, 0.456, 0.406];
-const IMAGENET_STD: [f32; 3] = [0.229, 0.224, 0.225];
 
 pub struct CpuPreProcessor {
     pub input_size: (u32, u32),
     letterboxed_buffer: Vec<u8>,
+    /// Storage for output tensors, reused once the previous tensor is dropped
+    pool: TensorPool,
+    /// Low-light denoise applied before resizing, off when unset
+    denoiser: Option<TemporalDenoiser>,
+    /// Normalization, resize mode and channel order; the input size is `input_size`
+    profile: PreprocessProfile,
 }
 
 impl CpuPreProcessor {
@@ -22,15 +33,35 @@ impl CpuPreProcessor {
         Self {
             input_size,
             letterboxed_buffer: vec![LETTERBOX_COLOR; (input_size.0 * input_size.1 * 3) as usize],
+            pool: TensorPool::new(),
+            denoiser: None,
+            profile: PreprocessProfile::default(),
         }
     }
 
+    /// Prepare inputs as `profile` does, keeping the input size given to `new`
+    pub fn with_profile(mut self, profile: PreprocessProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// Denoise dark frames before resizing them
+    pub fn with_denoise(mut self, config: DenoiseConfig) -> Self {
+        self.denoiser = Some(TemporalDenoiser::new(config));
+        self
+    }
+
+    /// Pool backing the returned tensors, e.g. to check they are being reused
+    pub fn pool(&self) -> &TensorPool {
+        &self.pool
+    }
+
     pub fn preprocess_frame(
         &mut self,
         pixels: flatbuffers::Vector<u8>,
         width: u32,
         height: u32,
-    ) -> anyhow::Result<(Array<f32, IxDyn>, f32, f32, f32)> {
+    ) -> anyhow::Result<(PooledTensor, f32, f32, f32)> {
         let _s = span!("preprocess_frame");
 
         tracing::trace!(
@@ -49,24 +80,80 @@ impl CpuPreProcessor {
             );
         }
 
-        let (scale, offset_x, offset_y, resized) =
-            self.resize_and_letterbox(pixels.bytes(), width, height)?;
+        self.resize_and_normalize(pixels.bytes(), width, height)
+    }
 
-        let input = Self::normalize(&resized)?;
+    pub fn preprocess_from_u8_slice(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<(PooledTensor, f32, f32, f32)> {
+        self.resize_and_normalize(pixels, width, height)
+    }
 
-        Ok((input, scale, offset_x, offset_y))
+    fn resize_and_normalize(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<(PooledTensor, f32, f32, f32)> {
+        // Taken out for the call so the denoised frame can borrow from it
+        let mut denoiser = self.denoiser.take();
+        let pixels = match denoiser.as_mut() {
+            Some(denoiser) => denoiser.apply(pixels),
+            None => pixels,
+        };
+        let result = self.letterbox_and_normalize(pixels, width, height);
+        self.denoiser = denoiser;
+        result
     }
 
-    pub fn preprocess_from_u8_slice(
+    fn letterbox_and_normalize(
         &mut self,
         pixels: &[u8],
         width: u32,
         height: u32,
-    ) -> anyhow::Result<(Array<f32, IxDyn>, f32, f32, f32)> {
-        let (scale, offset_x, offset_y, resized) =
-            self.resize_and_letterbox(pixels, width, height)?;
-        let input = Self::normalize(&resized)?;
-        Ok((input, scale, offset_x, offset_y))
+    ) -> anyhow::Result<(PooledTensor, f32, f32, f32)> {
+        let letterbox = Letterbox::new(width, height, self.input_size);
+        let path = letterbox.path(width, height, self.input_size);
+        let pool = self.pool.clone();
+        let profile = self.profile;
+
+        // Frames already at the model input size skip resize and letterbox entirely
+        if path == ResizePath::Passthrough {
+            let input = Self::normalize(&pool, &profile, pixels, width, height)?;
+            return Ok((input, 1.0, 0.0, 0.0));
+        }
+
+        if profile.resize == ResizeMode::CenterCrop {
+            let (cropped, scale, offset_x, offset_y) =
+                self.resize_and_crop(pixels, width, height)?;
+            let input = Self::normalize(
+                &pool,
+                &profile,
+                cropped.buffer(),
+                cropped.width(),
+                cropped.height(),
+            )?;
+            return Ok((input, scale, offset_x, offset_y));
+        }
+
+        let resized = self.resize_and_letterbox(pixels, width, height, &letterbox, path)?;
+        let input = Self::normalize(
+            &pool,
+            &profile,
+            resized.buffer(),
+            resized.width(),
+            resized.height(),
+        )?;
+
+        Ok((
+            input,
+            letterbox.scale,
+            letterbox.offset_x as f32,
+            letterbox.offset_y as f32,
+        ))
     }
 
     fn resize_and_letterbox(
@@ -74,26 +161,37 @@ impl CpuPreProcessor {
         pixels: &[u8],
         width: u32,
         height: u32,
-    ) -> anyhow::Result<(f32, f32, f32, Image<'_>)> {
+        letterbox: &Letterbox,
+        path: ResizePath,
+    ) -> anyhow::Result<Image<'_>> {
         let _s = span!("resize_and_letterbox");
 
-        let scale =
-            (self.input_size.0 as f32 / width as f32).min(self.input_size.1 as f32 / height as f32);
-        let new_width = (width as f32 * scale) as u32;
-        let new_height = (height as f32 * scale) as u32;
-
-        let offset_x = (self.input_size.0 - new_width) / 2;
-        let offset_y = (self.input_size.1 - new_height) / 2;
+        let Letterbox {
+            new_width,
+            new_height,
+            offset_x,
+            offset_y,
+            ..
+        } = *letterbox;
 
         let src = ImageRef::new(width, height, pixels, PixelType::U8x3)?;
+        let options = ResizeOptions::new().resize_alg(ResizeAlg::Convolution(FilterType::Bilinear));
+
+        // Resized frame covers the whole input: write it in place, no padding pass
+        if path == ResizePath::ResizeOnly {
+            let mut final_img = Image::from_slice_u8(
+                self.input_size.0,
+                self.input_size.1,
+                &mut self.letterboxed_buffer,
+                PixelType::U8x3,
+            )?;
+            Resizer::new().resize(&src, &mut final_img, &options)?;
+            return Ok(final_img);
+        }
 
         let mut resized = Image::new(new_width, new_height, PixelType::U8x3);
 
-        Resizer::new().resize(
-            &src,
-            &mut resized,
-            &ResizeOptions::new().resize_alg(ResizeAlg::Convolution(FilterType::Bilinear)),
-        )?;
+        Resizer::new().resize(&src, &mut resized, &options)?;
 
         self.letterboxed_buffer.fill(LETTERBOX_COLOR);
 
@@ -115,33 +213,89 @@ impl CpuPreProcessor {
             PixelType::U8x3,
         )?;
 
-        Ok((scale, offset_x as f32, offset_y as f32, final_img))
+        Ok(final_img)
     }
 
-    fn normalize(image: &Image) -> anyhow::Result<Array<f32, IxDyn>> {
+    /// Scale the frame to cover the input and crop the overflow evenly, returning
+    /// the scale and the offsets of the frame in the input, negative
+    fn resize_and_crop(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<(Image<'_>, f32, f32, f32)> {
+        let _s = span!("resize_and_crop");
+
+        let (input_width, input_height) = self.input_size;
+        let scale = (input_width as f32 / width as f32).max(input_height as f32 / height as f32);
+        let crop_width = input_width as f32 / scale;
+        let crop_height = input_height as f32 / scale;
+        let left = (width as f32 - crop_width) / 2.0;
+        let top = (height as f32 - crop_height) / 2.0;
+
+        let src = ImageRef::new(width, height, pixels, PixelType::U8x3)?;
+        let options = ResizeOptions::new()
+            .resize_alg(ResizeAlg::Convolution(FilterType::Bilinear))
+            .crop(
+                left as f64,
+                top as f64,
+                crop_width as f64,
+                crop_height as f64,
+            );
+        let mut final_img = Image::from_slice_u8(
+            input_width,
+            input_height,
+            &mut self.letterboxed_buffer,
+            PixelType::U8x3,
+        )?;
+        Resizer::new().resize(&src, &mut final_img, &options)?;
+
+        Ok((final_img, scale, -left * scale, -top * scale))
+    }
+
+    fn normalize(
+        pool: &TensorPool,
+        profile: &PreprocessProfile,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<PooledTensor> {
         let _s = span!("normalize");
 
-        let width = image.width() as usize;
-        let height = image.height() as usize;
+        let width = width as usize;
+        let height = height as usize;
         let spatial = width * height;
 
-        let mut output = vec![0.0f32; 3 * spatial];
-        let buf = image.buffer();
+        if buf.len() != spatial * 3 {
+            anyhow::bail!(
+                "Buffer size mismatch: expected {}, got {} bytes",
+                spatial * 3,
+                buf.len()
+            );
+        }
+
+        let mut output = pool.take(3 * spatial);
+        let PreprocessProfile { mean, std, .. } = *profile;
+        // Output plane of the red and blue channels
+        let (r_plane, b_plane) = match profile.channel_order {
+            ChannelOrder::Rgb => (0, 2 * spatial),
+            ChannelOrder::Bgr => (2 * spatial, 0),
+        };
 
         for (i, px) in buf.chunks_exact(3).enumerate() {
             let r = px[0] as f32 / 255.0;
             let g = px[1] as f32 / 255.0;
             let b = px[2] as f32 / 255.0;
 
-            output[i] = (r - IMAGENET_MEAN[0]) / IMAGENET_STD[0];
-            output[i + spatial] = (g - IMAGENET_MEAN[1]) / IMAGENET_STD[1];
-            output[i + 2 * spatial] = (b - IMAGENET_MEAN[2]) / IMAGENET_STD[2];
+            output[i + r_plane] = (r - mean[0]) / std[0];
+            output[i + spatial] = (g - mean[1]) / std[1];
+            output[i + b_plane] = (b - mean[2]) / std[2];
         }
 
-        Ok(Array::from_shape_vec(
+        Ok(pool.wrap(Array::from_shape_vec(
             IxDyn(&[1, 3, height, width]),
             output,
-        )?)
+        )?))
     }
 }
 
@@ -162,15 +316,28 @@ impl Preprocess for CpuPreProcessor {
             self.preprocess_from_u8_slice(pixels, width, height)?;
         Ok(PreprocessResult {
             data: PreprocessOutput::Cpu(array),
-            scale,
-            offset_x,
-            offset_y,
+            transform: LetterboxTransform {
+                orig_width: width,
+                orig_height: height,
+                input_width: self.input_size.0,
+                input_height: self.input_size.1,
+                scale,
+                offset_x,
+                offset_y,
+            },
         })
     }
 
     fn input_size(&self) -> (u32, u32) {
         self.input_size
     }
+
+    fn frame_size_changed(&mut self, _width: u32, _height: u32) -> anyhow::Result<()> {
+        if let Some(denoiser) = self.denoiser.as_mut() {
+            denoiser.reset();
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -189,12 +356,21 @@ mod tests {
             &schema::FrameArgs {
                 frame_number: 1,
                 timestamp_ns: 0,
+                timestamp_clock: schema::ClockDomain::WallClock,
+                signature: None,
+                format_generation: 0,
                 camera_id: 0,
                 width,
                 height,
                 channels: 3,
                 pixels: Some(pixel_vector),
+                original_width: 0,
+                original_height: 0,
+                nvmm_surface: -1,
                 trace: None,
+                provenance: None,
+                encoding: schema::FrameEncoding::Rgb,
+                burst: None,
             },
         );
 
@@ -329,6 +505,114 @@ mod tests {
         );
     }
 
+    /// Test frames at the model input size bypass resize and keep exact pixel values
+    #[test]
+    fn test_matching_size_skips_resize() {
+        let mut pixels = vec![0u8; 64 * 64 * 3];
+        pixels[..3].copy_from_slice(&[255, 0, 0]);
+
+        let mut preprocessor = CpuPreProcessor::new((64, 64));
+        let (output, scale, offset_x, offset_y) = preprocessor
+            .preprocess_from_u8_slice(&pixels, 64, 64)
+            .unwrap();
+
+        assert_eq!(scale, 1.0);
+        assert_eq!((offset_x, offset_y), (0.0, 0.0));
+        assert_eq!(output.shape(), &[1, 3, 64, 64]);
+
+        let expected_r = (1.0 - IMAGENET_MEAN[0]) / IMAGENET_STD[0];
+        let expected_g = (0.0 - IMAGENET_MEAN[1]) / IMAGENET_STD[1];
+        assert_eq!(output[[0, 0, 0, 0]], expected_r);
+        assert_eq!(output[[0, 1, 0, 0]], expected_g);
+    }
+
+    /// Test output tensors reuse their storage once the previous one is dropped
+    #[test]
+    fn test_output_tensor_is_pooled() {
+        let pixels = vec![0u8; 64 * 64 * 3];
+        let mut preprocessor = CpuPreProcessor::new((64, 64));
+
+        for _ in 0..3 {
+            let (output, ..) = preprocessor
+                .preprocess_from_u8_slice(&pixels, 64, 64)
+                .unwrap();
+            assert_eq!(output.shape(), &[1, 3, 64, 64]);
+        }
+        assert_eq!(preprocessor.pool().allocations(), 1);
+
+        // A tensor still in use forces a second buffer
+        let (_held, ..) = preprocessor
+            .preprocess_from_u8_slice(&pixels, 64, 64)
+            .unwrap();
+        let _ = preprocessor
+            .preprocess_from_u8_slice(&pixels, 64, 64)
+            .unwrap();
+        assert_eq!(preprocessor.pool().allocations(), 2);
+    }
+
+    /// Test frames at the input size are normalized and ordered as the profile says
+    #[test]
+    fn test_matching_size_uses_profile_normalization() {
+        let mut pixels = vec![0u8; 64 * 64 * 3];
+        pixels[..3].copy_from_slice(&[255, 0, 51]);
+
+        let mut preprocessor = CpuPreProcessor::new((64, 64)).with_profile(PreprocessProfile {
+            channel_order: ChannelOrder::Bgr,
+            ..PreprocessProfile::YOLOV8
+        });
+        let (output, ..) = preprocessor
+            .preprocess_from_u8_slice(&pixels, 64, 64)
+            .unwrap();
+
+        assert_eq!(output[[0, 0, 0, 0]], 0.2);
+        assert_eq!(output[[0, 1, 0, 0]], 0.0);
+        assert_eq!(output[[0, 2, 0, 0]], 1.0);
+    }
+
+    /// Test center crop covers the input and maps the cropped frame back
+    #[test]
+    fn test_center_crop_geometry() {
+        let pixels = vec![0u8; 400 * 200 * 3];
+        let mut preprocessor =
+            CpuPreProcessor::new((100, 100)).with_profile(PreprocessProfile::TORCHVISION_IMAGENET);
+        let result = preprocessor.preprocess(&pixels, 400, 200).unwrap();
+        let transform = result.transform;
+
+        assert_eq!(transform.scale, 0.5);
+        assert_eq!((transform.offset_x, transform.offset_y), (-50.0, 0.0));
+        // The input covers the middle 200x200 of the frame
+        assert_eq!(
+            transform.to_image([0.0, 0.0, 100.0, 100.0]),
+            [100.0, 0.0, 300.0, 200.0]
+        );
+    }
+
+    /// Test the fast path still rejects truncated buffers
+    #[test]
+    fn test_matching_size_buffer_mismatch() {
+        let pixels = vec![0u8; 64 * 64 * 3 - 1];
+        let mut preprocessor = CpuPreProcessor::new((64, 64));
+        let result = preprocessor.preprocess_from_u8_slice(&pixels, 64, 64);
+        assert!(result.unwrap_err().to_string().contains("mismatch"));
+    }
+
+    /// Test aspect-matching frames are resized without padding
+    #[test]
+    fn test_matching_aspect_has_no_padding() {
+        let pixels = vec![200u8; 1024 * 1024 * 3];
+        let mut preprocessor = CpuPreProcessor::default();
+        let (output, scale, offset_x, offset_y) = preprocessor
+            .preprocess_from_u8_slice(&pixels, 1024, 1024)
+            .unwrap();
+
+        assert_eq!(scale, 0.5);
+        assert_eq!((offset_x, offset_y), (0.0, 0.0));
+
+        // Corner pixel comes from the image, not the letterbox color
+        let expected_r = (200.0 / 255.0 - IMAGENET_MEAN[0]) / IMAGENET_STD[0];
+        assert!((output[[0, 0, 0, 0]] - expected_r).abs() < 0.01);
+    }
+
     /// Test the Preprocess trait implementation
     #[test]
     fn test_preprocess_trait() {
@@ -340,6 +624,6 @@ mod tests {
 
         let preprocess_result = result.unwrap();
         assert!(matches!(preprocess_result.data, PreprocessOutput::Cpu(_)));
-        assert!(preprocess_result.scale > 0.0);
+        assert!(preprocess_result.transform.scale > 0.0);
     }
 }
@@ -0,0 +1,208 @@
+//! Motion-adaptive temporal denoise for low-light frames
+//!
+//! At night the sensor gain turns into per-pixel noise that changes every frame,
+//! and the model reacts to it with flickering low-confidence detections. Each
+//! pixel is blended with its value in the previous denoised frame, unless it
+//! changed by more than the motion threshold, so static noise averages out while
+//! moving objects stay sharp. The stage only runs while the frame is dark.
+//!
+//! The CUDA kernel in `cuda/preprocess.cu` implements the same blend as
+//! [`blend`], low-light detection always runs on the host pixels.
+
+/// Luma samples taken per frame to detect low light
+const LUMA_SAMPLES: usize = 4096;
+/// Mean luma margin above the threshold before the stage switches off again
+const HYSTERESIS: f32 = 8.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DenoiseConfig {
+    /// Weight of the previous frame in the blend, 0-1
+    pub strength: f32,
+    /// Per-channel change above which a pixel is treated as motion and kept as is
+    pub motion_threshold: u8,
+    /// Mean luma (0-255) below which frames are denoised, every frame when `None`
+    pub low_light_brightness: Option<f32>,
+}
+
+impl Default for DenoiseConfig {
+    fn default() -> Self {
+        Self {
+            strength: 0.6,
+            motion_threshold: 24,
+            low_light_brightness: Some(60.0),
+        }
+    }
+}
+
+/// Blend a channel value with its denoised value in the previous frame
+#[inline]
+pub fn blend(previous: u8, current: u8, strength: f32, motion_threshold: u8) -> u8 {
+    if previous.abs_diff(current) > motion_threshold {
+        return current;
+    }
+    (previous as f32 * strength + current as f32 * (1.0 - strength)).round() as u8
+}
+
+/// Approximate mean BT.601 luma of an RGB frame
+pub fn mean_luma(pixels: &[u8]) -> f32 {
+    let count = pixels.len() / 3;
+    if count == 0 {
+        return 0.0;
+    }
+    let step = (count / LUMA_SAMPLES).max(1);
+
+    let (sum, samples) =
+        pixels
+            .chunks_exact(3)
+            .step_by(step)
+            .fold((0f32, 0usize), |(sum, n), px| {
+                let luma = 0.299 * px[0] as f32 + 0.587 * px[1] as f32 + 0.114 * px[2] as f32;
+                (sum + luma, n + 1)
+            });
+    sum / samples as f32
+}
+
+/// Decides whether frames are dark enough to denoise, with hysteresis
+#[derive(Debug)]
+pub struct LowLightDetector {
+    threshold: Option<f32>,
+    active: bool,
+}
+
+impl LowLightDetector {
+    pub fn new(threshold: Option<f32>) -> Self {
+        Self {
+            threshold,
+            active: false,
+        }
+    }
+
+    /// Update with the next frame. Returns true if it should be denoised.
+    pub fn update(&mut self, pixels: &[u8]) -> bool {
+        let Some(threshold) = self.threshold else {
+            return true;
+        };
+
+        let brightness = mean_luma(pixels);
+        let active = if self.active {
+            brightness < threshold + HYSTERESIS
+        } else {
+            brightness < threshold
+        };
+        if active != self.active {
+            tracing::info!(brightness, active, "Low-light denoise toggled");
+            self.active = active;
+        }
+        active
+    }
+}
+
+/// CPU implementation of the temporal denoise
+pub struct TemporalDenoiser {
+    config: DenoiseConfig,
+    detector: LowLightDetector,
+    /// Previous denoised frame, empty until primed
+    history: Vec<u8>,
+}
+
+impl TemporalDenoiser {
+    pub fn new(config: DenoiseConfig) -> Self {
+        Self {
+            config,
+            detector: LowLightDetector::new(config.low_light_brightness),
+            history: Vec::new(),
+        }
+    }
+
+    /// Forget the previous frame, which would blend into a differently laid out one
+    pub fn reset(&mut self) {
+        self.history.clear();
+    }
+
+    /// Denoised frame, or `pixels` untouched while the scene is bright enough
+    pub fn apply<'a>(&'a mut self, pixels: &'a [u8]) -> &'a [u8] {
+        if !self.detector.update(pixels) {
+            // Stale history would ghost the first dark frames
+            self.history.clear();
+            return pixels;
+        }
+
+        let _s = common::span!("temporal_denoise");
+        if self.history.len() != pixels.len() {
+            self.history.clear();
+            self.history.extend_from_slice(pixels);
+            return &self.history;
+        }
+
+        let DenoiseConfig {
+            strength,
+            motion_threshold,
+            ..
+        } = self.config;
+        for (previous, &current) in self.history.iter_mut().zip(pixels) {
+            *previous = blend(*previous, current, strength, motion_threshold);
+        }
+        &self.history
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(low_light_brightness: Option<f32>) -> DenoiseConfig {
+        DenoiseConfig {
+            strength: 0.5,
+            motion_threshold: 20,
+            low_light_brightness,
+        }
+    }
+
+    #[test]
+    fn test_blend_keeps_motion() {
+        assert_eq!(blend(40, 50, 0.5, 20), 45);
+        assert_eq!(blend(40, 100, 0.5, 20), 100);
+        assert_eq!(blend(40, 40, 0.9, 20), 40);
+    }
+
+    #[test]
+    fn test_noise_is_averaged_over_frames() {
+        let mut denoiser = TemporalDenoiser::new(config(None));
+
+        assert_eq!(denoiser.apply(&[30, 30, 30]), [30, 30, 30]);
+        assert_eq!(denoiser.apply(&[40, 20, 30]), [35, 25, 30]);
+        // A jump past the threshold is motion, not noise
+        assert_eq!(denoiser.apply(&[200, 25, 30]), [200, 25, 30]);
+    }
+
+    #[test]
+    fn test_reset_starts_over() {
+        let mut denoiser = TemporalDenoiser::new(config(None));
+        denoiser.apply(&[30, 30, 30]);
+        denoiser.reset();
+
+        // Same length, e.g. 640x480 after 480x640, but nothing in common
+        assert_eq!(denoiser.apply(&[40, 20, 30]), [40, 20, 30]);
+    }
+
+    #[test]
+    fn test_bright_frames_pass_through() {
+        let mut denoiser = TemporalDenoiser::new(config(Some(60.0)));
+        let bright = [200u8; 30];
+        let dark = [20u8; 30];
+
+        assert_eq!(denoiser.apply(&bright).as_ptr(), bright.as_ptr());
+        assert_ne!(denoiser.apply(&dark).as_ptr(), dark.as_ptr());
+    }
+
+    #[test]
+    fn test_low_light_hysteresis() {
+        let mut detector = LowLightDetector::new(Some(60.0));
+
+        assert!(!detector.update(&[64; 3]));
+        assert!(detector.update(&[50; 3]));
+        // Slightly above the threshold stays on
+        assert!(detector.update(&[64; 3]));
+        assert!(!detector.update(&[80; 3]));
+    }
+}
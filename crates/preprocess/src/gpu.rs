@@ -5,10 +5,16 @@
 //! - Letterbox padding (gray 114)
 //! - ImageNet normalization
 //! - HWC -> CHW transpose
+//! - BGR -> RGB swizzle, for BGR frames, and RGB -> BGR planes for models
+//!   trained on BGR input (see [`GpuPreProcessor::with_model_order`])
 //!
 //! All operations are fused into a single CUDA kernel for maximum performance.
+//! Frames already at the model input size use a normalize-only kernel instead.
 
 use crate::config::DEFAULT_INPUT_SIZE;
+use crate::denoise::{DenoiseConfig, LowLightDetector};
+use crate::letterbox::{Letterbox, LetterboxTransform, ResizePath};
+use crate::profile::ChannelOrder;
 use crate::{Preprocess, PreprocessOutput, PreprocessResult};
 use anyhow::{Context, Result};
 use common::span;
@@ -19,21 +25,74 @@ use std::sync::Arc;
 /// PTX kernel embedded at compile time (compiled by nvcc in build.rs)
 const PREPROCESS_PTX: &str = include_str!(concat!(env!("OUT_DIR"), "/preprocess.ptx"));
 
+/// Snapshot of device memory held by a [`GpuPreProcessor`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GpuMemoryStats {
+    /// Bytes currently allocated for the input pool
+    pub input_capacity_bytes: usize,
+    /// Bytes of the input pool used by the last uploaded frame
+    pub input_used_bytes: usize,
+    /// Upper bound the input pool is allowed to grow to
+    pub input_max_bytes: usize,
+    /// Bytes allocated for the output tensor
+    pub output_bytes: usize,
+    /// Number of times the input pool had to grow
+    pub input_reallocations: u64,
+}
+
+impl GpuMemoryStats {
+    /// Total device memory held by the preprocessor
+    pub fn total_bytes(&self) -> usize {
+        self.input_capacity_bytes + self.output_bytes
+    }
+}
+
+/// Next input pool capacity (in pixels) able to hold `required` pixels.
+///
+/// Capacity doubles to amortize growth across resolution switches and is
+/// clamped to `max`, so the pool never shrinks and never exceeds the limit.
+fn grow_capacity(current: usize, required: usize, max: usize) -> usize {
+    if required <= current {
+        return current;
+    }
+    current.saturating_mul(2).max(required).min(max)
+}
+
 /// GPU-accelerated image preprocessor
 pub struct GpuPreProcessor {
     /// Target input size (width, height)
     input_size: (u32, u32),
     /// CUDA device handle
     device: Arc<CudaDevice>,
-    /// Pre-allocated device buffer for input image (RGB u8)
-    /// Size matches the last processed frame; reallocated if frame size changes
+    /// Device input pool for RGB u8 frames
+    /// Grows up to `max_input_pixels` and is reused across resolution switches,
+    /// shrinking only when frames become much smaller; only the first
+    /// `current_input_pixels * 3` bytes are valid
     d_input: CudaSlice<u8>,
-    /// Current input buffer size in pixels (width * height)
+    /// Input pool capacity in pixels
+    input_capacity_pixels: usize,
+    /// Size of the last uploaded frame in pixels (width * height)
     current_input_pixels: usize,
+    /// Number of times the input pool had to grow
+    input_reallocations: u64,
     /// Pre-allocated device buffer for output (CHW f32)
     d_output: CudaSlice<f32>,
     /// Maximum input image size we can handle
     max_input_pixels: usize,
+    /// Low-light denoise applied to the uploaded frame, off when unset
+    denoise: Option<GpuDenoise>,
+    /// Channel order of the output tensor
+    model_order: ChannelOrder,
+}
+
+/// Device state of the temporal denoise
+struct GpuDenoise {
+    config: DenoiseConfig,
+    detector: LowLightDetector,
+    /// Previous denoised frame
+    d_history: CudaSlice<u8>,
+    /// Bytes of `d_history` holding the previous frame, 0 until primed
+    primed_len: usize,
 }
 
 impl GpuPreProcessor {
@@ -60,14 +119,23 @@ impl GpuPreProcessor {
         // Load the PTX kernel
         let ptx = Ptx::from_src(PREPROCESS_PTX);
         device
-            .load_ptx(ptx, "preprocess", &["preprocess_kernel"])
+            .load_ptx(
+                ptx,
+                "preprocess",
+                &[
+                    "preprocess_kernel",
+                    "normalize_kernel",
+                    "normalize_rgba_kernel",
+                    "temporal_denoise_kernel",
+                ],
+            )
             .context("Failed to load preprocess PTX")?;
 
         let max_input_pixels = (max_input_size.0 * max_input_size.1) as usize;
         let output_pixels = (input_size.0 * input_size.1) as usize;
 
         // Pre-allocate device buffers
-        // Start with a small input buffer; it will be reallocated on first use
+        // Start with a single-pixel input pool; it grows on first use
         let d_input = device
             .alloc_zeros::<u8>(3)
             .context("Failed to allocate input buffer")?;
@@ -80,12 +148,91 @@ impl GpuPreProcessor {
             input_size,
             device,
             d_input,
+            input_capacity_pixels: 1,
             current_input_pixels: 0,
+            input_reallocations: 0,
             d_output,
             max_input_pixels,
+            denoise: None,
+            model_order: ChannelOrder::Rgb,
         })
     }
 
+    /// Write the output planes in `order`, for models trained on BGR input
+    pub fn with_model_order(mut self, order: ChannelOrder) -> Self {
+        self.model_order = order;
+        self
+    }
+
+    pub fn model_order(&self) -> ChannelOrder {
+        self.model_order
+    }
+
+    /// Denoise dark frames on the device before resizing them
+    pub fn with_denoise(mut self, config: DenoiseConfig) -> Result<Self> {
+        let d_history = self
+            .device
+            .alloc_zeros::<u8>(3)
+            .context("Failed to allocate denoise history")?;
+        self.denoise = Some(GpuDenoise {
+            config,
+            detector: LowLightDetector::new(config.low_light_brightness),
+            d_history,
+            primed_len: 0,
+        });
+        Ok(self)
+    }
+
+    /// Run the temporal denoise on the frame uploaded from `pixels`, if it is dark
+    fn denoise_on_device(&mut self, pixels: &[u8]) -> Result<()> {
+        let Some(denoise) = self.denoise.as_mut() else {
+            return Ok(());
+        };
+        if !denoise.detector.update(pixels) {
+            denoise.primed_len = 0;
+            return Ok(());
+        }
+        let _s = span!("temporal_denoise");
+
+        let len = pixels.len();
+        if denoise.primed_len != len {
+            denoise.primed_len = 0;
+            if denoise.d_history.len() < len {
+                denoise.d_history = self
+                    .device
+                    .alloc_zeros::<u8>(self.input_capacity_pixels * 3)
+                    .context("Failed to grow denoise history")?;
+            }
+        }
+
+        let func = self
+            .device
+            .get_func("preprocess", "temporal_denoise_kernel")
+            .context("Failed to get denoise kernel")?;
+        let block_size = 256u32;
+        let config = LaunchConfig {
+            grid_dim: ((len as u32).div_ceil(block_size), 1, 1),
+            block_dim: (block_size, 1, 1),
+            shared_mem_bytes: 0,
+        };
+        unsafe {
+            func.launch(
+                config,
+                (
+                    &mut self.d_input,
+                    &mut denoise.d_history,
+                    len as i32,
+                    denoise.config.strength,
+                    denoise.config.motion_threshold as i32,
+                    (denoise.primed_len == len) as i32,
+                ),
+            )
+            .context("Failed to launch denoise kernel")?;
+        }
+        denoise.primed_len = len;
+        Ok(())
+    }
+
     /// Get the device pointer to the output buffer
     ///
     /// This pointer can be passed directly to TensorRT for zero-copy inference.
@@ -93,11 +240,34 @@ impl GpuPreProcessor {
         *self.d_output.device_ptr() as u64
     }
 
+    /// Device handle the output tensor lives on
+    #[cfg(feature = "jetson")]
+    pub(crate) fn device(&self) -> &Arc<CudaDevice> {
+        &self.device
+    }
+
+    /// Output tensor, for kernels writing the same model input from other sources
+    #[cfg(feature = "jetson")]
+    pub(crate) fn output(&self) -> &CudaSlice<f32> {
+        &self.d_output
+    }
+
     /// Get the number of output elements
     pub fn output_len(&self) -> usize {
         (self.input_size.0 * self.input_size.1 * 3) as usize
     }
 
+    /// Current device memory usage of the input pool and output tensor
+    pub fn memory_stats(&self) -> GpuMemoryStats {
+        GpuMemoryStats {
+            input_capacity_bytes: self.input_capacity_pixels * 3,
+            input_used_bytes: self.current_input_pixels * 3,
+            input_max_bytes: self.max_input_pixels * 3,
+            output_bytes: self.output_len() * std::mem::size_of::<f32>(),
+            input_reallocations: self.input_reallocations,
+        }
+    }
+
     /// Copy the output buffer from device to host (for testing/verification)
     pub fn copy_output_to_host(&self) -> Result<Vec<f32>> {
         self.device
@@ -132,18 +302,30 @@ impl GpuPreProcessor {
             );
         }
 
-        // Reallocate input buffer if frame size changed
-        if num_pixels != self.current_input_pixels {
-            self.d_input = self
-                .device
-                .alloc_zeros::<u8>(input_bytes)
-                .context("Failed to reallocate input buffer")?;
-            self.current_input_pixels = num_pixels;
+        // Grow the input pool only when the frame no longer fits
+        if num_pixels > self.input_capacity_pixels {
+            let capacity = grow_capacity(
+                self.input_capacity_pixels,
+                num_pixels,
+                self.max_input_pixels,
+            );
+            // SAFETY: the buffer is fully overwritten up to `input_bytes` below and the
+            // kernel never reads past `width * height * 3` bytes.
+            self.d_input = unsafe { self.device.alloc::<u8>(capacity * 3) }
+                .context("Failed to grow input buffer")?;
+            self.input_capacity_pixels = capacity;
+            self.input_reallocations += 1;
+            tracing::debug!(
+                capacity_bytes = capacity * 3,
+                reallocations = self.input_reallocations,
+                "Grew GPU input pool"
+            );
         }
+        self.current_input_pixels = num_pixels;
 
-        // Copy input to device
+        // Copy input into the front of the pool
         self.device
-            .htod_copy_into(pixels.to_vec(), &mut self.d_input)
+            .htod_sync_copy_into(pixels, &mut self.d_input.slice_mut(..input_bytes))
             .context("Failed to copy input to device")?;
 
         Ok(())
@@ -152,22 +334,26 @@ impl GpuPreProcessor {
     /// Run the preprocessing kernel only (assumes data already uploaded via `upload_to_device`)
     ///
     /// This is useful for benchmarking kernel performance without host-to-device copy overhead.
-    pub fn run_kernel(&mut self, width: u32, height: u32) -> Result<(u64, f32, f32, f32)> {
+    /// `order` is the channel order of the uploaded frame.
+    pub fn run_kernel(
+        &mut self,
+        width: u32,
+        height: u32,
+        order: ChannelOrder,
+    ) -> Result<(u64, f32, f32, f32)> {
         let _s = span!("preprocess_kernel");
+        let bgr = (order == ChannelOrder::Bgr) as i32;
+        let bgr_output = (self.model_order == ChannelOrder::Bgr) as i32;
 
         // Calculate letterbox parameters
-        let scale =
-            (self.input_size.0 as f32 / width as f32).min(self.input_size.1 as f32 / height as f32);
-        let new_width = (width as f32 * scale) as u32;
-        let new_height = (height as f32 * scale) as u32;
-        let offset_x = (self.input_size.0 - new_width) / 2;
-        let offset_y = (self.input_size.1 - new_height) / 2;
-
-        // Launch the preprocessing kernel
-        let func = self
-            .device
-            .get_func("preprocess", "preprocess_kernel")
-            .context("Failed to get preprocess kernel")?;
+        let letterbox = Letterbox::new(width, height, self.input_size);
+        let Letterbox {
+            scale,
+            new_width,
+            new_height,
+            offset_x,
+            offset_y,
+        } = letterbox;
 
         // Calculate grid/block dimensions
         let output_pixels = (self.input_size.0 * self.input_size.1) as u32;
@@ -180,25 +366,56 @@ impl GpuPreProcessor {
             shared_mem_bytes: 0,
         };
 
-        // Kernel parameters (11 params - ImageNet constants are embedded in kernel)
-        unsafe {
-            func.launch(
-                config,
-                (
-                    &self.d_input,
-                    &self.d_output,
-                    width as i32,
-                    height as i32,
-                    self.input_size.0 as i32,
-                    self.input_size.1 as i32,
-                    new_width as i32,
-                    new_height as i32,
-                    offset_x as i32,
-                    offset_y as i32,
-                    scale,
-                ),
-            )
-            .context("Failed to launch preprocess kernel")?;
+        if letterbox.path(width, height, self.input_size) == ResizePath::Passthrough {
+            // Frame already matches the model input: normalize without resampling
+            let func = self
+                .device
+                .get_func("preprocess", "normalize_kernel")
+                .context("Failed to get normalize kernel")?;
+
+            unsafe {
+                func.launch(
+                    config,
+                    (
+                        &self.d_input,
+                        &self.d_output,
+                        self.input_size.0 as i32,
+                        self.input_size.1 as i32,
+                        bgr,
+                        bgr_output,
+                    ),
+                )
+                .context("Failed to launch normalize kernel")?;
+            }
+        } else {
+            // Launch the preprocessing kernel
+            let func = self
+                .device
+                .get_func("preprocess", "preprocess_kernel")
+                .context("Failed to get preprocess kernel")?;
+
+            // Kernel parameters (13 params - ImageNet constants are embedded in kernel)
+            unsafe {
+                func.launch(
+                    config,
+                    (
+                        &self.d_input,
+                        &self.d_output,
+                        width as i32,
+                        height as i32,
+                        self.input_size.0 as i32,
+                        self.input_size.1 as i32,
+                        new_width as i32,
+                        new_height as i32,
+                        offset_x as i32,
+                        offset_y as i32,
+                        scale,
+                        bgr,
+                        bgr_output,
+                    ),
+                )
+                .context("Failed to launch preprocess kernel")?;
+            }
         }
 
         // Synchronize to ensure kernel completion
@@ -215,9 +432,10 @@ impl GpuPreProcessor {
     /// Preprocess an image on the GPU (full pipeline: upload + kernel)
     ///
     /// # Arguments
-    /// * `pixels` - RGB pixel data in HWC format
+    /// * `pixels` - RGB or BGR pixel data in HWC format
     /// * `width` - Image width
     /// * `height` - Image height
+    /// * `order` - Channel order of `pixels`, BGR is swizzled by the kernel
     ///
     /// # Returns
     /// Device pointer to preprocessed data and transformation parameters
@@ -226,9 +444,39 @@ impl GpuPreProcessor {
         pixels: &[u8],
         width: u32,
         height: u32,
+        order: ChannelOrder,
     ) -> Result<(u64, f32, f32, f32)> {
         self.upload_to_device(pixels, width, height)?;
-        self.run_kernel(width, height)
+        self.denoise_on_device(pixels)?;
+        self.run_kernel(width, height, order)
+    }
+
+    /// [`Preprocess::preprocess`] for frames in either channel order
+    pub fn preprocess_with_order(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        order: ChannelOrder,
+    ) -> Result<PreprocessResult> {
+        let (ptr, scale, offset_x, offset_y) =
+            self.preprocess_to_device(pixels, width, height, order)?;
+
+        Ok(PreprocessResult {
+            data: PreprocessOutput::Gpu {
+                ptr,
+                len: self.output_len(),
+            },
+            transform: LetterboxTransform {
+                orig_width: width,
+                orig_height: height,
+                input_width: self.input_size.0,
+                input_height: self.input_size.1,
+                scale,
+                offset_x,
+                offset_y,
+            },
+        })
     }
 }
 
@@ -242,22 +490,34 @@ impl Default for GpuPreProcessor {
 
 impl Preprocess for GpuPreProcessor {
     fn preprocess(&mut self, pixels: &[u8], width: u32, height: u32) -> Result<PreprocessResult> {
-        let (ptr, scale, offset_x, offset_y) = self.preprocess_to_device(pixels, width, height)?;
-
-        Ok(PreprocessResult {
-            data: PreprocessOutput::Gpu {
-                ptr,
-                len: self.output_len(),
-            },
-            scale,
-            offset_x,
-            offset_y,
-        })
+        self.preprocess_with_order(pixels, width, height, ChannelOrder::Rgb)
     }
 
     fn input_size(&self) -> (u32, u32) {
         self.input_size
     }
+
+    fn frame_size_changed(&mut self, width: u32, height: u32) -> Result<()> {
+        if let Some(denoise) = self.denoise.as_mut() {
+            denoise.primed_len = 0;
+        }
+        // A fallback to a much lower resolution gives back the pool sized for the old one
+        let pixels = ((width * height) as usize).max(1);
+        if pixels.saturating_mul(4) < self.input_capacity_pixels {
+            // SAFETY: every frame is uploaded into the pool before a kernel reads it
+            self.d_input = unsafe { self.device.alloc::<u8>(pixels * 3) }
+                .context("Failed to shrink input buffer")?;
+            self.input_capacity_pixels = pixels;
+            self.current_input_pixels = 0;
+            self.input_reallocations += 1;
+            tracing::debug!(
+                capacity_bytes = pixels * 3,
+                reallocations = self.input_reallocations,
+                "Shrank GPU input pool"
+            );
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -272,7 +532,7 @@ mod tests {
             Ok(mut gpu) => {
                 // Try a simple preprocess to verify kernel works
                 let test_pixels = vec![128u8; 128 * 128 * 3];
-                match gpu.preprocess_to_device(&test_pixels, 128, 128) {
+                match gpu.preprocess_to_device(&test_pixels, 128, 128, ChannelOrder::Rgb) {
                     Ok(_) => None,
                     Err(e) => Some(format!("Kernel execution failed: {}", e)),
                 }
@@ -281,6 +541,56 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_grow_capacity_doubles_and_clamps() {
+        assert_eq!(grow_capacity(1, 640 * 480, 1920 * 1080), 640 * 480);
+        assert_eq!(
+            grow_capacity(640 * 480, 800 * 600, 1920 * 1080),
+            640 * 480 * 2
+        );
+        assert_eq!(
+            grow_capacity(1280 * 720, 1920 * 1080, 1920 * 1080),
+            1920 * 1080
+        );
+    }
+
+    #[test]
+    fn test_grow_capacity_never_shrinks() {
+        assert_eq!(
+            grow_capacity(1920 * 1080, 320 * 240, 1920 * 1080),
+            1920 * 1080
+        );
+        assert_eq!(grow_capacity(640 * 480, 640 * 480, 1920 * 1080), 640 * 480);
+    }
+
+    #[test]
+    fn test_gpu_pool_reused_across_resolutions() {
+        if let Some(reason) = gpu_not_available() {
+            eprintln!("Skipping GPU pool test: {}", reason);
+            return;
+        }
+
+        let mut gpu = GpuPreProcessor::new((64, 64), (1280, 720)).unwrap();
+
+        let large = vec![128u8; 1280 * 720 * 3];
+        gpu.preprocess_to_device(&large, 1280, 720, ChannelOrder::Rgb)
+            .unwrap();
+        let after_large = gpu.memory_stats();
+        assert_eq!(after_large.input_capacity_bytes, 1280 * 720 * 3);
+        assert_eq!(after_large.input_reallocations, 1);
+
+        let small = vec![128u8; 640 * 480 * 3];
+        gpu.preprocess_to_device(&small, 640, 480, ChannelOrder::Rgb)
+            .unwrap();
+        gpu.preprocess_to_device(&large, 1280, 720, ChannelOrder::Rgb)
+            .unwrap();
+        let stats = gpu.memory_stats();
+        assert_eq!(stats.input_capacity_bytes, after_large.input_capacity_bytes);
+        assert_eq!(stats.input_reallocations, 1);
+        assert_eq!(stats.input_used_bytes, 1280 * 720 * 3);
+        assert_eq!(stats.output_bytes, 64 * 64 * 3 * 4);
+    }
+
     #[test]
     fn test_gpu_preprocessor_creation() {
         let result = GpuPreProcessor::new((512, 512), (1920, 1080));
@@ -323,8 +633,9 @@ mod tests {
 
         // GPU preprocessing
         let mut gpu = GpuPreProcessor::new(input_size, (width, height)).unwrap();
-        let (_, gpu_scale, gpu_offset_x, gpu_offset_y) =
-            gpu.preprocess_to_device(&pixels, width, height).unwrap();
+        let (_, gpu_scale, gpu_offset_x, gpu_offset_y) = gpu
+            .preprocess_to_device(&pixels, width, height, ChannelOrder::Rgb)
+            .unwrap();
         let gpu_output = gpu.copy_output_to_host().unwrap();
 
         // Verify transformation parameters match
@@ -390,6 +701,91 @@ mod tests {
         eprintln!("GPU vs CPU test passed!");
     }
 
+    #[test]
+    fn test_gpu_passthrough_matches_cpu() {
+        if let Some(reason) = gpu_not_available() {
+            eprintln!("Skipping GPU passthrough test: {}", reason);
+            return;
+        }
+
+        let input_size = (64, 64);
+        let pixels: Vec<u8> = (0..64 * 64 * 3).map(|i| (i % 251) as u8).collect();
+
+        let mut cpu = CpuPreProcessor::new(input_size);
+        let (cpu_output, ..) = cpu.preprocess_from_u8_slice(&pixels, 64, 64).unwrap();
+
+        let mut gpu = GpuPreProcessor::new(input_size, input_size).unwrap();
+        let (_, scale, offset_x, offset_y) = gpu
+            .preprocess_to_device(&pixels, 64, 64, ChannelOrder::Rgb)
+            .unwrap();
+        let gpu_output = gpu.copy_output_to_host().unwrap();
+
+        assert_eq!(scale, 1.0);
+        assert_eq!((offset_x, offset_y), (0.0, 0.0));
+        for (cpu_val, gpu_val) in cpu_output.as_slice().unwrap().iter().zip(&gpu_output) {
+            assert!((cpu_val - gpu_val).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_gpu_bgr_matches_rgb() {
+        if let Some(reason) = gpu_not_available() {
+            eprintln!("Skipping GPU BGR test: {}", reason);
+            return;
+        }
+
+        let input_size = (64, 64);
+        let mut gpu = GpuPreProcessor::new(input_size, (160, 120)).unwrap();
+        // Resized and passthrough kernels
+        for (width, height) in [(160u32, 120u32), (64, 64)] {
+            let rgb: Vec<u8> = (0..width * height * 3).map(|i| (i % 251) as u8).collect();
+            let bgr: Vec<u8> = rgb.chunks(3).flat_map(|p| [p[2], p[1], p[0]]).collect();
+
+            gpu.preprocess_to_device(&rgb, width, height, ChannelOrder::Rgb)
+                .unwrap();
+            let from_rgb = gpu.copy_output_to_host().unwrap();
+            gpu.preprocess_to_device(&bgr, width, height, ChannelOrder::Bgr)
+                .unwrap();
+            let from_bgr = gpu.copy_output_to_host().unwrap();
+
+            for (a, b) in from_rgb.iter().zip(&from_bgr) {
+                assert!((a - b).abs() < 1e-5, "{width}x{height}: {a} vs {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_gpu_bgr_model_swaps_planes() {
+        if let Some(reason) = gpu_not_available() {
+            eprintln!("Skipping GPU BGR model test: {}", reason);
+            return;
+        }
+
+        let input_size = (64, 64);
+        let plane = (input_size.0 * input_size.1) as usize;
+        let mut rgb_model = GpuPreProcessor::new(input_size, (160, 120)).unwrap();
+        let mut bgr_model = GpuPreProcessor::new(input_size, (160, 120))
+            .unwrap()
+            .with_model_order(ChannelOrder::Bgr);
+        // Resized and passthrough kernels
+        for (width, height) in [(160u32, 120u32), (64, 64)] {
+            let rgb: Vec<u8> = (0..width * height * 3).map(|i| (i % 251) as u8).collect();
+
+            rgb_model
+                .preprocess_to_device(&rgb, width, height, ChannelOrder::Rgb)
+                .unwrap();
+            let rgb_planes = rgb_model.copy_output_to_host().unwrap();
+            bgr_model
+                .preprocess_to_device(&rgb, width, height, ChannelOrder::Rgb)
+                .unwrap();
+            let bgr_planes = bgr_model.copy_output_to_host().unwrap();
+
+            assert_eq!(bgr_planes[..plane], rgb_planes[2 * plane..]);
+            assert_eq!(bgr_planes[plane..2 * plane], rgb_planes[plane..2 * plane]);
+            assert_eq!(bgr_planes[2 * plane..], rgb_planes[..plane]);
+        }
+    }
+
     #[test]
     fn test_gpu_letterbox_padding() {
         if let Some(reason) = gpu_not_available() {
@@ -406,8 +802,9 @@ mod tests {
         let pixels = vec![255u8, 0, 0].repeat((width * height) as usize);
 
         let mut gpu = GpuPreProcessor::new(input_size, (width, height)).unwrap();
-        let (_, scale, offset_x, offset_y) =
-            gpu.preprocess_to_device(&pixels, width, height).unwrap();
+        let (_, scale, offset_x, offset_y) = gpu
+            .preprocess_to_device(&pixels, width, height, ChannelOrder::Rgb)
+            .unwrap();
         let output = gpu.copy_output_to_host().unwrap();
 
         // Verify letterbox parameters
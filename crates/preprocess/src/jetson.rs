@@ -0,0 +1,322 @@
+//! Jetson zero-copy preprocessing from NVMM surfaces
+//!
+//! Capture shares its raw YUYV frames as NVMM surfaces (see `bridge::nvmm`). For
+//! those frames the VIC converts to RGBA, resizes and letterboxes straight into an
+//! NVMM surface owned by this preprocessor, which is mapped into CUDA once through
+//! EGL. The normalize kernel then writes the model input next to the TensorRT
+//! bindings, so no frame byte is touched by the CPU on the inference side.
+//!
+//! Frames without a surface (other cameras, MJPEG) take the regular GPU path.
+//!
+//! The source surface is held on capture's NVMM write gate while the VIC reads
+//! it, so capture does not overwrite it mid-transform.
+
+use crate::gpu::GpuPreProcessor;
+use crate::letterbox::Letterbox;
+use crate::profile::ChannelOrder;
+use crate::{Preprocess, PreprocessOutput, PreprocessResult};
+use anyhow::{Context, Result, bail};
+use bridge::nvmm::{NvmmFormat, NvmmSurface, ffi::NvBufSurface, surface_hold};
+use bridge::{GateSubscription, import_surfaces, paths};
+use common::span;
+use cudarc::driver::{LaunchAsync, LaunchConfig};
+use std::ffi::{c_int, c_uint, c_void};
+use std::os::fd::{AsFd, OwnedFd};
+
+/// Letterbox fill, matching the CPU and CUDA paths
+const LETTERBOX_GRAY: u8 = 114;
+
+mod ffi {
+    //! `nvbufsurftransform.h` and the CUDA EGL interop entry points
+
+    use super::*;
+
+    pub const NVBUFSURF_TRANSFORM_CROP_DST: u32 = 1 << 1;
+    pub const NVBUFSURF_TRANSFORM_FILTER: u32 = 1 << 2;
+    pub const NVBUFSURF_TRANSFORM_INTER_BILINEAR: u32 = 1;
+
+    #[repr(C)]
+    pub struct NvBufSurfTransformRect {
+        pub top: u32,
+        pub left: u32,
+        pub width: u32,
+        pub height: u32,
+    }
+
+    #[repr(C)]
+    pub struct NvBufSurfTransformParams {
+        pub transform_flag: u32,
+        pub transform_flip: u32,
+        pub transform_filter: u32,
+        pub src_rect: *mut NvBufSurfTransformRect,
+        pub dst_rect: *mut NvBufSurfTransformRect,
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    const _: () = {
+        use std::mem::{offset_of, size_of};
+
+        assert!(size_of::<NvBufSurfTransformRect>() == 16);
+        assert!(size_of::<NvBufSurfTransformParams>() == 32);
+        assert!(offset_of!(NvBufSurfTransformParams, src_rect) == 16);
+        assert!(offset_of!(NvBufSurfTransformParams, dst_rect) == 24);
+    };
+
+    #[link(name = "nvbufsurftransform")]
+    unsafe extern "C" {
+        pub fn NvBufSurfTransform(
+            src: *mut NvBufSurface,
+            dst: *mut NvBufSurface,
+            params: *mut NvBufSurfTransformParams,
+        ) -> c_int;
+    }
+
+    pub type CUgraphicsResource = *mut c_void;
+
+    #[repr(C)]
+    pub struct CUeglFrame {
+        pub p_pitch: [*mut c_void; 3],
+        pub width: c_uint,
+        pub height: c_uint,
+        pub depth: c_uint,
+        pub pitch: c_uint,
+        pub plane_count: c_uint,
+        pub num_channels: c_uint,
+        pub frame_type: c_uint,
+        pub egl_color_format: c_uint,
+        pub cu_format: c_uint,
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    const _: () = {
+        assert!(std::mem::size_of::<CUeglFrame>() == 64);
+        assert!(std::mem::offset_of!(CUeglFrame, width) == 24);
+        assert!(std::mem::offset_of!(CUeglFrame, cu_format) == 56);
+    };
+
+    #[link(name = "cuda")]
+    unsafe extern "C" {
+        pub fn cuGraphicsEGLRegisterImage(
+            resource: *mut CUgraphicsResource,
+            image: *mut c_void,
+            flags: c_uint,
+        ) -> c_int;
+        pub fn cuGraphicsResourceGetMappedEglFrame(
+            frame: *mut CUeglFrame,
+            resource: CUgraphicsResource,
+            index: c_uint,
+            mip_level: c_uint,
+        ) -> c_int;
+        pub fn cuGraphicsUnregisterResource(resource: CUgraphicsResource) -> c_int;
+    }
+}
+
+/// Letterboxed RGBA surface mapped into CUDA
+struct MappedInput {
+    surface: NvmmSurface,
+    resource: ffi::CUgraphicsResource,
+    ptr: u64,
+    pitch: u32,
+}
+
+impl MappedInput {
+    fn new(input_size: (u32, u32)) -> Result<Self> {
+        let mut surface = NvmmSurface::allocate(input_size.0, input_size.1, NvmmFormat::Rgba)
+            .context("Failed to allocate letterbox surface")?;
+        let image = surface.egl_image()?;
+
+        let mut resource = std::ptr::null_mut();
+        let mut frame = std::mem::MaybeUninit::<ffi::CUeglFrame>::zeroed();
+        unsafe {
+            if ffi::cuGraphicsEGLRegisterImage(&mut resource, image, 0) != 0 {
+                bail!("cuGraphicsEGLRegisterImage failed");
+            }
+            if ffi::cuGraphicsResourceGetMappedEglFrame(frame.as_mut_ptr(), resource, 0, 0) != 0 {
+                ffi::cuGraphicsUnregisterResource(resource);
+                bail!("cuGraphicsResourceGetMappedEglFrame failed");
+            }
+        }
+        let frame = unsafe { frame.assume_init() };
+
+        Ok(Self {
+            surface,
+            resource,
+            ptr: frame.p_pitch[0] as u64,
+            pitch: frame.pitch,
+        })
+    }
+}
+
+impl Drop for MappedInput {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::cuGraphicsUnregisterResource(self.resource);
+        }
+    }
+}
+
+/// Surfaces imported from capture, keeping their descriptors open
+struct Imported {
+    surfaces: Vec<NvmmSurface>,
+    _fds: Vec<OwnedFd>,
+}
+
+pub struct JetsonPreProcessor {
+    gpu: GpuPreProcessor,
+    input: MappedInput,
+    imported: Option<Imported>,
+    /// Holds the surface being transformed
+    gate: GateSubscription,
+    /// Letterbox the input surface is currently padded for
+    padded_for: Option<(u32, u32)>,
+}
+
+// The raw CUDA resource is only used from the thread driving the preprocessor
+unsafe impl Send for JetsonPreProcessor {}
+
+impl JetsonPreProcessor {
+    /// Preprocessor writing the model input with channels in `model_order`
+    pub fn new(
+        input_size: (u32, u32),
+        max_input_size: (u32, u32),
+        model_order: ChannelOrder,
+    ) -> Result<Self> {
+        let gpu = GpuPreProcessor::new(input_size, max_input_size)?.with_model_order(model_order);
+        gpu.device().bind_to_thread()?;
+        let input = MappedInput::new(input_size)?;
+        let gate = GateSubscription::register(paths::NVMM_WRITE_GATE_PATH, "inference")
+            .context("Failed to register on the NVMM write gate")?;
+
+        Ok(Self {
+            gpu,
+            input,
+            imported: None,
+            gate,
+            padded_for: None,
+        })
+    }
+
+    /// Output tensor device pointer, shared with the regular GPU path
+    pub fn output_device_ptr(&self) -> u64 {
+        self.gpu.output_device_ptr()
+    }
+
+    /// Preprocess the frame held by capture's NVMM surface `index`
+    pub fn preprocess_surface(
+        &mut self,
+        index: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<PreprocessResult> {
+        let _s = span!("preprocess_nvmm");
+
+        let result = self.transform(index, width, height);
+        if result.is_err() {
+            // Capture may have restarted with new surfaces, import again next frame
+            self.imported = None;
+        }
+        result
+    }
+
+    fn transform(&mut self, index: u32, width: u32, height: u32) -> Result<PreprocessResult> {
+        let input_size = self.gpu.input_size();
+        let letterbox = Letterbox::new(width, height, input_size);
+
+        if self.padded_for != Some((width, height)) {
+            self.input.surface.fill(LETTERBOX_GRAY)?;
+            self.padded_for = Some((width, height));
+        }
+
+        let imported = match self.imported.as_mut() {
+            Some(imported) => imported,
+            None => self.imported.insert(import()?),
+        };
+        let source = imported
+            .surfaces
+            .get(index as usize)
+            .with_context(|| format!("No NVMM surface {}", index))?;
+
+        let mut dst_rect = ffi::NvBufSurfTransformRect {
+            top: letterbox.offset_y,
+            left: letterbox.offset_x,
+            width: letterbox.new_width,
+            height: letterbox.new_height,
+        };
+        let mut params = ffi::NvBufSurfTransformParams {
+            transform_flag: ffi::NVBUFSURF_TRANSFORM_CROP_DST | ffi::NVBUFSURF_TRANSFORM_FILTER,
+            transform_flip: 0,
+            transform_filter: ffi::NVBUFSURF_TRANSFORM_INTER_BILINEAR,
+            src_rect: std::ptr::null_mut(),
+            dst_rect: &mut dst_rect,
+        };
+        // NvBufSurfTransform is synchronous, the source is no longer read once it returns
+        self.gate.hold(surface_hold(index));
+        let ret =
+            unsafe { ffi::NvBufSurfTransform(source.raw(), self.input.surface.raw(), &mut params) };
+        self.gate.release();
+        if ret != 0 {
+            bail!("NvBufSurfTransform failed ({})", ret);
+        }
+
+        let device = self.gpu.device();
+        let func = device
+            .get_func("preprocess", "normalize_rgba_kernel")
+            .context("Failed to get RGBA normalize kernel")?;
+        let output_pixels = input_size.0 * input_size.1;
+        let config = LaunchConfig::for_num_elems(output_pixels);
+        unsafe {
+            func.launch(
+                config,
+                (
+                    self.input.ptr,
+                    self.input.pitch as i32,
+                    self.gpu.output(),
+                    input_size.0 as i32,
+                    input_size.1 as i32,
+                    (self.gpu.model_order() == ChannelOrder::Bgr) as i32,
+                ),
+            )
+            .context("Failed to launch RGBA normalize kernel")?;
+        }
+        device.synchronize().context("Failed to synchronize")?;
+
+        Ok(PreprocessResult {
+            data: PreprocessOutput::Gpu {
+                ptr: self.gpu.output_device_ptr(),
+                len: self.gpu.output_len(),
+            },
+            transform: letterbox.transform(width, height, input_size),
+        })
+    }
+}
+
+fn import() -> Result<Imported> {
+    let fds = import_surfaces(paths::NVMM_SURFACES_SOCKET_PATH)
+        .context("Failed to import NVMM surfaces from capture")?;
+    let surfaces = fds
+        .iter()
+        .map(|fd| NvmmSurface::from_fd(fd.as_fd()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    tracing::info!(surfaces = surfaces.len(), "Imported capture NVMM surfaces");
+    Ok(Imported {
+        surfaces,
+        _fds: fds,
+    })
+}
+
+impl Preprocess for JetsonPreProcessor {
+    /// Frames without an NVMM surface go through the regular GPU path
+    fn preprocess(&mut self, pixels: &[u8], width: u32, height: u32) -> Result<PreprocessResult> {
+        self.gpu.preprocess(pixels, width, height)
+    }
+
+    fn input_size(&self) -> (u32, u32) {
+        self.gpu.input_size()
+    }
+
+    fn frame_size_changed(&mut self, width: u32, height: u32) -> Result<()> {
+        self.padded_for = None;
+        self.gpu.frame_size_changed(width, height)
+    }
+}
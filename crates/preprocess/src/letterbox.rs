@@ -0,0 +1,171 @@
+/// Geometry of fitting a source frame into the model input while preserving aspect ratio
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Letterbox {
+    pub scale: f32,
+    pub new_width: u32,
+    pub new_height: u32,
+    pub offset_x: u32,
+    pub offset_y: u32,
+}
+
+/// Mapping between source frame pixels and model input pixels
+///
+/// Returned with every preprocessed frame, so consumers map boxes back to the
+/// frame instead of re-deriving the letterbox geometry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LetterboxTransform {
+    pub orig_width: u32,
+    pub orig_height: u32,
+    pub input_width: u32,
+    pub input_height: u32,
+    /// Factor from source to input pixels
+    pub scale: f32,
+    /// Padding left of the resized frame, in input pixels, negative when cropped
+    pub offset_x: f32,
+    /// Padding above the resized frame, in input pixels, negative when cropped
+    pub offset_y: f32,
+}
+
+impl LetterboxTransform {
+    /// Transform of a `width` x `height` frame letterboxed into `input_size`
+    pub fn new(width: u32, height: u32, input_size: (u32, u32)) -> Self {
+        Letterbox::new(width, height, input_size).transform(width, height, input_size)
+    }
+
+    /// Source frame point to model input pixels
+    pub fn to_model(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (
+            x * self.scale + self.offset_x,
+            y * self.scale + self.offset_y,
+        )
+    }
+
+    /// `[x1, y1, x2, y2]` box in model input pixels to the source frame, clamped to it
+    pub fn to_image(&self, [x1, y1, x2, y2]: [f32; 4]) -> [f32; 4] {
+        let x = |x: f32| ((x - self.offset_x) / self.scale).clamp(0.0, self.orig_width as f32);
+        let y = |y: f32| ((y - self.offset_y) / self.scale).clamp(0.0, self.orig_height as f32);
+        [x(x1), y(y1), x(x2), y(y2)]
+    }
+
+    /// Same mapping, reporting boxes in a `width` x `height` resize of the source frame
+    ///
+    /// Resizes are assumed to keep the aspect ratio, so only widths set the factor.
+    pub fn rescaled_to(&self, (width, height): (u32, u32)) -> Self {
+        let factor = if width == self.orig_width || width == 0 {
+            1.0
+        } else {
+            self.orig_width as f32 / width as f32
+        };
+        Self {
+            orig_width: width,
+            orig_height: height,
+            scale: self.scale * factor,
+            ..*self
+        }
+    }
+}
+
+/// How much work a frame needs before normalization
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ResizePath {
+    /// Frame already matches the model input: normalize the source pixels directly
+    Passthrough,
+    /// Frame has the input aspect ratio: resize straight into the input, no padding
+    ResizeOnly,
+    /// General case: resize then pad with the letterbox color
+    Letterbox,
+}
+
+impl Letterbox {
+    pub fn new(width: u32, height: u32, input_size: (u32, u32)) -> Self {
+        let scale = (input_size.0 as f32 / width as f32).min(input_size.1 as f32 / height as f32);
+        let new_width = (width as f32 * scale) as u32;
+        let new_height = (height as f32 * scale) as u32;
+
+        Self {
+            scale,
+            new_width,
+            new_height,
+            offset_x: (input_size.0 - new_width) / 2,
+            offset_y: (input_size.1 - new_height) / 2,
+        }
+    }
+
+    pub fn transform(&self, width: u32, height: u32, input_size: (u32, u32)) -> LetterboxTransform {
+        LetterboxTransform {
+            orig_width: width,
+            orig_height: height,
+            input_width: input_size.0,
+            input_height: input_size.1,
+            scale: self.scale,
+            offset_x: self.offset_x as f32,
+            offset_y: self.offset_y as f32,
+        }
+    }
+
+    /// Pick the cheapest path able to produce the model input for this frame
+    pub fn path(&self, width: u32, height: u32, input_size: (u32, u32)) -> ResizePath {
+        if (width, height) == input_size {
+            ResizePath::Passthrough
+        } else if (self.new_width, self.new_height) == input_size {
+            ResizePath::ResizeOnly
+        } else {
+            ResizePath::Letterbox
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_size_is_passthrough() {
+        let lb = Letterbox::new(512, 512, (512, 512));
+        assert_eq!(lb.scale, 1.0);
+        assert_eq!((lb.offset_x, lb.offset_y), (0, 0));
+        assert_eq!(lb.path(512, 512, (512, 512)), ResizePath::Passthrough);
+    }
+
+    #[test]
+    fn test_matching_aspect_is_resize_only() {
+        let lb = Letterbox::new(1024, 1024, (512, 512));
+        assert_eq!(lb.scale, 0.5);
+        assert_eq!((lb.offset_x, lb.offset_y), (0, 0));
+        assert_eq!(lb.path(1024, 1024, (512, 512)), ResizePath::ResizeOnly);
+
+        let lb = Letterbox::new(1280, 720, (640, 360));
+        assert_eq!(lb.path(1280, 720, (640, 360)), ResizePath::ResizeOnly);
+    }
+
+    #[test]
+    fn test_other_aspect_needs_letterbox() {
+        let lb = Letterbox::new(800, 600, (512, 512));
+        assert_eq!(lb.scale, 0.64);
+        assert_eq!((lb.new_width, lb.new_height), (512, 384));
+        assert_eq!((lb.offset_x, lb.offset_y), (0, 64));
+        assert_eq!(lb.path(800, 600, (512, 512)), ResizePath::Letterbox);
+    }
+
+    #[test]
+    fn test_transform_round_trip() {
+        let transform = LetterboxTransform::new(800, 600, (512, 512));
+
+        let (x, y) = transform.to_model((400.0, 300.0));
+        assert_eq!((x, y), (256.0, 256.0));
+        let (x2, y2) = transform.to_model((800.0, 600.0));
+        assert_eq!(
+            transform.to_image([x, y, x2, y2]),
+            [400.0, 300.0, 800.0, 600.0]
+        );
+
+        // Boxes reaching into the padding are clamped to the frame
+        assert_eq!(
+            transform.to_image([0.0, 0.0, 512.0, 512.0]),
+            [0.0, 0.0, 800.0, 600.0]
+        );
+
+        let half = transform.rescaled_to((400, 300));
+        assert_eq!(half.to_image([x, y, x2, y2]), [200.0, 150.0, 400.0, 300.0]);
+    }
+}
@@ -1,20 +1,30 @@
 pub mod config;
 pub mod cpu;
+pub mod denoise;
 #[cfg(feature = "cuda")]
 pub mod gpu;
-
-use ndarray::{Array, IxDyn};
+#[cfg(feature = "jetson")]
+pub mod jetson;
+mod letterbox;
+pub mod pool;
+pub mod profile;
 
 pub use config::DEFAULT_INPUT_SIZE;
 pub use cpu::CpuPreProcessor;
+pub use denoise::DenoiseConfig;
 #[cfg(feature = "cuda")]
-pub use gpu::GpuPreProcessor;
+pub use gpu::{GpuMemoryStats, GpuPreProcessor};
+#[cfg(feature = "jetson")]
+pub use jetson::JetsonPreProcessor;
+pub use letterbox::LetterboxTransform;
+pub use pool::{PooledTensor, TensorPool};
+pub use profile::{ChannelOrder, PreprocessProfile};
 
 /// Output from preprocessing - either CPU array or GPU device pointer
 #[derive(Debug)]
 pub enum PreprocessOutput {
-    /// CPU array ready for host-side inference
-    Cpu(Array<f32, IxDyn>),
+    /// CPU array ready for host-side inference, its storage is reused once dropped
+    Cpu(PooledTensor),
     /// GPU device pointer with length (no copy needed for TRT)
     Gpu {
         /// Device pointer to the preprocessed data
@@ -29,12 +39,8 @@ pub enum PreprocessOutput {
 pub struct PreprocessResult {
     /// Preprocessed image data (CPU or GPU)
     pub data: PreprocessOutput,
-    /// Scale factor applied during letterboxing
-    pub scale: f32,
-    /// X offset from letterboxing (in pixels)
-    pub offset_x: f32,
-    /// Y offset from letterboxing (in pixels)
-    pub offset_y: f32,
+    /// Mapping between the frame and the model input
+    pub transform: LetterboxTransform,
 }
 
 /// Trait for image preprocessing implementations
@@ -57,6 +63,13 @@ pub trait Preprocess {
 
     /// Get the input size this preprocessor targets
     fn input_size(&self) -> (u32, u32);
+
+    /// Drop the state kept for frames of the previous size once the frames
+    /// change to `width` x `height`, e.g. after the camera renegotiated its
+    /// resolution. Size dependent buffers are reallocated as needed.
+    fn frame_size_changed(&mut self, _width: u32, _height: u32) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
 
 // Re-export the old PreProcessor name for backwards compatibility
@@ -0,0 +1,152 @@
+//! Reusable storage for preprocessed tensors
+//!
+//! A 640x640 input is a 4.7MB `f32` tensor. Allocating it per frame costs a fresh
+//! mapping and page faults on every call; instead the preprocessor hands out
+//! [`PooledTensor`]s whose storage goes back to the pool when dropped.
+
+use ndarray::{Array, IxDyn};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Buffers kept for reuse, enough for a tensor in flight plus the next one
+const MAX_POOLED: usize = 2;
+
+#[derive(Default)]
+struct Inner {
+    free: Mutex<Vec<Vec<f32>>>,
+    allocations: AtomicUsize,
+}
+
+/// Free list of tensor buffers, shared with the tensors handed out
+#[derive(Clone, Default)]
+pub struct TensorPool {
+    inner: Arc<Inner>,
+}
+
+impl TensorPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of buffers allocated because none was free
+    pub fn allocations(&self) -> usize {
+        self.inner.allocations.load(Ordering::Relaxed)
+    }
+
+    /// A buffer of exactly `len` elements with unspecified contents
+    pub(crate) fn take(&self, len: usize) -> Vec<f32> {
+        let reused = self
+            .inner
+            .free
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop();
+
+        let mut buf = reused.unwrap_or_else(|| {
+            self.inner.allocations.fetch_add(1, Ordering::Relaxed);
+            Vec::with_capacity(len)
+        });
+        // No-op when the input size is unchanged, the caller overwrites every element
+        buf.resize(len, 0.0);
+        buf
+    }
+
+    /// Wrap `data` in a tensor that returns its storage here when dropped
+    pub(crate) fn wrap(&self, data: Array<f32, IxDyn>) -> PooledTensor {
+        PooledTensor {
+            data: Some(data),
+            pool: self.clone(),
+        }
+    }
+
+    fn give(&self, buf: Vec<f32>) {
+        let mut free = self.inner.free.lock().unwrap_or_else(|e| e.into_inner());
+        if free.len() < MAX_POOLED {
+            free.push(buf);
+        }
+    }
+}
+
+/// Preprocessed tensor borrowing its storage from a [`TensorPool`]
+#[derive(Debug)]
+pub struct PooledTensor {
+    data: Option<Array<f32, IxDyn>>,
+    pool: TensorPool,
+}
+
+impl PooledTensor {
+    /// Take the array out of the pool, e.g. to keep it beyond the next frame
+    pub fn into_array(mut self) -> Array<f32, IxDyn> {
+        self.data.take().expect("tensor data present until drop")
+    }
+}
+
+impl Deref for PooledTensor {
+    type Target = Array<f32, IxDyn>;
+
+    fn deref(&self) -> &Self::Target {
+        self.data.as_ref().expect("tensor data present until drop")
+    }
+}
+
+impl Drop for PooledTensor {
+    fn drop(&mut self) {
+        if let Some(data) = self.data.take() {
+            let (buf, _) = data.into_raw_vec_and_offset();
+            self.pool.give(buf);
+        }
+    }
+}
+
+impl std::fmt::Debug for TensorPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TensorPool")
+            .field("allocations", &self.allocations())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tensor(pool: &TensorPool, len: usize) -> PooledTensor {
+        let buf = pool.take(len);
+        pool.wrap(Array::from_shape_vec(IxDyn(&[len]), buf).unwrap())
+    }
+
+    #[test]
+    fn test_dropped_tensor_storage_is_reused() {
+        let pool = TensorPool::new();
+
+        let first = tensor(&pool, 16);
+        let ptr = first.as_ptr();
+        drop(first);
+
+        let second = tensor(&pool, 16);
+        assert_eq!(second.as_ptr(), ptr);
+        assert_eq!(pool.allocations(), 1);
+    }
+
+    #[test]
+    fn test_tensors_in_flight_get_distinct_buffers() {
+        let pool = TensorPool::new();
+
+        let first = tensor(&pool, 16);
+        let second = tensor(&pool, 16);
+        assert_ne!(first.as_ptr(), second.as_ptr());
+        assert_eq!(pool.allocations(), 2);
+    }
+
+    #[test]
+    fn test_detached_array_is_not_returned() {
+        let pool = TensorPool::new();
+
+        let array = tensor(&pool, 16).into_array();
+        assert_eq!(array.len(), 16);
+
+        let _next = tensor(&pool, 16);
+        assert_eq!(pool.allocations(), 2);
+    }
+}
@@ -0,0 +1,198 @@
+//! Named preprocessing profiles
+//!
+//! Models expect their input prepared the way they were trained: input size,
+//! normalization, how the frame is fitted into the input and channel order.
+//! Profiles bundle those for common model families, selected by name with
+//! `PREPROCESS_PROFILE`, instead of each deployment assembling them by hand.
+
+use std::fmt;
+use std::str::FromStr;
+
+pub const IMAGENET_MEAN: [f32; 3] = [0.485, 0.456, 0.406];
+pub const IMAGENET_STD: [f32; 3] = [0.229, 0.224, 0.225];
+const CLIP_MEAN: [f32; 3] = [0.481_454_66, 0.457_827_5, 0.408_210_73];
+const CLIP_STD: [f32; 3] = [0.268_629_54, 0.261_302_6, 0.275_777_1];
+
+/// How a frame is fitted into the model input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeMode {
+    /// Scale to fit inside the input, centered and padded with the letterbox color
+    Letterbox,
+    /// Scale to cover the input, cropping the overflow evenly on both sides
+    CenterCrop,
+}
+
+/// Order of the color channels in the model input tensor, or in a frame handed to
+/// `GpuPreProcessor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelOrder {
+    Rgb,
+    Bgr,
+}
+
+impl FromStr for ChannelOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rgb" => Ok(Self::Rgb),
+            "bgr" => Ok(Self::Bgr),
+            _ => anyhow::bail!("Unknown channel order {:?} (expected rgb or bgr)", s),
+        }
+    }
+}
+
+impl fmt::Display for ChannelOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Rgb => "rgb",
+            Self::Bgr => "bgr",
+        })
+    }
+}
+
+/// Input preparation a model family expects
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreprocessProfile {
+    pub name: &'static str,
+    pub input_size: (u32, u32),
+    /// Per-channel mean subtracted from pixels scaled to 0-1, in RGB order
+    pub mean: [f32; 3],
+    /// Per-channel standard deviation dividing them, in RGB order
+    pub std: [f32; 3],
+    pub resize: ResizeMode,
+    pub channel_order: ChannelOrder,
+}
+
+impl PreprocessProfile {
+    /// RF-DETR, the default
+    pub const RFDETR: Self = Self {
+        name: "rfdetr",
+        input_size: (512, 512),
+        mean: IMAGENET_MEAN,
+        std: IMAGENET_STD,
+        resize: ResizeMode::Letterbox,
+        channel_order: ChannelOrder::Rgb,
+    };
+
+    /// RT-DETR as exported by Ultralytics, pixels scaled to 0-1
+    pub const RTDETR: Self = Self {
+        name: "rtdetr",
+        input_size: (640, 640),
+        mean: [0.0; 3],
+        std: [1.0; 3],
+        resize: ResizeMode::Letterbox,
+        channel_order: ChannelOrder::Rgb,
+    };
+
+    /// YOLOv8, pixels scaled to 0-1
+    pub const YOLOV8: Self = Self {
+        name: "yolov8",
+        input_size: (640, 640),
+        mean: [0.0; 3],
+        std: [1.0; 3],
+        resize: ResizeMode::Letterbox,
+        channel_order: ChannelOrder::Rgb,
+    };
+
+    /// Torchvision classification models trained on ImageNet
+    pub const TORCHVISION_IMAGENET: Self = Self {
+        name: "torchvision-imagenet",
+        input_size: (224, 224),
+        mean: IMAGENET_MEAN,
+        std: IMAGENET_STD,
+        resize: ResizeMode::CenterCrop,
+        channel_order: ChannelOrder::Rgb,
+    };
+
+    /// CLIP image encoders
+    pub const CLIP: Self = Self {
+        name: "clip",
+        input_size: (224, 224),
+        mean: CLIP_MEAN,
+        std: CLIP_STD,
+        resize: ResizeMode::CenterCrop,
+        channel_order: ChannelOrder::Rgb,
+    };
+
+    pub const ALL: [Self; 5] = [
+        Self::RFDETR,
+        Self::RTDETR,
+        Self::YOLOV8,
+        Self::TORCHVISION_IMAGENET,
+        Self::CLIP,
+    ];
+
+    /// Whether the CUDA and Jetson preprocessors, fixed to the default profile's
+    /// normalization and resize, produce this profile's input. They write either
+    /// channel order, see `GpuPreProcessor::with_model_order`.
+    pub fn gpu_compatible(&self) -> bool {
+        self.mean == Self::RFDETR.mean
+            && self.std == Self::RFDETR.std
+            && self.resize == Self::RFDETR.resize
+    }
+}
+
+impl Default for PreprocessProfile {
+    fn default() -> Self {
+        Self::RFDETR
+    }
+}
+
+impl FromStr for PreprocessProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|profile| profile.name.eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::ALL.iter().map(|profile| profile.name).collect();
+                anyhow::anyhow!(
+                    "Unknown preprocess profile {:?} (expected one of {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for PreprocessProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles_by_name() {
+        assert_eq!(
+            "YOLOv8".parse::<PreprocessProfile>().unwrap(),
+            PreprocessProfile::YOLOV8
+        );
+        assert_eq!(
+            "torchvision-imagenet"
+                .parse::<PreprocessProfile>()
+                .unwrap()
+                .resize,
+            ResizeMode::CenterCrop
+        );
+        let err = "detr".parse::<PreprocessProfile>().unwrap_err();
+        assert!(err.to_string().contains("rfdetr, rtdetr, yolov8"));
+
+        assert!(PreprocessProfile::default().gpu_compatible());
+        assert!(!PreprocessProfile::CLIP.gpu_compatible());
+        assert!(
+            PreprocessProfile {
+                channel_order: ChannelOrder::Bgr,
+                ..PreprocessProfile::RFDETR
+            }
+            .gpu_compatible()
+        );
+        assert_eq!("BGR".parse::<ChannelOrder>().unwrap(), ChannelOrder::Bgr);
+        assert!("rgba".parse::<ChannelOrder>().is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! Preprocessor output against golden tensors
+//!
+//! Each case is a synthetic frame run through a profile; its golden is the
+//! expected NCHW tensor, stored gzipped as little-endian f32 in
+//! `tests/golden/<case>.f32.gz`. The CPU preprocessor must match it within
+//! `CPU_TOLERANCE`, the CUDA one (with the `cuda` feature, on a GPU) within
+//! `GPU_TOLERANCE` for the profiles it supports, so neither path drifts when the
+//! other is optimized.
+//!
+//! `PREPROCESS_BLESS=1 cargo test -p preprocess --test preprocess-conformance`
+//! rewrites the goldens from the CPU preprocessor. A golden changing in a diff
+//! means preprocessing output changed, review it as such.
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use preprocess::profile::ResizeMode;
+use preprocess::{ChannelOrder, CpuPreProcessor, PreprocessProfile};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+/// Model input of every case, small enough to keep the goldens small. Frames of
+/// other sizes go through the resize, and letterbox when their aspect differs.
+const INPUT_SIZE: (u32, u32) = (64, 64);
+
+/// The CPU path is deterministic, this only allows for rounding across targets
+const CPU_TOLERANCE: f32 = 1e-5;
+
+/// About one 8-bit level after ImageNet normalization
+#[cfg(feature = "cuda")]
+const GPU_TOLERANCE: f32 = 0.02;
+
+#[derive(Clone, Copy)]
+enum Pattern {
+    /// Red along x, green along y, blue along the diagonal
+    Gradient,
+    /// Alternating black and white 4x4 squares, sharp edges
+    Checker,
+    /// Deterministic pseudo-random pixels
+    Noise,
+}
+
+struct Case {
+    name: &'static str,
+    pattern: Pattern,
+    width: u32,
+    height: u32,
+    profile: PreprocessProfile,
+}
+
+const BGR_IMAGENET: PreprocessProfile = PreprocessProfile {
+    name: "bgr-imagenet",
+    input_size: INPUT_SIZE,
+    mean: PreprocessProfile::RFDETR.mean,
+    std: PreprocessProfile::RFDETR.std,
+    resize: ResizeMode::Letterbox,
+    channel_order: ChannelOrder::Bgr,
+};
+
+const CASES: &[Case] = &[
+    Case {
+        name: "gradient_rfdetr",
+        pattern: Pattern::Gradient,
+        width: 64,
+        height: 64,
+        profile: PreprocessProfile::RFDETR,
+    },
+    Case {
+        name: "checker_rfdetr",
+        pattern: Pattern::Checker,
+        width: 64,
+        height: 64,
+        profile: PreprocessProfile::RFDETR,
+    },
+    Case {
+        name: "noise_yolov8",
+        pattern: Pattern::Noise,
+        width: 64,
+        height: 64,
+        profile: PreprocessProfile::YOLOV8,
+    },
+    Case {
+        name: "gradient_letterbox_rfdetr",
+        pattern: Pattern::Gradient,
+        width: 96,
+        height: 64,
+        profile: PreprocessProfile::RFDETR,
+    },
+    Case {
+        name: "checker_resize_rfdetr",
+        pattern: Pattern::Checker,
+        width: 128,
+        height: 128,
+        profile: PreprocessProfile::RFDETR,
+    },
+    Case {
+        name: "gradient_bgr",
+        pattern: Pattern::Gradient,
+        width: 64,
+        height: 64,
+        profile: BGR_IMAGENET,
+    },
+];
+
+fn render(pattern: Pattern, width: u32, height: u32) -> Vec<u8> {
+    let mut state = 0x2545_f491u32;
+    let mut pixels = Vec::with_capacity((width * height * 3) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let rgb = match pattern {
+                Pattern::Gradient => [
+                    (x * 255 / width.max(2).saturating_sub(1)) as u8,
+                    (y * 255 / height.max(2).saturating_sub(1)) as u8,
+                    ((x + y) * 255 / (width + height).saturating_sub(2).max(1)) as u8,
+                ],
+                Pattern::Checker => [if (x / 4 + y / 4) % 2 == 0 { 255 } else { 0 }; 3],
+                Pattern::Noise => {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    let [r, g, b, _] = state.to_le_bytes();
+                    [r, g, b]
+                }
+            };
+            pixels.extend_from_slice(&rgb);
+        }
+    }
+    pixels
+}
+
+fn golden_path(case: &Case) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.f32.gz", case.name))
+}
+
+fn read_golden(case: &Case) -> Vec<f32> {
+    let path = golden_path(case);
+    let file = std::fs::File::open(&path).unwrap_or_else(|e| {
+        panic!(
+            "{}: {e}, run with PREPROCESS_BLESS=1 to create it",
+            path.display()
+        )
+    });
+    let mut bytes = Vec::new();
+    GzDecoder::new(file).read_to_end(&mut bytes).unwrap();
+    bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect()
+}
+
+fn write_golden(case: &Case, tensor: &[f32]) {
+    let path = golden_path(case);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let mut encoder = GzEncoder::new(std::fs::File::create(&path).unwrap(), Compression::best());
+    for value in tensor {
+        encoder.write_all(&value.to_le_bytes()).unwrap();
+    }
+    encoder.finish().unwrap();
+}
+
+/// Largest difference to the golden, with the index it is at
+fn max_difference(actual: &[f32], golden: &[f32]) -> (f32, usize) {
+    assert_eq!(actual.len(), golden.len(), "tensor length differs");
+    let mut max = (0.0, 0);
+    for (i, (a, g)) in actual.iter().zip(golden).enumerate() {
+        let diff = (a - g).abs();
+        if diff > max.0 {
+            max = (diff, i);
+        }
+    }
+    max
+}
+
+fn cpu_output(case: &Case) -> Vec<f32> {
+    let pixels = render(case.pattern, case.width, case.height);
+    let mut preprocessor = CpuPreProcessor::new(INPUT_SIZE).with_profile(case.profile);
+    let (tensor, ..) = preprocessor
+        .preprocess_from_u8_slice(&pixels, case.width, case.height)
+        .unwrap();
+    assert_eq!(
+        tensor.shape(),
+        &[1, 3, INPUT_SIZE.1 as usize, INPUT_SIZE.0 as usize]
+    );
+    tensor.iter().copied().collect()
+}
+
+#[test]
+fn test_cpu_matches_golden() {
+    let bless = std::env::var_os("PREPROCESS_BLESS").is_some();
+    for case in CASES {
+        let actual = cpu_output(case);
+        if bless {
+            write_golden(case, &actual);
+            continue;
+        }
+        let (diff, at) = max_difference(&actual, &read_golden(case));
+        assert!(
+            diff <= CPU_TOLERANCE,
+            "{}: CPU output off by {diff} at element {at}",
+            case.name
+        );
+    }
+}
+
+/// The GPU suite runs on the cases `gpu_compatible` picks, BGR ones included
+#[test]
+fn test_gpu_cases_include_bgr() {
+    let bgr = CASES
+        .iter()
+        .find(|case| case.profile.channel_order == ChannelOrder::Bgr)
+        .unwrap();
+    assert!(bgr.profile.gpu_compatible(), "{} skipped on GPU", bgr.name);
+}
+
+#[cfg(feature = "cuda")]
+#[test]
+fn test_gpu_matches_golden() {
+    use preprocess::GpuPreProcessor;
+
+    for case in CASES.iter().filter(|case| case.profile.gpu_compatible()) {
+        let pixels = render(case.pattern, case.width, case.height);
+        let mut preprocessor = GpuPreProcessor::new(INPUT_SIZE, (case.width, case.height))
+            .unwrap()
+            .with_model_order(case.profile.channel_order);
+        preprocessor
+            .preprocess_with_order(&pixels, case.width, case.height, ChannelOrder::Rgb)
+            .unwrap();
+        let actual = preprocessor.copy_output_to_host().unwrap();
+
+        let (diff, at) = max_difference(&actual, &read_golden(case));
+        assert!(
+            diff <= GPU_TOLERANCE,
+            "{}: GPU output off by {diff} at element {at}",
+            case.name
+        );
+    }
+}
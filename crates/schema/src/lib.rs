@@ -1,7 +1,25 @@
 #[allow(unused_imports, dead_code, clippy::all, unsafe_op_in_unsafe_fn)]
 mod trace_context_generated;
 
+#[allow(
+    unused_imports,
+    dead_code,
+    clippy::all,
+    unsafe_op_in_unsafe_fn,
+    mismatched_lifetime_syntaxes
+)]
+mod provenance_generated;
+
 #[allow(unused_imports, dead_code, clippy::all, unsafe_op_in_unsafe_fn)]
+mod clock_generated;
+
+#[allow(
+    unused_imports,
+    dead_code,
+    clippy::all,
+    unsafe_op_in_unsafe_fn,
+    mismatched_lifetime_syntaxes
+)]
 mod frame_generated;
 
 #[allow(
@@ -13,6 +31,8 @@ mod frame_generated;
 )]
 mod detection_generated;
 
+pub use clock_generated::bridge::schema::*;
 pub use detection_generated::bridge::schema::*;
 pub use frame_generated::bridge::schema::*;
+pub use provenance_generated::bridge::schema::*;
 pub use trace_context_generated::bridge::schema::*;
@@ -6,6 +6,7 @@ to users for building their own TensorRT engines without needing to repeat
 the calibration process.
 """
 import argparse
+import json
 from pathlib import Path
 
 import numpy as np
@@ -16,6 +17,23 @@ DEFAULT_CALIBRATION_DIR = SCRIPT_DIR / "calibration_tensors"
 # Input shape: NCHW format, matching preprocessing
 INPUT_SHAPE = (1, 3, 512, 512)
 
+# Calibrator classes by the `trt_calibrator` of calibration.json
+CALIBRATORS = {
+    "EntropyCalibration2": "IInt8EntropyCalibrator2",
+    "MinMaxCalibration": "IInt8MinMaxCalibrator",
+}
+
+
+def load_metadata(calibration_dir: Path) -> dict:
+    """Read the calibration.json the calibration crate writes next to the tensors.
+
+    Tensor sets from before it existed have none, and get the defaults.
+    """
+    path = calibration_dir / "calibration.json"
+    if not path.exists():
+        return {}
+    return json.loads(path.read_text())
+
 
 class CacheOnlyCalibrator:
     """Calibrator that only builds the cache, doesn't build an engine."""
@@ -118,15 +136,23 @@ def build_calibration_cache(
     config.set_flag(trt.BuilderFlag.INT8)
     config.set_flag(trt.BuilderFlag.FP16)
 
+    # Method and input shape the tensors were generated for
+    metadata = load_metadata(calibration_dir)
+    input_shape = tuple(metadata.get("input_shape", INPUT_SHAPE))
+    algorithm = metadata.get("trt_calibrator", "EntropyCalibration2")
+    if algorithm not in CALIBRATORS:
+        raise RuntimeError(f"Unsupported calibrator {algorithm} in calibration.json")
+    print(f"Calibrating with {algorithm} on input shape {input_shape}")
+
     # Create calibrator
     calibrator = CacheOnlyCalibrator(
         calibration_dir,
         cache_path,
-        INPUT_SHAPE,
+        input_shape,
     )
 
-    # Make the calibrator a proper IInt8EntropyCalibrator2
-    class TRTCalibrator(trt.IInt8EntropyCalibrator2):
+    # Make the calibrator a proper TensorRT calibrator of the chosen method
+    class TRTCalibrator(getattr(trt, CALIBRATORS[algorithm])):
         def __init__(self, inner):
             super().__init__()
             self.inner = inner
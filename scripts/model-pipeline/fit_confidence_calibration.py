@@ -0,0 +1,121 @@
+#!/usr/bin/env python3
+"""Fit a confidence calibration for the inference service.
+
+Input is a CSV of matched detections with two columns, `confidence,correct`:
+the uncalibrated confidence reported by the model (sigmoid of the max-class
+logit) and whether the detection matched a ground truth box (1 or 0). It is
+typically produced by running the model over a labelled validation set.
+
+The output JSON is loaded by the inference service via
+CONFIDENCE_CALIBRATION_PATH:
+
+    {"method": "temperature", "temperature": 1.35}
+    {"method": "isotonic", "x": [...], "y": [...]}
+"""
+import argparse
+import json
+from pathlib import Path
+
+import numpy as np
+
+EPS = 1e-6
+
+
+def load_samples(path: Path) -> tuple[np.ndarray, np.ndarray]:
+    """Load (confidence, correct) pairs from CSV, skipping a header row if present."""
+    data = np.genfromtxt(path, delimiter=",", names=True, dtype=np.float64)
+    confidence = np.clip(data["confidence"], EPS, 1.0 - EPS)
+    correct = data["correct"].astype(np.float64)
+    return confidence, correct
+
+
+def fit_temperature(confidence: np.ndarray, correct: np.ndarray) -> float:
+    """Temperature minimizing the negative log likelihood, by golden-section search."""
+    logits = np.log(confidence / (1.0 - confidence))
+
+    def nll(log_t: float) -> float:
+        p = np.clip(1.0 / (1.0 + np.exp(-logits / np.exp(log_t))), EPS, 1.0 - EPS)
+        return float(-np.mean(correct * np.log(p) + (1.0 - correct) * np.log(1.0 - p)))
+
+    lo, hi = np.log(0.05), np.log(20.0)
+    ratio = (np.sqrt(5.0) - 1.0) / 2.0
+    for _ in range(100):
+        a = hi - ratio * (hi - lo)
+        b = lo + ratio * (hi - lo)
+        if nll(a) < nll(b):
+            hi = b
+        else:
+            lo = a
+    return float(np.exp((lo + hi) / 2.0))
+
+
+def fit_isotonic(
+    confidence: np.ndarray, correct: np.ndarray, max_points: int
+) -> tuple[list[float], list[float]]:
+    """Pool-adjacent-violators fit, downsampled to at most `max_points` knots."""
+    order = np.argsort(confidence)
+    xs = confidence[order]
+
+    # Each block holds (sum of labels, count, mean confidence)
+    blocks: list[list[float]] = []
+    for x, y in zip(xs, correct[order]):
+        blocks.append([y, 1.0, x])
+        while len(blocks) > 1 and blocks[-2][0] / blocks[-2][1] > blocks[-1][0] / blocks[-1][1]:
+            y_sum, n, x_mean = blocks.pop()
+            prev = blocks[-1]
+            prev[2] = (prev[2] * prev[1] + x_mean * n) / (prev[1] + n)
+            prev[0] += y_sum
+            prev[1] += n
+
+    x_knots = np.array([b[2] for b in blocks])
+    y_knots = np.array([b[0] / b[1] for b in blocks])
+
+    # Blocks with equal mean confidence would break strict ordering
+    x_knots, unique = np.unique(x_knots, return_index=True)
+    y_knots = y_knots[unique]
+
+    if len(x_knots) > max_points:
+        keep = np.unique(np.linspace(0, len(x_knots) - 1, max_points).round().astype(int))
+        x_knots, y_knots = x_knots[keep], y_knots[keep]
+
+    return [round(float(v), 6) for v in x_knots], [round(float(v), 6) for v in y_knots]
+
+
+def main() -> None:
+    parser = argparse.ArgumentParser(description="Fit a confidence calibration")
+    parser.add_argument("samples", type=Path, help="CSV with confidence,correct columns")
+    parser.add_argument("--output", type=Path, required=True, help="Calibration JSON path")
+    parser.add_argument(
+        "--method",
+        choices=["temperature", "isotonic"],
+        default="temperature",
+        help="Calibration method (default: temperature)",
+    )
+    parser.add_argument(
+        "--max-points",
+        type=int,
+        default=64,
+        help="Maximum isotonic table size (default: 64)",
+    )
+    args = parser.parse_args()
+
+    confidence, correct = load_samples(args.samples)
+    print(f"Loaded {len(confidence)} samples, {int(correct.sum())} correct")
+
+    if args.method == "temperature":
+        temperature = fit_temperature(confidence, correct)
+        calibration = {"method": "temperature", "temperature": round(temperature, 6)}
+        print(f"Fitted temperature: {temperature:.4f}")
+    else:
+        x, y = fit_isotonic(confidence, correct, args.max_points)
+        if len(x) < 2:
+            raise SystemExit("Not enough distinct confidences to fit an isotonic table")
+        calibration = {"method": "isotonic", "x": x, "y": y}
+        print(f"Fitted isotonic table with {len(x)} points")
+
+    args.output.write_text(json.dumps(calibration, indent=2) + "\n")
+    print(f"Wrote {args.output}")
+
+
+if __name__ == "__main__":
+    main()